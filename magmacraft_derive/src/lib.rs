@@ -0,0 +1,240 @@
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{
+    parse_macro_input,
+    Data,
+    DeriveInput,
+    Error,
+    Fields,
+    Lit,
+    Meta,
+    NestedMeta,
+    Result,
+    Type
+};
+
+// Size of push constants storage every Vulkan implementation is required to provide
+const GUARANTEED_PUSH_CONSTANTS_SIZE: u64 = 128;
+
+#[proc_macro_derive(PushConstants, attributes(push_constants))]
+pub fn derive_push_constants(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    match expand_push_constants(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into()
+    }
+}
+
+fn expand_push_constants(input: &DeriveInput) -> Result<TokenStream2> {
+    check_repr_c(input)?;
+    check_no_generics(input)?;
+
+    let name = &input.ident;
+    let field_types = &field_types(input)?;
+    let max_size = max_size(input)?;
+
+    let size_message = format!(
+        "push constants struct `{}` exceeds the maximum size of {} bytes",
+        name, max_size);
+
+    let padding_message = format!(
+        "push constants struct `{}` contains padding between or after its fields",
+        name);
+
+    Ok(quote! {
+        unsafe impl ::magmacraft::vulkan::command_buffer::PushConstants for #name {}
+
+        #(const _: fn() = || {
+            fn assert_push_constants<T: ::magmacraft::vulkan::command_buffer::PushConstants>() {}
+            assert_push_constants::<#field_types>();
+        };)*
+
+        const _: () = {
+            let size = std::mem::size_of::<#name>();
+            let fields_size = 0 #(+ std::mem::size_of::<#field_types>())*;
+
+            assert!(size <= #max_size as usize, #size_message);
+            assert!(size == fields_size, #padding_message);
+        };
+    })
+}
+
+fn check_repr_c(input: &DeriveInput) -> Result<()> {
+    for attribute in &input.attrs {
+        if let Ok(Meta::List(list)) = attribute.parse_meta() {
+            if list.ident != "repr" {
+                continue;
+            }
+
+            for nested in &list.nested {
+                if let NestedMeta::Meta(Meta::Word(word)) = nested {
+                    if word == "C" {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    }
+
+    Err(Error::new_spanned(
+        &input.ident,
+        "push constants must be `#[repr(C)]` to have a layout matching the shader"))
+}
+
+fn check_no_generics(input: &DeriveInput) -> Result<()> {
+    if input.generics.params.is_empty() {
+        Ok(())
+    }
+    else {
+        Err(Error::new_spanned(
+            &input.generics,
+            "push constants cannot be generic, their layout has to be checked at compile time"))
+    }
+}
+
+fn field_types(input: &DeriveInput) -> Result<Vec<&Type>> {
+    let data = match &input.data {
+        Data::Struct(data) => data,
+        _ => return Err(Error::new_spanned(
+            &input.ident,
+            "push constants can only be derived for structs"))
+    };
+
+    let types = match &data.fields {
+        Fields::Named(fields) => fields.named.iter().map(|field| &field.ty).collect(),
+        Fields::Unnamed(fields) => fields.unnamed.iter().map(|field| &field.ty).collect(),
+        Fields::Unit => Vec::new()
+    };
+
+    Ok(types)
+}
+
+// Allows structs targeting devices with a larger `maxPushConstantsSize`
+// to opt out of the guaranteed limit with `#[push_constants(max_size = 256)]`
+fn max_size(input: &DeriveInput) -> Result<u64> {
+    let mut max_size = None;
+
+    for attribute in &input.attrs {
+        let list = match attribute.parse_meta() {
+            Ok(Meta::List(list)) if list.ident == "push_constants" => list,
+            _ => continue
+        };
+
+        for nested in &list.nested {
+            let name_value = match nested {
+                NestedMeta::Meta(Meta::NameValue(name_value))
+                    if name_value.ident == "max_size" => name_value,
+                _ => return Err(Error::new_spanned(
+                    nested,
+                    "unknown push constants attribute, expected `max_size = ...`"))
+            };
+
+            let size = match &name_value.lit {
+                Lit::Int(size) => size.value(),
+                _ => return Err(Error::new_spanned(
+                    &name_value.lit,
+                    "`max_size` has to be an integer"))
+            };
+
+            if max_size.is_some() {
+                return Err(Error::new_spanned(nested, "`max_size` is given more than once"));
+            }
+
+            max_size = Some(size);
+        }
+    }
+
+    Ok(max_size.unwrap_or(GUARANTEED_PUSH_CONSTANTS_SIZE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> DeriveInput {
+        syn::parse_str(source).unwrap()
+    }
+
+    fn expansion_error(source: &str) -> String {
+        expand_push_constants(&parse(source)).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn expands_repr_c_struct() {
+        let expansion = expand_push_constants(&parse(
+            "#[repr(C)] struct Constants { color: [f32; 4], scale: f32 }")).unwrap();
+
+        let expansion = expansion.to_string();
+        assert!(expansion.contains("unsafe impl :: magmacraft :: vulkan :: command_buffer :: \
+            PushConstants for Constants"));
+        assert!(expansion.contains("size_of :: < [ f32 ; 4 ] >"));
+        assert!(expansion.contains("size_of :: < f32 >"));
+        assert!(expansion.contains("assert_push_constants :: < [ f32 ; 4 ] >"));
+        assert!(expansion.contains("assert_push_constants :: < f32 >"));
+    }
+
+    #[test]
+    fn accepts_repr_c_among_other_representation_hints() {
+        assert!(expand_push_constants(&parse("#[repr(C, align(16))] struct Constants;")).is_ok());
+    }
+
+    #[test]
+    fn rejects_missing_repr_c() {
+        assert!(expansion_error("struct Constants { a: f32 }").contains("#[repr(C)]"));
+        assert!(expansion_error("#[repr(packed)] struct Constants { a: f32 }")
+            .contains("#[repr(C)]"));
+    }
+
+    #[test]
+    fn rejects_generics() {
+        assert!(expansion_error("#[repr(C)] struct Constants<T> { a: T }")
+            .contains("cannot be generic"));
+    }
+
+    #[test]
+    fn rejects_non_structs() {
+        assert!(expansion_error("#[repr(C)] enum Constants { A, B }")
+            .contains("only be derived for structs"));
+    }
+
+    #[test]
+    fn defaults_to_guaranteed_size() {
+        let input = parse("#[repr(C)] struct Constants;");
+        assert_eq!(max_size(&input).unwrap(), GUARANTEED_PUSH_CONSTANTS_SIZE);
+    }
+
+    #[test]
+    fn reads_max_size() {
+        let input = parse("#[repr(C)] #[push_constants(max_size = 256)] struct Constants;");
+        assert_eq!(max_size(&input).unwrap(), 256);
+    }
+
+    #[test]
+    fn rejects_unknown_attributes_after_max_size() {
+        let input = parse(
+            "#[repr(C)] #[push_constants(max_size = 256, bogus)] struct Constants;");
+        assert!(max_size(&input).unwrap_err().to_string().contains("unknown"));
+    }
+
+    #[test]
+    fn rejects_duplicate_max_size() {
+        let input = parse(
+            "#[repr(C)] #[push_constants(max_size = 256, max_size = 512)] struct Constants;");
+        assert!(max_size(&input).unwrap_err().to_string().contains("more than once"));
+
+        let input = parse(
+            "#[repr(C)] #[push_constants(max_size = 256)] #[push_constants(max_size = 256)] \
+             struct Constants;");
+        assert!(max_size(&input).unwrap_err().to_string().contains("more than once"));
+    }
+
+    #[test]
+    fn rejects_non_integer_max_size() {
+        let input = parse("#[repr(C)] #[push_constants(max_size = \"256\")] struct Constants;");
+        assert!(max_size(&input).unwrap_err().to_string().contains("integer"));
+    }
+}
@@ -0,0 +1,15 @@
+#version 460
+
+layout(push_constant) uniform PushConstant {
+    mat4 view_projection;
+} push_constant;
+
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec4 color;
+
+layout(location = 0) out vec4 vertex_color;
+
+void main() {
+    gl_Position = push_constant.view_projection * vec4(position, 1.0);
+    vertex_color = color;
+}
@@ -0,0 +1,17 @@
+#version 460
+
+layout(set = 0, binding = 0) uniform sampler2D linear_image;
+
+layout(location = 0) out vec4 fragment_color;
+
+// Piecewise sRGB transfer function, the same one sRGB formats apply when written to
+vec3 encode_srgb(vec3 linear) {
+    vec3 low = linear * 12.92;
+    vec3 high = 1.055 * pow(linear, vec3(1.0 / 2.4)) - 0.055;
+    return mix(low, high, step(0.0031308, linear));
+}
+
+void main() {
+    vec4 color = texelFetch(linear_image, ivec2(gl_FragCoord.xy), 0);
+    fragment_color = vec4(encode_srgb(clamp(color.rgb, 0.0, 1.0)), color.a);
+}
@@ -0,0 +1,11 @@
+#version 460
+
+layout(set = 0, binding = 0) uniform samplerCube sky;
+
+layout(location = 0) in vec3 direction;
+
+layout(location = 0) out vec4 fragment_color;
+
+void main() {
+    fragment_color = vec4(texture(sky, direction).rgb, 1.0);
+}
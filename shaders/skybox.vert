@@ -0,0 +1,18 @@
+#version 460
+
+layout(push_constant) uniform Camera {
+    // Inverse of the projection times the view without its translation
+    mat4 inverse_view_projection;
+};
+
+layout(location = 0) out vec3 direction;
+
+// A single triangle covering the whole viewport, whose corners on the far plane
+// are unprojected into the directions they look in
+void main() {
+    vec2 position = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2) * 2.0 - 1.0;
+    gl_Position = vec4(position, 0.0, 1.0);
+
+    vec4 far = inverse_view_projection * vec4(position, 1.0, 1.0);
+    direction = far.xyz / far.w;
+}
@@ -0,0 +1,12 @@
+#version 460
+
+layout(set = 0, binding = 0) uniform sampler2DArray sprite_texture;
+
+layout(location = 0) in vec3 vertex_uv;
+layout(location = 1) in vec4 vertex_color;
+
+layout(location = 0) out vec4 fragment_color;
+
+void main() {
+    fragment_color = texture(sprite_texture, vertex_uv) * vertex_color;
+}
@@ -0,0 +1,18 @@
+#version 460
+
+layout(push_constant) uniform PushConstant {
+    mat4 projection;
+} push_constant;
+
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec3 uv;
+layout(location = 2) in vec4 color;
+
+layout(location = 0) out vec3 vertex_uv;
+layout(location = 1) out vec4 vertex_color;
+
+void main() {
+    gl_Position = push_constant.projection * vec4(position, 1.0);
+    vertex_uv = uv;
+    vertex_color = color;
+}
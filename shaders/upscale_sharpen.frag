@@ -0,0 +1,36 @@
+#version 460
+
+layout(set = 0, binding = 0) uniform sampler2D scaled_image;
+
+layout(push_constant) uniform Extents {
+    // Width and height of the scaled image followed by those of the swapchain image
+    vec4 extents;
+};
+
+layout(location = 0) out vec4 fragment_color;
+
+// From 0.0 for the least to 1.0 for the most sharpening
+const float SHARPNESS = 0.5;
+
+// Bilinear upscale followed by contrast adaptive sharpening, which brings back some of the detail
+// lost to the lower resolution while leaving edges which already have a high contrast alone
+void main() {
+    vec2 uv = gl_FragCoord.xy / extents.zw;
+    vec2 texel = 1.0 / extents.xy;
+
+    vec4 center = texture(scaled_image, uv);
+    vec3 north = texture(scaled_image, uv - vec2(0.0, texel.y)).rgb;
+    vec3 south = texture(scaled_image, uv + vec2(0.0, texel.y)).rgb;
+    vec3 west = texture(scaled_image, uv - vec2(texel.x, 0.0)).rgb;
+    vec3 east = texture(scaled_image, uv + vec2(texel.x, 0.0)).rgb;
+
+    vec3 minimum = min(center.rgb, min(min(north, south), min(west, east)));
+    vec3 maximum = max(center.rgb, max(max(north, south), max(west, east)));
+
+    // Close to 0.0 where the neighbourhood already spans most of the range
+    vec3 amplitude = sqrt(clamp(min(minimum, 1.0 - maximum) / max(maximum, 1e-5), 0.0, 1.0));
+    vec3 weight = -amplitude * mix(0.125, 0.2, SHARPNESS);
+
+    vec3 color = (center.rgb + (north + south + west + east) * weight) / (1.0 + 4.0 * weight);
+    fragment_color = vec4(clamp(color, 0.0, 1.0), center.a);
+}
@@ -0,0 +1,18 @@
+#version 460
+
+layout(location = 0) in vec3 vertex_normal;
+layout(location = 1) flat in uint vertex_block;
+layout(location = 2) in vec2 vertex_uv;
+
+// Layer `block - 1` holds the texture of block `block`, as air is never meshed
+layout(set = 0, binding = 1) uniform sampler2DArray block_textures;
+
+layout(location = 0) out vec4 fragment_color;
+
+const vec3 light_direction = normalize(vec3(0.3, 1.0, 0.5));
+
+void main() {
+    vec4 block_color = texture(block_textures, vec3(vertex_uv, float(vertex_block - 1)));
+    float light = max(dot(vertex_normal, light_direction), 0.0) * 0.7 + 0.3;
+    fragment_color = vec4(block_color.rgb * light, block_color.a);
+}
@@ -0,0 +1,34 @@
+#version 460
+
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec3 normal;
+layout(location = 2) in uint block;
+
+layout(push_constant) uniform PushConstant {
+    mat4 view_projection;
+} push_constant;
+
+layout(location = 0) out vec3 vertex_normal;
+layout(location = 1) flat out uint vertex_block;
+layout(location = 2) out vec2 vertex_uv;
+
+// Faces are axis aligned, so texture coordinates are the world position
+// projected onto the face's plane, with v pointing down
+vec2 face_uv(vec3 position, vec3 normal) {
+    if (abs(normal.x) > 0.5) {
+        return vec2(position.z * normal.x, -position.y);
+    }
+    else if (abs(normal.y) > 0.5) {
+        return vec2(position.x, position.z * normal.y);
+    }
+    else {
+        return vec2(-position.x * normal.z, -position.y);
+    }
+}
+
+void main() {
+    gl_Position = push_constant.view_projection * vec4(position, 1.0);
+    vertex_normal = normal;
+    vertex_block = block;
+    vertex_uv = face_uv(position, normal);
+}
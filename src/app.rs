@@ -0,0 +1,105 @@
+use std::time::{
+    Duration,
+    Instant
+};
+use crate::{
+    window::{
+        Window,
+        WindowEvent
+    },
+    rendering::{
+        RenderingResult,
+        renderer::{
+            Renderer,
+            RendererEvent
+        }
+    }
+};
+
+// Game driven by `run`, which owns the main loop instead of the game itself
+pub trait App {
+    const UPDATES_PER_SECOND: u32 = 60;
+
+    // Creates everything the game renders with, at startup and whenever the renderer is rebuilt
+    fn init(&mut self, renderer: &mut Renderer) -> RenderingResult<()>;
+
+    // Drops everything created in `init`, before the renderer is rebuilt or destroyed
+    fn release(&mut self) {}
+
+    fn event(&mut self, _event: &WindowEvent) {}
+
+    // Advances the game by a fixed time step of `1 / UPDATES_PER_SECOND` seconds
+    fn update(&mut self, dt: f32);
+
+    // Interpolation is the fraction of a time step which has passed since the last update,
+    // e.g. to blend the previous and current positions of moving objects
+    fn render(&mut self, renderer: &mut Renderer, interpolation: f32) -> RenderingResult<()>;
+}
+
+// Updates lagging further behind are dropped, so that a slow frame does not cause
+// ever more updates to catch up on
+const MAX_UPDATES_PER_FRAME: u32 = 8;
+
+// Runs the game until the window is closed. Rendering pauses while the window is minimized,
+// and with `RedrawMode::OnDemand` until a redraw is requested, e.g. by `render` for animations.
+// Every window event requests one, as it may change what the game renders.
+// The swapchain is recreated whenever the framebuffer is resized, before the app sees
+// the event. The renderer is only rebuilt as a whole after the device is lost,
+// with `release` and `init` around it.
+pub fn run<A: App>(window: &mut Window, app: &mut A) -> RenderingResult<()> {
+    let mut renderer = Renderer::new(window.handle())?;
+    app.init(&mut renderer)?;
+
+    let time_step = Duration::from_secs(1) / A::UPDATES_PER_SECOND.max(1);
+    let mut previous_frame = Instant::now();
+    let mut lag = Duration::from_secs(0);
+
+    while window.loop_condition() {
+        window.poll_events();
+
+        let events = window.take_events();
+        // So that the app can size its own targets, e.g. those of custom passes,
+        // after the new swapchain when handling the event
+        if events.iter().any(|event| matches!(event, WindowEvent::FramebufferResized {..})) {
+            renderer.recreate_swapchain()?;
+        }
+
+        for event in events {
+            app.event(&event);
+            renderer.request_redraw();
+        }
+
+        if window.is_minimized() || !renderer.needs_redraw() {
+            window.wait_events();
+            previous_frame = Instant::now();
+            continue;
+        }
+
+        if renderer.take_events().contains(&RendererEvent::DeviceLost) {
+            app.release();
+            renderer = renderer.rebuild()?;
+            app.init(&mut renderer)?;
+        }
+
+        let now = Instant::now();
+        lag += now - previous_frame;
+        previous_frame = now;
+
+        let mut updates = 0;
+        while lag >= time_step && updates < MAX_UPDATES_PER_FRAME {
+            app.update(time_step.as_secs_f32());
+            lag -= time_step;
+            updates += 1;
+        }
+
+        if lag >= time_step {
+            lag = Duration::from_secs(0);
+        }
+
+        app.render(&mut renderer, lag.as_secs_f32() / time_step.as_secs_f32())?;
+    }
+
+    app.release();
+
+    Ok(())
+}
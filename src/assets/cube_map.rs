@@ -0,0 +1,117 @@
+use std::{
+    f32::consts::PI,
+    path::Path
+};
+use image::{
+    Rgba,
+    RgbaImage
+};
+use crate::assets::{
+    AssetError,
+    AssetResult,
+    path_string
+};
+
+// Faces of a cube map as read from files, in the order of its layers: +X, -X, +Y, -Y, +Z, -Z.
+// Built into a texture with `TextureArrayBuilder::layers` and `cube_map(true)`.
+pub struct CubeMapData {
+    pub faces: Vec<RgbaImage>
+}
+
+impl CubeMapData {
+    // Paths of the faces in layer order, each face has to be square and of the same size
+    pub fn load_faces(paths: [&Path; 6]) -> AssetResult<Self> {
+        let faces = paths
+            .iter()
+            .map(|path| load_image(path))
+            .collect::<AssetResult<_>>()?;
+
+        Ok(Self {
+            faces
+        })
+    }
+
+    // Panorama covering 360 degrees horizontally and 180 degrees vertically,
+    // with +Y up and -Z at its horizontal center
+    pub fn load_equirectangular(path: &Path, face_size: u32) -> AssetResult<Self> {
+        let panorama = load_image(path)?;
+        Ok(Self::from_equirectangular(&panorama, face_size))
+    }
+
+    pub fn from_equirectangular(panorama: &RgbaImage, face_size: u32) -> Self {
+        let faces = (0..6)
+            .map(|face| {
+                RgbaImage::from_fn(face_size, face_size, |x, y| {
+                    // Texel centers in [-1, 1] across the face
+                    let s = 2.0 * (x as f32 + 0.5) / face_size as f32 - 1.0;
+                    let t = 2.0 * (y as f32 + 0.5) / face_size as f32 - 1.0;
+
+                    sample_equirectangular(panorama, face_direction(face, s, t))
+                })
+            })
+            .collect();
+
+        Self {
+            faces
+        }
+    }
+}
+
+fn load_image(path: &Path) -> AssetResult<RgbaImage> {
+    let image = image::open(path)
+        .map_err(|source| AssetError::ImageDecodeError {path: path_string(path), source})?;
+
+    Ok(image.to_rgba())
+}
+
+// Direction through the given point of a face, following the face selection
+// of Vulkan's cube map sampling
+fn face_direction(face: usize, s: f32, t: f32) -> [f32; 3] {
+    match face {
+        0 => [1.0, -t, -s],
+        1 => [-1.0, -t, s],
+        2 => [s, 1.0, t],
+        3 => [s, -1.0, -t],
+        4 => [s, -t, 1.0],
+        _ => [-s, -t, -1.0]
+    }
+}
+
+// Blends the four texels around the direction, wrapping around horizontally
+fn sample_equirectangular(panorama: &RgbaImage, [x, y, z]: [f32; 3]) -> Rgba<u8> {
+    let length = (x * x + y * y + z * z).sqrt();
+    let u = 0.5 + x.atan2(-z) / (2.0 * PI);
+    let v = (y / length).clamp(-1.0, 1.0).acos() / PI;
+
+    let (width, height) = panorama.dimensions();
+    let column = u * width as f32 - 0.5;
+    let row = (v * height as f32 - 0.5).clamp(0.0, height as f32 - 1.0);
+
+    let (left, top) = (column.floor(), row.floor());
+    let (column_weight, row_weight) = (column - left, row - top);
+    let texel = |column: i64, row: i64| {
+        let column = column.rem_euclid(i64::from(width)) as u32;
+        let row = row.clamp(0, i64::from(height) - 1) as u32;
+        panorama.get_pixel(column, row).0
+    };
+
+    let (left, top) = (left as i64, top as i64);
+    let corners = [
+        (texel(left, top), (1.0 - column_weight) * (1.0 - row_weight)),
+        (texel(left + 1, top), column_weight * (1.0 - row_weight)),
+        (texel(left, top + 1), (1.0 - column_weight) * row_weight),
+        (texel(left + 1, top + 1), column_weight * row_weight)
+    ];
+
+    let mut blended = [0; 4];
+    for (channel, value) in blended.iter_mut().enumerate() {
+        let sum: f32 = corners
+            .iter()
+            .map(|(texel, weight)| f32::from(texel[channel]) * weight)
+            .sum();
+
+        *value = sum.round() as u8;
+    }
+
+    Rgba(blended)
+}
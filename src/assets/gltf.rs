@@ -0,0 +1,1000 @@
+use std::path::Path;
+use image::RgbaImage;
+use nalgebra_glm as glm;
+use crate::assets::{
+    AssetError,
+    AssetResult,
+    json::JsonValue,
+    model::{
+        ModelData,
+        ModelVertex,
+        SubmeshData,
+        MaterialData
+    },
+    path_string
+};
+
+// glTF 2.0, both `.gltf` with external or embedded buffers and binary `.glb`.
+// Only triangle primitives of the default scene are imported, with their node transforms
+// baked into the vertices, and only the base color of the materials is kept.
+pub fn load(path: &Path) -> AssetResult<ModelData> {
+    let bytes = std::fs::read(path)
+        .map_err(|source| AssetError::FileReadError {path: path_string(path), source})?;
+
+    GltfDocument::parse(path, &bytes)?.model()
+}
+
+struct GltfDocument<'a> {
+    path: &'a Path,
+    json: JsonValue,
+    buffers: Vec<Vec<u8>>
+}
+
+struct AccessorView<'b> {
+    data: &'b [u8],
+    count: usize,
+    component_type: usize,
+    components: usize,
+    stride: usize
+}
+
+impl<'a> GltfDocument<'a> {
+    const GLB_MAGIC: &'static [u8] = b"glTF";
+    const GLB_VERSION: u32 = 2;
+    const GLB_HEADER_SIZE: usize = 12;
+    const GLB_JSON_CHUNK: u32 = 0x4e4f_534a;
+    const GLB_BINARY_CHUNK: u32 = 0x004e_4942;
+    const TRIANGLES_MODE: usize = 4;
+    const UNSIGNED_BYTE: usize = 5121;
+    const UNSIGNED_SHORT: usize = 5123;
+    const UNSIGNED_INT: usize = 5125;
+    const FLOAT: usize = 5126;
+
+    fn parse(path: &'a Path, bytes: &[u8]) -> AssetResult<Self> {
+        let (json, binary_chunk) = if bytes.starts_with(Self::GLB_MAGIC) {
+            Self::split_glb(path, bytes)?
+        }
+        else {
+            (bytes, None)
+        };
+
+        let text = std::str::from_utf8(json)
+            .map_err(|_| gltf_error(path, "the document is not valid UTF-8"))?;
+        let json = JsonValue::parse(text)
+            .map_err(|offset| AssetError::JsonParseError {path: path_string(path), offset})?;
+
+        let mut document = Self {
+            path,
+            json,
+            buffers: Vec::new()
+        };
+
+        document.check_required_extensions()?;
+        document.buffers = document.load_buffers(binary_chunk)?;
+
+        Ok(document)
+    }
+
+    // Binary files consist of a header followed by a JSON chunk and an optional binary one.
+    // Bytes after the length given in the header are ignored.
+    fn split_glb<'b>(path: &Path, bytes: &'b [u8]) -> AssetResult<(&'b [u8], Option<&'b [u8]>)> {
+        let truncated = || gltf_error(path, "the binary file is truncated");
+        let read_u32 = |bytes: &[u8], offset: usize| {
+            bytes
+                .get(offset..offset + 4)
+                .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+                .ok_or_else(truncated)
+        };
+
+        let version = read_u32(bytes, 4)?;
+        if version != Self::GLB_VERSION {
+            return Err(unsupported(path, &format!("binary glTF version {}", version)));
+        }
+
+        let length = read_u32(bytes, 8)? as usize;
+        if length < Self::GLB_HEADER_SIZE {
+            return Err(gltf_error(path, "the binary file's header has an invalid length"));
+        }
+
+        let bytes = bytes.get(..length).ok_or_else(truncated)?;
+        let mut json = None;
+        let mut binary = None;
+        let mut offset = Self::GLB_HEADER_SIZE;
+
+        while offset < bytes.len() {
+            let length = read_u32(bytes, offset)? as usize;
+            let kind = read_u32(bytes, offset + 4)?;
+            let data = bytes.get(offset + 8..offset + 8 + length).ok_or_else(truncated)?;
+
+            if offset == Self::GLB_HEADER_SIZE && kind != Self::GLB_JSON_CHUNK {
+                return Err(gltf_error(path, "the binary file does not start with a JSON chunk"));
+            }
+
+            match kind {
+                Self::GLB_JSON_CHUNK if json.is_none() => json = Some(data),
+                Self::GLB_BINARY_CHUNK if binary.is_none() => binary = Some(data),
+                _ => ()
+            }
+
+            offset += 8 + length;
+        }
+
+        let json = json.ok_or_else(|| gltf_error(path, "the binary file has no JSON chunk"))?;
+
+        Ok((json, binary))
+    }
+
+    // E.g. mesh compression, without which the file cannot be read correctly
+    fn check_required_extensions(&self) -> AssetResult<()> {
+        let required_extensions = self.json
+            .get("extensionsRequired")
+            .and_then(JsonValue::as_array)
+            .unwrap_or(&[]);
+
+        match required_extensions.first() {
+            Some(extension) => Err(unsupported(
+                self.path,
+                &format!("extension {}", extension.as_str().unwrap_or("with an invalid name")))),
+            None => Ok(())
+        }
+    }
+
+    fn load_buffers(&self, binary_chunk: Option<&[u8]>) -> AssetResult<Vec<Vec<u8>>> {
+        let buffers = self.json.get("buffers").and_then(JsonValue::as_array).unwrap_or(&[]);
+
+        buffers
+            .iter()
+            .enumerate()
+            .map(|(index, buffer)| {
+                // Only the first buffer of a binary file may refer to its binary chunk
+                let data = match (buffer.get("uri").and_then(JsonValue::as_str), binary_chunk) {
+                    (Some(uri), _) => self.load_uri(uri)?,
+                    (None, Some(binary_chunk)) if index == 0 => binary_chunk.to_vec(),
+                    (None, _) => return Err(self.error(&format!("buffer {} has no data", index)))
+                };
+
+                if data.len() < self.required_usize(buffer, "byteLength", "buffer", index)? {
+                    return Err(self.error(&format!("buffer {} is shorter than declared", index)));
+                }
+
+                Ok(data)
+            })
+            .collect()
+    }
+
+    // Either a base64 data URI or a path relative to the document
+    fn load_uri(&self, uri: &str) -> AssetResult<Vec<u8>> {
+        if uri.starts_with("data:") {
+            let separator = uri.find(',').unwrap_or(uri.len());
+
+            if !uri[..separator].ends_with(";base64") {
+                return Err(unsupported(self.path, "data URIs not encoded in base64"));
+            }
+
+            return decode_base64(uri.get(separator + 1..).unwrap_or(""))
+                .ok_or_else(|| self.error("a data URI contains invalid base64"));
+        }
+
+        let directory = self.path.parent().unwrap_or_else(|| Path::new(""));
+        let file_path = directory.join(decode_percent_encoding(uri));
+
+        std::fs::read(&file_path)
+            .map_err(|source| AssetError::FileReadError {path: path_string(&file_path), source})
+    }
+
+    fn model(&self) -> AssetResult<ModelData> {
+        let mut model = ModelData {
+            submeshes: Vec::new(),
+            materials: self.materials()?
+        };
+
+        // Without scenes, the file is a library of meshes which are imported as they are
+        match self.scene_nodes()? {
+            Some(nodes) => {
+                for node in nodes {
+                    self.add_node(node, &glm::identity(), 0, &mut model)?;
+                }
+            },
+            None => {
+                for mesh in 0..self.element_count("meshes") {
+                    self.add_mesh(mesh, &glm::identity(), &mut model)?;
+                }
+            }
+        }
+
+        Ok(model)
+    }
+
+    fn scene_nodes(&self) -> AssetResult<Option<Vec<usize>>> {
+        if self.element_count("scenes") == 0 {
+            return Ok(None);
+        }
+
+        let scene_index = self.json.get("scene").and_then(JsonValue::as_usize).unwrap_or(0);
+        let scene = self.element("scenes", scene_index)?;
+
+        let nodes = scene
+            .get("nodes")
+            .and_then(JsonValue::as_array)
+            .unwrap_or(&[])
+            .iter()
+            .map(|node| node.as_usize().ok_or_else(|| self.error("invalid scene node index")))
+            .collect::<AssetResult<Vec<usize>>>()?;
+
+        Ok(Some(nodes))
+    }
+
+    fn add_node(
+        &self,
+        index: usize,
+        parent_transform: &glm::Mat4,
+        depth: usize,
+        model: &mut ModelData
+    ) -> AssetResult<()> {
+        // Deeper hierarchies than the number of nodes can only be cyclic
+        if depth > self.element_count("nodes") {
+            return Err(self.error("the node hierarchy contains a cycle"));
+        }
+
+        let node = self.element("nodes", index)?;
+        let transform = parent_transform * Self::node_transform(node);
+
+        if let Some(mesh) = node.get("mesh").and_then(JsonValue::as_usize) {
+            self.add_mesh(mesh, &transform, model)?;
+        }
+
+        for child in node.get("children").and_then(JsonValue::as_array).unwrap_or(&[]) {
+            let child = child.as_usize().ok_or_else(|| self.error("invalid child node index"))?;
+            self.add_node(child, &transform, depth + 1, model)?;
+        }
+
+        Ok(())
+    }
+
+    fn node_transform(node: &JsonValue) -> glm::Mat4 {
+        if let Some(matrix) = floats(node.get("matrix"), 16) {
+            return glm::make_mat4(&matrix);
+        }
+
+        let translation = floats(node.get("translation"), 3).unwrap_or_else(|| vec![0.0; 3]);
+        let rotation = floats(node.get("rotation"), 4).unwrap_or_else(|| vec![0.0, 0.0, 0.0, 1.0]);
+        let scale = floats(node.get("scale"), 3).unwrap_or_else(|| vec![1.0; 3]);
+
+        glm::translation(&glm::make_vec3(&translation))
+            * glm::quat_to_mat4(&glm::quat(rotation[0], rotation[1], rotation[2], rotation[3]))
+            * glm::scaling(&glm::make_vec3(&scale))
+    }
+
+    fn add_mesh(
+        &self,
+        index: usize,
+        transform: &glm::Mat4,
+        model: &mut ModelData
+    ) -> AssetResult<()> {
+        let mesh = self.element("meshes", index)?;
+        let primitives = mesh.get("primitives").and_then(JsonValue::as_array).unwrap_or(&[]);
+
+        for primitive in primitives {
+            let mode = primitive
+                .get("mode")
+                .and_then(JsonValue::as_usize)
+                .unwrap_or(Self::TRIANGLES_MODE);
+
+            if mode != Self::TRIANGLES_MODE {
+                warn!(
+                    "{}: skipping primitive of mesh {} with mode {}, only triangles are supported",
+                    self.path.display(), index, mode);
+                continue;
+            }
+
+            let mut submesh = self.primitive_submesh(primitive, transform, model.materials.len())?;
+            submesh.generate_missing_normals();
+            model.submeshes.push(submesh);
+        }
+
+        Ok(())
+    }
+
+    fn primitive_submesh(
+        &self,
+        primitive: &JsonValue,
+        transform: &glm::Mat4,
+        material_count: usize
+    ) -> AssetResult<SubmeshData> {
+        let attribute = |name: &str| {
+            primitive
+                .get("attributes")
+                .and_then(|attributes| attributes.get(name))
+                .and_then(JsonValue::as_usize)
+        };
+
+        let position_accessor = attribute("POSITION")
+            .ok_or_else(|| self.error("a primitive has no positions"))?;
+        let positions = self.read_floats(position_accessor, 3)?;
+        let vertex_count = positions.len() / 3;
+
+        let normals = match attribute("NORMAL") {
+            Some(accessor) => Some(self.read_floats(accessor, 3)?),
+            None => None
+        };
+
+        let uvs = match attribute("TEXCOORD_0") {
+            Some(accessor) => Some(self.read_floats(accessor, 2)?),
+            None => None
+        };
+
+        let has_vertex_count = |attribute: &Option<Vec<f32>>, components: usize| {
+            attribute
+                .as_ref()
+                .map_or(true, |attribute| attribute.len() == vertex_count * components)
+        };
+
+        if !has_vertex_count(&normals, 3) || !has_vertex_count(&uvs, 2) {
+            return Err(self.error("attributes of a primitive have different lengths"));
+        }
+
+        let normal_matrix = glm::mat4_to_mat3(&glm::inverse_transpose(*transform));
+        let vertices = (0..vertex_count)
+            .map(|vertex| {
+                let position = &positions[vertex * 3..vertex * 3 + 3];
+                let position = transform * glm::vec4(position[0], position[1], position[2], 1.0);
+
+                // Zeroed normals are left for `generate_missing_normals`
+                let normal = normals.as_ref().map_or([0.0; 3], |normals| {
+                    let normal = &normals[vertex * 3..vertex * 3 + 3];
+                    let normal = normal_matrix * glm::make_vec3(normal);
+
+                    if normal == glm::vec3(0.0, 0.0, 0.0) {
+                        [0.0; 3]
+                    }
+                    else {
+                        glm::normalize(&normal).into()
+                    }
+                });
+
+                let uv = uvs
+                    .as_ref()
+                    .map_or([0.0; 2], |uvs| [uvs[vertex * 2], uvs[vertex * 2 + 1]]);
+
+                ModelVertex {
+                    position: [position.x, position.y, position.z],
+                    normal,
+                    uv
+                }
+            })
+            .collect();
+
+        let mut indices = match primitive.get("indices").and_then(JsonValue::as_usize) {
+            Some(accessor) => self.read_indices(accessor)?,
+            None => (0..vertex_count as u32).collect()
+        };
+
+        if indices.len() % 3 != 0 || indices.iter().any(|index| *index as usize >= vertex_count) {
+            return Err(self.error("a primitive has invalid indices"));
+        }
+
+        // Mirroring transforms flip the winding order, which has to be restored for culling
+        if glm::determinant(transform) < 0.0 {
+            for triangle in indices.chunks_exact_mut(3) {
+                triangle.swap(1, 2);
+            }
+        }
+
+        let material = primitive.get("material").and_then(JsonValue::as_usize);
+        if material.map_or(false, |material| material >= material_count) {
+            return Err(self.error("a primitive uses a material which does not exist"));
+        }
+
+        Ok(SubmeshData {
+            vertices,
+            indices,
+            material
+        })
+    }
+
+    fn materials(&self) -> AssetResult<Vec<MaterialData>> {
+        let materials = self.json.get("materials").and_then(JsonValue::as_array).unwrap_or(&[]);
+
+        materials
+            .iter()
+            .enumerate()
+            .map(|(index, material)| {
+                let pbr = material.get("pbrMetallicRoughness");
+                let base_color = floats(pbr.and_then(|pbr| pbr.get("baseColorFactor")), 4)
+                    .map_or([1.0; 4], |color| [color[0], color[1], color[2], color[3]]);
+
+                let texture = pbr
+                    .and_then(|pbr| pbr.get("baseColorTexture"))
+                    .and_then(|texture| texture.get("index"))
+                    .and_then(JsonValue::as_usize);
+
+                let base_color_texture = match texture {
+                    Some(texture) => Some(self.texture_image(texture)?),
+                    None => None
+                };
+
+                let name = material
+                    .get("name")
+                    .and_then(JsonValue::as_str)
+                    .map_or_else(|| format!("material {}", index), str::to_owned);
+
+                Ok(MaterialData {
+                    name,
+                    base_color,
+                    base_color_texture
+                })
+            })
+            .collect()
+    }
+
+    fn texture_image(&self, index: usize) -> AssetResult<RgbaImage> {
+        let texture = self.element("textures", index)?;
+        let image_index = texture
+            .get("source")
+            .and_then(JsonValue::as_usize)
+            .ok_or_else(|| unsupported(self.path, "textures without a PNG or JPEG source"))?;
+        let image = self.element("images", image_index)?;
+
+        let uri = image.get("uri").and_then(JsonValue::as_str);
+        let buffer_view = image.get("bufferView").and_then(JsonValue::as_usize);
+        let data = match (uri, buffer_view) {
+            (Some(uri), _) => self.load_uri(uri)?,
+            (None, Some(buffer_view)) => self.buffer_view(buffer_view)?.to_vec(),
+            (None, None) => {
+                return Err(self.error(&format!("image {} has no data", image_index)));
+            }
+        };
+
+        let decoded = image::load_from_memory(&data)
+            .map_err(|source| AssetError::ImageDecodeError {
+                path: format!("{} (image {})", self.path.display(), image_index),
+                source
+            })?;
+
+        Ok(decoded.to_rgba())
+    }
+
+    fn read_floats(&self, accessor: usize, components: usize) -> AssetResult<Vec<f32>> {
+        let view = self.accessor_view(accessor)?;
+
+        if view.component_type != Self::FLOAT || view.components != components {
+            return Err(unsupported(
+                self.path,
+                &format!("accessor {} in place of {} float components", accessor, components)));
+        }
+
+        let mut floats = Vec::with_capacity(view.count * components);
+
+        for element in 0..view.count {
+            for component in 0..components {
+                let offset = element * view.stride + component * 4;
+                floats.push(f32::from_bits(read_le(view.data, offset, 4)));
+            }
+        }
+
+        Ok(floats)
+    }
+
+    fn read_indices(&self, accessor: usize) -> AssetResult<Vec<u32>> {
+        let view = self.accessor_view(accessor)?;
+        let index_size = match view.component_type {
+            Self::UNSIGNED_BYTE => 1,
+            Self::UNSIGNED_SHORT => 2,
+            Self::UNSIGNED_INT => 4,
+            _ => return Err(self.error(&format!("accessor {} cannot hold indices", accessor)))
+        };
+
+        if view.components != 1 {
+            return Err(self.error(&format!("accessor {} cannot hold indices", accessor)));
+        }
+
+        Ok((0..view.count)
+            .map(|index| read_le(view.data, index * view.stride, index_size))
+            .collect())
+    }
+
+    fn accessor_view(&self, index: usize) -> AssetResult<AccessorView<'_>> {
+        let accessor = self.element("accessors", index)?;
+
+        if accessor.get("sparse").is_some() {
+            return Err(unsupported(self.path, "sparse accessors"));
+        }
+
+        let buffer_view = accessor
+            .get("bufferView")
+            .and_then(JsonValue::as_usize)
+            .ok_or_else(|| unsupported(self.path, "accessors without buffer views"))?;
+
+        let count = self.required_usize(accessor, "count", "accessor", index)?;
+        let component_type = self.required_usize(accessor, "componentType", "accessor", index)?;
+        let components = match accessor.get("type").and_then(JsonValue::as_str) {
+            Some("SCALAR") => 1,
+            Some("VEC2") => 2,
+            Some("VEC3") => 3,
+            Some("VEC4") => 4,
+            _ => return Err(unsupported(self.path, &format!("the type of accessor {}", index)))
+        };
+
+        let component_size = match component_type {
+            5120 | Self::UNSIGNED_BYTE => 1,
+            5122 | Self::UNSIGNED_SHORT => 2,
+            Self::UNSIGNED_INT | Self::FLOAT => 4,
+            _ => {
+                let message = format!("accessor {} has an invalid component type", index);
+                return Err(self.error(&message));
+            }
+        };
+
+        let element_size = component_size * components;
+        let stride = self
+            .element("bufferViews", buffer_view)?
+            .get("byteStride")
+            .and_then(JsonValue::as_usize)
+            .unwrap_or(element_size);
+
+        if stride < element_size {
+            let message = format!("accessor {} has elements larger than its stride", index);
+            return Err(self.error(&message));
+        }
+
+        let offset = accessor.get("byteOffset").and_then(JsonValue::as_usize).unwrap_or(0);
+        let data = self.buffer_view(buffer_view)?.get(offset..).unwrap_or(&[]);
+
+        // Counts come from the file, so the end of the last element may not even fit in usize
+        let end = match count.checked_sub(1) {
+            Some(last) => last
+                .checked_mul(stride)
+                .and_then(|last_offset| last_offset.checked_add(element_size)),
+            None => Some(0)
+        };
+
+        if end.map_or(true, |end| end > data.len()) {
+            return Err(self.error(&format!("accessor {} is out of bounds", index)));
+        }
+
+        Ok(AccessorView {
+            data,
+            count,
+            component_type,
+            components,
+            stride
+        })
+    }
+
+    fn buffer_view(&self, index: usize) -> AssetResult<&[u8]> {
+        let buffer_view = self.element("bufferViews", index)?;
+        let buffer = self.required_usize(buffer_view, "buffer", "buffer view", index)?;
+        let length = self.required_usize(buffer_view, "byteLength", "buffer view", index)?;
+        let offset = buffer_view.get("byteOffset").and_then(JsonValue::as_usize).unwrap_or(0);
+
+        offset
+            .checked_add(length)
+            .and_then(|end| self.buffers.get(buffer)?.get(offset..end))
+            .ok_or_else(|| self.error(&format!("buffer view {} is out of bounds", index)))
+    }
+
+    fn element(&self, array: &str, index: usize) -> AssetResult<&JsonValue> {
+        self.json
+            .get(array)
+            .and_then(JsonValue::as_array)
+            .and_then(|elements| elements.get(index))
+            .ok_or_else(|| self.error(&format!("{} has no element {}", array, index)))
+    }
+
+    fn element_count(&self, array: &str) -> usize {
+        self.json.get(array).and_then(JsonValue::as_array).map_or(0, <[JsonValue]>::len)
+    }
+
+    fn required_usize(
+        &self,
+        value: &JsonValue,
+        key: &str,
+        kind: &str,
+        index: usize
+    ) -> AssetResult<usize> {
+        value
+            .get(key)
+            .and_then(JsonValue::as_usize)
+            .ok_or_else(|| self.error(&format!("{} {} has no valid {}", kind, index, key)))
+    }
+
+    fn error(&self, message: &str) -> AssetError {
+        gltf_error(self.path, message)
+    }
+}
+
+fn gltf_error(path: &Path, message: &str) -> AssetError {
+    AssetError::GltfParseError {
+        path: path_string(path),
+        message: message.to_owned()
+    }
+}
+
+fn unsupported(path: &Path, feature: &str) -> AssetError {
+    AssetError::UnsupportedGltfFeature {
+        path: path_string(path),
+        feature: feature.to_owned()
+    }
+}
+
+fn floats(value: Option<&JsonValue>, count: usize) -> Option<Vec<f32>> {
+    let elements = value?.as_array()?;
+
+    if elements.len() != count {
+        return None;
+    }
+
+    elements.iter().map(|element| element.as_f64().map(|number| number as f32)).collect()
+}
+
+// Little endian unsigned integer of 1, 2 or 4 bytes
+fn read_le(data: &[u8], offset: usize, size: usize) -> u32 {
+    data[offset..offset + size]
+        .iter()
+        .rev()
+        .fold(0, |value, byte| value << 8 | u32::from(*byte))
+}
+
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(text.len() * 3 / 4);
+    let mut accumulator = 0u32;
+    let mut bit_count = 0;
+
+    for character in text.bytes().take_while(|character| *character != b'=') {
+        let value = match character {
+            b'A'..=b'Z' => character - b'A',
+            b'a'..=b'z' => character - b'a' + 26,
+            b'0'..=b'9' => character - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None
+        };
+
+        accumulator = accumulator << 6 | u32::from(value);
+        bit_count += 6;
+
+        if bit_count >= 8 {
+            bit_count -= 8;
+            bytes.push((accumulator >> bit_count) as u8);
+            accumulator &= (1 << bit_count) - 1;
+        }
+    }
+
+    Some(bytes)
+}
+
+// Relative URIs may escape characters such as spaces in file names
+fn decode_percent_encoding(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+
+    while index < bytes.len() {
+        let escaped = bytes
+            .get(index + 1..index + 3)
+            .filter(|_| bytes[index] == b'%')
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                index += 3;
+            },
+            None => {
+                decoded.push(bytes[index]);
+                index += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Triangle in the XY plane moved 5 units along Z, with 16 bit indices after its positions
+    const TRIANGLE: &str = r#"{
+        "asset": {"version": "2.0"},
+        "scene": 0,
+        "scenes": [{"nodes": [0]}],
+        "nodes": [{"mesh": 0, "translation": [0, 0, 5]}],
+        "meshes": [{"primitives": [{"attributes": {"POSITION": 0}, "indices": 1}]}],
+        "buffers": [BUFFER],
+        "bufferViews": [
+            {"buffer": 0, "byteOffset": 0, "byteLength": 36},
+            {"buffer": 0, "byteOffset": 36, "byteLength": 6}
+        ],
+        "accessors": [
+            {"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3"},
+            {"bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR"}
+        ]
+    }"#;
+
+    fn triangle_buffer() -> Vec<u8> {
+        let mut buffer = float_bytes(&[0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0]);
+        buffer.extend_from_slice(&[0, 0, 1, 0, 2, 0, 0, 0]);
+        buffer
+    }
+
+    fn triangle_with_data_uri(replacements: &[(&str, &str)]) -> String {
+        let buffer = format!(
+            r#"{{"uri": "data:application/octet-stream;base64,{}", "byteLength": 44}}"#,
+            encode_base64(&triangle_buffer()));
+
+        replacements
+            .iter()
+            .fold(TRIANGLE.replace("BUFFER", &buffer), |json, (from, to)| {
+                assert!(json.contains(from), "{} is not in the document", from);
+                json.replace(from, to)
+            })
+    }
+
+    fn float_bytes(floats: &[f32]) -> Vec<u8> {
+        floats.iter().flat_map(|float| float.to_bits().to_le_bytes().to_vec()).collect()
+    }
+
+    fn encode_base64(bytes: &[u8]) -> String {
+        const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+        let mut text = String::new();
+
+        for chunk in bytes.chunks(3) {
+            let word = chunk
+                .iter()
+                .enumerate()
+                .fold(0u32, |word, (index, byte)| word | u32::from(*byte) << (16 - 8 * index));
+
+            for index in 0..4 {
+                if index <= chunk.len() {
+                    text.push(ALPHABET[(word >> (18 - 6 * index) & 0x3f) as usize] as char);
+                }
+                else {
+                    text.push('=');
+                }
+            }
+        }
+
+        text
+    }
+
+    fn glb(json: &str, binary: Option<&[u8]>) -> Vec<u8> {
+        let mut chunks = Vec::new();
+        let mut push_chunk = |kind: u32, data: &[u8], padding: u8| {
+            let padded_length = (data.len() + 3) / 4 * 4;
+            chunks.extend_from_slice(&(padded_length as u32).to_le_bytes());
+            chunks.extend_from_slice(&kind.to_le_bytes());
+            chunks.extend_from_slice(data);
+            chunks.resize(chunks.len() + padded_length - data.len(), padding);
+        };
+
+        push_chunk(GltfDocument::GLB_JSON_CHUNK, json.as_bytes(), b' ');
+        if let Some(binary) = binary {
+            push_chunk(GltfDocument::GLB_BINARY_CHUNK, binary, 0);
+        }
+
+        let mut bytes = b"glTF".to_vec();
+        bytes.extend_from_slice(&2u32.to_le_bytes());
+        bytes.extend_from_slice(&((12 + chunks.len()) as u32).to_le_bytes());
+        bytes.extend_from_slice(&chunks);
+        bytes
+    }
+
+    fn load(bytes: &[u8]) -> AssetResult<ModelData> {
+        GltfDocument::parse(Path::new("model.gltf"), bytes)?.model()
+    }
+
+    fn error_message(result: AssetResult<ModelData>) -> String {
+        match result {
+            Err(AssetError::GltfParseError {message, ..}) => message,
+            Err(error) => panic!("unexpected error: {}", error),
+            Ok(_) => panic!("expected a glTF parse error")
+        }
+    }
+
+    fn unsupported_feature(result: AssetResult<ModelData>) -> String {
+        match result {
+            Err(AssetError::UnsupportedGltfFeature {feature, ..}) => feature,
+            Err(error) => panic!("unexpected error: {}", error),
+            Ok(_) => panic!("expected an unsupported feature error")
+        }
+    }
+
+    #[test]
+    fn decodes_base64() {
+        assert_eq!(decode_base64("TWFu").unwrap(), b"Man");
+        assert_eq!(decode_base64("TWE=").unwrap(), b"Ma");
+        assert_eq!(decode_base64("TQ==").unwrap(), b"M");
+        assert_eq!(decode_base64("").unwrap(), b"");
+        assert_eq!(decode_base64("+/+/").unwrap(), &[0xfb, 0xff, 0xbf]);
+        assert_eq!(decode_base64("TW!u"), None);
+        assert_eq!(decode_base64(&encode_base64(&triangle_buffer())).unwrap(), triangle_buffer());
+    }
+
+    #[test]
+    fn decodes_percent_encoded_paths() {
+        assert_eq!(decode_percent_encoding("my%20model.bin"), "my model.bin");
+        assert_eq!(decode_percent_encoding("100%.bin"), "100%.bin");
+        assert_eq!(decode_percent_encoding("a%zz"), "a%zz");
+    }
+
+    #[test]
+    fn loads_buffers_from_data_uris() {
+        let model = load(triangle_with_data_uri(&[]).as_bytes()).unwrap();
+        let submesh = &model.submeshes[0];
+
+        let positions: Vec<[f32; 3]> =
+            submesh.vertices.iter().map(|vertex| vertex.position).collect();
+        assert_eq!(positions, vec![[0.0, 0.0, 5.0], [1.0, 0.0, 5.0], [0.0, 1.0, 5.0]]);
+        assert_eq!(submesh.indices, vec![0, 1, 2]);
+        assert!(submesh.vertices.iter().all(|vertex| vertex.normal == [0.0, 0.0, 1.0]));
+    }
+
+    #[test]
+    fn rejects_invalid_data_uris() {
+        let json = triangle_with_data_uri(&[(";base64,", ",")]);
+        assert!(unsupported_feature(load(json.as_bytes())).contains("base64"));
+
+        let json = triangle_with_data_uri(&[(";base64,", ";base64,!")]);
+        assert!(error_message(load(json.as_bytes())).contains("invalid base64"));
+
+        let json = triangle_with_data_uri(&[(r#""byteLength": 44"#, r#""byteLength": 48"#)]);
+        assert!(error_message(load(json.as_bytes())).contains("shorter than declared"));
+    }
+
+    #[test]
+    fn reads_interleaved_attributes() {
+        let mut buffer = Vec::new();
+        for position in &[[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]] {
+            buffer.extend(float_bytes(position));
+            buffer.extend(float_bytes(&[0.0, 1.0, 0.0]));
+        }
+
+        let json = r#"{
+            "meshes": [{"primitives": [{"attributes": {"POSITION": 0, "NORMAL": 1}}]}],
+            "buffers": [{"uri": "data:;base64,DATA", "byteLength": 72}],
+            "bufferViews": [{"buffer": 0, "byteLength": 72, "byteStride": 24}],
+            "accessors": [
+                {"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3"},
+                {"bufferView": 0, "byteOffset": 12, "componentType": 5126, "count": 3,
+                    "type": "VEC3"}
+            ]
+        }"#.replace("DATA", &encode_base64(&buffer));
+
+        let model = load(json.as_bytes()).unwrap();
+        let vertices = &model.submeshes[0].vertices;
+
+        assert_eq!(vertices[2].position, [0.0, 1.0, 0.0]);
+        assert!(vertices.iter().all(|vertex| vertex.normal == [0.0, 1.0, 0.0]));
+        assert_eq!(model.submeshes[0].indices, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn checks_accessor_bounds() {
+        let out_of_bounds = [
+            (r#""count": 3, "type": "VEC3""#, r#""count": 4, "type": "VEC3""#),
+            (r#""count": 3, "type": "VEC3""#, r#""count": 1e18, "type": "VEC3""#),
+            (r#""bufferView": 0, "#, r#""bufferView": 0, "byteOffset": 4, "#),
+            (r#""count": 3, "type": "SCALAR""#, r#""count": 4, "type": "SCALAR""#)
+        ];
+
+        for replacement in &out_of_bounds {
+            let json = triangle_with_data_uri(&[*replacement]);
+            let message = error_message(load(json.as_bytes()));
+            assert!(message.contains("is out of bounds"), "{:?}", replacement);
+        }
+
+        let json = triangle_with_data_uri(&[
+            (r#""byteLength": 36}"#, r#""byteLength": 36, "byteStride": 8}"#)]);
+        assert!(error_message(load(json.as_bytes())).contains("larger than its stride"));
+    }
+
+    #[test]
+    fn checks_buffer_view_bounds() {
+        let out_of_bounds = [
+            r#""byteOffset": 36, "byteLength": 9"#,
+            r#""byteOffset": 1e300, "byteLength": 6"#
+        ];
+
+        for buffer_view in &out_of_bounds {
+            let json =
+                triangle_with_data_uri(&[(r#""byteOffset": 36, "byteLength": 6"#, buffer_view)]);
+            let message = error_message(load(json.as_bytes()));
+            assert!(message.contains("buffer view 1 is out of bounds"));
+        }
+    }
+
+    #[test]
+    fn restores_winding_of_mirrored_meshes() {
+        let json = triangle_with_data_uri(&[
+            (r#""translation": [0, 0, 5]"#, r#""scale": [-1, 1, 1]"#)]);
+        let model = load(json.as_bytes()).unwrap();
+
+        assert_eq!(model.submeshes[0].indices, vec![0, 2, 1]);
+        assert_eq!(model.submeshes[0].vertices[1].position, [-1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn rejects_cyclic_node_hierarchies() {
+        let json = triangle_with_data_uri(&[(
+            r#""nodes": [{"mesh": 0, "translation": [0, 0, 5]}]"#,
+            r#""nodes": [{"children": [1]}, {"mesh": 0, "children": [0]}]"#)]);
+
+        assert!(error_message(load(json.as_bytes())).contains("cycle"));
+    }
+
+    #[test]
+    fn rejects_required_extensions() {
+        let json = triangle_with_data_uri(&[(
+            r#""scene": 0,"#,
+            r#""scene": 0, "extensionsRequired": ["KHR_draco_mesh_compression"],"#)]);
+
+        assert!(unsupported_feature(load(json.as_bytes())).contains("KHR_draco_mesh_compression"));
+    }
+
+    #[test]
+    fn reports_json_errors_with_offsets() {
+        match load(br#"{"asset": }"#) {
+            Err(AssetError::JsonParseError {offset, ..}) => assert_eq!(offset, 10),
+            _ => panic!("expected a JSON parse error")
+        }
+    }
+
+    #[test]
+    fn loads_binary_files() {
+        let json = TRIANGLE.replace("BUFFER", r#"{"byteLength": 44}"#);
+        let model = load(&glb(&json, Some(&triangle_buffer()))).unwrap();
+
+        assert_eq!(model.submeshes[0].indices, vec![0, 1, 2]);
+        assert_eq!(model.submeshes[0].vertices[1].position, [1.0, 0.0, 5.0]);
+
+        // Anything after the length given in the header is not part of the file
+        let mut bytes = glb(&json, Some(&triangle_buffer()));
+        bytes.extend_from_slice(b"trailing garbage");
+        assert!(load(&bytes).is_ok());
+    }
+
+    #[test]
+    fn validates_binary_headers() {
+        let json = TRIANGLE.replace("BUFFER", r#"{"byteLength": 44}"#);
+        let valid = glb(&json, Some(&triangle_buffer()));
+
+        let mut version_1 = valid.clone();
+        version_1[4] = 1;
+        assert!(unsupported_feature(load(&version_1)).contains("binary glTF version 1"));
+
+        assert!(error_message(load(&valid[..10])).contains("truncated"));
+
+        let mut too_long = valid.clone();
+        too_long[8..12].copy_from_slice(&(valid.len() as u32 + 4).to_le_bytes());
+        assert!(error_message(load(&too_long)).contains("truncated"));
+
+        let mut too_short = valid;
+        too_short[8..12].copy_from_slice(&8u32.to_le_bytes());
+        assert!(error_message(load(&too_short)).contains("invalid length"));
+    }
+
+    #[test]
+    fn validates_binary_chunks() {
+        let json = TRIANGLE.replace("BUFFER", r#"{"byteLength": 44}"#);
+        let valid = glb(&json, Some(&triangle_buffer()));
+
+        // The binary chunk claims more bytes than the file has left
+        let binary_chunk = valid.len() - 44 - 8;
+        let mut truncated = valid.clone();
+        truncated[binary_chunk..binary_chunk + 4].copy_from_slice(&48u32.to_le_bytes());
+        assert!(error_message(load(&truncated)).contains("truncated"));
+
+        let mut binary_first = valid.clone();
+        binary_first[16..20].copy_from_slice(&GltfDocument::GLB_BINARY_CHUNK.to_le_bytes());
+        assert!(error_message(load(&binary_first)).contains("does not start with a JSON chunk"));
+
+        let without_binary = glb(&json, None);
+        assert!(error_message(load(&without_binary)).contains("buffer 0 has no data"));
+
+        let mut without_chunks = valid[..12].to_vec();
+        without_chunks[8..12].copy_from_slice(&12u32.to_le_bytes());
+        assert!(error_message(load(&without_chunks)).contains("no JSON chunk"));
+    }
+}
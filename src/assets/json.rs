@@ -0,0 +1,388 @@
+use std::collections::BTreeMap;
+
+// Arrays and objects nested deeper than this are rejected instead of overflowing the stack,
+// documents can come from untrusted files
+const MAX_DEPTH: usize = 128;
+
+// Minimal JSON parser, just enough for glTF documents and shader program manifests
+#[derive(Clone, PartialEq, Debug)]
+pub enum JsonValue {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<JsonValue>),
+    Object(BTreeMap<String, JsonValue>)
+}
+
+impl JsonValue {
+    // Returns the byte offset at which parsing failed on error
+    pub fn parse(text: &str) -> Result<Self, usize> {
+        let mut parser = JsonParser {
+            bytes: text.as_bytes(),
+            position: 0,
+            depth: 0
+        };
+
+        let value = parser.parse_value()?;
+        parser.skip_whitespace();
+
+        if parser.position != parser.bytes.len() {
+            return Err(parser.position);
+        }
+
+        Ok(value)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&JsonValue> {
+        match self {
+            JsonValue::Object(members) => members.get(key),
+            _ => None
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[JsonValue]> {
+        match self {
+            JsonValue::Array(elements) => Some(elements),
+            _ => None
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            JsonValue::Number(number) => Some(*number),
+            _ => None
+        }
+    }
+
+    pub fn as_usize(&self) -> Option<usize> {
+        let number = self.as_f64()?;
+
+        if number >= 0.0 && number.fract() == 0.0 {
+            Some(number as usize)
+        }
+        else {
+            None
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            JsonValue::String(string) => Some(string),
+            _ => None
+        }
+    }
+}
+
+struct JsonParser<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    depth: usize
+}
+
+impl<'a> JsonParser<'a> {
+    fn parse_value(&mut self) -> Result<JsonValue, usize> {
+        self.skip_whitespace();
+
+        match self.peek() {
+            Some(b'{') => self.parse_nested(Self::parse_object),
+            Some(b'[') => self.parse_nested(Self::parse_array),
+            Some(b'"') => self.parse_string().map(JsonValue::String),
+            Some(b't') => self.parse_literal("true", JsonValue::Bool(true)),
+            Some(b'f') => self.parse_literal("false", JsonValue::Bool(false)),
+            Some(b'n') => self.parse_literal("null", JsonValue::Null),
+            Some(b'-') | Some(b'0'..=b'9') => self.parse_number(),
+            _ => Err(self.position)
+        }
+    }
+
+    // Fails at the opening bracket of the first array or object nested too deep
+    fn parse_nested(
+        &mut self,
+        parse: fn(&mut Self) -> Result<JsonValue, usize>
+    ) -> Result<JsonValue, usize> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.position);
+        }
+
+        self.depth += 1;
+        let value = parse(self);
+        self.depth -= 1;
+
+        value
+    }
+
+    fn parse_object(&mut self) -> Result<JsonValue, usize> {
+        self.expect(b'{')?;
+        let mut members = BTreeMap::new();
+        self.skip_whitespace();
+
+        if self.peek() == Some(b'}') {
+            self.position += 1;
+            return Ok(JsonValue::Object(members));
+        }
+
+        loop {
+            self.skip_whitespace();
+            let key = self.parse_string()?;
+            self.skip_whitespace();
+            self.expect(b':')?;
+            members.insert(key, self.parse_value()?);
+            self.skip_whitespace();
+
+            if self.expect(b'}').is_ok() {
+                return Ok(JsonValue::Object(members));
+            }
+
+            self.expect(b',')?;
+        }
+    }
+
+    fn parse_array(&mut self) -> Result<JsonValue, usize> {
+        self.expect(b'[')?;
+        let mut elements = Vec::new();
+        self.skip_whitespace();
+
+        if self.peek() == Some(b']') {
+            self.position += 1;
+            return Ok(JsonValue::Array(elements));
+        }
+
+        loop {
+            elements.push(self.parse_value()?);
+            self.skip_whitespace();
+
+            if self.expect(b']').is_ok() {
+                return Ok(JsonValue::Array(elements));
+            }
+
+            self.expect(b',')?;
+        }
+    }
+
+    // Bytes are copied as they are, the input is UTF-8 already
+    fn parse_string(&mut self) -> Result<String, usize> {
+        self.expect(b'"')?;
+        let mut bytes = Vec::new();
+
+        loop {
+            match self.next().ok_or(self.position)? {
+                b'"' => break,
+                b'\\' => {
+                    let escaped = match self.next().ok_or(self.position)? {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => self.parse_unicode_escape()?,
+                        _ => return Err(self.position - 1)
+                    };
+
+                    let mut buffer = [0; 4];
+                    bytes.extend_from_slice(escaped.encode_utf8(&mut buffer).as_bytes());
+                },
+                byte => bytes.push(byte)
+            }
+        }
+
+        String::from_utf8(bytes).map_err(|_| self.position)
+    }
+
+    // Characters outside of the basic multilingual plane are escaped as surrogate pairs.
+    // Errors point at the first hex digit of the offending code unit.
+    fn parse_unicode_escape(&mut self) -> Result<char, usize> {
+        let first_position = self.position;
+        let first = self.parse_hex_code_unit()?;
+
+        let code_point = if (0xd800..0xdc00).contains(&first) {
+            if self.bytes.get(self.position..self.position + 2) != Some(&b"\\u"[..]) {
+                return Err(self.position);
+            }
+
+            self.position += 2;
+            let second_position = self.position;
+            let second = self.parse_hex_code_unit()?;
+
+            if !(0xdc00..0xe000).contains(&second) {
+                return Err(second_position);
+            }
+
+            0x10000 + ((first - 0xd800) << 10) + (second - 0xdc00)
+        }
+        else {
+            first
+        };
+
+        // Low surrogates without a high one before them
+        std::char::from_u32(code_point).ok_or(first_position)
+    }
+
+    fn parse_hex_code_unit(&mut self) -> Result<u32, usize> {
+        let digits = self.bytes.get(self.position..self.position + 4).ok_or(self.position)?;
+        let digits = std::str::from_utf8(digits).map_err(|_| self.position)?;
+        let code_unit = u32::from_str_radix(digits, 16).map_err(|_| self.position)?;
+        self.position += 4;
+
+        Ok(code_unit)
+    }
+
+    fn parse_number(&mut self) -> Result<JsonValue, usize> {
+        let start = self.position;
+
+        while let Some(b'0'..=b'9') | Some(b'-') | Some(b'+') | Some(b'.') | Some(b'e')
+            | Some(b'E') = self.peek() {
+            self.position += 1;
+        }
+
+        std::str::from_utf8(&self.bytes[start..self.position])
+            .ok()
+            .and_then(|number| number.parse().ok())
+            .map(JsonValue::Number)
+            .ok_or(start)
+    }
+
+    fn parse_literal(&mut self, literal: &str, value: JsonValue) -> Result<JsonValue, usize> {
+        if !self.bytes[self.position..].starts_with(literal.as_bytes()) {
+            return Err(self.position);
+        }
+
+        self.position += literal.len();
+
+        Ok(value)
+    }
+
+    // Fails at the offending byte, or at the end of the text if it ended early
+    fn expect(&mut self, byte: u8) -> Result<(), usize> {
+        if self.peek() == Some(byte) {
+            self.position += 1;
+            Ok(())
+        }
+        else {
+            Err(self.position)
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ') | Some(b'\t') | Some(b'\n') | Some(b'\r') = self.peek() {
+            self.position += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.position).copied()
+    }
+
+    fn next(&mut self) -> Option<u8> {
+        let byte = self.peek()?;
+        self.position += 1;
+
+        Some(byte)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(text: &str) -> Result<String, usize> {
+        JsonValue::parse(text).map(|value| value.as_str().unwrap().to_owned())
+    }
+
+    #[test]
+    fn parses_nested_values() {
+        let value = JsonValue::parse(
+            r#" {"a": [1, -2.5e2, true, false, null], "b": {"c": "d"}, "e": {}, "f": []} "#)
+            .unwrap();
+
+        let a = value.get("a").and_then(JsonValue::as_array).unwrap();
+        assert_eq!(a[0], JsonValue::Number(1.0));
+        assert_eq!(a[1], JsonValue::Number(-250.0));
+        assert_eq!(&a[2..], &[JsonValue::Bool(true), JsonValue::Bool(false), JsonValue::Null]);
+        assert_eq!(value.get("b").and_then(|b| b.get("c")).and_then(JsonValue::as_str), Some("d"));
+        assert_eq!(value.get("e"), Some(&JsonValue::Object(BTreeMap::new())));
+        assert_eq!(value.get("f"), Some(&JsonValue::Array(Vec::new())));
+        assert_eq!(value.get("g"), None);
+    }
+
+    #[test]
+    fn converts_only_whole_non_negative_numbers_to_usize() {
+        assert_eq!(JsonValue::Number(3.0).as_usize(), Some(3));
+        assert_eq!(JsonValue::Number(3.5).as_usize(), None);
+        assert_eq!(JsonValue::Number(-1.0).as_usize(), None);
+        assert_eq!(JsonValue::Null.as_usize(), None);
+    }
+
+    #[test]
+    fn decodes_escapes() {
+        assert_eq!(string(r#""\"\\\/\b\f\n\r\t""#).unwrap(), "\"\\/\u{8}\u{c}\n\r\t");
+        assert_eq!(string(r#""caf\u00e9 \u0041""#).unwrap(), "café A");
+        assert_eq!(string(r#""\u017c\u00F3\u0142""#).unwrap(), "żół");
+    }
+
+    #[test]
+    fn decodes_surrogate_pairs() {
+        assert_eq!(string(r#""\ud83d\ude00""#).unwrap(), "😀");
+        assert_eq!(string(r#""\uD834\uDD1E!""#).unwrap(), "𝄞!");
+    }
+
+    #[test]
+    fn rejects_invalid_surrogates() {
+        // High surrogate without a low one, pointing where the `\u` should be
+        assert_eq!(string(r#""\ud83d""#), Err(7));
+        assert_eq!(string(r#""\ud83dx""#), Err(7));
+        // High surrogate followed by something other than a low one
+        assert_eq!(string(r#""\ud83d\u0041""#), Err(9));
+        assert_eq!(string(r#""\ud83d\ud83d""#), Err(9));
+        // Low surrogate on its own
+        assert_eq!(string(r#""\ude00""#), Err(3));
+    }
+
+    #[test]
+    fn reports_offsets_of_invalid_escapes() {
+        assert_eq!(string(r#""a\x""#), Err(3));
+        assert_eq!(string(r#""\u00g1""#), Err(3));
+        assert_eq!(string(r#""\u00""#), Err(3));
+    }
+
+    #[test]
+    fn reports_offsets_of_malformed_documents() {
+        assert_eq!(JsonValue::parse(""), Err(0));
+        assert_eq!(JsonValue::parse("   "), Err(3));
+        assert_eq!(JsonValue::parse(r#"{"a" 1}"#), Err(5));
+        assert_eq!(JsonValue::parse(r#"{"a": 1 "b": 2}"#), Err(8));
+        assert_eq!(JsonValue::parse(r#"{1: 2}"#), Err(1));
+        assert_eq!(JsonValue::parse("[1,]"), Err(3));
+        assert_eq!(JsonValue::parse("[1 2]"), Err(3));
+        assert_eq!(JsonValue::parse("tru"), Err(0));
+        assert_eq!(JsonValue::parse("[nul]"), Err(1));
+        assert_eq!(JsonValue::parse("-"), Err(0));
+        assert_eq!(JsonValue::parse("[1.2.3]"), Err(1));
+        assert_eq!(JsonValue::parse("1 x"), Err(2));
+    }
+
+    #[test]
+    fn limits_nesting_depth() {
+        let nested = |depth| "[".repeat(depth) + &"]".repeat(depth);
+        assert!(JsonValue::parse(&nested(MAX_DEPTH)).is_ok());
+        assert_eq!(JsonValue::parse(&nested(MAX_DEPTH + 1)), Err(MAX_DEPTH));
+
+        let objects = r#"{"a": "#.repeat(MAX_DEPTH + 1) + &"}".repeat(MAX_DEPTH + 1);
+        assert_eq!(JsonValue::parse(&objects), Err(MAX_DEPTH * 6));
+
+        // Deep enough to overflow the stack without the limit
+        assert_eq!(JsonValue::parse(&"[".repeat(1_000_000)), Err(MAX_DEPTH));
+    }
+
+    #[test]
+    fn reports_end_of_text_for_unterminated_documents() {
+        assert_eq!(JsonValue::parse("[1"), Err(2));
+        assert_eq!(JsonValue::parse("[1, "), Err(4));
+        assert_eq!(JsonValue::parse(r#"{"a""#), Err(4));
+        assert_eq!(JsonValue::parse(r#"{"a": 1"#), Err(7));
+        assert_eq!(JsonValue::parse(r#""abc"#), Err(4));
+    }
+}
@@ -0,0 +1,41 @@
+use custom_error::custom_error;
+use crate::rendering;
+
+custom_error!{pub AssetError
+    RenderingError {source: rendering::RenderingError} =
+        "encountered a rendering error while loading an asset: {source}",
+    FileReadError {path: String, source: std::io::Error} =
+        "failed to read {path}: {source}",
+    ImageDecodeError {path: String, source: image::ImageError} =
+        "failed to decode image {path}: {source}",
+    UnsupportedModelFormat {path: String} =
+        "{path} is not an OBJ or glTF model",
+    ObjParseError {path: String, line: usize, message: String} =
+        "failed to parse {path} at line {line}: {message}",
+    JsonParseError {path: String, offset: usize} =
+        "invalid JSON in {path} at byte {offset}",
+    ShaderProgramManifestError {path: String, message: String} =
+        "invalid shader program manifest {path}: {message}",
+    GltfParseError {path: String, message: String} =
+        "failed to parse glTF model {path}: {message}",
+    UnsupportedGltfFeature {path: String, feature: String} =
+        "glTF model {path} uses {feature}, which is not supported",
+    BuilderMissingField {builder: &'static str, field: &'static str} =
+        "{builder} was built without setting required field `{field}`"
+}
+
+pub type AssetResult<T> = Result<T, AssetError>;
+
+pub(crate) fn path_string(path: &std::path::Path) -> String {
+    path.display().to_string()
+}
+
+#[cfg(feature = "model-import")]
+pub mod model;
+#[cfg(feature = "model-import")]
+mod obj;
+#[cfg(feature = "model-import")]
+mod gltf;
+pub mod shader_program;
+pub mod cube_map;
+mod json;
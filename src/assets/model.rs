@@ -0,0 +1,353 @@
+use std::path::{
+    Path,
+    PathBuf
+};
+use image::RgbaImage;
+use nalgebra_glm as glm;
+use crate::{
+    builder::{
+        BuilderRequirement,
+        BuilderInternal,
+        BuilderProduct
+    },
+    assets::{
+        AssetError,
+        AssetResult,
+        obj,
+        gltf,
+        path_string
+    },
+    rendering::{
+        renderer::Renderer,
+        mesh::Mesh,
+        material::Material,
+        texture::Texture
+    },
+    vulkan::{
+        pipeline::VertexAttributeFormat,
+        shader::{
+            VertexShader,
+            FragmentShader
+        }
+    }
+};
+
+// Vertex layout of every imported mesh: position at location 0, normal at 1, UV at 2
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Default, Debug)]
+pub struct ModelVertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub uv: [f32; 2]
+}
+
+// Uniform parameters of imported materials, bound at set 0, binding 0
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ModelMaterialParameters {
+    pub base_color: [f32; 4]
+}
+
+// Model as read from a file, before anything is uploaded to the GPU
+#[derive(Default)]
+pub struct ModelData {
+    pub submeshes: Vec<SubmeshData>,
+    pub materials: Vec<MaterialData>
+}
+
+#[derive(Default)]
+pub struct SubmeshData {
+    pub vertices: Vec<ModelVertex>,
+    pub indices: Vec<u32>,
+    // Index into `ModelData::materials`, submeshes without one use a plain white material
+    pub material: Option<usize>
+}
+
+pub struct MaterialData {
+    pub name: String,
+    pub base_color: [f32; 4],
+    pub base_color_texture: Option<RgbaImage>
+}
+
+impl ModelData {
+    // The format is picked by the file extension: `.obj`, `.gltf` or `.glb`
+    pub fn load(path: &Path) -> AssetResult<Self> {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_ascii_lowercase());
+
+        let model = match extension.as_deref() {
+            Some("obj") => obj::load(path)?,
+            Some("gltf") | Some("glb") => gltf::load(path)?,
+            _ => return Err(AssetError::UnsupportedModelFormat {path: path_string(path)})
+        };
+
+        debug!(
+            "loaded model {}: {} submeshes, {} materials",
+            path.display(), model.submeshes.len(), model.materials.len());
+
+        Ok(model)
+    }
+}
+
+impl SubmeshData {
+    // Vertices without a normal get the area weighted average of their faces' normals
+    pub(crate) fn generate_missing_normals(&mut self) {
+        let is_missing: Vec<bool> = self.vertices
+            .iter()
+            .map(|vertex| vertex.normal == [0.0; 3])
+            .collect();
+
+        if !is_missing.contains(&true) {
+            return;
+        }
+
+        let mut normals = vec![glm::vec3(0.0, 0.0, 0.0); self.vertices.len()];
+        let position = |index: u32| glm::Vec3::from(self.vertices[index as usize].position);
+
+        for triangle in self.indices.chunks_exact(3) {
+            let edge_1 = position(triangle[1]) - position(triangle[0]);
+            let edge_2 = position(triangle[2]) - position(triangle[0]);
+            let face_normal = glm::cross(&edge_1, &edge_2);
+
+            for index in triangle {
+                normals[*index as usize] += face_normal;
+            }
+        }
+
+        for (index, vertex) in self.vertices.iter_mut().enumerate() {
+            if is_missing[index] && normals[index] != glm::vec3(0.0, 0.0, 0.0) {
+                vertex.normal = glm::normalize(&normals[index]).into();
+            }
+        }
+    }
+}
+
+pub struct Submesh {
+    pub mesh: Mesh,
+    // Index into `Model::materials`
+    pub material: usize
+}
+
+// Meshes and materials of a model file, ready to be given to render states.
+// Every material binds its parameters at binding 0 and its base color texture,
+// a single layer `sampler2DArray`, at binding 1.
+pub struct Model<'a> {
+    submeshes: Vec<Submesh>,
+    materials: Vec<Material<'a>>
+}
+
+impl<'a> Model<'a> {
+    pub const TEXTURE_BINDING: u32 = 1;
+
+    pub fn builder() -> ModelBuilder<'a> {
+        ModelBuilder {
+            ..Default::default()
+        }
+    }
+
+    pub fn submeshes(&self) -> &[Submesh] {
+        &self.submeshes
+    }
+
+    pub fn materials(&self) -> &[Material<'a>] {
+        &self.materials
+    }
+
+    pub fn material_of(&self, submesh: &Submesh) -> &Material<'a> {
+        &self.materials[submesh.material]
+    }
+}
+
+#[derive(Default)]
+pub struct ModelBuilder<'a> {
+    renderer: BuilderRequirement<&'a Renderer>,
+    path: BuilderRequirement<PathBuf>,
+    vertex_shader: BuilderRequirement<&'a VertexShader>,
+    fragment_shader: BuilderRequirement<&'a FragmentShader>,
+
+    model_data: BuilderInternal<ModelData>,
+    materials: BuilderInternal<Vec<Material<'a>>>,
+    submeshes: BuilderInternal<Vec<Submesh>>,
+
+    model: BuilderProduct<Model<'a>>
+}
+
+impl<'a> ModelBuilder<'a> {
+    pub fn renderer(mut self, renderer: &'a Renderer) -> Self {
+        self.renderer.set(renderer);
+        self
+    }
+
+    pub fn path(mut self, path: &Path) -> Self {
+        self.path.set(path.to_owned());
+        self
+    }
+
+    // Shaders have to accept `ModelVertex` attributes and the bindings described on `Model`
+    pub fn vertex_shader(mut self, shader: &'a VertexShader) -> Self {
+        self.vertex_shader.set(shader);
+        self
+    }
+
+    pub fn fragment_shader(mut self, shader: &'a FragmentShader) -> Self {
+        self.fragment_shader.set(shader);
+        self
+    }
+
+    pub fn build(mut self) -> AssetResult<Model<'a>> {
+        check_required_fields!(
+            self, AssetError, "ModelBuilder",
+            [renderer, path, vertex_shader, fragment_shader]);
+
+        self.init_model_data()?;
+        self.init_materials()?;
+        self.init_submeshes()?;
+        self.create_model();
+
+        Ok(self.model.unwrap())
+    }
+
+    fn init_model_data(&mut self) -> AssetResult<()> {
+        let model_data = ModelData::load(&self.path)?;
+        self.model_data.set(model_data);
+
+        Ok(())
+    }
+
+    // The last material is the white one used by submeshes without a material
+    fn init_materials(&mut self) -> AssetResult<()> {
+        let white = MaterialData {
+            name: String::from("default"),
+            base_color: [1.0; 4],
+            base_color_texture: None
+        };
+
+        let white_texture = RgbaImage::from_pixel(1, 1, image::Rgba([255; 4]));
+        let mut materials = Vec::with_capacity(self.model_data.materials.len() + 1);
+
+        for material_data in self.model_data.materials.iter().chain(std::iter::once(&white)) {
+            let texture = Texture::array_builder()
+                .renderer(&self.renderer)
+                .layer(material_data.base_color_texture.as_ref().unwrap_or(&white_texture))
+                .build()?;
+
+            let parameters = ModelMaterialParameters {
+                base_color: material_data.base_color
+            };
+
+            let material = Material::builder()
+                .renderer(&self.renderer)
+                .vertex_shader(&self.vertex_shader)
+                .fragment_shader(&self.fragment_shader)
+                .parameters(&parameters)
+                .texture(Model::TEXTURE_BINDING, &texture)
+                .build()?;
+
+            materials.push(material);
+        }
+
+        self.materials.set(materials);
+
+        Ok(())
+    }
+
+    fn init_submeshes(&mut self) -> AssetResult<()> {
+        let default_material = self.model_data.materials.len();
+        let mut submeshes = Vec::with_capacity(self.model_data.submeshes.len());
+
+        for submesh_data in &self.model_data.submeshes {
+            let mesh = Mesh::builder()
+                .renderer(&self.renderer)
+                .vertices(&submesh_data.vertices)
+                .indices(&submesh_data.indices)
+                .vertex_attribute(VertexAttributeFormat::F32(3), 0)
+                .vertex_attribute(VertexAttributeFormat::F32(3), 12)
+                .vertex_attribute(VertexAttributeFormat::F32(2), 24)
+                .build()?;
+
+            submeshes.push(Submesh {
+                mesh,
+                material: submesh_data.material.unwrap_or(default_material)
+            });
+        }
+
+        self.submeshes.set(submeshes);
+
+        Ok(())
+    }
+
+    fn create_model(&mut self) {
+        self.model.set(Model {
+            submeshes: self.submeshes.take(),
+            materials: self.materials.take()
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vertex(position: [f32; 3], normal: [f32; 3]) -> ModelVertex {
+        ModelVertex {
+            position,
+            normal,
+            uv: [0.0; 2]
+        }
+    }
+
+    #[test]
+    fn rejects_unsupported_formats() {
+        match ModelData::load(Path::new("model.fbx")) {
+            Err(AssetError::UnsupportedModelFormat {path}) => assert_eq!(path, "model.fbx"),
+            _ => panic!("expected an unsupported format error")
+        }
+
+        assert!(matches!(
+            ModelData::load(Path::new("model")),
+            Err(AssetError::UnsupportedModelFormat {..})));
+    }
+
+    #[test]
+    fn generates_only_missing_normals() {
+        let mut submesh = SubmeshData {
+            vertices: vec![
+                vertex([0.0, 0.0, 0.0], [0.0; 3]),
+                vertex([1.0, 0.0, 0.0], [0.0; 3]),
+                vertex([0.0, 1.0, 0.0], [1.0, 0.0, 0.0])
+            ],
+            indices: vec![0, 1, 2],
+            material: None
+        };
+
+        submesh.generate_missing_normals();
+
+        assert_eq!(submesh.vertices[0].normal, [0.0, 0.0, 1.0]);
+        assert_eq!(submesh.vertices[1].normal, [0.0, 0.0, 1.0]);
+        assert_eq!(submesh.vertices[2].normal, [1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn weights_generated_normals_by_face_area() {
+        // Two faces meeting at vertex 0, the one facing +Z being four times larger
+        let mut submesh = SubmeshData {
+            vertices: vec![
+                vertex([0.0, 0.0, 0.0], [0.0; 3]),
+                vertex([2.0, 0.0, 0.0], [0.0; 3]),
+                vertex([0.0, 2.0, 0.0], [0.0; 3]),
+                vertex([0.0, 0.0, 1.0], [0.0; 3]),
+                vertex([0.0, 1.0, 0.0], [0.0; 3])
+            ],
+            indices: vec![0, 1, 2, 0, 3, 4],
+            material: None
+        };
+
+        submesh.generate_missing_normals();
+
+        let normal = glm::Vec3::from(submesh.vertices[0].normal);
+        let expected = glm::normalize(&glm::vec3(-1.0, 0.0, 4.0));
+        assert!((normal - expected).norm() < 1e-6);
+    }
+}
@@ -0,0 +1,422 @@
+use std::{
+    collections::HashMap,
+    path::Path
+};
+use crate::assets::{
+    AssetError,
+    AssetResult,
+    model::{
+        ModelData,
+        ModelVertex,
+        SubmeshData,
+        MaterialData
+    },
+    path_string
+};
+
+// Wavefront OBJ with MTL materials. Polygons are triangulated as fans, so they should be
+// convex. Every `usemtl` starts a new submesh, groups and objects are merged.
+pub fn load(path: &Path) -> AssetResult<ModelData> {
+    parse(path, &read_to_string(path)?)
+}
+
+// Material libraries are read relative to `path`
+fn parse(path: &Path, text: &str) -> AssetResult<ModelData> {
+    let mut parser = ObjParser::new(path);
+
+    for (line_index, line) in text.lines().enumerate() {
+        parser.line = line_index + 1;
+        parser.parse_line(line)?;
+    }
+
+    parser.finish_submesh();
+
+    Ok(ModelData {
+        submeshes: parser.submeshes,
+        materials: parser.materials
+    })
+}
+
+fn read_to_string(path: &Path) -> AssetResult<String> {
+    std::fs::read_to_string(path)
+        .map_err(|source| AssetError::FileReadError {path: path_string(path), source})
+}
+
+// Position, UV and normal indices of a face corner
+type CornerKey = (usize, Option<usize>, Option<usize>);
+
+struct ObjParser<'a> {
+    path: &'a Path,
+    line: usize,
+    positions: Vec<[f32; 3]>,
+    normals: Vec<[f32; 3]>,
+    uvs: Vec<[f32; 2]>,
+    materials: Vec<MaterialData>,
+    submeshes: Vec<SubmeshData>,
+    submesh: SubmeshData,
+    // Corners already added to the current submesh, so that vertices are shared
+    corners: HashMap<CornerKey, u32>
+}
+
+impl<'a> ObjParser<'a> {
+    fn new(path: &'a Path) -> Self {
+        Self {
+            path,
+            line: 0,
+            positions: Vec::new(),
+            normals: Vec::new(),
+            uvs: Vec::new(),
+            materials: Vec::new(),
+            submeshes: Vec::new(),
+            submesh: SubmeshData::default(),
+            corners: HashMap::new()
+        }
+    }
+
+    fn parse_line(&mut self, line: &str) -> AssetResult<()> {
+        let line = line.split('#').next().unwrap_or("");
+        let mut tokens = line.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) => keyword,
+            None => return Ok(())
+        };
+
+        match keyword {
+            "v" => {
+                let position = parse_floats(tokens, 3).map_err(|message| self.error(message))?;
+                self.positions.push([position[0], position[1], position[2]]);
+            },
+            "vn" => {
+                let normal = parse_floats(tokens, 3).map_err(|message| self.error(message))?;
+                self.normals.push([normal[0], normal[1], normal[2]]);
+            },
+            "vt" => {
+                let uv = parse_floats(tokens, 2).map_err(|message| self.error(message))?;
+                // OBJ's V axis points up, Vulkan's down
+                self.uvs.push([uv[0], 1.0 - uv[1]]);
+            },
+            "f" => self.parse_face(tokens).map_err(|message| self.error(message))?,
+            "usemtl" => self.use_material(tokens.next().unwrap_or("")),
+            "mtllib" => {
+                let directory = self.path.parent().unwrap_or_else(|| Path::new(""));
+
+                for library in tokens {
+                    self.materials.extend(load_materials(&directory.join(library))?);
+                }
+            },
+            // Grouping, smoothing groups, lines, points and the like do not affect triangles
+            _ => ()
+        }
+
+        Ok(())
+    }
+
+    fn parse_face<'b, I: Iterator<Item = &'b str>>(&mut self, corners: I) -> Result<(), String> {
+        let corners = corners
+            .map(|corner| self.parse_corner(corner))
+            .collect::<Result<Vec<u32>, String>>()?;
+
+        if corners.len() < 3 {
+            return Err(String::from("face has less than 3 vertices"));
+        }
+
+        for index in 1..corners.len() - 1 {
+            self.submesh.indices.extend(&[corners[0], corners[index], corners[index + 1]]);
+        }
+
+        Ok(())
+    }
+
+    // Corners are written as `v`, `v/vt`, `v//vn` or `v/vt/vn`
+    fn parse_corner(&mut self, corner: &str) -> Result<u32, String> {
+        let mut indices = corner.split('/');
+        let position = resolve_index(indices.next(), self.positions.len())?
+            .ok_or_else(|| format!("face corner `{}` has no position", corner))?;
+        let uv = resolve_index(indices.next(), self.uvs.len())?;
+        let normal = resolve_index(indices.next(), self.normals.len())?;
+        let key = (position, uv, normal);
+
+        if let Some(index) = self.corners.get(&key) {
+            return Ok(*index);
+        }
+
+        let index = self.submesh.vertices.len() as u32;
+        self.submesh.vertices.push(ModelVertex {
+            position: self.positions[position],
+            normal: normal.map_or([0.0; 3], |normal| self.normals[normal]),
+            uv: uv.map_or([0.0; 2], |uv| self.uvs[uv])
+        });
+        self.corners.insert(key, index);
+
+        Ok(index)
+    }
+
+    fn use_material(&mut self, name: &str) {
+        let material = self.materials.iter().position(|material| material.name == name);
+
+        if material.is_none() {
+            warn!("{} uses undefined material `{}`", self.path.display(), name);
+        }
+
+        if material != self.submesh.material {
+            self.finish_submesh();
+            self.submesh.material = material;
+        }
+    }
+
+    fn finish_submesh(&mut self) {
+        let material = self.submesh.material;
+        let mut submesh = std::mem::take(&mut self.submesh);
+        self.submesh.material = material;
+        self.corners.clear();
+
+        if !submesh.indices.is_empty() {
+            submesh.generate_missing_normals();
+            self.submeshes.push(submesh);
+        }
+    }
+
+    fn error(&self, message: String) -> AssetError {
+        AssetError::ObjParseError {
+            path: path_string(self.path),
+            line: self.line,
+            message
+        }
+    }
+}
+
+// Only the diffuse color, its texture and the opacity are used
+fn load_materials(path: &Path) -> AssetResult<Vec<MaterialData>> {
+    let text = read_to_string(path)?;
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut materials: Vec<MaterialData> = Vec::new();
+
+    for (line_index, line) in text.lines().enumerate() {
+        let error = |message: String| AssetError::ObjParseError {
+            path: path_string(path),
+            line: line_index + 1,
+            message
+        };
+
+        let line = line.split('#').next().unwrap_or("");
+        let mut tokens = line.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) => keyword,
+            None => continue
+        };
+
+        if keyword == "newmtl" {
+            materials.push(MaterialData {
+                name: tokens.next().unwrap_or("").to_owned(),
+                base_color: [1.0; 4],
+                base_color_texture: None
+            });
+            continue;
+        }
+
+        let material = match materials.last_mut() {
+            Some(material) => material,
+            None => continue
+        };
+
+        match keyword {
+            "Kd" => {
+                let color = parse_floats(tokens, 3).map_err(error)?;
+                material.base_color[..3].copy_from_slice(&color);
+            },
+            "d" => material.base_color[3] = parse_floats(tokens, 1).map_err(error)?[0],
+            "Tr" => material.base_color[3] = 1.0 - parse_floats(tokens, 1).map_err(error)?[0],
+            "map_Kd" => {
+                // Options may precede the file name, which comes last
+                let texture_path = directory.join(tokens.last().unwrap_or(""));
+                let texture = image::open(&texture_path)
+                    .map_err(|source| AssetError::ImageDecodeError {
+                        path: path_string(&texture_path),
+                        source
+                    })?;
+
+                material.base_color_texture = Some(texture.to_rgba());
+            },
+            _ => ()
+        }
+    }
+
+    Ok(materials)
+}
+
+fn parse_floats<'a, I: Iterator<Item = &'a str>>(
+    tokens: I,
+    count: usize
+) -> Result<Vec<f32>, String> {
+    let floats = tokens
+        .take(count)
+        .map(|token| token.parse().map_err(|_| format!("`{}` is not a number", token)))
+        .collect::<Result<Vec<f32>, String>>()?;
+
+    if floats.len() < count {
+        return Err(format!("expected {} numbers, found {}", count, floats.len()));
+    }
+
+    Ok(floats)
+}
+
+// Indices start at 1, negative ones count back from the last element defined so far
+fn resolve_index(index: Option<&str>, length: usize) -> Result<Option<usize>, String> {
+    let index = match index {
+        Some(index) if !index.is_empty() => index,
+        _ => return Ok(None)
+    };
+
+    let parsed: i64 = index.parse().map_err(|_| format!("`{}` is not an index", index))?;
+    let resolved = if parsed < 0 {
+        length as i64 + parsed
+    }
+    else {
+        parsed - 1
+    };
+
+    if resolved < 0 || resolved >= length as i64 {
+        return Err(format!("index {} is out of range", parsed));
+    }
+
+    Ok(Some(resolved as usize))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_text(text: &str) -> AssetResult<ModelData> {
+        parse(Path::new("model.obj"), text)
+    }
+
+    fn positions(submesh: &SubmeshData) -> Vec<[f32; 3]> {
+        submesh.indices
+            .iter()
+            .map(|index| submesh.vertices[*index as usize].position)
+            .collect()
+    }
+
+    fn error_line(result: AssetResult<ModelData>) -> (usize, String) {
+        match result {
+            Err(AssetError::ObjParseError {line, message, ..}) => (line, message),
+            _ => panic!("expected an OBJ parse error")
+        }
+    }
+
+    const SQUARE: &str = "
+        v 0 0 0
+        v 1 0 0
+        v 1 1 0
+        v 0 1 0
+    ";
+
+    #[test]
+    fn triangulates_polygons_as_fans() {
+        let model = parse_text(&format!("{}\nv 0.5 2 0\nf 1 2 3 4\nf 1 2 3 5 4", SQUARE))
+            .unwrap();
+        let submesh = &model.submeshes[0];
+
+        assert_eq!(submesh.vertices.len(), 5);
+        assert_eq!(&submesh.indices[..6], &[0, 1, 2, 0, 2, 3]);
+        assert_eq!(&submesh.indices[6..], &[0, 1, 2, 0, 2, 4, 0, 4, 3]);
+    }
+
+    #[test]
+    fn resolves_negative_indices_relative_to_elements_defined_so_far() {
+        let model = parse_text("
+            v 0 0 0
+            v 1 0 0
+            v 0 1 0
+            f -3 -2 -1
+            v 5 0 0
+            v 6 0 0
+            v 5 1 0
+            f -3 -2 -1
+            f 1 -3 3
+        ").unwrap();
+
+        assert_eq!(positions(&model.submeshes[0]), vec![
+            [0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0],
+            [5.0, 0.0, 0.0], [6.0, 0.0, 0.0], [5.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0], [5.0, 0.0, 0.0], [0.0, 1.0, 0.0]
+        ]);
+    }
+
+    #[test]
+    fn reads_uvs_and_normals_of_corners() {
+        let model = parse_text(&format!("{}
+            vt 0 0
+            vt 1 0.25
+            vn 0 0 1
+            f 1/1/1 2/-1/-1 3//1
+        ", SQUARE)).unwrap();
+        let vertices = &model.submeshes[0].vertices;
+
+        // V is flipped to point down
+        assert_eq!(vertices[0].uv, [0.0, 1.0]);
+        assert_eq!(vertices[1].uv, [1.0, 0.75]);
+        assert_eq!(vertices[2].uv, [0.0, 0.0]);
+        assert!(vertices.iter().all(|vertex| vertex.normal == [0.0, 0.0, 1.0]));
+    }
+
+    #[test]
+    fn shares_vertices_of_identical_corners() {
+        let model = parse_text(&format!("{}\nvt 0 0\nf 1 2 3\nf 1 3 4\nf 1/1 3 4", SQUARE))
+            .unwrap();
+
+        // `1/1` differs from `1` by its UV
+        assert_eq!(model.submeshes[0].vertices.len(), 5);
+    }
+
+    #[test]
+    fn generates_missing_normals() {
+        let model = parse_text(&format!("{}\nf 1 2 3 4", SQUARE)).unwrap();
+
+        for vertex in &model.submeshes[0].vertices {
+            assert_eq!(vertex.normal, [0.0, 0.0, 1.0]);
+        }
+    }
+
+    #[test]
+    fn ignores_comments_and_unsupported_statements() {
+        let text = format!("# header\n{}\ng group\ns 1\nl 1 2\nf 1 2 3 # tail", SQUARE);
+        let model = parse_text(&text).unwrap();
+
+        assert_eq!(model.submeshes.len(), 1);
+        assert_eq!(model.submeshes[0].indices.len(), 3);
+    }
+
+    #[test]
+    fn starts_submeshes_only_when_the_material_changes() {
+        let model = parse_text(&format!(
+            "{}\nusemtl missing\nf 1 2 3\nusemtl missing\nf 1 3 4", SQUARE)).unwrap();
+
+        assert_eq!(model.submeshes.len(), 1);
+        assert_eq!(model.submeshes[0].material, None);
+        assert_eq!(model.submeshes[0].indices.len(), 6);
+    }
+
+    #[test]
+    fn reports_lines_of_errors() {
+        let (line, message) = error_line(parse_text("v 0 0 0\nv 1 0 0\nf 1 2"));
+        assert_eq!(line, 3);
+        assert!(message.contains("less than 3"));
+
+        let (line, message) = error_line(parse_text("v 0 0 0\nv 1 x 0"));
+        assert_eq!(line, 2);
+        assert!(message.contains("`x` is not a number"));
+
+        let (line, message) = error_line(parse_text("v 0 0"));
+        assert_eq!(line, 1);
+        assert!(message.contains("expected 3 numbers"));
+    }
+
+    #[test]
+    fn rejects_out_of_range_indices() {
+        for face in &["f 1 2 5", "f 0 1 2", "f -5 1 2", "f 1/1 2 3", "f 1//2 2 3", "f a 2 3"] {
+            let (line, _) = error_line(parse_text(&format!("{}\nvn 0 0 1\n{}", SQUARE, face)));
+            assert_eq!(line, 8, "{}", face);
+        }
+    }
+}
@@ -0,0 +1,383 @@
+use std::{
+    fs,
+    path::Path,
+    rc::Rc
+};
+use crate::{
+    assets::{
+        AssetError,
+        AssetResult,
+        json::JsonValue,
+        path_string
+    },
+    rendering::{
+        RenderingError,
+        mesh::{
+            Mesh,
+            VertexAttribute
+        }
+    },
+    vulkan::{
+        VulkanResult,
+        logical_device::LogicalDevice,
+        pipeline::VertexAttributeFormat,
+        shader::{
+            GeometryShader,
+            VertexShader,
+            FragmentShader,
+            ShaderStage
+        }
+    }
+};
+
+// Shader stages of a program together with the layout they are written against,
+// described by a JSON manifest like
+//
+// {
+//     "stages": {"vertex": "sprite.vert.spv", "fragment": "sprite.frag.spv"},
+//     "vertex_layout": {
+//         "stride": 16,
+//         "attributes": [{"format": "f32x2", "offset": 0}, {"format": "f32x2", "offset": 8}]
+//     },
+//     "push_constants": {"vertex": 64},
+//     "bindings": [{"binding": 1, "type": "combined_image_sampler", "stages": ["fragment"]}]
+// }
+//
+// Stage paths are relative to the manifest. Everything but the stages is optional,
+// materials and render states built from the program are checked only against what is given.
+pub struct ShaderProgram {
+    geometry_shader: Option<GeometryShader>,
+    vertex_shader: Option<VertexShader>,
+    fragment_shader: Option<FragmentShader>,
+    vertex_layout: Option<VertexLayout>,
+    push_constants_sizes: Vec<(ShaderStage, usize)>,
+    bindings: Option<Vec<ProgramBinding>>
+}
+
+#[derive(Clone)]
+pub struct VertexLayout {
+    pub stride: usize,
+    pub attributes: Vec<VertexAttribute>
+}
+
+impl VertexLayout {
+    pub fn matches(&self, mesh: &Mesh) -> bool {
+        self.stride == mesh.vertex_stride()
+            && self.attributes.len() == mesh.vertex_attributes().len()
+            && self.attributes
+                .iter()
+                .zip(mesh.vertex_attributes())
+                .all(|(attribute, other)| {
+                    attribute.format == other.format && attribute.offset == other.offset
+                })
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BindingType {
+    UniformBuffer,
+    CombinedImageSampler
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ProgramBinding {
+    pub binding: u32,
+    pub binding_type: BindingType,
+    pub stages: Vec<ShaderStage>
+}
+
+impl ShaderProgram {
+    pub fn load(logical_device: Rc<LogicalDevice>, path: &Path) -> AssetResult<Self> {
+        let manifest = Self::read_manifest(path)?;
+
+        let parser = ManifestParser {
+            path,
+            logical_device
+        };
+
+        let program = Self {
+            geometry_shader: parser.stage(&manifest, "geometry", GeometryShader::from_file)?,
+            vertex_shader: parser.stage(&manifest, "vertex", VertexShader::from_file)?,
+            fragment_shader: parser.stage(&manifest, "fragment", FragmentShader::from_file)?,
+            vertex_layout: parser.vertex_layout(&manifest)?,
+            push_constants_sizes: parser.push_constants_sizes(&manifest)?,
+            bindings: parser.bindings(&manifest)?
+        };
+
+        let has_stages = program.geometry_shader.is_some()
+            || program.vertex_shader.is_some()
+            || program.fragment_shader.is_some();
+
+        if !has_stages {
+            return Err(parser.error("no shader stages are given"));
+        }
+
+        debug!("loaded shader program {}", path.display());
+
+        Ok(program)
+    }
+
+    fn read_manifest(path: &Path) -> AssetResult<JsonValue> {
+        let text = fs::read_to_string(path)
+            .map_err(|source| AssetError::FileReadError {path: path_string(path), source})?;
+
+        JsonValue::parse(&text)
+            .map_err(|offset| AssetError::JsonParseError {path: path_string(path), offset})
+    }
+
+    pub fn geometry_shader(&self) -> Option<&GeometryShader> {
+        self.geometry_shader.as_ref()
+    }
+
+    pub fn vertex_shader(&self) -> Option<&VertexShader> {
+        self.vertex_shader.as_ref()
+    }
+
+    pub fn fragment_shader(&self) -> Option<&FragmentShader> {
+        self.fragment_shader.as_ref()
+    }
+
+    pub fn vertex_layout(&self) -> Option<&VertexLayout> {
+        self.vertex_layout.as_ref()
+    }
+
+    // None for stages whose push constants the manifest does not declare
+    pub fn push_constants_size(&self, stage: ShaderStage) -> Option<usize> {
+        self.push_constants_sizes
+            .iter()
+            .find(|(declared_stage, _)| *declared_stage == stage)
+            .map(|(_, size)| *size)
+    }
+
+    // None if the manifest does not declare any bindings
+    pub fn bindings(&self) -> Option<&[ProgramBinding]> {
+        self.bindings.as_deref()
+    }
+
+    // Any binding is accepted if the manifest does not declare them
+    pub fn accepts_binding(&self, binding: u32, binding_type: BindingType) -> bool {
+        match &self.bindings {
+            Some(bindings) => bindings.iter().any(|declared| {
+                declared.binding == binding && declared.binding_type == binding_type
+            }),
+            None => true
+        }
+    }
+
+    pub fn binding_stages(&self, binding: u32) -> Option<&[ShaderStage]> {
+        self.bindings
+            .as_ref()?
+            .iter()
+            .find(|declared| declared.binding == binding)
+            .map(|declared| declared.stages.as_slice())
+    }
+}
+
+struct ManifestParser<'a> {
+    path: &'a Path,
+    logical_device: Rc<LogicalDevice>
+}
+
+impl<'a> ManifestParser<'a> {
+    fn stage<S, L>(
+        &self,
+        manifest: &JsonValue,
+        name: &str,
+        load: L
+    ) -> AssetResult<Option<S>> where
+        L: FnOnce(Rc<LogicalDevice>, &Path) -> VulkanResult<S> {
+        let file = match manifest.get("stages").and_then(|stages| stages.get(name)) {
+            Some(file) => file
+                .as_str()
+                .ok_or_else(|| self.error(&format!("`stages.{}` is not a path", name)))?,
+            None => return Ok(None)
+        };
+
+        let directory = self.path.parent().unwrap_or_else(|| Path::new(""));
+        let shader = load(Rc::clone(&self.logical_device), &directory.join(file))
+            .map_err(RenderingError::from)?;
+
+        Ok(Some(shader))
+    }
+
+    fn vertex_layout(&self, manifest: &JsonValue) -> AssetResult<Option<VertexLayout>> {
+        let layout = match manifest.get("vertex_layout") {
+            Some(layout) => layout,
+            None => return Ok(None)
+        };
+
+        let stride = layout
+            .get("stride")
+            .and_then(JsonValue::as_usize)
+            .ok_or_else(|| self.error("`vertex_layout.stride` is missing or not a size"))?;
+
+        let attributes = layout
+            .get("attributes")
+            .and_then(JsonValue::as_array)
+            .ok_or_else(|| self.error("`vertex_layout.attributes` is missing or not an array"))?
+            .iter()
+            .map(|attribute| self.vertex_attribute(attribute))
+            .collect::<AssetResult<_>>()?;
+
+        Ok(Some(VertexLayout {
+            stride,
+            attributes
+        }))
+    }
+
+    fn vertex_attribute(&self, attribute: &JsonValue) -> AssetResult<VertexAttribute> {
+        let format = attribute.get("format").and_then(JsonValue::as_str);
+        let format = format
+            .and_then(parse_attribute_format)
+            .ok_or_else(|| self.error(&format!(
+                "vertex attribute format {} is not one of i32, u32, f32 or f64 \
+                with an optional x2, x3 or x4 suffix",
+                format.unwrap_or("(missing)"))))?;
+
+        let offset = attribute
+            .get("offset")
+            .and_then(JsonValue::as_usize)
+            .ok_or_else(|| self.error("vertex attribute offset is missing or not a size"))?;
+
+        Ok(VertexAttribute {
+            format,
+            offset
+        })
+    }
+
+    fn push_constants_sizes(
+        &self,
+        manifest: &JsonValue
+    ) -> AssetResult<Vec<(ShaderStage, usize)>> {
+        let push_constants = match manifest.get("push_constants") {
+            Some(push_constants) => push_constants,
+            None => return Ok(Vec::new())
+        };
+
+        let mut sizes = Vec::new();
+
+        for stage in ShaderStage::ALL.iter() {
+            if let Some(size) = push_constants.get(stage_name(*stage)) {
+                let size = size.as_usize().ok_or_else(|| self.error(&format!(
+                    "`push_constants.{}` is not a size", stage_name(*stage))))?;
+
+                sizes.push((*stage, size));
+            }
+        }
+
+        Ok(sizes)
+    }
+
+    fn bindings(&self, manifest: &JsonValue) -> AssetResult<Option<Vec<ProgramBinding>>> {
+        let bindings = match manifest.get("bindings") {
+            Some(bindings) => bindings
+                .as_array()
+                .ok_or_else(|| self.error("`bindings` is not an array"))?,
+            None => return Ok(None)
+        };
+
+        let bindings = bindings
+            .iter()
+            .map(|binding| self.binding(binding))
+            .collect::<AssetResult<_>>()?;
+
+        Ok(Some(bindings))
+    }
+
+    fn binding(&self, binding: &JsonValue) -> AssetResult<ProgramBinding> {
+        let index = binding
+            .get("binding")
+            .and_then(JsonValue::as_usize)
+            .ok_or_else(|| self.error("binding index is missing or not an index"))?;
+
+        let binding_type = match binding.get("type").and_then(JsonValue::as_str) {
+            Some("uniform_buffer") => BindingType::UniformBuffer,
+            Some("combined_image_sampler") => BindingType::CombinedImageSampler,
+            _ => return Err(self.error(&format!(
+                "binding {} is neither a uniform_buffer nor a combined_image_sampler", index)))
+        };
+
+        let stages = binding
+            .get("stages")
+            .and_then(JsonValue::as_array)
+            .ok_or_else(|| self.error(&format!("stages of binding {} are missing", index)))?
+            .iter()
+            .map(|stage| stage
+                .as_str()
+                .and_then(parse_stage)
+                .ok_or_else(|| self.error(&format!("binding {} has an unknown stage", index))))
+            .collect::<AssetResult<_>>()?;
+
+        Ok(ProgramBinding {
+            binding: index as u32,
+            binding_type,
+            stages
+        })
+    }
+
+    fn error(&self, message: &str) -> AssetError {
+        AssetError::ShaderProgramManifestError {
+            path: path_string(self.path),
+            message: message.to_owned()
+        }
+    }
+}
+
+fn stage_name(stage: ShaderStage) -> &'static str {
+    match stage {
+        ShaderStage::Geometry => "geometry",
+        ShaderStage::Vertex => "vertex",
+        ShaderStage::Fragment => "fragment",
+        ShaderStage::Compute => "compute",
+        ShaderStage::RayGen => "ray_gen",
+        ShaderStage::Miss => "miss",
+        ShaderStage::ClosestHit => "closest_hit",
+        ShaderStage::AnyHit => "any_hit"
+    }
+}
+
+fn parse_stage(name: &str) -> Option<ShaderStage> {
+    ShaderStage::ALL.iter().copied().find(|stage| stage_name(*stage) == name)
+}
+
+// e.g. `f32x3`, a missing suffix means a single component
+fn parse_attribute_format(format: &str) -> Option<VertexAttributeFormat> {
+    let (component, count) = match format.find('x') {
+        Some(separator) => (&format[..separator], format[separator + 1..].parse().ok()?),
+        None => (format, 1)
+    };
+
+    if !(1..=4).contains(&count) {
+        return None;
+    }
+
+    match component {
+        "i32" => Some(VertexAttributeFormat::I32(count)),
+        "u32" => Some(VertexAttributeFormat::U32(count)),
+        "f32" => Some(VertexAttributeFormat::F32(count)),
+        "f64" => Some(VertexAttributeFormat::F64(count)),
+        _ => None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_deeply_nested_manifests() {
+        let path = std::env::temp_dir()
+            .join(format!("magmacraft-nested-{}.program.json", std::process::id()));
+
+        let manifest = r#"{"vertex": "#.to_owned() + &"[".repeat(100_000) + &"]".repeat(100_000)
+            + "}";
+        fs::write(&path, manifest).unwrap();
+        let result = ShaderProgram::read_manifest(&path);
+        fs::remove_file(&path).unwrap();
+
+        match result {
+            Err(AssetError::JsonParseError {offset, ..}) => assert!(offset < 1000),
+            _ => panic!("deeply nested manifest was not rejected")
+        }
+    }
+}
@@ -15,6 +15,10 @@ macro_rules! declare_builder_field {
                 self.0 = Some(owned);
             }
 
+            pub fn is_set(&self) -> bool {
+                self.0.is_some()
+            }
+
             pub fn take(&mut self) -> T {
                 let error_message = format!("{} {}", Self::ERROR_MESSAGE, stringify!($name));
                 self.0.take().expect(&error_message)
@@ -64,10 +68,6 @@ declare_builder_field!(BuilderRequirement);
 pub struct BuilderProduct<T> (Option<T>);
 
 impl<T> BuilderProduct<T> {
-    pub fn none() -> Self {
-        Self(None)
-    }
-
     pub fn set(&mut self, owned: T) {
         self.0 = Some(owned)
     }
@@ -80,3 +80,16 @@ impl<T> BuilderProduct<T> {
 impl<T> Default for BuilderProduct<T> {
     fn default() -> Self { Self(None) }
 }
+
+macro_rules! check_required_fields {
+    ($builder:expr, $error:ident, $builder_name:expr, [$($field:ident),+]) => {
+        $(
+            if !$builder.$field.is_set() {
+                return Err($error::BuilderMissingField {
+                    builder: $builder_name,
+                    field: stringify!($field)
+                });
+            }
+        )+
+    }
+}
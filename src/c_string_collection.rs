@@ -8,6 +8,8 @@ macro_rules! create_c_string_collection_type {
             pointers: Vec<*const std::os::raw::c_char>
         }
 
+        // Not every collection type uses every method
+        #[allow(dead_code)]
         impl $name {
             pub fn new() -> Self {
                 Self {
@@ -71,6 +73,10 @@ macro_rules! create_c_string_collection_type {
             pub fn len(&self) -> usize {
                 self.strings.len()
             }
+
+            pub fn is_empty(&self) -> bool {
+                self.strings.is_empty()
+            }
         }
 
         impl Clone for $name {
@@ -88,8 +94,6 @@ macro_rules! create_c_string_collection_type {
     }
 }
 
-create_c_string_collection_type!(CStringCollection);
-
 macro_rules! c_string_collection {
     ($collection:ident: [$($item:expr),+]) => {
         {
@@ -8,8 +8,11 @@ use std::{
     marker::PhantomData
 };
 
-pub struct DoubleTypeBuffer<T, U> {
+// Owned allocation which can be viewed as a slice of either of two types.
+// Views borrow the buffer, so a mutable view of one type cannot alias a view of the other.
+pub(crate) struct DoubleTypeBuffer<T, U> {
     buffer: *mut u8,
+    size: usize,
     layout: alloc::Layout,
     phantom_t: PhantomData<T>,
     phantom_u: PhantomData<U>
@@ -25,20 +28,24 @@ impl<T, U> DoubleTypeBuffer<T, U> {
         let size_w = size_of::<W>();
         let size = std::cmp::max(size_v * length_v, size_w * length_w);
 
-        unsafe {
-            let layout = alloc::Layout::from_size_align_unchecked(size, alignment);
-            let buffer = alloc::alloc(layout);
+        // Zero-sized allocations are undefined behaviour, so at least one byte is allocated
+        let layout = alloc::Layout::from_size_align(std::cmp::max(size, 1), alignment)
+            .expect("invalid double type buffer layout");
+
+        // Zeroed, so that views of either type never expose uninitialized memory
+        let buffer = unsafe {
+            alloc::alloc_zeroed(layout)
+        };
 
-            Self {
-                buffer, layout,
-                phantom_t: PhantomData,
-                phantom_u: PhantomData
-            }
+        if buffer.is_null() {
+            alloc::handle_alloc_error(layout);
         }
-    }
 
-    pub fn as_slice_first(&self) -> &[T] {
-        self.as_slice::<T>()
+        Self {
+            buffer, size, layout,
+            phantom_t: PhantomData,
+            phantom_u: PhantomData
+        }
     }
 
     pub fn as_mut_slice_first(&mut self) -> &mut [T] {
@@ -49,21 +56,17 @@ impl<T, U> DoubleTypeBuffer<T, U> {
         self.as_slice::<U>()
     }
 
-    pub fn as_mut_slice_second(&mut self) -> &mut [U] {
-        self.as_mut_slice::<U>()
-    }
-
     fn as_slice<V>(&self) -> &[V] {
         let buffer = self.buffer as *const V;
-        let length = self.layout.size() / size_of::<V>();
+        let length = self.size / size_of::<V>();
         unsafe {
             slice::from_raw_parts(buffer, length)
         }
     }
 
-    fn as_mut_slice<V>(&self) -> &mut [V] {
+    fn as_mut_slice<V>(&mut self) -> &mut [V] {
         let buffer = self.buffer as *mut V;
-        let length = self.layout.size() / size_of::<V>();
+        let length = self.size / size_of::<V>();
         unsafe {
             slice::from_raw_parts_mut(buffer, length)
         }
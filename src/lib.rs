@@ -0,0 +1,25 @@
+#[macro_use]
+extern crate log;
+// Lets code generated by magmacraft_derive refer to this crate by name from inside of it too
+extern crate self as magmacraft;
+#[macro_use]
+mod c_string_collection;
+#[macro_use]
+mod builder;
+mod double_type_buffer;
+pub mod window;
+pub mod app;
+// Both layers stay public, as the renderer hands out pipelines, images and command buffer
+// recorders of the Vulkan layer; `prelude` is the part covered by the compatibility promise
+pub mod rendering;
+pub mod vulkan;
+pub mod voxel;
+pub mod assets;
+pub mod debugging;
+pub mod logging;
+pub mod metrics;
+pub mod profiling;
+#[cfg(feature = "renderdoc")]
+pub mod renderdoc;
+pub mod soak;
+pub mod prelude;
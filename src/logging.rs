@@ -0,0 +1,45 @@
+use log::{
+    Log,
+    Level,
+    LevelFilter,
+    Metadata,
+    Record,
+    SetLoggerError
+};
+
+static LOGGER: StderrLogger = StderrLogger;
+
+struct StderrLogger;
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!("[{} {}] {}", Self::level_name(record.level()), record.target(), record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+impl StderrLogger {
+    fn level_name(level: Level) -> &'static str {
+        match level {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN ",
+            Level::Info => "INFO ",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE"
+        }
+    }
+}
+
+pub fn init(level: LevelFilter) -> Result<(), SetLoggerError> {
+    log::set_logger(&LOGGER)?;
+    log::set_max_level(level);
+
+    Ok(())
+}
@@ -1,68 +1,112 @@
-#[macro_use]
-mod c_string_collection;
-mod double_type_buffer;
-mod window;
-mod rendering;
-mod vulkan;
-mod debugging;
-mod builder;
-
 use std::rc::Rc;
-use std::cell::RefCell;
-use window::{
-    Window,
-    WindowSize
-};
-use rendering::{
-    RenderingResult,
-    renderer::Renderer,
-    render_state::{
-        RenderState,
-        PushConstants,
-        VertexShader,
-        FragmentShader
-    }
+use nalgebra_glm as glm;
+use magmacraft::{
+    prelude::*,
+    debugging,
+    logging,
+    metrics::MetricsExporter,
+    soak::SoakTest,
+    vulkan::leak_tracking
 };
 
 #[repr(C)]
+#[derive(PushConstants)]
 struct Positions {
     number: [f32; 4]
 }
 
-impl PushConstants for Positions {}
-
 fn main() -> RenderingResult<()> {
-    let window = Rc::new(RefCell::new(Window::builder()
+    let log_level = if debugging::is_in_debug_mode() {
+        log::LevelFilter::Debug
+    }
+    else {
+        log::LevelFilter::Warn
+    };
+
+    logging::init(log_level).expect("failed to initialize logger");
+
+    let mut window = Window::builder()
         .title("Magmacraft")
         .size(WindowSize { width: 800, height: 600 })
         .build()
-        .expect("failed to create game window")));
+        .expect("failed to create game window");
+
+    let mut soak_test = SoakTest::from_arguments(std::env::args());
+    run(&mut window, &mut soak_test)?;
+    // Reports objects which kept the Vulkan state alive past the renderer
+    leak_tracking::report_live_objects();
 
-    let mut renderer = Renderer::new(Rc::clone(&window)).unwrap();
-    let vertex_shader = VertexShader::from_file(Rc::clone(renderer.logical_device()), &std::path::Path::new("shaders/triangle.vert.spv")).unwrap();
-    let fragment_shader = FragmentShader::from_file(Rc::clone(renderer.logical_device()), &std::path::Path::new("shaders/triangle.frag.spv")).unwrap();
+    // Everything rendering related is destroyed by now, so exiting early leaks nothing
+    if let Some(soak_test) = soak_test {
+        let report = soak_test.report();
+        eprint!("{}", report);
+
+        if !report.passed() {
+            std::process::exit(1);
+        }
+    }
+
+    Ok(())
+}
+
+fn run(window: &mut Window, soak_test: &mut Option<SoakTest>) -> RenderingResult<()> {
+    let mut renderer = Renderer::new(window.handle()).unwrap();
+    let program = ShaderProgram::load(
+        Rc::clone(renderer.logical_device()),
+        std::path::Path::new("shaders/triangle.program.json")).unwrap();
     let mut render_state = RenderState::<(), Positions, ()>::builder()
         .renderer(&renderer)
-        .vertex_shader(&vertex_shader)
-        .fragment_shader(&fragment_shader)
+        .program(&program)
+        .vertex_count(3)
         .build()?;
     let mut render_state2 = RenderState::<(), Positions, ()>::builder()
         .renderer(&renderer)
-        .vertex_shader(&vertex_shader)
-        .fragment_shader(&fragment_shader)
+        .program(&program)
+        .vertex_count(3)
         .build()?;
 
-    let mut window = window.borrow_mut();
     let mut x: f32 = 0.0;
+    let mut metrics = MetricsExporter::from_environment();
+    let mut frame_start = std::time::Instant::now();
+
+    while window.loop_condition() && !soak_test.as_ref().map_or(false, SoakTest::is_finished) {
+        if window.is_minimized() {
+            window.wait_events();
+            frame_start = std::time::Instant::now();
+            continue;
+        }
 
-    while window.loop_condition() {
         x += 0.001;
         let number = Positions { number: [x.tan() * x.tan(), x.cos(), x.cos().cos().sin(), 0.0] };
         let number2 = Positions { number: [x.cos() * x.sin(), x.tan().sin(), x.sin(), x.sin() / 2.0] };
         render_state.push_vertex_constants(number);
         render_state2.push_vertex_constants(number2);
-        renderer.render(&[&render_state, &render_state2])?;
+        let mut scene = Scene::new();
+        scene.add(RenderLayer::World, &render_state, glm::identity());
+        scene.add(RenderLayer::World, &render_state2, glm::identity());
+        if let Some(soak_test) = soak_test {
+            scene.set_camera_position(soak_test.camera_position());
+        }
+
+        renderer.render_scene(&scene)?;
         window.poll_events();
+
+        if let Some(metrics) = &mut metrics {
+            metrics.record_frame(frame_start.elapsed());
+            metrics.record_renderer(&renderer)?;
+            if let Err(error) = metrics.export_if_due() {
+                log::warn!("failed to export metrics: {}", error);
+            }
+        }
+
+        if let Some(soak_test) = soak_test {
+            if soak_test.should_sample() {
+                soak_test.sample_renderer(&renderer)?;
+                soak_test.sample_host_memory();
+            }
+        }
+
+        frame_start = std::time::Instant::now();
     }
 
     Ok(())
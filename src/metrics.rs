@@ -0,0 +1,168 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    io,
+    path::PathBuf,
+    time::{
+        Duration,
+        Instant
+    }
+};
+use crate::{
+    rendering::{
+        RenderingResult,
+        renderer::Renderer
+    },
+    voxel::world::World
+};
+
+// Periodically writes engine metrics to a file in the Prometheus text format,
+// e.g. for node_exporter's textfile collector on dedicated servers and soak test machines.
+// Exporting is opt-in, nothing is collected unless an exporter is created.
+pub struct MetricsExporter {
+    path: PathBuf,
+    interval: Duration,
+    last_export: Option<Instant>,
+    metrics: BTreeMap<&'static str, Metric>,
+    // Longest frame since the last export, so that spikes between exports are not lost
+    max_frame_time: Duration
+}
+
+struct Metric {
+    help: &'static str,
+    kind: MetricKind,
+    value: f64
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum MetricKind {
+    Gauge,
+    Counter
+}
+
+impl MetricsExporter {
+    pub const ENVIRONMENT_VARIABLE: &'static str = "MAGMACRAFT_METRICS_FILE";
+    pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(5);
+
+    pub fn new(path: PathBuf, interval: Duration) -> Self {
+        info!("exporting metrics to {} every {:?}", path.display(), interval);
+
+        Self {
+            path,
+            interval,
+            last_export: None,
+            metrics: BTreeMap::new(),
+            max_frame_time: Duration::from_secs(0)
+        }
+    }
+
+    // Returns an exporter writing to the file named by `MAGMACRAFT_METRICS_FILE`, if it is set
+    pub fn from_environment() -> Option<Self> {
+        let path = std::env::var_os(Self::ENVIRONMENT_VARIABLE)?;
+        Some(Self::new(PathBuf::from(path), Self::DEFAULT_INTERVAL))
+    }
+
+    // Names should follow Prometheus conventions, e.g. `magmacraft_chunks` or `..._bytes`
+    pub fn set_gauge(&mut self, name: &'static str, help: &'static str, value: f64) {
+        self.set(name, help, MetricKind::Gauge, value);
+    }
+
+    // Counters only ever grow, their names should end with `_total`
+    pub fn increment_counter(&mut self, name: &'static str, help: &'static str, by: f64) {
+        let value = self.metrics.get(name).map_or(0.0, |metric| metric.value) + by;
+        self.set(name, help, MetricKind::Counter, value);
+    }
+
+    fn set(&mut self, name: &'static str, help: &'static str, kind: MetricKind, value: f64) {
+        debug_assert!(
+            self.metrics.get(name).map_or(true, |metric| metric.kind == kind),
+            "metric {} is used both as a gauge and a counter", name);
+
+        self.metrics.insert(name, Metric {help, kind, value});
+    }
+
+    pub fn record_frame(&mut self, frame_time: Duration) {
+        let seconds = frame_time.as_secs_f64();
+        self.max_frame_time = self.max_frame_time.max(frame_time);
+
+        self.increment_counter(
+            "magmacraft_frames_total", "Number of frames rendered", 1.0);
+        self.increment_counter(
+            "magmacraft_frame_time_seconds_total", "Time spent on all frames", seconds);
+        self.set_gauge(
+            "magmacraft_frame_time_seconds", "Duration of the last frame", seconds);
+        self.set_gauge(
+            "magmacraft_frame_time_max_seconds",
+            "Duration of the longest frame since the previous export",
+            self.max_frame_time.as_secs_f64());
+    }
+
+    pub fn record_renderer(&mut self, renderer: &Renderer) -> RenderingResult<()> {
+        let statistics = renderer.allocator().statistics()?;
+
+        self.set_gauge(
+            "magmacraft_gpu_allocations", "Number of live GPU memory allocations",
+            f64::from(statistics.allocation_count));
+        self.set_gauge(
+            "magmacraft_gpu_memory_used_bytes", "GPU memory taken by live allocations",
+            statistics.used_bytes as f64);
+        self.set_gauge(
+            "magmacraft_gpu_memory_allocated_bytes", "GPU memory blocks allocated from the driver",
+            statistics.allocated_bytes as f64);
+
+        Ok(())
+    }
+
+    pub fn record_world(&mut self, world: &World) {
+        self.set_gauge(
+            "magmacraft_chunks", "Number of loaded chunks",
+            world.chunks().count() as f64);
+        self.set_gauge(
+            "magmacraft_remesh_queue_length", "Number of chunks waiting to be remeshed",
+            world.remesh_queue_len() as f64);
+    }
+
+    // Meant to be called every frame, writes the file at most once per interval
+    pub fn export_if_due(&mut self) -> io::Result<()> {
+        let is_due = self.last_export
+            .map_or(true, |last_export| last_export.elapsed() >= self.interval);
+
+        if is_due {
+            self.export()?;
+        }
+
+        Ok(())
+    }
+
+    // The file is replaced atomically, so that it is never scraped half written
+    pub fn export(&mut self) -> io::Result<()> {
+        let mut temporary_path = self.path.clone().into_os_string();
+        temporary_path.push(".tmp");
+
+        std::fs::write(&temporary_path, self.to_text())?;
+        std::fs::rename(&temporary_path, &self.path)?;
+
+        self.last_export = Some(Instant::now());
+        self.max_frame_time = Duration::from_secs(0);
+
+        Ok(())
+    }
+
+    fn to_text(&self) -> String {
+        let mut text = String::new();
+
+        for (name, metric) in &self.metrics {
+            let kind = match metric.kind {
+                MetricKind::Gauge => "gauge",
+                MetricKind::Counter => "counter"
+            };
+
+            // Writing into a string cannot fail
+            writeln!(text, "# HELP {} {}", name, metric.help).unwrap();
+            writeln!(text, "# TYPE {} {}", name, kind).unwrap();
+            writeln!(text, "{} {}", name, metric.value).unwrap();
+        }
+
+        text
+    }
+}
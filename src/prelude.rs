@@ -0,0 +1,125 @@
+// Items needed by typical users of the engine; only additions are allowed here
+// between minor versions, removals go through a deprecation period first
+pub use crate::{
+    window::{
+        Window,
+        WindowSize,
+        WindowEvent,
+        KeyModifiers
+    },
+    app::App,
+    assets::{
+        shader_program::ShaderProgram,
+        cube_map::CubeMapData
+    },
+    rendering::{
+        RenderingError,
+        RenderingResult,
+        renderer::{
+            Renderer,
+            RecordingMode,
+            EmptyFrameMode,
+            RedrawMode,
+            RendererEvent
+        },
+        render_state::{
+            RenderState,
+            RenderStateTrait,
+            PushConstants,
+            GeometryShader,
+            VertexShader,
+            FragmentShader
+        },
+        render_layers::{
+            RenderLayers,
+            RenderLayer
+        },
+        mesh::Mesh,
+        material::Material,
+        texture::Texture,
+        custom_pass::{
+            CustomPass,
+            CustomPassContext,
+            CustomPassPoint
+        },
+        scene::{
+            Scene,
+            SceneObjectId,
+            CullingStats
+        },
+        scene_sync::{
+            SceneSync,
+            Renderable
+        },
+        debug_draw::DebugDraw,
+        sprite::{
+            SpriteBatch,
+            Sprite,
+            SpriteRegion,
+            SpriteTextureId
+        },
+        ui::{
+            UiPass,
+            UiRect,
+            UiTextureId
+        },
+        gamma::{
+            GammaCorrection,
+            GammaPath,
+            GammaPass
+        },
+        skybox::SkyboxPass,
+        gpu_culling::{
+            GpuCulling,
+            IndirectDraw
+        },
+        render_scale::{
+            Upscaling,
+            UpscaleShader
+        },
+        submission::Submission,
+        frame_context::FrameContext,
+        frustum::{
+            Frustum,
+            BoundingBox,
+            BoundingSphere
+        },
+        viewport::{
+            ViewportArea,
+            SplitViewport
+        },
+        memory_stats::{
+            MemoryStats,
+            HeapMemoryStats
+        }
+    },
+    voxel::{
+        chunk::{
+            Block,
+            ChunkPosition
+        },
+        world::World,
+        world_renderer::WorldRenderer
+    },
+    vulkan::{
+        handle::VulkanHandle,
+        swapchain::PresentModePreference,
+        command_buffer::{
+            GraphicsQueue,
+            ComputeQueue,
+            TransferQueue
+        },
+        barrier::{
+            PipelineBarrier,
+            MemoryAccess,
+            BufferBarrier,
+            ImageBarrier
+        },
+        shader::ComputeShader,
+        pipeline::{
+            VertexAttributeFormat,
+            BlendMode,
+            CullMode
+        }
+    }
+};
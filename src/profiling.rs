@@ -0,0 +1,330 @@
+#[cfg(feature = "profiling")]
+use std::cell::RefCell;
+use std::rc::Rc;
+use ash::vk;
+use crate::{
+    rendering::RenderingResult,
+    vulkan::{
+        logical_device::LogicalDevice,
+        physical_device::PhysicalDevice,
+        query::QueryPool,
+        command_buffer::CommandBufferRecorder
+    }
+};
+
+// Receives zones of the renderer's frames, e.g. acquiring, recording, submitting and presenting
+// on the CPU and the whole frame and its main pass on the GPU. Meant for a small adapter
+// forwarding them to a profiler through its client crate, like `TracyProfiler` does for Tracy.
+// Nothing is measured unless the `profiling` feature is enabled and a profiler is set.
+pub trait Profiler {
+    // Zones of a thread are nested, every zone ends before the one it was begun in
+    fn begin_zone(&self, name: &'static str);
+    fn end_zone(&self, name: &'static str);
+
+    // Called once every presented frame
+    fn frame_mark(&self) {}
+
+    // In nanoseconds of the GPU's clock, reported once the frame has finished executing,
+    // which is a few frames after it was recorded
+    fn gpu_zone(&self, _name: &'static str, _begin: u64, _end: u64) {}
+}
+
+#[cfg(feature = "profiling")]
+thread_local! {
+    static PROFILER: RefCell<Option<Box<dyn Profiler>>> = RefCell::new(None);
+}
+
+// Zones are measured on the calling thread only, which has to be the renderer's
+#[cfg(feature = "profiling")]
+pub fn set_profiler(profiler: Box<dyn Profiler>) {
+    PROFILER.with(|current| *current.borrow_mut() = Some(profiler));
+}
+
+#[cfg(feature = "profiling")]
+pub fn remove_profiler() -> Option<Box<dyn Profiler>> {
+    PROFILER.with(|current| current.borrow_mut().take())
+}
+
+#[cfg(feature = "profiling")]
+fn with_profiler<F: FnOnce(&dyn Profiler)>(report: F) {
+    PROFILER.with(|current| {
+        if let Some(profiler) = current.borrow().as_ref() {
+            report(profiler.as_ref());
+        }
+    });
+}
+
+// Reports go nowhere without the feature, so that they compile away
+#[cfg(not(feature = "profiling"))]
+fn with_profiler<F: FnOnce(&dyn Profiler)>(_report: F) {}
+
+// Forwards zones to Tracy, e.g. with `set_profiler(Box::new(TracyProfiler::new()))`.
+// GPU zones are put on Tracy's GPU timeline at the next frame mark, as Tracy expects the zones
+// of a frame in the order they began in and their timestamps in the order they were written.
+// The timeline is aligned with the CPU one by the first GPU zone reported, so GPU zones appear
+// later than they ran by the few frames it takes to read them back.
+#[cfg(feature = "profiling")]
+pub struct TracyProfiler {
+    client: tracy_client::Client,
+    // Zones begun and not ended yet, the innermost one last
+    zones: RefCell<Vec<tracy_client::Span>>,
+    gpu_context: RefCell<Option<tracy_client::GpuContext>>,
+    gpu_zones: RefCell<Vec<(&'static str, u64, u64)>>
+}
+
+#[cfg(feature = "profiling")]
+impl TracyProfiler {
+    pub fn new() -> Self {
+        Self {
+            client: tracy_client::Client::start(),
+            zones: RefCell::new(Vec::new()),
+            gpu_context: RefCell::new(None),
+            gpu_zones: RefCell::new(Vec::new())
+        }
+    }
+
+    fn report_gpu_zones(&self) {
+        let gpu_zones = std::mem::take(&mut *self.gpu_zones.borrow_mut());
+        let first_begin = match gpu_zones.iter().map(|(_, begin, _)| *begin).min() {
+            Some(first_begin) => first_begin,
+            None => return
+        };
+
+        let mut gpu_context = self.gpu_context.borrow_mut();
+        if gpu_context.is_none() {
+            // Timestamps are reported in nanoseconds already
+            let context = self.client.clone().new_gpu_context(
+                Some("graphics queue"),
+                tracy_client::GpuContextType::Vulkan,
+                first_begin as i64,
+                1.0);
+
+            match context {
+                Ok(context) => *gpu_context = Some(context),
+                Err(error) => {
+                    warn!("cannot report GPU zones to Tracy: {}", error);
+                    return;
+                }
+            }
+        }
+
+        let gpu_context = gpu_context.as_ref().unwrap();
+
+        // Beginnings and ends of the zones ordered by time. Outer zones begin first and end last
+        // when their timestamps are equal to those of zones nested in them.
+        let mut events: Vec<(u64, bool, usize)> = gpu_zones
+            .iter()
+            .enumerate()
+            .flat_map(|(zone, (_, begin, end))| [(*begin, false, zone), (*end, true, zone)])
+            .collect();
+        events.sort_by_key(|&(time, is_end, zone)| {
+            let (_, begin, end) = gpu_zones[zone];
+            (time, is_end, std::cmp::Reverse(if is_end { begin } else { end }))
+        });
+
+        let mut spans: Vec<Option<tracy_client::GpuSpan>> =
+            gpu_zones.iter().map(|_| None).collect();
+
+        for &(_, is_end, zone) in &events {
+            if is_end {
+                if let Some(span) = &mut spans[zone] {
+                    span.end_zone();
+                }
+
+                continue;
+            }
+
+            let name = gpu_zones[zone].0;
+            match gpu_context.span_alloc(name, name, file!(), line!()) {
+                Ok(span) => spans[zone] = Some(span),
+                Err(error) => warn!("cannot report GPU zone {} to Tracy: {}", name, error)
+            }
+        }
+
+        for &(time, is_end, zone) in &events {
+            match (&spans[zone], is_end) {
+                (Some(span), false) => span.upload_timestamp_start(time as i64),
+                (Some(span), true) => span.upload_timestamp_end(time as i64),
+                (None, _) => ()
+            }
+        }
+    }
+}
+
+#[cfg(feature = "profiling")]
+impl Default for TracyProfiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "profiling")]
+impl Profiler for TracyProfiler {
+    fn begin_zone(&self, name: &'static str) {
+        let span = self.client.clone().span_alloc(Some(name), name, file!(), line!(), 0);
+        self.zones.borrow_mut().push(span);
+    }
+
+    fn end_zone(&self, _name: &'static str) {
+        self.zones.borrow_mut().pop();
+    }
+
+    fn frame_mark(&self) {
+        self.report_gpu_zones();
+        self.client.frame_mark();
+    }
+
+    fn gpu_zone(&self, name: &'static str, begin: u64, end: u64) {
+        self.gpu_zones.borrow_mut().push((name, begin, end));
+    }
+}
+
+// Ends its zone when dropped
+pub(crate) struct Scope {
+    name: &'static str
+}
+
+pub(crate) fn scope(name: &'static str) -> Scope {
+    with_profiler(|profiler| profiler.begin_zone(name));
+
+    Scope {
+        name
+    }
+}
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        let name = self.name;
+        with_profiler(|profiler| profiler.end_zone(name));
+    }
+}
+
+pub(crate) fn frame_mark() {
+    with_profiler(|profiler| profiler.frame_mark());
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum GpuTimestamp {
+    FrameBegin,
+    MainPassBegin,
+    MainPassEnd,
+    FrameEnd
+}
+
+impl GpuTimestamp {
+    const COUNT: u32 = 4;
+
+    fn query(self) -> u32 {
+        match self {
+            GpuTimestamp::FrameBegin => 0,
+            GpuTimestamp::MainPassBegin => 1,
+            GpuTimestamp::MainPassEnd => 2,
+            GpuTimestamp::FrameEnd => 3
+        }
+    }
+
+    fn stage(self) -> vk::PipelineStageFlags {
+        match self {
+            GpuTimestamp::FrameBegin | GpuTimestamp::MainPassBegin => {
+                vk::PipelineStageFlags::TOP_OF_PIPE
+            },
+            GpuTimestamp::MainPassEnd | GpuTimestamp::FrameEnd => {
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE
+            }
+        }
+    }
+}
+
+// Timestamp queries of every swapchain image's command buffer, so that cached command buffers
+// keep writing them. An image's results are read back before it is rendered to again,
+// by which time its previous frame has finished.
+pub(crate) struct GpuTimestamps {
+    query_pool: QueryPool,
+    timestamp_period: f64,
+    // Whether the queries of the given image were submitted and not read back yet
+    is_pending: Vec<bool>
+}
+
+impl GpuTimestamps {
+    const ZONES: [(&'static str, GpuTimestamp, GpuTimestamp); 2] = [
+        ("frame", GpuTimestamp::FrameBegin, GpuTimestamp::FrameEnd),
+        ("main pass", GpuTimestamp::MainPassBegin, GpuTimestamp::MainPassEnd)
+    ];
+
+    // None without the `profiling` feature, or if the device cannot write timestamps
+    // on its graphics queue
+    pub(crate) fn new(
+        physical_device: &PhysicalDevice,
+        logical_device: &Rc<LogicalDevice>,
+        image_count: usize
+    ) -> RenderingResult<Option<Self>> {
+        let limits = physical_device.limits();
+        let is_supported = cfg!(feature = "profiling")
+            && limits.timestamp_compute_and_graphics == vk::TRUE;
+
+        if !is_supported {
+            return Ok(None);
+        }
+
+        let query_pool = QueryPool::timestamps(
+            Rc::clone(logical_device),
+            image_count as u32 * GpuTimestamp::COUNT)?;
+
+        Ok(Some(Self {
+            query_pool,
+            timestamp_period: f64::from(limits.timestamp_period),
+            is_pending: vec![false; image_count]
+        }))
+    }
+
+    // Has to be recorded outside of render passes, with `FrameBegin` resetting the image's
+    // queries before anything else is recorded
+    pub(crate) fn write<'a>(
+        &self,
+        recorder: CommandBufferRecorder<'a>,
+        image_index: usize,
+        timestamp: GpuTimestamp
+    ) -> CommandBufferRecorder<'a> {
+        let first_query = image_index as u32 * GpuTimestamp::COUNT;
+        let recorder = if timestamp == GpuTimestamp::FrameBegin {
+            recorder.reset_query_pool(
+                &self.query_pool,
+                first_query..first_query + GpuTimestamp::COUNT)
+        }
+        else {
+            recorder
+        };
+
+        let query = first_query + timestamp.query();
+        recorder.write_timestamp(timestamp.stage(), &self.query_pool, query)
+    }
+
+    pub(crate) fn mark_submitted(&mut self, image_index: usize) {
+        self.is_pending[image_index] = true;
+    }
+
+    // Reports the zones of the image's previous frame, which has to have finished
+    pub(crate) fn read_back(&mut self, image_index: usize) -> RenderingResult<()> {
+        if !std::mem::replace(&mut self.is_pending[image_index], false) {
+            return Ok(());
+        }
+
+        let first_query = image_index as u32 * GpuTimestamp::COUNT;
+        let queries = first_query..first_query + GpuTimestamp::COUNT;
+        let ticks = match self.query_pool.results(queries)? {
+            Some(ticks) => ticks,
+            None => return Ok(())
+        };
+
+        let nanoseconds = |timestamp: GpuTimestamp| {
+            (ticks[timestamp.query() as usize] as f64 * self.timestamp_period) as u64
+        };
+
+        for (name, begin, end) in Self::ZONES.iter() {
+            let (begin, end) = (nanoseconds(*begin), nanoseconds(*end));
+            with_profiler(|profiler| profiler.gpu_zone(name, begin, end));
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,181 @@
+use std::{
+    ffi::CString,
+    os::raw::{
+        c_char,
+        c_int,
+        c_void
+    },
+    ptr
+};
+
+// RenderDoc's in-application API, available when the game was launched from RenderDoc
+// or with its library injected, e.g. to capture frames in which an intermittent bug shows up
+// the moment it is detected instead of racing RenderDoc's capture key.
+pub struct RenderDoc {
+    api: &'static RenderDocApi
+}
+
+// RENDERDOC_API_1_1_2, entries which are not used are left untyped
+#[repr(C)]
+struct RenderDocApi {
+    get_api_version: extern "C" fn(major: *mut c_int, minor: *mut c_int, patch: *mut c_int),
+    _set_capture_option_u32: *const c_void,
+    _set_capture_option_f32: *const c_void,
+    _get_capture_option_u32: *const c_void,
+    _get_capture_option_f32: *const c_void,
+    _set_focus_toggle_keys: *const c_void,
+    _set_capture_keys: *const c_void,
+    _get_overlay_bits: *const c_void,
+    _mask_overlay_bits: *const c_void,
+    _remove_hooks: *const c_void,
+    _unload_crash_handler: *const c_void,
+    set_capture_file_path_template: extern "C" fn(path_template: *const c_char),
+    _get_capture_file_path_template: *const c_void,
+    get_num_captures: extern "C" fn() -> u32,
+    _get_capture: *const c_void,
+    trigger_capture: extern "C" fn(),
+    _is_target_control_connected: *const c_void,
+    launch_replay_ui: extern "C" fn(connect: u32, command_line: *const c_char) -> u32,
+    _set_active_window: *const c_void,
+    start_frame_capture: extern "C" fn(device: *const c_void, window: *const c_void),
+    is_frame_capturing: extern "C" fn() -> u32,
+    end_frame_capture: extern "C" fn(device: *const c_void, window: *const c_void) -> u32,
+    trigger_multi_frame_capture: extern "C" fn(frame_count: u32)
+}
+
+type GetApi = extern "C" fn(version: c_int, api: *mut *mut c_void) -> c_int;
+
+impl RenderDoc {
+    // eRENDERDOC_API_Version_1_1_2
+    const API_VERSION: c_int = 10102;
+
+    // None unless RenderDoc's library is already loaded into the process,
+    // it is never loaded by the game itself
+    pub fn attached() -> Option<Self> {
+        let get_api = unsafe { platform::get_api_function() };
+        if get_api.is_null() {
+            return None;
+        }
+
+        let get_api: GetApi = unsafe { std::mem::transmute(get_api) };
+        let mut api = ptr::null_mut();
+
+        if get_api(Self::API_VERSION, &mut api) != 1 || api.is_null() {
+            warn!("RenderDoc is loaded, but does not support API version 1.1.2");
+            return None;
+        }
+
+        Some(Self {
+            api: unsafe { &*(api as *const RenderDocApi) }
+        })
+    }
+
+    pub fn api_version(&self) -> (i32, i32, i32) {
+        let (mut major, mut minor, mut patch) = (0, 0, 0);
+        (self.api.get_api_version)(&mut major, &mut minor, &mut patch);
+
+        (major, minor, patch)
+    }
+
+    // Captures the next presented frame
+    pub fn trigger_capture(&self) {
+        (self.api.trigger_capture)();
+    }
+
+    pub fn trigger_multi_frame_capture(&self, frame_count: u32) {
+        (self.api.trigger_multi_frame_capture)(frame_count);
+    }
+
+    // Captures everything submitted until `end_frame_capture` instead of a whole frame,
+    // e.g. work done outside of the renderer's frames
+    pub fn start_frame_capture(&self) {
+        (self.api.start_frame_capture)(ptr::null(), ptr::null());
+    }
+
+    // Returns whether the capture succeeded
+    pub fn end_frame_capture(&self) -> bool {
+        (self.api.end_frame_capture)(ptr::null(), ptr::null()) == 1
+    }
+
+    pub fn is_frame_capturing(&self) -> bool {
+        (self.api.is_frame_capturing)() == 1
+    }
+
+    pub fn capture_count(&self) -> u32 {
+        (self.api.get_num_captures)()
+    }
+
+    // Captures are saved as `<template>_frame<number>.rdc`.
+    // Templates with interior null bytes are ignored.
+    pub fn set_capture_path_template(&self, path_template: &str) {
+        if let Ok(path_template) = CString::new(path_template) {
+            (self.api.set_capture_file_path_template)(path_template.as_ptr());
+        }
+    }
+
+    // Opens RenderDoc's replay UI connected to the game, returns whether it was started
+    pub fn launch_replay_ui(&self) -> bool {
+        (self.api.launch_replay_ui)(1, ptr::null()) != 0
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod platform {
+    use std::os::raw::{
+        c_char,
+        c_int,
+        c_void
+    };
+
+    const RTLD_NOW: c_int = 2;
+    const RTLD_NOLOAD: c_int = 4;
+
+    #[link(name = "dl")]
+    extern "C" {
+        fn dlopen(file_name: *const c_char, flags: c_int) -> *mut c_void;
+        fn dlsym(handle: *mut c_void, symbol: *const c_char) -> *mut c_void;
+    }
+
+    pub(super) unsafe fn get_api_function() -> *mut c_void {
+        let library_name = b"librenderdoc.so\0".as_ptr() as *const c_char;
+        let library = dlopen(library_name, RTLD_NOW | RTLD_NOLOAD);
+        if library.is_null() {
+            return library;
+        }
+
+        dlsym(library, b"RENDERDOC_GetAPI\0".as_ptr() as *const c_char)
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use std::os::raw::{
+        c_char,
+        c_void
+    };
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetModuleHandleA(module_name: *const c_char) -> *mut c_void;
+        fn GetProcAddress(module: *mut c_void, proc_name: *const c_char) -> *mut c_void;
+    }
+
+    pub(super) unsafe fn get_api_function() -> *mut c_void {
+        let module = GetModuleHandleA(b"renderdoc.dll\0".as_ptr() as *const c_char);
+        if module.is_null() {
+            return module;
+        }
+
+        GetProcAddress(module, b"RENDERDOC_GetAPI\0".as_ptr() as *const c_char)
+    }
+}
+
+// RenderDoc does not run on other platforms
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "windows")))]
+mod platform {
+    use std::os::raw::c_void;
+
+    pub(super) unsafe fn get_api_function() -> *mut c_void {
+        std::ptr::null_mut()
+    }
+}
@@ -0,0 +1,122 @@
+use ash::vk;
+
+// 5x7 pixel font embedded in the engine, covering digits, uppercase letters and a few symbols,
+// for text which has to be readable without loading any assets, e.g. of `DebugOverlay`.
+// Lowercase letters are drawn as uppercase ones and unknown characters as question marks.
+// Text is turned into rectangles of lit pixels, which can be filled without any shaders,
+// e.g. with `CommandBufferRecorder::clear_color_attachment`.
+pub struct BitmapFont;
+
+impl BitmapFont {
+    pub const GLYPH_WIDTH: u32 = 5;
+    pub const GLYPH_HEIGHT: u32 = 7;
+    // Glyph width and one pixel of spacing
+    pub const ADVANCE: u32 = 6;
+    pub const LINE_HEIGHT: u32 = 9;
+
+    // Width in font pixels
+    pub fn text_width(text: &str) -> u32 {
+        match text.chars().count() as u32 {
+            0 => 0,
+            count => count * Self::ADVANCE - 1
+        }
+    }
+
+    // Rectangles covering the text's lit pixels, each font pixel becoming a square with sides
+    // of `scale` pixels, with the top left corner of the first glyph at `origin`
+    pub fn text_rects(text: &str, origin: vk::Offset2D, scale: u32) -> Vec<vk::Rect2D> {
+        let mut rects = Vec::new();
+
+        for (index, character) in text.chars().enumerate() {
+            let glyph_x = origin.x + (index as u32 * Self::ADVANCE * scale) as i32;
+
+            for (row, bits) in Self::glyph(character).iter().enumerate() {
+                let y = origin.y + (row as u32 * scale) as i32;
+                let mut column = 0;
+
+                // Adjacent lit pixels of a row share a rectangle
+                while column < Self::GLYPH_WIDTH {
+                    if !Self::is_lit(*bits, column) {
+                        column += 1;
+                        continue;
+                    }
+
+                    let run_start = column;
+                    while column < Self::GLYPH_WIDTH && Self::is_lit(*bits, column) {
+                        column += 1;
+                    }
+
+                    rects.push(vk::Rect2D {
+                        offset: vk::Offset2D {
+                            x: glyph_x + (run_start * scale) as i32,
+                            y
+                        },
+                        extent: vk::Extent2D {
+                            width: (column - run_start) * scale,
+                            height: scale
+                        }
+                    });
+                }
+            }
+        }
+
+        rects
+    }
+
+    fn is_lit(bits: u8, column: u32) -> bool {
+        bits & (1 << (Self::GLYPH_WIDTH - 1 - column)) != 0
+    }
+
+    // Rows from the top, the most significant of the five bits being the leftmost pixel
+    fn glyph(character: char) -> [u8; 7] {
+        match character.to_ascii_uppercase() {
+            ' ' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+            '0' => [0x0e, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0e],
+            '1' => [0x04, 0x0c, 0x04, 0x04, 0x04, 0x04, 0x0e],
+            '2' => [0x0e, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1f],
+            '3' => [0x1f, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0e],
+            '4' => [0x02, 0x06, 0x0a, 0x12, 0x1f, 0x02, 0x02],
+            '5' => [0x1f, 0x10, 0x1e, 0x01, 0x01, 0x11, 0x0e],
+            '6' => [0x06, 0x08, 0x10, 0x1e, 0x11, 0x11, 0x0e],
+            '7' => [0x1f, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+            '8' => [0x0e, 0x11, 0x11, 0x0e, 0x11, 0x11, 0x0e],
+            '9' => [0x0e, 0x11, 0x11, 0x0f, 0x01, 0x02, 0x0c],
+            'A' => [0x0e, 0x11, 0x11, 0x11, 0x1f, 0x11, 0x11],
+            'B' => [0x1e, 0x11, 0x11, 0x1e, 0x11, 0x11, 0x1e],
+            'C' => [0x0e, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0e],
+            'D' => [0x1c, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1c],
+            'E' => [0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x1f],
+            'F' => [0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x10],
+            'G' => [0x0e, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0f],
+            'H' => [0x11, 0x11, 0x11, 0x1f, 0x11, 0x11, 0x11],
+            'I' => [0x0e, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0e],
+            'J' => [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0c],
+            'K' => [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11],
+            'L' => [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1f],
+            'M' => [0x11, 0x1b, 0x15, 0x15, 0x11, 0x11, 0x11],
+            'N' => [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11],
+            'O' => [0x0e, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0e],
+            'P' => [0x1e, 0x11, 0x11, 0x1e, 0x10, 0x10, 0x10],
+            'Q' => [0x0e, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0d],
+            'R' => [0x1e, 0x11, 0x11, 0x1e, 0x14, 0x12, 0x11],
+            'S' => [0x0f, 0x10, 0x10, 0x0e, 0x01, 0x01, 0x1e],
+            'T' => [0x1f, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+            'U' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0e],
+            'V' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x0a, 0x04],
+            'W' => [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0a],
+            'X' => [0x11, 0x11, 0x0a, 0x04, 0x0a, 0x11, 0x11],
+            'Y' => [0x11, 0x11, 0x11, 0x0a, 0x04, 0x04, 0x04],
+            'Z' => [0x1f, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1f],
+            '.' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x0c, 0x0c],
+            ',' => [0x00, 0x00, 0x00, 0x00, 0x0c, 0x04, 0x08],
+            ':' => [0x00, 0x0c, 0x0c, 0x00, 0x0c, 0x0c, 0x00],
+            '/' => [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00],
+            '%' => [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03],
+            '-' => [0x00, 0x00, 0x00, 0x1f, 0x00, 0x00, 0x00],
+            '+' => [0x00, 0x04, 0x04, 0x1f, 0x04, 0x04, 0x00],
+            '(' => [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02],
+            ')' => [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08],
+            _ => [0x0e, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04]
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use std::rc::Rc;
+use ash::vk;
+use crate::{
+    rendering::{
+        RenderingResult,
+        custom_pass::CustomPasses,
+        viewport::ViewportLayers
+    },
+    vulkan::{
+        physical_device::PhysicalDevice,
+        logical_device::LogicalDevice,
+        command_pool::CommandPool,
+        command_buffer::{
+            CommandBuffer,
+            GraphicsCommandBuffer
+        }
+    }
+};
+
+// Command buffers recorded once per swapchain image and submitted
+// again as long as nothing they were recorded from has changed
+pub struct CommandBufferCache {
+    command_buffers: Vec<GraphicsCommandBuffer>,
+    recorded_signatures: Vec<Option<RecordingSignature>>
+}
+
+impl CommandBufferCache {
+    pub fn new(
+        physical_device: &Rc<PhysicalDevice>,
+        logical_device: &Rc<LogicalDevice>,
+        image_count: usize
+    ) -> RenderingResult<Self> {
+        let command_pool = CommandPool::builder()
+            .physical_device(Rc::clone(physical_device))
+            .logical_device(Rc::clone(logical_device))
+            .submit_buffers_once(false)
+            .build()?;
+
+        let command_buffers = Rc::new(command_pool).allocate_command_buffers(image_count)?;
+
+        Ok(Self {
+            command_buffers,
+            recorded_signatures: (0..image_count).map(|_| None).collect()
+        })
+    }
+
+    pub fn is_up_to_date(
+        &self,
+        image_index: usize,
+        viewports: &[ViewportLayers],
+        custom_passes: &CustomPasses,
+        captures_frame: bool
+    ) -> bool {
+        self.recorded_signatures[image_index]
+            .as_ref()
+            .map_or(false, |signature| signature.matches(viewports, custom_passes, captures_frame))
+    }
+
+    // For changes the signatures do not cover, e.g. of the clear color
+    pub fn invalidate(&mut self) {
+        for signature in &mut self.recorded_signatures {
+            *signature = None;
+        }
+    }
+
+    pub fn set_recorded(&mut self, image_index: usize, signature: RecordingSignature) {
+        self.recorded_signatures[image_index] = Some(signature);
+    }
+
+    pub fn command_buffer(&self, image_index: usize) -> &CommandBuffer {
+        &self.command_buffers[image_index]
+    }
+
+    pub fn command_buffer_mut(&mut self, image_index: usize) -> &mut CommandBuffer {
+        &mut self.command_buffers[image_index]
+    }
+}
+
+#[derive(PartialEq)]
+pub struct RecordingSignature {
+    // (viewport index, id, version) of every recorded render state, in recording order
+    render_states: Vec<(usize, u64, u64)>,
+    custom_pass_versions: Vec<u64>,
+    captures_frame: bool,
+    // Full and letterboxed area of every viewport
+    viewport_areas: Vec<(AreaTuple, AreaTuple)>
+}
+
+type AreaTuple = (i32, i32, u32, u32);
+
+impl RecordingSignature {
+    pub fn new(
+        viewports: &[ViewportLayers],
+        custom_passes: &CustomPasses,
+        captures_frame: bool
+    ) -> Self {
+        let mut render_states = Vec::new();
+        for (index, viewport) in viewports.iter().enumerate() {
+            render_states.extend(
+                viewport.render_layers
+                    .iter()
+                    .map(|render_state| (index, render_state.id(), render_state.version())));
+        }
+
+        Self {
+            render_states,
+            custom_pass_versions: custom_passes.versions().collect(),
+            captures_frame,
+            viewport_areas: viewports.iter().map(Self::areas).collect()
+        }
+    }
+
+    // Compares without building a new signature, so that checking an up to date
+    // command buffer every frame does not allocate
+    pub fn matches(
+        &self,
+        viewports: &[ViewportLayers],
+        custom_passes: &CustomPasses,
+        captures_frame: bool
+    ) -> bool {
+        self.captures_frame == captures_frame &&
+            self.viewport_areas.iter().copied().eq(viewports.iter().map(Self::areas)) &&
+            self.custom_pass_versions.iter().copied().eq(custom_passes.versions()) &&
+            self.render_states.iter().copied().eq(
+                viewports
+                    .iter()
+                    .enumerate()
+                    .flat_map(|(index, viewport)| {
+                        viewport.render_layers
+                            .iter()
+                            .map(move |render_state| {
+                                (index, render_state.id(), render_state.version())
+                            })
+                    }))
+    }
+
+    fn areas(viewport: &ViewportLayers) -> (AreaTuple, AreaTuple) {
+        (Self::area_tuple(viewport.full_area), Self::area_tuple(viewport.letterboxed_area))
+    }
+
+    fn area_tuple(area: vk::Rect2D) -> AreaTuple {
+        (area.offset.x, area.offset.y, area.extent.width, area.extent.height)
+    }
+}
@@ -0,0 +1,223 @@
+use std::rc::Rc;
+use ash::vk;
+use crate::{
+    rendering::{
+        RenderingError,
+        RenderingResult,
+        render_layers::RenderLayer
+    },
+    vulkan::{
+        command_buffer::CommandBufferRecorder,
+        swapchain::Swapchain
+    }
+};
+
+// Commands recorded by code outside of the renderer, e.g. by mods, at a given point of a frame.
+// Passes bind their own pipelines and descriptor sets, nothing they bind is assumed
+// to stay bound for the render states recorded after them.
+pub trait CustomPass {
+    fn record<'a>(
+        &self,
+        recorder: CommandBufferRecorder<'a>,
+        context: &CustomPassContext
+    ) -> CommandBufferRecorder<'a>;
+
+    // Cached command buffers are rerecorded whenever the version of any pass changes,
+    // passes recording different commands every frame should change it every frame
+    fn version(&self) -> u64 {
+        0
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CustomPassPoint {
+    // Outside of any render pass, before anything else is drawn, e.g. for shadow maps
+    // and other offscreen passes whose results the main pass samples
+    BeforeMainPass,
+    // Inside of the main render pass, before any layer, e.g. for backgrounds like a skybox.
+    // Drawn at the render scale together with the letterboxed layers.
+    MainPassBegin,
+    // Inside of the main render pass, after the layers recorded together with the given one
+    AfterLayer(RenderLayer),
+    // Outside of any render pass, after everything else is drawn, e.g. for post effects.
+    // The swapchain image is in the present layout and has to be left in it.
+    AfterMainPass
+}
+
+impl CustomPassPoint {
+    fn recording_order(self) -> u16 {
+        match self {
+            CustomPassPoint::BeforeMainPass => 0,
+            CustomPassPoint::MainPassBegin => 1,
+            CustomPassPoint::AfterLayer(layer) => u16::from(layer.recording_order()) + 2,
+            CustomPassPoint::AfterMainPass => u16::MAX
+        }
+    }
+}
+
+pub struct CustomPassContext<'a> {
+    pub swapchain: &'a Swapchain,
+    pub image_index: usize,
+    pub swapchain_image: vk::Image,
+    pub full_area: vk::Rect2D,
+    pub letterboxed_area: vk::Rect2D,
+    // Increases by one every frame, e.g. to pick per-frame regions of buffers
+    pub frame_number: u64
+}
+
+struct RegisteredPass {
+    name: String,
+    point: CustomPassPoint,
+    after: Vec<String>,
+    pass: Rc<dyn CustomPass>
+}
+
+// Passes ordered by their points and declared dependencies.
+// Dependencies on passes which are not registered are ignored, so that passes
+// can be ordered relative to optional ones.
+#[derive(Default)]
+pub struct CustomPasses {
+    passes: Vec<RegisteredPass>,
+    // Indices into `passes` in recording order
+    order: Vec<usize>,
+    generation: u64
+}
+
+impl CustomPasses {
+    pub fn new() -> Self {
+        Self {
+            ..Default::default()
+        }
+    }
+
+    pub fn register(
+        &mut self,
+        name: &str,
+        point: CustomPassPoint,
+        after: &[&str],
+        pass: Rc<dyn CustomPass>
+    ) -> RenderingResult<()> {
+        if self.passes.iter().any(|registered| registered.name == name) {
+            return Err(RenderingError::CustomPassAlreadyRegistered {name: name.to_owned()});
+        }
+
+        self.passes.push(RegisteredPass {
+            name: name.to_owned(),
+            point,
+            after: after.iter().map(|dependency| (*dependency).to_owned()).collect(),
+            pass
+        });
+
+        if let Err(error) = self.update_order() {
+            self.passes.pop();
+            self.update_order()?;
+            return Err(error);
+        }
+
+        debug!("registered custom pass `{}` at {:?}", name, point);
+
+        Ok(())
+    }
+
+    pub fn unregister(&mut self, name: &str) -> Option<Rc<dyn CustomPass>> {
+        let index = self.passes.iter().position(|registered| registered.name == name)?;
+        let removed = self.passes.remove(index);
+
+        // Removing a pass cannot introduce a cycle or a misordered dependency
+        self.update_order().unwrap();
+
+        Some(removed.pass)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.passes.is_empty()
+    }
+
+    // Passes recorded at the given point in recording order, including the ones
+    // placed after layers recorded together with the point's layer
+    pub fn at(&self, point: CustomPassPoint) -> impl Iterator<Item = &dyn CustomPass> + '_ {
+        self.order
+            .iter()
+            .map(move |index| &self.passes[*index])
+            .filter(move |registered| {
+                registered.point.recording_order() == point.recording_order()
+            })
+            .map(|registered| &*registered.pass)
+    }
+
+    // Identifies the set of passes and their versions, for command buffer caching
+    pub fn versions(&self) -> impl Iterator<Item = u64> + '_ {
+        std::iter::once(self.generation)
+            .chain(self.order.iter().map(move |index| self.passes[*index].pass.version()))
+    }
+
+    // Topological sort which keeps registration order among independent passes
+    fn update_order(&mut self) -> RenderingResult<()> {
+        self.generation += 1;
+        self.check_dependency_points()?;
+
+        let dependencies: Vec<Vec<usize>> = self.passes
+            .iter()
+            .map(|registered| {
+                registered.after
+                    .iter()
+                    .filter_map(|dependency| self.index_of(dependency))
+                    .collect()
+            })
+            .collect();
+
+        let mut order = Vec::with_capacity(self.passes.len());
+        let mut is_ordered = vec![false; self.passes.len()];
+
+        while order.len() < self.passes.len() {
+            let next = (0..self.passes.len())
+                .filter(|index| !is_ordered[*index])
+                .filter(|index| {
+                    dependencies[*index].iter().all(|dependency| is_ordered[*dependency])
+                })
+                .min_by_key(|index| (self.passes[*index].point.recording_order(), *index));
+
+            match next {
+                Some(index) => {
+                    is_ordered[index] = true;
+                    order.push(index);
+                },
+                None => {
+                    let index = is_ordered.iter().position(|ordered| !ordered).unwrap();
+                    return Err(RenderingError::CustomPassDependencyCycle {
+                        name: self.passes[index].name.clone()
+                    });
+                }
+            }
+        }
+
+        self.order = order;
+
+        Ok(())
+    }
+
+    // A pass cannot depend on a pass recorded at a later point
+    fn check_dependency_points(&self) -> RenderingResult<()> {
+        for registered in &self.passes {
+            for dependency in &registered.after {
+                let dependency_point = match self.index_of(dependency) {
+                    Some(index) => self.passes[index].point,
+                    None => continue
+                };
+
+                if dependency_point.recording_order() > registered.point.recording_order() {
+                    return Err(RenderingError::CustomPassDependencyAfterPass {
+                        name: registered.name.clone(),
+                        dependency: dependency.clone()
+                    });
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn index_of(&self, name: &str) -> Option<usize> {
+        self.passes.iter().position(|registered| registered.name == name)
+    }
+}
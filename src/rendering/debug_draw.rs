@@ -0,0 +1,310 @@
+use std::{
+    cell::{
+        Cell,
+        RefCell
+    },
+    rc::Rc
+};
+use ash::vk;
+use nalgebra_glm as glm;
+use crate::{
+    builder::{
+        BuilderRequirement,
+        BuilderInternal,
+        BuilderProduct
+    },
+    rendering::{
+        RenderingError,
+        RenderingResult,
+        renderer::Renderer,
+        frustum::BoundingBox,
+        custom_pass::{
+            CustomPass,
+            CustomPassContext
+        }
+    },
+    vulkan::{
+        allocator::MemoryUsage,
+        buffer::Buffer,
+        pipeline::{
+            Pipeline,
+            BlendMode,
+            VertexAttributeFormat
+        },
+        shader::{
+            VertexShader,
+            FragmentShader,
+            ShaderStage
+        },
+        command_buffer::{
+            CommandBufferRecorder,
+            PushConstants
+        }
+    }
+};
+
+// Immediate mode lines, e.g. for bounding boxes and physics shapes. Everything drawn
+// during a frame is collected on the CPU and recorded at once by registering the `DebugDraw`
+// as a custom pass at `CustomPassPoint::AfterLayer(RenderLayer::Debug)`, then cleared.
+//
+// Vertices have a vec3 position at location 0 and a vec4 color at location 1,
+// the vertex shader gets the view projection matrix as a vertex push constant,
+// see shaders/debug_line.vert. Lines beyond the capacity of a frame are dropped.
+pub struct DebugDraw {
+    pipeline: Rc<Pipeline>,
+    // One region per frame in flight and one for the frame being recorded
+    vertex_buffer: RefCell<Buffer>,
+    region_count: usize,
+    capacity: usize,
+    vertices: RefCell<Vec<DebugVertex>>,
+    view_projection: Cell<glm::Mat4>,
+    version: Cell<u64>
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct DebugVertex {
+    position: [f32; 3],
+    color: [f32; 4]
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, PushConstants)]
+struct DebugDrawConstants {
+    view_projection: [[f32; 4]; 4]
+}
+
+impl DebugDraw {
+    const SPHERE_SEGMENTS: usize = 24;
+
+    pub fn builder<'a>() -> DebugDrawBuilder<'a> {
+        DebugDrawBuilder {
+            ..Default::default()
+        }
+    }
+
+    // Lines drawn from now on are transformed by it
+    pub fn set_view_projection(&self, view_projection: &glm::Mat4) {
+        self.view_projection.set(*view_projection);
+    }
+
+    // Colors are RGBA
+    pub fn draw_line(&self, start: &glm::Vec3, end: &glm::Vec3, color: [f32; 4]) {
+        let mut vertices = self.vertices.borrow_mut();
+
+        if vertices.len() + 2 > 2 * self.capacity {
+            return;
+        }
+
+        vertices.push(DebugVertex {position: [start.x, start.y, start.z], color});
+        vertices.push(DebugVertex {position: [end.x, end.y, end.z], color});
+    }
+
+    pub fn draw_wire_box(&self, bounding_box: &BoundingBox, color: [f32; 4]) {
+        let corner = |index: usize| glm::vec3(
+            if index & 1 == 0 { bounding_box.min.x } else { bounding_box.max.x },
+            if index & 2 == 0 { bounding_box.min.y } else { bounding_box.max.y },
+            if index & 4 == 0 { bounding_box.min.z } else { bounding_box.max.z });
+
+        // Corners differing in exactly one coordinate are connected by an edge
+        for index in 0..8 {
+            for axis in [1, 2, 4].iter() {
+                if index & axis == 0 {
+                    self.draw_line(&corner(index), &corner(index | axis), color);
+                }
+            }
+        }
+    }
+
+    // Three circles around the axes
+    pub fn draw_sphere(&self, center: &glm::Vec3, radius: f32, color: [f32; 4]) {
+        let point = |axis: usize, segment: usize| {
+            let angle = segment as f32 / Self::SPHERE_SEGMENTS as f32 * std::f32::consts::PI * 2.0;
+            let (sin, cos) = (angle.sin() * radius, angle.cos() * radius);
+
+            center + match axis {
+                0 => glm::vec3(0.0, cos, sin),
+                1 => glm::vec3(cos, 0.0, sin),
+                _ => glm::vec3(cos, sin, 0.0)
+            }
+        };
+
+        for axis in 0..3 {
+            for segment in 0..Self::SPHERE_SEGMENTS {
+                self.draw_line(&point(axis, segment), &point(axis, segment + 1), color);
+            }
+        }
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.vertices.borrow().len() / 2
+    }
+
+    // Drops everything drawn since the last frame without recording it
+    pub fn clear(&self) {
+        self.vertices.borrow_mut().clear();
+    }
+}
+
+impl CustomPass for DebugDraw {
+    fn record<'a>(
+        &self,
+        recorder: CommandBufferRecorder<'a>,
+        context: &CustomPassContext
+    ) -> CommandBufferRecorder<'a> {
+        let mut vertices = self.vertices.borrow_mut();
+        self.version.set(self.version.get() + 1);
+
+        if vertices.is_empty() {
+            return recorder;
+        }
+
+        let region = (context.frame_number % self.region_count as u64) as usize;
+        let offset = region * self.capacity * 2 * std::mem::size_of::<DebugVertex>();
+        let data = unsafe {
+            std::slice::from_raw_parts(
+                vertices.as_ptr() as *const u8,
+                vertices.len() * std::mem::size_of::<DebugVertex>())
+        };
+
+        if let Err(error) = self.vertex_buffer.borrow_mut().write_data(offset, data) {
+            error!("failed to upload debug lines: {}", error);
+            vertices.clear();
+            return recorder;
+        }
+
+        let constants = DebugDrawConstants {
+            view_projection: self.view_projection.get().into()
+        };
+        let vertex_count = vertices.len() as u32;
+        vertices.clear();
+
+        recorder
+            .set_viewport(context.letterboxed_area)
+            .bind_pipeline(Rc::clone(&self.pipeline))
+            .push_constant(&self.pipeline, ShaderStage::Vertex, &constants)
+            .bind_vertex_buffer_at(0, &self.vertex_buffer.borrow(), offset)
+            .draw(vertex_count, 1)
+    }
+
+    // Lines are drawn anew every frame
+    fn version(&self) -> u64 {
+        self.version.get()
+    }
+}
+
+#[derive(Default)]
+pub struct DebugDrawBuilder<'a> {
+    renderer: BuilderRequirement<&'a Renderer>,
+    vertex_shader: BuilderRequirement<&'a VertexShader>,
+    fragment_shader: BuilderRequirement<&'a FragmentShader>,
+    capacity: Option<usize>,
+    depth_test: bool,
+
+    region_count: BuilderInternal<usize>,
+    pipeline: BuilderInternal<Rc<Pipeline>>,
+    vertex_buffer: BuilderInternal<Buffer>,
+
+    debug_draw: BuilderProduct<DebugDraw>
+}
+
+impl<'a> DebugDrawBuilder<'a> {
+    const DEFAULT_CAPACITY: usize = 16 * 1024;
+
+    pub fn renderer(mut self, renderer: &'a Renderer) -> Self {
+        self.renderer.set(renderer);
+        self
+    }
+
+    pub fn vertex_shader(mut self, vertex_shader: &'a VertexShader) -> Self {
+        self.vertex_shader.set(vertex_shader);
+        self
+    }
+
+    pub fn fragment_shader(mut self, fragment_shader: &'a FragmentShader) -> Self {
+        self.fragment_shader.set(fragment_shader);
+        self
+    }
+
+    // Lines per frame
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = Some(capacity);
+        self
+    }
+
+    // Lines are drawn over everything by default,
+    // hidden ones are only culled if the main pass has a depth attachment
+    pub fn depth_test(mut self, depth_test: bool) -> Self {
+        self.depth_test = depth_test;
+        self
+    }
+
+    pub fn build(mut self) -> RenderingResult<DebugDraw> {
+        check_required_fields!(
+            self, RenderingError, "DebugDrawBuilder",
+            [renderer, vertex_shader, fragment_shader]);
+
+        self.init_region_count();
+        self.init_pipeline()?;
+        self.init_vertex_buffer()?;
+        self.create_debug_draw();
+
+        Ok(self.debug_draw.unwrap())
+    }
+
+    fn init_region_count(&mut self) {
+        self.region_count.set(Renderer::FRAMES_IN_FLIGHT + 1);
+    }
+
+    fn init_pipeline(&mut self) -> RenderingResult<()> {
+        let pipeline = Pipeline::builder()
+            .logical_device(Rc::clone(self.renderer.logical_device()))
+            .swapchain(Rc::clone(self.renderer.swapchain()))
+            .render_pass(Rc::clone(self.renderer.render_pass()))
+            .subpass(0)
+            .vertex_shader(*self.vertex_shader)
+            .fragment_shader(*self.fragment_shader)
+            .topology(vk::PrimitiveTopology::LINE_LIST)
+            .blend_mode(BlendMode::AlphaBlend)
+            .depth_test(self.depth_test)
+            .vertex_attribute_description(VertexAttributeFormat::F32(3), 0)
+            .vertex_attribute_description(
+                VertexAttributeFormat::F32(4), 3 * std::mem::size_of::<f32>())
+            .vertex_binding_stride(std::mem::size_of::<DebugVertex>())
+            .push_constants_size(ShaderStage::Vertex, std::mem::size_of::<DebugDrawConstants>())
+            .build()?;
+
+        self.pipeline.set(Rc::new(pipeline));
+
+        Ok(())
+    }
+
+    fn init_vertex_buffer(&mut self) -> RenderingResult<()> {
+        let capacity = self.capacity.unwrap_or(Self::DEFAULT_CAPACITY);
+        let size = *self.region_count * capacity * 2 * std::mem::size_of::<DebugVertex>();
+
+        let vertex_buffer = Buffer::builder()
+            .allocator(Rc::clone(self.renderer.allocator()))
+            .size(size.max(1))
+            .usage(vk::BufferUsageFlags::VERTEX_BUFFER)
+            .memory_usage(MemoryUsage::CpuToGpu)
+            .host_mapped(true)
+            .build()?;
+
+        self.vertex_buffer.set(vertex_buffer);
+
+        Ok(())
+    }
+
+    fn create_debug_draw(&mut self) {
+        self.debug_draw.set(DebugDraw {
+            pipeline: self.pipeline.take(),
+            vertex_buffer: RefCell::new(self.vertex_buffer.take()),
+            region_count: self.region_count.take(),
+            capacity: self.capacity.unwrap_or(Self::DEFAULT_CAPACITY),
+            vertices: RefCell::new(Vec::new()),
+            view_projection: Cell::new(glm::Mat4::identity()),
+            version: Cell::new(0)
+        });
+    }
+}
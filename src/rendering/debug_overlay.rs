@@ -0,0 +1,294 @@
+use std::{
+    rc::Rc,
+    collections::VecDeque,
+    time::{
+        Duration,
+        Instant
+    }
+};
+use ash::vk;
+use crate::{
+    rendering::{
+        RenderingResult,
+        bitmap_font::BitmapFont,
+        memory_stats::MemoryStats
+    },
+    vulkan::{
+        logical_device::LogicalDevice,
+        physical_device::PhysicalDevice,
+        allocator::Allocator,
+        swapchain::Swapchain,
+        render_pass::RenderPass,
+        framebuffers::Framebuffers,
+        command_buffer::CommandBufferRecorder
+    }
+};
+
+// FPS, a graph of recent frame times, draw calls and device local memory usage, drawn in the
+// top left corner over everything else, see `Renderer::set_debug_overlay`. Text uses the
+// embedded `BitmapFont` and everything is filled with attachment clears, so the overlay needs
+// neither shaders nor assets. Draw calls are the ones of the previous frame.
+pub struct DebugOverlay {
+    render_pass: Rc<RenderPass>,
+    framebuffers: Framebuffers,
+    // Size of a font pixel in framebuffer pixels
+    scale: u32,
+    frame_times: VecDeque<Duration>,
+    last_frame_start: Option<Instant>,
+    draw_call_count: u32,
+    // Usage and budget of device local heaps
+    memory: Option<(vk::DeviceSize, vk::DeviceSize)>,
+    last_memory_query: Option<Instant>
+}
+
+impl DebugOverlay {
+    const GRAPH_FRAMES: usize = 120;
+    // Frames averaged for the displayed FPS and frame time, so that they stay readable
+    const AVERAGED_FRAMES: usize = 30;
+    // Querying memory statistics walks all of the allocator's blocks
+    const MEMORY_QUERY_INTERVAL: Duration = Duration::from_millis(500);
+    // In font pixels
+    const MARGIN: u32 = 4;
+    const PADDING: u32 = 3;
+    const GRAPH_HEIGHT: u32 = 24;
+    // Frame times filling the graph's whole height
+    const GRAPH_MAX_FRAME_TIME: f32 = 1.0 / 30.0;
+    const TARGET_FRAME_TIME: f32 = 1.0 / 60.0;
+
+    const BACKGROUND_COLOR: [f32; 4] = [0.05, 0.05, 0.05, 1.0];
+    const TEXT_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+    const TARGET_LINE_COLOR: [f32; 4] = [0.35, 0.35, 0.35, 1.0];
+    const FAST_FRAME_COLOR: [f32; 4] = [0.2, 0.8, 0.2, 1.0];
+    const SLOW_FRAME_COLOR: [f32; 4] = [0.9, 0.8, 0.1, 1.0];
+    const HITCH_COLOR: [f32; 4] = [0.9, 0.2, 0.1, 1.0];
+
+    // Font pixels are twice the window's content scale, so that text stays legible on HiDPI
+    pub fn new(
+        logical_device: &Rc<LogicalDevice>,
+        swapchain: &Rc<Swapchain>,
+        content_scale: (f32, f32)
+    ) -> RenderingResult<Self> {
+        let render_pass = RenderPass::builder()
+            .logical_device(Rc::clone(logical_device))
+            .swapchain(Rc::clone(swapchain))
+            .load_op(vk::AttachmentLoadOp::LOAD)
+            .build()?;
+        let render_pass = Rc::new(render_pass);
+
+        let framebuffers = Framebuffers::builder()
+            .logical_device(Rc::clone(logical_device))
+            .swapchain(Rc::clone(swapchain))
+            .render_pass(Rc::clone(&render_pass))
+            .build()?;
+
+        Ok(Self {
+            render_pass,
+            framebuffers,
+            scale: 2 * (content_scale.0.round() as u32).max(1),
+            frame_times: VecDeque::with_capacity(Self::GRAPH_FRAMES),
+            last_frame_start: None,
+            draw_call_count: 0,
+            memory: None,
+            last_memory_query: None
+        })
+    }
+
+    // Has to be called once at the start of every frame, frame times being the intervals
+    // between the calls
+    pub fn begin_frame(&mut self) {
+        let now = Instant::now();
+        if let Some(last_frame_start) = self.last_frame_start {
+            if self.frame_times.len() == Self::GRAPH_FRAMES {
+                self.frame_times.pop_front();
+            }
+
+            self.frame_times.push_back(now - last_frame_start);
+        }
+
+        self.last_frame_start = Some(now);
+    }
+
+    pub fn set_draw_call_count(&mut self, draw_call_count: u32) {
+        self.draw_call_count = draw_call_count;
+    }
+
+    pub fn update_memory(
+        &mut self,
+        physical_device: &PhysicalDevice,
+        allocator: &Allocator
+    ) -> RenderingResult<()> {
+        let is_due = self.last_memory_query
+            .map_or(true, |last_query| last_query.elapsed() >= Self::MEMORY_QUERY_INTERVAL);
+
+        if !is_due {
+            return Ok(());
+        }
+
+        let memory_stats = MemoryStats::query(physical_device, allocator)?;
+        self.memory = Some((
+            memory_stats.device_local_usage(),
+            memory_stats.device_local_budget()));
+        self.last_memory_query = Some(Instant::now());
+
+        Ok(())
+    }
+
+    // Draws over the swapchain image in a render pass of its own
+    pub fn record<'a>(
+        &self,
+        recorder: CommandBufferRecorder<'a>,
+        image_index: usize,
+        extent: vk::Extent2D
+    ) -> CommandBufferRecorder<'a> {
+        let lines = self.lines();
+        let scale = self.scale;
+        let text_width = lines
+            .iter()
+            .map(|line| BitmapFont::text_width(line))
+            .max()
+            .unwrap_or(0);
+        let graph_width = Self::GRAPH_FRAMES as u32;
+        let content_width = text_width.max(graph_width);
+        let content_height =
+            lines.len() as u32 * BitmapFont::LINE_HEIGHT + Self::PADDING + Self::GRAPH_HEIGHT;
+
+        let panel = Self::rect(
+            Self::MARGIN * scale,
+            Self::MARGIN * scale,
+            (content_width + 2 * Self::PADDING) * scale,
+            (content_height + 2 * Self::PADDING) * scale);
+
+        let content_x = (Self::MARGIN + Self::PADDING) * scale;
+        let content_y = (Self::MARGIN + Self::PADDING) * scale;
+        let text_rects: Vec<vk::Rect2D> = lines
+            .iter()
+            .enumerate()
+            .flat_map(|(index, line)| {
+                let origin = vk::Offset2D {
+                    x: content_x as i32,
+                    y: (content_y + index as u32 * BitmapFont::LINE_HEIGHT * scale) as i32
+                };
+
+                BitmapFont::text_rects(line, origin, scale)
+            })
+            .collect();
+
+        let graph_top =
+            content_y + (lines.len() as u32 * BitmapFont::LINE_HEIGHT + Self::PADDING) * scale;
+        let graph_bottom = graph_top + Self::GRAPH_HEIGHT * scale;
+        let target_line = Self::rect(
+            content_x,
+            graph_bottom - Self::bar_height(Self::TARGET_FRAME_TIME) * scale,
+            graph_width * scale,
+            (scale / 2).max(1));
+
+        let mut bars = [Vec::new(), Vec::new(), Vec::new()];
+        // The newest frame is on the right
+        let first_bar = Self::GRAPH_FRAMES - self.frame_times.len();
+        for (index, frame_time) in self.frame_times.iter().enumerate() {
+            let frame_time = frame_time.as_secs_f32();
+            let height = Self::bar_height(frame_time) * scale;
+            let color = if frame_time <= Self::TARGET_FRAME_TIME {
+                0
+            }
+            else if frame_time <= Self::GRAPH_MAX_FRAME_TIME {
+                1
+            }
+            else {
+                2
+            };
+
+            bars[color].push(Self::rect(
+                content_x + (first_bar + index) as u32 * scale,
+                graph_bottom - height,
+                scale,
+                height));
+        }
+
+        let [fast_bars, slow_bars, hitches] = bars;
+        let clip = |rects: Vec<vk::Rect2D>| Self::clip_rects(rects, extent);
+
+        recorder
+            .begin_render_pass(&self.render_pass, &self.framebuffers, image_index)
+            .clear_color_attachment(0, Self::BACKGROUND_COLOR, &clip(vec![panel]))
+            .clear_color_attachment(0, Self::TARGET_LINE_COLOR, &clip(vec![target_line]))
+            .clear_color_attachment(0, Self::FAST_FRAME_COLOR, &clip(fast_bars))
+            .clear_color_attachment(0, Self::SLOW_FRAME_COLOR, &clip(slow_bars))
+            .clear_color_attachment(0, Self::HITCH_COLOR, &clip(hitches))
+            .clear_color_attachment(0, Self::TEXT_COLOR, &clip(text_rects))
+            .end_render_pass()
+    }
+
+    fn lines(&self) -> Vec<String> {
+        let averaged = self.frame_times
+            .iter()
+            .rev()
+            .take(Self::AVERAGED_FRAMES)
+            .collect::<Vec<_>>();
+
+        let frame_time = match averaged.len() {
+            0 => 0.0,
+            count => averaged.iter().map(|time| time.as_secs_f32()).sum::<f32>() / count as f32
+        };
+
+        let fps = if frame_time > 0.0 {
+            1.0 / frame_time
+        }
+        else {
+            0.0
+        };
+
+        let memory = match self.memory {
+            Some((usage, budget)) => {
+                let megabytes = |bytes: vk::DeviceSize| bytes / (1024 * 1024);
+                format!("GPU MEM {} / {} MB", megabytes(usage), megabytes(budget))
+            },
+            None => String::from("GPU MEM -")
+        };
+
+        vec![
+            format!("FPS {:.1}", fps),
+            format!("FRAME {:.2} MS", frame_time * 1000.0),
+            format!("DRAWS {}", self.draw_call_count),
+            memory
+        ]
+    }
+
+    // In font pixels
+    fn bar_height(frame_time: f32) -> u32 {
+        let share = (frame_time / Self::GRAPH_MAX_FRAME_TIME).clamp(0.0, 1.0);
+        ((share * Self::GRAPH_HEIGHT as f32).round() as u32).max(1)
+    }
+
+    fn rect(x: u32, y: u32, width: u32, height: u32) -> vk::Rect2D {
+        vk::Rect2D {
+            offset: vk::Offset2D {
+                x: x as i32,
+                y: y as i32
+            },
+            extent: vk::Extent2D {
+                width,
+                height
+            }
+        }
+    }
+
+    // Cleared rectangles have to lie within the render area, e.g. of small windows
+    fn clip_rects(rects: Vec<vk::Rect2D>, extent: vk::Extent2D) -> Vec<vk::Rect2D> {
+        rects
+            .into_iter()
+            .filter_map(|rect| {
+                let x = rect.offset.x as u32;
+                let y = rect.offset.y as u32;
+                let right = (x + rect.extent.width).min(extent.width);
+                let bottom = (y + rect.extent.height).min(extent.height);
+
+                if right <= x || bottom <= y {
+                    return None;
+                }
+
+                Some(Self::rect(x, y, right - x, bottom - y))
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,428 @@
+use std::rc::Rc;
+use ash::vk;
+use crate::{
+    builder::{
+        BuilderRequirement,
+        BuilderInternal,
+        BuilderProduct
+    },
+    rendering::{
+        RenderingError,
+        RenderingResult,
+        renderer::Renderer,
+        custom_pass::CustomPassContext
+    },
+    vulkan::{
+        image::Image,
+        render_pass::RenderPass,
+        framebuffers::Framebuffers,
+        descriptor::{
+            DescriptorSetLayout,
+            DescriptorSet
+        },
+        pipeline::{
+            Pipeline,
+            PipelineBuilder
+        },
+        command_buffer::CommandBufferRecorder
+    }
+};
+
+// Deferred shading in a single render pass of two subpasses. The geometry subpass writes
+// albedo and normals into the G-buffer's color attachments 0 and 1 and fills its depth.
+// The lighting subpass then reads them back as input attachments 0, 1 and 2
+// of `input_attachments()` and shades the swapchain image, e.g. with a fullscreen triangle.
+//
+// The pass is recorded with `record` by a custom pass at `CustomPassPoint::AfterMainPass`,
+// on top of what the main pass has drawn. Lighting shaders discard fragments whose depth
+// is 1.0, leaving the main pass' drawing, e.g. a sky, wherever there is no geometry.
+// The G-buffer has the size of the swapchain, so it is built anew with the renderer.
+pub struct DeferredShading {
+    render_pass: Rc<RenderPass>,
+    framebuffers: Framebuffers,
+    input_attachments_layout: Rc<DescriptorSetLayout>,
+    input_attachments: Rc<DescriptorSet>
+}
+
+impl DeferredShading {
+    pub const ALBEDO_FORMAT: vk::Format = vk::Format::R8G8B8A8_UNORM;
+    // Signed, so that normals need no encoding
+    pub const NORMAL_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+
+    const DEPTH_FORMAT_CANDIDATES: [vk::Format; 3] = [
+        vk::Format::D32_SFLOAT,
+        vk::Format::X8_D24_UNORM_PACK32,
+        vk::Format::D16_UNORM
+    ];
+
+    const GEOMETRY_SUBPASS: u32 = 0;
+    const LIGHTING_SUBPASS: u32 = 1;
+
+    pub fn builder<'a>() -> DeferredShadingBuilder<'a> {
+        DeferredShadingBuilder {
+            ..Default::default()
+        }
+    }
+
+    pub fn render_pass(&self) -> &Rc<RenderPass> {
+        &self.render_pass
+    }
+
+    // Albedo, normal and depth input attachments at bindings 0, 1 and 2, bound as set 0
+    // of the pipelines made from `lighting_pipeline_builder`
+    pub fn input_attachments(&self) -> &Rc<DescriptorSet> {
+        &self.input_attachments
+    }
+
+    // Pipeline writing the G-buffer, still without shaders and vertex input
+    pub fn geometry_pipeline_builder<'a>(&self, renderer: &Renderer) -> PipelineBuilder<'a> {
+        Pipeline::builder()
+            .logical_device(Rc::clone(renderer.logical_device()))
+            .swapchain(Rc::clone(renderer.swapchain()))
+            .render_pass(Rc::clone(&self.render_pass))
+            .subpass(Self::GEOMETRY_SUBPASS)
+            .color_attachment_count(2)
+            .depth_test(true)
+            .depth_write(true)
+    }
+
+    // Pipeline shading the swapchain image, still without shaders and vertex input.
+    // Its first descriptor set is `input_attachments()`, further ones may be added.
+    pub fn lighting_pipeline_builder<'a>(&self, renderer: &Renderer) -> PipelineBuilder<'a> {
+        Pipeline::builder()
+            .logical_device(Rc::clone(renderer.logical_device()))
+            .swapchain(Rc::clone(renderer.swapchain()))
+            .render_pass(Rc::clone(&self.render_pass))
+            .subpass(Self::LIGHTING_SUBPASS)
+            .descriptor_set_layout(Rc::clone(&self.input_attachments_layout))
+    }
+
+    // Clears the G-buffer, fills it with the commands recorded by `geometry` and shades
+    // the swapchain image with the ones recorded by `lighting`, which bind their own
+    // pipelines and `input_attachments()`
+    pub fn record<'a, G, L>(
+        &self,
+        recorder: CommandBufferRecorder<'a>,
+        context: &CustomPassContext,
+        geometry: G,
+        lighting: L
+    ) -> CommandBufferRecorder<'a> where
+        G: FnOnce(CommandBufferRecorder<'a>) -> CommandBufferRecorder<'a>,
+        L: FnOnce(CommandBufferRecorder<'a>) -> CommandBufferRecorder<'a> {
+        let cleared_color = vk::ClearValue {
+            color: vk::ClearColorValue {
+                float32: [0.0, 0.0, 0.0, 0.0]
+            }
+        };
+
+        // The swapchain image is loaded, its clear value is ignored
+        let clear_values = [
+            cleared_color,
+            cleared_color,
+            cleared_color,
+            vk::ClearValue {
+                depth_stencil: vk::ClearDepthStencilValue {
+                    depth: 1.0,
+                    stencil: 0
+                }
+            }
+        ];
+
+        let recorder = recorder
+            .begin_render_pass_with_clear_values(
+                &self.render_pass, &self.framebuffers, context.image_index, &clear_values)
+            .set_viewport(context.letterboxed_area);
+
+        let recorder = geometry(recorder)
+            .next_subpass()
+            .set_viewport(context.letterboxed_area);
+
+        lighting(recorder).end_render_pass()
+    }
+}
+
+#[derive(Default)]
+pub struct DeferredShadingBuilder<'a> {
+    renderer: BuilderRequirement<&'a Renderer>,
+
+    depth_format: BuilderInternal<vk::Format>,
+    albedo: BuilderInternal<Rc<Image>>,
+    normal: BuilderInternal<Rc<Image>>,
+    depth: BuilderInternal<Rc<Image>>,
+    render_pass: BuilderInternal<Rc<RenderPass>>,
+    framebuffers: BuilderInternal<Framebuffers>,
+    input_attachments_layout: BuilderInternal<Rc<DescriptorSetLayout>>,
+    input_attachments: BuilderInternal<Rc<DescriptorSet>>,
+
+    deferred_shading: BuilderProduct<DeferredShading>
+}
+
+impl<'a> DeferredShadingBuilder<'a> {
+    pub fn renderer(mut self, renderer: &'a Renderer) -> Self {
+        self.renderer.set(renderer);
+        self
+    }
+
+    pub fn build(mut self) -> RenderingResult<DeferredShading> {
+        check_required_fields!(self, RenderingError, "DeferredShadingBuilder", [renderer]);
+
+        self.init_depth_format()?;
+        self.init_images()?;
+        self.init_render_pass()?;
+        self.init_framebuffers()?;
+        self.init_input_attachments()?;
+        self.create_deferred_shading();
+
+        Ok(self.deferred_shading.unwrap())
+    }
+
+    fn init_depth_format(&mut self) -> RenderingResult<()> {
+        let required_features = vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT;
+
+        let format = DeferredShading::DEPTH_FORMAT_CANDIDATES
+            .iter()
+            .copied()
+            .find(|format| {
+                self.renderer
+                    .physical_device()
+                    .format_properties(*format)
+                    .optimal_tiling_features
+                    .contains(required_features)
+            })
+            .ok_or(RenderingError::GBufferDepthFormatNotSupported)?;
+
+        debug!("G-buffer depth uses format {:?}", format);
+        self.depth_format.set(format);
+
+        Ok(())
+    }
+
+    // The G-buffer never leaves the render pass, so its images can be transient
+    fn init_images(&mut self) -> RenderingResult<()> {
+        let color_usage = vk::ImageUsageFlags::COLOR_ATTACHMENT
+            | vk::ImageUsageFlags::INPUT_ATTACHMENT
+            | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT;
+        let depth_usage = vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT
+            | vk::ImageUsageFlags::INPUT_ATTACHMENT
+            | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT;
+
+        let albedo = self.create_image(
+            DeferredShading::ALBEDO_FORMAT, color_usage, vk::ImageAspectFlags::COLOR)?;
+        let normal = self.create_image(
+            DeferredShading::NORMAL_FORMAT, color_usage, vk::ImageAspectFlags::COLOR)?;
+        let depth = self.create_image(
+            *self.depth_format, depth_usage, vk::ImageAspectFlags::DEPTH)?;
+
+        self.albedo.set(Rc::new(albedo));
+        self.normal.set(Rc::new(normal));
+        self.depth.set(Rc::new(depth));
+
+        Ok(())
+    }
+
+    fn create_image(
+        &self,
+        format: vk::Format,
+        usage: vk::ImageUsageFlags,
+        aspect: vk::ImageAspectFlags
+    ) -> RenderingResult<Image> {
+        let image = Image::builder()
+            .allocator(Rc::clone(self.renderer.allocator()))
+            .logical_device(Rc::clone(self.renderer.logical_device()))
+            .format(format)
+            .extent(self.renderer.swapchain().extent())
+            .usage(usage)
+            .aspect(aspect)
+            .build()?;
+
+        Ok(image)
+    }
+
+    // Attachments are the swapchain image, albedo, normal and depth
+    fn init_render_pass(&mut self) -> RenderingResult<()> {
+        let attachment = |format, initial_layout, load_op, store_op, final_layout| {
+            vk::AttachmentDescription::builder()
+                .format(format)
+                .samples(vk::SampleCountFlags::TYPE_1)
+                .load_op(load_op)
+                .store_op(store_op)
+                .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+                .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+                .initial_layout(initial_layout)
+                .final_layout(final_layout)
+                .build()
+        };
+
+        let attachment_descriptions = [
+            attachment(
+                self.renderer.swapchain().image_format(),
+                vk::ImageLayout::PRESENT_SRC_KHR,
+                vk::AttachmentLoadOp::LOAD,
+                vk::AttachmentStoreOp::STORE,
+                vk::ImageLayout::PRESENT_SRC_KHR),
+            attachment(
+                DeferredShading::ALBEDO_FORMAT,
+                vk::ImageLayout::UNDEFINED,
+                vk::AttachmentLoadOp::CLEAR,
+                vk::AttachmentStoreOp::DONT_CARE,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+            attachment(
+                DeferredShading::NORMAL_FORMAT,
+                vk::ImageLayout::UNDEFINED,
+                vk::AttachmentLoadOp::CLEAR,
+                vk::AttachmentStoreOp::DONT_CARE,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+            attachment(
+                *self.depth_format,
+                vk::ImageLayout::UNDEFINED,
+                vk::AttachmentLoadOp::CLEAR,
+                vk::AttachmentStoreOp::DONT_CARE,
+                vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL)
+        ];
+
+        let reference = |attachment, layout| {
+            vk::AttachmentReference::builder()
+                .attachment(attachment)
+                .layout(layout)
+                .build()
+        };
+
+        let geometry_color_references = [
+            reference(1, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL),
+            reference(2, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+        ];
+        let geometry_depth_reference =
+            reference(3, vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
+
+        let lighting_color_references = [
+            reference(0, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+        ];
+        let lighting_input_references = [
+            reference(1, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+            reference(2, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+            reference(3, vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL)
+        ];
+
+        let subpass_descriptions = [
+            vk::SubpassDescription::builder()
+                .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+                .color_attachments(&geometry_color_references)
+                .depth_stencil_attachment(&geometry_depth_reference)
+                .build(),
+            vk::SubpassDescription::builder()
+                .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+                .color_attachments(&lighting_color_references)
+                .input_attachments(&lighting_input_references)
+                .build()
+        ];
+
+        let depth_tests = vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+            | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS;
+        let color_and_depth_writes = vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+            | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE;
+
+        let subpass_dependencies = [
+            // The previous frame's lighting has to be done reading the G-buffer
+            vk::SubpassDependency::builder()
+                .src_subpass(vk::SUBPASS_EXTERNAL)
+                .dst_subpass(DeferredShading::GEOMETRY_SUBPASS)
+                .src_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER)
+                .src_access_mask(vk::AccessFlags::empty())
+                .dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | depth_tests)
+                .dst_access_mask(color_and_depth_writes)
+                .build(),
+            // The main pass has to be done drawing what the lighting is drawn over
+            vk::SubpassDependency::builder()
+                .src_subpass(vk::SUBPASS_EXTERNAL)
+                .dst_subpass(DeferredShading::LIGHTING_SUBPASS)
+                .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+                .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+                .dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+                .dst_access_mask(
+                    vk::AccessFlags::COLOR_ATTACHMENT_READ
+                    | vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+                .build(),
+            // Every fragment only reads the G-buffer at its own position
+            vk::SubpassDependency::builder()
+                .src_subpass(DeferredShading::GEOMETRY_SUBPASS)
+                .dst_subpass(DeferredShading::LIGHTING_SUBPASS)
+                .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | depth_tests)
+                .src_access_mask(color_and_depth_writes)
+                .dst_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER)
+                .dst_access_mask(vk::AccessFlags::INPUT_ATTACHMENT_READ)
+                .dependency_flags(vk::DependencyFlags::BY_REGION)
+                .build(),
+            // For the passes drawing over the shaded image
+            vk::SubpassDependency::builder()
+                .src_subpass(DeferredShading::LIGHTING_SUBPASS)
+                .dst_subpass(vk::SUBPASS_EXTERNAL)
+                .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+                .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+                .dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+                .dst_access_mask(
+                    vk::AccessFlags::COLOR_ATTACHMENT_READ
+                    | vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+                .build()
+        ];
+
+        let render_pass_create_info = vk::RenderPassCreateInfo::builder()
+            .attachments(&attachment_descriptions)
+            .subpasses(&subpass_descriptions)
+            .dependencies(&subpass_dependencies);
+
+        let render_pass = RenderPass::from_create_info(
+            Rc::clone(self.renderer.logical_device()),
+            &render_pass_create_info)?;
+
+        self.render_pass.set(Rc::new(render_pass));
+
+        Ok(())
+    }
+
+    fn init_framebuffers(&mut self) -> RenderingResult<()> {
+        let framebuffers = Framebuffers::builder()
+            .logical_device(Rc::clone(self.renderer.logical_device()))
+            .swapchain(Rc::clone(self.renderer.swapchain()))
+            .render_pass(Rc::clone(&self.render_pass))
+            .attachment(Rc::clone(&self.albedo))
+            .attachment(Rc::clone(&self.normal))
+            .attachment(Rc::clone(&self.depth))
+            .build()?;
+
+        self.framebuffers.set(framebuffers);
+
+        Ok(())
+    }
+
+    fn init_input_attachments(&mut self) -> RenderingResult<()> {
+        let layout = DescriptorSetLayout::builder()
+            .logical_device(Rc::clone(self.renderer.logical_device()))
+            .input_attachment(0)
+            .input_attachment(1)
+            .input_attachment(2)
+            .build()?;
+        let layout = Rc::new(layout);
+
+        let mut input_attachments = DescriptorSet::new(Rc::clone(&layout))?;
+        input_attachments.write_input_attachment(
+            0, Rc::clone(&self.albedo), vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)?;
+        input_attachments.write_input_attachment(
+            1, Rc::clone(&self.normal), vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)?;
+        input_attachments.write_input_attachment(
+            2, Rc::clone(&self.depth), vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL)?;
+
+        self.input_attachments_layout.set(layout);
+        self.input_attachments.set(Rc::new(input_attachments));
+
+        Ok(())
+    }
+
+    fn create_deferred_shading(&mut self) {
+        self.deferred_shading.set(DeferredShading {
+            render_pass: self.render_pass.take(),
+            framebuffers: self.framebuffers.take(),
+            input_attachments_layout: self.input_attachments_layout.take(),
+            input_attachments: self.input_attachments.take()
+        });
+    }
+}
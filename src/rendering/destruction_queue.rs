@@ -0,0 +1,36 @@
+use std::{
+    any::Any,
+    collections::VecDeque
+};
+
+// GPU resources which are not needed anymore, but may still be referenced by frames in flight.
+// Every resource is tagged with the number of the frame during or after which it was queued,
+// which is conservative when it is queued between frames, and it is dropped only once
+// that frame's fence has been signaled. Frames finish in submission order, so the queue
+// stays sorted by frame number and is released from the front.
+pub struct DestructionQueue {
+    pending: VecDeque<(u64, Box<dyn Any>)>
+}
+
+impl DestructionQueue {
+    pub fn new() -> Self {
+        Self {
+            pending: VecDeque::new()
+        }
+    }
+
+    pub fn push<T: 'static>(&mut self, frame_number: u64, resource: T) {
+        self.pending.push_back((frame_number, Box::new(resource)));
+    }
+
+    // Has to be called only after the fence of the given frame has been signaled
+    pub fn release_finished(&mut self, finished_frame_number: u64) {
+        while let Some((frame_number, _)) = self.pending.front() {
+            if *frame_number > finished_frame_number {
+                break;
+            }
+
+            self.pending.pop_front();
+        }
+    }
+}
@@ -0,0 +1,227 @@
+use std::{
+    alloc,
+    cell::{
+        Cell,
+        RefCell
+    },
+    ptr::NonNull
+};
+
+// Bump allocator for data that lives for a single frame, e.g. draw lists and sort keys.
+// Allocating only moves a pointer, and resetting the arena keeps its memory,
+// so once it has grown to fit a frame, later frames do not touch the heap.
+// Only `Copy` types can be allocated, as nothing is dropped on reset.
+pub struct FrameArena {
+    blocks: RefCell<Vec<ArenaBlock>>,
+    current_block: Cell<usize>,
+    offset: Cell<usize>
+}
+
+struct ArenaBlock {
+    pointer: NonNull<u8>,
+    layout: alloc::Layout
+}
+
+impl FrameArena {
+    const BLOCK_ALIGNMENT: usize = 16;
+    const MIN_BLOCK_SIZE: usize = 16 * 1024;
+
+    // Does not allocate until something is allocated in the arena
+    pub fn new() -> Self {
+        Self {
+            blocks: RefCell::new(Vec::new()),
+            current_block: Cell::new(0),
+            offset: Cell::new(0)
+        }
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    pub fn alloc_slice_copy<T: Copy>(&self, values: &[T]) -> &mut [T] {
+        self.alloc_slice_fill_with(values.len(), |index| values[index])
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    pub fn alloc_slice_fill_with<T, F>(&self, len: usize, mut fill: F) -> &mut [T] where
+        T: Copy,
+        F: FnMut(usize) -> T {
+        let layout = alloc::Layout::array::<T>(len).expect("frame arena allocation too large");
+        let pointer = self.allocate(layout) as *mut T;
+
+        // SAFETY: `allocate` hands out every byte of a block at most once, as the offset only
+        // moves forward until `reset`, which takes `&mut self` and so cannot be called while
+        // any returned slice is still borrowed. Slices handed out through `&self` therefore
+        // never overlap, even though several of them are mutable at once. Zero-sized
+        // allocations get a dangling, well aligned pointer, which is valid for empty slices.
+        unsafe {
+            for index in 0..len {
+                pointer.add(index).write(fill(index));
+            }
+
+            std::slice::from_raw_parts_mut(pointer, len)
+        }
+    }
+
+    // Invalidates all allocations, which the borrow checker enforces by requiring `&mut self`
+    pub fn reset(&mut self) {
+        self.current_block.set(0);
+        self.offset.set(0);
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.blocks
+            .borrow()
+            .iter()
+            .map(|block| block.layout.size())
+            .sum()
+    }
+
+    fn allocate(&self, layout: alloc::Layout) -> *mut u8 {
+        assert!(
+            layout.align() <= Self::BLOCK_ALIGNMENT,
+            "frame arena does not support types aligned to more than {} bytes",
+            Self::BLOCK_ALIGNMENT);
+
+        if layout.size() == 0 {
+            return layout.align() as *mut u8;
+        }
+
+        let mut blocks = self.blocks.borrow_mut();
+
+        loop {
+            let current_block = self.current_block.get();
+
+            if current_block == blocks.len() {
+                let previous_size = blocks.last().map_or(0, |block| block.layout.size());
+                let size = (previous_size * 2)
+                    .max(layout.size())
+                    .max(Self::MIN_BLOCK_SIZE);
+
+                blocks.push(ArenaBlock::new(size, Self::BLOCK_ALIGNMENT));
+            }
+
+            let block = &blocks[current_block];
+            let offset = align_up(self.offset.get(), layout.align());
+
+            if offset + layout.size() <= block.layout.size() {
+                self.offset.set(offset + layout.size());
+                return unsafe { block.pointer.as_ptr().add(offset) };
+            }
+
+            self.current_block.set(current_block + 1);
+            self.offset.set(0);
+        }
+    }
+}
+
+impl Default for FrameArena {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ArenaBlock {
+    fn new(size: usize, alignment: usize) -> Self {
+        let layout = alloc::Layout::from_size_align(size, alignment)
+            .expect("invalid frame arena block layout");
+
+        let pointer = unsafe {
+            alloc::alloc(layout)
+        };
+
+        match NonNull::new(pointer) {
+            Some(pointer) => Self {pointer, layout},
+            None => alloc::handle_alloc_error(layout)
+        }
+    }
+}
+
+impl Drop for ArenaBlock {
+    fn drop(&mut self) {
+        unsafe {
+            alloc::dealloc(self.pointer.as_ptr(), self.layout);
+        }
+    }
+}
+
+fn align_up(offset: usize, alignment: usize) -> usize {
+    (offset + alignment - 1) & !(alignment - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allocations_are_aligned() {
+        let arena = FrameArena::new();
+        arena.alloc_slice_copy(&[1u8, 2, 3]);
+        let values = arena.alloc_slice_copy(&[1u64, 2]);
+        arena.alloc_slice_copy(&[1u16]);
+        let vectors = arena.alloc_slice_copy(&[[1.0f32; 4]]);
+
+        assert_eq!(values.as_ptr() as usize % std::mem::align_of::<u64>(), 0);
+        assert_eq!(vectors.as_ptr() as usize % std::mem::align_of::<[f32; 4]>(), 0);
+    }
+
+    #[test]
+    fn allocations_do_not_overlap() {
+        let arena = FrameArena::new();
+        let first = arena.alloc_slice_fill_with(100, |index| index as u32);
+        let second = arena.alloc_slice_fill_with(100, |index| 1000 + index as u32);
+        first.iter_mut().for_each(|value| *value = 0);
+
+        assert!(second.iter().enumerate().all(|(index, value)| *value == 1000 + index as u32));
+    }
+
+    #[test]
+    fn grows_into_new_block() {
+        let arena = FrameArena::new();
+        assert_eq!(arena.capacity(), 0);
+
+        let first = arena.alloc_slice_fill_with(FrameArena::MIN_BLOCK_SIZE - 8, |_| 1u8);
+        assert_eq!(arena.capacity(), FrameArena::MIN_BLOCK_SIZE);
+
+        // Does not fit in the rest of the first block, which keeps its contents
+        let second = arena.alloc_slice_fill_with(64, |_| 2u8);
+        assert_eq!(arena.capacity(), 3 * FrameArena::MIN_BLOCK_SIZE);
+        assert!(first.iter().all(|value| *value == 1));
+        assert!(second.iter().all(|value| *value == 2));
+    }
+
+    #[test]
+    fn allocations_larger_than_a_block_get_one_of_their_own() {
+        let arena = FrameArena::new();
+        let values = arena.alloc_slice_fill_with(4 * FrameArena::MIN_BLOCK_SIZE, |_| 7u8);
+
+        assert_eq!(values.len(), 4 * FrameArena::MIN_BLOCK_SIZE);
+        assert_eq!(arena.capacity(), 4 * FrameArena::MIN_BLOCK_SIZE);
+    }
+
+    #[test]
+    fn reuses_memory_after_reset() {
+        let mut arena = FrameArena::new();
+        let first_pointer = arena.alloc_slice_copy(&[1u32, 2, 3]).as_ptr();
+        arena.alloc_slice_fill_with(FrameArena::MIN_BLOCK_SIZE, |_| 0u8);
+        let capacity = arena.capacity();
+
+        arena.reset();
+        let values = arena.alloc_slice_copy(&[4u32, 5, 6]);
+        assert_eq!(values.as_ptr(), first_pointer);
+        assert_eq!(values, &[4, 5, 6]);
+
+        arena.alloc_slice_fill_with(FrameArena::MIN_BLOCK_SIZE, |_| 0u8);
+        assert_eq!(arena.capacity(), capacity);
+    }
+
+    #[test]
+    fn zero_length_slices_do_not_allocate() {
+        let arena = FrameArena::new();
+        let empty: &mut [u64] = arena.alloc_slice_copy(&[]);
+        let unit = arena.alloc_slice_fill_with(3, |_| ());
+
+        assert!(empty.is_empty());
+        assert_eq!(empty.as_ptr() as usize % std::mem::align_of::<u64>(), 0);
+        assert_eq!(unit.len(), 3);
+        assert_eq!(arena.capacity(), 0);
+    }
+}
@@ -0,0 +1,183 @@
+use std::rc::Rc;
+use ash::vk;
+use image::RgbaImage;
+use crate::{
+    rendering::{
+        RenderingError,
+        RenderingResult
+    },
+    vulkan::{
+        allocator::{
+            Allocator,
+            MemoryUsage
+        },
+        buffer::Buffer,
+        command_buffer::CommandBufferRecorder,
+        swapchain::Swapchain,
+        barrier::{
+            PipelineBarrier,
+            MemoryAccess,
+            BufferBarrier,
+            ImageBarrier
+        }
+    }
+};
+
+pub struct FrameCapture {
+    allocator: Rc<Allocator>,
+    readback_buffer: Option<Buffer>,
+    state: FrameCaptureState,
+    captured_frame: Option<RgbaImage>
+}
+
+#[derive(PartialEq, Clone, Copy)]
+enum FrameCaptureState {
+    Idle,
+    Requested,
+    Recorded {frame: usize}
+}
+
+impl FrameCapture {
+    pub(crate) const BYTES_PER_PIXEL: usize = 4;
+
+    pub fn new(allocator: Rc<Allocator>) -> Self {
+        Self {
+            allocator,
+            readback_buffer: None,
+            state: FrameCaptureState::Idle,
+            captured_frame: None
+        }
+    }
+
+    pub fn request(&mut self, swapchain: &Swapchain) -> RenderingResult<()> {
+        if !swapchain.image_usage().contains(vk::ImageUsageFlags::TRANSFER_SRC) {
+            return Err(RenderingError::CaptureNotSupported);
+        }
+
+        Self::check_format_supported(swapchain.image_format())?;
+        self.ensure_readback_buffer(swapchain.extent())?;
+        self.state = FrameCaptureState::Requested;
+
+        Ok(())
+    }
+
+    pub(crate) fn check_format_supported(format: vk::Format) -> RenderingResult<()> {
+        if Self::is_bgra(format) || Self::is_rgba(format) {
+            Ok(())
+        }
+        else {
+            Err(RenderingError::CaptureFormatNotSupported)
+        }
+    }
+
+    pub(crate) fn is_bgra(format: vk::Format) -> bool {
+        format == vk::Format::B8G8R8A8_UNORM || format == vk::Format::B8G8R8A8_SRGB
+    }
+
+    fn is_rgba(format: vk::Format) -> bool {
+        format == vk::Format::R8G8B8A8_UNORM || format == vk::Format::R8G8B8A8_SRGB
+    }
+
+    fn ensure_readback_buffer(&mut self, extent: vk::Extent2D) -> RenderingResult<()> {
+        let size = Self::frame_size(extent);
+        let is_large_enough = self.readback_buffer
+            .as_ref()
+            .map_or(false, |buffer| buffer.size() >= size);
+
+        if !is_large_enough {
+            let buffer = Buffer::builder()
+                .allocator(Rc::clone(&self.allocator))
+                .size(size)
+                .usage(vk::BufferUsageFlags::TRANSFER_DST)
+                .memory_usage(MemoryUsage::GpuToCpu)
+                .host_mapped(true)
+                .build()?;
+
+            self.readback_buffer = Some(buffer);
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn frame_size(extent: vk::Extent2D) -> usize {
+        extent.width as usize * extent.height as usize * Self::BYTES_PER_PIXEL
+    }
+
+    pub fn record_copy<'a>(
+        &mut self,
+        recorder: CommandBufferRecorder<'a>,
+        swapchain: &Swapchain,
+        image_index: usize,
+        frame: usize
+    ) -> CommandBufferRecorder<'a> {
+        let readback_buffer = match (&self.readback_buffer, self.state) {
+            (Some(buffer), FrameCaptureState::Requested) => buffer,
+            _ => return recorder
+        };
+
+        let image = swapchain.images()[image_index];
+        self.state = FrameCaptureState::Recorded {frame};
+
+        recorder
+            .pipeline_barrier(&PipelineBarrier::new()
+                .image(ImageBarrier::from_handle(
+                    image,
+                    (vk::ImageLayout::PRESENT_SRC_KHR, vk::ImageLayout::TRANSFER_SRC_OPTIMAL),
+                    (MemoryAccess::color_attachment_write(), MemoryAccess::transfer_read()))))
+            .copy_image_to_buffer(
+                image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                readback_buffer,
+                swapchain.extent())
+            .pipeline_barrier(&PipelineBarrier::new()
+                .buffer(BufferBarrier::new(
+                    readback_buffer,
+                    (MemoryAccess::transfer_write(), MemoryAccess::host_read())))
+                .image(ImageBarrier::from_handle(
+                    image,
+                    (vk::ImageLayout::TRANSFER_SRC_OPTIMAL, vk::ImageLayout::PRESENT_SRC_KHR),
+                    (MemoryAccess::transfer_read(), MemoryAccess::none()))))
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.state == FrameCaptureState::Requested
+    }
+
+    pub fn is_recorded_in_frame(&self, frame: usize) -> bool {
+        self.state == FrameCaptureState::Recorded {frame}
+    }
+
+    // Has to be called only after the frame in which the copy was recorded has finished rendering
+    pub fn read_back(&mut self, swapchain: &Swapchain) -> RenderingResult<()> {
+        let readback_buffer = match &self.readback_buffer {
+            Some(buffer) => buffer,
+            None => return Ok(())
+        };
+
+        let extent = swapchain.extent();
+        let data = &readback_buffer.mapped_data()?[..Self::frame_size(extent)];
+        let swap_red_and_blue = Self::is_bgra(swapchain.image_format());
+        let mut pixels = Vec::with_capacity(data.len());
+
+        for pixel in data.chunks_exact(Self::BYTES_PER_PIXEL) {
+            if swap_red_and_blue {
+                pixels.extend_from_slice(&[pixel[2], pixel[1], pixel[0]]);
+            }
+            else {
+                pixels.extend_from_slice(&pixel[..3]);
+            }
+
+            // Swapchain images are composited as opaque, so alpha carries no information
+            pixels.push(u8::MAX);
+        }
+
+        self.captured_frame = RgbaImage::from_raw(extent.width, extent.height, pixels);
+        self.state = FrameCaptureState::Idle;
+
+        Ok(())
+    }
+
+    pub fn take(&mut self) -> Option<RgbaImage> {
+        self.captured_frame.take()
+    }
+}
@@ -0,0 +1,143 @@
+use std::rc::Rc;
+use crate::{
+    rendering::{
+        RenderingResult,
+        frame_arena::FrameArena,
+        destruction_queue::DestructionQueue
+    },
+    vulkan::{
+        logical_device::LogicalDevice,
+        command_buffer::{
+            CommandBufferRecorder,
+            GraphicsCommandBuffer
+        },
+        descriptor::{
+            DescriptorSet,
+            DescriptorSetLayout,
+            TransientDescriptorPool
+        },
+        synchronization::{
+            Semaphore,
+            Fence,
+            FenceStatus
+        }
+    }
+};
+
+// Everything the renderer keeps once per frame in flight. A frame's resources are reused
+// `Renderer::FRAMES_IN_FLIGHT` frames later, once its fence has been waited for.
+pub(crate) struct FrameResources {
+    pub(crate) command_buffer: GraphicsCommandBuffer,
+    pub(crate) image_acquired_semaphore: Semaphore,
+    pub(crate) image_rendered_semaphore: Semaphore,
+    pub(crate) image_rendered_fence: Fence,
+    // Recorded through `FrameContext` and submitted right before `command_buffer`
+    transient_command_buffer: GraphicsCommandBuffer,
+    is_transient_command_buffer_recorded: bool,
+    descriptor_pool: TransientDescriptorPool,
+    arena: FrameArena
+}
+
+impl FrameResources {
+    pub(crate) fn new(
+        logical_device: &Rc<LogicalDevice>,
+        command_buffer: GraphicsCommandBuffer,
+        transient_command_buffer: GraphicsCommandBuffer
+    ) -> RenderingResult<Self> {
+        Ok(Self {
+            command_buffer,
+            image_acquired_semaphore: Semaphore::new(Rc::clone(logical_device))?,
+            image_rendered_semaphore: Semaphore::new(Rc::clone(logical_device))?,
+            image_rendered_fence: Fence::new(Rc::clone(logical_device), FenceStatus::Ready)?,
+            transient_command_buffer,
+            is_transient_command_buffer_recorded: false,
+            descriptor_pool: TransientDescriptorPool::new(Rc::clone(logical_device)),
+            arena: FrameArena::new()
+        })
+    }
+
+    // None unless it was recorded during the frame
+    pub(crate) fn recorded_transient_command_buffer(&self) -> Option<&GraphicsCommandBuffer> {
+        if self.is_transient_command_buffer_recorded {
+            Some(&self.transient_command_buffer)
+        }
+        else {
+            None
+        }
+    }
+
+    // Has to be called only after the frame's fence has been signaled
+    pub(crate) fn recycle(&mut self) -> RenderingResult<()> {
+        self.is_transient_command_buffer_recorded = false;
+        self.descriptor_pool.reset()?;
+        self.arena.reset();
+
+        Ok(())
+    }
+}
+
+// Resources of the frame which is about to be rendered, handed out by `Renderer::begin_frame`
+// once everything the previous use of the frame's resources submitted has finished
+pub struct FrameContext<'a> {
+    frame_index: usize,
+    frame_number: u64,
+    resources: &'a mut FrameResources,
+    destruction_queue: &'a mut DestructionQueue
+}
+
+impl<'a> FrameContext<'a> {
+    pub(crate) fn new(
+        frame_index: usize,
+        frame_number: u64,
+        resources: &'a mut FrameResources,
+        destruction_queue: &'a mut DestructionQueue
+    ) -> Self {
+        Self {
+            frame_index,
+            frame_number,
+            resources,
+            destruction_queue
+        }
+    }
+
+    // Frame in flight, below `Renderer::FRAMES_IN_FLIGHT`, e.g. to pick per-frame copies
+    // of buffers written by the CPU
+    pub fn frame_index(&self) -> usize {
+        self.frame_index
+    }
+
+    pub fn frame_number(&self) -> u64 {
+        self.frame_number
+    }
+
+    // Commands submitted to the graphics queue right before the frame's own, e.g. uploads
+    // or compute work whose results the frame uses. Recording again during the same frame
+    // replaces the commands recorded before.
+    pub fn record<F>(&mut self, record: F) -> RenderingResult<()> where
+        F: FnOnce(CommandBufferRecorder) -> CommandBufferRecorder {
+        self.resources.is_transient_command_buffer_recorded = false;
+        record(self.resources.transient_command_buffer.record()?).end_recording()?;
+        self.resources.is_transient_command_buffer_recorded = true;
+
+        Ok(())
+    }
+
+    // The set must not be used by later frames, it is freed once the frame's resources
+    // are reused
+    pub fn descriptor_set(
+        &mut self,
+        layout: Rc<DescriptorSetLayout>
+    ) -> RenderingResult<DescriptorSet> {
+        Ok(self.resources.descriptor_pool.allocate(layout)?)
+    }
+
+    // CPU data which is only needed until the frame's resources are reused
+    pub fn arena(&self) -> &FrameArena {
+        &self.resources.arena
+    }
+
+    // Drops the resource once the frame has finished rendering
+    pub fn destroy_later<T: 'static>(&mut self, resource: T) {
+        self.destruction_queue.push(self.frame_number, resource);
+    }
+}
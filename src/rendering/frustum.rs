@@ -0,0 +1,141 @@
+use nalgebra_glm as glm;
+
+// Axis aligned box, e.g. around a mesh's vertices in its own space
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct BoundingBox {
+    pub min: glm::Vec3,
+    pub max: glm::Vec3
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct BoundingSphere {
+    pub center: glm::Vec3,
+    pub radius: f32
+}
+
+impl BoundingBox {
+    pub fn new(min: glm::Vec3, max: glm::Vec3) -> Self {
+        Self {
+            min,
+            max
+        }
+    }
+
+    // None if there are no points
+    pub fn from_points<I>(points: I) -> Option<Self> where
+        I: IntoIterator<Item = glm::Vec3> {
+        let mut points = points.into_iter();
+        let first = points.next()?;
+
+        Some(points.fold(Self::new(first, first), |bounding_box, point| Self::new(
+            glm::min2(&bounding_box.min, &point),
+            glm::max2(&bounding_box.max, &point))))
+    }
+
+    pub fn center(&self) -> glm::Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn half_extents(&self) -> glm::Vec3 {
+        (self.max - self.min) * 0.5
+    }
+
+    // Smallest axis aligned box around the transformed box
+    pub fn transformed(&self, transform: &glm::Mat4) -> Self {
+        let center = transform_point(transform, &self.center());
+        let half_extents = self.half_extents();
+        let linear = glm::mat4_to_mat3(transform);
+        let abs_linear = linear.map(f32::abs);
+        let transformed_half_extents = abs_linear * half_extents;
+
+        Self::new(center - transformed_half_extents, center + transformed_half_extents)
+    }
+
+    pub fn bounding_sphere(&self) -> BoundingSphere {
+        BoundingSphere {
+            center: self.center(),
+            radius: glm::length(&self.half_extents())
+        }
+    }
+}
+
+impl BoundingSphere {
+    // Non-uniform scaling stretches the sphere to the largest scale factor
+    pub fn transformed(&self, transform: &glm::Mat4) -> Self {
+        let linear = glm::mat4_to_mat3(transform);
+        let max_scale = (0..3)
+            .map(|column| glm::length(&linear.column(column).into_owned()))
+            .fold(0.0, f32::max);
+
+        Self {
+            center: transform_point(transform, &self.center),
+            radius: self.radius * max_scale
+        }
+    }
+}
+
+fn transform_point(transform: &glm::Mat4, point: &glm::Vec3) -> glm::Vec3 {
+    glm::vec4_to_vec3(&(transform * glm::vec4(point.x, point.y, point.z, 1.0)))
+}
+
+// Planes bounding what a camera sees, with normals pointing inwards
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Frustum {
+    // Left, right, bottom, top, near and far, as (normal, distance)
+    planes: [glm::Vec4; 6]
+}
+
+impl Frustum {
+    // Expects Vulkan's clip space with depth from 0 to 1, as made by `glm::perspective_rh_zo`
+    pub fn from_view_projection(view_projection: &glm::Mat4) -> Self {
+        let row = |index: usize| view_projection.row(index).transpose();
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+
+        let planes = [w + x, w - x, w + y, w - y, z, w - z];
+
+        Self {
+            planes: [
+                normalize_plane(&planes[0]),
+                normalize_plane(&planes[1]),
+                normalize_plane(&planes[2]),
+                normalize_plane(&planes[3]),
+                normalize_plane(&planes[4]),
+                normalize_plane(&planes[5])
+            ]
+        }
+    }
+
+    // e.g. for culling in shaders
+    pub fn planes(&self) -> &[glm::Vec4; 6] {
+        &self.planes
+    }
+
+    // Conservative, spheres near corners of the frustum may intersect it without being seen
+    pub fn intersects_sphere(&self, sphere: &BoundingSphere) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| signed_distance(plane, &sphere.center) >= -sphere.radius)
+    }
+
+    // Conservative like `intersects_sphere`
+    pub fn intersects_box(&self, bounding_box: &BoundingBox) -> bool {
+        self.planes.iter().all(|plane| {
+            // Corner of the box furthest along the plane's normal
+            let corner = glm::vec3(
+                if plane.x >= 0.0 { bounding_box.max.x } else { bounding_box.min.x },
+                if plane.y >= 0.0 { bounding_box.max.y } else { bounding_box.min.y },
+                if plane.z >= 0.0 { bounding_box.max.z } else { bounding_box.min.z });
+
+            signed_distance(plane, &corner) >= 0.0
+        })
+    }
+}
+
+fn normalize_plane(plane: &glm::Vec4) -> glm::Vec4 {
+    let normal_length = glm::length(&glm::vec4_to_vec3(plane));
+    plane / normal_length
+}
+
+fn signed_distance(plane: &glm::Vec4, point: &glm::Vec3) -> f32 {
+    glm::dot(&glm::vec4_to_vec3(plane), point) + plane.w
+}
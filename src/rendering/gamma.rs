@@ -0,0 +1,344 @@
+use std::rc::Rc;
+use ash::vk;
+use crate::{
+    builder::{
+        BuilderRequirement,
+        BuilderInternal,
+        BuilderProduct
+    },
+    rendering::{
+        RenderingError,
+        RenderingResult,
+        renderer::Renderer,
+        custom_pass::{
+            CustomPass,
+            CustomPassContext
+        }
+    },
+    vulkan::{
+        image::Image,
+        sampler::Sampler,
+        render_pass::RenderPass,
+        framebuffers::Framebuffers,
+        descriptor::{
+            DescriptorSet,
+            DescriptorSetLayout
+        },
+        pipeline::{
+            Pipeline,
+            BlendMode
+        },
+        shader::{
+            VertexShader,
+            FragmentShader,
+            ShaderStage
+        },
+        command_buffer::CommandBufferRecorder,
+        barrier::{
+            PipelineBarrier,
+            MemoryAccess,
+            ImageBarrier
+        }
+    }
+};
+
+// Whether shaders write linear colors, which have to be encoded for the display,
+// or colors which are displayed as they are
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum GammaCorrection {
+    Enabled,
+    Disabled
+}
+
+// How the renderer ended up encoding colors, which depends on the formats the surface supports
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum GammaPath {
+    // The swapchain has an sRGB format, so colors are encoded whenever they are written.
+    // Chosen whenever possible with gamma correction enabled, but also used with it
+    // disabled if the surface supports no UNORM format.
+    SrgbSwapchain,
+    // The swapchain has a UNORM format and gamma correction is enabled,
+    // so colors are encoded at the end of the frame by a `GammaPass`
+    GammaPass,
+    // The swapchain has a UNORM format and gamma correction is disabled
+    Uncorrected
+}
+
+impl GammaPath {
+    pub(crate) fn choose(gamma_correction: GammaCorrection, format: vk::Format) -> Self {
+        match (is_srgb(format), gamma_correction) {
+            (true, _) => GammaPath::SrgbSwapchain,
+            (false, GammaCorrection::Enabled) => GammaPath::GammaPass,
+            (false, GammaCorrection::Disabled) => GammaPath::Uncorrected
+        }
+    }
+}
+
+pub fn is_srgb(format: vk::Format) -> bool {
+    matches!(
+        format,
+        vk::Format::B8G8R8A8_SRGB | vk::Format::R8G8B8A8_SRGB | vk::Format::A8B8G8R8_SRGB_PACK32)
+}
+
+// Swapchain formats in order of preference, the same on every machine regardless of
+// the order in which the surface lists its formats
+pub(crate) fn preferred_formats(gamma_correction: GammaCorrection) -> [vk::SurfaceFormatKHR; 4] {
+    let format = |format| vk::SurfaceFormatKHR {
+        format,
+        color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR
+    };
+
+    let srgb = [format(vk::Format::B8G8R8A8_SRGB), format(vk::Format::R8G8B8A8_SRGB)];
+    let unorm = [format(vk::Format::B8G8R8A8_UNORM), format(vk::Format::R8G8B8A8_UNORM)];
+
+    match gamma_correction {
+        GammaCorrection::Enabled => [srgb[0], srgb[1], unorm[0], unorm[1]],
+        GammaCorrection::Disabled => [unorm[0], unorm[1], srgb[0], srgb[1]]
+    }
+}
+
+// Encodes the linear colors of a UNORM swapchain image for the display when the renderer's
+// gamma path is `GammaPath::GammaPass`, and records nothing otherwise. The image is copied
+// aside and drawn back with a fullscreen triangle, e.g. with shaders/fullscreen.vert and
+// shaders/gamma_encode.frag, sampling the copy at set 0, binding 0.
+//
+// Registered at `CustomPassPoint::AfterMainPass` after every other pass drawing
+// linear colors, e.g. the UI pass. The copy has the size of the swapchain,
+// so the pass is built anew with the renderer.
+pub struct GammaPass {
+    path: GammaPath,
+    resources: Option<GammaPassResources>
+}
+
+struct GammaPassResources {
+    render_pass: Rc<RenderPass>,
+    framebuffers: Framebuffers,
+    pipeline: Rc<Pipeline>,
+    copy: Rc<Image>,
+    copy_set: Rc<DescriptorSet>
+}
+
+impl GammaPass {
+    pub fn builder<'a>() -> GammaPassBuilder<'a> {
+        GammaPassBuilder {
+            ..Default::default()
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.resources.is_some()
+    }
+
+    pub fn path(&self) -> GammaPath {
+        self.path
+    }
+}
+
+impl CustomPass for GammaPass {
+    fn record<'a>(
+        &self,
+        recorder: CommandBufferRecorder<'a>,
+        context: &CustomPassContext
+    ) -> CommandBufferRecorder<'a> {
+        let resources = match &self.resources {
+            Some(resources) => resources,
+            None => return recorder
+        };
+
+        recorder
+            // The previous frame may still be sampling the copy
+            .pipeline_barrier(&PipelineBarrier::new()
+                .image(ImageBarrier::from_handle(
+                    context.swapchain_image,
+                    (vk::ImageLayout::PRESENT_SRC_KHR, vk::ImageLayout::TRANSFER_SRC_OPTIMAL),
+                    (MemoryAccess::color_attachment_write(), MemoryAccess::transfer_read())))
+                .image(ImageBarrier::new(
+                    &resources.copy,
+                    (vk::ImageLayout::UNDEFINED, vk::ImageLayout::TRANSFER_DST_OPTIMAL),
+                    (MemoryAccess::fragment_shader_read(), MemoryAccess::transfer_write()))))
+            .copy_image(
+                context.swapchain_image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                &resources.copy,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+            // The render pass discards the swapchain image's contents, so only the copy
+            // has to be finished before it overwrites them
+            .pipeline_barrier(&PipelineBarrier::new()
+                .image(ImageBarrier::from_handle(
+                    context.swapchain_image,
+                    (vk::ImageLayout::TRANSFER_SRC_OPTIMAL, vk::ImageLayout::PRESENT_SRC_KHR),
+                    (MemoryAccess::transfer_read(), MemoryAccess::color_attachment_write())))
+                .image(ImageBarrier::new(
+                    &resources.copy,
+                    (
+                        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+                    ),
+                    (MemoryAccess::transfer_write(), MemoryAccess::fragment_shader_read()))))
+            .begin_render_pass(&resources.render_pass, &resources.framebuffers, context.image_index)
+            .set_viewport(context.full_area)
+            .bind_pipeline(Rc::clone(&resources.pipeline))
+            .bind_descriptor_set(&resources.pipeline, 0, Rc::clone(&resources.copy_set))
+            .draw(3, 1)
+            .end_render_pass()
+    }
+}
+
+#[derive(Default)]
+pub struct GammaPassBuilder<'a> {
+    renderer: BuilderRequirement<&'a Renderer>,
+    vertex_shader: BuilderRequirement<&'a VertexShader>,
+    fragment_shader: BuilderRequirement<&'a FragmentShader>,
+
+    copy: BuilderInternal<Rc<Image>>,
+    copy_set_layout: BuilderInternal<Rc<DescriptorSetLayout>>,
+    copy_set: BuilderInternal<Rc<DescriptorSet>>,
+    render_pass: BuilderInternal<Rc<RenderPass>>,
+    framebuffers: BuilderInternal<Framebuffers>,
+    pipeline: BuilderInternal<Rc<Pipeline>>,
+
+    gamma_pass: BuilderProduct<GammaPass>
+}
+
+impl<'a> GammaPassBuilder<'a> {
+    pub fn renderer(mut self, renderer: &'a Renderer) -> Self {
+        self.renderer.set(renderer);
+        self
+    }
+
+    pub fn vertex_shader(mut self, vertex_shader: &'a VertexShader) -> Self {
+        self.vertex_shader.set(vertex_shader);
+        self
+    }
+
+    pub fn fragment_shader(mut self, fragment_shader: &'a FragmentShader) -> Self {
+        self.fragment_shader.set(fragment_shader);
+        self
+    }
+
+    // Nothing is created unless the renderer encodes colors in a gamma pass
+    pub fn build(mut self) -> RenderingResult<GammaPass> {
+        check_required_fields!(
+            self, RenderingError, "GammaPassBuilder",
+            [renderer, vertex_shader, fragment_shader]);
+
+        let path = self.renderer.gamma_path();
+        if path != GammaPath::GammaPass {
+            return Ok(GammaPass {
+                path,
+                resources: None
+            });
+        }
+
+        self.check_swapchain_copyable()?;
+        self.init_copy()?;
+        self.init_copy_set()?;
+        self.init_render_pass()?;
+        self.init_framebuffers()?;
+        self.init_pipeline()?;
+        self.create_gamma_pass(path);
+
+        Ok(self.gamma_pass.unwrap())
+    }
+
+    fn check_swapchain_copyable(&self) -> RenderingResult<()> {
+        let image_usage = self.renderer.swapchain().image_usage();
+
+        if image_usage.contains(vk::ImageUsageFlags::TRANSFER_SRC) {
+            Ok(())
+        }
+        else {
+            Err(RenderingError::GammaPassNotSupported)
+        }
+    }
+
+    fn init_copy(&mut self) -> RenderingResult<()> {
+        let swapchain = self.renderer.swapchain();
+        let copy = Image::builder()
+            .allocator(Rc::clone(self.renderer.allocator()))
+            .logical_device(Rc::clone(self.renderer.logical_device()))
+            .format(swapchain.image_format())
+            .extent(swapchain.extent())
+            .usage(vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED)
+            .aspect(vk::ImageAspectFlags::COLOR)
+            .build()?;
+
+        self.copy.set(Rc::new(copy));
+
+        Ok(())
+    }
+
+    fn init_copy_set(&mut self) -> RenderingResult<()> {
+        let logical_device = self.renderer.logical_device();
+        let layout = DescriptorSetLayout::builder()
+            .logical_device(Rc::clone(logical_device))
+            .combined_image_sampler(0, &[ShaderStage::Fragment])
+            .build()?;
+        let layout = Rc::new(layout);
+
+        // Texels are drawn back one to one, so they need no filtering
+        let sampler = Sampler::new(Rc::clone(logical_device), vk::Filter::NEAREST, 1)?;
+        let mut copy_set = DescriptorSet::new(Rc::clone(&layout))?;
+        copy_set.write_combined_image_sampler(0, Rc::clone(&self.copy), Rc::new(sampler))?;
+
+        self.copy_set_layout.set(layout);
+        self.copy_set.set(Rc::new(copy_set));
+
+        Ok(())
+    }
+
+    // Every pixel is overwritten, so the swapchain image is not loaded
+    fn init_render_pass(&mut self) -> RenderingResult<()> {
+        let render_pass = RenderPass::builder()
+            .logical_device(Rc::clone(self.renderer.logical_device()))
+            .swapchain(Rc::clone(self.renderer.swapchain()))
+            .load_op(vk::AttachmentLoadOp::DONT_CARE)
+            .build()?;
+
+        self.render_pass.set(Rc::new(render_pass));
+
+        Ok(())
+    }
+
+    fn init_framebuffers(&mut self) -> RenderingResult<()> {
+        let framebuffers = Framebuffers::builder()
+            .logical_device(Rc::clone(self.renderer.logical_device()))
+            .swapchain(Rc::clone(self.renderer.swapchain()))
+            .render_pass(Rc::clone(&self.render_pass))
+            .build()?;
+
+        self.framebuffers.set(framebuffers);
+
+        Ok(())
+    }
+
+    fn init_pipeline(&mut self) -> RenderingResult<()> {
+        let pipeline = Pipeline::builder()
+            .logical_device(Rc::clone(self.renderer.logical_device()))
+            .swapchain(Rc::clone(self.renderer.swapchain()))
+            .render_pass(Rc::clone(&self.render_pass))
+            .subpass(0)
+            .vertex_shader(*self.vertex_shader)
+            .fragment_shader(*self.fragment_shader)
+            .blend_mode(BlendMode::Opaque)
+            .descriptor_set_layout(Rc::clone(&self.copy_set_layout))
+            .build()?;
+
+        self.pipeline.set(Rc::new(pipeline));
+
+        Ok(())
+    }
+
+    fn create_gamma_pass(&mut self, path: GammaPath) {
+        self.gamma_pass.set(GammaPass {
+            path,
+            resources: Some(GammaPassResources {
+                render_pass: self.render_pass.take(),
+                framebuffers: self.framebuffers.take(),
+                pipeline: self.pipeline.take(),
+                copy: self.copy.take(),
+                copy_set: self.copy_set.take()
+            })
+        });
+    }
+}
@@ -0,0 +1,348 @@
+use std::{
+    cell::RefCell,
+    rc::Rc
+};
+use ash::vk;
+use crate::{
+    builder::{
+        BuilderRequirement,
+        BuilderInternal,
+        BuilderProduct
+    },
+    rendering::{
+        RenderingError,
+        RenderingResult,
+        renderer::Renderer,
+        frustum::{
+            Frustum,
+            BoundingSphere
+        }
+    },
+    vulkan::{
+        allocator::MemoryUsage,
+        buffer::Buffer,
+        descriptor::{
+            DescriptorSet,
+            DescriptorSetLayout
+        },
+        pipeline::Pipeline,
+        shader::{
+            ComputeShader,
+            ShaderStage
+        },
+        command_buffer::{
+            CommandBufferRecorder,
+            PushConstants
+        },
+        barrier::{
+            PipelineBarrier,
+            MemoryAccess
+        }
+    }
+};
+
+// Indexed draw of a part of the bound index and vertex buffers, culled against
+// the frustum with its bounding sphere
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct IndirectDraw {
+    pub bounding_sphere: BoundingSphere,
+    pub index_count: u32,
+    pub first_index: u32,
+    pub vertex_offset: i32,
+    // Values other than 0 need `draw_indirect_first_instance`
+    pub first_instance: u32
+}
+
+// Layout of the draws in the shader's storage buffer
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct GpuDraw {
+    bounding_sphere: [f32; 4],
+    index_count: u32,
+    first_index: u32,
+    vertex_offset: i32,
+    first_instance: u32
+}
+
+impl From<&IndirectDraw> for GpuDraw {
+    fn from(draw: &IndirectDraw) -> Self {
+        let center = draw.bounding_sphere.center;
+
+        Self {
+            bounding_sphere: [center.x, center.y, center.z, draw.bounding_sphere.radius],
+            index_count: draw.index_count,
+            first_index: draw.first_index,
+            vertex_offset: draw.vertex_offset,
+            first_instance: draw.first_instance
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, PushConstants)]
+struct CullingConstants {
+    planes: [[f32; 4]; 6],
+    first_draw: u32,
+    draw_count: u32
+}
+
+// Frustum culling of many draws on the GPU, e.g. of all chunks of a voxel world sharing
+// one vertex and index buffer. A compute shader, e.g. shaders/cull_draws.comp, writes
+// a `vk::DrawIndexedIndirectCommand` for every draw, with no instances if the draw is culled,
+// and all of them are drawn with a single `CommandBufferRecorder::draw_indexed_indirect`.
+//
+// The shader reads the draws from a storage buffer at set 0, binding 0 and writes
+// the commands into one at binding 1. Its push constants hold the frustum planes followed
+// by the index of the first draw and the number of draws, in workgroups of
+// `GpuCulling::WORKGROUP_SIZE` invocations.
+//
+// Culling is recorded outside of render passes every frame, e.g. with `FrameContext::record`,
+// and the draws are recorded in the frame's passes, e.g. in a custom pass.
+pub struct GpuCulling {
+    pipeline: Rc<Pipeline>,
+    descriptor_set: Rc<DescriptorSet>,
+    // Draws of every region are written by the CPU, see `set_draws`
+    draw_buffer: Rc<RefCell<Buffer>>,
+    command_buffer: Rc<RefCell<Buffer>>,
+    capacity: usize,
+    region_count: usize,
+    region: usize,
+    // Frame in which the current region was written
+    written_frame: Option<u64>,
+    draw_count: usize,
+    is_multi_draw_enabled: bool
+}
+
+impl GpuCulling {
+    pub const WORKGROUP_SIZE: u32 = 64;
+
+    pub fn builder<'a>() -> GpuCullingBuilder<'a> {
+        GpuCullingBuilder {
+            ..Default::default()
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn draw_count(&self) -> usize {
+        self.draw_count
+    }
+
+    // Draws are written into a region of their own buffer no submitted frame culls with,
+    // so they can be replaced in any frame. Draws recorded before keep drawing
+    // the previous count, so command buffers recording them have to be rerecorded
+    // once the count changes.
+    pub fn set_draws(
+        &mut self,
+        renderer: &Renderer,
+        draws: &[IndirectDraw]
+    ) -> RenderingResult<()> {
+        if draws.len() > self.capacity {
+            return Err(RenderingError::GpuCullingTooManyDraws {
+                count: draws.len(),
+                capacity: self.capacity
+            });
+        }
+
+        // Regions rotate at most once per frame, so the next region was last culled with
+        // more than `Renderer::FRAMES_IN_FLIGHT` frames ago
+        if self.written_frame != Some(renderer.frame_number()) {
+            self.region = (self.region + 1) % self.region_count;
+            self.written_frame = Some(renderer.frame_number());
+        }
+
+        let gpu_draws: Vec<GpuDraw> = draws.iter().map(GpuDraw::from).collect();
+        let data = unsafe {
+            std::slice::from_raw_parts(
+                gpu_draws.as_ptr() as *const u8,
+                gpu_draws.len() * std::mem::size_of::<GpuDraw>())
+        };
+
+        let offset = self.region * self.capacity * std::mem::size_of::<GpuDraw>();
+        self.draw_buffer.borrow_mut().write_data(offset, data)?;
+        self.draw_count = draws.len();
+
+        Ok(())
+    }
+
+    // Waits for draws of earlier frames to read their commands before overwriting them
+    // and makes the new ones visible to the draws recorded after
+    pub fn record_culling<'a>(
+        &self,
+        recorder: CommandBufferRecorder<'a>,
+        frustum: &Frustum
+    ) -> CommandBufferRecorder<'a> {
+        if self.draw_count == 0 {
+            return recorder;
+        }
+
+        let mut planes = [[0.0; 4]; 6];
+        for (plane, frustum_plane) in planes.iter_mut().zip(frustum.planes()) {
+            *plane = (*frustum_plane).into();
+        }
+
+        let constants = CullingConstants {
+            planes,
+            first_draw: (self.region * self.capacity) as u32,
+            draw_count: self.draw_count as u32
+        };
+
+        let group_count =
+            (self.draw_count as u32 + Self::WORKGROUP_SIZE - 1) / Self::WORKGROUP_SIZE;
+
+        recorder
+            .pipeline_barrier(&PipelineBarrier::new()
+                .memory(
+                    MemoryAccess::execution(vk::PipelineStageFlags::DRAW_INDIRECT),
+                    MemoryAccess::compute_shader_write()))
+            .bind_pipeline(Rc::clone(&self.pipeline))
+            .bind_descriptor_set(&self.pipeline, 0, Rc::clone(&self.descriptor_set))
+            .push_constant(&self.pipeline, ShaderStage::Compute, &constants)
+            .dispatch([group_count, 1, 1])
+            .pipeline_barrier(&PipelineBarrier::new().compute_to_graphics())
+    }
+
+    // The index and vertex buffers the draws refer to have to be bound.
+    // Draws one command at a time without `multi_draw_indirect`.
+    pub fn record_draws<'a>(
+        &self,
+        recorder: CommandBufferRecorder<'a>
+    ) -> CommandBufferRecorder<'a> {
+        let command_buffer = self.command_buffer.borrow();
+
+        if self.is_multi_draw_enabled {
+            return recorder.draw_indexed_indirect(&command_buffer, 0, self.draw_count as u32);
+        }
+
+        let stride = std::mem::size_of::<vk::DrawIndexedIndirectCommand>();
+        (0..self.draw_count).fold(recorder, |recorder, draw| {
+            recorder.draw_indexed_indirect(&command_buffer, draw * stride, 1)
+        })
+    }
+}
+
+#[derive(Default)]
+pub struct GpuCullingBuilder<'a> {
+    renderer: BuilderRequirement<&'a Renderer>,
+    compute_shader: BuilderRequirement<&'a ComputeShader>,
+    capacity: BuilderRequirement<usize>,
+
+    region_count: BuilderInternal<usize>,
+    draw_buffer: BuilderInternal<Rc<RefCell<Buffer>>>,
+    command_buffer: BuilderInternal<Rc<RefCell<Buffer>>>,
+    descriptor_set_layout: BuilderInternal<Rc<DescriptorSetLayout>>,
+    descriptor_set: BuilderInternal<Rc<DescriptorSet>>,
+    pipeline: BuilderInternal<Rc<Pipeline>>,
+
+    gpu_culling: BuilderProduct<GpuCulling>
+}
+
+impl<'a> GpuCullingBuilder<'a> {
+    pub fn renderer(mut self, renderer: &'a Renderer) -> Self {
+        self.renderer.set(renderer);
+        self
+    }
+
+    pub fn compute_shader(mut self, compute_shader: &'a ComputeShader) -> Self {
+        self.compute_shader.set(compute_shader);
+        self
+    }
+
+    // Most draws which can be set at once
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity.set(capacity);
+        self
+    }
+
+    pub fn build(mut self) -> RenderingResult<GpuCulling> {
+        check_required_fields!(
+            self, RenderingError, "GpuCullingBuilder",
+            [renderer, compute_shader, capacity]);
+
+        self.init_region_count();
+        self.init_buffers()?;
+        self.init_descriptor_set()?;
+        self.init_pipeline()?;
+        self.create_gpu_culling();
+
+        Ok(self.gpu_culling.unwrap())
+    }
+
+    fn init_region_count(&mut self) {
+        self.region_count.set(Renderer::FRAMES_IN_FLIGHT + 1);
+    }
+
+    fn init_buffers(&mut self) -> RenderingResult<()> {
+        let draw_buffer = Buffer::builder()
+            .allocator(Rc::clone(self.renderer.allocator()))
+            .size((std::mem::size_of::<GpuDraw>() * *self.capacity * *self.region_count).max(1))
+            .usage(vk::BufferUsageFlags::STORAGE_BUFFER)
+            .memory_usage(MemoryUsage::CpuToGpu)
+            .host_mapped(true)
+            .build()?;
+
+        let command_size = std::mem::size_of::<vk::DrawIndexedIndirectCommand>();
+        let command_buffer = Buffer::builder()
+            .allocator(Rc::clone(self.renderer.allocator()))
+            .size((command_size * *self.capacity).max(1))
+            .usage(vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::INDIRECT_BUFFER)
+            .memory_usage(MemoryUsage::GpuOnly)
+            .build()?;
+
+        self.draw_buffer.set(Rc::new(RefCell::new(draw_buffer)));
+        self.command_buffer.set(Rc::new(RefCell::new(command_buffer)));
+
+        Ok(())
+    }
+
+    fn init_descriptor_set(&mut self) -> RenderingResult<()> {
+        let layout = DescriptorSetLayout::builder()
+            .logical_device(Rc::clone(self.renderer.logical_device()))
+            .storage_buffer(0, &[ShaderStage::Compute])
+            .storage_buffer(1, &[ShaderStage::Compute])
+            .build()?;
+        let layout = Rc::new(layout);
+
+        let mut descriptor_set = DescriptorSet::new(Rc::clone(&layout))?;
+        descriptor_set.write_storage_buffer(0, Rc::clone(&self.draw_buffer))?;
+        descriptor_set.write_storage_buffer(1, Rc::clone(&self.command_buffer))?;
+
+        self.descriptor_set_layout.set(layout);
+        self.descriptor_set.set(Rc::new(descriptor_set));
+
+        Ok(())
+    }
+
+    fn init_pipeline(&mut self) -> RenderingResult<()> {
+        let pipeline = Pipeline::compute_builder()
+            .logical_device(Rc::clone(self.renderer.logical_device()))
+            .compute_shader(*self.compute_shader)
+            .descriptor_set_layout(Rc::clone(&self.descriptor_set_layout))
+            .push_constants_size(std::mem::size_of::<CullingConstants>())
+            .build()?;
+
+        self.pipeline.set(Rc::new(pipeline));
+
+        Ok(())
+    }
+
+    fn create_gpu_culling(&mut self) {
+        self.gpu_culling.set(GpuCulling {
+            pipeline: self.pipeline.take(),
+            descriptor_set: self.descriptor_set.take(),
+            draw_buffer: self.draw_buffer.take(),
+            command_buffer: self.command_buffer.take(),
+            capacity: *self.capacity,
+            region_count: self.region_count.take(),
+            region: 0,
+            written_frame: None,
+            draw_count: 0,
+            is_multi_draw_enabled: self.renderer
+                .logical_device()
+                .enabled_features()
+                .multi_draw_indirect
+        });
+    }
+}
@@ -0,0 +1,819 @@
+use std::{
+    collections::HashSet,
+    rc::Rc
+};
+use ash::vk;
+use crate::{
+    rendering::{
+        RenderingError,
+        RenderingResult,
+        renderer::Renderer,
+        custom_pass::{
+            CustomPass,
+            CustomPassContext
+        }
+    },
+    vulkan::{
+        handle::VulkanHandle,
+        buffer::Buffer,
+        image::Image,
+        command_buffer::CommandBufferRecorder,
+        barrier::{
+            PipelineBarrier,
+            MemoryAccess,
+            BufferBarrier,
+            ImageBarrier
+        }
+    }
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct GraphImage(usize);
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct GraphBuffer(usize);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ImageUse {
+    // Rendered to inside of a render pass which keeps the image in COLOR_ATTACHMENT_OPTIMAL
+    ColorAttachment,
+    Sampled(vk::PipelineStageFlags),
+    StorageRead(vk::PipelineStageFlags),
+    StorageWrite(vk::PipelineStageFlags),
+    TransferSrc,
+    TransferDst
+}
+
+impl ImageUse {
+    fn layout(self) -> vk::ImageLayout {
+        match self {
+            ImageUse::ColorAttachment => vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            ImageUse::Sampled(_) => vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            ImageUse::StorageRead(_) | ImageUse::StorageWrite(_) => vk::ImageLayout::GENERAL,
+            ImageUse::TransferSrc => vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            ImageUse::TransferDst => vk::ImageLayout::TRANSFER_DST_OPTIMAL
+        }
+    }
+
+    fn access(self) -> MemoryAccess {
+        match self {
+            // Loading the previous contents reads them
+            ImageUse::ColorAttachment => MemoryAccess::new(
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE),
+            ImageUse::Sampled(stages) | ImageUse::StorageRead(stages) => {
+                MemoryAccess::shader_read(stages)
+            },
+            ImageUse::StorageWrite(stages) => {
+                MemoryAccess::shader_read(stages).union(MemoryAccess::shader_write(stages))
+            },
+            ImageUse::TransferSrc => MemoryAccess::transfer_read(),
+            ImageUse::TransferDst => MemoryAccess::transfer_write()
+        }
+    }
+
+    fn usage_flags(self) -> vk::ImageUsageFlags {
+        match self {
+            ImageUse::ColorAttachment => vk::ImageUsageFlags::COLOR_ATTACHMENT,
+            ImageUse::Sampled(_) => vk::ImageUsageFlags::SAMPLED,
+            ImageUse::StorageRead(_) | ImageUse::StorageWrite(_) => vk::ImageUsageFlags::STORAGE,
+            ImageUse::TransferSrc => vk::ImageUsageFlags::TRANSFER_SRC,
+            ImageUse::TransferDst => vk::ImageUsageFlags::TRANSFER_DST
+        }
+    }
+
+    fn is_write(self) -> bool {
+        match self {
+            ImageUse::ColorAttachment | ImageUse::StorageWrite(_) | ImageUse::TransferDst => true,
+            ImageUse::Sampled(_) | ImageUse::StorageRead(_) | ImageUse::TransferSrc => false
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BufferUse {
+    VertexInput,
+    Uniform(vk::PipelineStageFlags),
+    StorageRead(vk::PipelineStageFlags),
+    StorageWrite(vk::PipelineStageFlags),
+    TransferSrc,
+    TransferDst
+}
+
+impl BufferUse {
+    fn access(self) -> MemoryAccess {
+        match self {
+            BufferUse::VertexInput => MemoryAccess::vertex_input_read(),
+            BufferUse::Uniform(stages) => MemoryAccess::uniform_read(stages),
+            BufferUse::StorageRead(stages) => MemoryAccess::shader_read(stages),
+            BufferUse::StorageWrite(stages) => {
+                MemoryAccess::shader_read(stages).union(MemoryAccess::shader_write(stages))
+            },
+            BufferUse::TransferSrc => MemoryAccess::transfer_read(),
+            BufferUse::TransferDst => MemoryAccess::transfer_write()
+        }
+    }
+
+    fn is_write(self) -> bool {
+        match self {
+            BufferUse::StorageWrite(_) | BufferUse::TransferDst => true,
+            BufferUse::VertexInput
+                | BufferUse::Uniform(_)
+                | BufferUse::StorageRead(_)
+                | BufferUse::TransferSrc => false
+        }
+    }
+}
+
+type RecordFunction = dyn for<'a> Fn(
+    CommandBufferRecorder<'a>,
+    &RenderGraphResources
+) -> CommandBufferRecorder<'a>;
+
+// Commands of a single pass together with every graph resource they touch.
+// Resources which are not declared are not synchronized by the graph.
+pub struct RenderGraphPass {
+    name: String,
+    images: Vec<(GraphImage, ImageUse)>,
+    buffers: Vec<(GraphBuffer, BufferUse)>,
+    record: Box<RecordFunction>
+}
+
+impl RenderGraphPass {
+    pub fn new<F>(name: &str, record: F) -> Self where
+        F: for<'a> Fn(
+            CommandBufferRecorder<'a>,
+            &RenderGraphResources
+        ) -> CommandBufferRecorder<'a> + 'static {
+        Self {
+            name: name.to_owned(),
+            images: Vec::new(),
+            buffers: Vec::new(),
+            record: Box::new(record)
+        }
+    }
+
+    pub fn image(mut self, image: GraphImage, image_use: ImageUse) -> Self {
+        self.images.push((image, image_use));
+        self
+    }
+
+    pub fn buffer(mut self, buffer: GraphBuffer, buffer_use: BufferUse) -> Self {
+        self.buffers.push((buffer, buffer_use));
+        self
+    }
+}
+
+enum ImageSource {
+    // Created by the graph, contents do not outlive the frame
+    Transient {
+        format: vk::Format,
+        extent: vk::Extent2D
+    },
+    Imported(Rc<Image>),
+    // Image acquired for the frame being recorded
+    Swapchain
+}
+
+struct ImageResource {
+    name: String,
+    source: ImageSource,
+    // Layout and access of the image before the graph, and the ones it is left with
+    initial_state: (vk::ImageLayout, MemoryAccess),
+    final_state: (vk::ImageLayout, MemoryAccess)
+}
+
+// Slot of every graph image, None for imported and unused ones, and the image of every slot
+type TransientImagePlacement = (Vec<Option<usize>>, Vec<Rc<Image>>);
+
+struct BufferResource {
+    name: String,
+    buffer: Rc<Buffer>,
+    initial_access: MemoryAccess,
+    final_access: MemoryAccess
+}
+
+// Passes and the resources they read and write. Compiling the graph orders the passes,
+// culls the ones whose results are never used, places transient images with disjoint
+// lifetimes in the same image and works out the barriers between the passes.
+//
+// A pass reading a resource runs after every pass writing it,
+// while passes writing the same resource run in the order they were added.
+#[derive(Default)]
+pub struct RenderGraph {
+    images: Vec<ImageResource>,
+    buffers: Vec<BufferResource>,
+    passes: Vec<RenderGraphPass>
+}
+
+impl RenderGraph {
+    pub fn new() -> Self {
+        Self {
+            ..Default::default()
+        }
+    }
+
+    // The graph has to be compiled again when the extent should follow the swapchain's
+    pub fn create_image(
+        &mut self,
+        name: &str,
+        format: vk::Format,
+        extent: vk::Extent2D
+    ) -> GraphImage {
+        self.add_image(
+            name,
+            ImageSource::Transient {format, extent},
+            (vk::ImageLayout::UNDEFINED, MemoryAccess::none()),
+            (vk::ImageLayout::UNDEFINED, MemoryAccess::none()))
+    }
+
+    // `initial_state` is what the image was last used as before the graph,
+    // `final_state` is the layout it is left in and what it is used as next
+    pub fn import_image(
+        &mut self,
+        name: &str,
+        image: Rc<Image>,
+        initial_state: (vk::ImageLayout, MemoryAccess),
+        final_state: (vk::ImageLayout, MemoryAccess)
+    ) -> GraphImage {
+        self.add_image(name, ImageSource::Imported(image), initial_state, final_state)
+    }
+
+    // The image is rendered to by the main render pass before the graph
+    // and has to be presented after it
+    pub fn import_swapchain_image(&mut self, name: &str) -> GraphImage {
+        self.add_image(
+            name,
+            ImageSource::Swapchain,
+            (vk::ImageLayout::PRESENT_SRC_KHR, MemoryAccess::color_attachment_write()),
+            (vk::ImageLayout::PRESENT_SRC_KHR, MemoryAccess::none()))
+    }
+
+    fn add_image(
+        &mut self,
+        name: &str,
+        source: ImageSource,
+        initial_state: (vk::ImageLayout, MemoryAccess),
+        final_state: (vk::ImageLayout, MemoryAccess)
+    ) -> GraphImage {
+        self.images.push(ImageResource {
+            name: name.to_owned(),
+            source,
+            initial_state,
+            final_state
+        });
+
+        GraphImage(self.images.len() - 1)
+    }
+
+    pub fn import_buffer(
+        &mut self,
+        name: &str,
+        buffer: Rc<Buffer>,
+        (initial_access, final_access): (MemoryAccess, MemoryAccess)
+    ) -> GraphBuffer {
+        self.buffers.push(BufferResource {
+            name: name.to_owned(),
+            buffer,
+            initial_access,
+            final_access
+        });
+
+        GraphBuffer(self.buffers.len() - 1)
+    }
+
+    pub fn add_pass(&mut self, pass: RenderGraphPass) -> RenderingResult<()> {
+        if self.passes.iter().any(|added| added.name == pass.name) {
+            return Err(RenderingError::RenderGraphPassAlreadyAdded {name: pass.name});
+        }
+
+        let is_foreign = pass.images.iter().any(|(image, _)| image.0 >= self.images.len())
+            || pass.buffers.iter().any(|(buffer, _)| buffer.0 >= self.buffers.len());
+
+        if is_foreign {
+            return Err(RenderingError::RenderGraphUnknownResource {pass: pass.name});
+        }
+
+        let accesses = self.accesses(&pass);
+
+        for (index, access) in accesses.iter().enumerate() {
+            if accesses[..index].iter().any(|other| other.resource == access.resource) {
+                return Err(RenderingError::RenderGraphResourceUsedTwice {
+                    pass: pass.name,
+                    resource: self.resource_name(access.resource).to_owned()
+                });
+            }
+        }
+
+        self.passes.push(pass);
+
+        Ok(())
+    }
+
+    fn accesses(&self, pass: &RenderGraphPass) -> Vec<ResourceAccess> {
+        let image_accesses = pass.images.iter().map(|(image, image_use)| ResourceAccess {
+            resource: Resource::Image(image.0),
+            access: image_use.access(),
+            layout: Some(image_use.layout()),
+            is_write: image_use.is_write()
+        });
+
+        let buffer_accesses = pass.buffers.iter().map(|(buffer, buffer_use)| ResourceAccess {
+            resource: Resource::Buffer(buffer.0),
+            access: buffer_use.access(),
+            layout: None,
+            is_write: buffer_use.is_write()
+        });
+
+        image_accesses.chain(buffer_accesses).collect()
+    }
+
+    fn resource_name(&self, resource: Resource) -> &str {
+        match resource {
+            Resource::Image(index) => &self.images[index].name,
+            Resource::Buffer(index) => &self.buffers[index].name
+        }
+    }
+
+    fn is_imported(&self, resource: Resource) -> bool {
+        match resource {
+            Resource::Image(index) => match self.images[index].source {
+                ImageSource::Transient {..} => false,
+                ImageSource::Imported(_) | ImageSource::Swapchain => true
+            },
+            Resource::Buffer(_) => true
+        }
+    }
+
+    // The graph is recorded as a custom pass outside of render passes, at
+    // `CustomPassPoint::BeforeMainPass` or `CustomPassPoint::AfterMainPass`.
+    // Graphs importing the swapchain image have to be recorded after the main pass.
+    pub fn compile(self, renderer: &Renderer) -> RenderingResult<CompiledRenderGraph> {
+        let accesses: Vec<_> = self.passes.iter().map(|pass| self.accesses(pass)).collect();
+        let order = self.execution_order(&accesses)?;
+        let order = self.cull(&accesses, order);
+        let (image_slots, transient_images) = self.place_transient_images(renderer, &order)?;
+
+        let mut compiler = BarrierCompiler::new(&self, &image_slots, transient_images.len());
+        let steps = order
+            .iter()
+            .map(|pass| CompiledStep {
+                pass: *pass,
+                transitions: compiler.pass_transitions(&accesses[*pass])
+            })
+            .collect();
+
+        let final_transitions = compiler.final_transitions(&self);
+
+        Ok(CompiledRenderGraph {
+            images: self.images,
+            buffers: self.buffers,
+            passes: self.passes,
+            image_slots,
+            transient_images,
+            steps,
+            final_transitions
+        })
+    }
+
+    // Topological sort which keeps the order of adding among independent passes
+    fn execution_order(&self, accesses: &[Vec<ResourceAccess>]) -> RenderingResult<Vec<usize>> {
+        let writes = |pass: usize, resource: Resource| {
+            accesses[pass]
+                .iter()
+                .any(|access| access.resource == resource && access.is_write)
+        };
+
+        let dependencies: Vec<Vec<usize>> = accesses
+            .iter()
+            .enumerate()
+            .map(|(pass, pass_accesses)| {
+                let mut dependencies = Vec::new();
+
+                for access in pass_accesses {
+                    let writers = (0..self.passes.len())
+                        .filter(|other| *other != pass && writes(*other, access.resource));
+
+                    if access.is_write {
+                        dependencies.extend(writers.filter(|writer| *writer < pass));
+                    }
+                    else {
+                        dependencies.extend(writers);
+                    }
+                }
+
+                dependencies
+            })
+            .collect();
+
+        let mut order = Vec::with_capacity(self.passes.len());
+        let mut is_ordered = vec![false; self.passes.len()];
+
+        while order.len() < self.passes.len() {
+            let next = (0..self.passes.len())
+                .filter(|pass| !is_ordered[*pass])
+                .find(|pass| dependencies[*pass].iter().all(|dependency| is_ordered[*dependency]));
+
+            match next {
+                Some(pass) => {
+                    is_ordered[pass] = true;
+                    order.push(pass);
+                },
+                None => {
+                    let pass = is_ordered.iter().position(|ordered| !ordered).unwrap();
+                    return Err(RenderingError::RenderGraphDependencyCycle {
+                        name: self.passes[pass].name.clone()
+                    });
+                }
+            }
+        }
+
+        Ok(order)
+    }
+
+    // Passes writing only transient images which no kept pass uses afterwards are culled.
+    // Passes without any declared writes are kept, they may have effects the graph
+    // does not know about.
+    fn cull(&self, accesses: &[Vec<ResourceAccess>], order: Vec<usize>) -> Vec<usize> {
+        let mut used_resources = HashSet::new();
+        let mut kept = Vec::with_capacity(order.len());
+
+        for pass in order.into_iter().rev() {
+            let mut writes = accesses[pass].iter().filter(|access| access.is_write).peekable();
+            let is_needed = writes.peek().is_none() || writes.any(|access| {
+                self.is_imported(access.resource) || used_resources.contains(&access.resource)
+            });
+
+            if is_needed {
+                used_resources.extend(accesses[pass].iter().map(|access| access.resource));
+                kept.push(pass);
+            }
+            else {
+                debug!("culled render graph pass `{}`", self.passes[pass].name);
+            }
+        }
+
+        kept.reverse();
+        kept
+    }
+
+    // Transient images of the same format, extent and usage share an image
+    // when no pass between the first and last use of one of them uses the other
+    fn place_transient_images(
+        &self,
+        renderer: &Renderer,
+        order: &[usize]
+    ) -> RenderingResult<TransientImagePlacement> {
+        struct Slot {
+            format: vk::Format,
+            extent: vk::Extent2D,
+            usage: vk::ImageUsageFlags,
+            last_use: usize
+        }
+
+        let mut image_slots = vec![None; self.images.len()];
+        let mut slots: Vec<Slot> = Vec::new();
+
+        for (index, image) in self.images.iter().enumerate() {
+            let (format, extent) = match image.source {
+                ImageSource::Transient {format, extent} => (format, extent),
+                _ => continue
+            };
+
+            let uses: Vec<(usize, ImageUse)> = order
+                .iter()
+                .enumerate()
+                .flat_map(|(position, pass)| {
+                    self.passes[*pass].images
+                        .iter()
+                        .filter(|(image, _)| image.0 == index)
+                        .map(move |(_, image_use)| (position, *image_use))
+                })
+                .collect();
+
+            let (first_use, last_use) = match (uses.first(), uses.last()) {
+                (Some(first), Some(last)) => (first.0, last.0),
+                _ => continue
+            };
+
+            let usage = uses
+                .iter()
+                .fold(vk::ImageUsageFlags::empty(), |usage, (_, image_use)| {
+                    usage | image_use.usage_flags()
+                });
+
+            let free_slot = slots.iter().position(|slot| {
+                slot.format == format
+                    && (slot.extent.width, slot.extent.height) == (extent.width, extent.height)
+                    && slot.usage == usage
+                    && slot.last_use < first_use
+            });
+
+            let slot = match free_slot {
+                Some(slot) => slot,
+                None => {
+                    slots.push(Slot {format, extent, usage, last_use});
+                    slots.len() - 1
+                }
+            };
+
+            slots[slot].last_use = last_use;
+            image_slots[index] = Some(slot);
+        }
+
+        debug!(
+            "render graph uses {} images for {} transient images",
+            slots.len(), image_slots.iter().filter(|slot| slot.is_some()).count());
+
+        let transient_images = slots
+            .iter()
+            .map(|slot| {
+                Image::builder()
+                    .allocator(Rc::clone(renderer.allocator()))
+                    .logical_device(Rc::clone(renderer.logical_device()))
+                    .format(slot.format)
+                    .extent(slot.extent)
+                    .usage(slot.usage)
+                    .build()
+                    .map(Rc::new)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok((image_slots, transient_images))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+enum Resource {
+    Image(usize),
+    Buffer(usize)
+}
+
+struct ResourceAccess {
+    resource: Resource,
+    access: MemoryAccess,
+    // Buffers have no layout
+    layout: Option<vk::ImageLayout>,
+    is_write: bool
+}
+
+struct Transition {
+    resource: Resource,
+    layouts: (vk::ImageLayout, vk::ImageLayout),
+    accesses: (MemoryAccess, MemoryAccess)
+}
+
+// Synchronization state of an image or buffer between passes
+struct ResourceState {
+    layout: vk::ImageLayout,
+    // Writes and layout transitions which later accesses have to wait for
+    pending_write: MemoryAccess,
+    // Reads since the last write, which already wait for it
+    reads: MemoryAccess
+}
+
+impl ResourceState {
+    fn new(layout: vk::ImageLayout, last_access: MemoryAccess) -> Self {
+        Self {
+            layout,
+            pending_write: last_access,
+            reads: MemoryAccess::none()
+        }
+    }
+
+    // Source access of the barrier needed before the given access, if any
+    fn access(
+        &mut self,
+        access: MemoryAccess,
+        layout: Option<vk::ImageLayout>,
+        is_write: bool
+    ) -> Option<(vk::ImageLayout, MemoryAccess)> {
+        let old_layout = self.layout;
+        let is_transition = layout.map_or(false, |layout| layout != old_layout);
+        self.layout = layout.unwrap_or(old_layout);
+
+        if is_write || is_transition {
+            // Writes have to wait for reads too, so that they do not overwrite what is read
+            let src = self.pending_write.union(MemoryAccess::execution(self.reads.stages));
+
+            // Later accesses are ordered after a transition through the stages waiting for it
+            self.pending_write = if is_write {
+                access
+            }
+            else {
+                MemoryAccess::execution(access.stages)
+            };
+
+            self.reads = if is_write {
+                MemoryAccess::none()
+            }
+            else {
+                access
+            };
+
+            return Some((old_layout, src));
+        }
+
+        let is_visible = self.reads.stages.contains(access.stages)
+            && self.reads.access.contains(access.access);
+        self.reads = self.reads.union(access);
+
+        if self.pending_write.stages.is_empty() || is_visible {
+            None
+        }
+        else {
+            Some((old_layout, self.pending_write))
+        }
+    }
+}
+
+struct BarrierCompiler<'a> {
+    image_slots: &'a [Option<usize>],
+    image_states: Vec<ResourceState>,
+    buffer_states: Vec<ResourceState>
+}
+
+impl<'a> BarrierCompiler<'a> {
+    // Transient images are tracked per image they are placed in,
+    // their states follow the imported images' ones
+    fn new(graph: &RenderGraph, image_slots: &'a [Option<usize>], slot_count: usize) -> Self {
+        let transient_states = (0..slot_count)
+            .map(|_| ResourceState::new(vk::ImageLayout::UNDEFINED, MemoryAccess::none()));
+
+        let imported_states = graph.images
+            .iter()
+            .map(|image| ResourceState::new(image.initial_state.0, image.initial_state.1));
+
+        let buffer_states = graph.buffers
+            .iter()
+            .map(|buffer| ResourceState::new(vk::ImageLayout::UNDEFINED, buffer.initial_access))
+            .collect();
+
+        Self {
+            image_slots,
+            image_states: transient_states.chain(imported_states).collect(),
+            buffer_states
+        }
+    }
+
+    fn state(&mut self, resource: Resource) -> &mut ResourceState {
+        match resource {
+            Resource::Image(index) => match self.image_slots[index] {
+                Some(slot) => &mut self.image_states[slot],
+                None => {
+                    let slot_count = self.image_states.len() - self.image_slots.len();
+                    &mut self.image_states[slot_count + index]
+                }
+            },
+            Resource::Buffer(index) => &mut self.buffer_states[index]
+        }
+    }
+
+    fn pass_transitions(&mut self, accesses: &[ResourceAccess]) -> Vec<Transition> {
+        accesses
+            .iter()
+            .filter_map(|access| {
+                let state = self.state(access.resource);
+                let (old_layout, src) = state.access(
+                    access.access, access.layout, access.is_write)?;
+
+                Some(Transition {
+                    resource: access.resource,
+                    layouts: (old_layout, state.layout),
+                    accesses: (src, access.access)
+                })
+            })
+            .collect()
+    }
+
+    // Imported resources are left in their final layouts and made available to their next use
+    fn final_transitions(&mut self, graph: &RenderGraph) -> Vec<Transition> {
+        let image_accesses = graph.images
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| graph.is_imported(Resource::Image(*index)))
+            .map(|(index, image)| ResourceAccess {
+                resource: Resource::Image(index),
+                access: image.final_state.1,
+                layout: Some(image.final_state.0),
+                is_write: false
+            });
+
+        let buffer_accesses = graph.buffers
+            .iter()
+            .enumerate()
+            .map(|(index, buffer)| ResourceAccess {
+                resource: Resource::Buffer(index),
+                access: buffer.final_access,
+                layout: None,
+                is_write: false
+            });
+
+        let accesses: Vec<_> = image_accesses.chain(buffer_accesses).collect();
+        self.pass_transitions(&accesses)
+    }
+}
+
+struct CompiledStep {
+    pass: usize,
+    transitions: Vec<Transition>
+}
+
+pub struct CompiledRenderGraph {
+    images: Vec<ImageResource>,
+    buffers: Vec<BufferResource>,
+    passes: Vec<RenderGraphPass>,
+    // Index into `transient_images` for every transient image which is used
+    image_slots: Vec<Option<usize>>,
+    transient_images: Vec<Rc<Image>>,
+    steps: Vec<CompiledStep>,
+    final_transitions: Vec<Transition>
+}
+
+impl CompiledRenderGraph {
+    // Names of the passes which are recorded, in recording order
+    pub fn pass_names(&self) -> impl Iterator<Item = &str> + '_ {
+        self.steps.iter().map(move |step| self.passes[step.pass].name.as_str())
+    }
+
+    fn barrier(&self, transitions: &[Transition], context: &CustomPassContext) -> PipelineBarrier {
+        transitions
+            .iter()
+            .fold(PipelineBarrier::new(), |barrier, transition| match transition.resource {
+                Resource::Image(index) => {
+                    // Transitioned images are used, so transient ones are placed in an image
+                    let image_barrier = match self.image(index) {
+                        Some(image) => {
+                            ImageBarrier::new(image, transition.layouts, transition.accesses)
+                        },
+                        None => ImageBarrier::from_handle(
+                            context.swapchain_image,
+                            transition.layouts,
+                            transition.accesses)
+                    };
+
+                    barrier.image(image_barrier)
+                },
+                Resource::Buffer(index) => barrier.buffer(
+                    BufferBarrier::new(&self.buffers[index].buffer, transition.accesses))
+            })
+    }
+
+    fn image(&self, index: usize) -> Option<&Rc<Image>> {
+        match &self.images[index].source {
+            ImageSource::Transient {..} => {
+                self.image_slots[index].map(|slot| &self.transient_images[slot])
+            },
+            ImageSource::Imported(image) => Some(image),
+            ImageSource::Swapchain => None
+        }
+    }
+}
+
+impl CustomPass for CompiledRenderGraph {
+    fn record<'a>(
+        &self,
+        recorder: CommandBufferRecorder<'a>,
+        context: &CustomPassContext
+    ) -> CommandBufferRecorder<'a> {
+        let resources = RenderGraphResources {
+            graph: self,
+            context
+        };
+
+        let recorder = self.steps.iter().fold(recorder, |recorder, step| {
+            let recorder = recorder.pipeline_barrier(&self.barrier(&step.transitions, context));
+            (self.passes[step.pass].record)(recorder, &resources)
+        });
+
+        recorder.pipeline_barrier(&self.barrier(&self.final_transitions, context))
+    }
+}
+
+// Resources of the graph as seen by the passes while they are recorded
+pub struct RenderGraphResources<'a> {
+    graph: &'a CompiledRenderGraph,
+    context: &'a CustomPassContext<'a>
+}
+
+impl<'a> RenderGraphResources<'a> {
+    pub fn image_handle(&self, image: GraphImage) -> vk::Image {
+        match self.graph.images[image.0].source {
+            ImageSource::Swapchain => self.context.swapchain_image,
+            _ => self.graph.image(image.0).map_or(vk::Image::null(), |image| image.handle())
+        }
+    }
+
+    // Not available for the swapchain image and for transient images used by no pass
+    pub fn image(&self, image: GraphImage) -> Option<&Rc<Image>> {
+        self.graph.image(image.0)
+    }
+
+    pub fn buffer(&self, buffer: GraphBuffer) -> &Rc<Buffer> {
+        &self.graph.buffers[buffer.0].buffer
+    }
+
+    pub fn context(&self) -> &CustomPassContext<'_> {
+        self.context
+    }
+}
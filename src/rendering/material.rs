@@ -0,0 +1,349 @@
+use std::{
+    cell::RefCell,
+    rc::Rc
+};
+use ash::vk;
+use crate::{
+    assets::shader_program::{
+        ShaderProgram,
+        BindingType
+    },
+    builder::{
+        BuilderRequirement,
+        BuilderInternal,
+        BuilderProduct
+    },
+    rendering::{
+        RenderingError,
+        RenderingResult,
+        renderer::Renderer,
+        texture::Texture
+    },
+    vulkan::{
+        allocator::MemoryUsage,
+        buffer::Buffer,
+        descriptor::{
+            DescriptorSet,
+            DescriptorSetLayout
+        },
+        pipeline::{
+            BlendMode,
+            CullMode
+        },
+        shader::{
+            GeometryShader,
+            VertexShader,
+            FragmentShader,
+            ShaderStage
+        }
+    }
+};
+
+// Shaders together with the fixed function state and uniform parameters they are drawn with.
+// A material is turned into a pipeline only once it is given to a render state,
+// as the pipeline also depends on the mesh's vertex layout and the push constants.
+// Parameters are bound as a uniform buffer at set 0, binding 0 of every stage,
+// textures as combined image samplers at the bindings given to the builder.
+pub struct Material<'a> {
+    geometry_shader: Option<&'a GeometryShader>,
+    vertex_shader: Option<&'a VertexShader>,
+    fragment_shader: Option<&'a FragmentShader>,
+    blend_mode: BlendMode,
+    cull_mode: CullMode,
+    alpha_to_coverage: bool,
+    parameters_buffer: Option<Rc<RefCell<Buffer>>>,
+    descriptor_set: Option<Rc<DescriptorSet>>
+}
+
+impl<'a> Material<'a> {
+    const PARAMETERS_BINDING: u32 = 0;
+
+    pub fn builder() -> MaterialBuilder<'a> {
+        MaterialBuilder {
+            ..Default::default()
+        }
+    }
+
+    pub fn geometry_shader(&self) -> Option<&'a GeometryShader> {
+        self.geometry_shader
+    }
+
+    pub fn vertex_shader(&self) -> Option<&'a VertexShader> {
+        self.vertex_shader
+    }
+
+    pub fn fragment_shader(&self) -> Option<&'a FragmentShader> {
+        self.fragment_shader
+    }
+
+    pub fn blend_mode(&self) -> BlendMode {
+        self.blend_mode
+    }
+
+    pub fn cull_mode(&self) -> CullMode {
+        self.cull_mode
+    }
+
+    pub fn alpha_to_coverage(&self) -> bool {
+        self.alpha_to_coverage
+    }
+
+    pub fn descriptor_set(&self) -> Option<&Rc<DescriptorSet>> {
+        self.descriptor_set.as_ref()
+    }
+
+    // Parameters have to have the same type as the ones the material was built with.
+    // The buffer is not duplicated per frame in flight, so frames that are still
+    // being rendered may pick up the new values as well.
+    pub fn set_parameters<T: Copy>(&self, parameters: &T) -> RenderingResult<()> {
+        let parameters_buffer = self.parameters_buffer
+            .as_ref()
+            .ok_or(RenderingError::MaterialWithoutParameters)?;
+
+        let mut parameters_buffer = parameters_buffer.borrow_mut();
+        let data = as_bytes(parameters);
+
+        if data.len() != parameters_buffer.size() {
+            return Err(RenderingError::MaterialParametersSizeMismatch {
+                size: data.len(),
+                expected_size: parameters_buffer.size()
+            });
+        }
+
+        parameters_buffer.write_data(0, data)?;
+
+        Ok(())
+    }
+}
+
+fn as_bytes<T: Copy>(value: &T) -> &[u8] {
+    unsafe {
+        std::slice::from_raw_parts(value as *const T as *const u8, std::mem::size_of::<T>())
+    }
+}
+
+#[derive(Default)]
+pub struct MaterialBuilder<'a> {
+    renderer: BuilderRequirement<&'a Renderer>,
+    geometry_shader: Option<&'a GeometryShader>,
+    vertex_shader: Option<&'a VertexShader>,
+    fragment_shader: Option<&'a FragmentShader>,
+    blend_mode: BlendMode,
+    cull_mode: CullMode,
+    alpha_to_coverage: bool,
+    parameters: Option<Vec<u8>>,
+    textures: Vec<(u32, Texture)>,
+    program: Option<&'a ShaderProgram>,
+
+    parameters_buffer: BuilderInternal<Option<Rc<RefCell<Buffer>>>>,
+    descriptor_set: BuilderInternal<Option<Rc<DescriptorSet>>>,
+
+    material: BuilderProduct<Material<'a>>
+}
+
+impl<'a> MaterialBuilder<'a> {
+    pub fn renderer(mut self, renderer: &'a Renderer) -> Self {
+        self.renderer.set(renderer);
+        self
+    }
+
+    pub fn geometry_shader(mut self, shader: &'a GeometryShader) -> Self {
+        self.geometry_shader = Some(shader);
+        self
+    }
+
+    pub fn vertex_shader(mut self, shader: &'a VertexShader) -> Self {
+        self.vertex_shader = Some(shader);
+        self
+    }
+
+    pub fn fragment_shader(mut self, shader: &'a FragmentShader) -> Self {
+        self.fragment_shader = Some(shader);
+        self
+    }
+
+    // Takes the program's shaders, replacing any set on the builder before.
+    // Parameters and textures have to be at bindings the program declares.
+    pub fn program(mut self, program: &'a ShaderProgram) -> Self {
+        self.geometry_shader = program.geometry_shader();
+        self.vertex_shader = program.vertex_shader();
+        self.fragment_shader = program.fragment_shader();
+        self.program = Some(program);
+        self
+    }
+
+    pub fn blend_mode(mut self, blend_mode: BlendMode) -> Self {
+        self.blend_mode = blend_mode;
+        self
+    }
+
+    pub fn cull_mode(mut self, cull_mode: CullMode) -> Self {
+        self.cull_mode = cull_mode;
+        self
+    }
+
+    pub fn alpha_to_coverage(mut self, alpha_to_coverage: bool) -> Self {
+        self.alpha_to_coverage = alpha_to_coverage;
+        self
+    }
+
+    // Parameters are copied byte for byte, so `T` should follow the std140 layout
+    // of the shaders' uniform block
+    pub fn parameters<T: Copy>(mut self, parameters: &T) -> Self {
+        self.parameters = Some(as_bytes(parameters).to_vec());
+        self
+    }
+
+    // Binding 0 is taken by the parameters, so textures start at binding 1
+    pub fn texture(mut self, binding: u32, texture: &Texture) -> Self {
+        self.textures.push((binding, texture.clone()));
+        self
+    }
+
+    pub fn build(mut self) -> RenderingResult<Material<'a>> {
+        check_required_fields!(self, RenderingError, "MaterialBuilder", [renderer]);
+
+        self.check_texture_bindings()?;
+        self.check_program_bindings()?;
+        self.init_parameters_buffer()?;
+        self.init_descriptor_set()?;
+        self.create_material();
+
+        Ok(self.material.unwrap())
+    }
+
+    fn check_texture_bindings(&self) -> RenderingResult<()> {
+        for (index, (binding, _)) in self.textures.iter().enumerate() {
+            let conflicts = *binding == Material::PARAMETERS_BINDING
+                || self.textures[..index].iter().any(|(other, _)| other == binding);
+
+            if conflicts {
+                return Err(RenderingError::TextureBindingConflict {binding: *binding});
+            }
+        }
+
+        Ok(())
+    }
+
+    fn check_program_bindings(&self) -> RenderingResult<()> {
+        let program = match self.program {
+            Some(program) => program,
+            None => return Ok(())
+        };
+
+        let parameters = self.parameters
+            .as_ref()
+            .map(|_| (Material::PARAMETERS_BINDING, BindingType::UniformBuffer));
+
+        let textures = self.textures
+            .iter()
+            .map(|(binding, _)| (*binding, BindingType::CombinedImageSampler));
+
+        for (binding, binding_type) in parameters.into_iter().chain(textures) {
+            if !program.accepts_binding(binding, binding_type) {
+                return Err(RenderingError::ProgramBindingMismatch {binding});
+            }
+        }
+
+        Ok(())
+    }
+
+    fn init_parameters_buffer(&mut self) -> RenderingResult<()> {
+        let parameters = match &self.parameters {
+            Some(parameters) => parameters,
+            None => {
+                self.parameters_buffer.set(None);
+                return Ok(());
+            }
+        };
+
+        let mut parameters_buffer = Buffer::builder()
+            .allocator(Rc::clone(self.renderer.allocator()))
+            .size(parameters.len().max(1))
+            .usage(vk::BufferUsageFlags::UNIFORM_BUFFER)
+            .memory_usage(MemoryUsage::CpuToGpu)
+            .host_mapped(true)
+            .build()?;
+
+        parameters_buffer.write_data(0, parameters)?;
+        self.parameters_buffer.set(Some(Rc::new(RefCell::new(parameters_buffer))));
+
+        Ok(())
+    }
+
+    fn init_descriptor_set(&mut self) -> RenderingResult<()> {
+        if self.parameters_buffer.is_none() && self.textures.is_empty() {
+            self.descriptor_set.set(None);
+            return Ok(());
+        }
+
+        let mut layout = DescriptorSetLayout::builder()
+            .logical_device(Rc::clone(self.renderer.logical_device()));
+
+        if self.parameters_buffer.is_some() {
+            let stages = self.binding_stages(Material::PARAMETERS_BINDING);
+            layout = layout.uniform_buffer(Material::PARAMETERS_BINDING, &stages);
+        }
+
+        for (binding, _) in &self.textures {
+            layout = layout.combined_image_sampler(*binding, &self.binding_stages(*binding));
+        }
+
+        let mut descriptor_set = DescriptorSet::new(Rc::new(layout.build()?))?;
+
+        if let Some(parameters_buffer) = &*self.parameters_buffer {
+            descriptor_set
+                .write_uniform_buffer(Material::PARAMETERS_BINDING, Rc::clone(parameters_buffer))?;
+        }
+
+        for (binding, texture) in &self.textures {
+            descriptor_set.write_combined_image_sampler(
+                *binding,
+                Rc::clone(texture.image()),
+                Rc::clone(texture.sampler()))?;
+        }
+
+        self.descriptor_set.set(Some(Rc::new(descriptor_set)));
+
+        Ok(())
+    }
+
+    // Stages the program declares the binding for, every stage of the material otherwise
+    fn binding_stages(&self, binding: u32) -> Vec<ShaderStage> {
+        self.program
+            .and_then(|program| program.binding_stages(binding))
+            .map(<[ShaderStage]>::to_vec)
+            .unwrap_or_else(|| self.shader_stages())
+    }
+
+    fn shader_stages(&self) -> Vec<ShaderStage> {
+        let mut stages = Vec::new();
+
+        if self.geometry_shader.is_some() {
+            stages.push(ShaderStage::Geometry);
+        }
+
+        if self.vertex_shader.is_some() {
+            stages.push(ShaderStage::Vertex);
+        }
+
+        if self.fragment_shader.is_some() {
+            stages.push(ShaderStage::Fragment);
+        }
+
+        stages
+    }
+
+    fn create_material(&mut self) {
+        self.material.set(Material {
+            geometry_shader: self.geometry_shader,
+            vertex_shader: self.vertex_shader,
+            fragment_shader: self.fragment_shader,
+            blend_mode: self.blend_mode,
+            cull_mode: self.cull_mode,
+            alpha_to_coverage: self.alpha_to_coverage,
+            parameters_buffer: self.parameters_buffer.take(),
+            descriptor_set: self.descriptor_set.take()
+        });
+    }
+}
@@ -0,0 +1,88 @@
+use ash::vk;
+use crate::{
+    rendering::RenderingResult,
+    vulkan::{
+        physical_device::PhysicalDevice,
+        allocator::{
+            Allocator,
+            AllocatorStatistics
+        }
+    }
+};
+
+// Memory heaps of the physical device with how much of them can and is being used.
+// With VK_EXT_memory_budget the budgets and usages come from the driver and account for
+// other processes as well. Without it, they are estimated from the heap sizes and what the
+// allocator allocated itself, see `is_budget_reported`.
+#[derive(Clone, Debug)]
+pub struct MemoryStats {
+    pub heaps: Vec<HeapMemoryStats>,
+    pub is_budget_reported: bool
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct HeapMemoryStats {
+    pub size: vk::DeviceSize,
+    pub is_device_local: bool,
+    // Bytes which can be allocated before the driver starts to evict memory or fail allocations
+    pub budget: vk::DeviceSize,
+    pub usage: vk::DeviceSize,
+    // Allocations of this renderer alone
+    pub allocator: AllocatorStatistics
+}
+
+impl MemoryStats {
+    // Share of a heap's size assumed to be available when budgets are not reported,
+    // the rest being left for other processes and the driver
+    const ESTIMATED_BUDGET_RATIO: f64 = 0.8;
+
+    pub fn query(physical_device: &PhysicalDevice, allocator: &Allocator) -> RenderingResult<Self> {
+        let memory_properties = physical_device.memory_properties();
+        let heap_count = memory_properties.memory_heap_count as usize;
+        let allocator_statistics = allocator.heap_statistics(heap_count)?;
+        let memory_budget = physical_device.memory_budget();
+
+        let heaps = memory_properties.memory_heaps[..heap_count]
+            .iter()
+            .zip(allocator_statistics)
+            .enumerate()
+            .map(|(index, (heap, allocator))| {
+                let (budget, usage) = match &memory_budget {
+                    Some(memory_budget) => (
+                        memory_budget.heap_budget[index],
+                        memory_budget.heap_usage[index]
+                    ),
+                    None => (
+                        (heap.size as f64 * Self::ESTIMATED_BUDGET_RATIO) as vk::DeviceSize,
+                        allocator.allocated_bytes
+                    )
+                };
+
+                HeapMemoryStats {
+                    size: heap.size,
+                    is_device_local: heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL),
+                    budget,
+                    usage,
+                    allocator
+                }
+            })
+            .collect();
+
+        Ok(Self {
+            heaps,
+            is_budget_reported: memory_budget.is_some()
+        })
+    }
+
+    pub fn device_local_budget(&self) -> vk::DeviceSize {
+        self.device_local_heaps().map(|heap| heap.budget).sum()
+    }
+
+    pub fn device_local_usage(&self) -> vk::DeviceSize {
+        self.device_local_heaps().map(|heap| heap.usage).sum()
+    }
+
+    fn device_local_heaps(&self) -> impl Iterator<Item = &HeapMemoryStats> {
+        self.heaps.iter().filter(|heap| heap.is_device_local)
+    }
+}
@@ -0,0 +1,241 @@
+use std::rc::Rc;
+use ash::vk;
+use nalgebra_glm as glm;
+use crate::{
+    builder::{
+        BuilderRequirement,
+        BuilderInternal,
+        BuilderProduct
+    },
+    rendering::{
+        RenderingError,
+        RenderingResult,
+        renderer::Renderer,
+        frustum::BoundingBox
+    },
+    vulkan::{
+        allocator::MemoryUsage,
+        buffer::Buffer,
+        pipeline::VertexAttributeFormat
+    }
+};
+
+// Vertex data living in GPU memory together with the layout the pipeline should read it with.
+// Cloning a mesh only shares its buffers.
+#[derive(Clone)]
+pub struct Mesh {
+    vertex_buffer: Rc<Buffer>,
+    index_buffer: Option<Rc<Buffer>>,
+    vertex_count: u32,
+    index_count: u32,
+    vertex_stride: usize,
+    vertex_attributes: Vec<VertexAttribute>,
+    bounding_box: Option<BoundingBox>
+}
+
+#[derive(Clone, Copy)]
+pub struct VertexAttribute {
+    pub format: VertexAttributeFormat,
+    pub offset: usize
+}
+
+impl Mesh {
+    pub fn builder<'a>() -> MeshBuilder<'a> {
+        MeshBuilder {
+            ..Default::default()
+        }
+    }
+
+    pub fn vertex_buffer(&self) -> &Rc<Buffer> {
+        &self.vertex_buffer
+    }
+
+    pub fn index_buffer(&self) -> Option<&Rc<Buffer>> {
+        self.index_buffer.as_ref()
+    }
+
+    pub fn vertex_count(&self) -> u32 {
+        self.vertex_count
+    }
+
+    pub fn index_count(&self) -> u32 {
+        self.index_count
+    }
+
+    // Number of vertices a draw of the whole mesh goes through,
+    // i.e. the number of indices for indexed meshes
+    pub fn element_count(&self) -> u32 {
+        if self.index_buffer.is_some() {
+            self.index_count
+        }
+        else {
+            self.vertex_count
+        }
+    }
+
+    pub fn vertex_stride(&self) -> usize {
+        self.vertex_stride
+    }
+
+    pub fn vertex_attributes(&self) -> &[VertexAttribute] {
+        &self.vertex_attributes
+    }
+
+    // In the mesh's own space, None if neither given nor found, see `MeshBuilder::bounds`
+    pub fn bounding_box(&self) -> Option<&BoundingBox> {
+        self.bounding_box.as_ref()
+    }
+}
+
+#[derive(Default)]
+pub struct MeshBuilder<'a> {
+    renderer: BuilderRequirement<&'a Renderer>,
+    vertex_data: BuilderRequirement<Vec<u8>>,
+    vertex_count: BuilderRequirement<u32>,
+    vertex_stride: BuilderRequirement<usize>,
+    vertex_attributes: Vec<VertexAttribute>,
+    indices: Option<Vec<u32>>,
+    bounding_box: Option<BoundingBox>,
+
+    vertex_buffer: BuilderInternal<Buffer>,
+    index_buffer: BuilderInternal<Option<Buffer>>,
+
+    mesh: BuilderProduct<Mesh>
+}
+
+impl<'a> MeshBuilder<'a> {
+    pub fn renderer(mut self, renderer: &'a Renderer) -> Self {
+        self.renderer.set(renderer);
+        self
+    }
+
+    // Vertices are copied byte for byte, so `T` should be `#[repr(C)]`
+    pub fn vertices<T: Copy>(mut self, vertices: &[T]) -> Self {
+        let vertex_data = unsafe {
+            std::slice::from_raw_parts(
+                vertices.as_ptr() as *const u8,
+                std::mem::size_of_val(vertices))
+        };
+
+        self.vertex_data.set(vertex_data.to_vec());
+        self.vertex_count.set(vertices.len() as u32);
+        self.vertex_stride.set(std::mem::size_of::<T>());
+        self
+    }
+
+    pub fn indices(mut self, indices: &[u32]) -> Self {
+        self.indices = Some(indices.to_vec());
+        self
+    }
+
+    // Attributes are assigned shader locations in the order they are added
+    pub fn vertex_attribute(mut self, format: VertexAttributeFormat, offset: usize) -> Self {
+        self.vertex_attributes.push(VertexAttribute {format, offset});
+        self
+    }
+
+    // Used for frustum culling. Defaults to the box around the first vertex attribute
+    // if it has three or four F32 components, which is then assumed to be the position.
+    pub fn bounds(mut self, bounding_box: BoundingBox) -> Self {
+        self.bounding_box = Some(bounding_box);
+        self
+    }
+
+    pub fn build(mut self) -> RenderingResult<Mesh> {
+        check_required_fields!(self, RenderingError, "MeshBuilder", [renderer, vertex_data]);
+
+        self.init_bounding_box();
+        self.init_vertex_buffer()?;
+        self.init_index_buffer()?;
+        self.create_mesh();
+
+        Ok(self.mesh.unwrap())
+    }
+
+    fn init_bounding_box(&mut self) {
+        if self.bounding_box.is_some() {
+            return;
+        }
+
+        let position = match self.vertex_attributes.first() {
+            Some(attribute @ VertexAttribute {format: VertexAttributeFormat::F32(3), ..}) |
+            Some(attribute @ VertexAttribute {format: VertexAttributeFormat::F32(4), ..}) =>
+                attribute.offset,
+            _ => return
+        };
+
+        let vertex_data = &*self.vertex_data;
+        let vertex_stride = *self.vertex_stride;
+        let positions = (0..*self.vertex_count as usize)
+            .map(|vertex| vertex * vertex_stride + position)
+            .filter(|offset| offset + 3 * std::mem::size_of::<f32>() <= vertex_data.len())
+            .map(|offset| {
+                let component = |index: usize| {
+                    let start = offset + index * std::mem::size_of::<f32>();
+                    let mut bytes = [0; 4];
+                    bytes.copy_from_slice(&vertex_data[start..start + 4]);
+                    f32::from_ne_bytes(bytes)
+                };
+
+                glm::vec3(component(0), component(1), component(2))
+            });
+
+        self.bounding_box = BoundingBox::from_points(positions);
+    }
+
+    fn init_vertex_buffer(&mut self) -> RenderingResult<()> {
+        let mut vertex_buffer = Buffer::builder()
+            .allocator(Rc::clone(self.renderer.allocator()))
+            .size(self.vertex_data.len().max(1))
+            .usage(vk::BufferUsageFlags::VERTEX_BUFFER)
+            .memory_usage(MemoryUsage::CpuToGpu)
+            .host_mapped(true)
+            .build()?;
+
+        vertex_buffer.write_data(0, &self.vertex_data)?;
+        self.vertex_buffer.set(vertex_buffer);
+
+        Ok(())
+    }
+
+    fn init_index_buffer(&mut self) -> RenderingResult<()> {
+        let indices = match &self.indices {
+            Some(indices) => indices,
+            None => {
+                self.index_buffer.set(None);
+                return Ok(());
+            }
+        };
+
+        let index_data = unsafe {
+            std::slice::from_raw_parts(
+                indices.as_ptr() as *const u8,
+                indices.len() * std::mem::size_of::<u32>())
+        };
+
+        let mut index_buffer = Buffer::builder()
+            .allocator(Rc::clone(self.renderer.allocator()))
+            .size(index_data.len().max(1))
+            .usage(vk::BufferUsageFlags::INDEX_BUFFER)
+            .memory_usage(MemoryUsage::CpuToGpu)
+            .host_mapped(true)
+            .build()?;
+
+        index_buffer.write_data(0, index_data)?;
+        self.index_buffer.set(Some(index_buffer));
+
+        Ok(())
+    }
+
+    fn create_mesh(&mut self) {
+        self.mesh.set(Mesh {
+            vertex_buffer: Rc::new(self.vertex_buffer.take()),
+            index_buffer: self.index_buffer.take().map(Rc::new),
+            vertex_count: self.vertex_count.take(),
+            index_count: self.indices.as_ref().map_or(0, |indices| indices.len() as u32),
+            vertex_stride: self.vertex_stride.take(),
+            vertex_attributes: std::mem::take(&mut self.vertex_attributes),
+            bounding_box: self.bounding_box.take()
+        });
+    }
+}
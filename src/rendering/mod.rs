@@ -12,10 +12,147 @@ custom_error!{pub RenderingError
     PresentImageError {result: vk::Result} =
         "failed to submit swapchain image for presentation: {result}",
     DeviceWaitIdleError {result: vk::Result} =
-        "faild to wait for vulkan logical device to become idle: {result}"
+        "faild to wait for vulkan logical device to become idle: {result}",
+    ImmediateSubmitError {result: vk::Result} =
+        "failed to submit commands for immediate execution: {result}",
+    QueueSubmitError {result: vk::Result} =
+        "failed to submit command buffers to a queue: {result}",
+    CaptureNotSupported =
+        "swapchain images cannot be used as a copy source on this surface",
+    PresentPreviousNotSupported =
+        "swapchain images cannot be used as both copy source and destination on this surface",
+    CaptureFormatNotSupported =
+        "capturing frames is not supported for the current swapchain format",
+    SequenceCaptureAlreadyStarted =
+        "a frame sequence is already being captured",
+    SequenceEncoderSpawnError {error: std::io::Error} =
+        "failed to start the encoder of a frame sequence: {error}",
+    SequenceWriteError {error: std::io::Error} =
+        "failed to write a captured frame: {error}",
+    SequenceEncoderFailed {status: std::process::ExitStatus} =
+        "encoder of a frame sequence failed with {status}",
+    SequenceWriterPanicked =
+        "writer thread of a frame sequence panicked",
+    MaterialWithoutParameters =
+        "parameters were set on a material built without any",
+    MaterialParametersSizeMismatch {size: usize, expected_size: usize} =
+        "material parameters take {size} bytes, but the material was built with {expected_size}",
+    TextureWithoutLayers =
+        "texture was built without any layers",
+    TextureLayerSizeMismatch {layer: usize, width: u32, height: u32} =
+        "texture layer {layer} is {width}x{height}, but all layers must have the same size",
+    TextureLayerOutOfRange {layer: usize, layer_count: usize} =
+        "texture layer {layer} does not exist, the texture has {layer_count} layers",
+    SkyboxTextureNotCubeMap =
+        "skybox texture was not built as a cube map",
+    TextureBindingConflict {binding: u32} =
+        "material binding {binding} is used more than once or by its parameters",
+    ProgramBindingMismatch {binding: u32} =
+        "material binding {binding} is not declared with a matching type by its shader program",
+    ProgramPushConstantsMismatch {stage: String, size: usize, expected_size: usize} =
+        "{stage} push constants take {size} bytes, but the shader program declares {expected_size}",
+    ProgramVertexLayoutMismatch =
+        "mesh vertex layout differs from the one declared by the shader program",
+    ShadowMapFormatNotSupported =
+        "no depth format supports being both rendered to and sampled as a shadow map",
+    GBufferDepthFormatNotSupported =
+        "no depth format supports being rendered to as the depth of a G-buffer",
+    CustomPassAlreadyRegistered {name: String} =
+        "custom pass `{name}` is already registered",
+    CustomPassDependencyCycle {name: String} =
+        "custom pass `{name}` is part of a dependency cycle",
+    CustomPassDependencyAfterPass {name: String, dependency: String} =
+        "custom pass `{name}` depends on `{dependency}`, which is recorded at a later point",
+    UniformRingElementTooLarge {size: usize, element_size: usize} =
+        "uniform ring element takes {size} bytes, but the ring was built for {element_size}",
+    UniformRingFull {capacity: usize} =
+        "uniform ring is full, it fits {capacity} elements per frame",
+    GpuCullingTooManyDraws {count: usize, capacity: usize} =
+        "{count} draws were given for GPU culling, but it was built for {capacity}",
+    UnknownSpriteTexture {texture: usize} =
+        "sprite texture {texture} was not added to the sprite batch",
+    UnknownUiTexture {texture: usize} =
+        "UI texture {texture} was not added to the UI pass",
+    InvalidRenderScale {scale: f32} =
+        "render scale {scale} is not a positive number",
+    UpscaleBlitNotSupported =
+        "swapchain images cannot be blitted to on this surface, upscaling needs a shader",
+    GammaPassNotSupported =
+        "gamma pass cannot copy swapchain images, the surface does not support transfers from them",
+    RenderGraphPassAlreadyAdded {name: String} =
+        "render graph pass `{name}` is already added",
+    RenderGraphUnknownResource {pass: String} =
+        "render graph pass `{pass}` uses a resource of another graph",
+    RenderGraphResourceUsedTwice {pass: String, resource: String} =
+        "render graph pass `{pass}` uses `{resource}` more than once",
+    RenderGraphDependencyCycle {name: String} =
+        "render graph pass `{name}` is part of a dependency cycle",
+    XrSwapchainWithoutImages =
+        "OpenXR swapchain target was built without any images",
+    XrDepthFormatNotSupported =
+        "no depth format supports being rendered to as the depth of an OpenXR swapchain target",
+    BuilderMissingField {builder: &'static str, field: &'static str} =
+        "{builder} was built without setting required field `{field}`"
 }
 
 pub type RenderingResult<T> = Result<T, RenderingError>;
 
+impl RenderingError {
+    // Errors of the submit and present paths, after which the renderer has to be rebuilt
+    pub fn is_device_lost(&self) -> bool {
+        let result = match self {
+            RenderingError::AcquireImageError {result} |
+            RenderingError::RenderImageError {result} |
+            RenderingError::PresentImageError {result} |
+            RenderingError::DeviceWaitIdleError {result} |
+            RenderingError::ImmediateSubmitError {result} |
+            RenderingError::QueueSubmitError {result} |
+            RenderingError::VulkanError {
+                source: vulkan::VulkanError::FenceGetStatusError {result} |
+                    vulkan::VulkanError::FenceWaitError {result} |
+                    vulkan::VulkanError::FenceResetError {result}
+            } => result,
+            _ => return false
+        };
+
+        *result == vk::Result::ERROR_DEVICE_LOST
+    }
+}
+
 pub mod renderer;
 pub mod render_state;
+pub mod pipeline_cache;
+pub mod mesh;
+pub mod primitives;
+pub mod material;
+pub mod texture;
+pub mod shadow_map;
+pub mod deferred;
+pub mod custom_pass;
+pub mod graph;
+pub mod render_layers;
+pub mod frustum;
+pub mod scene;
+pub mod scene_sync;
+pub mod debug_draw;
+pub mod sprite;
+pub mod ui;
+pub mod gamma;
+pub mod skybox;
+pub mod render_scale;
+pub mod submission;
+pub mod frame_arena;
+pub mod frame_context;
+pub mod frame_capture;
+pub mod sequence_capture;
+pub mod bitmap_font;
+pub mod debug_overlay;
+pub(crate) mod previous_frame;
+pub mod memory_stats;
+pub(crate) mod readback_ring;
+pub(crate) mod destruction_queue;
+pub mod uniform_ring;
+pub(crate) mod command_buffer_cache;
+pub mod viewport;
+pub mod gpu_culling;
+pub mod xr;
@@ -0,0 +1,79 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    rc::{
+        Rc,
+        Weak
+    }
+};
+use crate::{
+    rendering::RenderingResult,
+    vulkan::{
+        pipeline::{
+            Pipeline,
+            VertexAttributeFormat,
+            BlendMode,
+            CullMode
+        },
+        shader::ShaderStage
+    }
+};
+
+// Everything a render state's pipeline is built from
+#[derive(Clone, PartialEq, Eq, Hash, Default)]
+pub struct PipelineDescription {
+    pub shader_ids: Vec<(ShaderStage, u64)>,
+    pub push_constants_sizes: Vec<(ShaderStage, usize)>,
+    pub alpha_to_coverage: bool,
+    pub blend_mode: BlendMode,
+    pub cull_mode: CullMode,
+    // Raw handles of the layouts, the pipeline keeps them alive while it is cached
+    pub descriptor_set_layouts: Vec<u64>,
+    pub vertex_stride: Option<usize>,
+    pub vertex_attributes: Vec<(VertexAttributeFormat, usize)>
+}
+
+// Lets render states with identical descriptions share a single pipeline.
+// Pipelines are held weakly, so they are destroyed together with the last render state using them.
+pub struct PipelineCache {
+    pipelines: RefCell<HashMap<PipelineDescription, Weak<Pipeline>>>
+}
+
+impl PipelineCache {
+    pub fn new() -> Self {
+        Self {
+            pipelines: RefCell::new(HashMap::new())
+        }
+    }
+
+    pub fn get_or_create<F>(
+        &self,
+        description: PipelineDescription,
+        create_pipeline: F
+    ) -> RenderingResult<Rc<Pipeline>> where
+        F: FnOnce() -> RenderingResult<Pipeline> {
+        if let Some(pipeline) = self.get(&description) {
+            return Ok(pipeline);
+        }
+
+        let pipeline = Rc::new(create_pipeline()?);
+        let mut pipelines = self.pipelines.borrow_mut();
+        pipelines.retain(|_, pipeline| pipeline.strong_count() > 0);
+        pipelines.insert(description, Rc::downgrade(&pipeline));
+
+        Ok(pipeline)
+    }
+
+    fn get(&self, description: &PipelineDescription) -> Option<Rc<Pipeline>> {
+        self.pipelines
+            .borrow()
+            .get(description)
+            .and_then(|pipeline| pipeline.upgrade())
+    }
+}
+
+impl Default for PipelineCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
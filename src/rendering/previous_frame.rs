@@ -0,0 +1,150 @@
+use std::rc::Rc;
+use ash::vk;
+use crate::{
+    rendering::RenderingResult,
+    vulkan::{
+        logical_device::LogicalDevice,
+        allocator::Allocator,
+        image::Image,
+        command_buffer::CommandBufferRecorder,
+        swapchain::Swapchain,
+        barrier::{
+            PipelineBarrier,
+            MemoryAccess,
+            ImageBarrier
+        }
+    }
+};
+
+// Copy of the last rendered frame, presented again by frames with nothing to render
+// in `EmptyFrameMode::PresentPrevious`. Presented swapchain images belong to the presentation
+// engine until they are acquired again, so frames are copied before they are presented.
+// Between frames the copy is kept in the transfer source layout.
+pub struct PreviousFrame {
+    allocator: Rc<Allocator>,
+    logical_device: Rc<LogicalDevice>,
+    image: Option<Image>,
+    // Whether a frame storing its copy was submitted since the copy was last discarded
+    is_stored: bool
+}
+
+impl PreviousFrame {
+    pub fn new(allocator: Rc<Allocator>, logical_device: Rc<LogicalDevice>) -> Self {
+        Self {
+            allocator,
+            logical_device,
+            image: None,
+            is_stored: false
+        }
+    }
+
+    pub fn is_supported(swapchain: &Swapchain) -> bool {
+        let usage = vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::TRANSFER_DST;
+        swapchain.image_usage().contains(usage)
+    }
+
+    pub fn is_stored(&self) -> bool {
+        self.is_stored
+    }
+
+    pub fn mark_stored(&mut self) {
+        self.is_stored = true;
+    }
+
+    pub fn discard(&mut self) {
+        self.is_stored = false;
+    }
+
+    // Has to be called before recording either copy
+    pub fn prepare(&mut self, swapchain: &Swapchain) -> RenderingResult<()> {
+        let extent = swapchain.extent();
+        let is_matching = self.image.as_ref().map_or(false, |image| {
+            image.extent().width == extent.width
+                && image.extent().height == extent.height
+                && image.format() == swapchain.image_format()
+        });
+
+        if !is_matching {
+            let image = Image::builder()
+                .allocator(Rc::clone(&self.allocator))
+                .logical_device(Rc::clone(&self.logical_device))
+                .format(swapchain.image_format())
+                .extent(extent)
+                .usage(vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::TRANSFER_DST)
+                .build()?;
+
+            self.image = Some(image);
+            self.is_stored = false;
+        }
+
+        Ok(())
+    }
+
+    // Copies the rendered swapchain image, which is in the present layout and left in it.
+    // Its previous copy is overwritten, so it is discarded by the transition.
+    pub fn record_store<'a>(
+        &self,
+        recorder: CommandBufferRecorder<'a>,
+        swapchain: &Swapchain,
+        image_index: usize
+    ) -> CommandBufferRecorder<'a> {
+        let image = self.image.as_ref().expect("previous frame copy was not prepared");
+        let swapchain_image = swapchain.images()[image_index];
+
+        recorder
+            .pipeline_barrier(&PipelineBarrier::new()
+                .image(ImageBarrier::from_handle(
+                    swapchain_image,
+                    (vk::ImageLayout::PRESENT_SRC_KHR, vk::ImageLayout::TRANSFER_SRC_OPTIMAL),
+                    (MemoryAccess::color_attachment_write(), MemoryAccess::transfer_read())))
+                .image(ImageBarrier::new(
+                    image,
+                    (vk::ImageLayout::UNDEFINED, vk::ImageLayout::TRANSFER_DST_OPTIMAL),
+                    (MemoryAccess::transfer_read(), MemoryAccess::transfer_write()))))
+            .copy_image(
+                swapchain_image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+            .pipeline_barrier(&PipelineBarrier::new()
+                .image(ImageBarrier::from_handle(
+                    swapchain_image,
+                    (vk::ImageLayout::TRANSFER_SRC_OPTIMAL, vk::ImageLayout::PRESENT_SRC_KHR),
+                    (MemoryAccess::transfer_read(), MemoryAccess::none())))
+                .image(ImageBarrier::new(
+                    image,
+                    (vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::ImageLayout::TRANSFER_SRC_OPTIMAL),
+                    (MemoryAccess::transfer_write(), MemoryAccess::transfer_read()))))
+    }
+
+    // Copies the stored frame into the acquired swapchain image and leaves it
+    // in the present layout
+    pub fn record_present<'a>(
+        &self,
+        recorder: CommandBufferRecorder<'a>,
+        swapchain: &Swapchain,
+        image_index: usize
+    ) -> CommandBufferRecorder<'a> {
+        let image = self.image.as_ref().expect("previous frame copy was not prepared");
+        let swapchain_image = swapchain.images()[image_index];
+        // Chains with the wait for the image to be acquired
+        let acquired = MemoryAccess::execution(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT);
+
+        recorder
+            .pipeline_barrier(&PipelineBarrier::new()
+                .image(ImageBarrier::from_handle(
+                    swapchain_image,
+                    (vk::ImageLayout::UNDEFINED, vk::ImageLayout::TRANSFER_DST_OPTIMAL),
+                    (acquired, MemoryAccess::transfer_write()))))
+            .copy_image_to_handle(
+                image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                swapchain_image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+            .pipeline_barrier(&PipelineBarrier::new()
+                .image(ImageBarrier::from_handle(
+                    swapchain_image,
+                    (vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::ImageLayout::PRESENT_SRC_KHR),
+                    (MemoryAccess::transfer_write(), MemoryAccess::none()))))
+    }
+}
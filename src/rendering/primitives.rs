@@ -0,0 +1,339 @@
+use std::f32::consts::PI;
+use nalgebra_glm as glm;
+use crate::{
+    rendering::{
+        RenderingResult,
+        renderer::Renderer,
+        mesh::Mesh
+    },
+    vulkan::pipeline::VertexAttributeFormat
+};
+
+// Position at location 0, normal at 1, UV at 2, the same layout as the one of imported models,
+// so that primitives can be drawn with the same shader programs
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Default, Debug)]
+pub struct PrimitiveVertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub uv: [f32; 2]
+}
+
+// Shapes generated on the CPU, for samples and quick prototypes which should not need any model
+// files. Shapes are centered at the origin with Y pointing up and their front faces wound
+// counter-clockwise when seen from the outside, like the ones of imported models.
+// UVs grow to the right and downwards when looking at a face from the outside.
+#[derive(Clone, Default, Debug)]
+pub struct PrimitiveData {
+    pub vertices: Vec<PrimitiveVertex>,
+    pub indices: Vec<u32>
+}
+
+impl PrimitiveData {
+    // Every face has vertices of its own, so that edges stay sharp and each face is textured
+    // with the whole texture
+    pub fn cube(size: f32) -> Self {
+        // Normal and up direction of every face
+        let faces = [
+            (glm::vec3(1.0, 0.0, 0.0), glm::vec3(0.0, 1.0, 0.0)),
+            (glm::vec3(-1.0, 0.0, 0.0), glm::vec3(0.0, 1.0, 0.0)),
+            (glm::vec3(0.0, 1.0, 0.0), glm::vec3(0.0, 0.0, -1.0)),
+            (glm::vec3(0.0, -1.0, 0.0), glm::vec3(0.0, 0.0, 1.0)),
+            (glm::vec3(0.0, 0.0, 1.0), glm::vec3(0.0, 1.0, 0.0)),
+            (glm::vec3(0.0, 0.0, -1.0), glm::vec3(0.0, 1.0, 0.0))
+        ];
+
+        let half_size = size / 2.0;
+        let mut primitive = Self::default();
+        for (normal, up) in &faces {
+            let right = glm::cross(up, normal);
+            primitive.push_grid(1, 1, |u, v| {
+                let position =
+                    (normal + right * (2.0 * u - 1.0) + up * (1.0 - 2.0 * v)) * half_size;
+
+                (position, *normal)
+            });
+        }
+
+        primitive
+    }
+
+    // UV sphere with `segments` around the Y axis and `rings` from pole to pole.
+    // The seam lies on the positive X axis.
+    pub fn sphere(radius: f32, segments: u32, rings: u32) -> Self {
+        let mut primitive = Self::default();
+        primitive.push_grid(segments.max(3), rings.max(2), |u, v| {
+            let azimuth = 2.0 * PI * u;
+            let polar = PI * v;
+            let normal = glm::vec3(
+                polar.sin() * azimuth.cos(),
+                polar.cos(),
+                -polar.sin() * azimuth.sin());
+
+            (normal * radius, normal)
+        });
+
+        primitive
+    }
+
+    // Lies in the XZ plane facing up, split into `subdivisions` quads along both axes,
+    // e.g. for terrain displaced in a vertex shader
+    pub fn plane(width: f32, depth: f32, subdivisions: u32) -> Self {
+        let subdivisions = subdivisions.max(1);
+        let mut primitive = Self::default();
+        primitive.push_grid(subdivisions, subdivisions, |u, v| {
+            let position = glm::vec3((u - 0.5) * width, 0.0, (v - 0.5) * depth);
+            (position, glm::vec3(0.0, 1.0, 0.0))
+        });
+
+        primitive
+    }
+
+    // Stands along the Y axis, with the side's seam on the positive X axis. Caps are textured
+    // with the texture's inscribed circle.
+    pub fn cylinder(radius: f32, height: f32, segments: u32) -> Self {
+        let segments = segments.max(3);
+        let mut primitive = Self::default();
+        primitive.push_grid(segments, 1, |u, v| {
+            let azimuth = 2.0 * PI * u;
+            let normal = glm::vec3(azimuth.cos(), 0.0, -azimuth.sin());
+            let position = normal * radius + glm::vec3(0.0, (0.5 - v) * height, 0.0);
+
+            (position, normal)
+        });
+
+        primitive.push_cap(radius, height / 2.0, segments);
+        primitive.push_cap(radius, -height / 2.0, segments);
+        primitive
+    }
+
+    // A single triangle covering the whole viewport, with positions already in normalized
+    // device coordinates for a vertex shader passing them through, and UVs from 0 to 1 across
+    // the viewport. Like `shaders/fullscreen.vert`, but with a vertex buffer for shader programs
+    // which take one.
+    pub fn fullscreen_triangle() -> Self {
+        let vertex = |x: f32, y: f32| PrimitiveVertex {
+            position: [x, y, 0.0],
+            normal: [0.0, 0.0, -1.0],
+            uv: [(x + 1.0) / 2.0, (y + 1.0) / 2.0]
+        };
+
+        Self {
+            // Clockwise in framebuffer coordinates, whose Y axis points down
+            vertices: vec![vertex(-1.0, -1.0), vertex(3.0, -1.0), vertex(-1.0, 3.0)],
+            indices: vec![0, 1, 2]
+        }
+    }
+
+    pub fn build(&self, renderer: &Renderer) -> RenderingResult<Mesh> {
+        Mesh::builder()
+            .renderer(renderer)
+            .vertices(&self.vertices)
+            .indices(&self.indices)
+            .vertex_attribute(VertexAttributeFormat::F32(3), 0)
+            .vertex_attribute(VertexAttributeFormat::F32(3), 12)
+            .vertex_attribute(VertexAttributeFormat::F32(2), 24)
+            .build()
+    }
+
+    // Grid of `columns` by `rows` quads, whose vertices are placed by `vertex` given their UVs.
+    // Seen from the front, U has to grow to the right and V downwards.
+    fn push_grid<F>(&mut self, columns: u32, rows: u32, vertex: F) where
+        F: Fn(f32, f32) -> (glm::Vec3, glm::Vec3) {
+        let first_index = self.vertices.len() as u32;
+
+        for row in 0..=rows {
+            for column in 0..=columns {
+                let u = column as f32 / columns as f32;
+                let v = row as f32 / rows as f32;
+                let (position, normal) = vertex(u, v);
+
+                self.vertices.push(PrimitiveVertex {
+                    position: position.into(),
+                    normal: normal.into(),
+                    uv: [u, v]
+                });
+            }
+        }
+
+        let index = |column: u32, row: u32| first_index + row * (columns + 1) + column;
+        for row in 0..rows {
+            for column in 0..columns {
+                self.indices.extend_from_slice(&[
+                    index(column, row),
+                    index(column, row + 1),
+                    index(column + 1, row),
+                    index(column + 1, row),
+                    index(column, row + 1),
+                    index(column + 1, row + 1)
+                ]);
+            }
+        }
+    }
+
+    // Disk at the given height, facing up above the origin and down below it
+    fn push_cap(&mut self, radius: f32, y: f32, segments: u32) {
+        let normal_y = y.signum();
+        let first_index = self.vertices.len() as u32;
+
+        self.vertices.push(PrimitiveVertex {
+            position: [0.0, y, 0.0],
+            normal: [0.0, normal_y, 0.0],
+            uv: [0.5, 0.5]
+        });
+
+        for segment in 0..=segments {
+            let azimuth = 2.0 * PI * segment as f32 / segments as f32;
+            let (x, z) = (azimuth.cos(), -azimuth.sin());
+
+            self.vertices.push(PrimitiveVertex {
+                position: [x * radius, y, z * radius],
+                normal: [0.0, normal_y, 0.0],
+                // Seen from below, the cap's up direction is the positive Z axis
+                uv: [(x + 1.0) / 2.0, (normal_y * z + 1.0) / 2.0]
+            });
+        }
+
+        for segment in 0..segments {
+            let (current, next) = (first_index + 1 + segment, first_index + 2 + segment);
+            if normal_y > 0.0 {
+                self.indices.extend_from_slice(&[first_index, current, next]);
+            }
+            else {
+                self.indices.extend_from_slice(&[first_index, next, current]);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vec3(array: [f32; 3]) -> glm::Vec3 {
+        glm::vec3(array[0], array[1], array[2])
+    }
+
+    fn check_indices_and_normals(primitive: &PrimitiveData) {
+        assert_eq!(primitive.indices.len() % 3, 0);
+        assert!(primitive.indices.iter().all(|index| (*index as usize) < primitive.vertices.len()));
+
+        for vertex in &primitive.vertices {
+            assert!((glm::length(&vec3(vertex.normal)) - 1.0).abs() < 1e-5, "{:?}", vertex);
+            assert!(vertex.uv.iter().all(|coordinate| (0.0..=1.0).contains(coordinate)));
+        }
+    }
+
+    // Front faces are counter-clockwise seen from outside, i.e. along the normals
+    fn check_winding(primitive: &PrimitiveData) {
+        for triangle in primitive.indices.chunks(3) {
+            let corner = |index: usize| primitive.vertices[triangle[index] as usize];
+            let (a, b, c) = (corner(0), corner(1), corner(2));
+            let face_normal = glm::cross(
+                &(vec3(b.position) - vec3(a.position)),
+                &(vec3(c.position) - vec3(a.position)));
+
+            // Triangles touching a sphere's poles are degenerate
+            if glm::length(&face_normal) > 1e-6 {
+                assert!(glm::dot(&face_normal, &vec3(a.normal)) > 0.0, "{:?}", triangle);
+            }
+        }
+    }
+
+    #[test]
+    fn generates_cubes() {
+        let cube = PrimitiveData::cube(2.0);
+        assert_eq!((cube.vertices.len(), cube.indices.len()), (24, 36));
+        check_indices_and_normals(&cube);
+        check_winding(&cube);
+
+        for vertex in &cube.vertices {
+            assert!(vertex.position.iter().all(|coordinate| coordinate.abs() == 1.0));
+            // Every vertex lies on the face its normal points out of
+            assert_eq!(glm::dot(&vec3(vertex.position), &vec3(vertex.normal)), 1.0);
+        }
+    }
+
+    #[test]
+    fn generates_spheres() {
+        let sphere = PrimitiveData::sphere(3.0, 16, 8);
+        assert_eq!((sphere.vertices.len(), sphere.indices.len()), (17 * 9, 16 * 8 * 6));
+        check_indices_and_normals(&sphere);
+        check_winding(&sphere);
+
+        for vertex in &sphere.vertices {
+            assert!((glm::length(&vec3(vertex.position)) - 3.0).abs() < 1e-5);
+        }
+
+        let smallest = PrimitiveData::sphere(1.0, 0, 0);
+        assert_eq!((smallest.vertices.len(), smallest.indices.len()), (4 * 3, 3 * 2 * 6));
+    }
+
+    #[test]
+    fn generates_planes() {
+        let plane = PrimitiveData::plane(4.0, 2.0, 3);
+        assert_eq!((plane.vertices.len(), plane.indices.len()), (16, 54));
+        check_indices_and_normals(&plane);
+        check_winding(&plane);
+
+        for vertex in &plane.vertices {
+            let [x, y, z] = vertex.position;
+            assert!(x.abs() <= 2.0 && y == 0.0 && z.abs() <= 1.0);
+        }
+
+        let smallest = PrimitiveData::plane(1.0, 1.0, 0);
+        assert_eq!((smallest.vertices.len(), smallest.indices.len()), (4, 6));
+    }
+
+    #[test]
+    fn generates_cylinders() {
+        let cylinder = PrimitiveData::cylinder(0.5, 2.0, 12);
+        // Side grid and two caps, each with a center vertex and a closing seam vertex
+        assert_eq!(cylinder.vertices.len(), 13 * 2 + 2 * 14);
+        assert_eq!(cylinder.indices.len(), 12 * 6 + 2 * 12 * 3);
+        check_indices_and_normals(&cylinder);
+        check_winding(&cylinder);
+
+        for vertex in &cylinder.vertices {
+            let [x, y, z] = vertex.position;
+            assert!((x * x + z * z).sqrt() <= 0.5 + 1e-5 && y.abs() == 1.0);
+        }
+
+        let smallest = PrimitiveData::cylinder(1.0, 1.0, 1);
+        assert_eq!(smallest.indices.len(), 3 * 6 + 2 * 3 * 3);
+    }
+
+    #[test]
+    fn covers_the_viewport_with_the_fullscreen_triangle() {
+        let triangle = PrimitiveData::fullscreen_triangle();
+        assert_eq!((triangle.vertices.len(), triangle.indices.len()), (3, 3));
+        assert!(triangle.indices.iter().all(|index| *index < 3));
+
+        let corners: Vec<glm::Vec2> = triangle.vertices
+            .iter()
+            .map(|vertex| glm::vec2(vertex.position[0], vertex.position[1]))
+            .collect();
+
+        // Every corner of the NDC square lies inside of the triangle or on its edges
+        let edge = |from: glm::Vec2, to: glm::Vec2, point: glm::Vec2| {
+            (to.x - from.x) * (point.y - from.y) - (to.y - from.y) * (point.x - from.x)
+        };
+
+        for point in &[[-1.0, -1.0], [1.0, -1.0], [-1.0, 1.0], [1.0, 1.0]] {
+            let point = glm::vec2(point[0], point[1]);
+            let sides = [
+                edge(corners[0], corners[1], point),
+                edge(corners[1], corners[2], point),
+                edge(corners[2], corners[0], point)
+            ];
+
+            assert!(sides.iter().all(|side| *side >= 0.0), "{:?}", point);
+        }
+
+        // UVs span exactly 0 to 1 across the viewport
+        for vertex in &triangle.vertices {
+            let [x, y, _] = vertex.position;
+            assert_eq!(vertex.uv, [(x + 1.0) / 2.0, (y + 1.0) / 2.0]);
+        }
+    }
+}
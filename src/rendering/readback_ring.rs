@@ -0,0 +1,34 @@
+// GPU to CPU readbacks waiting for the frame in flight which records them to finish.
+// They are keyed by frame instead of by timeline semaphore values, as the frame's fence
+// already tells when its copies have landed, so results are collected only once
+// the fence is known to be signaled and the CPU never blocks on them.
+pub struct ReadbackRing<T> {
+    pending: Vec<Vec<T>>
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Readback {
+    CapturedFrame,
+    SequenceFrame
+}
+
+impl<T> ReadbackRing<T> {
+    pub fn new(frames_in_flight: usize) -> Self {
+        Self {
+            pending: (0..frames_in_flight).map(|_| Vec::new()).collect()
+        }
+    }
+
+    pub fn push(&mut self, frame: usize, readback: T) {
+        self.pending[frame].push(readback);
+    }
+
+    pub fn has_pending(&self, frame: usize) -> bool {
+        !self.pending[frame].is_empty()
+    }
+
+    // Has to be called only after the fence of the given frame has been signaled
+    pub fn take_finished(&mut self, frame: usize) -> Vec<T> {
+        std::mem::take(&mut self.pending[frame])
+    }
+}
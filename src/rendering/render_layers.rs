@@ -0,0 +1,148 @@
+use std::borrow::Cow;
+use crate::rendering::render_state::RenderStateTrait;
+
+// Layers are recorded in declaration order, except for translucent objects and particles,
+// which are recorded together as a single depth-sorted list
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Debug)]
+pub enum RenderLayer {
+    World,
+    Translucent,
+    Particles,
+    Debug,
+    UI
+}
+
+impl RenderLayer {
+    // In recording order
+    pub const ALL: [RenderLayer; 5] = [
+        RenderLayer::World,
+        RenderLayer::Translucent,
+        RenderLayer::Particles,
+        RenderLayer::Debug,
+        RenderLayer::UI
+    ];
+
+    // Letterboxed layers are confined to the aspect-ratio-locked area of the screen,
+    // the rest covers the whole window, so that the UI can draw over the bars
+    pub fn is_letterboxed(self) -> bool {
+        match self {
+            RenderLayer::World | RenderLayer::Translucent | RenderLayer::Particles => true,
+            RenderLayer::Debug | RenderLayer::UI => false
+        }
+    }
+
+    // Objects of depth-sorted layers blend with each other, so they have to be drawn
+    // back to front regardless of which layer they belong to
+    pub fn is_depth_sorted(self) -> bool {
+        match self {
+            RenderLayer::Translucent | RenderLayer::Particles => true,
+            RenderLayer::World | RenderLayer::Debug | RenderLayer::UI => false
+        }
+    }
+
+    // Layers with equal recording order are interleaved
+    pub fn recording_order(self) -> u8 {
+        match self {
+            RenderLayer::World => 0,
+            RenderLayer::Translucent | RenderLayer::Particles => 1,
+            RenderLayer::Debug => 2,
+            RenderLayer::UI => 3
+        }
+    }
+}
+
+// Render states sorted by the recording order of their layers,
+// registration order is kept among layers recorded together.
+// Layers built from a draw list allocated elsewhere (e.g. in a `FrameArena`) borrow it
+// and only copy it if more render states are registered.
+#[derive(Default)]
+pub struct RenderLayers<'a> {
+    layers: Cow<'a, [RenderLayer]>,
+    render_states: Cow<'a, [&'a dyn RenderStateTrait]>
+}
+
+impl<'a> RenderLayers<'a> {
+    pub fn new() -> Self {
+        Self {
+            layers: Cow::Owned(Vec::new()),
+            render_states: Cow::Owned(Vec::new())
+        }
+    }
+
+    // `layers[i]` is the layer of `render_states[i]`, layers have to be sorted by recording order
+    pub fn from_sorted(
+        layers: &'a [RenderLayer],
+        render_states: &'a [&'a dyn RenderStateTrait]
+    ) -> Self {
+        assert_eq!(layers.len(), render_states.len(), "every render state needs a layer");
+        debug_assert!(
+            layers.windows(2).all(|pair| pair[0].recording_order() <= pair[1].recording_order()),
+            "layers are not sorted by recording order");
+
+        Self {
+            layers: Cow::Borrowed(layers),
+            render_states: Cow::Borrowed(render_states)
+        }
+    }
+
+    pub fn register(&mut self, layer: RenderLayer, render_state: &'a dyn RenderStateTrait) {
+        let index = self.layers.partition_point(|registered_layer| {
+            registered_layer.recording_order() <= layer.recording_order()
+        });
+
+        self.layers.to_mut().insert(index, layer);
+        self.render_states.to_mut().insert(index, render_state);
+    }
+
+    pub fn layer(
+        &self,
+        layer: RenderLayer
+    ) -> impl Iterator<Item = &'a dyn RenderStateTrait> + '_ {
+        self.layers
+            .iter()
+            .zip(self.render_states.iter())
+            .filter(move |(registered_layer, _)| **registered_layer == layer)
+            .map(|(_, render_state)| *render_state)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.render_states.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        clear_keeping_capacity(&mut self.layers);
+        clear_keeping_capacity(&mut self.render_states);
+    }
+
+    // Visits runs of consecutive render states of the same layer,
+    // a layer can be visited more than once when it is interleaved with another
+    pub fn iter_layers(&self) -> impl Iterator<Item = (RenderLayer, &[&'a dyn RenderStateTrait])> {
+        let mut start = 0;
+
+        std::iter::from_fn(move || {
+            let layer = *self.layers.get(start)?;
+            let end = start + self.layers[start..]
+                .iter()
+                .take_while(|registered_layer| **registered_layer == layer)
+                .count();
+
+            let render_states = &self.render_states[start..end];
+            start = end;
+
+            Some((layer, render_states))
+        })
+    }
+
+    // Within a layer, render states are recorded in the order they were registered in
+    pub fn iter(&self) -> impl Iterator<Item = &'a dyn RenderStateTrait> + '_ {
+        self.render_states.iter().copied()
+    }
+}
+
+// Borrowed slices are dropped instead of being copied just to be cleared
+fn clear_keeping_capacity<T: Clone>(slice: &mut Cow<[T]>) {
+    match slice {
+        Cow::Owned(vector) => vector.clear(),
+        Cow::Borrowed(_) => *slice = Cow::Owned(Vec::new())
+    }
+}
@@ -0,0 +1,406 @@
+use std::rc::Rc;
+use ash::vk;
+use crate::{
+    builder::{
+        BuilderRequirement,
+        BuilderInternal,
+        BuilderProduct
+    },
+    rendering::{
+        RenderingError,
+        RenderingResult,
+        renderer::Renderer,
+        viewport
+    },
+    vulkan::{
+        image::Image,
+        sampler::Sampler,
+        render_pass::RenderPass,
+        framebuffers::Framebuffers,
+        swapchain::Swapchain,
+        descriptor::{
+            DescriptorSet,
+            DescriptorSetLayout
+        },
+        pipeline::{
+            Pipeline,
+            BlendMode
+        },
+        shader::{
+            VertexShader,
+            FragmentShader,
+            ShaderStage
+        },
+        command_buffer::{
+            CommandBufferRecorder,
+            PushConstants
+        },
+        barrier::{
+            PipelineBarrier,
+            MemoryAccess,
+            ImageBarrier
+        }
+    }
+};
+
+// How the letterboxed layers rendered at a different resolution are stretched
+// over the swapchain image, see `Renderer::set_render_scale`
+#[derive(Clone)]
+pub enum Upscaling {
+    // Blitted without filtering, e.g. for a pixelated look
+    Nearest,
+    // Blitted with bilinear filtering
+    Linear,
+    // Drawn with a fullscreen shader, e.g. one sharpening the stretched image
+    Shader(Rc<UpscaleShader>)
+}
+
+// Fullscreen triangle drawing the scaled image over the swapchain image, e.g. with
+// shaders/fullscreen.vert and shaders/upscale_sharpen.frag. The fragment shader samples
+// the scaled image at set 0, binding 0 and gets the width and height of the scaled image
+// followed by those of the swapchain image as a vec4 fragment push constant.
+pub struct UpscaleShader {
+    pipeline: Rc<Pipeline>,
+    image_set_layout: Rc<DescriptorSetLayout>
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, PushConstants)]
+struct UpscaleConstants {
+    extents: [f32; 4]
+}
+
+impl UpscaleShader {
+    pub fn builder<'a>() -> UpscaleShaderBuilder<'a> {
+        UpscaleShaderBuilder {
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct UpscaleShaderBuilder<'a> {
+    renderer: BuilderRequirement<&'a Renderer>,
+    vertex_shader: BuilderRequirement<&'a VertexShader>,
+    fragment_shader: BuilderRequirement<&'a FragmentShader>,
+
+    image_set_layout: BuilderInternal<Rc<DescriptorSetLayout>>,
+    pipeline: BuilderInternal<Rc<Pipeline>>,
+
+    upscale_shader: BuilderProduct<UpscaleShader>
+}
+
+impl<'a> UpscaleShaderBuilder<'a> {
+    pub fn renderer(mut self, renderer: &'a Renderer) -> Self {
+        self.renderer.set(renderer);
+        self
+    }
+
+    pub fn vertex_shader(mut self, vertex_shader: &'a VertexShader) -> Self {
+        self.vertex_shader.set(vertex_shader);
+        self
+    }
+
+    pub fn fragment_shader(mut self, fragment_shader: &'a FragmentShader) -> Self {
+        self.fragment_shader.set(fragment_shader);
+        self
+    }
+
+    pub fn build(mut self) -> RenderingResult<UpscaleShader> {
+        check_required_fields!(
+            self, RenderingError, "UpscaleShaderBuilder",
+            [renderer, vertex_shader, fragment_shader]);
+
+        self.init_image_set_layout()?;
+        self.init_pipeline()?;
+        self.create_upscale_shader();
+
+        Ok(self.upscale_shader.unwrap())
+    }
+
+    fn init_image_set_layout(&mut self) -> RenderingResult<()> {
+        let layout = DescriptorSetLayout::builder()
+            .logical_device(Rc::clone(self.renderer.logical_device()))
+            .combined_image_sampler(0, &[ShaderStage::Fragment])
+            .build()?;
+
+        self.image_set_layout.set(Rc::new(layout));
+
+        Ok(())
+    }
+
+    // Drawn as the first thing of the main render pass, which is compatible with the renderer's
+    fn init_pipeline(&mut self) -> RenderingResult<()> {
+        let pipeline = Pipeline::builder()
+            .logical_device(Rc::clone(self.renderer.logical_device()))
+            .swapchain(Rc::clone(self.renderer.swapchain()))
+            .render_pass(Rc::clone(self.renderer.render_pass()))
+            .subpass(0)
+            .vertex_shader(*self.vertex_shader)
+            .fragment_shader(*self.fragment_shader)
+            .blend_mode(BlendMode::Opaque)
+            .descriptor_set_layout(Rc::clone(&self.image_set_layout))
+            .push_constants_size(ShaderStage::Fragment, std::mem::size_of::<UpscaleConstants>())
+            .build()?;
+
+        self.pipeline.set(Rc::new(pipeline));
+
+        Ok(())
+    }
+
+    fn create_upscale_shader(&mut self) {
+        self.upscale_shader.set(UpscaleShader {
+            pipeline: self.pipeline.take(),
+            image_set_layout: self.image_set_layout.take()
+        });
+    }
+}
+
+// Image the letterboxed layers are rendered into at the render scale. Its render pass
+// only differs from the main one in its final layout, so that render states and custom
+// passes built for the main pass can be recorded into it.
+pub(crate) struct ScaledTarget {
+    scale: f32,
+    upscaling: Upscaling,
+    swapchain_extent: vk::Extent2D,
+    image: Rc<Image>,
+    render_pass: RenderPass,
+    framebuffers: Framebuffers,
+    // Main pass drawing the other layers over the blitted image
+    loading_render_pass: RenderPass,
+    // Only for upscaling shaders
+    image_set: Option<Rc<DescriptorSet>>
+}
+
+impl ScaledTarget {
+    pub(crate) fn new(
+        renderer: &Renderer,
+        scale: f32,
+        upscaling: Upscaling
+    ) -> RenderingResult<Self> {
+        let swapchain = renderer.swapchain();
+        if !Self::supports_upscaling(renderer, &upscaling) {
+            return Err(RenderingError::UpscaleBlitNotSupported);
+        }
+
+        let max_dimension = renderer.physical_device().limits().max_image_dimension2_d;
+        let scaled = |dimension: u32| {
+            ((dimension as f32 * scale).round() as u32).max(1).min(max_dimension)
+        };
+
+        let extent = vk::Extent2D {
+            width: scaled(swapchain.extent().width),
+            height: scaled(swapchain.extent().height)
+        };
+
+        let image = Image::builder()
+            .allocator(Rc::clone(renderer.allocator()))
+            .logical_device(Rc::clone(renderer.logical_device()))
+            .format(swapchain.image_format())
+            .extent(extent)
+            .usage(vk::ImageUsageFlags::COLOR_ATTACHMENT
+                | vk::ImageUsageFlags::SAMPLED
+                | vk::ImageUsageFlags::TRANSFER_SRC)
+            .aspect(vk::ImageAspectFlags::COLOR)
+            .build()?;
+        let image = Rc::new(image);
+
+        let logical_device = renderer.logical_device();
+        let render_pass = RenderPass::offscreen(
+            Rc::clone(logical_device), &[swapchain.image_format()], None)?;
+        let framebuffers = Framebuffers::for_image(
+            Rc::clone(logical_device), &render_pass, Rc::clone(&image))?;
+        let loading_render_pass = RenderPass::builder()
+            .logical_device(Rc::clone(logical_device))
+            .swapchain(Rc::clone(swapchain))
+            .load_op(vk::AttachmentLoadOp::LOAD)
+            .build()?;
+
+        let image_set = match &upscaling {
+            Upscaling::Shader(shader) => {
+                let sampler = Sampler::clamped(Rc::clone(logical_device), vk::Filter::LINEAR)?;
+                let mut image_set = DescriptorSet::new(Rc::clone(&shader.image_set_layout))?;
+                image_set.write_combined_image_sampler(0, Rc::clone(&image), Rc::new(sampler))?;
+
+                Some(Rc::new(image_set))
+            },
+            Upscaling::Nearest | Upscaling::Linear => None
+        };
+
+        debug!(
+            "rendering letterboxed layers at {}x{} for a {}x{} swapchain",
+            extent.width, extent.height, swapchain.extent().width, swapchain.extent().height);
+
+        Ok(Self {
+            scale,
+            upscaling,
+            swapchain_extent: swapchain.extent(),
+            image,
+            render_pass,
+            framebuffers,
+            loading_render_pass,
+            image_set
+        })
+    }
+
+    fn supports_upscaling(renderer: &Renderer, upscaling: &Upscaling) -> bool {
+        let filter_feature = match upscaling {
+            Upscaling::Shader(_) => return true,
+            Upscaling::Nearest => vk::FormatFeatureFlags::empty(),
+            Upscaling::Linear => vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR
+        };
+
+        let swapchain = renderer.swapchain();
+        let required_features = vk::FormatFeatureFlags::BLIT_SRC
+            | vk::FormatFeatureFlags::BLIT_DST
+            | filter_feature;
+
+        let format_features = renderer
+            .physical_device()
+            .format_properties(swapchain.image_format())
+            .optimal_tiling_features;
+
+        format_features.contains(required_features)
+            && swapchain.image_usage().contains(vk::ImageUsageFlags::TRANSFER_DST)
+    }
+
+    pub(crate) fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    pub(crate) fn upscaling(&self) -> &Upscaling {
+        &self.upscaling
+    }
+
+    pub(crate) fn extent(&self) -> vk::Extent2D {
+        self.image.extent()
+    }
+
+    // Part of the scaled image covered by an area of the swapchain image once upscaled
+    pub(crate) fn scaled_area(&self, area: vk::Rect2D) -> vk::Rect2D {
+        let extent = self.extent();
+        let scale_x = extent.width as f32 / self.swapchain_extent.width as f32;
+        let scale_y = extent.height as f32 / self.swapchain_extent.height as f32;
+
+        let left = (area.offset.x as f32 * scale_x).round() as i32;
+        let top = (area.offset.y as f32 * scale_y).round() as i32;
+        let right = ((area.offset.x as f32 + area.extent.width as f32) * scale_x).round() as i32;
+        let bottom = ((area.offset.y as f32 + area.extent.height as f32) * scale_y).round() as i32;
+
+        vk::Rect2D {
+            offset: vk::Offset2D {
+                x: left,
+                y: top
+            },
+            extent: vk::Extent2D {
+                width: (right - left) as u32,
+                height: (bottom - top) as u32
+            }
+        }
+    }
+
+    pub(crate) fn begin_render_pass<'a>(
+        &self,
+        recorder: CommandBufferRecorder<'a>,
+        clear_values: &[vk::ClearValue]
+    ) -> CommandBufferRecorder<'a> {
+        recorder.begin_render_pass_with_clear_values(
+            &self.render_pass,
+            &self.framebuffers,
+            0,
+            clear_values)
+    }
+
+    // Ends the scaled render pass and begins the main one with the upscaled image
+    // in the swapchain image
+    pub(crate) fn record_upscale<'a>(
+        &self,
+        recorder: CommandBufferRecorder<'a>,
+        render_pass: &RenderPass,
+        framebuffers: &Framebuffers,
+        swapchain: &Swapchain,
+        image_index: usize,
+        clear_values: &[vk::ClearValue]
+    ) -> CommandBufferRecorder<'a> {
+        let recorder = recorder.end_render_pass();
+        let filter = match &self.upscaling {
+            Upscaling::Shader(shader) => {
+                return self.record_upscale_shader(
+                    recorder, shader, render_pass, framebuffers, image_index, clear_values);
+            },
+            Upscaling::Nearest => vk::Filter::NEAREST,
+            Upscaling::Linear => vk::Filter::LINEAR
+        };
+
+        let swapchain_image = swapchain.images()[image_index];
+        // Chains with the wait for the image to be acquired
+        let acquired = MemoryAccess::execution(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT);
+        let loaded = MemoryAccess::new(
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE);
+
+        recorder
+            .pipeline_barrier(&PipelineBarrier::new()
+                .image(ImageBarrier::new(
+                    &self.image,
+                    (
+                        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                        vk::ImageLayout::TRANSFER_SRC_OPTIMAL
+                    ),
+                    (MemoryAccess::fragment_shader_read(), MemoryAccess::transfer_read())))
+                .image(ImageBarrier::from_handle(
+                    swapchain_image,
+                    (vk::ImageLayout::UNDEFINED, vk::ImageLayout::TRANSFER_DST_OPTIMAL),
+                    (acquired, MemoryAccess::transfer_write()))))
+            .blit_image_to_handle(&self.image, swapchain_image, swapchain.extent(), filter)
+            // The scaled render pass of the next frame waits for fragment shaders
+            // before overwriting the image
+            .pipeline_barrier(&PipelineBarrier::new()
+                .image(ImageBarrier::new(
+                    &self.image,
+                    (
+                        vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+                    ),
+                    (MemoryAccess::transfer_read(), MemoryAccess::fragment_shader_read())))
+                .image(ImageBarrier::from_handle(
+                    swapchain_image,
+                    (vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::ImageLayout::PRESENT_SRC_KHR),
+                    (MemoryAccess::transfer_write(), loaded))))
+            .begin_render_pass(&self.loading_render_pass, framebuffers, image_index)
+    }
+
+    // Every pixel is overwritten by the fullscreen triangle, the clear only begins the pass
+    fn record_upscale_shader<'a>(
+        &self,
+        recorder: CommandBufferRecorder<'a>,
+        shader: &UpscaleShader,
+        render_pass: &RenderPass,
+        framebuffers: &Framebuffers,
+        image_index: usize,
+        clear_values: &[vk::ClearValue]
+    ) -> CommandBufferRecorder<'a> {
+        let image_set = self.image_set.as_ref().expect("upscaling shader has no image set");
+        let extent = self.extent();
+        let constants = UpscaleConstants {
+            extents: [
+                extent.width as f32,
+                extent.height as f32,
+                self.swapchain_extent.width as f32,
+                self.swapchain_extent.height as f32
+            ]
+        };
+
+        recorder
+            .begin_render_pass_with_clear_values(
+                render_pass,
+                framebuffers,
+                image_index,
+                clear_values)
+            .set_viewport(viewport::full_area(self.swapchain_extent))
+            .bind_pipeline(Rc::clone(&shader.pipeline))
+            .bind_descriptor_set(&shader.pipeline, 0, Rc::clone(image_set))
+            .push_constant(&shader.pipeline, ShaderStage::Fragment, &constants)
+            .draw(3, 1)
+    }
+}
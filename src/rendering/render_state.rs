@@ -1,21 +1,34 @@
 use std::{
     collections::HashMap,
     rc::Rc,
-    marker::PhantomData
+    marker::PhantomData,
+    sync::atomic::{
+        AtomicU64,
+        Ordering
+    }
 };
 use crate::{
+    assets::shader_program::ShaderProgram,
     builder::{
         BuilderRequirement,
         BuilderInternal
     },
     rendering::{
+        RenderingError,
         RenderingResult,
-        renderer::Renderer
+        renderer::Renderer,
+        mesh::Mesh,
+        material::Material,
+        pipeline_cache::PipelineDescription
     },
     vulkan::{
+        handle::VulkanHandle,
+        descriptor::DescriptorSet,
         pipeline::{
             Pipeline,
-            PipelineBuilder
+            PipelineBuilder,
+            BlendMode,
+            CullMode
         },
         shader::ShaderStage
     }
@@ -29,12 +42,21 @@ pub use crate::vulkan::{
     command_buffer::PushConstants
 };
 
+static NEXT_RENDER_STATE_ID: AtomicU64 = AtomicU64::new(0);
+
 pub struct RenderState<'a, G, V, F> where
     G: PushConstants + 'a,
     V: PushConstants + 'a,
     F: PushConstants + 'a {
+    id: u64,
+    // Incremented on every change, so that cached command buffers know when to be rerecorded
+    version: u64,
     pipeline: Rc<Pipeline>,
     shaders: HashMap<ShaderStage, Box<dyn PushConstants + 'a>>,
+    mesh: Option<Mesh>,
+    descriptor_set: Option<Rc<DescriptorSet>>,
+    vertex_count: u32,
+    instance_count: u32,
     geometry_constants: PhantomData<G>,
     vertex_constants: PhantomData<V>,
     fragment_constants: PhantomData<F>
@@ -56,19 +78,43 @@ impl<'a, G, V, F> RenderState<'a, G, V, F> where
 
     pub fn push_geometry_constants(&mut self, constants: G) {
         self.shaders.insert(ShaderStage::Geometry, Box::new(constants));
+        self.mark_dirty();
     }
 
     pub fn push_vertex_constants(&mut self, constants: V) {
         self.shaders.insert(ShaderStage::Vertex, Box::new(constants));
+        self.mark_dirty();
     }
 
     pub fn push_fragment_constants(&mut self, constants: F) {
         self.shaders.insert(ShaderStage::Fragment, Box::new(constants));
+        self.mark_dirty();
+    }
+
+    pub fn set_vertex_count(&mut self, vertex_count: u32) {
+        self.vertex_count = vertex_count;
+        self.mark_dirty();
+    }
+
+    pub fn set_instance_count(&mut self, instance_count: u32) {
+        self.instance_count = instance_count;
+        self.mark_dirty();
+    }
+
+    pub fn mark_dirty(&mut self) {
+        self.version += 1;
     }
 }
 
 pub trait RenderStateTrait {
+    fn id(&self) -> u64;
+    fn version(&self) -> u64;
     fn pipeline(&self) -> &Rc<Pipeline>;
+    fn mesh(&self) -> Option<&Mesh>;
+    fn descriptor_set(&self) -> Option<&Rc<DescriptorSet>>;
+    fn vertex_count(&self) -> u32;
+    fn instance_count(&self) -> u32;
+    fn push_constants(&self, stage: ShaderStage) -> Option<&dyn PushConstants>;
     fn iterate_shaders(&self)
         -> std::collections::hash_map::IntoIter<ShaderStage, &dyn PushConstants>;
 }
@@ -77,10 +123,38 @@ impl<'a, G, V, F> RenderStateTrait for RenderState<'a, G, V, F> where
     G: PushConstants + 'a,
     V: PushConstants + 'a,
     F: PushConstants + 'a {
+    fn id(&self) -> u64 {
+        self.id
+    }
+
+    fn version(&self) -> u64 {
+        self.version
+    }
+
     fn pipeline(&self) -> &Rc<Pipeline> {
         &self.pipeline
     }
 
+    fn mesh(&self) -> Option<&Mesh> {
+        self.mesh.as_ref()
+    }
+
+    fn descriptor_set(&self) -> Option<&Rc<DescriptorSet>> {
+        self.descriptor_set.as_ref()
+    }
+
+    fn vertex_count(&self) -> u32 {
+        self.vertex_count
+    }
+
+    fn instance_count(&self) -> u32 {
+        self.instance_count
+    }
+
+    fn push_constants(&self, stage: ShaderStage) -> Option<&dyn PushConstants> {
+        self.shaders.get(&stage).map(|constants| constants.as_ref())
+    }
+
     fn iterate_shaders(
         &self
     ) -> std::collections::hash_map::IntoIter<ShaderStage, &dyn PushConstants> {
@@ -98,8 +172,16 @@ pub struct RenderStateBuilder<'a, G, V, F> where
     geometry_shader: Option<&'a GeometryShader>,
     vertex_shader: Option<&'a VertexShader>,
     fragment_shader: Option<&'a FragmentShader>,
+    alpha_to_coverage: bool,
+    blend_mode: BlendMode,
+    cull_mode: CullMode,
+    descriptor_set: Option<Rc<DescriptorSet>>,
+    mesh: Option<Mesh>,
+    vertex_count: Option<u32>,
+    instance_count: u32,
+    program: Option<&'a ShaderProgram>,
 
-    pipeline: BuilderInternal<Pipeline>,
+    pipeline: BuilderInternal<Rc<Pipeline>>,
 
     geometry_constants: PhantomData<G>,
     vertex_constants: PhantomData<V>,
@@ -125,41 +207,199 @@ impl<'a, G, V, F> RenderStateBuilder<'a, G, V, F> where
         self
     }
 
+    pub fn alpha_to_coverage(mut self, alpha_to_coverage: bool) -> Self {
+        self.alpha_to_coverage = alpha_to_coverage;
+        self
+    }
+
+    // Takes the program's shaders, replacing any set on the builder before.
+    // Push constants and the mesh's vertex layout have to match the ones the program declares.
+    pub fn program(mut self, program: &'a ShaderProgram) -> Self {
+        self.geometry_shader = program.geometry_shader();
+        self.vertex_shader = program.vertex_shader();
+        self.fragment_shader = program.fragment_shader();
+        self.program = Some(program);
+        self
+    }
+
+    // Takes the shaders, fixed function state and parameters from the material,
+    // replacing any set on the builder before
+    pub fn material(mut self, material: &Material<'a>) -> Self {
+        self.geometry_shader = material.geometry_shader();
+        self.vertex_shader = material.vertex_shader();
+        self.fragment_shader = material.fragment_shader();
+        self.alpha_to_coverage = material.alpha_to_coverage();
+        self.blend_mode = material.blend_mode();
+        self.cull_mode = material.cull_mode();
+        self.descriptor_set = material.descriptor_set().cloned();
+        self
+    }
+
+    // Vertex count defaults to the number of vertices (or indices) in the mesh
+    pub fn mesh(mut self, mesh: &Mesh) -> Self {
+        self.mesh = Some(mesh.clone());
+        self
+    }
+
+    pub fn vertex_count(mut self, vertex_count: u32) -> Self {
+        self.vertex_count = Some(vertex_count);
+        self
+    }
+
+    pub fn instance_count(mut self, instance_count: u32) -> Self {
+        self.instance_count = instance_count;
+        self
+    }
+
     pub fn renderer(mut self, renderer: &'a Renderer) -> Self {
         self.renderer.set(renderer);
         self
     }
 
     pub fn build(mut self) -> RenderingResult<RenderState<'static, G, V, F>> {
+        check_required_fields!(self, RenderingError, "RenderStateBuilder", [renderer]);
+
+        let vertex_count = self.vertex_count
+            .or_else(|| self.mesh.as_ref().map(|mesh| mesh.element_count()))
+            .ok_or(RenderingError::BuilderMissingField {
+                builder: "RenderStateBuilder",
+                field: "vertex_count"
+            })?;
+
+        self.check_program()?;
         self.init_pipeline()?;
 
         Ok(RenderState {
-            pipeline: Rc::new(self.pipeline.take()),
+            id: NEXT_RENDER_STATE_ID.fetch_add(1, Ordering::Relaxed),
+            version: 0,
+            pipeline: self.pipeline.take(),
             shaders: HashMap::new(),
+            mesh: self.mesh.take(),
+            descriptor_set: self.descriptor_set.take(),
+            vertex_count,
+            instance_count: self.instance_count,
             geometry_constants: PhantomData,
             vertex_constants: PhantomData,
             fragment_constants: PhantomData
         })
     }
 
+    fn check_program(&self) -> RenderingResult<()> {
+        let program = match self.program {
+            Some(program) => program,
+            None => return Ok(())
+        };
+
+        let push_constants_sizes = [
+            (ShaderStage::Geometry, std::mem::size_of::<G>()),
+            (ShaderStage::Vertex, std::mem::size_of::<V>()),
+            (ShaderStage::Fragment, std::mem::size_of::<F>())
+        ];
+
+        for (stage, size) in push_constants_sizes.iter() {
+            let expected_size = match program.push_constants_size(*stage) {
+                Some(expected_size) => expected_size,
+                None => continue
+            };
+
+            if *size != expected_size {
+                return Err(RenderingError::ProgramPushConstantsMismatch {
+                    stage: format!("{:?}", stage),
+                    size: *size,
+                    expected_size
+                });
+            }
+        }
+
+        if let (Some(layout), Some(mesh)) = (program.vertex_layout(), &self.mesh) {
+            if !layout.matches(mesh) {
+                return Err(RenderingError::ProgramVertexLayoutMismatch);
+            }
+        }
+
+        Ok(())
+    }
+
+    // Render states with identical descriptions share one pipeline through the renderer's cache
     fn init_pipeline(&mut self) -> RenderingResult<()> {
+        let description = self.pipeline_description();
+        let pipeline = self.renderer
+            .pipeline_cache()
+            .get_or_create(description, || self.create_pipeline())?;
+
+        self.pipeline.set(pipeline);
+
+        Ok(())
+    }
+
+    fn pipeline_description(&self) -> PipelineDescription {
+        let mut description = PipelineDescription {
+            alpha_to_coverage: self.alpha_to_coverage,
+            blend_mode: self.blend_mode,
+            cull_mode: self.cull_mode,
+            ..Default::default()
+        };
+
+        if let Some(descriptor_set) = &self.descriptor_set {
+            description.descriptor_set_layouts.push(descriptor_set.layout().raw_handle());
+        }
+
+        if let Some(shader) = self.geometry_shader {
+            description.shader_ids.push((ShaderStage::Geometry, shader.id()));
+            description.push_constants_sizes
+                .push((ShaderStage::Geometry, std::mem::size_of::<G>()));
+        }
+
+        if let Some(shader) = self.vertex_shader {
+            description.shader_ids.push((ShaderStage::Vertex, shader.id()));
+            description.push_constants_sizes
+                .push((ShaderStage::Vertex, std::mem::size_of::<V>()));
+        }
+
+        if let Some(shader) = self.fragment_shader {
+            description.shader_ids.push((ShaderStage::Fragment, shader.id()));
+            description.push_constants_sizes
+                .push((ShaderStage::Fragment, std::mem::size_of::<F>()));
+        }
+
+        if let Some(mesh) = &self.mesh {
+            description.vertex_stride = Some(mesh.vertex_stride());
+            description.vertex_attributes = mesh.vertex_attributes()
+                .iter()
+                .map(|attribute| (attribute.format, attribute.offset))
+                .collect();
+        }
+
+        description
+    }
+
+    fn create_pipeline(&self) -> RenderingResult<Pipeline> {
         let mut pipeline_builder = Pipeline::builder();
         pipeline_builder = self.add_shaders_to_pipeline_if_some(pipeline_builder);
+        pipeline_builder = self.add_mesh_layout_to_pipeline_if_some(pipeline_builder);
+
+        if let Some(descriptor_set) = &self.descriptor_set {
+            pipeline_builder = pipeline_builder
+                .descriptor_set_layout(Rc::clone(descriptor_set.layout()));
+        }
 
         let pipeline = pipeline_builder
             .logical_device(Rc::clone(self.renderer.logical_device()))
             .swapchain(Rc::clone(self.renderer.swapchain()))
             .render_pass(Rc::clone(self.renderer.render_pass()))
             .subpass(0)
+            .alpha_to_coverage(self.alpha_to_coverage)
+            .blend_mode(self.blend_mode)
+            .cull_mode(self.cull_mode)
             .build()?;
 
-        self.pipeline.set(pipeline);
-
-        Ok(())
+        Ok(pipeline)
     }
 
+    // Push constants of the stages are laid out one after another in geometry, vertex, fragment
+    // order, so that their ranges never overlap; later stages have to declare matching offsets
     fn add_shaders_to_pipeline_if_some(
-        &mut self,
+        &self,
         mut pipeline_builder: PipelineBuilder<'a>
     ) -> PipelineBuilder<'a> {
         pipeline_builder =
@@ -177,6 +417,22 @@ impl<'a, G, V, F> RenderStateBuilder<'a, G, V, F> where
         pipeline_builder
     }
 
+    fn add_mesh_layout_to_pipeline_if_some(
+        &self,
+        mut pipeline_builder: PipelineBuilder<'a>
+    ) -> PipelineBuilder<'a> {
+        if let Some(mesh) = &self.mesh {
+            for attribute in mesh.vertex_attributes() {
+                pipeline_builder = pipeline_builder
+                    .vertex_attribute_description(attribute.format, attribute.offset);
+            }
+
+            pipeline_builder = pipeline_builder.vertex_binding_stride(mesh.vertex_stride());
+        }
+
+        pipeline_builder
+    }
+
     fn add_geometry_shader_to_pipeline_if_some<'b>(
         pipeline_builder: PipelineBuilder<'b>,
         geometry_shader: Option<&'b GeometryShader>
@@ -224,6 +480,14 @@ impl<'a, G, V, F> Default for RenderStateBuilder<'a, G, V, F> where
             geometry_shader: None,
             vertex_shader: None,
             fragment_shader: None,
+            alpha_to_coverage: false,
+            blend_mode: BlendMode::Opaque,
+            cull_mode: CullMode::None,
+            descriptor_set: None,
+            mesh: None,
+            vertex_count: None,
+            instance_count: 1,
+            program: None,
 
             pipeline: BuilderInternal::none(),
             geometry_constants: PhantomData::<G>,
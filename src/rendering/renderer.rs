@@ -1,29 +1,95 @@
-use std::{
-    cell::RefCell,
-    rc::Rc
+use std::rc::{
+    Rc,
+    Weak
 };
 use ash::{
     version::DeviceV1_0,
     vk
 };
+use image::RgbaImage;
 use crate::{
     rendering::{
         RenderingError,
         RenderingResult,
         render_state::RenderStateTrait,
+        render_layers::RenderLayers,
+        scene::Scene,
+        viewport::{
+            self,
+            ViewportArea,
+            SplitViewport,
+            ViewportLayers
+        },
+        command_buffer_cache::{
+            CommandBufferCache,
+            RecordingSignature
+        },
+        frame_capture::FrameCapture,
+        debug_overlay::DebugOverlay,
+        sequence_capture::{
+            SequenceCapture,
+            SequenceOutput
+        },
+        previous_frame::PreviousFrame,
+        render_scale::{
+            ScaledTarget,
+            Upscaling
+        },
+        memory_stats::MemoryStats,
+        pipeline_cache::PipelineCache,
+        frame_arena::FrameArena,
+        frame_context::{
+            FrameResources,
+            FrameContext
+        },
+        render_layers::RenderLayer,
+        custom_pass::{
+            CustomPass,
+            CustomPassContext,
+            CustomPassPoint,
+            CustomPasses
+        },
+        readback_ring::{
+            ReadbackRing,
+            Readback
+        },
+        destruction_queue::DestructionQueue,
+        xr::XrRequirements,
+        gamma::{
+            self,
+            GammaCorrection,
+            GammaPath
+        },
+        submission::{
+            Submission,
+            SubmissionScheduler
+        }
     },
     vulkan::{
         self,
+        handle::VulkanHandle,
         state::VulkanState,
+        instance::Instance,
         logical_device::LogicalDevice,
-        surface::Surface,
-        swapchain::Swapchain,
+        surface::{
+            Surface,
+            SurfaceWindow
+        },
+        swapchain::{
+            Swapchain,
+            PresentModePreference
+        },
         render_pass::RenderPass,
+        diagnostics::diagnose,
+        shader::ShaderStage,
         framebuffers::Framebuffers,
+        allocator::Allocator,
         command_pool::CommandPool,
         command_buffer::{
             CommandBuffer,
-            CommandBufferRecorder
+            CommandBufferRecorder,
+            QueueType,
+            GraphicsQueue
         },
         physical_device::{
             PhysicalDevice,
@@ -32,12 +98,16 @@ use crate::{
         },
         synchronization::{
             Semaphore,
-            Fence,
-            FenceStatus
+            FenceStatus,
+            SyncObjectPool
         }
     },
-    window::Window,
-    debugging
+    debugging,
+    profiling::{
+        self,
+        GpuTimestamps,
+        GpuTimestamp
+    }
 };
 
 pub struct Renderer {
@@ -47,43 +117,174 @@ pub struct Renderer {
     logical_device: Rc<LogicalDevice>,
     surface: Rc<Surface>,
     swapchain: Rc<Swapchain>,
+    gamma_correction: GammaCorrection,
     render_pass: Rc<RenderPass>,
     framebuffers: Framebuffers,
-    command_pool: CommandPool,
-    command_buffers: Vec<CommandBuffer>,
-    // Vulkan synchronization
-    image_acquired_semaphores: Vec<Semaphore>,
-    image_rendered_semaphores: Vec<Semaphore>,
-    image_rendered_fences: Vec<Fence>,
-    current_frame: usize
+    pipeline_cache: PipelineCache,
+    allocator: Rc<Allocator>,
+    recording_mode: RecordingMode,
+    empty_frame_mode: EmptyFrameMode,
+    previous_frame: PreviousFrame,
+    redraw_mode: RedrawMode,
+    is_redraw_requested: bool,
+    viewport_area: ViewportArea,
+    clear_color: [f32; 4],
+    // None while the letterboxed layers are rendered at the swapchain's resolution
+    scaled_target: Option<ScaledTarget>,
+    command_buffer_cache: Option<CommandBufferCache>,
+    frame_capture: FrameCapture,
+    sequence_capture: SequenceCapture,
+    debug_overlay: Option<DebugOverlay>,
+    readbacks: ReadbackRing<Readback>,
+    destruction_queue: DestructionQueue,
+    frame_arena: FrameArena,
+    custom_passes: CustomPasses,
+    submissions: SubmissionScheduler,
+    // Semaphores the next frame's rendering waits for, e.g. signalled by submissions
+    // to other queues
+    frame_waits: Vec<(Rc<Semaphore>, vk::PipelineStageFlags)>,
+    gpu_timestamps: Option<GpuTimestamps>,
+    events: Vec<RendererEvent>,
+    is_device_lost: bool,
+    // Transient pool of the frames' command buffers and of those of `execute_immediately`
+    command_pool: Rc<CommandPool>,
+    // Command buffers, synchronization and transient resources of every frame in flight
+    frames: Vec<FrameResources>,
+    // Whether the current frame's resources were already waited for, e.g. by `begin_frame`
+    is_current_frame_ready: bool,
+    sync_objects: SyncObjectPool,
+    // Frame in flight which last rendered to the given swapchain image
+    images_in_flight: Vec<Option<usize>>,
+    current_frame: usize,
+    // Frames submitted since the renderer was created
+    frame_number: u64
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum RecordingMode {
+    // Command buffers are recorded anew every frame
+    EveryFrame,
+    // Command buffers are recorded once per swapchain image
+    // and rerecorded only when the rendered states change
+    Cached
+}
+
+// What is done with frames in which no viewport has any render states, e.g. in editors
+// which only redraw after changes
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum EmptyFrameMode {
+    // The frame is rendered like any other, including custom passes
+    Render,
+    // The swapchain image is only cleared, custom passes are not recorded
+    Clear,
+    // Nothing is rendered or presented, so the window keeps showing what it showed before
+    // on most platforms. Queued submissions wait for the next rendered frame.
+    Skip,
+    // The last rendered frame is presented again. Every rendered frame is copied
+    // for that purpose, which costs a copy of the swapchain image per frame.
+    PresentPrevious
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum RedrawMode {
+    // Every call to `render` renders a frame
+    Continuous,
+    // Calls to `render` only render a frame after `request_redraw` or after a setting
+    // of the renderer changed, e.g. in tools which wait for events with `Window::wait_events`
+    // while `needs_redraw` is false. Events for which `WindowEvent::requires_redraw` holds
+    // have to be followed by a request.
+    OnDemand
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+enum FrameContents {
+    Everything,
+    Clear,
+    PreviousFrame
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum RendererEvent {
+    // The device was lost, e.g. after a driver reset, and nothing is rendered anymore
+    // until the renderer is rebuilt
+    DeviceLost
 }
 
 impl Renderer {
-    const FRAMES_IN_FLIGHT: usize = 2;
-
-    pub fn new(window: Rc<RefCell<Window>>) -> RenderingResult<Renderer> {
-        let vulkan_state = Self::create_vulkan_state(&window)?;
-        let surface = Self::create_surface(&vulkan_state, &window)?;
-        let physical_device = Self::create_physical_device(&vulkan_state, &surface)?;
-        let logical_device = Self::create_logical_device(&vulkan_state, &physical_device)?;
-        let swapchain = Self::create_swapchain(&physical_device, &logical_device, &surface)?;
+    pub(crate) const FRAMES_IN_FLIGHT: usize = 2;
+    const DEFAULT_CLEAR_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
+
+    pub fn new(window: Weak<dyn SurfaceWindow>) -> RenderingResult<Renderer> {
+        Self::with_gamma_correction(window, GammaCorrection::Enabled)
+    }
+
+    // Which swapchain formats are preferred depends on the setting, see `gamma_path`
+    // for how colors end up being encoded
+    pub fn with_gamma_correction(
+        window: Weak<dyn SurfaceWindow>,
+        gamma_correction: GammaCorrection
+    ) -> RenderingResult<Renderer> {
+        Self::with_xr_requirements(window, gamma_correction, &XrRequirements::default())
+    }
+
+    // Creates the instance and the device with the extensions an OpenXR runtime needs,
+    // on the physical device it renders with. The window is still rendered to as usual,
+    // e.g. mirroring the headset, while `XrSwapchainTarget`s render to the runtime's images.
+    pub fn with_xr_requirements(
+        window: Weak<dyn SurfaceWindow>,
+        gamma_correction: GammaCorrection,
+        xr_requirements: &XrRequirements
+    ) -> RenderingResult<Renderer> {
+        let vulkan_state = diagnose("instance creation", None, || {
+            Self::create_vulkan_state(&window, xr_requirements)
+        })?;
+        let surface = diagnose("surface creation", Some(&vulkan_state), || {
+            Self::create_surface(&vulkan_state, window)
+        })?;
+        let physical_device = diagnose("physical device selection", Some(&vulkan_state), || {
+            Self::create_physical_device(&vulkan_state, &surface, xr_requirements)
+        })?;
+
+        Self::with_physical_device(vulkan_state, surface, physical_device, gamma_correction)
+    }
+
+    fn with_physical_device(
+        vulkan_state: Rc<VulkanState>,
+        surface: Rc<Surface>,
+        physical_device: Rc<PhysicalDevice>,
+        gamma_correction: GammaCorrection
+    ) -> RenderingResult<Renderer> {
+        let logical_device = diagnose("logical device creation", Some(&vulkan_state), || {
+            Self::create_logical_device(&vulkan_state, &physical_device)
+        })?;
+        let swapchain = diagnose("swapchain creation", Some(&vulkan_state), || {
+            Self::create_swapchain(
+                &physical_device, &logical_device, &surface, gamma_correction, None)
+        })?;
+        info!(
+            "swapchain format {:?}, gamma path {:?}",
+            swapchain.image_format(),
+            GammaPath::choose(gamma_correction, swapchain.image_format()));
         let render_pass = Self::create_render_pass(&logical_device, &swapchain)?;
         let framebuffers = Self::create_framebuffers(&logical_device, &swapchain, &render_pass)?;
+        let allocator = Self::create_allocator(&vulkan_state, &physical_device, &logical_device)?;
         let command_pool = Self::create_command_pool(&physical_device, &logical_device)?;
         let command_buffers =
             command_pool.allocate_command_buffers(Self::FRAMES_IN_FLIGHT)?;
-        let mut image_acquired_semaphores = Vec::with_capacity(Self::FRAMES_IN_FLIGHT);
-        let mut image_rendered_semaphores = Vec::with_capacity(Self::FRAMES_IN_FLIGHT);
-        let mut image_rendered_fences = Vec::with_capacity(Self::FRAMES_IN_FLIGHT);
-
-        for _ in 0..Self::FRAMES_IN_FLIGHT {
-            image_acquired_semaphores
-                .push(Semaphore::new(Rc::clone(&logical_device))?);
-            image_rendered_semaphores
-                .push(Semaphore::new(Rc::clone(&logical_device))?);
-            image_rendered_fences
-                .push(Fence::new(Rc::clone(&logical_device), FenceStatus::Ready)?);
-        }
+        let transient_command_buffers =
+            command_pool.allocate_command_buffers(Self::FRAMES_IN_FLIGHT)?;
+        let frames = command_buffers
+            .into_iter()
+            .zip(transient_command_buffers)
+            .map(|(command_buffer, transient_command_buffer)| {
+                FrameResources::new(&logical_device, command_buffer, transient_command_buffer)
+            })
+            .collect::<RenderingResult<_>>()?;
+        let images_in_flight = vec![None; swapchain.image_count()];
+        let sync_objects = SyncObjectPool::new(Rc::clone(&logical_device));
+        let previous_frame = PreviousFrame::new(Rc::clone(&allocator), Rc::clone(&logical_device));
+        let gpu_timestamps =
+            GpuTimestamps::new(&physical_device, &logical_device, swapchain.image_count())?;
 
         Ok(Renderer {
             vulkan_state,
@@ -91,23 +292,75 @@ impl Renderer {
             logical_device,
             surface,
             swapchain,
+            gamma_correction,
             render_pass,
             framebuffers,
+            pipeline_cache: PipelineCache::new(),
+            frame_capture: FrameCapture::new(Rc::clone(&allocator)),
+            sequence_capture: SequenceCapture::new(
+                Rc::clone(&allocator), Self::FRAMES_IN_FLIGHT),
+            debug_overlay: None,
+            allocator,
+            recording_mode: RecordingMode::EveryFrame,
+            empty_frame_mode: EmptyFrameMode::Render,
+            previous_frame,
+            redraw_mode: RedrawMode::Continuous,
+            // Nothing was rendered yet
+            is_redraw_requested: true,
+            viewport_area: ViewportArea::Full,
+            clear_color: Self::DEFAULT_CLEAR_COLOR,
+            scaled_target: None,
+            command_buffer_cache: None,
+            readbacks: ReadbackRing::new(Self::FRAMES_IN_FLIGHT),
+            destruction_queue: DestructionQueue::new(),
+            frame_arena: FrameArena::new(),
+            custom_passes: CustomPasses::new(),
+            submissions: SubmissionScheduler::new(),
+            frame_waits: Vec::new(),
+            gpu_timestamps,
+            events: Vec::new(),
+            is_device_lost: false,
             command_pool,
-            command_buffers,
-            image_acquired_semaphores,
-            image_rendered_semaphores,
-            image_rendered_fences,
-            current_frame: 0
+            frames,
+            is_current_frame_ready: false,
+            sync_objects,
+            images_in_flight,
+            current_frame: 0,
+            frame_number: 0
         })
     }
 
-    fn create_vulkan_state(window: &Rc<RefCell<Window>>) -> RenderingResult<Rc<VulkanState>> {
-        let window = window.borrow();
-        let glfw_extensions = window.required_vulkan_extensions();
+    fn create_vulkan_state(
+        window: &Weak<dyn SurfaceWindow>,
+        xr_requirements: &XrRequirements
+    ) -> RenderingResult<Rc<VulkanState>> {
+        let window = window
+            .upgrade()
+            .ok_or(vulkan::VulkanError::SurfaceWindowDestroyedError)?;
+
+        let mut instance_extensions = Surface::required_instance_extensions(window.as_ref())?;
+        // Runtimes commonly ask for surface extensions too
+        for extension in &xr_requirements.instance_extensions {
+            let is_requested = instance_extensions
+                .strings()
+                .iter()
+                .any(|requested| requested.to_bytes() == extension.as_bytes());
+
+            if !is_requested {
+                instance_extensions.push(extension);
+            }
+        }
+
         let vulkan_state = VulkanState::builder()
             .debug_mode(debugging::is_in_debug_mode())
-            .instance_extensions(glfw_extensions)
+            .instance_extensions(instance_extensions)
+            // Lists MoltenVK on macOS
+            .optional_instance_extension(Instance::PORTABILITY_ENUMERATION_EXTENSION)
+            // Required by portability subset devices and VK_KHR_push_descriptor
+            // when falling back to Vulkan 1.0
+            .optional_instance_extension("VK_KHR_get_physical_device_properties2")
+            // Needed for memory budgets, loaders without it fall back to 1.0
+            .api_version(1, 1)
             .build()?;
 
         Ok(Rc::new(vulkan_state))
@@ -115,28 +368,52 @@ impl Renderer {
 
     fn create_surface(
         vulkan_state: &Rc<VulkanState>,
-        window: &Rc<RefCell<Window>>
+        window: Weak<dyn SurfaceWindow>
     ) -> RenderingResult<Rc<Surface>> {
         let surface = vulkan::surface::Surface::new(
-            Rc::clone(&window),
-            Rc::clone(&vulkan_state));
+            window,
+            Rc::clone(vulkan_state))?;
 
         Ok(Rc::new(surface))
     }
 
     fn create_physical_device(
         vulkan_state: &Rc<VulkanState>,
-        surface: &Rc<Surface>
+        surface: &Rc<Surface>,
+        xr_requirements: &XrRequirements
     ) -> RenderingResult<Rc<PhysicalDevice>> {
         let queue_families = [QueueFamily::Graphics, QueueFamily::Transfer];
-        let physical_device_extensions = c_string_collection!(PhysicalDeviceExtensions:
+        let mut physical_device_extensions = c_string_collection!(PhysicalDeviceExtensions:
             [ash::extensions::khr::Swapchain::name().to_str().unwrap()]);
+        for extension in &xr_requirements.device_extensions {
+            let is_requested = physical_device_extensions
+                .strings()
+                .iter()
+                .any(|requested| requested.to_bytes() == extension.as_bytes());
+
+            if !is_requested {
+                physical_device_extensions.push(extension);
+            }
+        }
+
+        let mut selector = vulkan::physical_device::PhysicalDevice::selector();
+        if let Some(xr_physical_device) = &xr_requirements.physical_device {
+            let vk_instance = vulkan_state.instance().handle();
+            selector = selector.required_device(xr_physical_device(vk_instance));
+        }
 
-        let physical_device = vulkan::physical_device::PhysicalDevice::selector()
-            .vulkan_state(Rc::clone(&vulkan_state))
+        let physical_device = selector
+            .vulkan_state(Rc::clone(vulkan_state))
             .queue_families(&queue_families)
-            .surface_compatible(Rc::clone(&surface))
+            .surface_compatible(Rc::clone(surface))
             .device_extensions(physical_device_extensions)
+            .optional_device_extension(PhysicalDevice::MEMORY_BUDGET_EXTENSION)
+            .optional_device_extension(PhysicalDevice::CONDITIONAL_RENDERING_EXTENSION)
+            .optional_device_extension(PhysicalDevice::PUSH_DESCRIPTOR_EXTENSION)
+            .optional_device_extension(PhysicalDevice::MEMORY_REQUIREMENTS_2_EXTENSION)
+            .optional_device_extension(PhysicalDevice::RAY_TRACING_EXTENSION)
+            .optional_device_extension(PhysicalDevice::EXTERNAL_MEMORY_EXTENSION)
+            .optional_device_extension(PhysicalDevice::EXTERNAL_SEMAPHORE_EXTENSION)
             .select()?;
 
         Ok(Rc::new(physical_device))
@@ -153,8 +430,8 @@ impl Renderer {
         ];
 
         let logical_device = vulkan::logical_device::LogicalDevice::builder()
-            .vulkan_state(Rc::clone(&vulkan_state))
-            .physical_device(Rc::clone(&physical_device))
+            .vulkan_state(Rc::clone(vulkan_state))
+            .physical_device(Rc::clone(physical_device))
             .queue_families(&queue_families)
             .build()?;
 
@@ -164,13 +441,28 @@ impl Renderer {
     fn create_swapchain(
         physical_device: &Rc<PhysicalDevice>,
         logical_device: &Rc<LogicalDevice>,
-        surface: &Rc<Surface>
+        surface: &Rc<Surface>,
+        gamma_correction: GammaCorrection,
+        old_swapchain: Option<&Swapchain>
     ) -> RenderingResult<Rc<Swapchain>> {
-        let swapchain = vulkan::swapchain::Swapchain::builder()
-            .physical_device(Rc::clone(&physical_device))
-            .logical_device(Rc::clone(&logical_device))
-            .surface(Rc::clone(&surface))
-            .vsync(false)
+        let mut builder = vulkan::swapchain::Swapchain::builder()
+            .physical_device(Rc::clone(physical_device))
+            .logical_device(Rc::clone(logical_device))
+            .surface(Rc::clone(surface))
+            .present_mode_preference(&[
+                PresentModePreference::Immediate,
+                PresentModePreference::Mailbox,
+                PresentModePreference::Fifo
+            ]);
+
+        if let Some(old_swapchain) = old_swapchain {
+            builder = builder.old_swapchain(old_swapchain);
+        }
+
+        // The surface's own order of formats differs between machines, so it is never relied on
+        let swapchain = gamma::preferred_formats(gamma_correction)
+            .iter()
+            .fold(builder, |builder, format| builder.preferred_format(*format))
             .build()?;
 
         Ok(Rc::new(swapchain))
@@ -181,8 +473,8 @@ impl Renderer {
         swapchain: &Rc<Swapchain>
     ) -> RenderingResult<Rc<RenderPass>> {
         let render_pass = vulkan::render_pass::RenderPass::builder()
-            .logical_device(Rc::clone(&logical_device))
-            .swapchain(Rc::clone(&swapchain))
+            .logical_device(Rc::clone(logical_device))
+            .swapchain(Rc::clone(swapchain))
             .build()?;
 
         Ok(Rc::new(render_pass))
@@ -194,120 +486,870 @@ impl Renderer {
         render_pass: &Rc<RenderPass>
     ) -> RenderingResult<Framebuffers> {
         let framebuffers = vulkan::framebuffers::Framebuffers::builder()
-            .logical_device(Rc::clone(&logical_device))
-            .swapchain(Rc::clone(&swapchain))
-            .render_pass(Rc::clone(&render_pass))
+            .logical_device(Rc::clone(logical_device))
+            .swapchain(Rc::clone(swapchain))
+            .render_pass(Rc::clone(render_pass))
             .build()?;
 
         Ok(framebuffers)
     }
 
+    fn create_allocator(
+        vulkan_state: &Rc<VulkanState>,
+        physical_device: &Rc<PhysicalDevice>,
+        logical_device: &Rc<LogicalDevice>
+    ) -> RenderingResult<Rc<Allocator>> {
+        let allocator = Allocator::new(
+            vulkan_state,
+            physical_device,
+            Rc::clone(logical_device))?;
+
+        Ok(Rc::new(allocator))
+    }
+
     fn create_command_pool(
         physical_device: &Rc<PhysicalDevice>,
         logical_device: &Rc<LogicalDevice>
-    ) -> RenderingResult<CommandPool> {
+    ) -> RenderingResult<Rc<CommandPool<GraphicsQueue>>> {
         let command_pool = vulkan::command_pool::CommandPool::builder()
-            .physical_device(Rc::clone(&physical_device))
-            .logical_device(Rc::clone(&logical_device))
-            .queue_family(QueueFamily::Graphics)
+            .physical_device(Rc::clone(physical_device))
+            .logical_device(Rc::clone(logical_device))
             .submit_buffers_once(true)
             .build()?;
 
-        Ok(command_pool)
+        Ok(Rc::new(command_pool))
+    }
+
+    // Waits until the resources of the next frame are not in use anymore and hands them out,
+    // e.g. to record work submitted together with the frame. Calling it is optional,
+    // rendering the frame waits for them anyway.
+    pub fn begin_frame(&mut self) -> RenderingResult<FrameContext<'_>> {
+        if !self.is_device_lost {
+            let result = self.wait_for_current_frame_to_complete();
+            self.handle_device_lost(result)?;
+        }
+
+        Ok(FrameContext::new(
+            self.current_frame,
+            self.frame_number,
+            &mut self.frames[self.current_frame],
+            &mut self.destruction_queue))
+    }
+
+    // Once the device is lost, frames are silently dropped and `RendererEvent::DeviceLost`
+    // is reported instead of an error
+    pub fn render(&mut self, render_layers: &RenderLayers) -> RenderingResult<()> {
+        let extent = self.swapchain.extent();
+        let viewport = ViewportLayers {
+            full_area: viewport::full_area(extent),
+            letterboxed_area: self.viewport_area.area(extent),
+            render_layers
+        };
+
+        self.render_viewports(&[viewport])
+    }
+
+    // Split screen rendering into the same command buffer. Every layer is drawn into all
+    // viewports before the custom passes placed after it, so that e.g. a UI pass after
+    // the world layers draws over all of them.
+    pub fn render_split(&mut self, viewports: &[SplitViewport]) -> RenderingResult<()> {
+        let extent = self.swapchain.extent();
+        let viewports: Vec<ViewportLayers> = viewports
+            .iter()
+            .map(|viewport| viewport.layers(extent))
+            .collect();
+
+        self.render_viewports(&viewports)
+    }
+
+    // Frames are also skipped while the window has no framebuffer, e.g. when minimized,
+    // as the swapchain's images could not be presented anyway
+    fn render_viewports(&mut self, viewports: &[ViewportLayers]) -> RenderingResult<()> {
+        if self.is_device_lost || !self.needs_redraw() || self.is_surface_empty()? {
+            return Ok(());
+        }
+
+        let contents = match self.frame_contents(viewports) {
+            Some(contents) => contents,
+            None => return Ok(())
+        };
+
+        let result = self.render_frame(viewports, contents);
+        self.handle_device_lost(result)
     }
 
-    pub fn render(&mut self, render_states: &[&dyn RenderStateTrait]) -> RenderingResult<()> {
+    // None if the frame is skipped
+    fn frame_contents(&self, viewports: &[ViewportLayers]) -> Option<FrameContents> {
+        let is_empty = viewports.iter().all(|viewport| viewport.render_layers.is_empty());
+        if !is_empty {
+            return Some(FrameContents::Everything);
+        }
+
+        match self.empty_frame_mode {
+            EmptyFrameMode::Render => Some(FrameContents::Everything),
+            EmptyFrameMode::Clear => Some(FrameContents::Clear),
+            EmptyFrameMode::Skip => None,
+            EmptyFrameMode::PresentPrevious if self.previous_frame.is_stored() => {
+                Some(FrameContents::PreviousFrame)
+            },
+            // Nothing was stored yet, e.g. right after switching modes
+            EmptyFrameMode::PresentPrevious => Some(FrameContents::Everything)
+        }
+    }
+
+    fn render_frame(
+        &mut self,
+        viewports: &[ViewportLayers],
+        contents: FrameContents
+    ) -> RenderingResult<()> {
+        self.resolve_finished_readbacks()?;
+        if let Some(debug_overlay) = &mut self.debug_overlay {
+            debug_overlay.begin_frame();
+            debug_overlay.update_memory(&self.physical_device, &self.allocator)?;
+        }
+
         self.wait_for_current_frame_to_complete()?;
-        let image_index = self.acquire_next_image()?;
-        self.rerecord_command_buffer(image_index, render_states)?;
-        self.submit_for_rendering()?;
-        self.submit_for_presentation(image_index)?;
+        if self.empty_frame_mode == EmptyFrameMode::PresentPrevious {
+            self.previous_frame.prepare(&self.swapchain)?;
+        }
+
+        let (image_index, is_suboptimal) = match self.acquire_next_image()? {
+            Some(acquired) => acquired,
+            // Nothing was acquired, the frame is rendered with the new swapchain next time
+            None => return self.recreate_swapchain()
+        };
+
+        self.wait_for_image_to_be_released(image_index)?;
+        self.read_back_gpu_timestamps(image_index)?;
+        if contents == FrameContents::Everything {
+            self.record_command_buffer_if_needed(image_index, viewports)?;
+        }
+        else {
+            self.record_empty_frame(image_index, contents)?;
+        }
+
+        self.submit_for_rendering(image_index, contents)?;
+        self.count_draw_calls(image_index, contents);
+        if contents == FrameContents::Everything
+            && self.empty_frame_mode == EmptyFrameMode::PresentPrevious {
+            self.previous_frame.mark_stored();
+        }
+
+        let is_out_of_date = self.submit_for_presentation(image_index)?;
+        self.schedule_readbacks();
         self.advance_frame();
+        profiling::frame_mark();
+        self.is_redraw_requested = false;
+
+        if is_suboptimal || is_out_of_date {
+            self.recreate_swapchain()?;
+        }
+
+        Ok(())
+    }
+
+    fn is_surface_empty(&self) -> RenderingResult<bool> {
+        let extent = self.surface.framebuffer_extent()?;
+        Ok(extent.width == 0 || extent.height == 0)
+    }
+
+    fn handle_device_lost(&mut self, result: RenderingResult<()>) -> RenderingResult<()> {
+        match result {
+            Err(error) if error.is_device_lost() => {
+                error!("{}, the renderer has to be rebuilt", error);
+                self.is_device_lost = true;
+                self.events.push(RendererEvent::DeviceLost);
+                Ok(())
+            },
+            result => result
+        }
+    }
+
+    // Recreates the swapchain, e.g. after the window was resized, together with its
+    // framebuffers and the command buffers recorded for its images, which happens on its own
+    // when acquiring or presenting reports it as out of date or suboptimal. Everything else
+    // is kept, but whatever was built for the old swapchain, e.g. framebuffers of custom passes
+    // sized after it, keeps its old extent and has to be recreated by its owner.
+    // Does nothing while the window has no framebuffer or after the device was lost.
+    pub fn recreate_swapchain(&mut self) -> RenderingResult<()> {
+        if self.is_device_lost || self.is_surface_empty()? {
+            return Ok(());
+        }
+
+        info!("recreating swapchain");
+        unsafe {
+            self.logical_device.device_wait_idle()
+        }.map_err(|result| RenderingError::DeviceWaitIdleError {result})?;
+
+        // Captured frames are read back with the extent of the swapchain they were copied from
+        for frame in 0..Self::FRAMES_IN_FLIGHT {
+            if self.readbacks.has_pending(frame) {
+                self.resolve_readbacks(frame)?;
+            }
+        }
+
+        // Recorded with the old framebuffers
+        self.command_buffer_cache = None;
+        self.swapchain = Self::create_swapchain(
+            &self.physical_device,
+            &self.logical_device,
+            &self.surface,
+            self.gamma_correction,
+            Some(&self.swapchain))?;
+        self.framebuffers = Self::create_framebuffers(
+            &self.logical_device, &self.swapchain, &self.render_pass)?;
+        self.images_in_flight = vec![None; self.swapchain.image_count()];
+        self.gpu_timestamps = GpuTimestamps::new(
+            &self.physical_device, &self.logical_device, self.swapchain.image_count())?;
+
+        if let Some(target) = &self.scaled_target {
+            let (scale, upscaling) = (target.scale(), target.upscaling().clone());
+            self.set_render_scale(scale, upscaling)?;
+        }
+
+        if self.debug_overlay.is_some() {
+            let content_scale = self.surface.content_scale()?;
+            self.debug_overlay = Some(
+                DebugOverlay::new(&self.logical_device, &self.swapchain, content_scale)?);
+        }
+
+        if self.frame_capture.is_requested() {
+            self.frame_capture.request(&self.swapchain)?;
+        }
+
+        self.request_redraw();
+
+        Ok(())
+    }
+
+    // Recreates the logical device and everything depending on it, keeping the instance,
+    // surface and physical device, as well as the recording, empty frame and redraw modes,
+    // viewport area, clear color, gamma correction setting and render scale. Render scales
+    // upscaled by shaders are reset to 1.0, as the shaders were built for the old device.
+    // Everything created from the old renderer, e.g. render states, materials, textures
+    // and custom passes, keeps the lost device and its swapchain alive, so it has to be
+    // dropped beforehand and created anew afterwards.
+    pub fn rebuild(self) -> RenderingResult<Renderer> {
+        info!("rebuilding renderer");
+        let vulkan_state = Rc::clone(&self.vulkan_state);
+        let surface = Rc::clone(&self.surface);
+        let physical_device = Rc::clone(&self.physical_device);
+        let recording_mode = self.recording_mode;
+        let empty_frame_mode = self.empty_frame_mode;
+        let redraw_mode = self.redraw_mode;
+        let viewport_area = self.viewport_area;
+        let clear_color = self.clear_color;
+        let is_debug_overlay_enabled = self.debug_overlay.is_some();
+        let gamma_correction = self.gamma_correction;
+        let render_scale = self.scaled_target
+            .as_ref()
+            .and_then(|target| match target.upscaling() {
+                Upscaling::Shader(_) => None,
+                upscaling => Some((target.scale(), upscaling.clone()))
+            });
+
+        // A surface cannot have two swapchains at once
+        drop(self);
+
+        let mut renderer = diagnose("renderer rebuild", Some(&vulkan_state), || {
+            Self::with_physical_device(
+                Rc::clone(&vulkan_state),
+                surface,
+                physical_device,
+                gamma_correction)
+        })?;
+        renderer.recording_mode = recording_mode;
+        renderer.empty_frame_mode = empty_frame_mode;
+        renderer.redraw_mode = redraw_mode;
+        renderer.viewport_area = viewport_area;
+        renderer.clear_color = clear_color;
+        renderer.set_debug_overlay(is_debug_overlay_enabled)?;
+        if let Some((scale, upscaling)) = render_scale {
+            renderer.set_render_scale(scale, upscaling)?;
+        }
+
+        Ok(renderer)
+    }
+
+    pub fn gamma_correction(&self) -> GammaCorrection {
+        self.gamma_correction
+    }
+
+    // With `GammaPath::GammaPass` colors are only encoded if a `GammaPass` is registered
+    pub fn gamma_path(&self) -> GammaPath {
+        GammaPath::choose(self.gamma_correction, self.swapchain.image_format())
+    }
+
+    pub fn is_device_lost(&self) -> bool {
+        self.is_device_lost
+    }
+
+    // Events which occurred since the last call, meant to be checked every frame
+    pub fn take_events(&mut self) -> Vec<RendererEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    // The scene's draw list is built in an arena reused by every frame.
+    // It is moved out for the duration of the frame, so that it can be borrowed
+    // alongside the renderer.
+    pub fn render_scene(&mut self, scene: &Scene) -> RenderingResult<()> {
+        let mut frame_arena = std::mem::take(&mut self.frame_arena);
+        frame_arena.reset();
+
+        let result = self.render(&scene.render_layers_in(&frame_arena));
+        self.frame_arena = frame_arena;
+
+        result
+    }
+
+    // Split screen counterpart of `render_scene` with a scene per viewport,
+    // e.g. built from the render states and camera position of each player
+    pub fn render_split_scenes(&mut self, scenes: &[(vk::Rect2D, &Scene)]) -> RenderingResult<()> {
+        let mut frame_arena = std::mem::take(&mut self.frame_arena);
+        frame_arena.reset();
+
+        let result = {
+            let render_layers: Vec<RenderLayers> = scenes
+                .iter()
+                .map(|(_, scene)| scene.render_layers_in(&frame_arena))
+                .collect();
+
+            let viewports: Vec<SplitViewport> = scenes
+                .iter()
+                .zip(render_layers.iter())
+                .map(|((area, _), render_layers)| SplitViewport {
+                    area: *area,
+                    render_layers
+                })
+                .collect();
+
+            self.render_split(&viewports)
+        };
+        self.frame_arena = frame_arena;
+
+        result
+    }
+
+    // Only picks up readbacks of frames which have already finished, without waiting for any
+    fn resolve_finished_readbacks(&mut self) -> RenderingResult<()> {
+        for frame in 0..Self::FRAMES_IN_FLIGHT {
+            if self.readbacks.has_pending(frame) &&
+                self.frames[frame].image_rendered_fence.status()? == FenceStatus::Ready {
+                self.resolve_readbacks(frame)?;
+            }
+        }
 
         Ok(())
     }
 
-    fn wait_for_current_frame_to_complete(&self) -> RenderingResult<()> {
-        self.image_rendered_fences[self.current_frame].wait(
-            std::time::Duration::from_nanos(u64::max_value()))?;
-        self.image_rendered_fences[self.current_frame].reset()?;
+    fn resolve_readbacks(&mut self, frame: usize) -> RenderingResult<()> {
+        for readback in self.readbacks.take_finished(frame) {
+            match readback {
+                Readback::CapturedFrame => self.frame_capture.read_back(&self.swapchain)?,
+                Readback::SequenceFrame => self.sequence_capture.read_back(frame)?
+            }
+        }
 
         Ok(())
     }
 
-    fn acquire_next_image(&self) -> RenderingResult<usize> {
+    fn wait_for_current_frame_to_complete(&mut self) -> RenderingResult<()> {
+        if self.is_current_frame_ready {
+            return Ok(());
+        }
+
+        let _scope = profiling::scope("wait for frame");
+        self.frames[self.current_frame].image_rendered_fence.wait(
+            std::time::Duration::from_nanos(u64::MAX))?;
+        // Readbacks of this frame have to be collected before its fence is reset
+        self.resolve_readbacks(self.current_frame)?;
+        self.frames[self.current_frame].image_rendered_fence.reset()?;
+
+        // The fence belonged to the frame submitted `FRAMES_IN_FLIGHT` frames ago
+        if let Some(finished_frame_number) =
+            self.frame_number.checked_sub(Self::FRAMES_IN_FLIGHT as u64) {
+            self.destruction_queue.release_finished(finished_frame_number);
+        }
+
+        self.frames[self.current_frame].recycle()?;
+        self.is_current_frame_ready = true;
+
+        Ok(())
+    }
+
+    // The index of the acquired image and whether the swapchain is suboptimal, in which case
+    // the image is still rendered to and presented. None if the swapchain is out of date,
+    // e.g. after the window was resized, and no image was acquired.
+    fn acquire_next_image(&self) -> RenderingResult<Option<(usize, bool)>> {
+        let _scope = profiling::scope("acquire image");
         let swapchain_loader = self.logical_device.swapchain_loader();
-        let image_index = unsafe {
+        let result = unsafe {
             swapchain_loader.acquire_next_image(
                 self.swapchain.handle(),
-                u64::max_value(),
-                self.image_acquired_semaphores[self.current_frame].handle(),
+                u64::MAX,
+                self.frames[self.current_frame].image_acquired_semaphore.handle(),
                 vk::Fence::null())
-        }.map_err(|result| RenderingError::AcquireImageError {result})?.0;
+        };
 
-        Ok(image_index as usize)
+        match result {
+            Ok((image_index, is_suboptimal)) => Ok(Some((image_index as usize, is_suboptimal))),
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => Ok(None),
+            Err(result) => Err(RenderingError::AcquireImageError {result})
+        }
+    }
+
+    // A cached command buffer of an image cannot be rerecorded or resubmitted
+    // while a previous frame rendering to that image is still in flight
+    fn wait_for_image_to_be_released(&mut self, image_index: usize) -> RenderingResult<()> {
+        if let Some(frame) = self.images_in_flight[image_index] {
+            if frame != self.current_frame {
+                self.frames[frame].image_rendered_fence.wait(
+                    std::time::Duration::from_nanos(u64::MAX))?;
+            }
+        }
+
+        self.images_in_flight[image_index] = Some(self.current_frame);
+
+        Ok(())
+    }
+
+    fn record_command_buffer_if_needed(
+        &mut self,
+        image_index: usize,
+        viewports: &[ViewportLayers]
+    ) -> RenderingResult<()> {
+        let _scope = profiling::scope("record");
+
+        // Frames of a sequence are copied to the buffer of their frame in flight,
+        // which the cached command buffer of an image cannot follow,
+        // and the debug overlay changes every frame
+        let is_rerecorded_every_frame = self.recording_mode == RecordingMode::EveryFrame
+            || self.sequence_capture.is_active()
+            || self.debug_overlay.is_some();
+
+        if is_rerecorded_every_frame {
+            return self.rerecord_command_buffer(image_index, viewports);
+        }
+
+        let captures_frame = self.frame_capture.is_requested();
+        self.command_buffer_cache()?;
+        let is_up_to_date = self.command_buffer_cache.as_ref().unwrap().is_up_to_date(
+            image_index, viewports, &self.custom_passes, captures_frame);
+
+        if is_up_to_date {
+            return Ok(());
+        }
+
+        self.rerecord_command_buffer(image_index, viewports)?;
+        let signature = RecordingSignature::new(viewports, &self.custom_passes, captures_frame);
+        self.command_buffer_cache()?.set_recorded(image_index, signature);
+
+        Ok(())
+    }
+
+    fn command_buffer_cache(&mut self) -> RenderingResult<&mut CommandBufferCache> {
+        if self.command_buffer_cache.is_none() {
+            let command_buffer_cache = CommandBufferCache::new(
+                &self.physical_device,
+                &self.logical_device,
+                self.swapchain.image_count())?;
+
+            self.command_buffer_cache = Some(command_buffer_cache);
+        }
+
+        Ok(self.command_buffer_cache.as_mut().unwrap())
     }
 
     fn rerecord_command_buffer(
         &mut self,
         image_index: usize,
-        render_states: &[&dyn RenderStateTrait] 
+        viewports: &[ViewportLayers]
     ) -> RenderingResult<()> {
-        let mut recorder = self.command_buffers[self.current_frame].record()?
-            .begin_render_pass(&self.render_pass, &self.framebuffers, image_index);
+        let command_buffer = match (self.recording_mode, &mut self.command_buffer_cache) {
+            (RecordingMode::Cached, Some(cache)) => cache.command_buffer_mut(image_index),
+            _ => &mut self.frames[self.current_frame].command_buffer
+        };
 
-        for render_state in render_states {
-            recorder = Self::record_render_state_to_buffer(*render_state, recorder);
+        let full_area = viewport::full_area(self.swapchain.extent());
+        let letterboxed_area = self.viewport_area.area(self.swapchain.extent());
+
+        let context = CustomPassContext {
+            swapchain: &self.swapchain,
+            image_index,
+            swapchain_image: self.swapchain.images()[image_index],
+            full_area,
+            letterboxed_area,
+            frame_number: self.frame_number
+        };
+
+        // Passes recorded among the scaled layers draw into the scaled image
+        let scaled_context = match &self.scaled_target {
+            Some(target) => Some(CustomPassContext {
+                swapchain: &self.swapchain,
+                image_index,
+                swapchain_image: self.swapchain.images()[image_index],
+                full_area: viewport::full_area(target.extent()),
+                letterboxed_area: target.scaled_area(letterboxed_area),
+                frame_number: self.frame_number
+            }),
+            None => None
+        };
+
+        let mut bound_pipeline = None;
+        let mut recorder = Self::write_gpu_timestamp(
+            &self.gpu_timestamps,
+            command_buffer.record()?,
+            image_index,
+            GpuTimestamp::FrameBegin);
+        recorder = Self::record_custom_passes(
+            &self.custom_passes,
+            CustomPassPoint::BeforeMainPass,
+            recorder,
+            &context,
+            &mut bound_pipeline);
+        recorder = Self::write_gpu_timestamp(
+            &self.gpu_timestamps, recorder, image_index, GpuTimestamp::MainPassBegin);
+
+        let clear_values = Self::clear_values(self.clear_color);
+        // Taken once the scaled layers are upscaled
+        let mut scaled_target = self.scaled_target.as_ref();
+        recorder = match scaled_target {
+            Some(target) => target.begin_render_pass(recorder, &clear_values),
+            None => recorder.begin_render_pass_with_clear_values(
+                &self.render_pass,
+                &self.framebuffers,
+                image_index,
+                &clear_values)
+        };
+
+        recorder = Self::record_custom_passes(
+            &self.custom_passes,
+            CustomPassPoint::MainPassBegin,
+            recorder,
+            scaled_context.as_ref().unwrap_or(&context),
+            &mut bound_pipeline);
+
+        let mut layer_runs: Vec<_> = viewports
+            .iter()
+            .map(|viewport| viewport.render_layers.iter_layers().peekable())
+            .collect();
+
+        for (index, layer) in RenderLayer::ALL.iter().enumerate() {
+            // The letterboxed layers are recorded before all others,
+            // so the scaled image is complete by the first layer which is not
+            if !layer.is_letterboxed() {
+                if let Some(target) = scaled_target.take() {
+                    recorder = target.record_upscale(
+                        recorder,
+                        &self.render_pass,
+                        &self.framebuffers,
+                        &self.swapchain,
+                        image_index,
+                        &clear_values);
+                    bound_pipeline = None;
+                }
+            }
+
+            // Custom passes go after all layers recorded together
+            let is_last_recorded_together = RenderLayer::ALL
+                .get(index + 1)
+                .map_or(true, |next| next.recording_order() != layer.recording_order());
+
+            if !is_last_recorded_together {
+                continue;
+            }
+
+            for (viewport, layer_runs) in viewports.iter().zip(layer_runs.iter_mut()) {
+                while let Some((run_layer, render_states)) = layer_runs.next_if(|(run_layer, _)| {
+                    run_layer.recording_order() <= layer.recording_order()
+                }) {
+                    let area = if run_layer.is_letterboxed() {
+                        viewport.letterboxed_area
+                    }
+                    else {
+                        viewport.full_area
+                    };
+
+                    let area = match scaled_target {
+                        Some(target) => target.scaled_area(area),
+                        None => area
+                    };
+
+                    recorder = recorder.set_viewport(area);
+
+                    for render_state in render_states {
+                        recorder = Self::record_render_state_to_buffer(
+                            *render_state, recorder, &mut bound_pipeline);
+                    }
+                }
+            }
+
+            let layer_context = match (scaled_target, &scaled_context) {
+                (Some(_), Some(scaled_context)) => scaled_context,
+                _ => &context
+            };
+
+            recorder = Self::record_custom_passes(
+                &self.custom_passes,
+                CustomPassPoint::AfterLayer(*layer),
+                recorder,
+                layer_context,
+                &mut bound_pipeline);
         }
 
-        recorder
-            .end_render_pass()
-            .end_recording()?;
+        recorder = recorder.end_render_pass();
+        recorder = Self::write_gpu_timestamp(
+            &self.gpu_timestamps, recorder, image_index, GpuTimestamp::MainPassEnd);
+        recorder = Self::record_custom_passes(
+            &self.custom_passes,
+            CustomPassPoint::AfterMainPass,
+            recorder,
+            &context,
+            &mut bound_pipeline);
+        recorder = Self::record_debug_overlay(
+            &self.debug_overlay, recorder, image_index, self.swapchain.extent());
+        if self.empty_frame_mode == EmptyFrameMode::PresentPrevious {
+            recorder = self.previous_frame.record_store(recorder, &self.swapchain, image_index);
+        }
+
+        recorder = self.frame_capture.record_copy(
+            recorder, &self.swapchain, image_index, self.current_frame);
+        recorder = self.sequence_capture.record_copy(
+            recorder, &self.swapchain, image_index, self.current_frame);
+        recorder = Self::write_gpu_timestamp(
+            &self.gpu_timestamps, recorder, image_index, GpuTimestamp::FrameEnd);
+
+        recorder.end_recording()?;
+
+        Ok(())
+    }
+
+    // Frames only clearing the image or presenting the previous one are recorded every time,
+    // they never replace what is cached for full frames
+    fn record_empty_frame(
+        &mut self,
+        image_index: usize,
+        contents: FrameContents
+    ) -> RenderingResult<()> {
+        let _scope = profiling::scope("record");
+        let command_buffer = &mut self.frames[self.current_frame].command_buffer;
+
+        let mut recorder = Self::write_gpu_timestamp(
+            &self.gpu_timestamps,
+            command_buffer.record()?,
+            image_index,
+            GpuTimestamp::FrameBegin);
+        recorder = Self::write_gpu_timestamp(
+            &self.gpu_timestamps, recorder, image_index, GpuTimestamp::MainPassBegin);
+
+        recorder = if contents == FrameContents::PreviousFrame {
+            self.previous_frame.record_present(recorder, &self.swapchain, image_index)
+        }
+        else {
+            recorder
+                .begin_render_pass_with_clear_values(
+                    &self.render_pass,
+                    &self.framebuffers,
+                    image_index,
+                    &Self::clear_values(self.clear_color))
+                .end_render_pass()
+        };
+
+        recorder = Self::write_gpu_timestamp(
+            &self.gpu_timestamps, recorder, image_index, GpuTimestamp::MainPassEnd);
+        recorder = Self::record_debug_overlay(
+            &self.debug_overlay, recorder, image_index, self.swapchain.extent());
+        recorder = self.frame_capture.record_copy(
+            recorder, &self.swapchain, image_index, self.current_frame);
+        recorder = self.sequence_capture.record_copy(
+            recorder, &self.swapchain, image_index, self.current_frame);
+        recorder = Self::write_gpu_timestamp(
+            &self.gpu_timestamps, recorder, image_index, GpuTimestamp::FrameEnd);
+
+        recorder.end_recording()?;
 
         Ok(())
     }
 
+    fn record_debug_overlay<'a>(
+        debug_overlay: &Option<DebugOverlay>,
+        recorder: CommandBufferRecorder<'a>,
+        image_index: usize,
+        extent: vk::Extent2D
+    ) -> CommandBufferRecorder<'a> {
+        match debug_overlay {
+            Some(debug_overlay) => debug_overlay.record(recorder, image_index, extent),
+            None => recorder
+        }
+    }
+
+    fn count_draw_calls(&mut self, image_index: usize, contents: FrameContents) {
+        if self.debug_overlay.is_none() {
+            return;
+        }
+
+        let transient_draw_calls = self.frames[self.current_frame]
+            .recorded_transient_command_buffer()
+            .map_or(0, |command_buffer| command_buffer.draw_call_count());
+        let draw_calls = self.current_command_buffer(image_index, contents).draw_call_count();
+
+        if let Some(debug_overlay) = &mut self.debug_overlay {
+            debug_overlay.set_draw_call_count(draw_calls + transient_draw_calls);
+        }
+    }
+
+    fn clear_values(clear_color: [f32; 4]) -> [vk::ClearValue; 1] {
+        [vk::ClearValue {
+            color: vk::ClearColorValue {
+                float32: clear_color
+            }
+        }]
+    }
+
+    fn write_gpu_timestamp<'a>(
+        gpu_timestamps: &Option<GpuTimestamps>,
+        recorder: CommandBufferRecorder<'a>,
+        image_index: usize,
+        timestamp: GpuTimestamp
+    ) -> CommandBufferRecorder<'a> {
+        match gpu_timestamps {
+            Some(gpu_timestamps) => gpu_timestamps.write(recorder, image_index, timestamp),
+            None => recorder
+        }
+    }
+
+    fn record_custom_passes<'a>(
+        custom_passes: &CustomPasses,
+        point: CustomPassPoint,
+        mut recorder: CommandBufferRecorder<'a>,
+        context: &CustomPassContext,
+        bound_pipeline: &mut Option<u64>
+    ) -> CommandBufferRecorder<'a> {
+        for custom_pass in custom_passes.at(point) {
+            recorder = custom_pass.record(recorder, context);
+            *bound_pipeline = None;
+        }
+
+        recorder
+    }
+
+    // Consecutive render states sharing a pipeline bind it only once
     fn record_render_state_to_buffer<'a>(
         render_state: &dyn RenderStateTrait,
-        mut recorder: CommandBufferRecorder<'a>
+        mut recorder: CommandBufferRecorder<'a>,
+        bound_pipeline: &mut Option<u64>
     ) -> CommandBufferRecorder<'a> {
-        recorder = recorder
-            .bind_pipeline(Rc::clone(render_state.pipeline()));
+        let pipeline = render_state.pipeline().raw_handle();
+        if *bound_pipeline != Some(pipeline) {
+            recorder = recorder
+                .bind_pipeline(Rc::clone(render_state.pipeline()));
+            *bound_pipeline = Some(pipeline);
+        }
 
-        for (stage, constants) in render_state.iterate_shaders() {
+        if let Some(descriptor_set) = render_state.descriptor_set() {
             recorder = recorder
-                .push_constant(render_state.pipeline(), stage, constants);
+                .bind_descriptor_set(render_state.pipeline(), 0, Rc::clone(descriptor_set));
+        }
+
+        for stage in ShaderStage::ALL.iter() {
+            if let Some(constants) = render_state.push_constants(*stage) {
+                recorder = recorder
+                    .push_constant(render_state.pipeline(), *stage, constants);
+            }
+        }
+
+        let index_buffer = render_state.mesh().and_then(|mesh| mesh.index_buffer());
+
+        if let Some(mesh) = render_state.mesh() {
+            recorder = recorder.bind_vertex_buffer(0, Rc::clone(mesh.vertex_buffer()));
         }
 
-        recorder.draw(3)
+        // Vertex count of render states with indexed meshes counts indices
+        match index_buffer {
+            Some(index_buffer) => recorder
+                .bind_index_buffer(Rc::clone(index_buffer))
+                .draw_indexed(render_state.vertex_count(), render_state.instance_count()),
+            None => recorder
+                .draw(render_state.vertex_count(), render_state.instance_count())
+        }
     }
 
-    fn submit_for_rendering(&self) -> RenderingResult<()> {
-        let graphics_queue = self.logical_device.device_queue(QueueFamily::Graphics)?;
-        let wait_semaphores = [self.image_acquired_semaphores[self.current_frame].handle()];
-        let wait_stages = [vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT];
-        let command_buffers = [self.command_buffers[self.current_frame].handle()];
-        let signal_semaphores = [self.image_rendered_semaphores[self.current_frame].handle()];
-        let submit_infos = [vk::SubmitInfo::builder()
-            .wait_semaphores(&wait_semaphores)
-            .wait_dst_stage_mask(&wait_stages)
-            .command_buffers(&command_buffers)
-            .signal_semaphores(&signal_semaphores)
-            .build()];
+    fn current_command_buffer(
+        &self,
+        image_index: usize,
+        contents: FrameContents
+    ) -> &CommandBuffer {
+        match (self.recording_mode, &self.command_buffer_cache, contents) {
+            (RecordingMode::Cached, Some(cache), FrameContents::Everything) => {
+                cache.command_buffer(image_index)
+            },
+            _ => &self.frames[self.current_frame].command_buffer
+        }
+    }
 
-        unsafe {
-            self.logical_device.queue_submit(
-                graphics_queue,
-                &submit_infos,
-                self.image_rendered_fences[self.current_frame].handle())
-        }.map_err(|result| RenderingError::RenderImageError {result})?;
+    // Submissions queued since the last frame go first, batched together with the frame's own
+    fn submit_for_rendering(
+        &mut self,
+        image_index: usize,
+        contents: FrameContents
+    ) -> RenderingResult<()> {
+        let _scope = profiling::scope("submit");
+        trace!("submitting frame {} for rendering", self.current_frame);
+        let frame = &self.frames[self.current_frame];
+        let mut frame_submission = Submission::<GraphicsQueue>::new().wait_handle(
+            frame.image_acquired_semaphore.handle(),
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT);
+
+        for (semaphore, stages) in std::mem::take(&mut self.frame_waits) {
+            frame_submission = frame_submission.wait(&semaphore, stages);
+        }
+
+        if let Some(command_buffer) = frame.recorded_transient_command_buffer() {
+            frame_submission = frame_submission.command_buffer_handle(command_buffer.handle());
+        }
+
+        let frame_submission = frame_submission
+            .command_buffer_handle(self.current_command_buffer(image_index, contents).handle())
+            .signal_handle(frame.image_rendered_semaphore.handle());
+        self.submissions.push(frame_submission);
+
+        let submitted = self.submissions.flush(
+            &self.logical_device,
+            self.frames[self.current_frame].image_rendered_fence.handle())?;
+        self.destruction_queue.push(self.frame_number, submitted);
+
+        if let Some(gpu_timestamps) = &mut self.gpu_timestamps {
+            gpu_timestamps.mark_submitted(image_index);
+        }
 
         Ok(())
     }
 
-    fn submit_for_presentation(&self, image_index: usize) -> RenderingResult<()> {
+    // Queues work, e.g. compute, transfer or offscreen passes recorded into own command buffers,
+    // to be submitted right before the next rendered frame, with as few submit calls as possible.
+    // Submissions to queues other than the graphics one have to signal a semaphore passed
+    // to `wait_before_rendering`, so that the frame's fence also waits for them
+    // and everything they use is kept alive until they finish.
+    pub fn submit<Q: QueueType>(&mut self, submission: Submission<Q>) {
+        self.submissions.push(submission);
+    }
+
+    // Makes the given stages of the next frame wait for the semaphore
+    pub fn wait_before_rendering(
+        &mut self,
+        semaphore: Rc<Semaphore>,
+        stages: vk::PipelineStageFlags
+    ) {
+        self.frame_waits.push((semaphore, stages));
+    }
+
+    // Returns whether the swapchain is out of date or suboptimal and has to be recreated.
+    // Rendering waits for the frame's semaphore either way.
+    fn submit_for_presentation(&self, image_index: usize) -> RenderingResult<bool> {
+        let _scope = profiling::scope("present");
+        trace!("presenting swapchain image {}", image_index);
         let presentation_queue = self.logical_device.device_queue(QueueFamily::Presentation)?;
-        let wait_semaphores = [self.image_rendered_semaphores[self.current_frame].handle()];
+        let wait_semaphores = [self.frames[self.current_frame].image_rendered_semaphore.handle()];
         let swapchains = [self.swapchain.handle()];
         let image_indices = [image_index as u32];
         let present_info = vk::PresentInfoKHR::builder()
@@ -318,14 +1360,341 @@ impl Renderer {
 
         let swapchain_loader = self.logical_device.swapchain_loader();
 
-        unsafe {
+        let result = unsafe {
             swapchain_loader.queue_present(presentation_queue, &present_info)
-        }.map_err(|result| RenderingError::PresentImageError {result})?;
+        };
+
+        match result {
+            Ok(is_suboptimal) => Ok(is_suboptimal),
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => Ok(true),
+            Err(result) => Err(RenderingError::PresentImageError {result})
+        }
+    }
+
+    fn read_back_gpu_timestamps(&mut self, image_index: usize) -> RenderingResult<()> {
+        match &mut self.gpu_timestamps {
+            Some(gpu_timestamps) => gpu_timestamps.read_back(image_index),
+            None => Ok(())
+        }
+    }
+
+    fn schedule_readbacks(&mut self) {
+        if self.frame_capture.is_recorded_in_frame(self.current_frame) {
+            self.readbacks.push(self.current_frame, Readback::CapturedFrame);
+        }
+
+        if self.sequence_capture.is_recorded_in_frame(self.current_frame) {
+            self.readbacks.push(self.current_frame, Readback::SequenceFrame);
+        }
+    }
+
+    // Records commands into a temporary command buffer, submits it to the graphics queue
+    // and blocks until it finishes, meant for one-off work such as texture uploads
+    pub fn execute_immediately<F>(&self, record: F) -> RenderingResult<()> where
+        F: FnOnce(CommandBufferRecorder) -> CommandBufferRecorder {
+        let mut command_buffer = self.command_pool.allocate_command_buffers(1)?.remove(0);
+        record(command_buffer.record()?).end_recording()?;
+
+        let fence = self.sync_objects.acquire_fence()?;
+        let graphics_queue = self.logical_device.device_queue(QueueFamily::Graphics)?;
+        let command_buffers = [command_buffer.handle()];
+        let submit_infos = [vk::SubmitInfo::builder()
+            .command_buffers(&command_buffers)
+            .build()];
+
+        unsafe {
+            self.logical_device.queue_submit(graphics_queue, &submit_infos, fence.handle())
+        }.map_err(|result| RenderingError::ImmediateSubmitError {result})?;
+
+        fence.wait(std::time::Duration::from_nanos(u64::MAX))?;
+        self.sync_objects.release_fence(fence)?;
+        // Freed back to the pool only once the device is done with it
+        drop(command_buffer);
+
         Ok(())
     }
 
     fn advance_frame(&mut self) {
         self.current_frame = (self.current_frame + 1) % Self::FRAMES_IN_FLIGHT;
+        self.is_current_frame_ready = false;
+        self.frame_number += 1;
+    }
+
+    // Drops the resource, e.g. a buffer, texture or render state, once no frame in flight
+    // can use it anymore, instead of waiting for the device to become idle.
+    // Everything still queued is dropped together with the renderer.
+    pub fn destroy_later<T: 'static>(&mut self, resource: T) {
+        self.destruction_queue.push(self.frame_number, resource);
+    }
+
+    // Number of the frame which is being or is about to be rendered
+    pub(crate) fn frame_number(&self) -> u64 {
+        self.frame_number
+    }
+
+    // Passes are recorded at the given point after the passes named in `after`,
+    // which may also name passes that are registered later or never
+    pub fn register_custom_pass(
+        &mut self,
+        name: &str,
+        point: CustomPassPoint,
+        after: &[&str],
+        custom_pass: Rc<dyn CustomPass>
+    ) -> RenderingResult<()> {
+        self.request_redraw();
+        self.custom_passes.register(name, point, after, custom_pass)
+    }
+
+    pub fn unregister_custom_pass(&mut self, name: &str) -> Option<Rc<dyn CustomPass>> {
+        self.request_redraw();
+        self.custom_passes.unregister(name)
+    }
+
+    pub fn recording_mode(&self) -> RecordingMode {
+        self.recording_mode
+    }
+
+    pub fn set_recording_mode(&mut self, recording_mode: RecordingMode) {
+        self.recording_mode = recording_mode;
+    }
+
+    // `EmptyFrameMode::PresentPrevious` needs swapchain images which can be copied
+    // in both directions
+    pub fn set_empty_frame_mode(
+        &mut self,
+        empty_frame_mode: EmptyFrameMode
+    ) -> RenderingResult<()> {
+        let is_supported = empty_frame_mode != EmptyFrameMode::PresentPrevious
+            || PreviousFrame::is_supported(&self.swapchain);
+
+        if !is_supported {
+            return Err(RenderingError::PresentPreviousNotSupported);
+        }
+
+        if empty_frame_mode != self.empty_frame_mode {
+            self.empty_frame_mode = empty_frame_mode;
+            self.previous_frame.discard();
+            self.request_redraw();
+
+            // Cached frames store their copies only when recorded in the right mode
+            if let Some(cache) = &mut self.command_buffer_cache {
+                cache.invalidate();
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn empty_frame_mode(&self) -> EmptyFrameMode {
+        self.empty_frame_mode
+    }
+
+    pub fn redraw_mode(&self) -> RedrawMode {
+        self.redraw_mode
+    }
+
+    pub fn set_redraw_mode(&mut self, redraw_mode: RedrawMode) {
+        self.redraw_mode = redraw_mode;
+    }
+
+    // Makes the next call to `render` render a frame with `RedrawMode::OnDemand`,
+    // e.g. after the rendered states changed
+    pub fn request_redraw(&mut self) {
+        self.is_redraw_requested = true;
+    }
+
+    // Always true with `RedrawMode::Continuous`
+    pub fn needs_redraw(&self) -> bool {
+        self.redraw_mode == RedrawMode::Continuous || self.is_redraw_requested
+    }
+
+    // Confines the 3D layers to the given width to height ratio, leaving bars of the clear
+    // color around them, which can be drawn over by the UI layer
+    pub fn set_aspect_ratio_lock(&mut self, aspect_ratio: Option<f32>) {
+        self.set_viewport_area(match aspect_ratio {
+            Some(aspect_ratio) => ViewportArea::AspectRatio(aspect_ratio),
+            None => ViewportArea::Full
+        });
+    }
+
+    pub fn aspect_ratio_lock(&self) -> Option<f32> {
+        match self.viewport_area {
+            ViewportArea::AspectRatio(aspect_ratio) => Some(aspect_ratio),
+            _ => None
+        }
+    }
+
+    // Generalizes the aspect ratio lock to integer scaled resolutions and fixed regions,
+    // the viewport and scissor of the letterboxed layers follow it every frame
+    pub fn set_viewport_area(&mut self, viewport_area: ViewportArea) {
+        self.viewport_area = viewport_area;
+        self.request_redraw();
+    }
+
+    pub fn viewport_area(&self) -> ViewportArea {
+        self.viewport_area
+    }
+
+    // Renders the letterboxed layers, e.g. the 3D world, at `scale` times the swapchain's
+    // resolution and upscales them before the other layers are drawn over them at full
+    // resolution, trading sharpness for rendering cost. A scale of 1.0 renders them
+    // directly into the swapchain image again.
+    pub fn set_render_scale(&mut self, scale: f32, upscaling: Upscaling) -> RenderingResult<()> {
+        if !scale.is_finite() || scale <= 0.0 {
+            return Err(RenderingError::InvalidRenderScale {scale});
+        }
+
+        let scaled_target = if scale == 1.0 {
+            None
+        }
+        else {
+            Some(ScaledTarget::new(self, scale, upscaling)?)
+        };
+
+        if let Some(previous) = std::mem::replace(&mut self.scaled_target, scaled_target) {
+            // Frames in flight may still be rendering into it
+            self.destroy_later(previous);
+        }
+
+        self.request_redraw();
+        if let Some(cache) = &mut self.command_buffer_cache {
+            cache.invalidate();
+        }
+
+        Ok(())
+    }
+
+    pub fn render_scale(&self) -> f32 {
+        self.scaled_target.as_ref().map_or(1.0, ScaledTarget::scale)
+    }
+
+    // Resolution the letterboxed layers are rendered at, e.g. to size their own
+    // offscreen targets
+    pub fn scaled_extent(&self) -> vk::Extent2D {
+        self.scaled_target
+            .as_ref()
+            .map_or(self.swapchain.extent(), ScaledTarget::extent)
+    }
+
+    // RGBA color the swapchain image is cleared to at the beginning of the main pass
+    pub fn set_clear_color(&mut self, clear_color: [f32; 4]) {
+        if clear_color == self.clear_color {
+            return;
+        }
+
+        self.clear_color = clear_color;
+        self.request_redraw();
+
+        if let Some(cache) = &mut self.command_buffer_cache {
+            cache.invalidate();
+        }
+    }
+
+    pub fn clear_color(&self) -> [f32; 4] {
+        self.clear_color
+    }
+
+    pub fn letterboxed_area(&self) -> vk::Rect2D {
+        self.viewport_area.area(self.swapchain.extent())
+    }
+
+    // Everything the renderer measures, e.g. the swapchain extent, viewports and scissors,
+    // is in framebuffer pixels. Sizes meant in logical pixels, e.g. of UI layouts,
+    // have to be multiplied by the window's content scale.
+    pub fn content_scale(&self) -> RenderingResult<(f32, f32)> {
+        Ok(self.surface.content_scale()?)
+    }
+
+    // Budgets and usages of all memory heaps, see `MemoryStats`
+    pub fn memory_stats(&self) -> RenderingResult<MemoryStats> {
+        MemoryStats::query(&self.physical_device, &self.allocator)
+    }
+
+    pub fn capture_next_frame(&mut self) -> RenderingResult<()> {
+        self.request_redraw();
+        self.frame_capture.request(&self.swapchain)
+    }
+
+    pub fn take_captured_frame(&mut self) -> Option<RgbaImage> {
+        self.frame_capture.take()
+    }
+
+    // Shows FPS, a frame time graph, draw calls and GPU memory usage in the top left corner,
+    // without needing any shaders or fonts, see `DebugOverlay`
+    pub fn set_debug_overlay(&mut self, enabled: bool) -> RenderingResult<()> {
+        if enabled == self.debug_overlay.is_some() {
+            return Ok(());
+        }
+
+        self.debug_overlay = if enabled {
+            let content_scale = self.surface.content_scale()?;
+            Some(DebugOverlay::new(&self.logical_device, &self.swapchain, content_scale)?)
+        }
+        else {
+            None
+        };
+
+        self.request_redraw();
+
+        Ok(())
+    }
+
+    pub fn is_debug_overlay_enabled(&self) -> bool {
+        self.debug_overlay.is_some()
+    }
+
+    // Captures every presented frame until `stop_sequence_capture`, e.g. to record gameplay
+    // or deterministic footage of tests, see `SequenceCapture`
+    pub fn start_sequence_capture(&mut self, output: SequenceOutput) -> RenderingResult<()> {
+        self.sequence_capture.start(&self.swapchain, output)
+    }
+
+    // Waits for the frames in flight, so that the output ends with the last presented frame,
+    // and for the output to be written
+    pub fn stop_sequence_capture(&mut self) -> RenderingResult<()> {
+        if !self.sequence_capture.is_active() {
+            return Ok(());
+        }
+
+        unsafe {
+            self.logical_device.device_wait_idle()
+        }.map_err(|result| RenderingError::DeviceWaitIdleError {result})?;
+
+        for frame in 0..Self::FRAMES_IN_FLIGHT {
+            if self.readbacks.has_pending(frame) {
+                self.resolve_readbacks(frame)?;
+            }
+        }
+
+        self.sequence_capture.stop()
+    }
+
+    pub fn is_capturing_sequence(&self) -> bool {
+        self.sequence_capture.is_active()
+    }
+
+    // Makes RenderDoc capture the next presented frame, returns false if the game
+    // is not running under RenderDoc
+    #[cfg(feature = "renderdoc")]
+    pub fn capture_next_frame_in_renderdoc(&self) -> bool {
+        match crate::renderdoc::RenderDoc::attached() {
+            Some(renderdoc) => {
+                renderdoc.trigger_capture();
+                true
+            },
+            None => {
+                warn!("cannot capture a frame in RenderDoc, the game was not launched from it");
+                false
+            }
+        }
+    }
+
+    pub fn vulkan_state(&self) -> &Rc<VulkanState> {
+        &self.vulkan_state
+    }
+
+    pub fn physical_device(&self) -> &Rc<PhysicalDevice> {
+        &self.physical_device
     }
 
     pub fn logical_device(&self) -> &Rc<LogicalDevice> {
@@ -336,6 +1705,19 @@ impl Renderer {
         &self.swapchain
     }
 
+    pub fn pipeline_cache(&self) -> &PipelineCache {
+        &self.pipeline_cache
+    }
+
+    // Fences and semaphores of transient work done outside of the renderer's frames
+    pub fn sync_objects(&self) -> &SyncObjectPool {
+        &self.sync_objects
+    }
+
+    pub fn allocator(&self) -> &Rc<Allocator> {
+        &self.allocator
+    }
+
     pub fn render_pass(&self) -> &Rc<RenderPass> {
         &self.render_pass
     }
@@ -347,8 +1729,12 @@ impl Drop for Renderer {
             self.logical_device.device_wait_idle()
         };
 
-        wait_result
-            .map_err(|result| RenderingError::DeviceWaitIdleError {result})
-            .unwrap();
+        match wait_result {
+            // Nothing is executing on a lost device anymore, so it is safe to destroy
+            Err(vk::Result::ERROR_DEVICE_LOST) => (),
+            wait_result => wait_result
+                .map_err(|result| RenderingError::DeviceWaitIdleError {result})
+                .unwrap()
+        }
     }
 }
@@ -0,0 +1,272 @@
+use std::cell::Cell;
+use nalgebra_glm as glm;
+use crate::{
+    rendering::{
+        render_state::RenderStateTrait,
+        frame_arena::FrameArena,
+        frustum::Frustum,
+        render_layers::{
+            RenderLayers,
+            RenderLayer
+        }
+    },
+    vulkan::{
+        handle::VulkanHandle,
+        pipeline::BlendMode
+    }
+};
+
+// Collection of everything drawn in a frame, which the renderer turns into render layers.
+// Transforms are used on the CPU side, e.g. to order translucent objects and to cull
+// the ones outside of the camera's frustum, shaders still receive them through
+// the render states' push constants.
+pub struct Scene<'a> {
+    objects: Vec<Option<SceneObject<'a>>>,
+    camera_position: glm::Vec3,
+    frustum: Option<Frustum>,
+    culling_stats: Cell<CullingStats>
+}
+
+// Objects of the last built draw list
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct CullingStats {
+    pub submitted: usize,
+    pub culled: usize
+}
+
+pub struct SceneObject<'a> {
+    pub render_state: &'a dyn RenderStateTrait,
+    pub layer: RenderLayer,
+    pub transform: glm::Mat4
+}
+
+#[derive(Clone, Copy)]
+struct DrawItem<'a> {
+    layer: RenderLayer,
+    // Squared distance to the camera, only set for objects of depth-sorted layers
+    distance: f32,
+    index: usize,
+    pipeline: u64,
+    blend_mode: BlendMode,
+    render_state: &'a dyn RenderStateTrait
+}
+
+// Stays valid after other objects are removed from the scene
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct SceneObjectId(usize);
+
+impl<'a> Scene<'a> {
+    pub fn new() -> Self {
+        Self {
+            objects: Vec::new(),
+            camera_position: glm::Vec3::zeros(),
+            frustum: None,
+            culling_stats: Cell::new(CullingStats::default())
+        }
+    }
+
+    pub fn add(
+        &mut self,
+        layer: RenderLayer,
+        render_state: &'a dyn RenderStateTrait,
+        transform: glm::Mat4
+    ) -> SceneObjectId {
+        let object = SceneObject {
+            render_state,
+            layer,
+            transform
+        };
+
+        match self.objects.iter().position(Option::is_none) {
+            Some(index) => {
+                self.objects[index] = Some(object);
+                SceneObjectId(index)
+            },
+            None => {
+                self.objects.push(Some(object));
+                SceneObjectId(self.objects.len() - 1)
+            }
+        }
+    }
+
+    pub fn remove(&mut self, id: SceneObjectId) -> Option<SceneObject<'a>> {
+        self.objects.get_mut(id.0).and_then(Option::take)
+    }
+
+    pub fn object(&self, id: SceneObjectId) -> Option<&SceneObject<'a>> {
+        self.objects.get(id.0).and_then(Option::as_ref)
+    }
+
+    pub fn object_mut(&mut self, id: SceneObjectId) -> Option<&mut SceneObject<'a>> {
+        self.objects.get_mut(id.0).and_then(Option::as_mut)
+    }
+
+    pub fn objects(&self) -> impl Iterator<Item = &SceneObject<'a>> {
+        self.objects.iter().filter_map(Option::as_ref)
+    }
+
+    pub fn set_camera_position(&mut self, camera_position: glm::Vec3) {
+        self.camera_position = camera_position;
+    }
+
+    pub fn camera_position(&self) -> glm::Vec3 {
+        self.camera_position
+    }
+
+    // Objects whose meshes' bounding boxes lie outside of the frustum are left out of
+    // the render layers. Objects without meshes or bounds are always drawn.
+    pub fn set_view_projection(&mut self, view_projection: &glm::Mat4) {
+        self.frustum = Some(Frustum::from_view_projection(view_projection));
+    }
+
+    pub fn set_frustum(&mut self, frustum: Option<Frustum>) {
+        self.frustum = frustum;
+    }
+
+    pub fn frustum(&self) -> Option<&Frustum> {
+        self.frustum.as_ref()
+    }
+
+    pub fn culling_stats(&self) -> CullingStats {
+        self.culling_stats.get()
+    }
+
+    pub fn clear(&mut self) {
+        self.objects.clear();
+    }
+
+    // Translucent objects and particles are ordered back to front together, so that they
+    // blend correctly with each other, objects in other layers are drawn in the order
+    // they were added in
+    pub fn render_layers(&self) -> RenderLayers<'a> {
+        let frame_arena = FrameArena::new();
+        let mut render_layers = RenderLayers::new();
+
+        for item in self.sorted_draw_list(&frame_arena) {
+            render_layers.register(item.layer, item.render_state);
+        }
+
+        render_layers
+    }
+
+    // Same as `render_layers`, but built in the arena instead of on the heap
+    pub fn render_layers_in<'b>(&self, frame_arena: &'b FrameArena) -> RenderLayers<'b> where
+        'a: 'b {
+        let draw_list = self.sorted_draw_list(frame_arena);
+        let layers = frame_arena.alloc_slice_fill_with(
+            draw_list.len(), |index| draw_list[index].layer);
+        let render_states = frame_arena.alloc_slice_fill_with(
+            draw_list.len(), |index| draw_list[index].render_state);
+
+        RenderLayers::from_sorted(layers, render_states)
+    }
+
+    fn sorted_draw_list<'b>(&self, frame_arena: &'b FrameArena) -> &'b [DrawItem<'a>] {
+        let visible_objects = self.visible_objects(frame_arena);
+        let draw_list = frame_arena.alloc_slice_fill_with(visible_objects.len(), |index| {
+            let object = visible_objects[index];
+            let distance = if object.layer.is_depth_sorted() {
+                self.distance_to_camera(object)
+            }
+            else {
+                0.0
+            };
+
+            DrawItem {
+                layer: object.layer,
+                distance,
+                index,
+                pipeline: object.render_state.pipeline().raw_handle(),
+                blend_mode: object.render_state.pipeline().blend_mode(),
+                render_state: object.render_state
+            }
+        });
+
+        // Unstable sorting does not allocate, indices keep the result deterministic
+        draw_list.sort_unstable_by(|first, second| {
+            first.layer.recording_order().cmp(&second.layer.recording_order())
+                .then_with(|| {
+                    second.distance
+                        .partial_cmp(&first.distance)
+                        .unwrap_or(std::cmp::Ordering::Equal)
+                })
+                .then_with(|| first.index.cmp(&second.index))
+        });
+
+        Self::batch_additive_runs(draw_list);
+
+        draw_list
+    }
+
+    // Visible objects are moved to the front, keeping their order
+    fn visible_objects<'s, 'b>(
+        &'s self,
+        frame_arena: &'b FrameArena
+    ) -> &'b [&'s SceneObject<'a>] where
+        's: 'b {
+        let mut objects = self.objects();
+        let candidates = frame_arena.alloc_slice_fill_with(
+            self.objects().count(), |_| objects.next().unwrap());
+
+        let mut visible_count = 0;
+        for index in 0..candidates.len() {
+            if self.is_visible(candidates[index]) {
+                candidates[visible_count] = candidates[index];
+                visible_count += 1;
+            }
+        }
+
+        self.culling_stats.set(CullingStats {
+            submitted: visible_count,
+            culled: candidates.len() - visible_count
+        });
+
+        &candidates[..visible_count]
+    }
+
+    fn is_visible(&self, object: &SceneObject) -> bool {
+        let frustum = match &self.frustum {
+            Some(frustum) => frustum,
+            None => return true
+        };
+
+        match object.render_state.mesh().and_then(|mesh| mesh.bounding_box()) {
+            Some(bounding_box) =>
+                frustum.intersects_box(&bounding_box.transformed(&object.transform)),
+            None => true
+        }
+    }
+
+    // Additive blending is commutative, so consecutive additively blended objects can be
+    // drawn in any order; grouping them by pipeline saves pipeline binds between them
+    fn batch_additive_runs(draw_list: &mut [DrawItem]) {
+        let mut start = 0;
+
+        while start < draw_list.len() {
+            let end = start + draw_list[start..]
+                .iter()
+                .take_while(|item| {
+                    item.layer.is_depth_sorted() && item.blend_mode == BlendMode::Additive
+                })
+                .count();
+
+            if end - start > 1 {
+                draw_list[start..end]
+                    .sort_unstable_by_key(|item| (item.pipeline, item.index));
+            }
+
+            start = end.max(start + 1);
+        }
+    }
+
+    fn distance_to_camera(&self, object: &SceneObject) -> f32 {
+        let position = glm::vec4_to_vec3(&object.transform.column(3).into_owned());
+        glm::distance2(&position, &self.camera_position)
+    }
+}
+
+impl<'a> Default for Scene<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
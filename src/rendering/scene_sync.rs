@@ -0,0 +1,139 @@
+use std::{
+    collections::HashMap,
+    hash::Hash
+};
+use nalgebra_glm as glm;
+use crate::rendering::{
+    render_state::RenderStateTrait,
+    render_layers::RenderLayer,
+    scene::{
+        Scene,
+        SceneObject,
+        SceneObjectId
+    }
+};
+
+// What an entity of an external ECS needs to be drawn, e.g. built every frame by a query
+// over its mesh, material and transform components
+#[derive(Clone, Copy)]
+pub struct Renderable<'a> {
+    pub render_state: &'a dyn RenderStateTrait,
+    pub layer: RenderLayer,
+    pub transform: glm::Mat4
+}
+
+// Keeps a scene in step with the renderable entities of an external ECS, identified by
+// any copyable key, e.g. `hecs::Entity` or `bevy_ecs::entity::Entity`.
+// Every `sync` is given all entities which should be drawn: new ones are added to the scene,
+// changed ones are updated in place and ones which were not given anymore are removed,
+// so scene objects and their ids persist for as long as their entities do.
+pub struct SceneSync<E> {
+    objects: HashMap<E, SyncedObject>,
+    generation: u64
+}
+
+struct SyncedObject {
+    id: SceneObjectId,
+    // Generation of the last sync the entity was given to
+    generation: u64
+}
+
+// Entities handled by the last sync
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct SyncStats {
+    pub added: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+    pub removed: usize
+}
+
+impl<E: Hash + Eq + Copy> SceneSync<E> {
+    pub fn new() -> Self {
+        Self {
+            objects: HashMap::new(),
+            generation: 0
+        }
+    }
+
+    // The scene's objects which were not added by this sync are left alone,
+    // so entities and objects managed by hand can be drawn together
+    pub fn sync<'a, I>(&mut self, scene: &mut Scene<'a>, renderables: I) -> SyncStats where
+        I: IntoIterator<Item = (E, Renderable<'a>)> {
+        self.generation += 1;
+        let mut stats = SyncStats::default();
+
+        for (entity, renderable) in renderables {
+            let generation = self.generation;
+            let synced_object = self.objects.get_mut(&entity);
+            let object = synced_object
+                .as_ref()
+                .and_then(|synced_object| scene.object_mut(synced_object.id));
+
+            match (synced_object, object) {
+                (Some(synced_object), Some(object)) => {
+                    synced_object.generation = generation;
+
+                    if is_unchanged(object, &renderable) {
+                        stats.unchanged += 1;
+                        continue;
+                    }
+
+                    object.render_state = renderable.render_state;
+                    object.layer = renderable.layer;
+                    object.transform = renderable.transform;
+                    stats.updated += 1;
+                },
+                // Entities whose objects were removed from the scene by hand are added anew
+                _ => {
+                    let id = scene.add(
+                        renderable.layer, renderable.render_state, renderable.transform);
+                    self.objects.insert(entity, SyncedObject {id, generation});
+                    stats.added += 1;
+                }
+            }
+        }
+
+        let generation = self.generation;
+        self.objects.retain(|_, synced_object| {
+            let is_current = synced_object.generation == generation;
+
+            if !is_current {
+                scene.remove(synced_object.id);
+                stats.removed += 1;
+            }
+
+            is_current
+        });
+
+        stats
+    }
+
+    pub fn object_id(&self, entity: E) -> Option<SceneObjectId> {
+        self.objects.get(&entity).map(|synced_object| synced_object.id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+}
+
+impl<E: Hash + Eq + Copy> Default for SceneSync<E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Render states are compared by address, as the same one is shared by all entities
+// drawn with the same mesh and material
+fn is_unchanged(object: &SceneObject, renderable: &Renderable) -> bool {
+    let render_state = object.render_state as *const dyn RenderStateTrait as *const u8;
+    let new_render_state = renderable.render_state as *const dyn RenderStateTrait as *const u8;
+
+    render_state == new_render_state
+        && object.layer == renderable.layer
+        && object.transform == renderable.transform
+}
@@ -0,0 +1,337 @@
+use std::{
+    rc::Rc,
+    io::Write,
+    path::PathBuf,
+    process::{
+        Command,
+        Stdio
+    },
+    sync::mpsc::{
+        self,
+        SyncSender
+    },
+    thread::JoinHandle,
+    collections::BTreeMap
+};
+use ash::vk;
+use image::RgbaImage;
+use crate::{
+    rendering::{
+        RenderingError,
+        RenderingResult,
+        frame_capture::FrameCapture
+    },
+    vulkan::{
+        allocator::{
+            Allocator,
+            MemoryUsage
+        },
+        buffer::Buffer,
+        command_buffer::CommandBufferRecorder,
+        swapchain::Swapchain,
+        barrier::{
+            PipelineBarrier,
+            MemoryAccess,
+            BufferBarrier,
+            ImageBarrier
+        }
+    }
+};
+
+// Where the frames of a sequence capture are written to
+pub enum SequenceOutput {
+    // frame_000000.png, frame_000001.png and so on in the given directory, which has to exist
+    PngSequence(PathBuf),
+    // Encoded by ffmpeg, which has to be on the PATH, into a video whose container
+    // follows from the file's extension, e.g. .mp4 or .mkv
+    Ffmpeg {path: PathBuf, frame_rate: u32},
+    // Raw RGBA frames, row after row without padding, written to the command's standard input
+    Pipe(Command)
+}
+
+// Copies every presented frame to host memory and hands it to a writer thread, so neither the
+// copies nor the encoding stall rendering. Frames reach the output in presentation order once
+// their frame in flight has finished, i.e. with as many frames of latency as there are in flight.
+// Rendering waits only when the writer falls more than `QUEUED_FRAMES` frames behind,
+// so that no frame is ever dropped.
+pub struct SequenceCapture {
+    allocator: Rc<Allocator>,
+    // One per frame in flight
+    readback_buffers: Vec<Option<Buffer>>,
+    // Sequence numbers of the frames copied in every frame in flight
+    copied_frames: Vec<Option<u64>>,
+    session: Option<CaptureSession>
+}
+
+struct CaptureSession {
+    extent: vk::Extent2D,
+    swap_red_and_blue: bool,
+    frame_sender: SyncSender<Vec<u8>>,
+    writer: JoinHandle<RenderingResult<()>>,
+    next_frame: u64,
+    next_written_frame: u64,
+    // Frames read back before an earlier one, whose frame in flight finished later
+    out_of_order: BTreeMap<u64, Vec<u8>>,
+    has_warned_about_extent: bool
+}
+
+impl SequenceCapture {
+    const QUEUED_FRAMES: usize = 8;
+
+    pub fn new(allocator: Rc<Allocator>, frames_in_flight: usize) -> Self {
+        Self {
+            allocator,
+            readback_buffers: (0..frames_in_flight).map(|_| None).collect(),
+            copied_frames: vec![None; frames_in_flight],
+            session: None
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.session.is_some()
+    }
+
+    pub fn start(&mut self, swapchain: &Swapchain, output: SequenceOutput) -> RenderingResult<()> {
+        if self.session.is_some() {
+            return Err(RenderingError::SequenceCaptureAlreadyStarted);
+        }
+
+        if !swapchain.image_usage().contains(vk::ImageUsageFlags::TRANSFER_SRC) {
+            return Err(RenderingError::CaptureNotSupported);
+        }
+
+        FrameCapture::check_format_supported(swapchain.image_format())?;
+
+        let extent = swapchain.extent();
+        self.ensure_readback_buffers(extent)?;
+
+        let (frame_sender, frame_receiver) = mpsc::sync_channel(Self::QUEUED_FRAMES);
+        let writer = match output {
+            SequenceOutput::PngSequence(directory) => std::thread::spawn(move || {
+                write_png_sequence(directory, extent, frame_receiver.iter())
+            }),
+            SequenceOutput::Ffmpeg {path, frame_rate} => {
+                let command = ffmpeg_command(path, frame_rate, extent);
+                let child = spawn_piped(command)?;
+                std::thread::spawn(move || write_to_pipe(child, frame_receiver.iter()))
+            },
+            SequenceOutput::Pipe(command) => {
+                let child = spawn_piped(command)?;
+                std::thread::spawn(move || write_to_pipe(child, frame_receiver.iter()))
+            }
+        };
+
+        info!("started capturing a {}x{} frame sequence", extent.width, extent.height);
+        self.session = Some(CaptureSession {
+            extent,
+            swap_red_and_blue: FrameCapture::is_bgra(swapchain.image_format()),
+            frame_sender,
+            writer,
+            next_frame: 0,
+            next_written_frame: 0,
+            out_of_order: BTreeMap::new(),
+            has_warned_about_extent: false
+        });
+
+        Ok(())
+    }
+
+    fn ensure_readback_buffers(&mut self, extent: vk::Extent2D) -> RenderingResult<()> {
+        let size = FrameCapture::frame_size(extent);
+        for readback_buffer in &mut self.readback_buffers {
+            let is_large_enough = readback_buffer
+                .as_ref()
+                .map_or(false, |buffer| buffer.size() >= size);
+
+            if !is_large_enough {
+                let buffer = Buffer::builder()
+                    .allocator(Rc::clone(&self.allocator))
+                    .size(size)
+                    .usage(vk::BufferUsageFlags::TRANSFER_DST)
+                    .memory_usage(MemoryUsage::GpuToCpu)
+                    .host_mapped(true)
+                    .build()?;
+
+                *readback_buffer = Some(buffer);
+            }
+        }
+
+        Ok(())
+    }
+
+    // Frames whose extent differs from the one the capture started with are skipped,
+    // as encoders cannot change the resolution of a video halfway through
+    pub fn record_copy<'a>(
+        &mut self,
+        recorder: CommandBufferRecorder<'a>,
+        swapchain: &Swapchain,
+        image_index: usize,
+        frame: usize
+    ) -> CommandBufferRecorder<'a> {
+        let session = match &mut self.session {
+            Some(session) => session,
+            None => return recorder
+        };
+
+        let extent = swapchain.extent();
+        if (extent.width, extent.height) != (session.extent.width, session.extent.height) {
+            if !session.has_warned_about_extent {
+                warn!("skipping captured frames until the window is resized back");
+                session.has_warned_about_extent = true;
+            }
+
+            self.copied_frames[frame] = None;
+            return recorder;
+        }
+
+        let readback_buffer = self.readback_buffers[frame].as_ref().unwrap();
+        let image = swapchain.images()[image_index];
+        self.copied_frames[frame] = Some(session.next_frame);
+        session.next_frame += 1;
+
+        recorder
+            .pipeline_barrier(&PipelineBarrier::new()
+                .image(ImageBarrier::from_handle(
+                    image,
+                    (vk::ImageLayout::PRESENT_SRC_KHR, vk::ImageLayout::TRANSFER_SRC_OPTIMAL),
+                    (MemoryAccess::color_attachment_write(), MemoryAccess::transfer_read()))))
+            .copy_image_to_buffer(
+                image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                readback_buffer,
+                session.extent)
+            .pipeline_barrier(&PipelineBarrier::new()
+                .buffer(BufferBarrier::new(
+                    readback_buffer,
+                    (MemoryAccess::transfer_write(), MemoryAccess::host_read())))
+                .image(ImageBarrier::from_handle(
+                    image,
+                    (vk::ImageLayout::TRANSFER_SRC_OPTIMAL, vk::ImageLayout::PRESENT_SRC_KHR),
+                    (MemoryAccess::transfer_read(), MemoryAccess::none()))))
+    }
+
+    pub fn is_recorded_in_frame(&self, frame: usize) -> bool {
+        self.session.is_some() && self.copied_frames[frame].is_some()
+    }
+
+    // Has to be called only after the frame in which the copy was recorded has finished rendering
+    pub fn read_back(&mut self, frame: usize) -> RenderingResult<()> {
+        let (session, sequence_number) = match (&mut self.session, self.copied_frames[frame]) {
+            (Some(session), Some(sequence_number)) => (session, sequence_number),
+            _ => return Ok(())
+        };
+
+        self.copied_frames[frame] = None;
+        let readback_buffer = self.readback_buffers[frame].as_ref().unwrap();
+        let data = &readback_buffer.mapped_data()?[..FrameCapture::frame_size(session.extent)];
+        let mut pixels = data.to_vec();
+
+        for pixel in pixels.chunks_exact_mut(FrameCapture::BYTES_PER_PIXEL) {
+            if session.swap_red_and_blue {
+                pixel.swap(0, 2);
+            }
+
+            // Swapchain images are composited as opaque, so alpha carries no information
+            pixel[3] = u8::MAX;
+        }
+
+        session.out_of_order.insert(sequence_number, pixels);
+        while let Some(pixels) = session.out_of_order.remove(&session.next_written_frame) {
+            if session.frame_sender.send(pixels).is_err() {
+                // The writer stopped because of an error, which `stop` returns
+                return self.stop();
+            }
+
+            session.next_written_frame += 1;
+        }
+
+        Ok(())
+    }
+
+    // Frames still in flight have to be read back before, or they are missing from the output
+    pub fn stop(&mut self) -> RenderingResult<()> {
+        let session = match self.session.take() {
+            Some(session) => session,
+            None => return Ok(())
+        };
+
+        self.copied_frames.iter_mut().for_each(|copied_frame| *copied_frame = None);
+        drop(session.frame_sender);
+        let result = session.writer
+            .join()
+            .unwrap_or(Err(RenderingError::SequenceWriterPanicked));
+
+        info!("stopped capturing a frame sequence after {} frames", session.next_written_frame);
+        result
+    }
+}
+
+impl Drop for SequenceCapture {
+    fn drop(&mut self) {
+        if let Err(error) = self.stop() {
+            error!("failed to finish frame sequence capture: {}", error);
+        }
+    }
+}
+
+fn ffmpeg_command(path: PathBuf, frame_rate: u32, extent: vk::Extent2D) -> Command {
+    let mut command = Command::new("ffmpeg");
+    command
+        .args(["-y", "-loglevel", "error"])
+        .args(["-f", "rawvideo", "-pixel_format", "rgba"])
+        .args(["-video_size", &format!("{}x{}", extent.width, extent.height)])
+        .args(["-framerate", &frame_rate.to_string()])
+        .args(["-i", "-"])
+        // Most players only support 4:2:0 chroma subsampling, which needs even dimensions
+        .args(["-vf", "pad=ceil(iw/2)*2:ceil(ih/2)*2", "-pix_fmt", "yuv420p"])
+        .arg(path);
+
+    command
+}
+
+fn spawn_piped(mut command: Command) -> RenderingResult<std::process::Child> {
+    command
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|error| RenderingError::SequenceEncoderSpawnError {error})
+}
+
+fn write_png_sequence<I>(
+    directory: PathBuf,
+    extent: vk::Extent2D,
+    frames: I
+) -> RenderingResult<()> where
+    I: Iterator<Item = Vec<u8>> {
+    for (index, pixels) in frames.enumerate() {
+        let path = directory.join(format!("frame_{:06}.png", index));
+        RgbaImage::from_raw(extent.width, extent.height, pixels)
+            .unwrap()
+            .save(path)
+            .map_err(|error| RenderingError::SequenceWriteError {error})?;
+    }
+
+    Ok(())
+}
+
+fn write_to_pipe<I>(mut child: std::process::Child, frames: I) -> RenderingResult<()> where
+    I: Iterator<Item = Vec<u8>> {
+    let mut stdin = child.stdin.take().unwrap();
+    for pixels in frames {
+        stdin
+            .write_all(&pixels)
+            .map_err(|error| RenderingError::SequenceWriteError {error})?;
+    }
+
+    // Closing the pipe ends the input, letting the encoder finish
+    drop(stdin);
+    let status = child
+        .wait()
+        .map_err(|error| RenderingError::SequenceWriteError {error})?;
+
+    if status.success() {
+        Ok(())
+    }
+    else {
+        Err(RenderingError::SequenceEncoderFailed {status})
+    }
+}
@@ -0,0 +1,316 @@
+use std::rc::Rc;
+use ash::vk;
+use nalgebra_glm as glm;
+use crate::{
+    builder::{
+        BuilderRequirement,
+        BuilderInternal,
+        BuilderProduct
+    },
+    rendering::{
+        RenderingError,
+        RenderingResult,
+        renderer::Renderer,
+        texture::Texture
+    },
+    vulkan::{
+        image::Image,
+        sampler::Sampler,
+        render_pass::RenderPass,
+        framebuffers::Framebuffers,
+        pipeline::{
+            Pipeline,
+            PipelineBuilder
+        },
+        command_buffer::CommandBufferRecorder
+    }
+};
+
+// Depth of the scene as seen from a light. Material passes bind `texture()` as
+// a `sampler2DArrayShadow` and look it up at `shadow_matrix() * world_position`,
+// getting 1.0 where the fragment is lit and 0.0 where it is in shadow.
+//
+// The depth pass is recorded with `record` by a custom pass at
+// `CustomPassPoint::BeforeMainPass`, with pipelines made from `pipeline_builder`
+// whose vertex shaders transform positions by `light_view_projection()`.
+pub struct ShadowMap {
+    image: Rc<Image>,
+    sampler: Rc<Sampler>,
+    render_pass: Rc<RenderPass>,
+    framebuffers: Framebuffers,
+    light_view: glm::Mat4,
+    light_projection: glm::Mat4
+}
+
+impl ShadowMap {
+    // Preferred first, every device supports sampling and rendering to at least one of them
+    const FORMAT_CANDIDATES: [vk::Format; 3] = [
+        vk::Format::D32_SFLOAT,
+        vk::Format::X8_D24_UNORM_PACK32,
+        vk::Format::D16_UNORM
+    ];
+
+    pub fn builder<'a>() -> ShadowMapBuilder<'a> {
+        ShadowMapBuilder {
+            ..Default::default()
+        }
+    }
+
+    pub fn extent(&self) -> vk::Extent2D {
+        self.image.extent()
+    }
+
+    pub fn render_pass(&self) -> &Rc<RenderPass> {
+        &self.render_pass
+    }
+
+    // For `MaterialBuilder::texture`
+    pub fn texture(&self) -> Texture {
+        Texture::from_parts(Rc::clone(&self.image), Rc::clone(&self.sampler))
+    }
+
+    // Parallel light rays shining in `direction`, shadows are cast inside of the sphere
+    pub fn set_directional_light(
+        &mut self,
+        direction: &glm::Vec3,
+        center: &glm::Vec3,
+        radius: f32
+    ) {
+        let direction = glm::normalize(direction);
+        let eye = center - direction * radius;
+
+        self.light_view = glm::look_at_rh(&eye, center, &Self::up_vector(&direction));
+        self.light_projection = glm::ortho_rh_zo(
+            -radius, radius, -radius, radius, 0.0, 2.0 * radius);
+    }
+
+    // Light shining from `position` in `direction` inside of a cone with the given full angle,
+    // in radians, reaching up to `range`
+    pub fn set_spot_light(
+        &mut self,
+        position: &glm::Vec3,
+        direction: &glm::Vec3,
+        cone_angle: f32,
+        range: f32
+    ) {
+        let direction = glm::normalize(direction);
+        let near = range * 0.001;
+
+        self.light_view = glm::look_at_rh(
+            position, &(position + direction), &Self::up_vector(&direction));
+        self.light_projection = glm::perspective_rh_zo(1.0, cone_angle, near, range);
+    }
+
+    // Any vector not parallel to the light direction will do
+    fn up_vector(direction: &glm::Vec3) -> glm::Vec3 {
+        if direction.y.abs() > 0.99 {
+            glm::vec3(0.0, 0.0, 1.0)
+        }
+        else {
+            glm::vec3(0.0, 1.0, 0.0)
+        }
+    }
+
+    pub fn light_view(&self) -> glm::Mat4 {
+        self.light_view
+    }
+
+    pub fn light_projection(&self) -> glm::Mat4 {
+        self.light_projection
+    }
+
+    // Transforms world positions into the light's clip space, for the depth pass
+    pub fn light_view_projection(&self) -> glm::Mat4 {
+        self.light_projection * self.light_view
+    }
+
+    // Transforms world positions into shadow map coordinates in XY and the depth to compare
+    // against in Z, for the material passes. Needs a division by W for spot lights.
+    pub fn shadow_matrix(&self) -> glm::Mat4 {
+        let clip_to_texture = glm::translation(&glm::vec3(0.5, 0.5, 0.0))
+            * glm::scaling(&glm::vec3(0.5, 0.5, 1.0));
+
+        clip_to_texture * self.light_view_projection()
+    }
+
+    // Depth only pipeline writing into the shadow map, still without shaders and vertex input.
+    // The depth bias keeps surfaces from shadowing themselves, depth clamping (if the feature
+    // is enabled) keeps casters behind the light's near plane from being clipped away.
+    pub fn pipeline_builder<'a>(&self, renderer: &Renderer) -> PipelineBuilder<'a> {
+        let depth_clamp = renderer.logical_device().enabled_features().depth_clamp;
+
+        Pipeline::builder()
+            .logical_device(Rc::clone(renderer.logical_device()))
+            .swapchain(Rc::clone(renderer.swapchain()))
+            .render_pass(Rc::clone(&self.render_pass))
+            .subpass(0)
+            .color_attachment_count(0)
+            .depth_test(true)
+            .depth_write(true)
+            .depth_bias(1.25, 1.75)
+            .depth_clamp(depth_clamp)
+    }
+
+    // Clears the shadow map and renders it with the commands recorded by `draw`.
+    // Sampling it in the main render pass is synchronized by the depth pass itself.
+    pub fn record<'a, F>(
+        &self,
+        recorder: CommandBufferRecorder<'a>,
+        draw: F
+    ) -> CommandBufferRecorder<'a> where
+        F: FnOnce(CommandBufferRecorder<'a>) -> CommandBufferRecorder<'a> {
+        let clear_values = [vk::ClearValue {
+            depth_stencil: vk::ClearDepthStencilValue {
+                depth: 1.0,
+                stencil: 0
+            }
+        }];
+
+        let recorder = recorder
+            .begin_render_pass_with_clear_values(
+                &self.render_pass, &self.framebuffers, 0, &clear_values)
+            .set_viewport(CommandBufferRecorder::render_area(&self.framebuffers));
+
+        draw(recorder).end_render_pass()
+    }
+}
+
+#[derive(Default)]
+pub struct ShadowMapBuilder<'a> {
+    renderer: BuilderRequirement<&'a Renderer>,
+    size: BuilderRequirement<u32>,
+
+    format: BuilderInternal<vk::Format>,
+    image: BuilderInternal<Rc<Image>>,
+    sampler: BuilderInternal<Rc<Sampler>>,
+    render_pass: BuilderInternal<Rc<RenderPass>>,
+    framebuffers: BuilderInternal<Framebuffers>,
+
+    shadow_map: BuilderProduct<ShadowMap>
+}
+
+impl<'a> ShadowMapBuilder<'a> {
+    pub fn renderer(mut self, renderer: &'a Renderer) -> Self {
+        self.renderer.set(renderer);
+        self
+    }
+
+    // Width and height in texels
+    pub fn size(mut self, size: u32) -> Self {
+        self.size.set(size);
+        self
+    }
+
+    pub fn build(mut self) -> RenderingResult<ShadowMap> {
+        check_required_fields!(self, RenderingError, "ShadowMapBuilder", [renderer, size]);
+
+        self.init_format()?;
+        self.init_image()?;
+        self.init_sampler()?;
+        self.init_render_pass()?;
+        self.init_framebuffers()?;
+        self.create_shadow_map();
+
+        Ok(self.shadow_map.unwrap())
+    }
+
+    fn init_format(&mut self) -> RenderingResult<()> {
+        let required_features = vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT
+            | vk::FormatFeatureFlags::SAMPLED_IMAGE;
+
+        let format = ShadowMap::FORMAT_CANDIDATES
+            .iter()
+            .copied()
+            .find(|format| {
+                self.renderer
+                    .physical_device()
+                    .format_properties(*format)
+                    .optimal_tiling_features
+                    .contains(required_features)
+            })
+            .ok_or(RenderingError::ShadowMapFormatNotSupported)?;
+
+        debug!("shadow maps use format {:?}", format);
+        self.format.set(format);
+
+        Ok(())
+    }
+
+    fn init_image(&mut self) -> RenderingResult<()> {
+        let image = Image::builder()
+            .allocator(Rc::clone(self.renderer.allocator()))
+            .logical_device(Rc::clone(self.renderer.logical_device()))
+            .format(*self.format)
+            .extent(vk::Extent2D {
+                width: *self.size,
+                height: *self.size
+            })
+            .usage(
+                vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT
+                | vk::ImageUsageFlags::SAMPLED)
+            .aspect(vk::ImageAspectFlags::DEPTH)
+            .build()?;
+
+        self.image.set(Rc::new(image));
+
+        Ok(())
+    }
+
+    // Percentage closer filtering needs linear filtering of the format,
+    // without it every lookup compares a single texel
+    fn init_sampler(&mut self) -> RenderingResult<()> {
+        let supports_linear_filter = self.renderer
+            .physical_device()
+            .format_properties(*self.format)
+            .optimal_tiling_features
+            .contains(vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR);
+
+        let filter = if supports_linear_filter {
+            vk::Filter::LINEAR
+        }
+        else {
+            vk::Filter::NEAREST
+        };
+
+        let sampler = Sampler::depth_comparison(
+            Rc::clone(self.renderer.logical_device()),
+            filter,
+            vk::CompareOp::LESS_OR_EQUAL)?;
+
+        self.sampler.set(Rc::new(sampler));
+
+        Ok(())
+    }
+
+    fn init_render_pass(&mut self) -> RenderingResult<()> {
+        let render_pass = RenderPass::depth_only(
+            Rc::clone(self.renderer.logical_device()),
+            *self.format)?;
+
+        self.render_pass.set(Rc::new(render_pass));
+
+        Ok(())
+    }
+
+    fn init_framebuffers(&mut self) -> RenderingResult<()> {
+        let framebuffers = Framebuffers::for_image(
+            Rc::clone(self.renderer.logical_device()),
+            &self.render_pass,
+            Rc::clone(&self.image))?;
+
+        self.framebuffers.set(framebuffers);
+
+        Ok(())
+    }
+
+    fn create_shadow_map(&mut self) {
+        self.shadow_map.set(ShadowMap {
+            image: self.image.take(),
+            sampler: self.sampler.take(),
+            render_pass: self.render_pass.take(),
+            framebuffers: self.framebuffers.take(),
+            light_view: glm::Mat4::identity(),
+            light_projection: glm::Mat4::identity()
+        });
+    }
+}
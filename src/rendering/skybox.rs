@@ -0,0 +1,202 @@
+use std::{
+    cell::Cell,
+    rc::Rc
+};
+use nalgebra_glm as glm;
+use crate::{
+    builder::{
+        BuilderRequirement,
+        BuilderInternal,
+        BuilderProduct
+    },
+    rendering::{
+        RenderingError,
+        RenderingResult,
+        renderer::Renderer,
+        texture::Texture,
+        custom_pass::{
+            CustomPass,
+            CustomPassContext
+        }
+    },
+    vulkan::{
+        descriptor::{
+            DescriptorSet,
+            DescriptorSetLayout
+        },
+        pipeline::{
+            Pipeline,
+            BlendMode
+        },
+        shader::{
+            VertexShader,
+            FragmentShader,
+            ShaderStage
+        },
+        command_buffer::{
+            CommandBufferRecorder,
+            PushConstants
+        }
+    }
+};
+
+// Background of a scene sampled from a cube map in the direction of every pixel, drawn with
+// a fullscreen triangle, e.g. with shaders/skybox.vert and shaders/skybox.frag. The vertex
+// shader gets the inverse of the projection times the view without its translation
+// as a vertex push constant and the fragment shader samples the cube map as a `samplerCube`
+// at set 0, binding 0.
+//
+// The main pass has no depth buffer, so the skybox is registered at
+// `CustomPassPoint::MainPassBegin` and everything else is drawn over it.
+pub struct SkyboxPass {
+    pipeline: Rc<Pipeline>,
+    cube_map_set: Rc<DescriptorSet>,
+    inverse_view_projection: Cell<glm::Mat4>,
+    version: Cell<u64>
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, PushConstants)]
+struct SkyboxConstants {
+    inverse_view_projection: [[f32; 4]; 4]
+}
+
+impl SkyboxPass {
+    pub fn builder<'a>() -> SkyboxPassBuilder<'a> {
+        SkyboxPassBuilder {
+            ..Default::default()
+        }
+    }
+
+    // Only the rotation of the view is used, so that the sky stays infinitely far away
+    pub fn set_camera(&self, view: &glm::Mat4, projection: &glm::Mat4) {
+        let mut rotation = *view;
+        rotation.set_column(3, &glm::vec4(0.0, 0.0, 0.0, 1.0));
+
+        self.inverse_view_projection.set(glm::inverse(&(projection * rotation)));
+        self.version.set(self.version.get() + 1);
+    }
+}
+
+impl CustomPass for SkyboxPass {
+    fn record<'a>(
+        &self,
+        recorder: CommandBufferRecorder<'a>,
+        context: &CustomPassContext
+    ) -> CommandBufferRecorder<'a> {
+        let constants = SkyboxConstants {
+            inverse_view_projection: self.inverse_view_projection.get().into()
+        };
+
+        recorder
+            .set_viewport(context.letterboxed_area)
+            .bind_pipeline(Rc::clone(&self.pipeline))
+            .bind_descriptor_set(&self.pipeline, 0, Rc::clone(&self.cube_map_set))
+            .push_constant(&self.pipeline, ShaderStage::Vertex, &constants)
+            .draw(3, 1)
+    }
+
+    // Changes with the camera
+    fn version(&self) -> u64 {
+        self.version.get()
+    }
+}
+
+#[derive(Default)]
+pub struct SkyboxPassBuilder<'a> {
+    renderer: BuilderRequirement<&'a Renderer>,
+    cube_map: BuilderRequirement<&'a Texture>,
+    vertex_shader: BuilderRequirement<&'a VertexShader>,
+    fragment_shader: BuilderRequirement<&'a FragmentShader>,
+
+    cube_map_set_layout: BuilderInternal<Rc<DescriptorSetLayout>>,
+    cube_map_set: BuilderInternal<Rc<DescriptorSet>>,
+    pipeline: BuilderInternal<Rc<Pipeline>>,
+
+    skybox_pass: BuilderProduct<SkyboxPass>
+}
+
+impl<'a> SkyboxPassBuilder<'a> {
+    pub fn renderer(mut self, renderer: &'a Renderer) -> Self {
+        self.renderer.set(renderer);
+        self
+    }
+
+    // Has to be built with `TextureArrayBuilder::cube_map`
+    pub fn cube_map(mut self, cube_map: &'a Texture) -> Self {
+        self.cube_map.set(cube_map);
+        self
+    }
+
+    pub fn vertex_shader(mut self, vertex_shader: &'a VertexShader) -> Self {
+        self.vertex_shader.set(vertex_shader);
+        self
+    }
+
+    pub fn fragment_shader(mut self, fragment_shader: &'a FragmentShader) -> Self {
+        self.fragment_shader.set(fragment_shader);
+        self
+    }
+
+    pub fn build(mut self) -> RenderingResult<SkyboxPass> {
+        check_required_fields!(
+            self, RenderingError, "SkyboxPassBuilder",
+            [renderer, cube_map, vertex_shader, fragment_shader]);
+
+        if !self.cube_map.is_cube_map() {
+            return Err(RenderingError::SkyboxTextureNotCubeMap);
+        }
+
+        self.init_cube_map_set()?;
+        self.init_pipeline()?;
+        self.create_skybox_pass();
+
+        Ok(self.skybox_pass.unwrap())
+    }
+
+    fn init_cube_map_set(&mut self) -> RenderingResult<()> {
+        let layout = DescriptorSetLayout::builder()
+            .logical_device(Rc::clone(self.renderer.logical_device()))
+            .combined_image_sampler(0, &[ShaderStage::Fragment])
+            .build()?;
+        let layout = Rc::new(layout);
+
+        let mut cube_map_set = DescriptorSet::new(Rc::clone(&layout))?;
+        cube_map_set.write_combined_image_sampler(
+            0,
+            Rc::clone(self.cube_map.image()),
+            Rc::clone(self.cube_map.sampler()))?;
+
+        self.cube_map_set_layout.set(layout);
+        self.cube_map_set.set(Rc::new(cube_map_set));
+
+        Ok(())
+    }
+
+    fn init_pipeline(&mut self) -> RenderingResult<()> {
+        let pipeline = Pipeline::builder()
+            .logical_device(Rc::clone(self.renderer.logical_device()))
+            .swapchain(Rc::clone(self.renderer.swapchain()))
+            .render_pass(Rc::clone(self.renderer.render_pass()))
+            .subpass(0)
+            .vertex_shader(*self.vertex_shader)
+            .fragment_shader(*self.fragment_shader)
+            .blend_mode(BlendMode::Opaque)
+            .descriptor_set_layout(Rc::clone(&self.cube_map_set_layout))
+            .push_constants_size(ShaderStage::Vertex, std::mem::size_of::<SkyboxConstants>())
+            .build()?;
+
+        self.pipeline.set(Rc::new(pipeline));
+
+        Ok(())
+    }
+
+    fn create_skybox_pass(&mut self) {
+        self.skybox_pass.set(SkyboxPass {
+            pipeline: self.pipeline.take(),
+            cube_map_set: self.cube_map_set.take(),
+            inverse_view_projection: Cell::new(glm::identity()),
+            version: Cell::new(0)
+        });
+    }
+}
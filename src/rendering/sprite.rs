@@ -0,0 +1,434 @@
+use std::{
+    cell::{
+        Cell,
+        RefCell
+    },
+    rc::Rc
+};
+use ash::vk;
+use nalgebra_glm as glm;
+use crate::{
+    builder::{
+        BuilderRequirement,
+        BuilderInternal,
+        BuilderProduct
+    },
+    rendering::{
+        RenderingError,
+        RenderingResult,
+        renderer::Renderer,
+        texture::Texture,
+        custom_pass::{
+            CustomPass,
+            CustomPassContext
+        }
+    },
+    vulkan::{
+        allocator::MemoryUsage,
+        buffer::Buffer,
+        descriptor::{
+            DescriptorSet,
+            DescriptorSetLayout
+        },
+        pipeline::{
+            Pipeline,
+            BlendMode,
+            VertexAttributeFormat
+        },
+        shader::{
+            VertexShader,
+            FragmentShader,
+            ShaderStage
+        },
+        command_buffer::{
+            CommandBufferRecorder,
+            PushConstants
+        }
+    }
+};
+
+// Part of a texture a sprite shows, e.g. a cell of an atlas, in normalized coordinates
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct SpriteRegion {
+    pub layer: u32,
+    pub uv_min: glm::Vec2,
+    pub uv_max: glm::Vec2
+}
+
+impl SpriteRegion {
+    pub fn full(layer: u32) -> Self {
+        Self {
+            layer,
+            uv_min: glm::vec2(0.0, 0.0),
+            uv_max: glm::vec2(1.0, 1.0)
+        }
+    }
+
+    // Cell of an atlas made of equally sized cells, counted row by row from the top left
+    pub fn grid_cell(layer: u32, columns: u32, rows: u32, cell: u32) -> Self {
+        let size = glm::vec2(1.0 / columns as f32, 1.0 / rows as f32);
+        let uv_min = glm::vec2((cell % columns) as f32 * size.x, (cell / columns) as f32 * size.y);
+
+        Self {
+            layer,
+            uv_min,
+            uv_max: uv_min + size
+        }
+    }
+}
+
+// Index of a texture added to a sprite batch
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct SpriteTextureId(usize);
+
+#[derive(Clone, Copy, Debug)]
+pub struct Sprite {
+    pub texture: SpriteTextureId,
+    pub region: SpriteRegion,
+    // Places the unit square from (0, 0) to (1, 1), e.g. scaled to the sprite's size in pixels
+    pub transform: glm::Mat4,
+    // Multiplies the texture's colors, in RGBA
+    pub color: [f32; 4],
+    // Sprites with higher orders are drawn over the ones with lower orders
+    pub order: i32
+}
+
+// Batched textured quads for 2D games and HUDs. Sprites drawn during a frame are collected
+// on the CPU, sorted by order and then by texture, written into a single vertex buffer and
+// recorded with one draw per run of sprites sharing a texture by registering the batch
+// as a custom pass, e.g. at `CustomPassPoint::AfterLayer(RenderLayer::UI)`.
+//
+// Vertices have a vec3 position at location 0, a vec3 texture coordinate with the layer
+// as its third component at location 1 and a vec4 color at location 2. The vertex shader
+// gets the projection matrix as a vertex push constant and the fragment shader samples
+// the texture as a `sampler2DArray` at set 0, binding 0, see shaders/sprite.vert.
+pub struct SpriteBatch {
+    pipeline: Rc<Pipeline>,
+    descriptor_set_layout: Rc<DescriptorSetLayout>,
+    texture_descriptor_sets: RefCell<Vec<Rc<DescriptorSet>>>,
+    // One region per frame in flight and one for the frame being recorded
+    vertex_buffer: RefCell<Buffer>,
+    region_count: usize,
+    capacity: usize,
+    letterboxed: bool,
+    sprites: RefCell<Vec<Sprite>>,
+    projection: Cell<glm::Mat4>,
+    version: Cell<u64>
+}
+
+// Also drawn by the UI pass, so that both can share their shaders
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub(crate) struct SpriteVertex {
+    pub position: [f32; 3],
+    pub uv: [f32; 3],
+    pub color: [f32; 4]
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, PushConstants)]
+pub(crate) struct SpriteConstants {
+    pub projection: [[f32; 4]; 4]
+}
+
+impl SpriteBatch {
+    const VERTICES_PER_SPRITE: usize = 6;
+    // Corners of two triangles covering the unit square
+    const CORNERS: [(f32, f32); 6] =
+        [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (1.0, 1.0), (0.0, 1.0), (0.0, 0.0)];
+
+    pub fn builder<'a>() -> SpriteBatchBuilder<'a> {
+        SpriteBatchBuilder {
+            ..Default::default()
+        }
+    }
+
+    // Textures are never removed, so atlases should be added once and shared by their sprites
+    pub fn add_texture(&self, texture: &Texture) -> RenderingResult<SpriteTextureId> {
+        let mut descriptor_set = DescriptorSet::new(Rc::clone(&self.descriptor_set_layout))?;
+        descriptor_set.write_combined_image_sampler(
+            0,
+            Rc::clone(texture.image()),
+            Rc::clone(texture.sampler()))?;
+
+        let mut texture_descriptor_sets = self.texture_descriptor_sets.borrow_mut();
+        texture_descriptor_sets.push(Rc::new(descriptor_set));
+
+        Ok(SpriteTextureId(texture_descriptor_sets.len() - 1))
+    }
+
+    // E.g. `glm::ortho_rh_zo(0.0, width, 0.0, height, 0.0, 1.0)` for positions in pixels
+    pub fn set_projection(&self, projection: &glm::Mat4) {
+        self.projection.set(*projection);
+    }
+
+    // Sprites beyond the capacity of a frame are dropped
+    pub fn draw(&self, sprite: Sprite) -> RenderingResult<()> {
+        if sprite.texture.0 >= self.texture_descriptor_sets.borrow().len() {
+            return Err(RenderingError::UnknownSpriteTexture {texture: sprite.texture.0});
+        }
+
+        let mut sprites = self.sprites.borrow_mut();
+
+        if sprites.len() < self.capacity {
+            sprites.push(sprite);
+        }
+
+        Ok(())
+    }
+
+    pub fn sprite_count(&self) -> usize {
+        self.sprites.borrow().len()
+    }
+
+    // Drops everything drawn since the last frame without recording it
+    pub fn clear(&self) {
+        self.sprites.borrow_mut().clear();
+    }
+
+    fn vertices(sprites: &[Sprite]) -> Vec<SpriteVertex> {
+        let mut vertices = Vec::with_capacity(sprites.len() * Self::VERTICES_PER_SPRITE);
+
+        for sprite in sprites {
+            let region = &sprite.region;
+
+            for &(x, y) in Self::CORNERS.iter() {
+                let position = sprite.transform * glm::vec4(x, y, 0.0, 1.0);
+                let uv = region.uv_min
+                    + glm::vec2(x, y).component_mul(&(region.uv_max - region.uv_min));
+
+                vertices.push(SpriteVertex {
+                    position: [position.x, position.y, position.z],
+                    uv: [uv.x, uv.y, region.layer as f32],
+                    color: sprite.color
+                });
+            }
+        }
+
+        vertices
+    }
+}
+
+impl CustomPass for SpriteBatch {
+    fn record<'a>(
+        &self,
+        recorder: CommandBufferRecorder<'a>,
+        context: &CustomPassContext
+    ) -> CommandBufferRecorder<'a> {
+        let mut sprites = self.sprites.borrow_mut();
+        self.version.set(self.version.get() + 1);
+
+        if sprites.is_empty() {
+            return recorder;
+        }
+
+        // Stable, so sprites of the same order and texture keep the order they were drawn in
+        sprites.sort_by_key(|sprite| (sprite.order, sprite.texture));
+
+        let vertex_size = std::mem::size_of::<SpriteVertex>();
+        let region = (context.frame_number % self.region_count as u64) as usize;
+        let region_offset = region * self.capacity * Self::VERTICES_PER_SPRITE * vertex_size;
+        let vertices = Self::vertices(&sprites);
+        let data = unsafe {
+            std::slice::from_raw_parts(
+                vertices.as_ptr() as *const u8,
+                vertices.len() * vertex_size)
+        };
+
+        if let Err(error) = self.vertex_buffer.borrow_mut().write_data(region_offset, data) {
+            error!("failed to upload sprites: {}", error);
+            sprites.clear();
+            return recorder;
+        }
+
+        let constants = SpriteConstants {
+            projection: self.projection.get().into()
+        };
+        let area = if self.letterboxed {
+            context.letterboxed_area
+        }
+        else {
+            context.full_area
+        };
+
+        let mut recorder = recorder
+            .set_viewport(area)
+            .bind_pipeline(Rc::clone(&self.pipeline))
+            .push_constant(&self.pipeline, ShaderStage::Vertex, &constants);
+
+        let vertex_buffer = self.vertex_buffer.borrow();
+        let texture_descriptor_sets = self.texture_descriptor_sets.borrow();
+        let mut first = 0;
+
+        while first < sprites.len() {
+            let texture = sprites[first].texture;
+            let count = sprites[first..]
+                .iter()
+                .take_while(|sprite| sprite.texture == texture)
+                .count();
+            let offset = region_offset + first * Self::VERTICES_PER_SPRITE * vertex_size;
+
+            recorder = recorder
+                .bind_descriptor_set(
+                    &self.pipeline, 0, Rc::clone(&texture_descriptor_sets[texture.0]))
+                .bind_vertex_buffer_at(0, &vertex_buffer, offset)
+                .draw((count * Self::VERTICES_PER_SPRITE) as u32, 1);
+
+            first += count;
+        }
+
+        sprites.clear();
+
+        recorder
+    }
+
+    // Sprites are drawn anew every frame
+    fn version(&self) -> u64 {
+        self.version.get()
+    }
+}
+
+#[derive(Default)]
+pub struct SpriteBatchBuilder<'a> {
+    renderer: BuilderRequirement<&'a Renderer>,
+    vertex_shader: BuilderRequirement<&'a VertexShader>,
+    fragment_shader: BuilderRequirement<&'a FragmentShader>,
+    capacity: Option<usize>,
+    blend_mode: Option<BlendMode>,
+    letterboxed: Option<bool>,
+
+    region_count: BuilderInternal<usize>,
+    descriptor_set_layout: BuilderInternal<Rc<DescriptorSetLayout>>,
+    pipeline: BuilderInternal<Rc<Pipeline>>,
+    vertex_buffer: BuilderInternal<Buffer>,
+
+    sprite_batch: BuilderProduct<SpriteBatch>
+}
+
+impl<'a> SpriteBatchBuilder<'a> {
+    const DEFAULT_CAPACITY: usize = 4 * 1024;
+
+    pub fn renderer(mut self, renderer: &'a Renderer) -> Self {
+        self.renderer.set(renderer);
+        self
+    }
+
+    pub fn vertex_shader(mut self, vertex_shader: &'a VertexShader) -> Self {
+        self.vertex_shader.set(vertex_shader);
+        self
+    }
+
+    pub fn fragment_shader(mut self, fragment_shader: &'a FragmentShader) -> Self {
+        self.fragment_shader.set(fragment_shader);
+        self
+    }
+
+    // Sprites per frame
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = Some(capacity);
+        self
+    }
+
+    // Defaults to alpha blending
+    pub fn blend_mode(mut self, blend_mode: BlendMode) -> Self {
+        self.blend_mode = Some(blend_mode);
+        self
+    }
+
+    // Whether sprites are drawn into the letterboxed area or over the whole window,
+    // defaults to true
+    pub fn letterboxed(mut self, letterboxed: bool) -> Self {
+        self.letterboxed = Some(letterboxed);
+        self
+    }
+
+    pub fn build(mut self) -> RenderingResult<SpriteBatch> {
+        check_required_fields!(
+            self, RenderingError, "SpriteBatchBuilder",
+            [renderer, vertex_shader, fragment_shader]);
+
+        self.init_region_count();
+        self.init_descriptor_set_layout()?;
+        self.init_pipeline()?;
+        self.init_vertex_buffer()?;
+        self.create_sprite_batch();
+
+        Ok(self.sprite_batch.unwrap())
+    }
+
+    fn capacity_or_default(&self) -> usize {
+        self.capacity.unwrap_or(Self::DEFAULT_CAPACITY)
+    }
+
+    fn init_region_count(&mut self) {
+        self.region_count.set(Renderer::FRAMES_IN_FLIGHT + 1);
+    }
+
+    fn init_descriptor_set_layout(&mut self) -> RenderingResult<()> {
+        let descriptor_set_layout = DescriptorSetLayout::builder()
+            .logical_device(Rc::clone(self.renderer.logical_device()))
+            .combined_image_sampler(0, &[ShaderStage::Fragment])
+            .build()?;
+
+        self.descriptor_set_layout.set(Rc::new(descriptor_set_layout));
+
+        Ok(())
+    }
+
+    fn init_pipeline(&mut self) -> RenderingResult<()> {
+        let float_size = std::mem::size_of::<f32>();
+        let pipeline = Pipeline::builder()
+            .logical_device(Rc::clone(self.renderer.logical_device()))
+            .swapchain(Rc::clone(self.renderer.swapchain()))
+            .render_pass(Rc::clone(self.renderer.render_pass()))
+            .subpass(0)
+            .vertex_shader(*self.vertex_shader)
+            .fragment_shader(*self.fragment_shader)
+            .blend_mode(self.blend_mode.unwrap_or(BlendMode::AlphaBlend))
+            .descriptor_set_layout(Rc::clone(&self.descriptor_set_layout))
+            .vertex_attribute_description(VertexAttributeFormat::F32(3), 0)
+            .vertex_attribute_description(VertexAttributeFormat::F32(3), 3 * float_size)
+            .vertex_attribute_description(VertexAttributeFormat::F32(4), 6 * float_size)
+            .vertex_binding_stride(std::mem::size_of::<SpriteVertex>())
+            .push_constants_size(ShaderStage::Vertex, std::mem::size_of::<SpriteConstants>())
+            .build()?;
+
+        self.pipeline.set(Rc::new(pipeline));
+
+        Ok(())
+    }
+
+    fn init_vertex_buffer(&mut self) -> RenderingResult<()> {
+        let size = *self.region_count
+            * self.capacity_or_default()
+            * SpriteBatch::VERTICES_PER_SPRITE
+            * std::mem::size_of::<SpriteVertex>();
+
+        let vertex_buffer = Buffer::builder()
+            .allocator(Rc::clone(self.renderer.allocator()))
+            .size(size.max(1))
+            .usage(vk::BufferUsageFlags::VERTEX_BUFFER)
+            .memory_usage(MemoryUsage::CpuToGpu)
+            .host_mapped(true)
+            .build()?;
+
+        self.vertex_buffer.set(vertex_buffer);
+
+        Ok(())
+    }
+
+    fn create_sprite_batch(&mut self) {
+        self.sprite_batch.set(SpriteBatch {
+            pipeline: self.pipeline.take(),
+            descriptor_set_layout: self.descriptor_set_layout.take(),
+            texture_descriptor_sets: RefCell::new(Vec::new()),
+            vertex_buffer: RefCell::new(self.vertex_buffer.take()),
+            region_count: self.region_count.take(),
+            capacity: self.capacity_or_default(),
+            letterboxed: self.letterboxed.unwrap_or(true),
+            sprites: RefCell::new(Vec::new()),
+            projection: Cell::new(glm::Mat4::identity()),
+            version: Cell::new(0)
+        });
+    }
+}
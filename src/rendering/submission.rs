@@ -0,0 +1,251 @@
+use std::{
+    any::Any,
+    marker::PhantomData,
+    rc::Rc
+};
+use ash::{
+    version::DeviceV1_0,
+    vk
+};
+use crate::{
+    rendering::{
+        RenderingError,
+        RenderingResult
+    },
+    vulkan::{
+        handle::VulkanHandle,
+        logical_device::LogicalDevice,
+        physical_device::QueueFamily,
+        command_buffer::{
+            QueueType,
+            GraphicsQueue,
+            QueueCommandBuffer
+        },
+        synchronization::Semaphore
+    }
+};
+
+// Command buffers executed on a queue of the family of `Q` after waiting for semaphores,
+// which signal other semaphores once they finish
+pub struct Submission<Q: QueueType = GraphicsQueue> {
+    wait_semaphores: Vec<vk::Semaphore>,
+    wait_stages: Vec<vk::PipelineStageFlags>,
+    command_buffers: Vec<vk::CommandBuffer>,
+    signal_semaphores: Vec<vk::Semaphore>,
+    // Kept alive until the submission finishes executing
+    semaphores_in_use: Vec<Rc<Semaphore>>,
+    command_buffers_in_use: Vec<Rc<QueueCommandBuffer<Q>>>,
+    _queue: PhantomData<Q>
+}
+
+impl<Q: QueueType> Submission<Q> {
+    pub fn new() -> Self {
+        Self {
+            wait_semaphores: Vec::new(),
+            wait_stages: Vec::new(),
+            command_buffers: Vec::new(),
+            signal_semaphores: Vec::new(),
+            semaphores_in_use: Vec::new(),
+            command_buffers_in_use: Vec::new(),
+            _queue: PhantomData
+        }
+    }
+
+    pub fn queue_family(&self) -> QueueFamily {
+        Q::FAMILY
+    }
+
+    // Only the given stages of the command buffers wait, earlier ones may start right away
+    pub fn wait(self, semaphore: &Rc<Semaphore>, stages: vk::PipelineStageFlags) -> Self {
+        let mut submission = self.wait_handle(semaphore.handle(), stages);
+        submission.semaphores_in_use.push(Rc::clone(semaphore));
+        submission
+    }
+
+    // Command buffers are executed in the order they were added in
+    pub fn command_buffer(self, command_buffer: &Rc<QueueCommandBuffer<Q>>) -> Self {
+        let mut submission = self.command_buffer_handle(command_buffer.handle());
+        submission.command_buffers_in_use.push(Rc::clone(command_buffer));
+        submission
+    }
+
+    pub fn signal(self, semaphore: &Rc<Semaphore>) -> Self {
+        let mut submission = self.signal_handle(semaphore.handle());
+        submission.semaphores_in_use.push(Rc::clone(semaphore));
+        submission
+    }
+
+    // Handles of objects the renderer itself keeps alive until the submission finishes
+    pub(crate) fn wait_handle(
+        mut self,
+        semaphore: vk::Semaphore,
+        stages: vk::PipelineStageFlags
+    ) -> Self {
+        self.wait_semaphores.push(semaphore);
+        self.wait_stages.push(stages);
+        self
+    }
+
+    pub(crate) fn command_buffer_handle(mut self, command_buffer: vk::CommandBuffer) -> Self {
+        self.command_buffers.push(command_buffer);
+        self
+    }
+
+    pub(crate) fn signal_handle(mut self, semaphore: vk::Semaphore) -> Self {
+        self.signal_semaphores.push(semaphore);
+        self
+    }
+
+    fn schedule(self) -> ScheduledSubmission {
+        ScheduledSubmission {
+            queue_family: Q::FAMILY,
+            wait_semaphores: self.wait_semaphores,
+            wait_stages: self.wait_stages,
+            command_buffers: self.command_buffers,
+            signal_semaphores: self.signal_semaphores,
+            _resources_in_use: Box::new((self.semaphores_in_use, self.command_buffers_in_use))
+        }
+    }
+}
+
+impl<Q: QueueType> Default for Submission<Q> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Submission whose queue family is only known at runtime, so that submissions
+// to different queues can be scheduled together
+pub struct ScheduledSubmission {
+    queue_family: QueueFamily,
+    wait_semaphores: Vec<vk::Semaphore>,
+    wait_stages: Vec<vk::PipelineStageFlags>,
+    command_buffers: Vec<vk::CommandBuffer>,
+    signal_semaphores: Vec<vk::Semaphore>,
+    // lifetime extenders
+    _resources_in_use: Box<dyn Any>
+}
+
+// Collects submissions and submits them with as few vkQueueSubmit calls as possible.
+// Consecutive submissions to the same queue share a call, and share a single
+// `vk::SubmitInfo` too unless a semaphore has to be waited for or signalled between them.
+// Submissions are submitted in the order they were pushed in, so a submission can wait
+// for semaphores signalled by earlier ones on other queues.
+pub struct SubmissionScheduler {
+    submissions: Vec<ScheduledSubmission>
+}
+
+struct SubmitBatch<'a> {
+    wait_semaphores: Vec<vk::Semaphore>,
+    wait_stages: Vec<vk::PipelineStageFlags>,
+    command_buffers: Vec<vk::CommandBuffer>,
+    signal_semaphores: &'a [vk::Semaphore]
+}
+
+impl SubmissionScheduler {
+    pub fn new() -> Self {
+        Self {
+            submissions: Vec::new()
+        }
+    }
+
+    pub fn push<Q: QueueType>(&mut self, submission: Submission<Q>) {
+        self.submissions.push(submission.schedule());
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.submissions.is_empty()
+    }
+
+    // The fence is signalled by the last call, once the last queue finishes its submissions.
+    // Returns the submitted submissions, which have to be kept alive until they finish.
+    pub fn flush(
+        &mut self,
+        logical_device: &LogicalDevice,
+        fence: vk::Fence
+    ) -> RenderingResult<Vec<ScheduledSubmission>> {
+        let submissions = std::mem::take(&mut self.submissions);
+        let mut start = 0;
+
+        while start < submissions.len() {
+            let queue_family = submissions[start].queue_family;
+            let end = submissions[start..]
+                .iter()
+                .position(|submission| submission.queue_family != queue_family)
+                .map_or(submissions.len(), |length| start + length);
+
+            let call_fence = if end == submissions.len() {
+                fence
+            }
+            else {
+                vk::Fence::null()
+            };
+
+            Self::submit_to_queue(logical_device, &submissions[start..end], call_fence)?;
+            start = end;
+        }
+
+        Ok(submissions)
+    }
+
+    fn submit_to_queue(
+        logical_device: &LogicalDevice,
+        submissions: &[ScheduledSubmission],
+        fence: vk::Fence
+    ) -> RenderingResult<()> {
+        let queue = logical_device.device_queue(submissions[0].queue_family)?;
+        let batches = Self::batches(submissions);
+        let submit_infos: Vec<vk::SubmitInfo> = batches
+            .iter()
+            .map(|batch| vk::SubmitInfo::builder()
+                .wait_semaphores(&batch.wait_semaphores)
+                .wait_dst_stage_mask(&batch.wait_stages)
+                .command_buffers(&batch.command_buffers)
+                .signal_semaphores(batch.signal_semaphores)
+                .build())
+            .collect();
+
+        trace!(
+            "submitting {} submissions to {:?} queue as {} batches",
+            submissions.len(), submissions[0].queue_family, submit_infos.len());
+
+        unsafe {
+            logical_device.queue_submit(queue, &submit_infos, fence)
+        }.map_err(|result| RenderingError::QueueSubmitError {result})?;
+
+        Ok(())
+    }
+
+    // Waits would hold back command buffers of the batch which come before them,
+    // and signals would wait for the ones after them, so both end a batch
+    fn batches(submissions: &[ScheduledSubmission]) -> Vec<SubmitBatch<'_>> {
+        let mut batches: Vec<SubmitBatch> = Vec::new();
+
+        for submission in submissions {
+            let can_join_last = submission.wait_semaphores.is_empty()
+                && batches.last().map_or(false, |batch| batch.signal_semaphores.is_empty());
+
+            if can_join_last {
+                let batch = batches.last_mut().unwrap();
+                batch.command_buffers.extend_from_slice(&submission.command_buffers);
+                batch.signal_semaphores = &submission.signal_semaphores;
+            }
+            else {
+                batches.push(SubmitBatch {
+                    wait_semaphores: submission.wait_semaphores.clone(),
+                    wait_stages: submission.wait_stages.clone(),
+                    command_buffers: submission.command_buffers.clone(),
+                    signal_semaphores: &submission.signal_semaphores
+                });
+            }
+        }
+
+        batches
+    }
+}
+
+impl Default for SubmissionScheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
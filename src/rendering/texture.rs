@@ -0,0 +1,340 @@
+use std::rc::Rc;
+use ash::vk;
+use image::RgbaImage;
+use crate::{
+    builder::{
+        BuilderRequirement,
+        BuilderInternal,
+        BuilderProduct
+    },
+    rendering::{
+        RenderingError,
+        RenderingResult,
+        renderer::Renderer
+    },
+    vulkan::{
+        allocator::MemoryUsage,
+        buffer::Buffer,
+        image::Image,
+        sampler::Sampler,
+        barrier::{
+            PipelineBarrier,
+            MemoryAccess,
+            ImageBarrier
+        }
+    }
+};
+
+// Array of equally sized images sampled through a single `sampler2DArray`,
+// e.g. one layer per block texture, so that a whole chunk can be drawn with one binding.
+// Cube maps are sampled through a `samplerCube` instead.
+#[derive(Clone)]
+pub struct Texture {
+    image: Rc<Image>,
+    sampler: Rc<Sampler>
+}
+
+impl Texture {
+    const FORMAT: vk::Format = vk::Format::R8G8B8A8_SRGB;
+
+    pub fn array_builder<'a>() -> TextureArrayBuilder<'a> {
+        TextureArrayBuilder {
+            ..Default::default()
+        }
+    }
+
+    // For images filled by the GPU, e.g. shadow maps, which are not uploaded as layers
+    pub(crate) fn from_parts(image: Rc<Image>, sampler: Rc<Sampler>) -> Self {
+        Self {
+            image,
+            sampler
+        }
+    }
+
+    pub fn image(&self) -> &Rc<Image> {
+        &self.image
+    }
+
+    pub fn sampler(&self) -> &Rc<Sampler> {
+        &self.sampler
+    }
+
+    pub fn layer_count(&self) -> usize {
+        self.image.array_layers() as usize
+    }
+
+    pub fn is_cube_map(&self) -> bool {
+        self.image.is_cube_map()
+    }
+
+    // Replaces a single layer and regenerates its mip levels.
+    // Waits for the upload to finish, frames still in flight see either the old or the new layer.
+    pub fn upload_layer(
+        &self,
+        renderer: &Renderer,
+        layer: usize,
+        data: &RgbaImage
+    ) -> RenderingResult<()> {
+        if layer >= self.layer_count() {
+            return Err(RenderingError::TextureLayerOutOfRange {
+                layer,
+                layer_count: self.layer_count()
+            });
+        }
+
+        Self::check_layer_size(&self.image, layer, data)?;
+        Self::upload(renderer, &self.image, layer as u32, &[data])
+    }
+
+    fn check_layer_size(image: &Image, layer: usize, data: &RgbaImage) -> RenderingResult<()> {
+        let extent = image.extent();
+        if data.width() != extent.width || data.height() != extent.height {
+            return Err(RenderingError::TextureLayerSizeMismatch {
+                layer,
+                width: data.width(),
+                height: data.height()
+            });
+        }
+
+        Ok(())
+    }
+
+    // Copies consecutive layers starting at `first_layer` and downsamples them into
+    // all mip levels, leaving them in the shader read only layout
+    fn upload(
+        renderer: &Renderer,
+        image: &Image,
+        first_layer: u32,
+        layers: &[&RgbaImage]
+    ) -> RenderingResult<()> {
+        let layer_size = layers[0].len();
+        let mut staging_buffer = Buffer::builder()
+            .allocator(Rc::clone(renderer.allocator()))
+            .size(layer_size * layers.len())
+            .usage(vk::BufferUsageFlags::TRANSFER_SRC)
+            .memory_usage(MemoryUsage::CpuToGpu)
+            .host_mapped(true)
+            .build()?;
+
+        for (index, layer) in layers.iter().enumerate() {
+            let texels: &[u8] = layer;
+            staging_buffer.write_data(index * layer_size, texels)?;
+        }
+
+        let mip_levels = image.mip_levels();
+        let array_layers = first_layer..first_layer + layers.len() as u32;
+
+        renderer.execute_immediately(|recorder| {
+            // Previous frames may still be sampling the layers that are overwritten
+            let mut recorder = recorder.pipeline_barrier(&PipelineBarrier::new()
+                .image(ImageBarrier::new(
+                    image,
+                    (vk::ImageLayout::UNDEFINED, vk::ImageLayout::TRANSFER_DST_OPTIMAL),
+                    (MemoryAccess::fragment_shader_read(), MemoryAccess::transfer_write()))
+                    .array_layers(array_layers.clone())));
+
+            for (index, layer) in array_layers.clone().enumerate() {
+                recorder = recorder
+                    .copy_buffer_to_image(&staging_buffer, index * layer_size, image, layer);
+            }
+
+            for mip_level in 1..mip_levels {
+                recorder = recorder
+                    .pipeline_barrier(&PipelineBarrier::new()
+                        .image(ImageBarrier::new(
+                            image,
+                            (
+                                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                                vk::ImageLayout::TRANSFER_SRC_OPTIMAL
+                            ),
+                            (MemoryAccess::transfer_write(), MemoryAccess::transfer_read()))
+                            .mip_levels(mip_level - 1..mip_level)
+                            .array_layers(array_layers.clone())))
+                    .blit_mip_level(
+                        image,
+                        mip_level,
+                        array_layers.start,
+                        array_layers.len() as u32);
+            }
+
+            let mut final_barrier = PipelineBarrier::new()
+                .image(ImageBarrier::new(
+                    image,
+                    (
+                        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+                    ),
+                    (MemoryAccess::transfer_write(), MemoryAccess::fragment_shader_read()))
+                    .mip_levels(mip_levels - 1..mip_levels)
+                    .array_layers(array_layers.clone()));
+
+            // All levels but the last one were blitted from
+            if mip_levels > 1 {
+                final_barrier = final_barrier.image(ImageBarrier::new(
+                    image,
+                    (
+                        vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+                    ),
+                    (MemoryAccess::transfer_read(), MemoryAccess::fragment_shader_read()))
+                    .mip_levels(0..mip_levels - 1)
+                    .array_layers(array_layers.clone()));
+            }
+
+            recorder.pipeline_barrier(&final_barrier)
+        })
+    }
+}
+
+#[derive(Default)]
+pub struct TextureArrayBuilder<'a> {
+    renderer: BuilderRequirement<&'a Renderer>,
+    layers: Vec<&'a RgbaImage>,
+    mipmaps: Option<bool>,
+    filter: Option<vk::Filter>,
+    cube_map: bool,
+
+    mip_levels: BuilderInternal<u32>,
+    image: BuilderInternal<Rc<Image>>,
+    sampler: BuilderInternal<Rc<Sampler>>,
+
+    texture: BuilderProduct<Texture>
+}
+
+impl<'a> TextureArrayBuilder<'a> {
+    pub fn renderer(mut self, renderer: &'a Renderer) -> Self {
+        self.renderer.set(renderer);
+        self
+    }
+
+    // Layers are numbered in the order they are added in
+    pub fn layer(mut self, layer: &'a RgbaImage) -> Self {
+        self.layers.push(layer);
+        self
+    }
+
+    pub fn layers(mut self, layers: &'a [RgbaImage]) -> Self {
+        self.layers.extend(layers);
+        self
+    }
+
+    // Defaults to true
+    pub fn mipmaps(mut self, mipmaps: bool) -> Self {
+        self.mipmaps = Some(mipmaps);
+        self
+    }
+
+    // Defaults to `vk::Filter::LINEAR`, `NEAREST` keeps pixel art sharp up close
+    pub fn filter(mut self, filter: vk::Filter) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    // Builds a cube map from six square layers, the faces +X, -X, +Y, -Y, +Z and -Z
+    // in that order, e.g. loaded as `CubeMapData`
+    pub fn cube_map(mut self, cube_map: bool) -> Self {
+        self.cube_map = cube_map;
+        self
+    }
+
+    pub fn build(mut self) -> RenderingResult<Texture> {
+        check_required_fields!(self, RenderingError, "TextureArrayBuilder", [renderer]);
+
+        self.check_layers()?;
+        self.init_mip_levels();
+        self.init_image()?;
+        self.init_sampler()?;
+        self.upload_layers()?;
+        self.create_texture();
+
+        Ok(self.texture.unwrap())
+    }
+
+    fn check_layers(&self) -> RenderingResult<()> {
+        let first_layer = self.layers.first().ok_or(RenderingError::TextureWithoutLayers)?;
+
+        match self.layers.iter().position(|layer| layer.dimensions() != first_layer.dimensions()) {
+            Some(layer) => Err(RenderingError::TextureLayerSizeMismatch {
+                layer,
+                width: self.layers[layer].width(),
+                height: self.layers[layer].height()
+            }),
+            None => Ok(())
+        }
+    }
+
+    fn init_mip_levels(&mut self) {
+        let (width, height) = self.layers[0].dimensions();
+        let full_mip_chain = 32 - width.max(height).leading_zeros();
+
+        let mip_levels = if !self.mipmaps.unwrap_or(true) {
+            1
+        }
+        else if !self.supports_blitting() {
+            warn!(
+                "texture format {:?} does not support linear blitting, mipmaps are disabled",
+                Texture::FORMAT);
+            1
+        }
+        else {
+            full_mip_chain
+        };
+
+        self.mip_levels.set(mip_levels);
+    }
+
+    fn supports_blitting(&self) -> bool {
+        let required_features = vk::FormatFeatureFlags::BLIT_SRC
+            | vk::FormatFeatureFlags::BLIT_DST
+            | vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR;
+
+        self.renderer
+            .physical_device()
+            .format_properties(Texture::FORMAT)
+            .optimal_tiling_features
+            .contains(required_features)
+    }
+
+    fn init_image(&mut self) -> RenderingResult<()> {
+        let (width, height) = self.layers[0].dimensions();
+        let image = Image::builder()
+            .allocator(Rc::clone(self.renderer.allocator()))
+            .logical_device(Rc::clone(self.renderer.logical_device()))
+            .format(Texture::FORMAT)
+            .extent(vk::Extent2D {width, height})
+            .usage(
+                vk::ImageUsageFlags::TRANSFER_SRC
+                | vk::ImageUsageFlags::TRANSFER_DST
+                | vk::ImageUsageFlags::SAMPLED)
+            .mip_levels(*self.mip_levels)
+            .array_layers(self.layers.len() as u32)
+            .cube_map(self.cube_map)
+            .build()?;
+
+        self.image.set(Rc::new(image));
+
+        Ok(())
+    }
+
+    fn init_sampler(&mut self) -> RenderingResult<()> {
+        let sampler = Sampler::new(
+            Rc::clone(self.renderer.logical_device()),
+            self.filter.unwrap_or(vk::Filter::LINEAR),
+            *self.mip_levels)?;
+
+        self.sampler.set(Rc::new(sampler));
+
+        Ok(())
+    }
+
+    fn upload_layers(&self) -> RenderingResult<()> {
+        Texture::upload(&self.renderer, &self.image, 0, &self.layers)
+    }
+
+    fn create_texture(&mut self) {
+        self.texture.set(Texture {
+            image: self.image.take(),
+            sampler: self.sampler.take()
+        });
+    }
+}
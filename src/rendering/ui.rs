@@ -0,0 +1,549 @@
+use std::{
+    cell::{
+        Cell,
+        RefCell
+    },
+    rc::Rc
+};
+use ash::vk;
+use image::{
+    Rgba,
+    RgbaImage
+};
+use nalgebra_glm as glm;
+use crate::{
+    builder::{
+        BuilderRequirement,
+        BuilderInternal,
+        BuilderProduct
+    },
+    rendering::{
+        RenderingError,
+        RenderingResult,
+        renderer::Renderer,
+        texture::Texture,
+        sprite::{
+            SpriteRegion,
+            SpriteVertex,
+            SpriteConstants
+        },
+        custom_pass::{
+            CustomPass,
+            CustomPassContext
+        }
+    },
+    vulkan::{
+        allocator::MemoryUsage,
+        buffer::Buffer,
+        render_pass::RenderPass,
+        framebuffers::Framebuffers,
+        descriptor::{
+            DescriptorSet,
+            DescriptorSetLayout
+        },
+        pipeline::{
+            Pipeline,
+            BlendMode,
+            VertexAttributeFormat
+        },
+        shader::{
+            VertexShader,
+            FragmentShader,
+            ShaderStage
+        },
+        command_buffer::CommandBufferRecorder
+    }
+};
+
+// Rectangle in framebuffer pixels, with y pointing down from the top left corner.
+// On HiDPI displays these are more than the window's logical pixels, see `scaled`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct UiRect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32
+}
+
+impl UiRect {
+    pub fn new(x: f32, y: f32, width: f32, height: f32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height
+        }
+    }
+
+    // Turns a rectangle in logical pixels, e.g. of a layout designed at 100% scale,
+    // into framebuffer pixels, given the window's content scale
+    pub fn scaled(&self, (x_scale, y_scale): (f32, f32)) -> Self {
+        Self::new(self.x * x_scale, self.y * y_scale, self.width * x_scale, self.height * y_scale)
+    }
+
+    // Empty rectangles have no width or height
+    pub fn intersection(&self, other: &UiRect) -> Self {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = (self.x + self.width).min(other.x + other.width);
+        let bottom = (self.y + self.height).min(other.y + other.height);
+
+        Self::new(x, y, (right - x).max(0.0), (bottom - y).max(0.0))
+    }
+
+    // Pixels covered at least partially, confined to the area
+    fn to_scissor(self, area: vk::Rect2D) -> vk::Rect2D {
+        let clamp = |value: f32, min: i32, max: i32| (value as i32).max(min).min(max);
+        let area_right = area.offset.x + area.extent.width as i32;
+        let area_bottom = area.offset.y + area.extent.height as i32;
+
+        let x = clamp(self.x.floor(), area.offset.x, area_right);
+        let y = clamp(self.y.floor(), area.offset.y, area_bottom);
+        let right = clamp((self.x + self.width).ceil(), x, area_right);
+        let bottom = clamp((self.y + self.height).ceil(), y, area_bottom);
+
+        vk::Rect2D {
+            offset: vk::Offset2D {x, y},
+            extent: vk::Extent2D {
+                width: (right - x) as u32,
+                height: (bottom - y) as u32
+            }
+        }
+    }
+}
+
+// Index of a texture added to a UI pass
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct UiTextureId(usize);
+
+#[derive(Clone, Copy)]
+struct UiQuad {
+    rect: UiRect,
+    texture: UiTextureId,
+    region: SpriteRegion,
+    color: [f32; 4],
+    scissor: Option<UiRect>
+}
+
+// HUD and menus drawn in a render pass of their own after everything else, with an
+// orthographic projection in framebuffer pixels and its own blend state. Quads drawn during
+// a frame are recorded in the order they were drawn in, clipped by the scissor rectangles
+// pushed at the time, by registering the pass as a custom pass at
+// `CustomPassPoint::AfterMainPass`, then cleared.
+//
+// The vertex layout, push constants and descriptor set are the ones of `SpriteBatch`,
+// so shaders/sprite.vert and shaders/sprite.frag can draw the UI as well. Untextured quads
+// sample a white texel. The pass draws into the swapchain's images, so it is built
+// anew with the renderer.
+pub struct UiPass {
+    render_pass: Rc<RenderPass>,
+    framebuffers: Framebuffers,
+    pipeline: Rc<Pipeline>,
+    descriptor_set_layout: Rc<DescriptorSetLayout>,
+    texture_descriptor_sets: RefCell<Vec<Rc<DescriptorSet>>>,
+    // One region per frame in flight and one for the frame being recorded
+    vertex_buffer: RefCell<Buffer>,
+    region_count: usize,
+    capacity: usize,
+    quads: RefCell<Vec<UiQuad>>,
+    scissors: RefCell<Vec<UiRect>>,
+    version: Cell<u64>
+}
+
+impl UiPass {
+    const VERTICES_PER_QUAD: usize = 6;
+    const CORNERS: [(f32, f32); 6] =
+        [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (1.0, 1.0), (0.0, 1.0), (0.0, 0.0)];
+    const WHITE_TEXTURE: UiTextureId = UiTextureId(0);
+
+    pub fn builder<'a>() -> UiPassBuilder<'a> {
+        UiPassBuilder {
+            ..Default::default()
+        }
+    }
+
+    // Textures are never removed, so atlases should be added once and shared
+    pub fn add_texture(&self, texture: &Texture) -> RenderingResult<UiTextureId> {
+        let descriptor_set = create_texture_descriptor_set(&self.descriptor_set_layout, texture)?;
+        let mut texture_descriptor_sets = self.texture_descriptor_sets.borrow_mut();
+        texture_descriptor_sets.push(Rc::new(descriptor_set));
+
+        Ok(UiTextureId(texture_descriptor_sets.len() - 1))
+    }
+
+    // Maps framebuffer pixels with y pointing down to Vulkan's clip space
+    pub fn projection(extent: vk::Extent2D) -> glm::Mat4 {
+        glm::ortho_rh_zo(0.0, extent.width as f32, 0.0, extent.height as f32, 0.0, 1.0)
+    }
+
+    // Colors are RGBA
+    pub fn draw_rect(&self, rect: &UiRect, color: [f32; 4]) {
+        self.push_quad(rect, Self::WHITE_TEXTURE, SpriteRegion::full(0), color);
+    }
+
+    // The region's texture colors are multiplied by the color
+    pub fn draw_image(
+        &self,
+        rect: &UiRect,
+        texture: UiTextureId,
+        region: &SpriteRegion,
+        color: [f32; 4]
+    ) -> RenderingResult<()> {
+        if texture.0 >= self.texture_descriptor_sets.borrow().len() {
+            return Err(RenderingError::UnknownUiTexture {texture: texture.0});
+        }
+
+        self.push_quad(rect, texture, *region, color);
+
+        Ok(())
+    }
+
+    // Quads drawn until the matching `pop_scissor` are clipped to the rectangle,
+    // nested rectangles are clipped to the ones pushed before them
+    pub fn push_scissor(&self, rect: &UiRect) {
+        let mut scissors = self.scissors.borrow_mut();
+        let scissor = match scissors.last() {
+            Some(parent) => parent.intersection(rect),
+            None => *rect
+        };
+
+        scissors.push(scissor);
+    }
+
+    pub fn pop_scissor(&self) {
+        let popped = self.scissors.borrow_mut().pop();
+        debug_assert!(popped.is_some(), "popped a scissor rectangle which was never pushed");
+    }
+
+    pub fn quad_count(&self) -> usize {
+        self.quads.borrow().len()
+    }
+
+    // Drops everything drawn since the last frame without recording it
+    pub fn clear(&self) {
+        self.quads.borrow_mut().clear();
+        self.scissors.borrow_mut().clear();
+    }
+
+    // Quads beyond the capacity of a frame are dropped
+    fn push_quad(
+        &self,
+        rect: &UiRect,
+        texture: UiTextureId,
+        region: SpriteRegion,
+        color: [f32; 4]
+    ) {
+        let mut quads = self.quads.borrow_mut();
+
+        if quads.len() == self.capacity {
+            return;
+        }
+
+        quads.push(UiQuad {
+            rect: *rect,
+            texture,
+            region,
+            color,
+            scissor: self.scissors.borrow().last().copied()
+        });
+    }
+
+    fn vertices(quads: &[UiQuad]) -> Vec<SpriteVertex> {
+        let mut vertices = Vec::with_capacity(quads.len() * Self::VERTICES_PER_QUAD);
+
+        for quad in quads {
+            let (rect, region) = (&quad.rect, &quad.region);
+
+            for &(x, y) in Self::CORNERS.iter() {
+                let uv = region.uv_min
+                    + glm::vec2(x, y).component_mul(&(region.uv_max - region.uv_min));
+
+                vertices.push(SpriteVertex {
+                    position: [rect.x + x * rect.width, rect.y + y * rect.height, 0.0],
+                    uv: [uv.x, uv.y, region.layer as f32],
+                    color: quad.color
+                });
+            }
+        }
+
+        vertices
+    }
+}
+
+impl CustomPass for UiPass {
+    fn record<'a>(
+        &self,
+        recorder: CommandBufferRecorder<'a>,
+        context: &CustomPassContext
+    ) -> CommandBufferRecorder<'a> {
+        let mut quads = self.quads.borrow_mut();
+        self.version.set(self.version.get() + 1);
+
+        if !self.scissors.borrow().is_empty() {
+            warn!("UI scissor rectangles were pushed without being popped");
+            self.scissors.borrow_mut().clear();
+        }
+
+        if quads.is_empty() {
+            return recorder;
+        }
+
+        let vertex_size = std::mem::size_of::<SpriteVertex>();
+        let region = (context.frame_number % self.region_count as u64) as usize;
+        let region_offset = region * self.capacity * Self::VERTICES_PER_QUAD * vertex_size;
+        let vertices = Self::vertices(&quads);
+        let data = unsafe {
+            std::slice::from_raw_parts(
+                vertices.as_ptr() as *const u8,
+                vertices.len() * vertex_size)
+        };
+
+        if let Err(error) = self.vertex_buffer.borrow_mut().write_data(region_offset, data) {
+            error!("failed to upload UI quads: {}", error);
+            quads.clear();
+            return recorder;
+        }
+
+        let constants = SpriteConstants {
+            projection: Self::projection(context.full_area.extent).into()
+        };
+
+        let mut recorder = recorder
+            .begin_render_pass(&self.render_pass, &self.framebuffers, context.image_index)
+            .set_viewport(context.full_area)
+            .bind_pipeline(Rc::clone(&self.pipeline))
+            .push_constant(&self.pipeline, ShaderStage::Vertex, &constants);
+
+        let vertex_buffer = self.vertex_buffer.borrow();
+        let texture_descriptor_sets = self.texture_descriptor_sets.borrow();
+        let mut first = 0;
+
+        // Quads keep their order, consecutive ones sharing a texture and a scissor
+        // rectangle are drawn together
+        while first < quads.len() {
+            let (texture, scissor) = (quads[first].texture, quads[first].scissor);
+            let count = quads[first..]
+                .iter()
+                .take_while(|quad| quad.texture == texture && quad.scissor == scissor)
+                .count();
+            let offset = region_offset + first * Self::VERTICES_PER_QUAD * vertex_size;
+            let scissor = scissor
+                .map(|scissor| scissor.to_scissor(context.full_area))
+                .unwrap_or(context.full_area);
+
+            recorder = recorder
+                .set_scissor(scissor)
+                .bind_descriptor_set(
+                    &self.pipeline, 0, Rc::clone(&texture_descriptor_sets[texture.0]))
+                .bind_vertex_buffer_at(0, &vertex_buffer, offset)
+                .draw((count * Self::VERTICES_PER_QUAD) as u32, 1);
+
+            first += count;
+        }
+
+        quads.clear();
+
+        recorder.end_render_pass()
+    }
+
+    // Quads are drawn anew every frame
+    fn version(&self) -> u64 {
+        self.version.get()
+    }
+}
+
+fn create_texture_descriptor_set(
+    descriptor_set_layout: &Rc<DescriptorSetLayout>,
+    texture: &Texture
+) -> RenderingResult<DescriptorSet> {
+    let mut descriptor_set = DescriptorSet::new(Rc::clone(descriptor_set_layout))?;
+    descriptor_set.write_combined_image_sampler(
+        0,
+        Rc::clone(texture.image()),
+        Rc::clone(texture.sampler()))?;
+
+    Ok(descriptor_set)
+}
+
+#[derive(Default)]
+pub struct UiPassBuilder<'a> {
+    renderer: BuilderRequirement<&'a Renderer>,
+    vertex_shader: BuilderRequirement<&'a VertexShader>,
+    fragment_shader: BuilderRequirement<&'a FragmentShader>,
+    capacity: Option<usize>,
+    blend_mode: Option<BlendMode>,
+
+    region_count: BuilderInternal<usize>,
+    render_pass: BuilderInternal<Rc<RenderPass>>,
+    framebuffers: BuilderInternal<Framebuffers>,
+    descriptor_set_layout: BuilderInternal<Rc<DescriptorSetLayout>>,
+    white_texture: BuilderInternal<Rc<DescriptorSet>>,
+    pipeline: BuilderInternal<Rc<Pipeline>>,
+    vertex_buffer: BuilderInternal<Buffer>,
+
+    ui_pass: BuilderProduct<UiPass>
+}
+
+impl<'a> UiPassBuilder<'a> {
+    const DEFAULT_CAPACITY: usize = 4 * 1024;
+
+    pub fn renderer(mut self, renderer: &'a Renderer) -> Self {
+        self.renderer.set(renderer);
+        self
+    }
+
+    pub fn vertex_shader(mut self, vertex_shader: &'a VertexShader) -> Self {
+        self.vertex_shader.set(vertex_shader);
+        self
+    }
+
+    pub fn fragment_shader(mut self, fragment_shader: &'a FragmentShader) -> Self {
+        self.fragment_shader.set(fragment_shader);
+        self
+    }
+
+    // Quads per frame
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = Some(capacity);
+        self
+    }
+
+    // Defaults to alpha blending, regardless of how the main pass blends
+    pub fn blend_mode(mut self, blend_mode: BlendMode) -> Self {
+        self.blend_mode = Some(blend_mode);
+        self
+    }
+
+    pub fn build(mut self) -> RenderingResult<UiPass> {
+        check_required_fields!(
+            self, RenderingError, "UiPassBuilder",
+            [renderer, vertex_shader, fragment_shader]);
+
+        self.init_region_count();
+        self.init_render_pass()?;
+        self.init_framebuffers()?;
+        self.init_descriptor_set_layout()?;
+        self.init_white_texture()?;
+        self.init_pipeline()?;
+        self.init_vertex_buffer()?;
+        self.create_ui_pass();
+
+        Ok(self.ui_pass.unwrap())
+    }
+
+    fn capacity_or_default(&self) -> usize {
+        self.capacity.unwrap_or(Self::DEFAULT_CAPACITY)
+    }
+
+    fn init_region_count(&mut self) {
+        self.region_count.set(Renderer::FRAMES_IN_FLIGHT + 1);
+    }
+
+    // Draws over what the main pass and the custom passes before it left in the image
+    fn init_render_pass(&mut self) -> RenderingResult<()> {
+        let render_pass = RenderPass::builder()
+            .logical_device(Rc::clone(self.renderer.logical_device()))
+            .swapchain(Rc::clone(self.renderer.swapchain()))
+            .load_op(vk::AttachmentLoadOp::LOAD)
+            .build()?;
+
+        self.render_pass.set(Rc::new(render_pass));
+
+        Ok(())
+    }
+
+    fn init_framebuffers(&mut self) -> RenderingResult<()> {
+        let framebuffers = Framebuffers::builder()
+            .logical_device(Rc::clone(self.renderer.logical_device()))
+            .swapchain(Rc::clone(self.renderer.swapchain()))
+            .render_pass(Rc::clone(&self.render_pass))
+            .build()?;
+
+        self.framebuffers.set(framebuffers);
+
+        Ok(())
+    }
+
+    fn init_descriptor_set_layout(&mut self) -> RenderingResult<()> {
+        let descriptor_set_layout = DescriptorSetLayout::builder()
+            .logical_device(Rc::clone(self.renderer.logical_device()))
+            .combined_image_sampler(0, &[ShaderStage::Fragment])
+            .build()?;
+
+        self.descriptor_set_layout.set(Rc::new(descriptor_set_layout));
+
+        Ok(())
+    }
+
+    fn init_white_texture(&mut self) -> RenderingResult<()> {
+        let white = RgbaImage::from_pixel(1, 1, Rgba([255, 255, 255, 255]));
+        let texture = Texture::array_builder()
+            .renderer(*self.renderer)
+            .layer(&white)
+            .mipmaps(false)
+            .build()?;
+
+        let descriptor_set = create_texture_descriptor_set(&self.descriptor_set_layout, &texture)?;
+        self.white_texture.set(Rc::new(descriptor_set));
+
+        Ok(())
+    }
+
+    fn init_pipeline(&mut self) -> RenderingResult<()> {
+        let float_size = std::mem::size_of::<f32>();
+        let pipeline = Pipeline::builder()
+            .logical_device(Rc::clone(self.renderer.logical_device()))
+            .swapchain(Rc::clone(self.renderer.swapchain()))
+            .render_pass(Rc::clone(&self.render_pass))
+            .subpass(0)
+            .vertex_shader(*self.vertex_shader)
+            .fragment_shader(*self.fragment_shader)
+            .blend_mode(self.blend_mode.unwrap_or(BlendMode::AlphaBlend))
+            .descriptor_set_layout(Rc::clone(&self.descriptor_set_layout))
+            .vertex_attribute_description(VertexAttributeFormat::F32(3), 0)
+            .vertex_attribute_description(VertexAttributeFormat::F32(3), 3 * float_size)
+            .vertex_attribute_description(VertexAttributeFormat::F32(4), 6 * float_size)
+            .vertex_binding_stride(std::mem::size_of::<SpriteVertex>())
+            .push_constants_size(ShaderStage::Vertex, std::mem::size_of::<SpriteConstants>())
+            .build()?;
+
+        self.pipeline.set(Rc::new(pipeline));
+
+        Ok(())
+    }
+
+    fn init_vertex_buffer(&mut self) -> RenderingResult<()> {
+        let size = *self.region_count
+            * self.capacity_or_default()
+            * UiPass::VERTICES_PER_QUAD
+            * std::mem::size_of::<SpriteVertex>();
+
+        let vertex_buffer = Buffer::builder()
+            .allocator(Rc::clone(self.renderer.allocator()))
+            .size(size.max(1))
+            .usage(vk::BufferUsageFlags::VERTEX_BUFFER)
+            .memory_usage(MemoryUsage::CpuToGpu)
+            .host_mapped(true)
+            .build()?;
+
+        self.vertex_buffer.set(vertex_buffer);
+
+        Ok(())
+    }
+
+    fn create_ui_pass(&mut self) {
+        self.ui_pass.set(UiPass {
+            render_pass: self.render_pass.take(),
+            framebuffers: self.framebuffers.take(),
+            pipeline: self.pipeline.take(),
+            descriptor_set_layout: self.descriptor_set_layout.take(),
+            texture_descriptor_sets: RefCell::new(vec![self.white_texture.take()]),
+            vertex_buffer: RefCell::new(self.vertex_buffer.take()),
+            region_count: self.region_count.take(),
+            capacity: self.capacity_or_default(),
+            quads: RefCell::new(Vec::new()),
+            scissors: RefCell::new(Vec::new()),
+            version: Cell::new(0)
+        });
+    }
+}
@@ -0,0 +1,238 @@
+use std::{
+    cell::RefCell,
+    rc::Rc
+};
+use ash::vk;
+use crate::{
+    builder::{
+        BuilderRequirement,
+        BuilderInternal,
+        BuilderProduct
+    },
+    rendering::{
+        RenderingError,
+        RenderingResult,
+        renderer::Renderer
+    },
+    vulkan::{
+        allocator::MemoryUsage,
+        buffer::Buffer,
+        descriptor::{
+            DescriptorSet,
+            DescriptorSetLayout
+        },
+        shader::ShaderStage
+    }
+};
+
+// Per-object uniform data, e.g. transforms, written anew every frame into a single buffer
+// and bound through one descriptor set with a dynamic uniform buffer at different offsets.
+// The buffer has a region for every frame in flight and one more for the frame being
+// prepared, so writing never touches data a submitted frame may still read.
+//
+// Every frame starts with `begin_frame`, after which `push` returns the offset to bind
+// the set at with `CommandBufferRecorder::bind_descriptor_set_at`. The offsets are only
+// valid in the frame they were pushed in, so command buffers using them have to be
+// recorded every frame, e.g. with `RecordingMode::EveryFrame` or in custom passes.
+pub struct UniformRing {
+    buffer: Rc<RefCell<Buffer>>,
+    descriptor_set_layout: Rc<DescriptorSetLayout>,
+    descriptor_set: Rc<DescriptorSet>,
+    element_size: usize,
+    // Element size rounded up to `minUniformBufferOffsetAlignment`
+    stride: usize,
+    capacity: usize,
+    region_count: usize,
+    region: usize,
+    len: usize
+}
+
+impl UniformRing {
+    pub fn builder<'a>() -> UniformRingBuilder<'a> {
+        UniformRingBuilder {
+            ..Default::default()
+        }
+    }
+
+    // For `PipelineBuilder::descriptor_set_layout`
+    pub fn descriptor_set_layout(&self) -> &Rc<DescriptorSetLayout> {
+        &self.descriptor_set_layout
+    }
+
+    pub fn descriptor_set(&self) -> &Rc<DescriptorSet> {
+        &self.descriptor_set
+    }
+
+    // Elements which can be pushed in a single frame
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // Discards the elements of the previous frame and moves on to the region of the frame
+    // the renderer is about to render
+    pub fn begin_frame(&mut self, renderer: &Renderer) {
+        self.region = (renderer.frame_number() % self.region_count as u64) as usize;
+        self.len = 0;
+    }
+
+    // Returns the dynamic offset of the element
+    pub fn push<T: Copy>(&mut self, element: &T) -> RenderingResult<u32> {
+        let data = unsafe {
+            std::slice::from_raw_parts(element as *const T as *const u8, std::mem::size_of::<T>())
+        };
+
+        if data.len() > self.element_size {
+            return Err(RenderingError::UniformRingElementTooLarge {
+                size: data.len(),
+                element_size: self.element_size
+            });
+        }
+
+        if self.len == self.capacity {
+            return Err(RenderingError::UniformRingFull {capacity: self.capacity});
+        }
+
+        let offset = (self.region * self.capacity + self.len) * self.stride;
+        self.buffer.borrow_mut().write_data(offset, data)?;
+        self.len += 1;
+
+        Ok(offset as u32)
+    }
+}
+
+#[derive(Default)]
+pub struct UniformRingBuilder<'a> {
+    renderer: BuilderRequirement<&'a Renderer>,
+    element_size: BuilderRequirement<usize>,
+    capacity: BuilderRequirement<usize>,
+    binding: Option<u32>,
+    stages: Option<Vec<ShaderStage>>,
+
+    stride: BuilderInternal<usize>,
+    region_count: BuilderInternal<usize>,
+    buffer: BuilderInternal<Rc<RefCell<Buffer>>>,
+    descriptor_set_layout: BuilderInternal<Rc<DescriptorSetLayout>>,
+    descriptor_set: BuilderInternal<Rc<DescriptorSet>>,
+
+    uniform_ring: BuilderProduct<UniformRing>
+}
+
+impl<'a> UniformRingBuilder<'a> {
+    pub fn renderer(mut self, renderer: &'a Renderer) -> Self {
+        self.renderer.set(renderer);
+        self
+    }
+
+    // Size in bytes of the largest type which will be pushed
+    pub fn element_size(mut self, element_size: usize) -> Self {
+        self.element_size.set(element_size);
+        self
+    }
+
+    // Elements per frame
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity.set(capacity);
+        self
+    }
+
+    // Defaults to 0
+    pub fn binding(mut self, binding: u32) -> Self {
+        self.binding = Some(binding);
+        self
+    }
+
+    // Defaults to the vertex stage
+    pub fn stages(mut self, stages: &[ShaderStage]) -> Self {
+        self.stages = Some(stages.to_vec());
+        self
+    }
+
+    pub fn build(mut self) -> RenderingResult<UniformRing> {
+        check_required_fields!(
+            self, RenderingError, "UniformRingBuilder",
+            [renderer, element_size, capacity]);
+
+        self.init_stride();
+        self.init_region_count();
+        self.init_buffer()?;
+        self.init_descriptor_set_layout()?;
+        self.init_descriptor_set()?;
+        self.create_uniform_ring();
+
+        Ok(self.uniform_ring.unwrap())
+    }
+
+    fn init_stride(&mut self) {
+        let alignment = self.renderer
+            .physical_device()
+            .limits()
+            .min_uniform_buffer_offset_alignment as usize;
+        let alignment = alignment.max(1);
+
+        self.stride.set((*self.element_size + alignment - 1) / alignment * alignment);
+    }
+
+    fn init_region_count(&mut self) {
+        self.region_count.set(Renderer::FRAMES_IN_FLIGHT + 1);
+    }
+
+    fn init_buffer(&mut self) -> RenderingResult<()> {
+        let buffer = Buffer::builder()
+            .allocator(Rc::clone(self.renderer.allocator()))
+            .size((*self.stride * *self.capacity * *self.region_count).max(1))
+            .usage(vk::BufferUsageFlags::UNIFORM_BUFFER)
+            .memory_usage(MemoryUsage::CpuToGpu)
+            .host_mapped(true)
+            .build()?;
+
+        self.buffer.set(Rc::new(RefCell::new(buffer)));
+
+        Ok(())
+    }
+
+    fn init_descriptor_set_layout(&mut self) -> RenderingResult<()> {
+        let stages = self.stages.take().unwrap_or_else(|| vec![ShaderStage::Vertex]);
+        let descriptor_set_layout = DescriptorSetLayout::builder()
+            .logical_device(Rc::clone(self.renderer.logical_device()))
+            .dynamic_uniform_buffer(self.binding.unwrap_or(0), &stages)
+            .build()?;
+
+        self.descriptor_set_layout.set(Rc::new(descriptor_set_layout));
+
+        Ok(())
+    }
+
+    fn init_descriptor_set(&mut self) -> RenderingResult<()> {
+        let mut descriptor_set = DescriptorSet::new(Rc::clone(&self.descriptor_set_layout))?;
+        descriptor_set.write_dynamic_uniform_buffer(
+            self.binding.unwrap_or(0),
+            Rc::clone(&self.buffer),
+            *self.element_size as vk::DeviceSize)?;
+
+        self.descriptor_set.set(Rc::new(descriptor_set));
+
+        Ok(())
+    }
+
+    fn create_uniform_ring(&mut self) {
+        self.uniform_ring.set(UniformRing {
+            buffer: self.buffer.take(),
+            descriptor_set_layout: self.descriptor_set_layout.take(),
+            descriptor_set: self.descriptor_set.take(),
+            element_size: *self.element_size,
+            stride: self.stride.take(),
+            capacity: *self.capacity,
+            region_count: self.region_count.take(),
+            region: 0,
+            len: 0
+        });
+    }
+}
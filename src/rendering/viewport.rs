@@ -0,0 +1,305 @@
+use ash::vk;
+use crate::rendering::render_layers::RenderLayers;
+
+pub fn full_area(extent: vk::Extent2D) -> vk::Rect2D {
+    vk::Rect2D::builder()
+        .offset(vk::Offset2D::builder().x(0).y(0).build())
+        .extent(extent)
+        .build()
+}
+
+// Largest area of the given aspect ratio centered in `extent`,
+// leaving bars on the sides or on the top and bottom
+pub fn letterboxed_area(extent: vk::Extent2D, aspect_ratio: Option<f32>) -> vk::Rect2D {
+    let aspect_ratio = match aspect_ratio {
+        Some(aspect_ratio) if extent.width > 0 && extent.height > 0 => aspect_ratio,
+        _ => return full_area(extent)
+    };
+
+    let extent_aspect_ratio = extent.width as f32 / extent.height as f32;
+    let (width, height) = if extent_aspect_ratio > aspect_ratio {
+        ((extent.height as f32 * aspect_ratio).round() as u32, extent.height)
+    }
+    else {
+        (extent.width, (extent.width as f32 / aspect_ratio).round() as u32)
+    };
+
+    let offset = vk::Offset2D::builder()
+        .x(((extent.width - width) / 2) as i32)
+        .y(((extent.height - height) / 2) as i32)
+        .build();
+
+    let extent = vk::Extent2D::builder()
+        .width(width)
+        .height(height)
+        .build();
+
+    vk::Rect2D::builder()
+        .offset(offset)
+        .extent(extent)
+        .build()
+}
+
+// Part of the swapchain image the letterboxed layers are drawn to
+#[derive(Clone, Copy, Debug, Default)]
+pub enum ViewportArea {
+    #[default]
+    Full,
+    // Largest area of the given width to height ratio, see `letterboxed_area`
+    AspectRatio(f32),
+    // Largest whole multiple of the given resolution, so that every pixel of a pixel-art game
+    // covers the same number of screen pixels. Falls back to the resolution's aspect ratio
+    // when the image is smaller than the resolution itself.
+    IntegerScaled {width: u32, height: u32},
+    // Fixed rectangle in framebuffer pixels, e.g. of an editor's docked viewport,
+    // clipped to the image
+    Region(vk::Rect2D)
+}
+
+impl ViewportArea {
+    pub fn area(self, extent: vk::Extent2D) -> vk::Rect2D {
+        match self {
+            ViewportArea::Full => full_area(extent),
+            ViewportArea::AspectRatio(aspect_ratio) => letterboxed_area(extent, Some(aspect_ratio)),
+            ViewportArea::IntegerScaled {width, height} => {
+                integer_scaled_area(extent, width, height)
+            },
+            ViewportArea::Region(region) => clipped_area(extent, region)
+        }
+    }
+}
+
+fn integer_scaled_area(extent: vk::Extent2D, width: u32, height: u32) -> vk::Rect2D {
+    if width == 0 || height == 0 {
+        return full_area(extent);
+    }
+
+    let scale = std::cmp::min(extent.width / width, extent.height / height);
+    if scale == 0 {
+        return letterboxed_area(extent, Some(width as f32 / height as f32));
+    }
+
+    let (width, height) = (width * scale, height * scale);
+
+    let offset = vk::Offset2D::builder()
+        .x(((extent.width - width) / 2) as i32)
+        .y(((extent.height - height) / 2) as i32)
+        .build();
+
+    let extent = vk::Extent2D::builder()
+        .width(width)
+        .height(height)
+        .build();
+
+    vk::Rect2D::builder()
+        .offset(offset)
+        .extent(extent)
+        .build()
+}
+
+fn clipped_area(extent: vk::Extent2D, region: vk::Rect2D) -> vk::Rect2D {
+    let x = region.offset.x.max(0).min(extent.width as i32);
+    let y = region.offset.y.max(0).min(extent.height as i32);
+    let right = (region.offset.x as i64 + region.extent.width as i64)
+        .max(x as i64)
+        .min(extent.width as i64);
+    let bottom = (region.offset.y as i64 + region.extent.height as i64)
+        .max(y as i64)
+        .min(extent.height as i64);
+
+    let offset = vk::Offset2D::builder()
+        .x(x)
+        .y(y)
+        .build();
+
+    let extent = vk::Extent2D::builder()
+        .width((right - x as i64) as u32)
+        .height((bottom - y as i64) as u32)
+        .build();
+
+    vk::Rect2D::builder()
+        .offset(offset)
+        .extent(extent)
+        .build()
+}
+
+// One player's part of a split screen frame, all of whose layers are drawn into `area`.
+// Cameras are per viewport as far as the render states carry them, e.g. in push constants.
+pub struct SplitViewport<'a, 'b> {
+    pub area: vk::Rect2D,
+    pub render_layers: &'b RenderLayers<'a>
+}
+
+impl<'a, 'b> SplitViewport<'a, 'b> {
+    pub(crate) fn layers(&self, extent: vk::Extent2D) -> ViewportLayers<'a, 'b> {
+        let area = clipped_area(extent, self.area);
+
+        ViewportLayers {
+            full_area: area,
+            letterboxed_area: area,
+            render_layers: self.render_layers
+        }
+    }
+}
+
+// Areas of `count` viewports splitting the image evenly, side by side for two
+// and in a grid of rows filled left to right for more
+pub fn split_areas(extent: vk::Extent2D, count: usize) -> Vec<vk::Rect2D> {
+    if count == 0 {
+        return Vec::new();
+    }
+
+    let columns = (count as f32).sqrt().ceil() as u32;
+    let rows = (count as u32 + columns - 1) / columns;
+    let (width, height) = (extent.width / columns, extent.height / rows);
+
+    (0..count as u32)
+        .map(|index| {
+            let offset = vk::Offset2D::builder()
+                .x(((index % columns) * width) as i32)
+                .y(((index / columns) * height) as i32)
+                .build();
+
+            let extent = vk::Extent2D::builder()
+                .width(width)
+                .height(height)
+                .build();
+
+            vk::Rect2D::builder()
+                .offset(offset)
+                .extent(extent)
+                .build()
+        })
+        .collect()
+}
+
+// Draw list of one viewport with the areas of its letterboxed and other layers
+pub struct ViewportLayers<'a, 'b> {
+    pub full_area: vk::Rect2D,
+    pub letterboxed_area: vk::Rect2D,
+    pub render_layers: &'b RenderLayers<'a>
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn extent(width: u32, height: u32) -> vk::Extent2D {
+        vk::Extent2D::builder()
+            .width(width)
+            .height(height)
+            .build()
+    }
+
+    fn rect(x: i32, y: i32, width: u32, height: u32) -> vk::Rect2D {
+        vk::Rect2D::builder()
+            .offset(vk::Offset2D::builder().x(x).y(y).build())
+            .extent(extent(width, height))
+            .build()
+    }
+
+    fn parts(area: vk::Rect2D) -> (i32, i32, u32, u32) {
+        (area.offset.x, area.offset.y, area.extent.width, area.extent.height)
+    }
+
+    #[test]
+    fn letterboxes_to_aspect_ratios() {
+        let area = |width, height, aspect_ratio| {
+            parts(ViewportArea::AspectRatio(aspect_ratio).area(extent(width, height)))
+        };
+
+        // Bars on the sides of wider images and above and below taller ones
+        assert_eq!(area(1920, 1080, 4.0 / 3.0), (240, 0, 1440, 1080));
+        assert_eq!(area(1000, 1000, 2.0), (0, 250, 1000, 500));
+        assert_eq!(area(1280, 720, 16.0 / 9.0), (0, 0, 1280, 720));
+
+        assert_eq!(parts(letterboxed_area(extent(640, 480), None)), (0, 0, 640, 480));
+        // Minimized windows have nothing to letterbox
+        assert_eq!(area(0, 480, 2.0), (0, 0, 0, 480));
+    }
+
+    #[test]
+    fn scales_by_whole_multiples() {
+        let area = |width, height, resolution_width, resolution_height| {
+            let viewport_area = ViewportArea::IntegerScaled {
+                width: resolution_width,
+                height: resolution_height
+            };
+
+            parts(viewport_area.area(extent(width, height)))
+        };
+
+        assert_eq!(area(1000, 700, 320, 180), (20, 80, 960, 540));
+        assert_eq!(area(640, 360, 320, 180), (0, 0, 640, 360));
+        // The smaller of both scales fits
+        assert_eq!(area(1000, 400, 320, 180), (180, 20, 640, 360));
+        // Images smaller than the resolution are letterboxed to its aspect ratio instead
+        assert_eq!(area(100, 100, 160, 90), (0, 22, 100, 56));
+        assert_eq!(area(800, 600, 0, 180), (0, 0, 800, 600));
+    }
+
+    #[test]
+    fn clips_regions_to_the_image() {
+        let area = |region| parts(ViewportArea::Region(region).area(extent(100, 100)));
+
+        assert_eq!(area(rect(10, 20, 30, 40)), (10, 20, 30, 40));
+        assert_eq!(area(rect(-10, 20, 50, 1000)), (0, 20, 40, 80));
+        assert_eq!(area(rect(50, 50, u32::MAX, 10)), (50, 50, 50, 10));
+        // Regions outside of the image are empty instead of negative
+        assert_eq!(area(rect(200, 200, 10, 10)), (100, 100, 0, 0));
+        assert_eq!(area(rect(-50, -50, 10, 10)), (0, 0, 0, 0));
+    }
+
+    #[test]
+    fn splits_images_evenly() {
+        let areas = |count| -> Vec<_> {
+            split_areas(extent(800, 600), count).into_iter().map(parts).collect()
+        };
+
+        assert!(areas(0).is_empty());
+        assert_eq!(areas(1), vec![(0, 0, 800, 600)]);
+        assert_eq!(areas(2), vec![(0, 0, 400, 600), (400, 0, 400, 600)]);
+        // Rows are filled left to right, leaving the rest of the last one empty
+        assert_eq!(areas(3), vec![(0, 0, 400, 300), (400, 0, 400, 300), (0, 300, 400, 300)]);
+        assert_eq!(areas(5)[4], (266, 300, 266, 300));
+    }
+
+    #[test]
+    fn keeps_split_viewports_apart_and_inside_of_the_image() {
+        let image = extent(1366, 767);
+
+        for count in 1..=16 {
+            let areas = split_areas(image, count);
+            assert_eq!(areas.len(), count);
+
+            for (index, area) in areas.iter().enumerate() {
+                let (x, y, width, height) = parts(*area);
+                assert!(width > 0 && height > 0);
+                assert!(x as u32 + width <= image.width && y as u32 + height <= image.height);
+
+                for other in &areas[index + 1..] {
+                    let (other_x, other_y, ..) = parts(*other);
+                    let apart_horizontally =
+                        x + width as i32 <= other_x || other_x + width as i32 <= x;
+                    let apart_vertically =
+                        y + height as i32 <= other_y || other_y + height as i32 <= y;
+
+                    assert!(apart_horizontally || apart_vertically, "{} viewports", count);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn draws_split_viewports_into_their_clipped_areas() {
+        let render_layers = RenderLayers::new();
+        let viewport = SplitViewport {
+            area: rect(400, -100, 800, 400),
+            render_layers: &render_layers
+        };
+
+        let layers = viewport.layers(extent(800, 600));
+        assert_eq!(parts(layers.full_area), (400, 0, 400, 300));
+        assert_eq!(parts(layers.letterboxed_area), (400, 0, 400, 300));
+    }
+}
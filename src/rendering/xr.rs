@@ -0,0 +1,354 @@
+use std::rc::Rc;
+use ash::vk;
+use crate::{
+    builder::{
+        BuilderRequirement,
+        BuilderInternal,
+        BuilderProduct
+    },
+    rendering::{
+        RenderingError,
+        RenderingResult,
+        renderer::Renderer
+    },
+    vulkan::{
+        handle::VulkanHandle,
+        physical_device::QueueFamily,
+        image::Image,
+        render_pass::RenderPass,
+        framebuffers::Framebuffers,
+        pipeline::{
+            Pipeline,
+            PipelineBuilder
+        },
+        command_buffer::CommandBufferRecorder
+    }
+};
+
+// What an OpenXR runtime needs from the instance and the device, as returned by
+// xrGetVulkanInstanceExtensionsKHR, xrGetVulkanDeviceExtensionsKHR and
+// xrGetVulkanGraphicsDeviceKHR, for `Renderer::with_xr_requirements`
+#[derive(Default)]
+pub struct XrRequirements {
+    pub instance_extensions: Vec<String>,
+    pub device_extensions: Vec<String>,
+    // Called with the created instance, returns the physical device the runtime renders with.
+    // Any suitable device is selected without it.
+    pub physical_device: Option<Box<dyn Fn(vk::Instance) -> vk::PhysicalDevice>>
+}
+
+// Handles of the renderer an OpenXR session is created with, i.e. the fields
+// of XrGraphicsBindingVulkanKHR. Frames are submitted to the given queue.
+#[derive(Clone, Copy, Debug)]
+pub struct XrGraphicsBinding {
+    pub instance: vk::Instance,
+    pub physical_device: vk::PhysicalDevice,
+    pub device: vk::Device,
+    pub queue_family_index: u32,
+    pub queue_index: u32
+}
+
+impl XrGraphicsBinding {
+    pub fn new(renderer: &Renderer) -> RenderingResult<Self> {
+        let queue_family_index = renderer
+            .physical_device()
+            .queue_family_index(QueueFamily::Graphics)?;
+
+        Ok(Self {
+            instance: renderer.vulkan_state().instance().handle(),
+            physical_device: renderer.physical_device().handle(),
+            device: renderer.logical_device().handle(),
+            queue_family_index,
+            // Only the first queue of every family is ever created
+            queue_index: 0
+        })
+    }
+}
+
+// Swapchain images of an OpenXR runtime, as returned by xrEnumerateSwapchainImages, rendered to
+// instead of the window's swapchain. Images with more than one array layer, e.g. one per eye,
+// are rendered with multiview, every draw reaching all of their layers.
+//
+// An image is acquired and waited for with the runtime, its index is recorded with `record`
+// into the commands of a frame, e.g. with `FrameContext::record`, and the image is released
+// once the frame is submitted. The runtime gets it back in COLOR_ATTACHMENT_OPTIMAL.
+pub struct XrSwapchainTarget {
+    render_pass: Rc<RenderPass>,
+    framebuffers: Vec<Framebuffers>,
+    extent: vk::Extent2D,
+    array_layers: u32,
+    has_depth: bool
+}
+
+impl XrSwapchainTarget {
+    // Preferred first, every device supports rendering to at least one of them
+    const DEPTH_FORMAT_CANDIDATES: [vk::Format; 3] = [
+        vk::Format::D32_SFLOAT,
+        vk::Format::X8_D24_UNORM_PACK32,
+        vk::Format::D16_UNORM
+    ];
+
+    pub fn builder<'a>() -> XrSwapchainTargetBuilder<'a> {
+        XrSwapchainTargetBuilder {
+            ..Default::default()
+        }
+    }
+
+    pub fn image_count(&self) -> usize {
+        self.framebuffers.len()
+    }
+
+    pub fn extent(&self) -> vk::Extent2D {
+        self.extent
+    }
+
+    pub fn array_layers(&self) -> u32 {
+        self.array_layers
+    }
+
+    pub fn render_pass(&self) -> &Rc<RenderPass> {
+        &self.render_pass
+    }
+
+    // Pipeline rendering to the images, still without shaders and vertex input
+    pub fn pipeline_builder<'a>(&self, renderer: &Renderer) -> PipelineBuilder<'a> {
+        Pipeline::builder()
+            .logical_device(Rc::clone(renderer.logical_device()))
+            .swapchain(Rc::clone(renderer.swapchain()))
+            .render_pass(Rc::clone(&self.render_pass))
+            .subpass(0)
+            .depth_test(self.has_depth)
+            .depth_write(self.has_depth)
+    }
+
+    // Clears the image of the given index and renders it with the commands recorded by `draw`
+    pub fn record<'a, F>(
+        &self,
+        recorder: CommandBufferRecorder<'a>,
+        image_index: usize,
+        clear_color: [f32; 4],
+        draw: F
+    ) -> CommandBufferRecorder<'a> where
+        F: FnOnce(CommandBufferRecorder<'a>) -> CommandBufferRecorder<'a> {
+        let clear_values = [
+            vk::ClearValue {
+                color: vk::ClearColorValue {
+                    float32: clear_color
+                }
+            },
+            vk::ClearValue {
+                depth_stencil: vk::ClearDepthStencilValue {
+                    depth: 1.0,
+                    stencil: 0
+                }
+            }
+        ];
+
+        let framebuffers = &self.framebuffers[image_index];
+        let recorder = recorder
+            .begin_render_pass_with_clear_values(
+                &self.render_pass, framebuffers, 0, &clear_values)
+            .set_viewport(CommandBufferRecorder::render_area(framebuffers));
+
+        draw(recorder).end_render_pass()
+    }
+}
+
+#[derive(Default)]
+pub struct XrSwapchainTargetBuilder<'a> {
+    renderer: BuilderRequirement<&'a Renderer>,
+    vk_images: BuilderRequirement<Vec<vk::Image>>,
+    format: BuilderRequirement<vk::Format>,
+    extent: BuilderRequirement<vk::Extent2D>,
+    array_layers: Option<u32>,
+    depth: bool,
+
+    images: BuilderInternal<Vec<Rc<Image>>>,
+    depth_format: BuilderInternal<vk::Format>,
+    depth_image: BuilderInternal<Option<Rc<Image>>>,
+    render_pass: BuilderInternal<Rc<RenderPass>>,
+    framebuffers: BuilderInternal<Vec<Framebuffers>>,
+
+    xr_swapchain_target: BuilderProduct<XrSwapchainTarget>
+}
+
+impl<'a> XrSwapchainTargetBuilder<'a> {
+    pub fn renderer(mut self, renderer: &'a Renderer) -> Self {
+        self.renderer.set(renderer);
+        self
+    }
+
+    // Owned by the runtime, which has to keep them alive for as long as the target
+    pub fn images(mut self, images: &[vk::Image]) -> Self {
+        self.vk_images.set(images.to_vec());
+        self
+    }
+
+    // The one the runtime's swapchain was created with
+    pub fn format(mut self, format: vk::Format) -> Self {
+        self.format.set(format);
+        self
+    }
+
+    pub fn extent(mut self, extent: vk::Extent2D) -> Self {
+        self.extent.set(extent);
+        self
+    }
+
+    // Defaults to 1
+    pub fn array_layers(mut self, array_layers: u32) -> Self {
+        self.array_layers = Some(array_layers);
+        self
+    }
+
+    // Renders with a depth buffer of the target's own, shared by all of its images
+    pub fn depth(mut self, depth: bool) -> Self {
+        self.depth = depth;
+        self
+    }
+
+    pub fn build(mut self) -> RenderingResult<XrSwapchainTarget> {
+        check_required_fields!(
+            self, RenderingError, "XrSwapchainTargetBuilder",
+            [renderer, vk_images, format, extent]);
+
+        if self.vk_images.is_empty() {
+            return Err(RenderingError::XrSwapchainWithoutImages);
+        }
+
+        self.init_images()?;
+        self.init_depth_format()?;
+        self.init_depth_image()?;
+        self.init_render_pass()?;
+        self.init_framebuffers()?;
+        self.create_xr_swapchain_target();
+
+        Ok(self.xr_swapchain_target.unwrap())
+    }
+
+    fn array_layer_count(&self) -> u32 {
+        self.array_layers.unwrap_or(1)
+    }
+
+    fn init_images(&mut self) -> RenderingResult<()> {
+        let images = self.vk_images
+            .iter()
+            .map(|vk_image| {
+                let image = Image::from_external(
+                    Rc::clone(self.renderer.logical_device()),
+                    *vk_image,
+                    *self.format,
+                    *self.extent,
+                    self.array_layer_count())?;
+
+                Ok(Rc::new(image))
+            })
+            .collect::<RenderingResult<_>>()?;
+
+        self.images.set(images);
+
+        Ok(())
+    }
+
+    fn init_depth_format(&mut self) -> RenderingResult<()> {
+        if !self.depth {
+            return Ok(());
+        }
+
+        let format = XrSwapchainTarget::DEPTH_FORMAT_CANDIDATES
+            .iter()
+            .copied()
+            .find(|format| {
+                self.renderer
+                    .physical_device()
+                    .format_properties(*format)
+                    .optimal_tiling_features
+                    .contains(vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT)
+            })
+            .ok_or(RenderingError::XrDepthFormatNotSupported)?;
+
+        debug!("OpenXR swapchain depth uses format {:?}", format);
+        self.depth_format.set(format);
+
+        Ok(())
+    }
+
+    fn init_depth_image(&mut self) -> RenderingResult<()> {
+        if !self.depth {
+            self.depth_image.set(None);
+            return Ok(());
+        }
+
+        let depth_image = Image::builder()
+            .allocator(Rc::clone(self.renderer.allocator()))
+            .logical_device(Rc::clone(self.renderer.logical_device()))
+            .format(*self.depth_format)
+            .extent(*self.extent)
+            .usage(vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT)
+            .aspect(vk::ImageAspectFlags::DEPTH)
+            .array_layers(self.array_layer_count())
+            .build()?;
+
+        self.depth_image.set(Some(Rc::new(depth_image)));
+
+        Ok(())
+    }
+
+    fn init_render_pass(&mut self) -> RenderingResult<()> {
+        let depth_format = if self.depth {
+            Some(*self.depth_format)
+        }
+        else {
+            None
+        };
+
+        // One view per layer
+        let view_mask = match self.array_layer_count() {
+            1 => 0,
+            array_layers => u32::MAX >> (u32::BITS - array_layers.min(u32::BITS))
+        };
+
+        let render_pass = RenderPass::external_multiview(
+            Rc::clone(self.renderer.logical_device()),
+            &[*self.format],
+            depth_format,
+            view_mask)?;
+
+        self.render_pass.set(Rc::new(render_pass));
+
+        Ok(())
+    }
+
+    fn init_framebuffers(&mut self) -> RenderingResult<()> {
+        let framebuffers = self.images
+            .iter()
+            .map(|image| {
+                let attachments = std::iter::once(image)
+                    .chain(self.depth_image.iter())
+                    .cloned()
+                    .collect();
+
+                let framebuffers = Framebuffers::for_images(
+                    Rc::clone(self.renderer.logical_device()),
+                    &self.render_pass,
+                    attachments)?;
+
+                Ok(framebuffers)
+            })
+            .collect::<RenderingResult<_>>()?;
+
+        self.framebuffers.set(framebuffers);
+
+        Ok(())
+    }
+
+    fn create_xr_swapchain_target(&mut self) {
+        self.xr_swapchain_target.set(XrSwapchainTarget {
+            render_pass: self.render_pass.take(),
+            framebuffers: self.framebuffers.take(),
+            extent: *self.extent,
+            array_layers: self.array_layer_count(),
+            has_depth: self.depth
+        });
+    }
+}
@@ -0,0 +1,201 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    time::{
+        Duration,
+        Instant
+    }
+};
+use nalgebra_glm as glm;
+use crate::{
+    rendering::{
+        RenderingResult,
+        renderer::Renderer
+    },
+    voxel::world::World
+};
+
+// Long-running mode which flies the camera along a fixed path and samples resource usage,
+// so that leaks in streaming and deferred destruction show up as steadily growing samples.
+// Usage can grow while caches warm up, so the first part of the run is not checked.
+pub struct SoakTest {
+    duration: Duration,
+    start: Instant,
+    last_sample: Option<Instant>,
+    series: BTreeMap<&'static str, Vec<f64>>
+}
+
+impl SoakTest {
+    pub const ARGUMENT: &'static str = "--soak";
+    pub const DEFAULT_MINUTES: u64 = 10;
+    const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+    const WARM_UP_FRACTION: f64 = 0.2;
+    // Allowed growth of the end of the run over its (post warm-up) start
+    const RELATIVE_TOLERANCE: f64 = 0.1;
+    const CAMERA_PATH_RADIUS: f32 = 256.0;
+    const CAMERA_SPEED: f32 = 16.0;
+
+    pub fn new(duration: Duration) -> Self {
+        info!("soak test running for {:?}", duration);
+
+        Self {
+            duration,
+            start: Instant::now(),
+            last_sample: None,
+            series: BTreeMap::new()
+        }
+    }
+
+    // Parses `--soak [minutes]`, returns `None` if the argument is not given
+    pub fn from_arguments<I: Iterator<Item = String>>(arguments: I) -> Option<Self> {
+        let mut arguments = arguments.skip_while(|argument| argument != Self::ARGUMENT);
+        arguments.next()?;
+
+        let minutes = arguments
+            .next()
+            .and_then(|minutes| minutes.parse().ok())
+            .unwrap_or(Self::DEFAULT_MINUTES);
+
+        Some(Self::new(Duration::from_secs(minutes * 60)))
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.start.elapsed() >= self.duration
+    }
+
+    // Wide circle with slow height changes, so that chunks keep being streamed in and out
+    pub fn camera_position(&self) -> glm::Vec3 {
+        let distance = self.start.elapsed().as_secs_f32() * Self::CAMERA_SPEED;
+        let angle = distance / Self::CAMERA_PATH_RADIUS;
+
+        glm::vec3(
+            angle.cos() * Self::CAMERA_PATH_RADIUS,
+            64.0 + (angle * 3.0).sin() * 32.0,
+            angle.sin() * Self::CAMERA_PATH_RADIUS)
+    }
+
+    // True at most once per sample interval, samples should only be taken then
+    pub fn should_sample(&mut self) -> bool {
+        let is_due = self.last_sample
+            .map_or(true, |last_sample| last_sample.elapsed() >= Self::SAMPLE_INTERVAL);
+
+        if is_due {
+            self.last_sample = Some(Instant::now());
+        }
+
+        is_due
+    }
+
+    pub fn sample(&mut self, name: &'static str, value: f64) {
+        self.series.entry(name).or_default().push(value);
+    }
+
+    pub fn sample_renderer(&mut self, renderer: &Renderer) -> RenderingResult<()> {
+        let statistics = renderer.allocator().statistics()?;
+        self.sample("GPU memory (bytes)", statistics.used_bytes as f64);
+        self.sample("GPU allocations", f64::from(statistics.allocation_count));
+
+        Ok(())
+    }
+
+    pub fn sample_world(&mut self, world: &World) {
+        self.sample("chunks", world.chunks().count() as f64);
+        self.sample("remesh queue length", world.remesh_queue_len() as f64);
+    }
+
+    // Only supported on Linux, elsewhere nothing is sampled
+    pub fn sample_host_memory(&mut self) {
+        if let Some(resident_bytes) = resident_memory_bytes() {
+            self.sample("host memory (bytes)", resident_bytes as f64);
+        }
+    }
+
+    pub fn report(&self) -> SoakReport {
+        let series = self.series
+            .iter()
+            .filter_map(|(name, samples)| Self::check_series(name, samples))
+            .collect();
+
+        SoakReport {
+            duration: self.start.elapsed(),
+            series
+        }
+    }
+
+    // Compares the peak of the last quarter of the samples with the peak of the first quarter
+    // after warm-up, peaks keep the check robust against periodic spikes
+    fn check_series(name: &'static str, samples: &[f64]) -> Option<SeriesReport> {
+        let warm_up = (samples.len() as f64 * Self::WARM_UP_FRACTION) as usize;
+        let checked = &samples[warm_up..];
+        let quarter = checked.len() / 4;
+
+        if quarter == 0 {
+            return None;
+        }
+
+        let peak = |samples: &[f64]| samples.iter().copied().fold(f64::MIN, f64::max);
+        let start_peak = peak(&checked[..quarter]);
+        let end_peak = peak(&checked[checked.len() - quarter..]);
+
+        Some(SeriesReport {
+            name,
+            start_peak,
+            end_peak,
+            is_bounded: end_peak <= start_peak * (1.0 + Self::RELATIVE_TOLERANCE) + 1.0
+        })
+    }
+}
+
+pub struct SoakReport {
+    duration: Duration,
+    series: Vec<SeriesReport>
+}
+
+struct SeriesReport {
+    name: &'static str,
+    start_peak: f64,
+    end_peak: f64,
+    is_bounded: bool
+}
+
+impl SoakReport {
+    pub fn passed(&self) -> bool {
+        self.series.iter().all(|series| series.is_bounded)
+    }
+}
+
+impl fmt::Display for SoakReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let result = if self.passed() { "passed" } else { "FAILED" };
+        writeln!(f, "soak test {} after {:?}", result, self.duration)?;
+
+        if self.series.is_empty() {
+            writeln!(f, "  not enough samples were taken to check anything")?;
+        }
+
+        for series in &self.series {
+            writeln!(
+                f, "  {:<5} {}: {} -> {}",
+                if series.is_bounded { "ok" } else { "GREW" },
+                series.name, series.start_peak, series.end_peak)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn resident_memory_bytes() -> Option<u64> {
+    // Pages are assumed to be 4 KiB, which holds for the platforms the game runs on
+    const PAGE_SIZE: u64 = 4096;
+
+    let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+    let resident_pages: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+
+    Some(resident_pages * PAGE_SIZE)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn resident_memory_bytes() -> Option<u64> {
+    None
+}
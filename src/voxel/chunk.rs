@@ -0,0 +1,157 @@
+use nalgebra_glm as glm;
+
+pub const CHUNK_SIZE: usize = 16;
+const CHUNK_VOLUME: usize = CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE;
+
+// Block type identifier, its meaning (e.g. texture) is up to the shaders,
+// whether it is translucent is up to the world
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub struct Block(pub u16);
+
+impl Block {
+    pub const AIR: Block = Block(0);
+
+    pub fn is_air(self) -> bool {
+        self == Block::AIR
+    }
+}
+
+// Position of a chunk measured in chunks, chunk (0, 0, 0) holds blocks (0, 0, 0) to (15, 15, 15)
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct ChunkPosition {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32
+}
+
+impl ChunkPosition {
+    pub fn new(x: i32, y: i32, z: i32) -> Self {
+        Self {x, y, z}
+    }
+
+    // Returns the chunk containing the block and the block's position inside of it
+    pub fn of_block(position: &glm::IVec3) -> (Self, [usize; 3]) {
+        let size = CHUNK_SIZE as i32;
+        let chunk_position = Self::new(
+            position.x.div_euclid(size),
+            position.y.div_euclid(size),
+            position.z.div_euclid(size));
+
+        let local_position = [
+            position.x.rem_euclid(size) as usize,
+            position.y.rem_euclid(size) as usize,
+            position.z.rem_euclid(size) as usize
+        ];
+
+        (chunk_position, local_position)
+    }
+
+    pub fn origin(&self) -> glm::IVec3 {
+        glm::vec3(self.x, self.y, self.z) * CHUNK_SIZE as i32
+    }
+
+    pub fn offset(&self, x: i32, y: i32, z: i32) -> Self {
+        Self::new(self.x + x, self.y + y, self.z + z)
+    }
+}
+
+pub struct Chunk {
+    blocks: Vec<Block>,
+    solid_block_count: usize
+}
+
+impl Chunk {
+    pub fn new() -> Self {
+        Self {
+            blocks: vec![Block::AIR; CHUNK_VOLUME],
+            solid_block_count: 0
+        }
+    }
+
+    pub fn block(&self, local_position: [usize; 3]) -> Block {
+        self.blocks[Self::index(local_position)]
+    }
+
+    pub fn set_block(&mut self, local_position: [usize; 3], block: Block) {
+        let index = Self::index(local_position);
+        let previous_block = std::mem::replace(&mut self.blocks[index], block);
+
+        if previous_block.is_air() && !block.is_air() {
+            self.solid_block_count += 1;
+        }
+        else if !previous_block.is_air() && block.is_air() {
+            self.solid_block_count -= 1;
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.solid_block_count == 0
+    }
+
+    fn index(local_position: [usize; 3]) -> usize {
+        let [x, y, z] = local_position;
+        (z * CHUNK_SIZE + y) * CHUNK_SIZE + x
+    }
+}
+
+impl Default for Chunk {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_chunks_of_blocks() {
+        let (chunk, local) = ChunkPosition::of_block(&glm::vec3(17, 0, 15));
+        assert_eq!(chunk, ChunkPosition::new(1, 0, 0));
+        assert_eq!(local, [1, 0, 15]);
+
+        // Negative coordinates round towards negative infinity
+        let (chunk, local) = ChunkPosition::of_block(&glm::vec3(-1, -16, -17));
+        assert_eq!(chunk, ChunkPosition::new(-1, -1, -2));
+        assert_eq!(local, [15, 0, 15]);
+        assert_eq!(chunk.origin(), glm::vec3(-16, -16, -32));
+    }
+
+    #[test]
+    fn keeps_blocks_apart() {
+        let mut chunk = Chunk::new();
+        let corners = [[0, 0, 0], [15, 0, 0], [0, 15, 0], [0, 0, 15], [15, 15, 15]];
+
+        for (index, corner) in corners.iter().enumerate() {
+            chunk.set_block(*corner, Block(index as u16 + 1));
+        }
+
+        for (index, corner) in corners.iter().enumerate() {
+            assert_eq!(chunk.block(*corner), Block(index as u16 + 1));
+        }
+
+        assert_eq!(chunk.block([1, 0, 0]), Block::AIR);
+    }
+
+    #[test]
+    fn counts_solid_blocks() {
+        let mut chunk = Chunk::new();
+        assert!(chunk.is_empty());
+
+        chunk.set_block([1, 2, 3], Block(1));
+        chunk.set_block([3, 2, 1], Block(1));
+        // Replacing a solid block with another one keeps the count
+        chunk.set_block([1, 2, 3], Block(2));
+        assert_eq!(chunk.solid_block_count, 2);
+
+        chunk.set_block([1, 2, 3], Block::AIR);
+        // Clearing air stays at the same count instead of underflowing
+        chunk.set_block([1, 2, 3], Block::AIR);
+        chunk.set_block([0, 0, 0], Block::AIR);
+        assert_eq!(chunk.solid_block_count, 1);
+        assert!(!chunk.is_empty());
+
+        chunk.set_block([3, 2, 1], Block::AIR);
+        assert!(chunk.is_empty());
+    }
+}
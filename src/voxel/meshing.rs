@@ -0,0 +1,262 @@
+use nalgebra_glm as glm;
+use crate::{
+    vulkan::pipeline::VertexAttributeFormat,
+    voxel::{
+        chunk::{
+            ChunkPosition,
+            CHUNK_SIZE
+        },
+        world::World
+    }
+};
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct VoxelVertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub block: u32
+}
+
+impl VoxelVertex {
+    // Layout matching `shaders/voxel.vert`
+    pub const ATTRIBUTES: [(VertexAttributeFormat, usize); 3] = [
+        (VertexAttributeFormat::F32(3), 0),
+        (VertexAttributeFormat::F32(3), 12),
+        (VertexAttributeFormat::U32(1), 24)
+    ];
+}
+
+pub struct ChunkMeshData {
+    pub vertices: Vec<VoxelVertex>,
+    pub indices: Vec<u32>
+}
+
+impl ChunkMeshData {
+    fn new() -> Self {
+        Self {
+            vertices: Vec::new(),
+            indices: Vec::new()
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+}
+
+// Translucent blocks are meshed separately, as they are drawn in another layer
+pub struct ChunkMeshes {
+    pub opaque: ChunkMeshData,
+    pub translucent: ChunkMeshData
+}
+
+struct Face {
+    normal: [i32; 3],
+    // Wound clockwise when looking at the face from outside of the block
+    corners: [[i32; 3]; 4]
+}
+
+const FACES: [Face; 6] = [
+    Face {normal: [1, 0, 0], corners: [[1, 1, 1], [1, 1, 0], [1, 0, 0], [1, 0, 1]]},
+    Face {normal: [-1, 0, 0], corners: [[0, 1, 0], [0, 1, 1], [0, 0, 1], [0, 0, 0]]},
+    Face {normal: [0, 1, 0], corners: [[0, 1, 0], [1, 1, 0], [1, 1, 1], [0, 1, 1]]},
+    Face {normal: [0, -1, 0], corners: [[0, 0, 1], [1, 0, 1], [1, 0, 0], [0, 0, 0]]},
+    Face {normal: [0, 0, 1], corners: [[0, 1, 1], [1, 1, 1], [1, 0, 1], [0, 0, 1]]},
+    Face {normal: [0, 0, -1], corners: [[1, 1, 0], [0, 1, 0], [0, 0, 0], [1, 0, 0]]}
+];
+
+const FACE_INDICES: [u32; 6] = [0, 1, 2, 0, 2, 3];
+
+// Culled meshing: a face is emitted only if the block next to it does not hide it,
+// looking into neighbouring chunks for blocks on the border.
+// Opaque blocks hide all faces, translucent ones only faces of blocks of the same type.
+// Vertex positions are in world space.
+pub fn mesh_chunk(world: &World, position: ChunkPosition) -> ChunkMeshes {
+    let mut meshes = ChunkMeshes {
+        opaque: ChunkMeshData::new(),
+        translucent: ChunkMeshData::new()
+    };
+
+    let chunk = match world.chunk(position) {
+        Some(chunk) if !chunk.is_empty() => chunk,
+        _ => return meshes
+    };
+
+    let origin = position.origin();
+
+    for z in 0..CHUNK_SIZE {
+        for y in 0..CHUNK_SIZE {
+            for x in 0..CHUNK_SIZE {
+                let block = chunk.block([x, y, z]);
+                if block.is_air() {
+                    continue;
+                }
+
+                let mesh_data = if world.is_translucent(block) {
+                    &mut meshes.translucent
+                }
+                else {
+                    &mut meshes.opaque
+                };
+
+                let block_position = origin + glm::vec3(x as i32, y as i32, z as i32);
+
+                for face in FACES.iter() {
+                    let normal = glm::vec3(face.normal[0], face.normal[1], face.normal[2]);
+                    let neighbour = world.block(&(block_position + normal));
+                    if world.is_opaque(neighbour) || neighbour == block {
+                        continue;
+                    }
+
+                    push_face(mesh_data, face, &block_position, block.0 as u32);
+                }
+            }
+        }
+    }
+
+    meshes
+}
+
+fn push_face(mesh_data: &mut ChunkMeshData, face: &Face, block_position: &glm::IVec3, block: u32) {
+    let first_index = mesh_data.vertices.len() as u32;
+    let normal = [face.normal[0] as f32, face.normal[1] as f32, face.normal[2] as f32];
+
+    for corner in face.corners.iter() {
+        mesh_data.vertices.push(VoxelVertex {
+            position: [
+                (block_position.x + corner[0]) as f32,
+                (block_position.y + corner[1]) as f32,
+                (block_position.z + corner[2]) as f32
+            ],
+            normal,
+            block
+        });
+    }
+
+    mesh_data.indices.extend(FACE_INDICES.iter().map(|index| first_index + index));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxel::chunk::Block;
+
+    const STONE: Block = Block(1);
+    const GLASS: Block = Block(2);
+    const WATER: Block = Block(3);
+
+    fn face_normals(mesh_data: &ChunkMeshData) -> Vec<[f32; 3]> {
+        mesh_data.vertices.chunks(4).map(|face| face[0].normal).collect()
+    }
+
+    fn world_with(blocks: &[([i32; 3], Block)]) -> World {
+        let mut world = World::new();
+        world.set_translucent(GLASS, true);
+        world.set_translucent(WATER, true);
+
+        for (position, block) in blocks {
+            world.set_block(&glm::vec3(position[0], position[1], position[2]), *block);
+        }
+
+        world
+    }
+
+    #[test]
+    fn meshes_every_face_of_a_lone_block() {
+        let world = world_with(&[([17, 2, 3], STONE)]);
+        let meshes = mesh_chunk(&world, ChunkPosition::new(1, 0, 0));
+
+        assert_eq!(meshes.opaque.vertices.len(), 24);
+        assert_eq!(meshes.opaque.indices.len(), 36);
+        assert!(meshes.translucent.is_empty());
+
+        // Positions are in world space
+        for vertex in &meshes.opaque.vertices {
+            let [x, y, z] = vertex.position;
+            assert!((17.0..=18.0).contains(&x) && (2.0..=3.0).contains(&y));
+            assert!((3.0..=4.0).contains(&z));
+            assert_eq!(vertex.block, 1);
+        }
+    }
+
+    #[test]
+    fn winds_faces_clockwise_from_outside() {
+        let world = world_with(&[([0, 0, 0], STONE)]);
+        let mesh_data = mesh_chunk(&world, ChunkPosition::new(0, 0, 0)).opaque;
+
+        for triangle in mesh_data.indices.chunks(3) {
+            let corner = |index: u32| {
+                let position = mesh_data.vertices[index as usize].position;
+                glm::vec3(position[0], position[1], position[2])
+            };
+
+            let normal = mesh_data.vertices[triangle[0] as usize].normal;
+            let winding = glm::cross(
+                &(corner(triangle[1]) - corner(triangle[0])),
+                &(corner(triangle[2]) - corner(triangle[0])));
+
+            assert!(glm::dot(&winding, &glm::vec3(normal[0], normal[1], normal[2])) < 0.0);
+        }
+    }
+
+    #[test]
+    fn culls_faces_hidden_by_neighbouring_chunks() {
+        let world = world_with(&[([15, 0, 0], STONE), ([16, 0, 0], STONE), ([15, -1, 0], STONE)]);
+
+        let meshes = mesh_chunk(&world, ChunkPosition::new(0, 0, 0));
+        let normals = face_normals(&meshes.opaque);
+        assert_eq!(normals.len(), 4);
+        assert!(!normals.contains(&[1.0, 0.0, 0.0]));
+        assert!(!normals.contains(&[0.0, -1.0, 0.0]));
+
+        let meshes = mesh_chunk(&world, ChunkPosition::new(1, 0, 0));
+        assert!(!face_normals(&meshes.opaque).contains(&[-1.0, 0.0, 0.0]));
+    }
+
+    #[test]
+    fn keeps_faces_on_borders_of_missing_chunks() {
+        let world = world_with(&[([0, 0, 0], STONE)]);
+        assert!(world.chunk(ChunkPosition::new(-1, 0, 0)).is_none());
+
+        let meshes = mesh_chunk(&world, ChunkPosition::new(0, 0, 0));
+        assert!(face_normals(&meshes.opaque).contains(&[-1.0, 0.0, 0.0]));
+    }
+
+    #[test]
+    fn culls_faces_between_translucent_blocks_of_the_same_type() {
+        let world = world_with(&[
+            ([15, 0, 0], GLASS),
+            ([16, 0, 0], GLASS),
+            ([15, 1, 0], WATER),
+            ([15, 0, 1], STONE)
+        ]);
+
+        let meshes = mesh_chunk(&world, ChunkPosition::new(0, 0, 0));
+        let glass_normals: Vec<[f32; 3]> = meshes.translucent.vertices
+            .chunks(4)
+            .filter(|face| face[0].block == u32::from(GLASS.0))
+            .map(|face| face[0].normal)
+            .collect();
+
+        // Hidden by the glass in the next chunk and by the stone, but not by the water
+        assert_eq!(glass_normals.len(), 4);
+        assert!(!glass_normals.contains(&[1.0, 0.0, 0.0]));
+        assert!(!glass_normals.contains(&[0.0, 0.0, 1.0]));
+        assert!(glass_normals.contains(&[0.0, 1.0, 0.0]));
+
+        // Stone next to glass is seen through it
+        assert!(face_normals(&meshes.opaque).contains(&[0.0, 0.0, -1.0]));
+    }
+
+    #[test]
+    fn meshes_empty_and_missing_chunks_as_nothing() {
+        let mut world = world_with(&[([0, 0, 0], STONE)]);
+        world.set_block(&glm::vec3(0, 0, 0), Block::AIR);
+
+        for position in &[ChunkPosition::new(0, 0, 0), ChunkPosition::new(5, 5, 5)] {
+            let meshes = mesh_chunk(&world, *position);
+            assert!(meshes.opaque.is_empty() && meshes.translucent.is_empty());
+        }
+    }
+}
@@ -0,0 +1,5 @@
+// Block world split into cubic chunks, which are meshed and drawn independently
+pub mod chunk;
+pub mod world;
+pub mod meshing;
+pub mod world_renderer;
@@ -0,0 +1,254 @@
+use std::collections::{
+    HashMap,
+    HashSet,
+    VecDeque
+};
+use nalgebra_glm as glm;
+use crate::voxel::chunk::{
+    Block,
+    Chunk,
+    ChunkPosition,
+    CHUNK_SIZE
+};
+
+// Chunks are created on the first write to them, blocks of missing chunks are air.
+// Every change schedules the chunks whose meshes it affects to be remeshed.
+pub struct World {
+    chunks: HashMap<ChunkPosition, Chunk>,
+    remesh_queue: VecDeque<ChunkPosition>,
+    scheduled_for_remesh: HashSet<ChunkPosition>,
+    translucent_blocks: HashSet<Block>
+}
+
+impl World {
+    pub fn new() -> Self {
+        Self {
+            chunks: HashMap::new(),
+            remesh_queue: VecDeque::new(),
+            scheduled_for_remesh: HashSet::new(),
+            translucent_blocks: HashSet::new()
+        }
+    }
+
+    pub fn block(&self, position: &glm::IVec3) -> Block {
+        let (chunk_position, local_position) = ChunkPosition::of_block(position);
+
+        self.chunks
+            .get(&chunk_position)
+            .map_or(Block::AIR, |chunk| chunk.block(local_position))
+    }
+
+    pub fn set_block(&mut self, position: &glm::IVec3, block: Block) {
+        let (chunk_position, local_position) = ChunkPosition::of_block(position);
+
+        if block.is_air() && !self.chunks.contains_key(&chunk_position) {
+            return;
+        }
+
+        let chunk = self.chunks.entry(chunk_position).or_default();
+
+        if chunk.block(local_position) == block {
+            return;
+        }
+
+        chunk.set_block(local_position, block);
+        self.schedule_remesh(chunk_position);
+        self.schedule_neighbours_remesh(chunk_position, local_position);
+    }
+
+    // Translucent blocks (e.g. glass or water) are drawn in the depth-sorted translucent layer
+    // and do not hide faces of the blocks behind them
+    pub fn set_translucent(&mut self, block: Block, translucent: bool) {
+        let changed = if translucent {
+            self.translucent_blocks.insert(block)
+        }
+        else {
+            self.translucent_blocks.remove(&block)
+        };
+
+        if changed {
+            let positions: Vec<ChunkPosition> = self.chunks.keys().copied().collect();
+            for position in positions {
+                self.schedule_remesh(position);
+            }
+        }
+    }
+
+    pub fn is_translucent(&self, block: Block) -> bool {
+        self.translucent_blocks.contains(&block)
+    }
+
+    pub fn is_opaque(&self, block: Block) -> bool {
+        !block.is_air() && !self.is_translucent(block)
+    }
+
+    pub fn chunk(&self, position: ChunkPosition) -> Option<&Chunk> {
+        self.chunks.get(&position)
+    }
+
+    pub fn chunks(&self) -> impl Iterator<Item = (&ChunkPosition, &Chunk)> {
+        self.chunks.iter()
+    }
+
+    pub fn schedule_remesh(&mut self, position: ChunkPosition) {
+        if self.scheduled_for_remesh.insert(position) {
+            self.remesh_queue.push_back(position);
+        }
+    }
+
+    // Chunks are handed out in the order they were first scheduled in
+    pub fn next_chunk_to_remesh(&mut self) -> Option<ChunkPosition> {
+        let position = self.remesh_queue.pop_front()?;
+        self.scheduled_for_remesh.remove(&position);
+        Some(position)
+    }
+
+    pub fn remesh_queue_len(&self) -> usize {
+        self.remesh_queue.len()
+    }
+
+    // Blocks on a chunk's border hide or reveal faces of the adjacent chunk
+    fn schedule_neighbours_remesh(&mut self, position: ChunkPosition, local_position: [usize; 3]) {
+        for axis in 0..3 {
+            let mut offset = [0; 3];
+
+            if local_position[axis] == 0 {
+                offset[axis] = -1;
+            }
+            else if local_position[axis] == CHUNK_SIZE - 1 {
+                offset[axis] = 1;
+            }
+            else {
+                continue;
+            }
+
+            let neighbour = position.offset(offset[0], offset[1], offset[2]);
+            if self.chunks.contains_key(&neighbour) {
+                self.schedule_remesh(neighbour);
+            }
+        }
+    }
+}
+
+impl Default for World {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STONE: Block = Block(1);
+
+    fn remesh_queue(world: &mut World) -> Vec<ChunkPosition> {
+        std::iter::from_fn(|| world.next_chunk_to_remesh()).collect()
+    }
+
+    // Chunks around the origin, with nothing left to remesh
+    fn world_of_chunks(positions: &[[i32; 3]]) -> World {
+        let mut world = World::new();
+        for position in positions {
+            let origin = ChunkPosition::new(position[0], position[1], position[2]).origin();
+            world.set_block(&(origin + glm::vec3(8, 8, 8)), STONE);
+        }
+
+        remesh_queue(&mut world);
+        world
+    }
+
+    #[test]
+    fn reads_air_outside_of_chunks() {
+        let mut world = World::new();
+        world.set_block(&glm::vec3(-3, 40, 7), STONE);
+
+        assert_eq!(world.block(&glm::vec3(-3, 40, 7)), STONE);
+        assert_eq!(world.block(&glm::vec3(3, 40, 7)), Block::AIR);
+        assert_eq!(world.block(&glm::vec3(1000, -1000, 0)), Block::AIR);
+    }
+
+    #[test]
+    fn creates_chunks_only_for_solid_blocks() {
+        let mut world = World::new();
+        world.set_block(&glm::vec3(0, 0, 0), Block::AIR);
+        assert_eq!(world.chunks().count(), 0);
+        assert_eq!(world.remesh_queue_len(), 0);
+
+        world.set_block(&glm::vec3(0, 0, 0), STONE);
+        world.set_block(&glm::vec3(0, 0, 0), Block::AIR);
+        // Emptied chunks are kept, so that their meshes get removed
+        assert!(world.chunk(ChunkPosition::new(0, 0, 0)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn remeshes_only_the_edited_chunk_for_inner_blocks() {
+        let mut world = world_of_chunks(&[[0, 0, 0], [1, 0, 0], [-1, 0, 0]]);
+        world.set_block(&glm::vec3(5, 6, 7), STONE);
+
+        assert_eq!(remesh_queue(&mut world), vec![ChunkPosition::new(0, 0, 0)]);
+    }
+
+    #[test]
+    fn remeshes_existing_neighbours_of_border_blocks() {
+        let mut world = world_of_chunks(&[[0, 0, 0], [1, 0, 0], [-1, 0, 0], [0, -1, 0]]);
+
+        world.set_block(&glm::vec3(15, 8, 8), STONE);
+        assert_eq!(
+            remesh_queue(&mut world),
+            vec![ChunkPosition::new(0, 0, 0), ChunkPosition::new(1, 0, 0)]);
+
+        // The chunk at -Z does not exist, so it has no mesh to update
+        world.set_block(&glm::vec3(0, 0, 0), STONE);
+        assert_eq!(
+            remesh_queue(&mut world),
+            vec![
+                ChunkPosition::new(0, 0, 0),
+                ChunkPosition::new(-1, 0, 0),
+                ChunkPosition::new(0, -1, 0)
+            ]);
+
+        // Blocks of negative chunks are on the border with their positive neighbours too
+        world.set_block(&glm::vec3(-1, 8, 8), STONE);
+        assert_eq!(
+            remesh_queue(&mut world),
+            vec![ChunkPosition::new(-1, 0, 0), ChunkPosition::new(0, 0, 0)]);
+    }
+
+    #[test]
+    fn schedules_chunks_once_in_first_scheduled_order() {
+        let mut world = world_of_chunks(&[[0, 0, 0], [2, 0, 0]]);
+
+        world.set_block(&glm::vec3(40, 0, 0), STONE);
+        world.set_block(&glm::vec3(1, 1, 1), STONE);
+        world.set_block(&glm::vec3(41, 0, 0), STONE);
+        assert_eq!(world.remesh_queue_len(), 2);
+
+        // Writing a block which is already there changes nothing
+        world.set_block(&glm::vec3(1, 1, 1), STONE);
+        assert_eq!(
+            remesh_queue(&mut world),
+            vec![ChunkPosition::new(2, 0, 0), ChunkPosition::new(0, 0, 0)]);
+
+        // Once handed out, a chunk can be scheduled again
+        world.schedule_remesh(ChunkPosition::new(0, 0, 0));
+        assert_eq!(remesh_queue(&mut world), vec![ChunkPosition::new(0, 0, 0)]);
+    }
+
+    #[test]
+    fn remeshes_everything_when_translucency_changes() {
+        let mut world = world_of_chunks(&[[0, 0, 0], [0, 3, 0]]);
+
+        world.set_translucent(STONE, true);
+        assert_eq!(world.remesh_queue_len(), 2);
+        assert!(world.is_translucent(STONE) && !world.is_opaque(STONE));
+        remesh_queue(&mut world);
+
+        world.set_translucent(STONE, true);
+        assert_eq!(world.remesh_queue_len(), 0);
+
+        world.set_translucent(STONE, false);
+        assert_eq!(world.remesh_queue_len(), 2);
+        assert!(world.is_opaque(STONE) && !world.is_opaque(Block::AIR));
+    }
+}
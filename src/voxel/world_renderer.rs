@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+use nalgebra_glm as glm;
+use crate::{
+    rendering::{
+        RenderingResult,
+        renderer::Renderer,
+        render_state::{
+            RenderState,
+            PushConstants
+        },
+        render_layers::RenderLayer,
+        mesh::Mesh,
+        material::Material,
+        scene::Scene
+    },
+    voxel::{
+        chunk::{
+            ChunkPosition,
+            CHUNK_SIZE
+        },
+        meshing::{
+            self,
+            ChunkMeshData,
+            VoxelVertex
+        },
+        world::World
+    }
+};
+
+// Keeps render states with meshes for every non-empty chunk of a world.
+// All chunks share the materials and the vertex push constants, e.g. the camera matrices.
+pub struct WorldRenderer<V> where
+    V: PushConstants + Clone + 'static {
+    chunk_states: HashMap<ChunkPosition, ChunkRenderStates<V>>,
+    vertex_constants: Option<V>
+}
+
+struct ChunkRenderStates<V> where
+    V: PushConstants + Clone + 'static {
+    opaque: Option<RenderState<'static, (), V, ()>>,
+    translucent: Option<RenderState<'static, (), V, ()>>
+}
+
+impl<V> WorldRenderer<V> where
+    V: PushConstants + Clone + 'static {
+    pub fn new() -> Self {
+        Self {
+            chunk_states: HashMap::new(),
+            vertex_constants: None
+        }
+    }
+
+    // Remeshes at most `max_remeshes` chunks scheduled for it, so that large edits
+    // are spread over several frames; the rest stay queued in the world.
+    // Translucent blocks are drawn with the translucent material, which should blend.
+    pub fn update(
+        &mut self,
+        world: &mut World,
+        renderer: &Renderer,
+        material: &Material,
+        translucent_material: &Material,
+        max_remeshes: usize
+    ) -> RenderingResult<()> {
+        for _ in 0..max_remeshes {
+            let position = match world.next_chunk_to_remesh() {
+                Some(position) => position,
+                None => break
+            };
+
+            self.remesh_chunk(world, position, renderer, material, translucent_material)?;
+        }
+
+        Ok(())
+    }
+
+    fn remesh_chunk(
+        &mut self,
+        world: &World,
+        position: ChunkPosition,
+        renderer: &Renderer,
+        material: &Material,
+        translucent_material: &Material
+    ) -> RenderingResult<()> {
+        let meshes = meshing::mesh_chunk(world, position);
+        if meshes.opaque.is_empty() && meshes.translucent.is_empty() {
+            self.chunk_states.remove(&position);
+            return Ok(());
+        }
+
+        let chunk_states = ChunkRenderStates {
+            opaque: self.create_render_state(&meshes.opaque, renderer, material)?,
+            translucent: self.create_render_state(
+                &meshes.translucent, renderer, translucent_material)?
+        };
+
+        self.chunk_states.insert(position, chunk_states);
+
+        Ok(())
+    }
+
+    fn create_render_state(
+        &self,
+        mesh_data: &ChunkMeshData,
+        renderer: &Renderer,
+        material: &Material
+    ) -> RenderingResult<Option<RenderState<'static, (), V, ()>>> {
+        if mesh_data.is_empty() {
+            return Ok(None);
+        }
+
+        let mut mesh_builder = Mesh::builder()
+            .renderer(renderer)
+            .vertices(&mesh_data.vertices)
+            .indices(&mesh_data.indices);
+
+        for (format, offset) in VoxelVertex::ATTRIBUTES.iter() {
+            mesh_builder = mesh_builder.vertex_attribute(*format, *offset);
+        }
+
+        let mesh = mesh_builder.build()?;
+        let mut render_state = RenderState::builder()
+            .renderer(renderer)
+            .material(material)
+            .mesh(&mesh)
+            .build()?;
+
+        if let Some(constants) = &self.vertex_constants {
+            render_state.push_vertex_constants(constants.clone());
+        }
+
+        Ok(Some(render_state))
+    }
+
+    pub fn push_vertex_constants(&mut self, constants: V) {
+        for chunk_states in self.chunk_states.values_mut() {
+            let render_states = chunk_states.opaque
+                .iter_mut()
+                .chain(chunk_states.translucent.iter_mut());
+
+            for render_state in render_states {
+                render_state.push_vertex_constants(constants.clone());
+            }
+        }
+
+        self.vertex_constants = Some(constants);
+    }
+
+    pub fn chunk_count(&self) -> usize {
+        self.chunk_states.len()
+    }
+
+    // Translucent chunks are sorted together with other translucent objects by their centers
+    pub fn add_to_scene<'a>(&'a self, scene: &mut Scene<'a>) {
+        for (position, chunk_states) in &self.chunk_states {
+            let center = position.origin().add_scalar(CHUNK_SIZE as i32 / 2);
+            let transform = glm::translation(
+                &glm::vec3(center.x as f32, center.y as f32, center.z as f32));
+
+            if let Some(render_state) = &chunk_states.opaque {
+                scene.add(RenderLayer::World, render_state, transform);
+            }
+
+            if let Some(render_state) = &chunk_states.translucent {
+                scene.add(RenderLayer::Translucent, render_state, transform);
+            }
+        }
+    }
+}
+
+impl<V> Default for WorldRenderer<V> where
+    V: PushConstants + Clone + 'static {
+    fn default() -> Self {
+        Self::new()
+    }
+}
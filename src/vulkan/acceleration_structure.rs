@@ -0,0 +1,502 @@
+use std::rc::Rc;
+use ash::vk;
+use crate::{
+    builder::{
+        BuilderRequirement,
+        BuilderInternal,
+        BuilderProduct
+    },
+    vulkan::{
+        VulkanError,
+        VulkanResult,
+        handle::VulkanHandle,
+        leak_tracking,
+        logical_device::LogicalDevice,
+        allocator::{
+            Allocator,
+            MemoryUsage
+        },
+        buffer::Buffer
+    }
+};
+
+// Triangles of a bottom level acceleration structure, with vertex positions as 3 floats
+// at the start of every vertex and 32-bit indices, like the ones of `Mesh`
+#[derive(Clone)]
+pub struct TriangleGeometry {
+    vertex_buffer: Rc<Buffer>,
+    vertex_count: u32,
+    vertex_stride: usize,
+    index_buffer: Option<(Rc<Buffer>, u32)>,
+    is_opaque: bool
+}
+
+impl TriangleGeometry {
+    pub fn new(vertex_buffer: Rc<Buffer>, vertex_count: u32, vertex_stride: usize) -> Self {
+        Self {
+            vertex_buffer,
+            vertex_count,
+            vertex_stride,
+            index_buffer: None,
+            is_opaque: true
+        }
+    }
+
+    pub fn indexed(mut self, index_buffer: Rc<Buffer>, index_count: u32) -> Self {
+        self.index_buffer = Some((index_buffer, index_count));
+        self
+    }
+
+    // Any hit shaders are skipped for opaque geometry, defaults to true
+    pub fn opaque(mut self, is_opaque: bool) -> Self {
+        self.is_opaque = is_opaque;
+        self
+    }
+
+    fn vk_geometry(&self) -> vk::GeometryNV {
+        let (index_data, index_count, index_type) = match &self.index_buffer {
+            Some((index_buffer, index_count)) => {
+                (index_buffer.handle(), *index_count, vk::IndexType::UINT32)
+            },
+            None => (vk::Buffer::null(), 0, vk::IndexType::NONE_NV)
+        };
+
+        let triangles = vk::GeometryTrianglesNV::builder()
+            .vertex_data(self.vertex_buffer.handle())
+            .vertex_count(self.vertex_count)
+            .vertex_stride(self.vertex_stride as vk::DeviceSize)
+            .vertex_format(vk::Format::R32G32B32_SFLOAT)
+            .index_data(index_data)
+            .index_count(index_count)
+            .index_type(index_type)
+            .build();
+
+        let flags = if self.is_opaque {
+            vk::GeometryFlagsNV::OPAQUE
+        }
+        else {
+            vk::GeometryFlagsNV::empty()
+        };
+
+        vk::GeometryNV::builder()
+            .geometry_type(vk::GeometryTypeNV::TRIANGLES)
+            .geometry(vk::GeometryDataNV::builder().triangles(triangles).build())
+            .flags(flags)
+            .build()
+    }
+}
+
+// Placement of a bottom level structure in a top level one
+#[derive(Clone, Copy, Debug)]
+pub struct AccelerationStructureInstance {
+    // Top three rows of the instance's transform
+    pub transform: [[f32; 4]; 3],
+    // Seen by shaders as gl_InstanceCustomIndexNV, only the lower 24 bits are kept
+    pub custom_index: u32,
+    // Rays are tested against the instance if their cull mask shares a bit with it
+    pub mask: u8,
+    // Index of the instance's first hit group in the shader binding table, 24 bits
+    pub hit_group_offset: u32,
+    pub flags: vk::GeometryInstanceFlagsNV,
+    bottom_level_handle: u64
+}
+
+impl AccelerationStructureInstance {
+    // Untransformed, visible to all rays and using the first hit group
+    pub fn new(bottom_level: &AccelerationStructure) -> Self {
+        Self {
+            transform: [
+                [1.0, 0.0, 0.0, 0.0],
+                [0.0, 1.0, 0.0, 0.0],
+                [0.0, 0.0, 1.0, 0.0]
+            ],
+            custom_index: 0,
+            mask: 0xff,
+            hit_group_offset: 0,
+            flags: vk::GeometryInstanceFlagsNV::empty(),
+            bottom_level_handle: bottom_level.device_handle
+        }
+    }
+}
+
+// Layout of VkGeometryInstanceNV
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct GpuInstance {
+    transform: [[f32; 4]; 3],
+    custom_index_and_mask: u32,
+    hit_group_offset_and_flags: u32,
+    bottom_level_handle: u64
+}
+
+impl From<&AccelerationStructureInstance> for GpuInstance {
+    fn from(instance: &AccelerationStructureInstance) -> Self {
+        Self {
+            transform: instance.transform,
+            custom_index_and_mask:
+                (instance.custom_index & 0x00ff_ffff) | (u32::from(instance.mask) << 24),
+            hit_group_offset_and_flags:
+                (instance.hit_group_offset & 0x00ff_ffff) | (instance.flags.as_raw() << 24),
+            bottom_level_handle: instance.bottom_level_handle
+        }
+    }
+}
+
+// Needs VK_NV_ray_tracing. Bottom level structures hold triangles and top level ones
+// hold instances of bottom level ones, which have to be built before them.
+// Structures are built with `CommandBufferRecorder::build_acceleration_structure`.
+pub struct AccelerationStructure {
+    vk_acceleration_structure: vk::AccelerationStructureNV,
+    allocation: vk_mem::Allocation,
+    // Refers to the structure in instances of top level structures
+    device_handle: u64,
+    flags: vk::BuildAccelerationStructureFlagsNV,
+    geometries: Vec<TriangleGeometry>,
+    max_instance_count: u32,
+    scratch_size: vk::DeviceSize,
+    allocator: Rc<Allocator>,
+    logical_device: Rc<LogicalDevice>
+}
+
+impl AccelerationStructure {
+    pub fn builder() -> AccelerationStructureBuilder {
+        AccelerationStructureBuilder {
+            ..Default::default()
+        }
+    }
+
+    pub fn is_top_level(&self) -> bool {
+        self.geometries.is_empty()
+    }
+
+    pub fn max_instance_count(&self) -> u32 {
+        self.max_instance_count
+    }
+
+    // Size of the scratch buffer the structure is built with
+    pub fn scratch_size(&self) -> vk::DeviceSize {
+        self.scratch_size
+    }
+
+    // Device local and usable for building any structure of at most `scratch_size` bytes
+    pub fn create_scratch_buffer(&self) -> VulkanResult<Buffer> {
+        Buffer::builder()
+            .allocator(Rc::clone(&self.allocator))
+            .size(self.scratch_size as usize)
+            .usage(vk::BufferUsageFlags::RAY_TRACING_NV)
+            .memory_usage(MemoryUsage::GpuOnly)
+            .build()
+    }
+
+    // Host visible buffer of the instances of a top level structure,
+    // for `CommandBufferRecorder::build_acceleration_structure`
+    pub fn create_instance_buffer(
+        &self,
+        instances: &[AccelerationStructureInstance]
+    ) -> VulkanResult<Buffer> {
+        if instances.len() > self.max_instance_count as usize {
+            return Err(VulkanError::AccelerationStructureInstanceCount {
+                count: instances.len(),
+                max_count: self.max_instance_count
+            });
+        }
+
+        let gpu_instances: Vec<GpuInstance> = instances.iter().map(GpuInstance::from).collect();
+        let data = unsafe {
+            std::slice::from_raw_parts(
+                gpu_instances.as_ptr() as *const u8,
+                gpu_instances.len() * std::mem::size_of::<GpuInstance>())
+        };
+
+        let mut buffer = Buffer::builder()
+            .allocator(Rc::clone(&self.allocator))
+            .size(data.len().max(1))
+            .usage(vk::BufferUsageFlags::RAY_TRACING_NV)
+            .memory_usage(MemoryUsage::CpuToGpu)
+            .host_mapped(true)
+            .build()?;
+
+        buffer.write_data(0, data)?;
+
+        Ok(buffer)
+    }
+
+    // Geometries borrowed by the returned info have to outlive its use
+    pub(crate) fn vk_geometries(&self) -> Vec<vk::GeometryNV> {
+        self.geometries.iter().map(TriangleGeometry::vk_geometry).collect()
+    }
+
+    pub(crate) fn vk_info(
+        &self,
+        vk_geometries: &[vk::GeometryNV],
+        instance_count: u32
+    ) -> vk::AccelerationStructureInfoNV {
+        let ty = if self.is_top_level() {
+            vk::AccelerationStructureTypeNV::TOP_LEVEL
+        }
+        else {
+            vk::AccelerationStructureTypeNV::BOTTOM_LEVEL
+        };
+
+        vk::AccelerationStructureInfoNV::builder()
+            .ty(ty)
+            .flags(self.flags)
+            .instance_count(instance_count)
+            .geometries(vk_geometries)
+            .build()
+    }
+}
+
+impl VulkanHandle for AccelerationStructure {
+    type Handle = vk::AccelerationStructureNV;
+
+    fn handle(&self) -> Self::Handle {
+        self.vk_acceleration_structure
+    }
+}
+
+impl Drop for AccelerationStructure {
+    fn drop(&mut self) {
+        leak_tracking::track_destruction(self);
+
+        // Structures cannot be created without the extension's functions
+        let ray_tracing_fn = self.logical_device.ray_tracing_fn().unwrap();
+        unsafe {
+            ray_tracing_fn.destroy_acceleration_structure_nv(
+                self.logical_device.handle(),
+                self.vk_acceleration_structure,
+                std::ptr::null());
+        }
+
+        self.allocator
+            .free_memory(&self.allocation)
+            .unwrap();
+    }
+}
+
+#[derive(Default)]
+pub struct AccelerationStructureBuilder {
+    logical_device: BuilderRequirement<Rc<LogicalDevice>>,
+    allocator: BuilderRequirement<Rc<Allocator>>,
+    geometries: Vec<TriangleGeometry>,
+    max_instance_count: u32,
+    flags: Option<vk::BuildAccelerationStructureFlagsNV>,
+
+    vk_acceleration_structure: BuilderInternal<vk::AccelerationStructureNV>,
+    allocation: BuilderInternal<vk_mem::Allocation>,
+    device_handle: BuilderInternal<u64>,
+    scratch_size: BuilderInternal<vk::DeviceSize>,
+
+    acceleration_structure: BuilderProduct<AccelerationStructure>
+}
+
+impl AccelerationStructureBuilder {
+    pub fn logical_device(mut self, logical_device: Rc<LogicalDevice>) -> Self {
+        self.logical_device.set(logical_device);
+        self
+    }
+
+    pub fn allocator(mut self, allocator: Rc<Allocator>) -> Self {
+        self.allocator.set(allocator);
+        self
+    }
+
+    // Makes a bottom level structure
+    pub fn geometry(mut self, geometry: TriangleGeometry) -> Self {
+        self.geometries.push(geometry);
+        self
+    }
+
+    // Makes a top level structure of at most the given number of instances
+    pub fn max_instance_count(mut self, max_instance_count: u32) -> Self {
+        self.max_instance_count = max_instance_count;
+        self
+    }
+
+    // Defaults to PREFER_FAST_TRACE
+    pub fn flags(mut self, flags: vk::BuildAccelerationStructureFlagsNV) -> Self {
+        self.flags = Some(flags);
+        self
+    }
+
+    pub fn build(mut self) -> VulkanResult<AccelerationStructure> {
+        check_required_fields!(
+            self, VulkanError, "AccelerationStructureBuilder",
+            [logical_device, allocator]);
+
+        if self.logical_device.ray_tracing_fn().is_none() {
+            return Err(VulkanError::RayTracingNotEnabled);
+        }
+
+        if self.geometries.is_empty() == (self.max_instance_count == 0) {
+            return Err(VulkanError::AccelerationStructureLevelMismatch);
+        }
+
+        self.init_vk_acceleration_structure()?;
+
+        if let Err(error) = self.init_memory() {
+            self.destroy_vk_acceleration_structure();
+            return Err(error);
+        }
+
+        self.create_acceleration_structure();
+
+        Ok(self.acceleration_structure.unwrap())
+    }
+
+    fn vk_info(&self, vk_geometries: &[vk::GeometryNV]) -> vk::AccelerationStructureInfoNV {
+        let ty = if self.geometries.is_empty() {
+            vk::AccelerationStructureTypeNV::TOP_LEVEL
+        }
+        else {
+            vk::AccelerationStructureTypeNV::BOTTOM_LEVEL
+        };
+
+        vk::AccelerationStructureInfoNV::builder()
+            .ty(ty)
+            .flags(self.vk_flags())
+            .instance_count(self.max_instance_count)
+            .geometries(vk_geometries)
+            .build()
+    }
+
+    fn vk_flags(&self) -> vk::BuildAccelerationStructureFlagsNV {
+        self.flags.unwrap_or(vk::BuildAccelerationStructureFlagsNV::PREFER_FAST_TRACE)
+    }
+
+    fn init_vk_acceleration_structure(&mut self) -> VulkanResult<()> {
+        let vk_geometries: Vec<vk::GeometryNV> = self.geometries
+            .iter()
+            .map(TriangleGeometry::vk_geometry)
+            .collect();
+
+        let create_info = vk::AccelerationStructureCreateInfoNV::builder()
+            .info(self.vk_info(&vk_geometries));
+
+        let ray_tracing_fn = self.logical_device.ray_tracing_fn().unwrap();
+        let mut vk_acceleration_structure = vk::AccelerationStructureNV::null();
+        let result = unsafe {
+            ray_tracing_fn.create_acceleration_structure_nv(
+                self.logical_device.handle(),
+                &*create_info,
+                std::ptr::null(),
+                &mut vk_acceleration_structure)
+        };
+
+        if result != vk::Result::SUCCESS {
+            return Err(VulkanError::AccelerationStructureCreateError {result});
+        }
+
+        self.vk_acceleration_structure.set(vk_acceleration_structure);
+
+        Ok(())
+    }
+
+    fn memory_requirements(
+        &self,
+        ty: vk::AccelerationStructureMemoryRequirementsTypeNV
+    ) -> vk::MemoryRequirements {
+        let info = vk::AccelerationStructureMemoryRequirementsInfoNV::builder()
+            .ty(ty)
+            .acceleration_structure(*self.vk_acceleration_structure);
+
+        let ray_tracing_fn = self.logical_device.ray_tracing_fn().unwrap();
+        let mut requirements = vk::MemoryRequirements2::default();
+        unsafe {
+            ray_tracing_fn.get_acceleration_structure_memory_requirements_nv(
+                self.logical_device.handle(),
+                &*info,
+                &mut requirements);
+        }
+
+        requirements.memory_requirements
+    }
+
+    fn init_memory(&mut self) -> VulkanResult<()> {
+        let requirements =
+            self.memory_requirements(vk::AccelerationStructureMemoryRequirementsTypeNV::OBJECT);
+
+        let allocation_create_info = vk_mem::AllocationCreateInfo {
+            usage: MemoryUsage::GpuOnly,
+            ..Default::default()
+        };
+
+        let (allocation, allocation_info) = self.allocator
+            .allocate_memory(&requirements, &allocation_create_info)
+            .map_err(|error| VulkanError::AccelerationStructureAllocateError {error})?;
+
+        let bind_info = vk::BindAccelerationStructureMemoryInfoNV::builder()
+            .acceleration_structure(*self.vk_acceleration_structure)
+            .memory(allocation_info.get_device_memory())
+            .memory_offset(allocation_info.get_offset() as vk::DeviceSize)
+            .build();
+
+        let ray_tracing_fn = self.logical_device.ray_tracing_fn().unwrap();
+        let mut device_handle = 0u64;
+        let result = unsafe {
+            let result = ray_tracing_fn.bind_acceleration_structure_memory_nv(
+                self.logical_device.handle(),
+                1,
+                &bind_info);
+
+            if result != vk::Result::SUCCESS {
+                Err(VulkanError::AccelerationStructureBindError {result})
+            }
+            else {
+                let result = ray_tracing_fn.get_acceleration_structure_handle_nv(
+                    self.logical_device.handle(),
+                    *self.vk_acceleration_structure,
+                    std::mem::size_of::<u64>(),
+                    &mut device_handle as *mut u64 as *mut std::ffi::c_void);
+
+                if result != vk::Result::SUCCESS {
+                    Err(VulkanError::AccelerationStructureHandleError {result})
+                }
+                else {
+                    Ok(())
+                }
+            }
+        };
+
+        if let Err(error) = result {
+            let _ = self.allocator.free_memory(&allocation);
+            return Err(error);
+        }
+
+        let scratch_requirements = self.memory_requirements(
+            vk::AccelerationStructureMemoryRequirementsTypeNV::BUILD_SCRATCH);
+
+        self.allocation.set(allocation);
+        self.device_handle.set(device_handle);
+        self.scratch_size.set(scratch_requirements.size);
+
+        Ok(())
+    }
+
+    fn destroy_vk_acceleration_structure(&mut self) {
+        let ray_tracing_fn = self.logical_device.ray_tracing_fn().unwrap();
+        unsafe {
+            ray_tracing_fn.destroy_acceleration_structure_nv(
+                self.logical_device.handle(),
+                self.vk_acceleration_structure.take(),
+                std::ptr::null());
+        }
+    }
+
+    fn create_acceleration_structure(&mut self) {
+        let acceleration_structure = AccelerationStructure {
+            vk_acceleration_structure: self.vk_acceleration_structure.take(),
+            allocation: self.allocation.take(),
+            device_handle: self.device_handle.take(),
+            flags: self.vk_flags(),
+            geometries: std::mem::take(&mut self.geometries),
+            max_instance_count: self.max_instance_count,
+            scratch_size: self.scratch_size.take(),
+            allocator: self.allocator.take(),
+            logical_device: self.logical_device.take()
+        };
+
+        leak_tracking::track_creation(&acceleration_structure);
+        self.acceleration_structure.set(acceleration_structure);
+    }
+}
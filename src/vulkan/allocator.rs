@@ -0,0 +1,90 @@
+use std::rc::Rc;
+use crate::vulkan::{
+    VulkanError,
+    VulkanResult,
+    handle::VulkanHandle,
+    state::VulkanState,
+    physical_device::PhysicalDevice,
+    logical_device::LogicalDevice
+};
+pub use vk_mem::MemoryUsage;
+
+pub struct Allocator {
+    vk_mem_allocator: vk_mem::Allocator,
+    // lifetime extenders
+    _logical_device: Rc<LogicalDevice>
+}
+
+impl Allocator {
+    pub fn new(
+        vulkan_state: &VulkanState,
+        physical_device: &PhysicalDevice,
+        logical_device: Rc<LogicalDevice>
+    ) -> VulkanResult<Self> {
+        let create_info = vk_mem::AllocatorCreateInfo {
+            physical_device: physical_device.handle(),
+            device: logical_device.loader().clone(),
+            instance: vulkan_state.instance_loader().clone(),
+            ..Default::default()
+        };
+
+        let vk_mem_allocator = vk_mem::Allocator::new(&create_info)
+            .map_err(|error| VulkanError::AllocatorCreateError {error})?;
+
+        Ok(Self {
+            vk_mem_allocator,
+            _logical_device: logical_device
+        })
+    }
+
+    pub fn handle(&self) -> &vk_mem::Allocator {
+        &self.vk_mem_allocator
+    }
+
+    // Summed over all memory heaps, both device local and host visible
+    pub fn statistics(&self) -> VulkanResult<AllocatorStatistics> {
+        let stats = self.vk_mem_allocator
+            .calculate_stats()
+            .map_err(|error| VulkanError::AllocatorStatisticsError {error})?;
+
+        Ok(AllocatorStatistics {
+            allocation_count: stats.total.allocationCount,
+            used_bytes: stats.total.usedBytes,
+            allocated_bytes: stats.total.usedBytes + stats.total.unusedBytes
+        })
+    }
+
+    // Indexed like the physical device's memory heaps
+    pub fn heap_statistics(&self, heap_count: usize) -> VulkanResult<Vec<AllocatorStatistics>> {
+        let stats = self.vk_mem_allocator
+            .calculate_stats()
+            .map_err(|error| VulkanError::AllocatorStatisticsError {error})?;
+
+        Ok(stats.memoryHeap
+            .iter()
+            .take(heap_count)
+            .map(|heap| AllocatorStatistics {
+                allocation_count: heap.allocationCount,
+                used_bytes: heap.usedBytes,
+                allocated_bytes: heap.usedBytes + heap.unusedBytes
+            })
+            .collect())
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct AllocatorStatistics {
+    pub allocation_count: u32,
+    // Taken by live allocations
+    pub used_bytes: u64,
+    // Taken by device memory blocks, including their free ranges
+    pub allocated_bytes: u64
+}
+
+impl std::ops::Deref for Allocator {
+    type Target = vk_mem::Allocator;
+
+    fn deref(&self) -> &Self::Target {
+        &self.vk_mem_allocator
+    }
+}
@@ -0,0 +1,427 @@
+use std::ops::Range;
+use ash::vk;
+use crate::vulkan::{
+    handle::VulkanHandle,
+    buffer::Buffer,
+    image::Image
+};
+
+// Pipeline stages and memory accesses on one side of a dependency.
+// Like in VK_KHR_synchronization2, every barrier carries its own stages. The bindings in use
+// do not expose that extension, so the stages of all barriers are merged into the masks of
+// a single vkCmdPipelineBarrier when recorded.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct MemoryAccess {
+    pub stages: vk::PipelineStageFlags,
+    pub access: vk::AccessFlags
+}
+
+impl MemoryAccess {
+    pub fn new(stages: vk::PipelineStageFlags, access: vk::AccessFlags) -> Self {
+        Self {
+            stages,
+            access
+        }
+    }
+
+    // Nothing is waited for on the source side, nothing waits on the destination side
+    pub fn none() -> Self {
+        Self::new(vk::PipelineStageFlags::empty(), vk::AccessFlags::empty())
+    }
+
+    // Only execution is ordered, e.g. before overwriting what the stages read
+    pub fn execution(stages: vk::PipelineStageFlags) -> Self {
+        Self::new(stages, vk::AccessFlags::empty())
+    }
+
+    pub fn transfer_read() -> Self {
+        Self::new(vk::PipelineStageFlags::TRANSFER, vk::AccessFlags::TRANSFER_READ)
+    }
+
+    pub fn transfer_write() -> Self {
+        Self::new(vk::PipelineStageFlags::TRANSFER, vk::AccessFlags::TRANSFER_WRITE)
+    }
+
+    pub fn vertex_input_read() -> Self {
+        Self::new(
+            vk::PipelineStageFlags::VERTEX_INPUT,
+            vk::AccessFlags::VERTEX_ATTRIBUTE_READ | vk::AccessFlags::INDEX_READ)
+    }
+
+    // Commands of `CommandBufferRecorder::draw_indexed_indirect`
+    pub fn indirect_command_read() -> Self {
+        Self::new(vk::PipelineStageFlags::DRAW_INDIRECT, vk::AccessFlags::INDIRECT_COMMAND_READ)
+    }
+
+    pub fn uniform_read(stages: vk::PipelineStageFlags) -> Self {
+        Self::new(stages, vk::AccessFlags::UNIFORM_READ)
+    }
+
+    pub fn shader_read(stages: vk::PipelineStageFlags) -> Self {
+        Self::new(stages, vk::AccessFlags::SHADER_READ)
+    }
+
+    pub fn shader_write(stages: vk::PipelineStageFlags) -> Self {
+        Self::new(stages, vk::AccessFlags::SHADER_WRITE)
+    }
+
+    pub fn fragment_shader_read() -> Self {
+        Self::shader_read(vk::PipelineStageFlags::FRAGMENT_SHADER)
+    }
+
+    // Storage buffers and images both read and written by the stages
+    pub fn shader_read_write(stages: vk::PipelineStageFlags) -> Self {
+        Self::new(stages, vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE)
+    }
+
+    pub fn compute_shader_read() -> Self {
+        Self::shader_read(vk::PipelineStageFlags::COMPUTE_SHADER)
+    }
+
+    pub fn compute_shader_write() -> Self {
+        Self::shader_write(vk::PipelineStageFlags::COMPUTE_SHADER)
+    }
+
+    pub fn fragment_shader_write() -> Self {
+        Self::shader_write(vk::PipelineStageFlags::FRAGMENT_SHADER)
+    }
+
+    // Acceleration structures and the scratch buffers of their builds
+    pub fn acceleration_structure_build_read() -> Self {
+        Self::new(
+            vk::PipelineStageFlags::ACCELERATION_STRUCTURE_BUILD_NV,
+            vk::AccessFlags::ACCELERATION_STRUCTURE_READ_NV)
+    }
+
+    pub fn acceleration_structure_build_write() -> Self {
+        Self::new(
+            vk::PipelineStageFlags::ACCELERATION_STRUCTURE_BUILD_NV,
+            vk::AccessFlags::ACCELERATION_STRUCTURE_WRITE_NV)
+    }
+
+    // Acceleration structures rays are traced against
+    pub fn ray_tracing_read() -> Self {
+        Self::new(
+            vk::PipelineStageFlags::RAY_TRACING_SHADER_NV,
+            vk::AccessFlags::ACCELERATION_STRUCTURE_READ_NV)
+    }
+
+    pub fn color_attachment_write() -> Self {
+        Self::new(
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+    }
+
+    pub fn depth_attachment_write() -> Self {
+        Self::new(
+            vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+                | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+            vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+    }
+
+    // Predicates of `CommandBufferRecorder::begin_conditional_rendering`
+    pub fn conditional_rendering_read() -> Self {
+        Self::new(
+            vk::PipelineStageFlags::CONDITIONAL_RENDERING_EXT,
+            vk::AccessFlags::CONDITIONAL_RENDERING_READ_EXT)
+    }
+
+    pub fn host_read() -> Self {
+        Self::new(vk::PipelineStageFlags::HOST, vk::AccessFlags::HOST_READ)
+    }
+
+    pub fn host_write() -> Self {
+        Self::new(vk::PipelineStageFlags::HOST, vk::AccessFlags::HOST_WRITE)
+    }
+
+    pub fn union(self, other: MemoryAccess) -> Self {
+        Self::new(self.stages | other.stages, self.access | other.access)
+    }
+}
+
+pub struct BufferBarrier {
+    buffer: vk::Buffer,
+    offset: vk::DeviceSize,
+    size: vk::DeviceSize,
+    src: MemoryAccess,
+    dst: MemoryAccess,
+    queue_families: (u32, u32)
+}
+
+impl BufferBarrier {
+    // The whole buffer is covered unless a range is given
+    pub fn new(buffer: &Buffer, (src, dst): (MemoryAccess, MemoryAccess)) -> Self {
+        Self {
+            buffer: buffer.handle(),
+            offset: 0,
+            size: vk::WHOLE_SIZE,
+            src,
+            dst,
+            queue_families: (vk::QUEUE_FAMILY_IGNORED, vk::QUEUE_FAMILY_IGNORED)
+        }
+    }
+
+    pub fn range(mut self, offset: vk::DeviceSize, size: vk::DeviceSize) -> Self {
+        self.offset = offset;
+        self.size = size;
+        self
+    }
+
+    // Queue family indices of an ownership transfer, see `QueueOwnershipTransfer`
+    pub fn queue_families(mut self, src: u32, dst: u32) -> Self {
+        self.queue_families = (src, dst);
+        self
+    }
+
+    pub fn src(&self) -> MemoryAccess {
+        self.src
+    }
+
+    pub fn dst(&self) -> MemoryAccess {
+        self.dst
+    }
+
+    pub fn with_accesses(&self, src: MemoryAccess, dst: MemoryAccess) -> Self {
+        Self {
+            src,
+            dst,
+            ..*self
+        }
+    }
+
+    fn vk_barrier(&self) -> vk::BufferMemoryBarrier {
+        vk::BufferMemoryBarrier::builder()
+            .buffer(self.buffer)
+            .offset(self.offset)
+            .size(self.size)
+            .src_access_mask(self.src.access)
+            .dst_access_mask(self.dst.access)
+            .src_queue_family_index(self.queue_families.0)
+            .dst_queue_family_index(self.queue_families.1)
+            .build()
+    }
+}
+
+pub struct ImageBarrier {
+    image: vk::Image,
+    aspect: vk::ImageAspectFlags,
+    mip_levels: Range<u32>,
+    array_layers: Range<u32>,
+    old_layout: vk::ImageLayout,
+    new_layout: vk::ImageLayout,
+    src: MemoryAccess,
+    dst: MemoryAccess,
+    queue_families: (u32, u32)
+}
+
+impl ImageBarrier {
+    // All mip levels and layers of the image's aspect are covered unless narrowed down.
+    // The old layout may be UNDEFINED if the previous contents can be discarded.
+    pub fn new(
+        image: &Image,
+        layouts: (vk::ImageLayout, vk::ImageLayout),
+        accesses: (MemoryAccess, MemoryAccess)
+    ) -> Self {
+        Self::from_handle(image.handle(), layouts, accesses)
+            .aspect(image.aspect())
+            .mip_levels(0..image.mip_levels())
+            .array_layers(0..image.array_layers())
+    }
+
+    // For images not owned by an `Image`, e.g. the swapchain's.
+    // Covers the first mip level and layer unless told otherwise.
+    pub fn from_handle(
+        image: vk::Image,
+        (old_layout, new_layout): (vk::ImageLayout, vk::ImageLayout),
+        (src, dst): (MemoryAccess, MemoryAccess)
+    ) -> Self {
+        debug_assert!(
+            new_layout != vk::ImageLayout::UNDEFINED
+                && new_layout != vk::ImageLayout::PREINITIALIZED,
+            "images cannot be transitioned to {:?}", new_layout);
+
+        Self {
+            image,
+            aspect: vk::ImageAspectFlags::COLOR,
+            mip_levels: 0..1,
+            array_layers: 0..1,
+            old_layout,
+            new_layout,
+            src,
+            dst,
+            queue_families: (vk::QUEUE_FAMILY_IGNORED, vk::QUEUE_FAMILY_IGNORED)
+        }
+    }
+
+    pub fn aspect(mut self, aspect: vk::ImageAspectFlags) -> Self {
+        self.aspect = aspect;
+        self
+    }
+
+    pub fn mip_levels(mut self, mip_levels: Range<u32>) -> Self {
+        self.mip_levels = mip_levels;
+        self
+    }
+
+    pub fn array_layers(mut self, array_layers: Range<u32>) -> Self {
+        self.array_layers = array_layers;
+        self
+    }
+
+    // Queue family indices of an ownership transfer, see `QueueOwnershipTransfer`
+    pub fn queue_families(mut self, src: u32, dst: u32) -> Self {
+        self.queue_families = (src, dst);
+        self
+    }
+
+    pub fn src(&self) -> MemoryAccess {
+        self.src
+    }
+
+    pub fn dst(&self) -> MemoryAccess {
+        self.dst
+    }
+
+    pub fn with_accesses(&self, src: MemoryAccess, dst: MemoryAccess) -> Self {
+        Self {
+            mip_levels: self.mip_levels.clone(),
+            array_layers: self.array_layers.clone(),
+            src,
+            dst,
+            ..*self
+        }
+    }
+
+    fn vk_barrier(&self) -> vk::ImageMemoryBarrier {
+        let subresource_range = vk::ImageSubresourceRange::builder()
+            .aspect_mask(self.aspect)
+            .base_mip_level(self.mip_levels.start)
+            .level_count(self.mip_levels.len() as u32)
+            .base_array_layer(self.array_layers.start)
+            .layer_count(self.array_layers.len() as u32)
+            .build();
+
+        vk::ImageMemoryBarrier::builder()
+            .image(self.image)
+            .old_layout(self.old_layout)
+            .new_layout(self.new_layout)
+            .src_access_mask(self.src.access)
+            .dst_access_mask(self.dst.access)
+            .src_queue_family_index(self.queue_families.0)
+            .dst_queue_family_index(self.queue_families.1)
+            .subresource_range(subresource_range)
+            .build()
+    }
+}
+
+// Set of barriers recorded together by `CommandBufferRecorder::pipeline_barrier`
+#[derive(Default)]
+pub struct PipelineBarrier {
+    memory_barriers: Vec<(MemoryAccess, MemoryAccess)>,
+    buffer_barriers: Vec<BufferBarrier>,
+    image_barriers: Vec<ImageBarrier>
+}
+
+impl PipelineBarrier {
+    pub fn new() -> Self {
+        Self {
+            ..Default::default()
+        }
+    }
+
+    // Covers every resource
+    pub fn memory(mut self, src: MemoryAccess, dst: MemoryAccess) -> Self {
+        self.memory_barriers.push((src, dst));
+        self
+    }
+
+    pub fn buffer(mut self, barrier: BufferBarrier) -> Self {
+        self.buffer_barriers.push(barrier);
+        self
+    }
+
+    pub fn image(mut self, barrier: ImageBarrier) -> Self {
+        self.image_barriers.push(barrier);
+        self
+    }
+
+    // Makes everything compute shaders wrote visible to the draws recorded after the barrier,
+    // whether read as indirect commands, vertices and indices or by vertex and fragment shaders
+    pub fn compute_to_graphics(self) -> Self {
+        let shader_stages =
+            vk::PipelineStageFlags::VERTEX_SHADER | vk::PipelineStageFlags::FRAGMENT_SHADER;
+
+        self.memory(
+            MemoryAccess::compute_shader_write(),
+            MemoryAccess::indirect_command_read()
+                .union(MemoryAccess::vertex_input_read())
+                .union(MemoryAccess::shader_read(shader_stages)))
+    }
+
+    // Makes built acceleration structures visible to later builds, e.g. of top level
+    // structures instancing them, and to traced rays. Builds sharing a scratch buffer
+    // need it in between too.
+    pub fn acceleration_structure_build(self) -> Self {
+        self.memory(
+            MemoryAccess::acceleration_structure_build_write(),
+            MemoryAccess::acceleration_structure_build_read()
+                .union(MemoryAccess::acceleration_structure_build_write())
+                .union(MemoryAccess::ray_tracing_read()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.memory_barriers.is_empty()
+            && self.buffer_barriers.is_empty()
+            && self.image_barriers.is_empty()
+    }
+
+    // Empty source and destination stages become TOP_OF_PIPE and BOTTOM_OF_PIPE,
+    // which is what VK_PIPELINE_STAGE_NONE means without synchronization2
+    pub(crate) fn stages(&self) -> (vk::PipelineStageFlags, vk::PipelineStageFlags) {
+        let accesses = self.memory_barriers
+            .iter()
+            .copied()
+            .chain(self.buffer_barriers.iter().map(|barrier| (barrier.src, barrier.dst)))
+            .chain(self.image_barriers.iter().map(|barrier| (barrier.src, barrier.dst)));
+
+        let (src, dst) = accesses.fold(
+            (MemoryAccess::none(), MemoryAccess::none()),
+            |(src, dst), (barrier_src, barrier_dst)| {
+                (src.union(barrier_src), dst.union(barrier_dst))
+            });
+
+        let src_stages = if src.stages.is_empty() {
+            vk::PipelineStageFlags::TOP_OF_PIPE
+        }
+        else {
+            src.stages
+        };
+
+        let dst_stages = if dst.stages.is_empty() {
+            vk::PipelineStageFlags::BOTTOM_OF_PIPE
+        }
+        else {
+            dst.stages
+        };
+
+        (src_stages, dst_stages)
+    }
+
+    pub(crate) fn vk_memory_barriers(&self) -> Vec<vk::MemoryBarrier> {
+        self.memory_barriers
+            .iter()
+            .map(|(src, dst)| vk::MemoryBarrier::builder()
+                .src_access_mask(src.access)
+                .dst_access_mask(dst.access)
+                .build())
+            .collect()
+    }
+
+    pub(crate) fn vk_buffer_barriers(&self) -> Vec<vk::BufferMemoryBarrier> {
+        self.buffer_barriers.iter().map(BufferBarrier::vk_barrier).collect()
+    }
+
+    pub(crate) fn vk_image_barriers(&self) -> Vec<vk::ImageMemoryBarrier> {
+        self.image_barriers.iter().map(ImageBarrier::vk_barrier).collect()
+    }
+}
@@ -0,0 +1,199 @@
+use std::rc::Rc;
+use ash::vk;
+use crate::{
+    builder::{
+        BuilderRequirement,
+        BuilderInternal,
+        BuilderProduct
+    },
+    vulkan::{
+        VulkanError,
+        VulkanResult,
+        handle::VulkanHandle,
+        leak_tracking,
+        allocator::{
+            Allocator,
+            MemoryUsage
+        }
+    }
+};
+
+pub struct Buffer {
+    vk_buffer: vk::Buffer,
+    allocation: vk_mem::Allocation,
+    allocation_info: vk_mem::AllocationInfo,
+    size: usize,
+    allocator: Rc<Allocator>
+}
+
+impl Buffer {
+    pub fn builder() -> BufferBuilder {
+        BufferBuilder {
+            ..Default::default()
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_host_mapped(&self) -> bool {
+        !self.allocation_info.get_mapped_data().is_null()
+    }
+
+    pub fn mapped_data(&self) -> VulkanResult<&[u8]> {
+        let mapped_data = self.mapped_pointer()?;
+
+        self.allocator
+            .invalidate_allocation(&self.allocation, 0, self.size)
+            .map_err(|error| VulkanError::BufferMemoryAccessError {error})?;
+
+        Ok(unsafe {
+            std::slice::from_raw_parts(mapped_data, self.size)
+        })
+    }
+
+    pub fn write_data(&mut self, offset: usize, data: &[u8]) -> VulkanResult<()> {
+        if offset + data.len() > self.size {
+            return Err(VulkanError::BufferWriteOutOfBoundsError);
+        }
+
+        let mapped_data = self.mapped_pointer()?;
+
+        unsafe {
+            std::ptr::copy_nonoverlapping(data.as_ptr(), mapped_data.add(offset), data.len());
+        }
+
+        self.allocator
+            .flush_allocation(&self.allocation, offset, data.len())
+            .map_err(|error| VulkanError::BufferMemoryAccessError {error})?;
+
+        Ok(())
+    }
+
+    fn mapped_pointer(&self) -> VulkanResult<*mut u8> {
+        let mapped_data = self.allocation_info.get_mapped_data();
+
+        if mapped_data.is_null() {
+            Err(VulkanError::BufferNotHostMappedError)
+        }
+        else {
+            Ok(mapped_data)
+        }
+    }
+}
+
+impl VulkanHandle for Buffer {
+    type Handle = vk::Buffer;
+
+    fn handle(&self) -> Self::Handle {
+        self.vk_buffer
+    }
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        leak_tracking::track_destruction(self);
+        self.allocator
+            .destroy_buffer(self.vk_buffer, &self.allocation)
+            .unwrap();
+    }
+}
+
+#[derive(Default)]
+pub struct BufferBuilder {
+    allocator: BuilderRequirement<Rc<Allocator>>,
+    size: BuilderRequirement<usize>,
+    usage: BuilderRequirement<vk::BufferUsageFlags>,
+    memory_usage: BuilderRequirement<MemoryUsage>,
+    host_mapped: bool,
+
+    vk_buffer: BuilderInternal<vk::Buffer>,
+    allocation: BuilderInternal<vk_mem::Allocation>,
+    allocation_info: BuilderInternal<vk_mem::AllocationInfo>,
+
+    buffer: BuilderProduct<Buffer>
+}
+
+impl BufferBuilder {
+    pub fn allocator(mut self, allocator: Rc<Allocator>) -> Self {
+        self.allocator.set(allocator);
+        self
+    }
+
+    pub fn size(mut self, size: usize) -> Self {
+        self.size.set(size);
+        self
+    }
+
+    pub fn usage(mut self, usage: vk::BufferUsageFlags) -> Self {
+        self.usage.set(usage);
+        self
+    }
+
+    pub fn memory_usage(mut self, memory_usage: MemoryUsage) -> Self {
+        self.memory_usage.set(memory_usage);
+        self
+    }
+
+    pub fn host_mapped(mut self, host_mapped: bool) -> Self {
+        self.host_mapped = host_mapped;
+        self
+    }
+
+    pub fn build(mut self) -> VulkanResult<Buffer> {
+        check_required_fields!(
+            self, VulkanError, "BufferBuilder",
+            [allocator, size, usage, memory_usage]);
+
+        self.init_vk_buffer()?;
+        self.create_buffer();
+
+        Ok(self.buffer.unwrap())
+    }
+
+    fn init_vk_buffer(&mut self) -> VulkanResult<()> {
+        let buffer_create_info = vk::BufferCreateInfo::builder()
+            .size(*self.size as vk::DeviceSize)
+            .usage(*self.usage)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+
+        let allocation_create_info = vk_mem::AllocationCreateInfo {
+            usage: self.memory_usage.take(),
+            flags: self.allocation_create_flags(),
+            ..Default::default()
+        };
+
+        let (vk_buffer, allocation, allocation_info) = self.allocator
+            .create_buffer(&buffer_create_info, &allocation_create_info)
+            .map_err(|error| VulkanError::BufferCreateError {error})?;
+
+        self.vk_buffer.set(vk_buffer);
+        self.allocation.set(allocation);
+        self.allocation_info.set(allocation_info);
+
+        Ok(())
+    }
+
+    fn allocation_create_flags(&self) -> vk_mem::AllocationCreateFlags {
+        if self.host_mapped {
+            vk_mem::AllocationCreateFlags::MAPPED
+        }
+        else {
+            vk_mem::AllocationCreateFlags::NONE
+        }
+    }
+
+    fn create_buffer(&mut self) {
+        let buffer = Buffer {
+            vk_buffer: self.vk_buffer.take(),
+            allocation: self.allocation.take(),
+            allocation_info: self.allocation_info.take(),
+            size: self.size.take(),
+            allocator: self.allocator.take()
+        };
+
+        leak_tracking::track_creation(&buffer);
+        self.buffer.set(buffer);
+    }
+}
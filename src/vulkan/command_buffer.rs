@@ -1,47 +1,202 @@
-use std::rc::Rc;
+use std::{
+    ops::{
+        Deref,
+        DerefMut,
+        Range
+    },
+    rc::Rc
+};
 use ash::{
     version::DeviceV1_0,
     vk
 };
+pub use magmacraft_derive::PushConstants;
 use crate::vulkan::{
     VulkanError,
     VulkanResult,
+    handle::VulkanHandle,
     logical_device::LogicalDevice,
+    physical_device::QueueFamily,
+    command_pool::CommandPool,
     render_pass::RenderPass,
     framebuffers::Framebuffers,
     pipeline::Pipeline,
-    shader::ShaderStage
+    shader::ShaderStage,
+    buffer::Buffer,
+    image::Image,
+    descriptor::{
+        DescriptorSet,
+        PushDescriptors
+    },
+    barrier::PipelineBarrier,
+    query::QueryPool,
+    acceleration_structure::AccelerationStructure,
+    ray_tracing::ShaderBindingTable
 };
 
 
 pub struct CommandBuffer {
     vk_command_buffer: vk::CommandBuffer,
     logical_device: Rc<LogicalDevice>,
+    level: vk::CommandBufferLevel,
     submit_once: bool,
-    pipelines_in_use: Vec<Rc<Pipeline>>
+    pipelines_in_use: Vec<Rc<Pipeline>>,
+    buffers_in_use: Vec<Rc<Buffer>>,
+    descriptor_sets_in_use: Vec<Rc<DescriptorSet>>,
+    push_descriptors_in_use: Vec<Rc<PushDescriptors>>,
+    draw_call_count: u32
 }
 
 impl CommandBuffer {
     pub fn from_handle(
         vk_command_buffer: vk::CommandBuffer,
         logical_device: Rc<LogicalDevice>,
+        level: vk::CommandBufferLevel,
         submit_once: bool
     ) -> CommandBuffer {
         Self {
             vk_command_buffer,
             logical_device,
+            level,
             submit_once,
-            pipelines_in_use: Vec::new()
+            pipelines_in_use: Vec::new(),
+            buffers_in_use: Vec::new(),
+            descriptor_sets_in_use: Vec::new(),
+            push_descriptors_in_use: Vec::new(),
+            draw_call_count: 0
         }
     }
 
-    pub fn handle(&self) -> vk::CommandBuffer {
-        self.vk_command_buffer
+    // Draws recorded since the buffer was last recorded, including those of the secondary
+    // buffers it executes, with every draw of an indirect one counted
+    pub fn draw_call_count(&self) -> u32 {
+        self.draw_call_count
+    }
+
+    pub fn is_secondary(&self) -> bool {
+        self.level == vk::CommandBufferLevel::SECONDARY
     }
 
-    pub fn record(&mut self) -> VulkanResult<CommandBufferRecorder> {
+    pub fn record(&mut self) -> VulkanResult<CommandBufferRecorder<'_>> {
         self.pipelines_in_use.clear();
-        CommandBufferRecorder::new(self)
+        self.buffers_in_use.clear();
+        self.descriptor_sets_in_use.clear();
+        self.push_descriptors_in_use.clear();
+        self.draw_call_count = 0;
+        let inheritance_info = vk::CommandBufferInheritanceInfo::default();
+        CommandBufferRecorder::new(self, &inheritance_info, vk::CommandBufferUsageFlags::empty())
+    }
+
+    // Records a secondary command buffer to be executed inside of the given subpass
+    // of a render pass begun with `begin_render_pass_with_secondary_buffers`
+    pub fn record_inside_render_pass(
+        &mut self,
+        render_pass: &RenderPass,
+        subpass: u32,
+        framebuffers: &Framebuffers,
+        framebuffer_index: usize
+    ) -> VulkanResult<CommandBufferRecorder<'_>> {
+        if !self.is_secondary() {
+            return Err(VulkanError::CommandBufferNotSecondaryError);
+        }
+
+        self.pipelines_in_use.clear();
+        self.buffers_in_use.clear();
+        self.descriptor_sets_in_use.clear();
+        self.push_descriptors_in_use.clear();
+        self.draw_call_count = 0;
+        let inheritance_info = vk::CommandBufferInheritanceInfo::builder()
+            .render_pass(render_pass.handle())
+            .subpass(subpass)
+            .framebuffer(framebuffers.handle(framebuffer_index))
+            .build();
+
+        CommandBufferRecorder::new(
+            self,
+            &inheritance_info,
+            vk::CommandBufferUsageFlags::RENDER_PASS_CONTINUE)
+    }
+}
+
+impl VulkanHandle for CommandBuffer {
+    type Handle = vk::CommandBuffer;
+
+    fn handle(&self) -> Self::Handle {
+        self.vk_command_buffer
+    }
+}
+
+// Queue family a command pool and the command buffers allocated from it are typed with,
+// so that command buffers cannot be submitted to queues of other families
+pub trait QueueType: Default + 'static {
+    const FAMILY: QueueFamily;
+}
+
+#[derive(Default)]
+pub struct GraphicsQueue;
+
+#[derive(Default)]
+pub struct ComputeQueue;
+
+#[derive(Default)]
+pub struct TransferQueue;
+
+impl QueueType for GraphicsQueue {
+    const FAMILY: QueueFamily = QueueFamily::Graphics;
+}
+
+impl QueueType for ComputeQueue {
+    const FAMILY: QueueFamily = QueueFamily::Compute;
+}
+
+impl QueueType for TransferQueue {
+    const FAMILY: QueueFamily = QueueFamily::Transfer;
+}
+
+pub type GraphicsCommandBuffer = QueueCommandBuffer<GraphicsQueue>;
+pub type ComputeCommandBuffer = QueueCommandBuffer<ComputeQueue>;
+pub type TransferCommandBuffer = QueueCommandBuffer<TransferQueue>;
+
+// Command buffer allocated from a pool, which it keeps alive and is freed to when dropped
+pub struct QueueCommandBuffer<Q: QueueType> {
+    command_buffer: CommandBuffer,
+    command_pool: Rc<CommandPool<Q>>
+}
+
+impl<Q: QueueType> QueueCommandBuffer<Q> {
+    pub(crate) fn new(command_buffer: CommandBuffer, command_pool: Rc<CommandPool<Q>>) -> Self {
+        Self {
+            command_buffer,
+            command_pool
+        }
+    }
+
+    pub fn queue_family(&self) -> QueueFamily {
+        Q::FAMILY
+    }
+}
+
+impl<Q: QueueType> Deref for QueueCommandBuffer<Q> {
+    type Target = CommandBuffer;
+
+    fn deref(&self) -> &Self::Target {
+        &self.command_buffer
+    }
+}
+
+impl<Q: QueueType> DerefMut for QueueCommandBuffer<Q> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.command_buffer
+    }
+}
+
+impl<Q: QueueType> Drop for QueueCommandBuffer<Q> {
+    fn drop(&mut self) {
+        unsafe {
+            self.command_buffer.logical_device.free_command_buffers(
+                self.command_pool.handle(),
+                &[self.command_buffer.handle()]);
+        }
     }
 }
 
@@ -51,10 +206,15 @@ pub struct CommandBufferRecorder<'a> {
 }
 
 impl<'a> CommandBufferRecorder<'a> {
-    fn new(command_buffer: &'a mut CommandBuffer) -> VulkanResult<Self> {
-        let flags = Self::begin_info_flags(command_buffer.submit_once);
+    fn new(
+        command_buffer: &'a mut CommandBuffer,
+        inheritance_info: &vk::CommandBufferInheritanceInfo,
+        additional_flags: vk::CommandBufferUsageFlags
+    ) -> VulkanResult<Self> {
+        let flags = Self::begin_info_flags(command_buffer.submit_once) | additional_flags;
         let begin_info = vk::CommandBufferBeginInfo::builder()
-            .flags(flags);
+            .flags(flags)
+            .inheritance_info(inheritance_info);
 
         unsafe {
             command_buffer.logical_device
@@ -81,22 +241,68 @@ impl<'a> CommandBufferRecorder<'a> {
         render_pass: &RenderPass,
         framebuffers: &Framebuffers,
         framebuffer_index: usize
+    ) -> Self {
+        self.begin_render_pass_with_contents(
+            render_pass,
+            framebuffers,
+            framebuffer_index,
+            &Self::render_clear_values(),
+            vk::SubpassContents::INLINE)
+    }
+
+    // Clear values are given in the order of the render pass' attachments
+    pub fn begin_render_pass_with_clear_values(
+        self,
+        render_pass: &RenderPass,
+        framebuffers: &Framebuffers,
+        framebuffer_index: usize,
+        clear_values: &[vk::ClearValue]
+    ) -> Self {
+        self.begin_render_pass_with_contents(
+            render_pass,
+            framebuffers,
+            framebuffer_index,
+            clear_values,
+            vk::SubpassContents::INLINE)
+    }
+
+    // The render pass can then only be filled by `execute_commands`
+    pub fn begin_render_pass_with_secondary_buffers(
+        self,
+        render_pass: &RenderPass,
+        framebuffers: &Framebuffers,
+        framebuffer_index: usize
+    ) -> Self {
+        self.begin_render_pass_with_contents(
+            render_pass,
+            framebuffers,
+            framebuffer_index,
+            &Self::render_clear_values(),
+            vk::SubpassContents::SECONDARY_COMMAND_BUFFERS)
+    }
+
+    fn begin_render_pass_with_contents(
+        self,
+        render_pass: &RenderPass,
+        framebuffers: &Framebuffers,
+        framebuffer_index: usize,
+        clear_values: &[vk::ClearValue],
+        contents: vk::SubpassContents
     ) -> Self {
         let render_area = Self::render_area(framebuffers);
-        let render_clear_values = Self::render_clear_values();
 
         let render_pass_begin_info = vk::RenderPassBeginInfo::builder()
             .render_pass(render_pass.handle())
             .framebuffer(framebuffers.handle(framebuffer_index))
             .render_area(render_area)
-            .clear_values(&render_clear_values);
+            .clear_values(clear_values);
 
         unsafe {
             self.command_buffer.logical_device
                 .cmd_begin_render_pass(
                     self.command_buffer.handle(),
                     &render_pass_begin_info,
-                    vk::SubpassContents::INLINE);
+                    contents);
         }
 
         self
@@ -123,6 +329,98 @@ impl<'a> CommandBufferRecorder<'a> {
         }]
     }
 
+    pub fn set_viewport(self, area: vk::Rect2D) -> Self {
+        let viewport = vk::Viewport::builder()
+            .x(area.offset.x as f32)
+            .y(area.offset.y as f32)
+            .width(area.extent.width as f32)
+            .height(area.extent.height as f32)
+            .min_depth(0.0)
+            .max_depth(1.0)
+            .build();
+
+        unsafe {
+            self.command_buffer.logical_device
+                .cmd_set_viewport(self.command_buffer.handle(), 0, &[viewport]);
+            self.command_buffer.logical_device
+                .cmd_set_scissor(self.command_buffer.handle(), 0, &[area]);
+        }
+
+        self
+    }
+
+    // For pipelines built with dynamic depth bias, a clamp other than 0
+    // needs the depth_bias_clamp feature
+    pub fn set_depth_bias(self, constant_factor: f32, clamp: f32, slope_factor: f32) -> Self {
+        unsafe {
+            self.command_buffer.logical_device.cmd_set_depth_bias(
+                self.command_buffer.handle(), constant_factor, clamp, slope_factor);
+        }
+
+        self
+    }
+
+    // Narrows what the following draws can touch without changing the viewport,
+    // `set_viewport` resets it to the whole viewport
+    pub fn set_scissor(self, area: vk::Rect2D) -> Self {
+        unsafe {
+            self.command_buffer.logical_device
+                .cmd_set_scissor(self.command_buffer.handle(), 0, &[area]);
+        }
+
+        self
+    }
+
+    // Fills the rectangles of a color attachment of the current subpass with the color,
+    // without any pipeline and ignoring blending, viewports and scissors
+    pub fn clear_color_attachment(
+        self,
+        attachment: u32,
+        color: [f32; 4],
+        rects: &[vk::Rect2D]
+    ) -> Self {
+        if rects.is_empty() {
+            return self;
+        }
+
+        let clear_attachment = vk::ClearAttachment {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            color_attachment: attachment,
+            clear_value: vk::ClearValue {
+                color: vk::ClearColorValue {
+                    float32: color
+                }
+            }
+        };
+
+        let clear_rects: Vec<vk::ClearRect> = rects
+            .iter()
+            .map(|rect| vk::ClearRect {
+                rect: *rect,
+                base_array_layer: 0,
+                layer_count: 1
+            })
+            .collect();
+
+        unsafe {
+            self.command_buffer.logical_device
+                .cmd_clear_attachments(
+                    self.command_buffer.handle(), &[clear_attachment], &clear_rects);
+        }
+
+        self
+    }
+
+    // Subsequent commands are recorded inline
+    pub fn next_subpass(self) -> Self {
+        unsafe {
+            self.command_buffer.logical_device
+                .cmd_next_subpass(self.command_buffer.handle(), vk::SubpassContents::INLINE);
+        }
+
+        self
+    }
+
     pub fn end_render_pass(self) -> Self {
         unsafe {
             self.command_buffer.logical_device
@@ -137,7 +435,7 @@ impl<'a> CommandBufferRecorder<'a> {
             self.command_buffer.logical_device
                 .cmd_bind_pipeline(
                     self.command_buffer.handle(),
-                    vk::PipelineBindPoint::GRAPHICS,
+                    pipeline.bind_point(),
                     pipeline.handle());
         }
 
@@ -146,34 +444,698 @@ impl<'a> CommandBufferRecorder<'a> {
         self
     }
 
-    pub fn draw(self, vertex_count: u32) -> Self {
+    pub fn execute_commands(self, secondary_command_buffers: &[&CommandBuffer]) -> Self {
+        let handles: Vec<vk::CommandBuffer> = secondary_command_buffers
+            .iter()
+            .map(|command_buffer| command_buffer.handle())
+            .collect();
+
+        unsafe {
+            self.command_buffer.logical_device
+                .cmd_execute_commands(self.command_buffer.handle(), &handles);
+        }
+
+        // Pipelines, buffers and descriptor sets bound by the secondary buffers
+        // have to outlive this buffer's execution too
+        for command_buffer in secondary_command_buffers {
+            self.command_buffer.pipelines_in_use
+                .extend(command_buffer.pipelines_in_use.iter().cloned());
+            self.command_buffer.buffers_in_use
+                .extend(command_buffer.buffers_in_use.iter().cloned());
+            self.command_buffer.descriptor_sets_in_use
+                .extend(command_buffer.descriptor_sets_in_use.iter().cloned());
+            self.command_buffer.push_descriptors_in_use
+                .extend(command_buffer.push_descriptors_in_use.iter().cloned());
+            self.command_buffer.draw_call_count += command_buffer.draw_call_count;
+        }
+
+        self
+    }
+
+    pub fn bind_vertex_buffer(self, binding: u32, buffer: Rc<Buffer>) -> Self {
+        unsafe {
+            self.command_buffer.logical_device
+                .cmd_bind_vertex_buffers(
+                    self.command_buffer.handle(),
+                    binding,
+                    &[buffer.handle()],
+                    &[0]);
+        }
+
+        self.command_buffer.buffers_in_use.push(buffer);
+
+        self
+    }
+
+    // Unlike `bind_vertex_buffer`, the buffer is not kept alive by the command buffer,
+    // its owner has to keep it until the commands finish executing
+    pub fn bind_vertex_buffer_at(self, binding: u32, buffer: &Buffer, offset: usize) -> Self {
+        unsafe {
+            self.command_buffer.logical_device
+                .cmd_bind_vertex_buffers(
+                    self.command_buffer.handle(),
+                    binding,
+                    &[buffer.handle()],
+                    &[offset as vk::DeviceSize]);
+        }
+
+        self
+    }
+
+    pub fn bind_descriptor_set(
+        self,
+        pipeline: &Pipeline,
+        set: u32,
+        descriptor_set: Rc<DescriptorSet>
+    ) -> Self {
+        self.bind_descriptor_set_at(pipeline, set, descriptor_set, &[])
+    }
+
+    // Sets with dynamic buffers take one offset per such binding, in binding order
+    pub fn bind_descriptor_set_at(
+        self,
+        pipeline: &Pipeline,
+        set: u32,
+        descriptor_set: Rc<DescriptorSet>,
+        dynamic_offsets: &[u32]
+    ) -> Self {
+        unsafe {
+            self.command_buffer.logical_device
+                .cmd_bind_descriptor_sets(
+                    self.command_buffer.handle(),
+                    pipeline.bind_point(),
+                    pipeline.layout(),
+                    set,
+                    &[descriptor_set.handle()],
+                    dynamic_offsets);
+        }
+
+        self.command_buffer.descriptor_sets_in_use.push(descriptor_set);
+
+        self
+    }
+
+    // Takes the place of binding a descriptor set at `set`, whose layout in the pipeline
+    // has to be the one the descriptors were written for
+    pub fn push_descriptors(
+        self,
+        pipeline: &Pipeline,
+        set: u32,
+        descriptors: Rc<PushDescriptors>
+    ) -> Self {
+        // Layouts for pushed descriptors cannot be created without the extension
+        let push_descriptor_fn = self.command_buffer.logical_device
+            .push_descriptor_fn()
+            .expect("push descriptors without VK_KHR_push_descriptor");
+
+        let writes = descriptors.writes();
+
+        unsafe {
+            push_descriptor_fn.cmd_push_descriptor_set_khr(
+                self.command_buffer.handle(),
+                pipeline.bind_point(),
+                pipeline.layout(),
+                set,
+                writes.len() as u32,
+                writes.as_ptr());
+        }
+
+        self.command_buffer.push_descriptors_in_use.push(descriptors);
+
+        self
+    }
+
+    // Indices are always 32-bit
+    pub fn bind_index_buffer(self, buffer: Rc<Buffer>) -> Self {
+        unsafe {
+            self.command_buffer.logical_device
+                .cmd_bind_index_buffer(
+                    self.command_buffer.handle(),
+                    buffer.handle(),
+                    0,
+                    vk::IndexType::UINT32);
+        }
+
+        self.command_buffer.buffers_in_use.push(buffer);
+
+        self
+    }
+
+    pub fn draw(self, vertex_count: u32, instance_count: u32) -> Self {
+        unsafe {
+            self.command_buffer.logical_device
+                .cmd_draw(self.command_buffer.handle(), vertex_count, instance_count, 0, 0);
+        }
+
+        self.command_buffer.draw_call_count += 1;
+        self
+    }
+
+    pub fn draw_indexed(self, index_count: u32, instance_count: u32) -> Self {
+        unsafe {
+            self.command_buffer.logical_device
+                .cmd_draw_indexed(
+                    self.command_buffer.handle(), index_count, instance_count, 0, 0, 0);
+        }
+
+        self.command_buffer.draw_call_count += 1;
+        self
+    }
+
+    // Runs the bound compute pipeline, has to be recorded outside of render passes
+    pub fn dispatch(self, group_counts: [u32; 3]) -> Self {
+        let [x, y, z] = group_counts;
+
+        unsafe {
+            self.command_buffer.logical_device
+                .cmd_dispatch(self.command_buffer.handle(), x, y, z);
+        }
+
+        self
+    }
+
+    // Top level structures are built from the given number of instances in the buffer,
+    // see `AccelerationStructure::create_instance_buffer`, and bottom level ones from their
+    // geometries. Later builds and traces need `PipelineBarrier::acceleration_structure_build`
+    // in between. The structure, the buffers and the geometries' buffers have to be kept alive
+    // by their owners.
+    pub fn build_acceleration_structure(
+        self,
+        acceleration_structure: &AccelerationStructure,
+        instances: Option<(&Buffer, u32)>,
+        scratch_buffer: &Buffer
+    ) -> Self {
+        debug_assert!(
+            instances.is_some() == acceleration_structure.is_top_level(),
+            "instances have to be given to top level acceleration structures only");
+        debug_assert!(
+            scratch_buffer.size() as vk::DeviceSize >= acceleration_structure.scratch_size(),
+            "acceleration structure scratch buffer is too small");
+
+        let (instance_buffer, instance_count) = match instances {
+            Some((instance_buffer, instance_count)) => (instance_buffer.handle(), instance_count),
+            None => (vk::Buffer::null(), 0)
+        };
+
+        let vk_geometries = acceleration_structure.vk_geometries();
+        let info = acceleration_structure.vk_info(&vk_geometries, instance_count);
+
+        // Structures cannot be created without the extension's functions
+        let ray_tracing_fn = self.command_buffer.logical_device.ray_tracing_fn().unwrap();
+        unsafe {
+            ray_tracing_fn.cmd_build_acceleration_structure_nv(
+                self.command_buffer.handle(),
+                &info,
+                instance_buffer,
+                0,
+                vk::FALSE,
+                acceleration_structure.handle(),
+                vk::AccelerationStructureNV::null(),
+                scratch_buffer.handle(),
+                0);
+        }
+
+        self
+    }
+
+    // Runs the ray generation shader of the table's pipeline, which has to be bound,
+    // once for every element of the grid. The table is kept alive by the command buffer.
+    pub fn trace_rays(self, table: &ShaderBindingTable, size: [u32; 3]) -> Self {
+        let [width, height, depth] = size;
+        let buffer = table.buffer().handle();
+        let (ray_gen, miss, hit) = table.regions();
+
+        let ray_tracing_fn = self.command_buffer.logical_device.ray_tracing_fn()
+            .expect("rays were traced without VK_NV_ray_tracing");
+        unsafe {
+            ray_tracing_fn.cmd_trace_rays_nv(
+                self.command_buffer.handle(),
+                buffer, ray_gen.offset,
+                buffer, miss.offset, miss.stride,
+                buffer, hit.offset, hit.stride,
+                vk::Buffer::null(), 0, 0,
+                width, height, depth);
+        }
+
+        self.command_buffer.buffers_in_use.push(Rc::clone(table.buffer()));
+
+        self
+    }
+
+    // Draws with `vk::DrawIndexedIndirectCommand`s tightly packed in the buffer from `offset`,
+    // e.g. written by a compute shader. More than one draw needs `multi_draw_indirect`
+    // and first instances other than 0 need `draw_indirect_first_instance`.
+    // Like with `bind_vertex_buffer_at`, the buffer's owner has to keep it alive.
+    pub fn draw_indexed_indirect(self, buffer: &Buffer, offset: usize, draw_count: u32) -> Self {
+        debug_assert!(
+            draw_count <= 1 || self.command_buffer.logical_device.enabled_features()
+                .multi_draw_indirect,
+            "multiple indirect draws without multi_draw_indirect");
+
         unsafe {
             self.command_buffer.logical_device
-                .cmd_draw(self.command_buffer.handle(), vertex_count, 1, 0, 0);
+                .cmd_draw_indexed_indirect(
+                    self.command_buffer.handle(),
+                    buffer.handle(),
+                    offset as vk::DeviceSize,
+                    draw_count,
+                    std::mem::size_of::<vk::DrawIndexedIndirectCommand>() as u32);
         }
 
+        self.command_buffer.draw_call_count += draw_count;
         self
     }
 
+    // Writes the constants into the pipeline's push constant range of the given stage.
+    // Nothing is pushed for stages without a range.
     pub fn push_constant(
         self,
         pipeline: &Pipeline,
         shader_stage: ShaderStage,
         constants: &dyn PushConstants
     ) -> Self {
+        let range = match pipeline.push_constant_range(shader_stage) {
+            Some(range) => range,
+            None => return self
+        };
+
         unsafe {
             self.command_buffer.logical_device
                 .cmd_push_constants(
                     self.command_buffer.handle(),
                     pipeline.layout(),
-                    shader_stage.into(),
-                    0,
+                    range.stage_flags(),
+                    range.offset as u32,
                     constants.data());
         }
 
         self
     }
 
+    // Has to be recorded outside of render passes
+    pub fn reset_query_pool(self, query_pool: &QueryPool, queries: Range<u32>) -> Self {
+        unsafe {
+            self.command_buffer.logical_device
+                .cmd_reset_query_pool(
+                    self.command_buffer.handle(),
+                    query_pool.handle(),
+                    queries.start,
+                    queries.len() as u32);
+        }
+
+        self
+    }
+
+    // Precise queries count the exact number of passing samples, which may be slower,
+    // otherwise they only tell whether any sample passed
+    pub fn begin_occlusion_query(self, query_pool: &QueryPool, query: u32, precise: bool) -> Self {
+        let flags = if precise {
+            vk::QueryControlFlags::PRECISE
+        }
+        else {
+            vk::QueryControlFlags::empty()
+        };
+
+        unsafe {
+            self.command_buffer.logical_device
+                .cmd_begin_query(self.command_buffer.handle(), query_pool.handle(), query, flags);
+        }
+
+        self
+    }
+
+    pub fn end_occlusion_query(self, query_pool: &QueryPool, query: u32) -> Self {
+        unsafe {
+            self.command_buffer.logical_device
+                .cmd_end_query(self.command_buffer.handle(), query_pool.handle(), query);
+        }
+
+        self
+    }
+
+    // Writes the GPU's clock once all previously recorded commands reach the given stage,
+    // e.g. TOP_OF_PIPE for the beginning of a section and BOTTOM_OF_PIPE for its end
+    pub fn write_timestamp(
+        self,
+        stage: vk::PipelineStageFlags,
+        query_pool: &QueryPool,
+        query: u32
+    ) -> Self {
+        unsafe {
+            self.command_buffer.logical_device
+                .cmd_write_timestamp(
+                    self.command_buffer.handle(),
+                    stage,
+                    query_pool.handle(),
+                    query);
+        }
+
+        self
+    }
+
+    // Writes the results as tightly packed 32-bit values, waiting for the queries to finish,
+    // e.g. for `begin_conditional_rendering`. Has to be recorded outside of render passes.
+    pub fn copy_query_results(
+        self,
+        query_pool: &QueryPool,
+        queries: Range<u32>,
+        buffer: &Buffer,
+        buffer_offset: usize
+    ) -> Self {
+        unsafe {
+            self.command_buffer.logical_device
+                .cmd_copy_query_pool_results(
+                    self.command_buffer.handle(),
+                    query_pool.handle(),
+                    queries.start,
+                    queries.len() as u32,
+                    buffer.handle(),
+                    buffer_offset as vk::DeviceSize,
+                    std::mem::size_of::<u32>() as vk::DeviceSize,
+                    vk::QueryResultFlags::WAIT);
+        }
+
+        self
+    }
+
+    // Draws until `end_conditional_rendering` are discarded when the 32-bit value at the
+    // offset is zero, or nonzero if inverted. The buffer needs the CONDITIONAL_RENDERING_EXT
+    // usage. Without VK_EXT_conditional_rendering everything is drawn unconditionally.
+    pub fn begin_conditional_rendering(
+        self,
+        buffer: &Buffer,
+        buffer_offset: usize,
+        inverted: bool
+    ) -> Self {
+        let conditional_rendering_fn = match self.command_buffer.logical_device
+            .conditional_rendering_fn() {
+            Some(conditional_rendering_fn) => conditional_rendering_fn,
+            None => return self
+        };
+
+        let flags = if inverted {
+            vk::ConditionalRenderingFlagsEXT::INVERTED
+        }
+        else {
+            vk::ConditionalRenderingFlagsEXT::empty()
+        };
+
+        let begin_info = vk::ConditionalRenderingBeginInfoEXT::builder()
+            .buffer(buffer.handle())
+            .offset(buffer_offset as vk::DeviceSize)
+            .flags(flags);
+
+        unsafe {
+            conditional_rendering_fn
+                .cmd_begin_conditional_rendering_ext(self.command_buffer.handle(), &*begin_info);
+        }
+
+        self
+    }
+
+    pub fn end_conditional_rendering(self) -> Self {
+        if let Some(conditional_rendering_fn) =
+            self.command_buffer.logical_device.conditional_rendering_fn() {
+            unsafe {
+                conditional_rendering_fn
+                    .cmd_end_conditional_rendering_ext(self.command_buffer.handle());
+            }
+        }
+
+        self
+    }
+
+    // Nothing is recorded for an empty barrier
+    pub fn pipeline_barrier(self, barrier: &PipelineBarrier) -> Self {
+        if barrier.is_empty() {
+            return self;
+        }
+
+        let (src_stages, dst_stages) = barrier.stages();
+
+        unsafe {
+            self.command_buffer.logical_device
+                .cmd_pipeline_barrier(
+                    self.command_buffer.handle(),
+                    src_stages,
+                    dst_stages,
+                    vk::DependencyFlags::empty(),
+                    &barrier.vk_memory_barriers(),
+                    &barrier.vk_buffer_barriers(),
+                    &barrier.vk_image_barriers());
+        }
+
+        self
+    }
+
+    // Copies tightly packed texels at `buffer_offset` into mip level 0 of the given layer
+    pub fn copy_buffer_to_image(
+        self,
+        buffer: &Buffer,
+        buffer_offset: usize,
+        image: &Image,
+        array_layer: u32
+    ) -> Self {
+        let subresource = vk::ImageSubresourceLayers::builder()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .mip_level(0)
+            .base_array_layer(array_layer)
+            .layer_count(1)
+            .build();
+
+        let region = vk::BufferImageCopy::builder()
+            .buffer_offset(buffer_offset as vk::DeviceSize)
+            .buffer_row_length(0)
+            .buffer_image_height(0)
+            .image_subresource(subresource)
+            .image_offset(vk::Offset3D::default())
+            .image_extent(vk::Extent3D {
+                width: image.extent().width,
+                height: image.extent().height,
+                depth: 1
+            })
+            .build();
+
+        unsafe {
+            self.command_buffer.logical_device
+                .cmd_copy_buffer_to_image(
+                    self.command_buffer.handle(),
+                    buffer.handle(),
+                    image.handle(),
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    &[region]);
+        }
+
+        self
+    }
+
+    // Downsamples mip level `mip_level - 1` into `mip_level` for the given layers,
+    // the source level has to be in the transfer source layout
+    // and the destination one in the transfer destination layout
+    pub fn blit_mip_level(
+        self,
+        image: &Image,
+        mip_level: u32,
+        base_array_layer: u32,
+        layer_count: u32
+    ) -> Self {
+        let subresource = |mip_level| {
+            vk::ImageSubresourceLayers::builder()
+                .aspect_mask(vk::ImageAspectFlags::COLOR)
+                .mip_level(mip_level)
+                .base_array_layer(base_array_layer)
+                .layer_count(layer_count)
+                .build()
+        };
+
+        let corner = |extent: vk::Extent2D| {
+            vk::Offset3D {
+                x: extent.width as i32,
+                y: extent.height as i32,
+                z: 1
+            }
+        };
+
+        let blit = vk::ImageBlit::builder()
+            .src_subresource(subresource(mip_level - 1))
+            .src_offsets([vk::Offset3D::default(), corner(image.mip_extent(mip_level - 1))])
+            .dst_subresource(subresource(mip_level))
+            .dst_offsets([vk::Offset3D::default(), corner(image.mip_extent(mip_level))])
+            .build();
+
+        unsafe {
+            self.command_buffer.logical_device
+                .cmd_blit_image(
+                    self.command_buffer.handle(),
+                    image.handle(),
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    image.handle(),
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    &[blit],
+                    vk::Filter::LINEAR);
+        }
+
+        self
+    }
+
+    // Stretches the first mip level and layer of an image over the whole destination image
+    // of the given extent, e.g. over a swapchain image. The source has to be
+    // in the transfer source layout and the destination in the transfer destination layout.
+    pub fn blit_image_to_handle(
+        self,
+        src_image: &Image,
+        dst_image: vk::Image,
+        dst_extent: vk::Extent2D,
+        filter: vk::Filter
+    ) -> Self {
+        let subresource = vk::ImageSubresourceLayers::builder()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .mip_level(0)
+            .base_array_layer(0)
+            .layer_count(1)
+            .build();
+
+        let corner = |extent: vk::Extent2D| {
+            vk::Offset3D {
+                x: extent.width as i32,
+                y: extent.height as i32,
+                z: 1
+            }
+        };
+
+        let blit = vk::ImageBlit::builder()
+            .src_subresource(subresource)
+            .src_offsets([vk::Offset3D::default(), corner(src_image.extent())])
+            .dst_subresource(subresource)
+            .dst_offsets([vk::Offset3D::default(), corner(dst_extent)])
+            .build();
+
+        unsafe {
+            self.command_buffer.logical_device
+                .cmd_blit_image(
+                    self.command_buffer.handle(),
+                    src_image.handle(),
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    dst_image,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    &[blit],
+                    filter);
+        }
+
+        self
+    }
+
+    pub fn copy_image_to_buffer(
+        self,
+        image: vk::Image,
+        image_layout: vk::ImageLayout,
+        buffer: &Buffer,
+        extent: vk::Extent2D
+    ) -> Self {
+        let subresource = vk::ImageSubresourceLayers::builder()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .mip_level(0)
+            .base_array_layer(0)
+            .layer_count(1)
+            .build();
+
+        let region = vk::BufferImageCopy::builder()
+            .buffer_offset(0)
+            .buffer_row_length(0)
+            .buffer_image_height(0)
+            .image_subresource(subresource)
+            .image_offset(vk::Offset3D::default())
+            .image_extent(vk::Extent3D {
+                width: extent.width,
+                height: extent.height,
+                depth: 1
+            })
+            .build();
+
+        unsafe {
+            self.command_buffer.logical_device
+                .cmd_copy_image_to_buffer(
+                    self.command_buffer.handle(),
+                    image,
+                    image_layout,
+                    buffer.handle(),
+                    &[region]);
+        }
+
+        self
+    }
+
+    // Copies the first mip level and layer of an image of the same size and a compatible
+    // format, e.g. of a swapchain image, into the destination's first mip level and layer
+    pub fn copy_image(
+        self,
+        src_image: vk::Image,
+        src_layout: vk::ImageLayout,
+        dst_image: &Image,
+        dst_layout: vk::ImageLayout
+    ) -> Self {
+        let extent = dst_image.extent();
+        self.copy_image_handles(
+            (src_image, src_layout),
+            (dst_image.handle(), dst_layout),
+            extent)
+    }
+
+    // Counterpart of `copy_image` into an image not owned by an `Image`,
+    // e.g. into a swapchain image
+    pub fn copy_image_to_handle(
+        self,
+        src_image: &Image,
+        src_layout: vk::ImageLayout,
+        dst_image: vk::Image,
+        dst_layout: vk::ImageLayout
+    ) -> Self {
+        let extent = src_image.extent();
+        self.copy_image_handles(
+            (src_image.handle(), src_layout),
+            (dst_image, dst_layout),
+            extent)
+    }
+
+    fn copy_image_handles(
+        self,
+        (src_image, src_layout): (vk::Image, vk::ImageLayout),
+        (dst_image, dst_layout): (vk::Image, vk::ImageLayout),
+        extent: vk::Extent2D
+    ) -> Self {
+        let subresource = vk::ImageSubresourceLayers::builder()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .mip_level(0)
+            .base_array_layer(0)
+            .layer_count(1)
+            .build();
+
+        let region = vk::ImageCopy::builder()
+            .src_subresource(subresource)
+            .src_offset(vk::Offset3D::default())
+            .dst_subresource(subresource)
+            .dst_offset(vk::Offset3D::default())
+            .extent(vk::Extent3D {
+                width: extent.width,
+                height: extent.height,
+                depth: 1
+            })
+            .build();
+
+        unsafe {
+            self.command_buffer.logical_device
+                .cmd_copy_image(
+                    self.command_buffer.handle(),
+                    src_image,
+                    src_layout,
+                    dst_image,
+                    dst_layout,
+                    &[region]);
+        }
+
+        self
+    }
+
     pub fn end_recording(mut self) -> VulkanResult<()> {
         unsafe {
             self.command_buffer.logical_device
@@ -193,7 +1155,16 @@ impl Drop for CommandBufferRecorder<'_> {
     }
 }
 
-pub trait PushConstants {
+/// Data which can be uploaded with `vkCmdPushConstants` as the raw bytes of the implementing type.
+///
+/// # Safety
+///
+/// Implementing types have to be `#[repr(C)]` (or primitive) so that their layout matches the
+/// shader, contain no padding between or after their fields, since padding bytes are
+/// uninitialized and must not be read, and consist only of plain-old-data fields which are
+/// themselves `PushConstants`: no references, pointers or other types whose bytes are not
+/// meaningful to the device. `#[derive(PushConstants)]` checks all of it at compile time.
+pub unsafe trait PushConstants {
     fn data(&self) -> &[u8] {
         unsafe {
             std::slice::from_raw_parts(self as *const Self as *const u8, self.size())
@@ -205,4 +1176,135 @@ pub trait PushConstants {
     }
 }
 
-impl PushConstants for () {}
+unsafe impl PushConstants for () {}
+
+macro_rules! impl_push_constants {
+    ($($primitive:ty),*) => {
+        $(unsafe impl PushConstants for $primitive {})*
+    };
+}
+
+impl_push_constants!(u8, i8, u16, i16, u32, i32, u64, i64, f32, f64);
+
+// Array elements are laid out back to back with a stride equal to their size,
+// so an array of padding-free elements is padding-free as well
+unsafe impl<T: PushConstants, const N: usize> PushConstants for [T; N] {}
+
+// Layout errors of `#[derive(PushConstants)]` are compile time assertions, so they can only be
+// observed by snippets which fail to build
+#[cfg(doctest)]
+mod push_constants_layout_errors {
+    /// ```compile_fail,E0080
+    /// #[repr(C)]
+    /// #[derive(magmacraft::prelude::PushConstants)]
+    /// struct Padded {
+    ///     flag: u8,
+    ///     value: u32
+    /// }
+    /// ```
+    struct PaddingBetweenFields;
+
+    /// ```compile_fail,E0080
+    /// #[repr(C)]
+    /// #[derive(magmacraft::prelude::PushConstants)]
+    /// struct Padded {
+    ///     value: u32,
+    ///     flag: u8
+    /// }
+    /// ```
+    struct PaddingAfterFields;
+
+    /// ```compile_fail,E0080
+    /// #[repr(C)]
+    /// #[derive(magmacraft::prelude::PushConstants)]
+    /// struct Oversized {
+    ///     matrices: [[f32; 16]; 3]
+    /// }
+    /// ```
+    struct LargerThanGuaranteedSize;
+
+    /// ```compile_fail,E0080
+    /// #[repr(C)]
+    /// #[derive(magmacraft::prelude::PushConstants)]
+    /// #[push_constants(max_size = 256)]
+    /// struct Oversized {
+    ///     matrices: [[f32; 16]; 5]
+    /// }
+    /// ```
+    struct LargerThanMaxSize;
+
+    /// ```
+    /// #[repr(C)]
+    /// #[derive(magmacraft::prelude::PushConstants)]
+    /// #[push_constants(max_size = 256)]
+    /// struct Matrices {
+    ///     matrices: [[f32; 16]; 3]
+    /// }
+    /// ```
+    struct WithinMaxSize;
+
+    /// ```compile_fail,E0277
+    /// #[repr(C)]
+    /// struct Padded {
+    ///     flag: u8,
+    ///     value: u32
+    /// }
+    ///
+    /// #[repr(C)]
+    /// #[derive(magmacraft::prelude::PushConstants)]
+    /// struct Nested {
+    ///     padded: Padded,
+    ///     other: [u8; 8]
+    /// }
+    /// ```
+    struct FieldNotPushConstants;
+
+    /// ```
+    /// #[repr(C)]
+    /// #[derive(magmacraft::prelude::PushConstants)]
+    /// struct Inner {
+    ///     color: [f32; 4]
+    /// }
+    ///
+    /// #[repr(C)]
+    /// #[derive(magmacraft::prelude::PushConstants)]
+    /// struct Outer {
+    ///     inner: Inner,
+    ///     layer: u32,
+    ///     scale: f32,
+    ///     padding: [u32; 2]
+    /// }
+    /// ```
+    struct NestedPushConstants;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[repr(C)]
+    #[derive(PushConstants)]
+    struct Constants {
+        color: [f32; 4],
+        scale: f32,
+        layer: u32
+    }
+
+    #[test]
+    fn push_constants_data_covers_whole_struct() {
+        let constants = Constants {
+            color: [1.0, 0.5, 0.25, 1.0],
+            scale: 2.0,
+            layer: 7
+        };
+
+        assert_eq!(constants.size(), 24);
+        assert_eq!(constants.data().len(), 24);
+        assert_eq!(&constants.data()[20..], &7u32.to_ne_bytes());
+    }
+
+    #[test]
+    fn unit_push_constants_are_empty() {
+        assert!(().data().is_empty());
+    }
+}
@@ -1,4 +1,7 @@
-use std::rc::Rc;
+use std::{
+    marker::PhantomData,
+    rc::Rc
+};
 use ash::{
     version::DeviceV1_0,
     vk
@@ -12,36 +15,56 @@ use crate::{
     vulkan::{
         VulkanResult,
         VulkanError,
-        physical_device::{
-            PhysicalDevice,
-            QueueFamily
-        },
+        handle::VulkanHandle,
+        physical_device::PhysicalDevice,
         logical_device::LogicalDevice,
-        command_buffer::CommandBuffer
+        command_buffer::{
+            CommandBuffer,
+            QueueType,
+            GraphicsQueue,
+            QueueCommandBuffer
+        }
     }
 };
 
-pub struct CommandPool {
+// Pool of command buffers for queues of the family of `Q`.
+// Its command buffers keep it alive and are freed when they are dropped.
+pub struct CommandPool<Q: QueueType = GraphicsQueue> {
     vk_command_pool: vk::CommandPool,
     logical_device: Rc<LogicalDevice>,
-    submit_buffers_once: bool
+    submit_buffers_once: bool,
+    _queue: PhantomData<Q>
 }
 
-impl CommandPool {
-    pub fn builder() -> CommandPoolBuilder {
+impl<Q: QueueType> CommandPool<Q> {
+    pub fn builder() -> CommandPoolBuilder<Q> {
         CommandPoolBuilder {
             ..Default::default()
         }
     }
 
-    pub fn handle(&self) -> vk::CommandPool {
-        self.vk_command_pool
+    pub fn allocate_command_buffers(
+        self: &Rc<Self>,
+        count: usize
+    ) -> VulkanResult<Vec<QueueCommandBuffer<Q>>> {
+        self.allocate_command_buffers_of_level(count, vk::CommandBufferLevel::PRIMARY)
+    }
+
+    pub fn allocate_secondary_command_buffers(
+        self: &Rc<Self>,
+        count: usize
+    ) -> VulkanResult<Vec<QueueCommandBuffer<Q>>> {
+        self.allocate_command_buffers_of_level(count, vk::CommandBufferLevel::SECONDARY)
     }
 
-    pub fn allocate_command_buffers(&self, count: usize) -> VulkanResult<Vec<CommandBuffer>> {
+    fn allocate_command_buffers_of_level(
+        self: &Rc<Self>,
+        count: usize,
+        level: vk::CommandBufferLevel
+    ) -> VulkanResult<Vec<QueueCommandBuffer<Q>>> {
         let allocate_info = vk::CommandBufferAllocateInfo::builder()
             .command_pool(self.handle())
-            .level(vk::CommandBufferLevel::PRIMARY)
+            .level(level)
             .command_buffer_count(count as u32);
 
         let command_buffers = unsafe {
@@ -49,13 +72,26 @@ impl CommandPool {
         }.map_err(|result| VulkanError::CommandBufferAllocateError {result})?;
 
         Ok(command_buffers.into_iter().map(|vk_command_buffer| {
-            CommandBuffer::from_handle(
-                vk_command_buffer, Rc::clone(&self.logical_device), self.submit_buffers_once)
+            let command_buffer = CommandBuffer::from_handle(
+                vk_command_buffer,
+                Rc::clone(&self.logical_device),
+                level,
+                self.submit_buffers_once);
+
+            QueueCommandBuffer::new(command_buffer, Rc::clone(self))
         }).collect())
     }
 }
 
-impl Drop for CommandPool {
+impl<Q: QueueType> VulkanHandle for CommandPool<Q> {
+    type Handle = vk::CommandPool;
+
+    fn handle(&self) -> Self::Handle {
+        self.vk_command_pool
+    }
+}
+
+impl<Q: QueueType> Drop for CommandPool<Q> {
     fn drop(&mut self) {
         unsafe {
             self.logical_device.destroy_command_pool(self.vk_command_pool, None);
@@ -64,19 +100,18 @@ impl Drop for CommandPool {
 }
 
 #[derive(Default)]
-pub struct CommandPoolBuilder {
+pub struct CommandPoolBuilder<Q: QueueType> {
     physical_device: BuilderRequirement<Rc<PhysicalDevice>>,
     logical_device: BuilderRequirement<Rc<LogicalDevice>>,
     submit_buffers_once: Option<bool>,
-    queue_family: BuilderRequirement<QueueFamily>,
 
     create_flags: BuilderInternal<vk::CommandPoolCreateFlags>,
     vk_command_pool: BuilderInternal<vk::CommandPool>,
 
-    command_pool: BuilderProduct<CommandPool>
+    command_pool: BuilderProduct<CommandPool<Q>>
 }
 
-impl CommandPoolBuilder {
+impl<Q: QueueType> CommandPoolBuilder<Q> {
     pub fn physical_device(mut self, physical_device: Rc<PhysicalDevice>) -> Self {
         self.physical_device.set(physical_device);
         self
@@ -87,17 +122,16 @@ impl CommandPoolBuilder {
         self
     }
 
-    pub fn queue_family(mut self, queue_family: QueueFamily) -> Self {
-        self.queue_family.set(queue_family);
-        self
-    }
-
     pub fn submit_buffers_once(mut self, submit_buffers_once: bool) -> Self {
         self.submit_buffers_once = Some(submit_buffers_once);
         self
     }
 
-    pub fn build(mut self) -> VulkanResult<CommandPool> {
+    pub fn build(mut self) -> VulkanResult<CommandPool<Q>> {
+        check_required_fields!(
+            self, VulkanError, "CommandPoolBuilder",
+            [physical_device, logical_device]);
+
         self.init_create_flags();
         self.init_vk_command_pool()?;
         self.create_command_pool();
@@ -115,8 +149,7 @@ impl CommandPoolBuilder {
     }
 
     fn init_vk_command_pool(&mut self) -> VulkanResult<()> {
-        let queue_family_index =
-            self.physical_device.queue_family_index(*self.queue_family)?;
+        let queue_family_index = self.physical_device.queue_family_index(Q::FAMILY)?;
 
         let command_pool_create_info = vk::CommandPoolCreateInfo::builder()
             .queue_family_index(queue_family_index)
@@ -134,7 +167,8 @@ impl CommandPoolBuilder {
         let command_pool = CommandPool {
             vk_command_pool: self.vk_command_pool.take(),
             logical_device: self.logical_device.take(),
-            submit_buffers_once: self.submit_buffers_once.unwrap_or(false)
+            submit_buffers_once: self.submit_buffers_once.unwrap_or(false),
+            _queue: PhantomData
         };
 
         self.command_pool.set(command_pool);
@@ -65,12 +65,11 @@ unsafe extern "system" fn debug_callback(
     message_type: vk::DebugUtilsMessageTypeFlagsEXT,
     callback_data: *const vk::DebugUtilsMessengerCallbackDataEXT,
     _user_data: *mut std::ffi::c_void) -> vk::Bool32 {
-    let message_severity = match message_severity {
-        vk::DebugUtilsMessageSeverityFlagsEXT::INFO => "info",
-        vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE => "verbose",
-        vk::DebugUtilsMessageSeverityFlagsEXT::WARNING => "warning",
-        vk::DebugUtilsMessageSeverityFlagsEXT::ERROR => "error",
-        _ => "Unknown severity"
+    let level = match message_severity {
+        vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE => log::Level::Trace,
+        vk::DebugUtilsMessageSeverityFlagsEXT::INFO => log::Level::Info,
+        vk::DebugUtilsMessageSeverityFlagsEXT::WARNING => log::Level::Warn,
+        _ => log::Level::Error
     };
 
     let message_type = match message_type {
@@ -83,7 +82,7 @@ unsafe extern "system" fn debug_callback(
     let message = std::ffi::CStr::from_ptr((*callback_data).p_message)
         .to_str().unwrap();
 
-    eprintln!("VL {} ({}): {}", message_severity, message_type, message);
+    log!(target: "vulkan_validation", level, "({}) {}", message_type, message);
 
     vk::FALSE
 }
@@ -0,0 +1,722 @@
+use std::{
+    cell::RefCell,
+    rc::Rc
+};
+use ash::{
+    version::DeviceV1_0,
+    vk
+};
+use crate::{
+    builder::{
+        BuilderRequirement,
+        BuilderInternal,
+        BuilderProduct
+    },
+    vulkan::{
+        VulkanError,
+        VulkanResult,
+        handle::VulkanHandle,
+        logical_device::LogicalDevice,
+        shader::ShaderStage,
+        buffer::Buffer,
+        image::Image,
+        sampler::Sampler,
+        acceleration_structure::AccelerationStructure
+    }
+};
+
+pub struct DescriptorSetLayout {
+    vk_descriptor_set_layout: vk::DescriptorSetLayout,
+    bindings: Vec<vk::DescriptorSetLayoutBinding>,
+    is_push_descriptor: bool,
+    logical_device: Rc<LogicalDevice>
+}
+
+impl DescriptorSetLayout {
+    pub fn builder() -> DescriptorSetLayoutBuilder {
+        DescriptorSetLayoutBuilder {
+            ..Default::default()
+        }
+    }
+
+    pub fn is_push_descriptor(&self) -> bool {
+        self.is_push_descriptor
+    }
+
+    fn descriptor_type(&self, binding: u32) -> Option<vk::DescriptorType> {
+        self.bindings
+            .iter()
+            .find(|layout_binding| layout_binding.binding == binding)
+            .map(|layout_binding| layout_binding.descriptor_type)
+    }
+}
+
+impl VulkanHandle for DescriptorSetLayout {
+    type Handle = vk::DescriptorSetLayout;
+
+    fn handle(&self) -> Self::Handle {
+        self.vk_descriptor_set_layout
+    }
+}
+
+impl Drop for DescriptorSetLayout {
+    fn drop(&mut self) {
+        unsafe {
+            self.logical_device
+                .destroy_descriptor_set_layout(self.vk_descriptor_set_layout, None);
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct DescriptorSetLayoutBuilder {
+    logical_device: BuilderRequirement<Rc<LogicalDevice>>,
+    bindings: Vec<vk::DescriptorSetLayoutBinding>,
+    push_descriptor: bool,
+
+    vk_descriptor_set_layout: BuilderInternal<vk::DescriptorSetLayout>,
+
+    descriptor_set_layout: BuilderProduct<DescriptorSetLayout>
+}
+
+impl DescriptorSetLayoutBuilder {
+    pub fn logical_device(mut self, logical_device: Rc<LogicalDevice>) -> Self {
+        self.logical_device.set(logical_device);
+        self
+    }
+
+    pub fn uniform_buffer(self, binding: u32, stages: &[ShaderStage]) -> Self {
+        self.binding(binding, vk::DescriptorType::UNIFORM_BUFFER, stages)
+    }
+
+    // Bound at an offset given when binding the set, e.g. to pick one object's data
+    // out of a buffer shared by many, see `CommandBufferRecorder::bind_descriptor_set_at`
+    pub fn dynamic_uniform_buffer(self, binding: u32, stages: &[ShaderStage]) -> Self {
+        self.binding(binding, vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC, stages)
+    }
+
+    // Buffers shaders can both read and write, e.g. particles updated by a compute shader.
+    // Writing from vertex, geometry or fragment shaders needs
+    // `vertex_pipeline_stores_and_atomics` or `fragment_stores_and_atomics` to be requested.
+    pub fn storage_buffer(self, binding: u32, stages: &[ShaderStage]) -> Self {
+        self.binding(binding, vk::DescriptorType::STORAGE_BUFFER, stages)
+    }
+
+    pub fn combined_image_sampler(self, binding: u32, stages: &[ShaderStage]) -> Self {
+        self.binding(binding, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, stages)
+    }
+
+    // Images shaders write without a sampler, e.g. the output of ray generation shaders
+    pub fn storage_image(self, binding: u32, stages: &[ShaderStage]) -> Self {
+        self.binding(binding, vk::DescriptorType::STORAGE_IMAGE, stages)
+    }
+
+    // Top level acceleration structures rays are traced against, needs VK_NV_ray_tracing
+    pub fn acceleration_structure(self, binding: u32, stages: &[ShaderStage]) -> Self {
+        self.binding(binding, vk::DescriptorType::ACCELERATION_STRUCTURE_NV, stages)
+    }
+
+    // Sets of the layout are pushed with `PushDescriptors` instead of being allocated,
+    // which needs VK_KHR_push_descriptor (`LogicalDevice::push_descriptor_fn`)
+    pub fn push_descriptor(mut self, push_descriptor: bool) -> Self {
+        self.push_descriptor = push_descriptor;
+        self
+    }
+
+    // Attachments written by an earlier subpass of the same render pass,
+    // only fragment shaders can read them
+    pub fn input_attachment(self, binding: u32) -> Self {
+        self.binding(binding, vk::DescriptorType::INPUT_ATTACHMENT, &[ShaderStage::Fragment])
+    }
+
+    fn binding(
+        mut self,
+        binding: u32,
+        descriptor_type: vk::DescriptorType,
+        stages: &[ShaderStage]
+    ) -> Self {
+        let stage_flags = stages
+            .iter()
+            .fold(vk::ShaderStageFlags::empty(), |flags, stage| flags | (*stage).into());
+
+        let layout_binding = vk::DescriptorSetLayoutBinding::builder()
+            .binding(binding)
+            .descriptor_type(descriptor_type)
+            .descriptor_count(1)
+            .stage_flags(stage_flags)
+            .build();
+
+        self.bindings.push(layout_binding);
+        self
+    }
+
+    pub fn build(mut self) -> VulkanResult<DescriptorSetLayout> {
+        check_required_fields!(self, VulkanError, "DescriptorSetLayoutBuilder", [logical_device]);
+
+        if self.push_descriptor && self.logical_device.push_descriptor_fn().is_none() {
+            return Err(VulkanError::PushDescriptorsNotEnabled);
+        }
+
+        self.init_vk_descriptor_set_layout()?;
+        self.create_descriptor_set_layout();
+
+        Ok(self.descriptor_set_layout.unwrap())
+    }
+
+    fn init_vk_descriptor_set_layout(&mut self) -> VulkanResult<()> {
+        let flags = if self.push_descriptor {
+            vk::DescriptorSetLayoutCreateFlags::PUSH_DESCRIPTOR_KHR
+        }
+        else {
+            vk::DescriptorSetLayoutCreateFlags::empty()
+        };
+
+        let create_info = vk::DescriptorSetLayoutCreateInfo::builder()
+            .flags(flags)
+            .bindings(&self.bindings);
+
+        let vk_descriptor_set_layout = unsafe {
+            self.logical_device.create_descriptor_set_layout(&create_info, None)
+        }.map_err(|result| VulkanError::DescriptorSetLayoutCreateError {result})?;
+
+        self.vk_descriptor_set_layout.set(vk_descriptor_set_layout);
+
+        Ok(())
+    }
+
+    fn create_descriptor_set_layout(&mut self) {
+        self.descriptor_set_layout.set(DescriptorSetLayout {
+            vk_descriptor_set_layout: self.vk_descriptor_set_layout.take(),
+            bindings: std::mem::take(&mut self.bindings),
+            is_push_descriptor: self.push_descriptor,
+            logical_device: self.logical_device.take()
+        });
+    }
+}
+
+// Every set gets a pool of its own sized exactly for its layout.
+// Sets are expected to be long-lived (e.g. one per material), so the pools stay few.
+// Short-lived sets come from a `TransientDescriptorPool` instead.
+pub struct DescriptorSet {
+    vk_descriptor_set: vk::DescriptorSet,
+    // None for sets of transient pools
+    vk_descriptor_pool: Option<vk::DescriptorPool>,
+    layout: Rc<DescriptorSetLayout>,
+    // lifetime extenders
+    buffers_in_use: Vec<Rc<RefCell<Buffer>>>,
+    images_in_use: Vec<(Rc<Image>, Rc<Sampler>)>,
+    input_attachments_in_use: Vec<Rc<Image>>,
+    storage_images_in_use: Vec<Rc<Image>>,
+    acceleration_structures_in_use: Vec<Rc<AccelerationStructure>>
+}
+
+impl DescriptorSet {
+    pub fn new(layout: Rc<DescriptorSetLayout>) -> VulkanResult<Self> {
+        if layout.is_push_descriptor {
+            return Err(VulkanError::DescriptorSetFromPushLayout);
+        }
+
+        let pool_sizes: Vec<vk::DescriptorPoolSize> = layout.bindings
+            .iter()
+            .map(|layout_binding| {
+                vk::DescriptorPoolSize::builder()
+                    .ty(layout_binding.descriptor_type)
+                    .descriptor_count(layout_binding.descriptor_count)
+                    .build()
+            })
+            .collect();
+
+        let pool_create_info = vk::DescriptorPoolCreateInfo::builder()
+            .max_sets(1)
+            .pool_sizes(&pool_sizes);
+
+        let vk_descriptor_pool = unsafe {
+            layout.logical_device.create_descriptor_pool(&pool_create_info, None)
+        }.map_err(|result| VulkanError::DescriptorPoolCreateError {result})?;
+
+        let set_layouts = [layout.handle()];
+        let allocate_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(vk_descriptor_pool)
+            .set_layouts(&set_layouts);
+
+        let vk_descriptor_sets = unsafe {
+            layout.logical_device.allocate_descriptor_sets(&allocate_info)
+        }.map_err(|result| {
+            unsafe {
+                layout.logical_device.destroy_descriptor_pool(vk_descriptor_pool, None);
+            }
+
+            VulkanError::DescriptorSetAllocateError {result}
+        })?;
+
+        Ok(Self::from_allocated(vk_descriptor_sets[0], Some(vk_descriptor_pool), layout))
+    }
+
+    fn from_allocated(
+        vk_descriptor_set: vk::DescriptorSet,
+        vk_descriptor_pool: Option<vk::DescriptorPool>,
+        layout: Rc<DescriptorSetLayout>
+    ) -> Self {
+        Self {
+            vk_descriptor_set,
+            vk_descriptor_pool,
+            layout,
+            buffers_in_use: Vec::new(),
+            images_in_use: Vec::new(),
+            input_attachments_in_use: Vec::new(),
+            storage_images_in_use: Vec::new(),
+            acceleration_structures_in_use: Vec::new()
+        }
+    }
+
+    pub fn layout(&self) -> &Rc<DescriptorSetLayout> {
+        &self.layout
+    }
+
+    // The buffer is kept alive for as long as the set is
+    pub fn write_uniform_buffer(
+        &mut self,
+        binding: u32,
+        buffer: Rc<RefCell<Buffer>>
+    ) -> VulkanResult<()> {
+        self.write_buffer(binding, vk::DescriptorType::UNIFORM_BUFFER, buffer, vk::WHOLE_SIZE)
+    }
+
+    // Shaders see `range` bytes of the buffer starting at the dynamic offset
+    // the set is bound with
+    pub fn write_dynamic_uniform_buffer(
+        &mut self,
+        binding: u32,
+        buffer: Rc<RefCell<Buffer>>,
+        range: vk::DeviceSize
+    ) -> VulkanResult<()> {
+        self.write_buffer(binding, vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC, buffer, range)
+    }
+
+    // The buffer needs the STORAGE_BUFFER usage. Accesses of shaders using the set
+    // are not ordered with anything else, writes have to be made visible with barriers,
+    // e.g. from `MemoryAccess::compute_shader_write()`.
+    pub fn write_storage_buffer(
+        &mut self,
+        binding: u32,
+        buffer: Rc<RefCell<Buffer>>
+    ) -> VulkanResult<()> {
+        self.write_buffer(binding, vk::DescriptorType::STORAGE_BUFFER, buffer, vk::WHOLE_SIZE)
+    }
+
+    fn write_buffer(
+        &mut self,
+        binding: u32,
+        descriptor_type: vk::DescriptorType,
+        buffer: Rc<RefCell<Buffer>>,
+        range: vk::DeviceSize
+    ) -> VulkanResult<()> {
+        if self.layout.descriptor_type(binding) != Some(descriptor_type) {
+            return Err(VulkanError::DescriptorBindingTypeMismatch {binding});
+        }
+
+        let buffer_info = vk::DescriptorBufferInfo::builder()
+            .buffer(buffer.borrow().handle())
+            .offset(0)
+            .range(range)
+            .build();
+
+        let write = vk::WriteDescriptorSet::builder()
+            .dst_set(self.vk_descriptor_set)
+            .dst_binding(binding)
+            .descriptor_type(descriptor_type)
+            .buffer_info(std::slice::from_ref(&buffer_info))
+            .build();
+
+        unsafe {
+            self.layout.logical_device.update_descriptor_sets(&[write], &[]);
+        }
+
+        self.buffers_in_use.push(buffer);
+
+        Ok(())
+    }
+
+    // The image has to be in the shader read only layout whenever the set is used.
+    // Both the image and the sampler are kept alive for as long as the set is.
+    pub fn write_combined_image_sampler(
+        &mut self,
+        binding: u32,
+        image: Rc<Image>,
+        sampler: Rc<Sampler>
+    ) -> VulkanResult<()> {
+        let descriptor_type = self.layout.descriptor_type(binding);
+        if descriptor_type != Some(vk::DescriptorType::COMBINED_IMAGE_SAMPLER) {
+            return Err(VulkanError::DescriptorBindingTypeMismatch {binding});
+        }
+
+        let image_info = vk::DescriptorImageInfo::builder()
+            .image_view(image.view())
+            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .sampler(sampler.handle())
+            .build();
+
+        let write = vk::WriteDescriptorSet::builder()
+            .dst_set(self.vk_descriptor_set)
+            .dst_binding(binding)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .image_info(std::slice::from_ref(&image_info))
+            .build();
+
+        unsafe {
+            self.layout.logical_device.update_descriptor_sets(&[write], &[]);
+        }
+
+        self.images_in_use.push((image, sampler));
+
+        Ok(())
+    }
+
+    // The layout is the one the subpass reading the attachment refers to it with
+    pub fn write_input_attachment(
+        &mut self,
+        binding: u32,
+        image: Rc<Image>,
+        layout: vk::ImageLayout
+    ) -> VulkanResult<()> {
+        if self.layout.descriptor_type(binding) != Some(vk::DescriptorType::INPUT_ATTACHMENT) {
+            return Err(VulkanError::DescriptorBindingTypeMismatch {binding});
+        }
+
+        let image_info = vk::DescriptorImageInfo::builder()
+            .image_view(image.view())
+            .image_layout(layout)
+            .build();
+
+        let write = vk::WriteDescriptorSet::builder()
+            .dst_set(self.vk_descriptor_set)
+            .dst_binding(binding)
+            .descriptor_type(vk::DescriptorType::INPUT_ATTACHMENT)
+            .image_info(std::slice::from_ref(&image_info))
+            .build();
+
+        unsafe {
+            self.layout.logical_device.update_descriptor_sets(&[write], &[]);
+        }
+
+        self.input_attachments_in_use.push(image);
+
+        Ok(())
+    }
+
+    // The image needs the STORAGE usage and has to be in the general layout
+    // whenever the set is used
+    pub fn write_storage_image(&mut self, binding: u32, image: Rc<Image>) -> VulkanResult<()> {
+        if self.layout.descriptor_type(binding) != Some(vk::DescriptorType::STORAGE_IMAGE) {
+            return Err(VulkanError::DescriptorBindingTypeMismatch {binding});
+        }
+
+        let image_info = vk::DescriptorImageInfo::builder()
+            .image_view(image.view())
+            .image_layout(vk::ImageLayout::GENERAL)
+            .build();
+
+        let write = vk::WriteDescriptorSet::builder()
+            .dst_set(self.vk_descriptor_set)
+            .dst_binding(binding)
+            .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+            .image_info(std::slice::from_ref(&image_info))
+            .build();
+
+        unsafe {
+            self.layout.logical_device.update_descriptor_sets(&[write], &[]);
+        }
+
+        self.storage_images_in_use.push(image);
+
+        Ok(())
+    }
+
+    // The structure has to be top level and built before the set is used
+    pub fn write_acceleration_structure(
+        &mut self,
+        binding: u32,
+        acceleration_structure: Rc<AccelerationStructure>
+    ) -> VulkanResult<()> {
+        let descriptor_type = self.layout.descriptor_type(binding);
+        if descriptor_type != Some(vk::DescriptorType::ACCELERATION_STRUCTURE_NV) {
+            return Err(VulkanError::DescriptorBindingTypeMismatch {binding});
+        }
+
+        let vk_acceleration_structures = [acceleration_structure.handle()];
+        let mut acceleration_structure_write =
+            vk::WriteDescriptorSetAccelerationStructureNV::builder()
+                .acceleration_structures(&vk_acceleration_structures);
+
+        // The count of acceleration structure writes is not set by any of the infos
+        let mut write = vk::WriteDescriptorSet::builder()
+            .dst_set(self.vk_descriptor_set)
+            .dst_binding(binding)
+            .descriptor_type(vk::DescriptorType::ACCELERATION_STRUCTURE_NV)
+            .push_next(&mut acceleration_structure_write)
+            .build();
+        write.descriptor_count = 1;
+
+        unsafe {
+            self.layout.logical_device.update_descriptor_sets(&[write], &[]);
+        }
+
+        self.acceleration_structures_in_use.push(acceleration_structure);
+
+        Ok(())
+    }
+}
+
+impl VulkanHandle for DescriptorSet {
+    type Handle = vk::DescriptorSet;
+
+    fn handle(&self) -> Self::Handle {
+        self.vk_descriptor_set
+    }
+}
+
+impl Drop for DescriptorSet {
+    fn drop(&mut self) {
+        if let Some(vk_descriptor_pool) = self.vk_descriptor_pool {
+            unsafe {
+                self.layout.logical_device.destroy_descriptor_pool(vk_descriptor_pool, None);
+            }
+        }
+    }
+}
+
+// Descriptor sets which are used during a single frame, e.g. of per-frame uniforms,
+// all freed at once by resetting the pool instead of each being created with a pool of its own.
+// Another Vulkan pool is created whenever the existing ones run out.
+pub struct TransientDescriptorPool {
+    vk_descriptor_pools: Vec<vk::DescriptorPool>,
+    // Index of the first pool which may still have room
+    current_pool: usize,
+    logical_device: Rc<LogicalDevice>
+}
+
+impl TransientDescriptorPool {
+    const SETS_PER_POOL: u32 = 64;
+    const DESCRIPTORS_PER_SET: u32 = 4;
+    const DESCRIPTOR_TYPES: [vk::DescriptorType; 6] = [
+        vk::DescriptorType::UNIFORM_BUFFER,
+        vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC,
+        vk::DescriptorType::STORAGE_BUFFER,
+        vk::DescriptorType::STORAGE_IMAGE,
+        vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+        vk::DescriptorType::INPUT_ATTACHMENT
+    ];
+
+    // Does not create any Vulkan pools until a set is allocated
+    pub fn new(logical_device: Rc<LogicalDevice>) -> Self {
+        Self {
+            vk_descriptor_pools: Vec::new(),
+            current_pool: 0,
+            logical_device
+        }
+    }
+
+    // The set must not be used anymore once the pool is reset
+    pub fn allocate(&mut self, layout: Rc<DescriptorSetLayout>) -> VulkanResult<DescriptorSet> {
+        if layout.is_push_descriptor {
+            return Err(VulkanError::DescriptorSetFromPushLayout);
+        }
+
+        loop {
+            let is_new_pool = self.current_pool == self.vk_descriptor_pools.len();
+            if is_new_pool {
+                let vk_descriptor_pool = self.create_vk_descriptor_pool()?;
+                self.vk_descriptor_pools.push(vk_descriptor_pool);
+            }
+
+            let set_layouts = [layout.handle()];
+            let allocate_info = vk::DescriptorSetAllocateInfo::builder()
+                .descriptor_pool(self.vk_descriptor_pools[self.current_pool])
+                .set_layouts(&set_layouts);
+
+            let result = unsafe {
+                self.logical_device.allocate_descriptor_sets(&allocate_info)
+            };
+
+            match result {
+                Ok(vk_descriptor_sets) => {
+                    return Ok(DescriptorSet::from_allocated(vk_descriptor_sets[0], None, layout));
+                },
+                // A set which does not fit into an empty pool would not fit into any other
+                Err(vk::Result::ERROR_OUT_OF_POOL_MEMORY) |
+                Err(vk::Result::ERROR_FRAGMENTED_POOL) if !is_new_pool => {
+                    self.current_pool += 1;
+                },
+                Err(result) => return Err(VulkanError::DescriptorSetAllocateError {result})
+            }
+        }
+    }
+
+    // Frees all sets allocated from the pool, none of which can be in use anymore
+    pub fn reset(&mut self) -> VulkanResult<()> {
+        for vk_descriptor_pool in &self.vk_descriptor_pools {
+            unsafe {
+                self.logical_device.reset_descriptor_pool(
+                    *vk_descriptor_pool,
+                    vk::DescriptorPoolResetFlags::empty())
+            }.map_err(|result| VulkanError::DescriptorPoolResetError {result})?;
+        }
+
+        self.current_pool = 0;
+        Ok(())
+    }
+
+    fn create_vk_descriptor_pool(&self) -> VulkanResult<vk::DescriptorPool> {
+        let pool_sizes: Vec<vk::DescriptorPoolSize> = Self::DESCRIPTOR_TYPES
+            .iter()
+            .map(|descriptor_type| {
+                vk::DescriptorPoolSize::builder()
+                    .ty(*descriptor_type)
+                    .descriptor_count(Self::SETS_PER_POOL * Self::DESCRIPTORS_PER_SET)
+                    .build()
+            })
+            .collect();
+
+        let pool_create_info = vk::DescriptorPoolCreateInfo::builder()
+            .max_sets(Self::SETS_PER_POOL)
+            .pool_sizes(&pool_sizes);
+
+        unsafe {
+            self.logical_device.create_descriptor_pool(&pool_create_info, None)
+        }.map_err(|result| VulkanError::DescriptorPoolCreateError {result})
+    }
+}
+
+impl Drop for TransientDescriptorPool {
+    fn drop(&mut self) {
+        for vk_descriptor_pool in &self.vk_descriptor_pools {
+            unsafe {
+                self.logical_device.destroy_descriptor_pool(*vk_descriptor_pool, None);
+            }
+        }
+    }
+}
+
+// Descriptors recorded straight into command buffers with
+// `CommandBufferRecorder::push_descriptors` instead of being written to an allocated set,
+// e.g. for a texture and a uniform buffer changing with every draw.
+// Written resources are kept alive by the command buffers they are pushed into.
+pub struct PushDescriptors {
+    layout: Rc<DescriptorSetLayout>,
+    buffer_writes: Vec<(u32, vk::DescriptorType, vk::DescriptorBufferInfo)>,
+    image_writes: Vec<(u32, vk::DescriptorType, vk::DescriptorImageInfo)>,
+    // lifetime extenders
+    buffers_in_use: Vec<Rc<RefCell<Buffer>>>,
+    images_in_use: Vec<(Rc<Image>, Rc<Sampler>)>
+}
+
+impl PushDescriptors {
+    pub fn new(layout: Rc<DescriptorSetLayout>) -> VulkanResult<Self> {
+        if !layout.is_push_descriptor {
+            return Err(VulkanError::PushDescriptorsWithoutPushLayout);
+        }
+
+        Ok(Self {
+            layout,
+            buffer_writes: Vec::new(),
+            image_writes: Vec::new(),
+            buffers_in_use: Vec::new(),
+            images_in_use: Vec::new()
+        })
+    }
+
+    pub fn layout(&self) -> &Rc<DescriptorSetLayout> {
+        &self.layout
+    }
+
+    // Writing a binding again replaces what was written to it before
+    pub fn write_uniform_buffer(
+        &mut self,
+        binding: u32,
+        buffer: Rc<RefCell<Buffer>>
+    ) -> VulkanResult<()> {
+        self.write_buffer(binding, vk::DescriptorType::UNIFORM_BUFFER, buffer)
+    }
+
+    // Needs barriers like `DescriptorSet::write_storage_buffer`
+    pub fn write_storage_buffer(
+        &mut self,
+        binding: u32,
+        buffer: Rc<RefCell<Buffer>>
+    ) -> VulkanResult<()> {
+        self.write_buffer(binding, vk::DescriptorType::STORAGE_BUFFER, buffer)
+    }
+
+    fn write_buffer(
+        &mut self,
+        binding: u32,
+        descriptor_type: vk::DescriptorType,
+        buffer: Rc<RefCell<Buffer>>
+    ) -> VulkanResult<()> {
+        if self.layout.descriptor_type(binding) != Some(descriptor_type) {
+            return Err(VulkanError::DescriptorBindingTypeMismatch {binding});
+        }
+
+        let buffer_info = vk::DescriptorBufferInfo::builder()
+            .buffer(buffer.borrow().handle())
+            .offset(0)
+            .range(vk::WHOLE_SIZE)
+            .build();
+
+        self.buffer_writes.retain(|(written, _, _)| *written != binding);
+        self.buffer_writes.push((binding, descriptor_type, buffer_info));
+        self.buffers_in_use.push(buffer);
+
+        Ok(())
+    }
+
+    // The image has to be in the shader read only layout whenever the descriptors are used
+    pub fn write_combined_image_sampler(
+        &mut self,
+        binding: u32,
+        image: Rc<Image>,
+        sampler: Rc<Sampler>
+    ) -> VulkanResult<()> {
+        let descriptor_type = vk::DescriptorType::COMBINED_IMAGE_SAMPLER;
+        if self.layout.descriptor_type(binding) != Some(descriptor_type) {
+            return Err(VulkanError::DescriptorBindingTypeMismatch {binding});
+        }
+
+        let image_info = vk::DescriptorImageInfo::builder()
+            .image_view(image.view())
+            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .sampler(sampler.handle())
+            .build();
+
+        self.image_writes.retain(|(written, _, _)| *written != binding);
+        self.image_writes.push((binding, descriptor_type, image_info));
+        self.images_in_use.push((image, sampler));
+
+        Ok(())
+    }
+
+    // Borrow the infos of `self`, which has to outlive the returned writes
+    pub(crate) fn writes(&self) -> Vec<vk::WriteDescriptorSet> {
+        let buffer_writes = self.buffer_writes
+            .iter()
+            .map(|(binding, descriptor_type, buffer_info)| {
+                vk::WriteDescriptorSet::builder()
+                    .dst_binding(*binding)
+                    .descriptor_type(*descriptor_type)
+                    .buffer_info(std::slice::from_ref(buffer_info))
+                    .build()
+            });
+
+        let image_writes = self.image_writes
+            .iter()
+            .map(|(binding, descriptor_type, image_info)| {
+                vk::WriteDescriptorSet::builder()
+                    .dst_binding(*binding)
+                    .descriptor_type(*descriptor_type)
+                    .image_info(std::slice::from_ref(image_info))
+                    .build()
+            });
+
+        buffer_writes.chain(image_writes).collect()
+    }
+}
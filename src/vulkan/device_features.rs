@@ -0,0 +1,55 @@
+use ash::vk;
+
+// Field names match the ones of `vk::PhysicalDeviceFeatures`
+macro_rules! requested_features {
+    ($($feature:ident),*) => {
+        // Optional device features to enable on the logical device, all of them default to off
+        #[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+        pub struct RequestedFeatures {
+            $(pub $feature: bool),*
+        }
+
+        impl RequestedFeatures {
+            pub fn vk_features(&self) -> vk::PhysicalDeviceFeatures {
+                vk::PhysicalDeviceFeatures {
+                    $($feature: self.$feature as vk::Bool32,)*
+                    ..Default::default()
+                }
+            }
+
+            // Names of the requested features the device does not support
+            pub fn unsupported(&self, available: &vk::PhysicalDeviceFeatures) -> Vec<&'static str> {
+                let mut unsupported = Vec::new();
+
+                $(
+                    if self.$feature && available.$feature != vk::TRUE {
+                        unsupported.push(stringify!($feature));
+                    }
+                )*
+
+                unsupported
+            }
+        }
+    }
+}
+
+requested_features!(
+    sampler_anisotropy,
+    fill_mode_non_solid,
+    wide_lines,
+    large_points,
+    geometry_shader,
+    tessellation_shader,
+    depth_clamp,
+    depth_bias_clamp,
+    multi_draw_indirect,
+    draw_indirect_first_instance,
+    independent_blend,
+    sample_rate_shading,
+    texture_compression_bc,
+    shader_float64,
+    // Writes to storage buffers and images from vertex, geometry and fragment shaders
+    vertex_pipeline_stores_and_atomics,
+    fragment_stores_and_atomics,
+    pipeline_statistics_query
+);
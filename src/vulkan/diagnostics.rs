@@ -0,0 +1,187 @@
+use std::{
+    fmt::{
+        self,
+        Write as _
+    },
+    ffi::CStr,
+    sync::mpsc,
+    thread,
+    time::{
+        Duration,
+        Instant
+    }
+};
+use ash::{
+    vk_version_major,
+    vk_version_minor,
+    vk_version_patch,
+    version::{
+        EntryV1_0,
+        InstanceV1_0
+    }
+};
+use crate::vulkan::state::VulkanState;
+
+// Environment variables which change which drivers and layers the Vulkan loader picks up,
+// or which GPU hybrid graphics setups render on
+const ENVIRONMENT_VARIABLES: [&str; 12] = [
+    "VK_ICD_FILENAMES",
+    "VK_DRIVER_FILES",
+    "VK_ADD_DRIVER_FILES",
+    "VK_LAYER_PATH",
+    "VK_ADD_LAYER_PATH",
+    "VK_INSTANCE_LAYERS",
+    "VK_LOADER_DEBUG",
+    "DRI_PRIME",
+    "MESA_VK_DEVICE_SELECT",
+    "__NV_PRIME_RENDER_OFFLOAD",
+    "__VK_LAYER_NV_optimus",
+    "MAGMACRAFT_GPU"
+];
+
+// Creation steps taking longer than this most likely hang in the driver
+const SLOW_STEP_THRESHOLD: Duration = Duration::from_secs(10);
+
+// Runs a creation step, e.g. instance or swapchain creation, logging how long it took.
+// Failures are logged together with a report of the Vulkan environment, and so are
+// steps that take suspiciously long, as those may never finish at all.
+pub fn diagnose<T, E, F>(step: &'static str, vulkan_state: Option<&VulkanState>, create: F)
+    -> Result<T, E> where
+    E: fmt::Display,
+    F: FnOnce() -> Result<T, E> {
+    let start = Instant::now();
+    let (finished_sender, finished_receiver) = mpsc::channel::<()>();
+    let watchdog = thread::spawn(move || {
+        if let Err(mpsc::RecvTimeoutError::Timeout) =
+            finished_receiver.recv_timeout(SLOW_STEP_THRESHOLD) {
+            warn!(
+                "{} has been running for over {:?}\n{}",
+                step, SLOW_STEP_THRESHOLD, environment_report());
+        }
+    });
+
+    let result = create();
+
+    drop(finished_sender);
+    // The watchdog returns as soon as the sender is dropped
+    let _ = watchdog.join();
+
+    debug!("{} took {:?}", step, start.elapsed());
+
+    if let Err(error) = &result {
+        error!("{} failed: {}\n{}", step, error, report(vulkan_state));
+    }
+
+    result
+}
+
+// Environment variables, available layers and, once an instance exists, all physical devices
+pub fn report(vulkan_state: Option<&VulkanState>) -> String {
+    let mut report = environment_report();
+
+    match vulkan_state {
+        Some(vulkan_state) => {
+            report += &layers_report(vulkan_state.entry());
+            report += &physical_devices_report(vulkan_state);
+        },
+        None => match ash::Entry::new() {
+            Ok(entry) => report += &layers_report(&entry),
+            Err(error) => {
+                writeln!(report, "Vulkan library could not be loaded: {}", error).unwrap();
+            }
+        }
+    }
+
+    report
+}
+
+pub fn environment_report() -> String {
+    let mut report = String::from("Vulkan environment:\n");
+
+    for variable in ENVIRONMENT_VARIABLES.iter() {
+        if let Some(value) = std::env::var_os(variable) {
+            writeln!(report, "  {}={}", variable, value.to_string_lossy()).unwrap();
+        }
+    }
+
+    report
+}
+
+fn layers_report(entry: &ash::Entry) -> String {
+    let mut report = String::from("Available layers:\n");
+
+    match entry.enumerate_instance_layer_properties() {
+        Ok(layers) => {
+            for layer in layers {
+                writeln!(
+                    report, "  {} (spec {})",
+                    c_string(&layer.layer_name), version_string(layer.spec_version)).unwrap();
+            }
+        },
+        Err(result) => writeln!(report, "  failed to enumerate layers: {}", result).unwrap()
+    }
+
+    report
+}
+
+fn physical_devices_report(vulkan_state: &VulkanState) -> String {
+    let mut report = String::from("Physical devices:\n");
+    let instance = vulkan_state.instance_loader();
+
+    let devices = match unsafe { instance.enumerate_physical_devices() } {
+        Ok(devices) => devices,
+        Err(result) => {
+            writeln!(report, "  failed to enumerate physical devices: {}", result).unwrap();
+            return report;
+        }
+    };
+
+    for device in devices {
+        let properties = unsafe {
+            instance.get_physical_device_properties(device)
+        };
+
+        writeln!(
+            report,
+            "  {} ({:?}), vendor {:#06x}, device {:#06x}, Vulkan {}, driver {}",
+            c_string(&properties.device_name),
+            properties.device_type,
+            properties.vendor_id,
+            properties.device_id,
+            version_string(properties.api_version),
+            driver_version_string(properties.vendor_id, properties.driver_version)).unwrap();
+    }
+
+    report
+}
+
+fn c_string(characters: &[std::os::raw::c_char]) -> std::borrow::Cow<'_, str> {
+    unsafe {
+        CStr::from_ptr(characters.as_ptr()).to_string_lossy()
+    }
+}
+
+fn version_string(version: u32) -> String {
+    format!(
+        "{}.{}.{}",
+        vk_version_major!(version),
+        vk_version_minor!(version),
+        vk_version_patch!(version))
+}
+
+// Driver versions are vendor specific, only NVIDIA's differs from Vulkan's own scheme
+fn driver_version_string(vendor_id: u32, driver_version: u32) -> String {
+    const NVIDIA_VENDOR_ID: u32 = 0x10de;
+
+    if vendor_id == NVIDIA_VENDOR_ID {
+        format!(
+            "{}.{}.{}.{}",
+            driver_version >> 22,
+            (driver_version >> 14) & 0xff,
+            (driver_version >> 6) & 0xff,
+            driver_version & 0x3f)
+    }
+    else {
+        version_string(driver_version)
+    }
+}
@@ -0,0 +1,258 @@
+use ash::{
+    version::DeviceV1_0,
+    vk
+};
+use crate::vulkan::{
+    VulkanError,
+    VulkanResult,
+    handle::VulkanHandle,
+    logical_device::LogicalDevice
+};
+
+// Memory and semaphores shared with other APIs or processes, e.g. CUDA, video decoders or
+// capture software, through opaque file descriptors on Unix-likes and NT handles on Windows.
+// Needs Vulkan 1.1 and the platform's VK_KHR_external_memory_* and VK_KHR_external_semaphore_*
+// extensions, see `LogicalDevice::external_memory_fn` and `LogicalDevice::external_semaphore_fn`.
+
+#[cfg(not(windows))]
+pub type ExternalHandle = std::os::raw::c_int;
+#[cfg(windows)]
+pub type ExternalHandle = vk::HANDLE;
+
+#[cfg(not(windows))]
+pub type ExternalMemoryFn = vk::KhrExternalMemoryFdFn;
+#[cfg(windows)]
+pub type ExternalMemoryFn = vk::KhrExternalMemoryWin32Fn;
+
+#[cfg(not(windows))]
+pub type ExternalSemaphoreFn = vk::KhrExternalSemaphoreFdFn;
+#[cfg(windows)]
+pub type ExternalSemaphoreFn = vk::KhrExternalSemaphoreWin32Fn;
+
+#[cfg(not(windows))]
+pub const MEMORY_HANDLE_TYPE: vk::ExternalMemoryHandleTypeFlags =
+    vk::ExternalMemoryHandleTypeFlags::EXTERNAL_MEMORY_HANDLE_TYPE_OPAQUE_FD;
+#[cfg(windows)]
+pub const MEMORY_HANDLE_TYPE: vk::ExternalMemoryHandleTypeFlags =
+    vk::ExternalMemoryHandleTypeFlags::EXTERNAL_MEMORY_HANDLE_TYPE_OPAQUE_WIN32;
+
+#[cfg(not(windows))]
+pub const SEMAPHORE_HANDLE_TYPE: vk::ExternalSemaphoreHandleTypeFlags =
+    vk::ExternalSemaphoreHandleTypeFlags::EXTERNAL_SEMAPHORE_HANDLE_TYPE_OPAQUE_FD;
+#[cfg(windows)]
+pub const SEMAPHORE_HANDLE_TYPE: vk::ExternalSemaphoreHandleTypeFlags =
+    vk::ExternalSemaphoreHandleTypeFlags::EXTERNAL_SEMAPHORE_HANDLE_TYPE_OPAQUE_WIN32;
+
+// Exported handles belong to the caller, which has to close them once they are imported
+// or no longer needed. Imports take ownership of file descriptors, but not of NT handles.
+#[derive(Clone, Copy, Debug)]
+pub struct ExternalMemoryHandle {
+    pub handle: ExternalHandle,
+    // Size of the whole allocation, which is needed to import it
+    pub size: vk::DeviceSize
+}
+
+fn external_memory_fn(logical_device: &LogicalDevice) -> VulkanResult<&ExternalMemoryFn> {
+    logical_device
+        .external_memory_fn()
+        .ok_or(VulkanError::ExternalMemoryNotEnabled)
+}
+
+fn external_semaphore_fn(logical_device: &LogicalDevice) -> VulkanResult<&ExternalSemaphoreFn> {
+    logical_device
+        .external_semaphore_fn()
+        .ok_or(VulkanError::ExternalSemaphoresNotEnabled)
+}
+
+// Memory bound to a single image, allocated either to be exported or from an imported handle
+pub(crate) fn allocate_image_memory(
+    logical_device: &LogicalDevice,
+    vk_image: vk::Image,
+    memory_type_index: u32,
+    size: vk::DeviceSize,
+    imported: Option<ExternalHandle>
+) -> VulkanResult<vk::DeviceMemory> {
+    external_memory_fn(logical_device)?;
+
+    let mut dedicated_allocate_info = vk::MemoryDedicatedAllocateInfo::builder()
+        .image(vk_image);
+
+    let mut export_allocate_info = vk::ExportMemoryAllocateInfo::builder()
+        .handle_types(MEMORY_HANDLE_TYPE);
+
+    let mut import_info = imported.map(import_memory_info);
+
+    let mut allocate_info = vk::MemoryAllocateInfo::builder()
+        .allocation_size(size)
+        .memory_type_index(memory_type_index)
+        .push_next(&mut dedicated_allocate_info);
+
+    allocate_info = match &mut import_info {
+        Some(import_info) => allocate_info.push_next(import_info),
+        None => allocate_info.push_next(&mut export_allocate_info)
+    };
+
+    unsafe {
+        logical_device.allocate_memory(&allocate_info, None)
+    }.map_err(|result| VulkanError::ExternalMemoryAllocateError {result})
+}
+
+#[cfg(not(windows))]
+fn import_memory_info(handle: ExternalHandle) -> vk::ImportMemoryFdInfoKHR {
+    vk::ImportMemoryFdInfoKHR::builder()
+        .handle_type(MEMORY_HANDLE_TYPE)
+        .fd(handle)
+        .build()
+}
+
+#[cfg(windows)]
+fn import_memory_info(handle: ExternalHandle) -> vk::ImportMemoryWin32HandleInfoKHR {
+    vk::ImportMemoryWin32HandleInfoKHR::builder()
+        .handle_type(MEMORY_HANDLE_TYPE)
+        .handle(handle)
+        .build()
+}
+
+#[cfg(not(windows))]
+pub(crate) fn export_memory(
+    logical_device: &LogicalDevice,
+    memory: vk::DeviceMemory
+) -> VulkanResult<ExternalHandle> {
+    let get_info = vk::MemoryGetFdInfoKHR::builder()
+        .memory(memory)
+        .handle_type(MEMORY_HANDLE_TYPE);
+
+    let mut handle = -1;
+    let result = unsafe {
+        external_memory_fn(logical_device)?
+            .get_memory_fd_khr(logical_device.handle(), &*get_info, &mut handle)
+    };
+
+    match result {
+        vk::Result::SUCCESS => Ok(handle),
+        result => Err(VulkanError::ExternalMemoryExportError {result})
+    }
+}
+
+#[cfg(windows)]
+pub(crate) fn export_memory(
+    logical_device: &LogicalDevice,
+    memory: vk::DeviceMemory
+) -> VulkanResult<ExternalHandle> {
+    let get_info = vk::MemoryGetWin32HandleInfoKHR::builder()
+        .memory(memory)
+        .handle_type(MEMORY_HANDLE_TYPE);
+
+    let mut handle = std::ptr::null_mut();
+    let result = unsafe {
+        external_memory_fn(logical_device)?
+            .get_memory_win32_handle_khr(logical_device.handle(), &*get_info, &mut handle)
+    };
+
+    match result {
+        vk::Result::SUCCESS => Ok(handle),
+        result => Err(VulkanError::ExternalMemoryExportError {result})
+    }
+}
+
+pub(crate) fn create_exportable_semaphore(
+    logical_device: &LogicalDevice
+) -> VulkanResult<vk::Semaphore> {
+    external_semaphore_fn(logical_device)?;
+
+    let mut export_create_info = vk::ExportSemaphoreCreateInfo::builder()
+        .handle_types(SEMAPHORE_HANDLE_TYPE);
+
+    let create_info = vk::SemaphoreCreateInfo::builder()
+        .push_next(&mut export_create_info);
+
+    unsafe {
+        logical_device.create_semaphore(&create_info, None)
+    }.map_err(|result| VulkanError::SemaphoreCreateError {result})
+}
+
+#[cfg(not(windows))]
+pub(crate) fn export_semaphore(
+    logical_device: &LogicalDevice,
+    semaphore: vk::Semaphore
+) -> VulkanResult<ExternalHandle> {
+    let get_info = vk::SemaphoreGetFdInfoKHR::builder()
+        .semaphore(semaphore)
+        .handle_type(SEMAPHORE_HANDLE_TYPE);
+
+    let mut handle = -1;
+    let result = unsafe {
+        external_semaphore_fn(logical_device)?
+            .get_semaphore_fd_khr(logical_device.handle(), &*get_info, &mut handle)
+    };
+
+    match result {
+        vk::Result::SUCCESS => Ok(handle),
+        result => Err(VulkanError::ExternalSemaphoreExportError {result})
+    }
+}
+
+#[cfg(windows)]
+pub(crate) fn export_semaphore(
+    logical_device: &LogicalDevice,
+    semaphore: vk::Semaphore
+) -> VulkanResult<ExternalHandle> {
+    let get_info = vk::SemaphoreGetWin32HandleInfoKHR::builder()
+        .semaphore(semaphore)
+        .handle_type(SEMAPHORE_HANDLE_TYPE);
+
+    let mut handle = std::ptr::null_mut();
+    let result = unsafe {
+        external_semaphore_fn(logical_device)?
+            .get_semaphore_win32_handle_khr(logical_device.handle(), &*get_info, &mut handle)
+    };
+
+    match result {
+        vk::Result::SUCCESS => Ok(handle),
+        result => Err(VulkanError::ExternalSemaphoreExportError {result})
+    }
+}
+
+#[cfg(not(windows))]
+pub(crate) fn import_semaphore(
+    logical_device: &LogicalDevice,
+    semaphore: vk::Semaphore,
+    handle: ExternalHandle
+) -> VulkanResult<()> {
+    let import_info = vk::ImportSemaphoreFdInfoKHR::builder()
+        .semaphore(semaphore)
+        .handle_type(SEMAPHORE_HANDLE_TYPE)
+        .fd(handle);
+
+    let result = unsafe {
+        external_semaphore_fn(logical_device)?
+            .import_semaphore_fd_khr(logical_device.handle(), &*import_info)
+    };
+
+    match result {
+        vk::Result::SUCCESS => Ok(()),
+        result => Err(VulkanError::ExternalSemaphoreImportError {result})
+    }
+}
+
+#[cfg(windows)]
+pub(crate) fn import_semaphore(
+    logical_device: &LogicalDevice,
+    semaphore: vk::Semaphore,
+    handle: ExternalHandle
+) -> VulkanResult<()> {
+    let import_info = vk::ImportSemaphoreWin32HandleInfoKHR::builder()
+        .semaphore(semaphore)
+        .handle_type(SEMAPHORE_HANDLE_TYPE)
+        .handle(handle);
+
+    let result = unsafe {
+        external_semaphore_fn(logical_device)?
+            .import_semaphore_win32_handle_khr(logical_device.handle(), &*import_info)
+    };
+
+    match result {
+        vk::Result::SUCCESS => Ok(()),
+        result => Err(VulkanError::ExternalSemaphoreImportError {result})
+    }
+}
@@ -12,16 +12,26 @@ use crate::{
     vulkan::{
         VulkanResult,
         VulkanError,
+        handle::VulkanHandle,
         logical_device::LogicalDevice,
         swapchain::Swapchain,
-        render_pass::RenderPass
+        render_pass::RenderPass,
+        image::Image
     }
 };
 
+// Images are kept alive for as long as their framebuffers
+enum FramebuffersTarget {
+    Swapchain(Rc<Swapchain>),
+    Images(Vec<Rc<Image>>)
+}
+
 pub struct Framebuffers {
     vk_framebuffers: Vec<vk::Framebuffer>,
     logical_device: Rc<LogicalDevice>,
-    swapchain: Rc<Swapchain>
+    target: FramebuffersTarget,
+    // lifetime extenders
+    _attachments: Vec<Rc<Image>>
 }
 
 impl Framebuffers {
@@ -31,15 +41,72 @@ impl Framebuffers {
         }
     }
 
+    // Single framebuffer with the image as its only attachment, for offscreen render passes
+    pub fn for_image(
+        logical_device: Rc<LogicalDevice>,
+        render_pass: &RenderPass,
+        image: Rc<Image>
+    ) -> VulkanResult<Self> {
+        Self::for_images(logical_device, render_pass, vec![image])
+    }
+
+    // Single framebuffer with the images as attachments in the given order,
+    // e.g. for render passes writing multiple color attachments at once.
+    // All images have to be of the same size.
+    pub fn for_images(
+        logical_device: Rc<LogicalDevice>,
+        render_pass: &RenderPass,
+        images: Vec<Rc<Image>>
+    ) -> VulkanResult<Self> {
+        debug_assert!(!images.is_empty(), "framebuffers need at least one attachment");
+
+        let extent = images[0].extent();
+        if !are_all_of_size(&images, extent) {
+            return Err(VulkanError::FramebufferAttachmentSizeMismatch);
+        }
+
+        let attachments: Vec<vk::ImageView> = images.iter().map(|image| image.view()).collect();
+
+        let framebuffer_create_info = vk::FramebufferCreateInfo::builder()
+            .render_pass(render_pass.handle())
+            .attachments(&attachments)
+            .width(extent.width)
+            .height(extent.height)
+            .layers(1);
+
+        let vk_framebuffer = unsafe {
+            logical_device.create_framebuffer(&framebuffer_create_info, None)
+        }.map_err(|result| VulkanError::FramebuffersCreateError {result})?;
+
+        Ok(Self {
+            vk_framebuffers: vec![vk_framebuffer],
+            logical_device,
+            target: FramebuffersTarget::Images(images),
+            _attachments: Vec::new()
+        })
+    }
+
     pub fn handle(&self, index: usize) -> vk::Framebuffer {
         self.vk_framebuffers[index]
     }
 
     pub fn image_extent(&self) -> vk::Extent2D {
-        self.swapchain.extent()
+        match &self.target {
+            FramebuffersTarget::Swapchain(swapchain) => swapchain.extent(),
+            FramebuffersTarget::Images(images) => images[0].extent()
+        }
     }
 }
 
+fn are_all_of_size(images: &[Rc<Image>], extent: vk::Extent2D) -> bool {
+    images
+        .iter()
+        .map(|image| image.extent())
+        .all(|image_extent| {
+            (image_extent.width, image_extent.height) == (extent.width, extent.height)
+        })
+}
+
 impl Drop for Framebuffers {
     fn drop(&mut self) {
         unsafe {
@@ -55,6 +122,7 @@ pub struct FramebuffersBuilder {
     logical_device: BuilderRequirement<Rc<LogicalDevice>>,
     swapchain: BuilderRequirement<Rc<Swapchain>>,
     render_pass: BuilderRequirement<Rc<RenderPass>>,
+    attachments: Vec<Rc<Image>>,
 
     vk_framebuffers: BuilderInternal<Vec<vk::Framebuffer>>,
 
@@ -77,7 +145,19 @@ impl FramebuffersBuilder {
         self
     }
 
+    // Attached after the swapchain image, in the order of the render pass' attachments.
+    // The framebuffers of all swapchain images share the image, so it can only hold
+    // contents which do not outlive a frame, e.g. a G-buffer.
+    pub fn attachment(mut self, image: Rc<Image>) -> Self {
+        self.attachments.push(image);
+        self
+    }
+
     pub fn build(mut self) -> VulkanResult<Framebuffers> {
+        check_required_fields!(
+            self, VulkanError, "FramebuffersBuilder",
+            [logical_device, swapchain, render_pass]);
+
         self.init_vk_framebuffers()?;
         self.create_framebuffers();
 
@@ -87,6 +167,11 @@ impl FramebuffersBuilder {
     fn init_vk_framebuffers(&mut self) -> VulkanResult<()> {
         let image_views = self.swapchain.image_views();
         let extent = self.swapchain.extent();
+
+        if !are_all_of_size(&self.attachments, extent) {
+            return Err(VulkanError::FramebufferAttachmentSizeMismatch);
+        }
+
         let mut vk_framebuffers = Vec::with_capacity(image_views.len());
 
         for image_view in image_views {
@@ -104,7 +189,9 @@ impl FramebuffersBuilder {
         extent: &vk::Extent2D,
         vk_framebuffers: &mut Vec<vk::Framebuffer>
     ) -> VulkanResult<()> {
-        let attachments = [image_view];
+        let attachments: Vec<vk::ImageView> = std::iter::once(image_view)
+            .chain(self.attachments.iter().map(|image| image.view()))
+            .collect();
 
         let framebuffer_create_info = vk::FramebufferCreateInfo::builder()
             .render_pass(self.render_pass.handle())
@@ -125,7 +212,8 @@ impl FramebuffersBuilder {
         let framebuffers = Framebuffers {
             vk_framebuffers: self.vk_framebuffers.take(),
             logical_device: self.logical_device.take(),
-            swapchain: self.swapchain.take(),
+            target: FramebuffersTarget::Swapchain(self.swapchain.take()),
+            _attachments: std::mem::take(&mut self.attachments)
         };
 
         self.framebuffers.set(framebuffers);
@@ -0,0 +1,17 @@
+use ash::vk;
+
+// Implemented by every wrapper owning a single Vulkan object,
+// so that generic utilities (e.g. naming objects for debuggers) can work with any of them
+pub trait VulkanHandle {
+    type Handle: vk::Handle + Copy;
+
+    fn handle(&self) -> Self::Handle;
+
+    fn raw_handle(&self) -> u64 {
+        vk::Handle::as_raw(self.handle())
+    }
+
+    fn object_type(&self) -> vk::ObjectType {
+        <Self::Handle as vk::Handle>::TYPE
+    }
+}
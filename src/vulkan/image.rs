@@ -0,0 +1,515 @@
+use std::rc::Rc;
+use ash::{
+    version::DeviceV1_0,
+    vk
+};
+use crate::{
+    builder::{
+        BuilderRequirement,
+        BuilderInternal,
+        BuilderProduct
+    },
+    vulkan::{
+        VulkanError,
+        VulkanResult,
+        handle::VulkanHandle,
+        leak_tracking,
+        logical_device::LogicalDevice,
+        external_memory::{
+            self,
+            ExternalMemoryHandle
+        },
+        allocator::{
+            Allocator,
+            MemoryUsage
+        }
+    }
+};
+
+// Sampled 2D image in device local memory, viewed as an array of all of its layers,
+// or as a cube if it is a cube map
+pub struct Image {
+    vk_image: vk::Image,
+    vk_image_view: vk::ImageView,
+    memory: ImageMemory,
+    format: vk::Format,
+    aspect: vk::ImageAspectFlags,
+    extent: vk::Extent2D,
+    mip_levels: u32,
+    array_layers: u32,
+    is_cube_map: bool,
+    logical_device: Rc<LogicalDevice>
+}
+
+impl Image {
+    pub fn builder() -> ImageBuilder {
+        ImageBuilder {
+            ..Default::default()
+        }
+    }
+
+    // Views an image owned by something else, e.g. a swapchain image of an OpenXR runtime,
+    // which has to outlive the returned one. Only the view is destroyed on drop.
+    pub fn from_external(
+        logical_device: Rc<LogicalDevice>,
+        vk_image: vk::Image,
+        format: vk::Format,
+        extent: vk::Extent2D,
+        array_layers: u32
+    ) -> VulkanResult<Image> {
+        let subresource_range = vk::ImageSubresourceRange::builder()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .base_mip_level(0)
+            .level_count(1)
+            .base_array_layer(0)
+            .layer_count(array_layers)
+            .build();
+
+        let image_view_create_info = vk::ImageViewCreateInfo::builder()
+            .image(vk_image)
+            .view_type(vk::ImageViewType::TYPE_2D_ARRAY)
+            .format(format)
+            .subresource_range(subresource_range);
+
+        let vk_image_view = unsafe {
+            logical_device.create_image_view(&image_view_create_info, None)
+        }.map_err(|result| VulkanError::ImageViewCreateError {result})?;
+
+        let image = Image {
+            vk_image,
+            vk_image_view,
+            memory: ImageMemory::Foreign,
+            format,
+            aspect: vk::ImageAspectFlags::COLOR,
+            extent,
+            mip_levels: 1,
+            array_layers,
+            is_cube_map: false,
+            logical_device
+        };
+
+        leak_tracking::track_creation(&image);
+        Ok(image)
+    }
+
+    pub fn view(&self) -> vk::ImageView {
+        self.vk_image_view
+    }
+
+    pub fn format(&self) -> vk::Format {
+        self.format
+    }
+
+    pub fn aspect(&self) -> vk::ImageAspectFlags {
+        self.aspect
+    }
+
+    pub fn extent(&self) -> vk::Extent2D {
+        self.extent
+    }
+
+    pub fn mip_levels(&self) -> u32 {
+        self.mip_levels
+    }
+
+    pub fn array_layers(&self) -> u32 {
+        self.array_layers
+    }
+
+    pub fn is_cube_map(&self) -> bool {
+        self.is_cube_map
+    }
+
+    // Handle to the image's memory for another API or process, which creates an image with the
+    // same parameters and binds the memory to it, e.g. with `ImageBuilder::imported_memory`
+    pub fn export_memory(&self) -> VulkanResult<ExternalMemoryHandle> {
+        match self.memory {
+            ImageMemory::Exportable {vk_memory, size} => {
+                let handle = external_memory::export_memory(&self.logical_device, vk_memory)?;
+                Ok(ExternalMemoryHandle {handle, size})
+            },
+            _ => Err(VulkanError::ImageMemoryNotExportable)
+        }
+    }
+
+    // Extent of the given mip level, never smaller than a single texel
+    pub fn mip_extent(&self, mip_level: u32) -> vk::Extent2D {
+        vk::Extent2D {
+            width: (self.extent.width >> mip_level).max(1),
+            height: (self.extent.height >> mip_level).max(1)
+        }
+    }
+}
+
+impl VulkanHandle for Image {
+    type Handle = vk::Image;
+
+    fn handle(&self) -> Self::Handle {
+        self.vk_image
+    }
+}
+
+impl Drop for Image {
+    fn drop(&mut self) {
+        leak_tracking::track_destruction(self);
+
+        unsafe {
+            self.logical_device.destroy_image_view(self.vk_image_view, None);
+        }
+
+        self.memory.destroy(&self.logical_device, self.vk_image);
+    }
+}
+
+enum ImageMemory {
+    Allocated(vk_mem::Allocation, Rc<Allocator>),
+    // Dedicated allocations shared with other APIs or processes
+    Exportable {vk_memory: vk::DeviceMemory, size: vk::DeviceSize},
+    Imported(vk::DeviceMemory),
+    // Images created and destroyed by someone else, see `from_external`
+    Foreign
+}
+
+impl ImageMemory {
+    fn destroy(&self, logical_device: &LogicalDevice, vk_image: vk::Image) {
+        match self {
+            ImageMemory::Allocated(allocation, allocator) => {
+                allocator
+                    .destroy_image(vk_image, allocation)
+                    .unwrap();
+            },
+            ImageMemory::Exportable {vk_memory, ..} | ImageMemory::Imported(vk_memory) => unsafe {
+                logical_device.destroy_image(vk_image, None);
+                logical_device.free_memory(*vk_memory, None);
+            },
+            ImageMemory::Foreign => ()
+        }
+    }
+}
+
+enum ExternalMemory {
+    Exportable,
+    Imported(ExternalMemoryHandle)
+}
+
+#[derive(Default)]
+pub struct ImageBuilder {
+    allocator: BuilderRequirement<Rc<Allocator>>,
+    logical_device: BuilderRequirement<Rc<LogicalDevice>>,
+    format: BuilderRequirement<vk::Format>,
+    extent: BuilderRequirement<vk::Extent2D>,
+    usage: BuilderRequirement<vk::ImageUsageFlags>,
+    aspect: Option<vk::ImageAspectFlags>,
+    mip_levels: Option<u32>,
+    array_layers: Option<u32>,
+    cube_map: bool,
+    external_memory: Option<ExternalMemory>,
+
+    vk_image: BuilderInternal<vk::Image>,
+    memory: BuilderInternal<ImageMemory>,
+    vk_image_view: BuilderInternal<vk::ImageView>,
+
+    image: BuilderProduct<Image>
+}
+
+impl ImageBuilder {
+    pub fn allocator(mut self, allocator: Rc<Allocator>) -> Self {
+        self.allocator.set(allocator);
+        self
+    }
+
+    pub fn logical_device(mut self, logical_device: Rc<LogicalDevice>) -> Self {
+        self.logical_device.set(logical_device);
+        self
+    }
+
+    pub fn format(mut self, format: vk::Format) -> Self {
+        self.format.set(format);
+        self
+    }
+
+    pub fn extent(mut self, extent: vk::Extent2D) -> Self {
+        self.extent.set(extent);
+        self
+    }
+
+    pub fn usage(mut self, usage: vk::ImageUsageFlags) -> Self {
+        self.usage.set(usage);
+        self
+    }
+
+    // Aspect seen through the image's view and covered by its barriers, defaults to COLOR.
+    // Depth images are viewed through their DEPTH aspect only.
+    pub fn aspect(mut self, aspect: vk::ImageAspectFlags) -> Self {
+        self.aspect = Some(aspect);
+        self
+    }
+
+    // Defaults to 1
+    pub fn mip_levels(mut self, mip_levels: u32) -> Self {
+        self.mip_levels = Some(mip_levels);
+        self
+    }
+
+    // Defaults to 1, or to 6 for cube maps
+    pub fn array_layers(mut self, array_layers: u32) -> Self {
+        self.array_layers = Some(array_layers);
+        self
+    }
+
+    // Six square layers, the faces +X, -X, +Y, -Y, +Z and -Z in that order,
+    // viewed as a cube, e.g. to be sampled through a `samplerCube`
+    pub fn cube_map(mut self, cube_map: bool) -> Self {
+        self.cube_map = cube_map;
+        self
+    }
+
+    // Memory which can be shared with other APIs or processes, see `Image::export_memory`.
+    // Allocated by itself instead of through the allocator.
+    pub fn exportable(mut self) -> Self {
+        self.external_memory = Some(ExternalMemory::Exportable);
+        self
+    }
+
+    // Binds memory exported by another API or process to the image, which has to be created
+    // with the same parameters the memory's image was created with
+    pub fn imported_memory(mut self, handle: ExternalMemoryHandle) -> Self {
+        self.external_memory = Some(ExternalMemory::Imported(handle));
+        self
+    }
+
+    pub fn build(mut self) -> VulkanResult<Image> {
+        check_required_fields!(
+            self, VulkanError, "ImageBuilder",
+            [allocator, logical_device, format, extent, usage]);
+
+        if self.cube_map {
+            self.check_cube_map()?;
+        }
+
+        if self.external_memory.is_some() {
+            self.init_external_vk_image()?;
+        }
+        else {
+            self.init_vk_image()?;
+        }
+
+        self.init_vk_image_view()?;
+        self.create_image();
+
+        Ok(self.image.unwrap())
+    }
+
+    fn check_cube_map(&self) -> VulkanResult<()> {
+        if self.extent.width != self.extent.height {
+            return Err(VulkanError::CubeImageNotSquare {
+                width: self.extent.width,
+                height: self.extent.height
+            });
+        }
+
+        match self.array_layers {
+            Some(layer_count) if layer_count != 6 => {
+                Err(VulkanError::CubeImageLayerCount {layer_count})
+            },
+            _ => Ok(())
+        }
+    }
+
+    fn array_layer_count(&self) -> u32 {
+        let default = if self.cube_map {
+            6
+        }
+        else {
+            1
+        };
+
+        self.array_layers.unwrap_or(default)
+    }
+
+    fn image_create_info(&self) -> vk::ImageCreateInfoBuilder<'static> {
+        let flags = if self.cube_map {
+            vk::ImageCreateFlags::CUBE_COMPATIBLE
+        }
+        else {
+            vk::ImageCreateFlags::empty()
+        };
+
+        vk::ImageCreateInfo::builder()
+            .flags(flags)
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(*self.format)
+            .extent(vk::Extent3D {
+                width: self.extent.width,
+                height: self.extent.height,
+                depth: 1
+            })
+            .mip_levels(self.mip_levels.unwrap_or(1))
+            .array_layers(self.array_layer_count())
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(*self.usage)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .initial_layout(vk::ImageLayout::UNDEFINED)
+    }
+
+    fn init_vk_image(&mut self) -> VulkanResult<()> {
+        let image_create_info = self.image_create_info();
+        let allocation_create_info = vk_mem::AllocationCreateInfo {
+            usage: MemoryUsage::GpuOnly,
+            ..Default::default()
+        };
+
+        let (vk_image, allocation, _) = self.allocator
+            .create_image(&image_create_info, &allocation_create_info)
+            .map_err(|error| VulkanError::ImageCreateError {error})?;
+
+        self.vk_image.set(vk_image);
+        self.memory.set(ImageMemory::Allocated(allocation, Rc::clone(&self.allocator)));
+
+        Ok(())
+    }
+
+    fn init_external_vk_image(&mut self) -> VulkanResult<()> {
+        let mut external_memory_create_info = vk::ExternalMemoryImageCreateInfo::builder()
+            .handle_types(external_memory::MEMORY_HANDLE_TYPE);
+
+        let image_create_info = self.image_create_info()
+            .push_next(&mut external_memory_create_info);
+
+        let vk_image = unsafe {
+            self.logical_device.create_image(&image_create_info, None)
+        }.map_err(|result| VulkanError::ExternalImageCreateError {result})?;
+
+        match self.allocate_external_memory(vk_image) {
+            Ok(memory) => {
+                self.vk_image.set(vk_image);
+                self.memory.set(memory);
+                Ok(())
+            },
+            Err(error) => {
+                unsafe {
+                    self.logical_device.destroy_image(vk_image, None);
+                }
+
+                Err(error)
+            }
+        }
+    }
+
+    fn allocate_external_memory(&self, vk_image: vk::Image) -> VulkanResult<ImageMemory> {
+        let requirements = unsafe {
+            self.logical_device.get_image_memory_requirements(vk_image)
+        };
+
+        let memory_type_index = self.external_memory_type_index(requirements.memory_type_bits)?;
+        let (size, imported) = match &self.external_memory {
+            Some(ExternalMemory::Imported(imported)) => (imported.size, Some(imported.handle)),
+            _ => (requirements.size, None)
+        };
+
+        let vk_memory = external_memory::allocate_image_memory(
+            &self.logical_device, vk_image, memory_type_index, size, imported)?;
+
+        let bind_result = unsafe {
+            self.logical_device.bind_image_memory(vk_image, vk_memory, 0)
+        };
+
+        if let Err(result) = bind_result {
+            unsafe {
+                self.logical_device.free_memory(vk_memory, None);
+            }
+
+            return Err(VulkanError::ExternalMemoryBindError {result});
+        }
+
+        let memory = match imported {
+            Some(_) => ImageMemory::Imported(vk_memory),
+            None => ImageMemory::Exportable {vk_memory, size}
+        };
+
+        Ok(memory)
+    }
+
+    // Device local memory is preferred, but any type the image can be bound to will do
+    fn external_memory_type_index(&self, memory_type_bits: u32) -> VulkanResult<u32> {
+        let memory_properties = self.logical_device
+            .physical_device()
+            .memory_properties();
+
+        let memory_types =
+            &memory_properties.memory_types[..memory_properties.memory_type_count as usize];
+
+        let is_allowed = |index: &usize| memory_type_bits & (1 << index) != 0;
+        let device_local = memory_types
+            .iter()
+            .enumerate()
+            .map(|(index, memory_type)| (index, memory_type.property_flags))
+            .find(|(index, flags)| {
+                is_allowed(index) && flags.contains(vk::MemoryPropertyFlags::DEVICE_LOCAL)
+            })
+            .map(|(index, _)| index);
+
+        device_local
+            .or_else(|| (0..memory_types.len()).find(is_allowed))
+            .map(|index| index as u32)
+            .ok_or(VulkanError::ExternalMemoryTypeNotFound)
+    }
+
+    fn init_vk_image_view(&mut self) -> VulkanResult<()> {
+        let subresource_range = vk::ImageSubresourceRange::builder()
+            .aspect_mask(self.aspect.unwrap_or(vk::ImageAspectFlags::COLOR))
+            .base_mip_level(0)
+            .level_count(self.mip_levels.unwrap_or(1))
+            .base_array_layer(0)
+            .layer_count(self.array_layer_count())
+            .build();
+
+        let view_type = if self.cube_map {
+            vk::ImageViewType::CUBE
+        }
+        else {
+            vk::ImageViewType::TYPE_2D_ARRAY
+        };
+
+        let image_view_create_info = vk::ImageViewCreateInfo::builder()
+            .image(*self.vk_image)
+            .view_type(view_type)
+            .format(*self.format)
+            .subresource_range(subresource_range);
+
+        let vk_image_view = unsafe {
+            self.logical_device.create_image_view(&image_view_create_info, None)
+        };
+
+        match vk_image_view {
+            Ok(vk_image_view) => {
+                self.vk_image_view.set(vk_image_view);
+                Ok(())
+            },
+            Err(result) => {
+                self.memory.destroy(&self.logical_device, *self.vk_image);
+
+                Err(VulkanError::ImageViewCreateError {result})
+            }
+        }
+    }
+
+    fn create_image(&mut self) {
+        let image = Image {
+            vk_image: self.vk_image.take(),
+            vk_image_view: self.vk_image_view.take(),
+            memory: self.memory.take(),
+            format: self.format.take(),
+            aspect: self.aspect.unwrap_or(vk::ImageAspectFlags::COLOR),
+            extent: self.extent.take(),
+            mip_levels: self.mip_levels.unwrap_or(1),
+            array_layers: self.array_layer_count(),
+            is_cube_map: self.cube_map,
+            logical_device: self.logical_device.take()
+        };
+
+        leak_tracking::track_creation(&image);
+        self.image.set(image);
+    }
+}
@@ -2,10 +2,7 @@ use std::rc::Rc;
 use ash::{
     self,
     vk_make_version,
-    vk::{
-        self,
-        Handle
-    },
+    vk,
     version::{
         InstanceV1_0,
         EntryV1_0
@@ -21,6 +18,7 @@ use crate::{
         self,
         VulkanError,
         VulkanResult,
+        handle::VulkanHandle,
         debug_utils::ValidationLayers
     }
 };
@@ -31,18 +29,31 @@ pub struct Instance {
 }
 
 impl Instance {
+    // Lists portability implementations, e.g. MoltenVK on macOS, which loaders since 1.3.216
+    // hide from instances created without it
+    pub const PORTABILITY_ENUMERATION_EXTENSION: &'static str = "VK_KHR_portability_enumeration";
+
+    // VK_INSTANCE_CREATE_ENUMERATE_PORTABILITY_BIT_KHR, which ash does not define yet
+    pub fn enumerate_portability_flag() -> vk::InstanceCreateFlags {
+        vk::InstanceCreateFlags::from_raw(0x1)
+    }
+
     pub fn builder() -> InstanceBuilder {
         InstanceBuilder {
             ..Default::default()
         }
     }
 
-    pub fn handle(&self) -> &ash::Instance {
+    pub fn loader(&self) -> &ash::Instance {
         &self.vk_instance
     }
+}
 
-    pub fn raw_handle(&self) -> u64 {
-        self.vk_instance.handle().as_raw()
+impl VulkanHandle for Instance {
+    type Handle = vk::Instance;
+
+    fn handle(&self) -> Self::Handle {
+        self.vk_instance.handle()
     }
 }
 
@@ -73,8 +84,10 @@ pub struct InstanceBuilder {
     entry: BuilderRequirement<Rc<ash::Entry>>,
     version: BuilderRequirement<u32>,
     name: BuilderRequirement<String>,
+    api_version: Option<u32>,
     extensions: InstanceExtensions,
     validation_layers: ValidationLayers,
+    flags: vk::InstanceCreateFlags,
 
     debug_mode: BuilderInternal<bool>,
     c_name: BuilderInternal<std::ffi::CString>,
@@ -101,6 +114,12 @@ impl InstanceBuilder {
         self
     }
 
+    // Defaults to Vulkan 1.0, the version has to be supported by the loader
+    pub fn api_version(mut self, api_version: u32) -> Self {
+        self.api_version = Some(api_version);
+        self
+    }
+
     pub fn extensions(mut self, extensions: InstanceExtensions) -> Self {
         self.extensions = extensions;
         self
@@ -111,7 +130,16 @@ impl InstanceBuilder {
         self
     }
 
+    // Defaults to no flags. Enumerating portability implementations requires
+    // `Instance::PORTABILITY_ENUMERATION_EXTENSION` to be among the extensions.
+    pub fn flags(mut self, flags: vk::InstanceCreateFlags) -> Self {
+        self.flags |= flags;
+        self
+    }
+
     pub fn build(mut self) -> VulkanResult<Instance> {
+        check_required_fields!(self, VulkanError, "InstanceBuilder", [entry, version, name]);
+
         self.get_ready_for_creation()?;
         self.create_instance()?;
         Ok(self.instance.unwrap())
@@ -125,7 +153,7 @@ impl InstanceBuilder {
     }
 
     fn init_debug_information(&mut self) -> VulkanResult<()> {
-        let is_debugging = self.validation_layers.len() != 0;
+        let is_debugging = !self.validation_layers.is_empty();
         self.debug_mode.set(is_debugging);
 
         if is_debugging {
@@ -140,7 +168,7 @@ impl InstanceBuilder {
         let properties = self.validation_layer_properties()?;
 
         for layer in self.validation_layers.strings() {
-            if !Self::is_validation_layer_in_properties(&layer, &properties) {
+            if !Self::is_validation_layer_in_properties(layer, &properties) {
                 return Err(VulkanError::ValidationLayersNotAvailable);
             }
         }
@@ -181,7 +209,7 @@ impl InstanceBuilder {
         self.c_name.set(c_name);
 
         self.app_info.set(*vk::ApplicationInfo::builder()
-            .api_version(vk_make_version!(1, 0, 0))
+            .api_version(self.api_version.unwrap_or(vk_make_version!(1, 0, 0)))
             .application_name(&self.c_name)
             .application_version(*self.version)
             .engine_name(&self.c_name)
@@ -195,7 +223,7 @@ impl InstanceBuilder {
             .application_info(&self.app_info)
             .enabled_extension_names(self.extensions.pointers())
             .enabled_layer_names(self.validation_layers.pointers())
-            .flags(vk::InstanceCreateFlags::empty());
+            .flags(self.flags);
 
         if *self.debug_mode {
             instance_create_info = instance_create_info
@@ -215,6 +243,10 @@ impl InstanceBuilder {
             )?
         };
 
+        debug!(
+            "created vulkan instance with extensions {:?}, layers {:?} and flags {:?}",
+            self.extensions.strings(), self.validation_layers.strings(), self.flags);
+
         self.instance.set(Instance {
             vk_instance
         });
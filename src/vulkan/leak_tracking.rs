@@ -0,0 +1,133 @@
+#[cfg(debug_assertions)]
+use std::{
+    backtrace::{
+        Backtrace,
+        BacktraceStatus
+    },
+    cell::RefCell,
+    collections::HashMap
+};
+use ash::vk;
+use crate::vulkan::handle::VulkanHandle;
+
+// Debug builds record which pipelines, buffers, images, semaphores and fences are alive,
+// with backtraces of where they were created if RUST_BACKTRACE or RUST_LIB_BACKTRACE is set.
+// Validation layers report objects leaked by the time the instance is destroyed,
+// but cannot tell what created them. Release builds track nothing.
+#[cfg(debug_assertions)]
+thread_local! {
+    static REGISTRY: RefCell<Registry> = RefCell::new(Registry::default());
+}
+
+#[cfg(debug_assertions)]
+#[derive(Default)]
+struct Registry {
+    live_objects: HashMap<(vk::ObjectType, u64), Option<Backtrace>>,
+    live_vulkan_states: usize
+}
+
+#[cfg(debug_assertions)]
+fn with_registry<T, F: FnOnce(&mut Registry) -> T>(access: F) -> Option<T> {
+    // The registry is gone once the thread is exiting, when nothing is worth tracking anymore
+    REGISTRY.try_with(|registry| access(&mut registry.borrow_mut())).ok()
+}
+
+pub(crate) fn track_creation<H: VulkanHandle>(object: &H) {
+    #[cfg(debug_assertions)]
+    {
+        let backtrace = Backtrace::capture();
+        let backtrace = if backtrace.status() == BacktraceStatus::Captured {
+            Some(backtrace)
+        }
+        else {
+            None
+        };
+
+        let key = (object.object_type(), object.raw_handle());
+        with_registry(|registry| registry.live_objects.insert(key, backtrace));
+    }
+
+    #[cfg(not(debug_assertions))]
+    let _ = object;
+}
+
+pub(crate) fn track_destruction<H: VulkanHandle>(object: &H) {
+    #[cfg(debug_assertions)]
+    {
+        let key = (object.object_type(), object.raw_handle());
+        with_registry(|registry| registry.live_objects.remove(&key));
+    }
+
+    #[cfg(not(debug_assertions))]
+    let _ = object;
+}
+
+// Leaks are reported once the last Vulkan state of the thread is dropped
+pub(crate) fn track_vulkan_state_creation() {
+    #[cfg(debug_assertions)]
+    with_registry(|registry| registry.live_vulkan_states += 1);
+}
+
+pub(crate) fn track_vulkan_state_destruction() {
+    #[cfg(debug_assertions)]
+    {
+        let is_last = with_registry(|registry| {
+            registry.live_vulkan_states -= 1;
+            registry.live_vulkan_states == 0
+        });
+
+        if is_last == Some(true) {
+            report_live_objects();
+            // Handles of a destroyed instance can be reused by the next one
+            with_registry(|registry| registry.live_objects.clear());
+        }
+    }
+}
+
+// Numbers of tracked objects of every type which are still alive, always empty in release builds
+pub fn live_object_counts() -> Vec<(vk::ObjectType, usize)> {
+    #[cfg(debug_assertions)]
+    return with_registry(|registry| live_counts(registry)).unwrap_or_default();
+
+    #[cfg(not(debug_assertions))]
+    Vec::new()
+}
+
+#[cfg(debug_assertions)]
+fn live_counts(registry: &Registry) -> Vec<(vk::ObjectType, usize)> {
+    let mut counts: HashMap<vk::ObjectType, usize> = HashMap::new();
+    for (object_type, _) in registry.live_objects.keys() {
+        *counts.entry(*object_type).or_insert(0) += 1;
+    }
+
+    let mut counts: Vec<_> = counts.into_iter().collect();
+    counts.sort_by_key(|(object_type, _)| object_type.as_raw());
+    counts
+}
+
+// Warns about every tracked object which is still alive. Wrappers keep the Vulkan state
+// alive through their logical device, so objects leaked e.g. by reference cycles prevent
+// the report at its destruction, calling this once rendering is done reports them anyway.
+pub fn report_live_objects() {
+    #[cfg(debug_assertions)]
+    with_registry(|registry| {
+        if registry.live_objects.is_empty() {
+            return;
+        }
+
+        warn!("{} Vulkan objects were not destroyed", registry.live_objects.len());
+        for (object_type, count) in live_counts(registry) {
+            warn!("    {:?}: {}", object_type, count);
+        }
+
+        for ((object_type, handle), backtrace) in &registry.live_objects {
+            if let Some(backtrace) = backtrace {
+                warn!("{:?} {:#x} was created at:\n{}", object_type, handle, backtrace);
+            }
+        }
+
+        if registry.live_objects.values().all(Option::is_none) {
+            warn!("set RUST_BACKTRACE=1 to find out where they were created");
+        }
+    });
+}
@@ -23,7 +23,13 @@ use crate::{
     vulkan::{
         VulkanError,
         VulkanResult,
+        handle::VulkanHandle,
         state::VulkanState,
+        device_features::RequestedFeatures,
+        external_memory::{
+            ExternalMemoryFn,
+            ExternalSemaphoreFn
+        },
         physical_device::{
             PhysicalDevice,
             QueueFamilyIndex,
@@ -36,9 +42,15 @@ use crate::{
 pub struct LogicalDevice {
     vk_logical_device: ash::Device,
     swapchain_loader: Rc<ash::extensions::khr::Swapchain>,
+    conditional_rendering_fn: Option<vk::ExtConditionalRenderingFn>,
+    push_descriptor_fn: Option<vk::KhrPushDescriptorFn>,
+    ray_tracing_fn: Option<vk::NvRayTracingFn>,
+    external_memory_fn: Option<ExternalMemoryFn>,
+    external_semaphore_fn: Option<ExternalSemaphoreFn>,
     device_queues: HashMap<QueueFamily, vk::Queue>,
-    // lifetime extenders
-    _physical_device: Rc<PhysicalDevice>
+    enabled_features: RequestedFeatures,
+    multiview_features: Option<vk::PhysicalDeviceMultiviewFeatures>,
+    physical_device: Rc<PhysicalDevice>
 }
 
 impl LogicalDevice {
@@ -48,14 +60,58 @@ impl LogicalDevice {
         }
     }
 
-    pub fn handle(&self) -> &ash::Device {
+    pub fn loader(&self) -> &ash::Device {
         &self.vk_logical_device
     }
 
+    pub fn physical_device(&self) -> &Rc<PhysicalDevice> {
+        &self.physical_device
+    }
+
     pub fn swapchain_loader(&self) -> Rc<ash::extensions::khr::Swapchain> {
         Rc::clone(&self.swapchain_loader)
     }
 
+    // Loaded only if VK_EXT_conditional_rendering is enabled
+    pub fn conditional_rendering_fn(&self) -> Option<&vk::ExtConditionalRenderingFn> {
+        self.conditional_rendering_fn.as_ref()
+    }
+
+    // Loaded only if VK_KHR_push_descriptor is enabled
+    pub fn push_descriptor_fn(&self) -> Option<&vk::KhrPushDescriptorFn> {
+        self.push_descriptor_fn.as_ref()
+    }
+
+    // Loaded only if VK_NV_ray_tracing is enabled and its properties can be queried,
+    // see `PhysicalDevice::ray_tracing_properties`
+    pub fn ray_tracing_fn(&self) -> Option<&vk::NvRayTracingFn> {
+        self.ray_tracing_fn.as_ref()
+    }
+
+    // Loaded only if `PhysicalDevice::supports_external_memory`
+    pub fn external_memory_fn(&self) -> Option<&ExternalMemoryFn> {
+        self.external_memory_fn.as_ref()
+    }
+
+    // Loaded only if `PhysicalDevice::supports_external_semaphores`
+    pub fn external_semaphore_fn(&self) -> Option<&ExternalSemaphoreFn> {
+        self.external_semaphore_fn.as_ref()
+    }
+
+    pub fn enabled_features(&self) -> &RequestedFeatures {
+        &self.enabled_features
+    }
+
+    // Enabled whenever the device supports it, see `PhysicalDevice::multiview_features`
+    pub fn is_multiview_enabled(&self) -> bool {
+        self.multiview_features.is_some()
+    }
+
+    // Geometry and tessellation shaders in pipelines of multiview render passes need these
+    pub fn multiview_features(&self) -> Option<&vk::PhysicalDeviceMultiviewFeatures> {
+        self.multiview_features.as_ref()
+    }
+
     pub fn device_queue(&self, queue_family: QueueFamily) -> VulkanResult<vk::Queue> {
         let device_queue = *self.device_queues.get(&queue_family)
             .ok_or(VulkanError::LogicalDeviceGetDeviceQueueError)?;
@@ -64,6 +120,14 @@ impl LogicalDevice {
     }
 }
 
+impl VulkanHandle for LogicalDevice {
+    type Handle = vk::Device;
+
+    fn handle(&self) -> Self::Handle {
+        self.vk_logical_device.handle()
+    }
+}
+
 impl std::ops::Deref for LogicalDevice {
     type Target = ash::Device;
 
@@ -93,13 +157,22 @@ pub struct LogicalDeviceBuilder {
     vulkan_state: BuilderRequirement<Rc<VulkanState>>,
     physical_device: BuilderRequirement<Rc<PhysicalDevice>>,
     queue_families: BuilderRequirement<Vec<QueueFamily>>,
+    features: RequestedFeatures,
 
     unique_queue_family_indices: BuilderInternal<Vec<QueueFamilyIndex>>,
     queue_create_infos: BuilderInternal<Vec<vk::DeviceQueueCreateInfo>>,
     device_extensions: BuilderInternal<PhysicalDeviceExtensions>,
+    vk_features: BuilderInternal<vk::PhysicalDeviceFeatures>,
+    conditional_rendering_features: vk::PhysicalDeviceConditionalRenderingFeaturesEXT,
+    multiview_features: Option<vk::PhysicalDeviceMultiviewFeatures>,
     logical_device_create_info: BuilderInternal<vk::DeviceCreateInfo>,
     vk_logical_device: BuilderInternal<ash::Device>,
     swapchain_loader: BuilderInternal<ash::extensions::khr::Swapchain>,
+    conditional_rendering_fn: BuilderInternal<Option<vk::ExtConditionalRenderingFn>>,
+    push_descriptor_fn: BuilderInternal<Option<vk::KhrPushDescriptorFn>>,
+    ray_tracing_fn: BuilderInternal<Option<vk::NvRayTracingFn>>,
+    external_memory_fn: BuilderInternal<Option<ExternalMemoryFn>>,
+    external_semaphore_fn: BuilderInternal<Option<ExternalSemaphoreFn>>,
     device_queues: BuilderInternal<HashMap<QueueFamily, vk::Queue>>,
 
     logical_device: BuilderProduct<LogicalDevice>
@@ -119,17 +192,21 @@ impl LogicalDeviceBuilder {
     }
 
     pub fn queue_families(mut self, queue_families: &[QueueFamily]) -> Self {
-        let mut queue_families_vec = Vec::with_capacity(queue_families.len());
-        unsafe {
-            queue_families_vec.set_len(queue_families.len());
-        }
+        self.queue_families.set(queue_families.to_vec());
+        self
+    }
 
-        queue_families_vec.copy_from_slice(queue_families);
-        self.queue_families.set(queue_families_vec);
+    // Defaults to no optional features
+    pub fn features(mut self, features: RequestedFeatures) -> Self {
+        self.features = features;
         self
     }
 
     pub fn build(mut self) -> VulkanResult<LogicalDevice> {
+        check_required_fields!(
+            self, VulkanError, "LogicalDeviceBuilder",
+            [vulkan_state, physical_device, queue_families]);
+
         self.get_ready_for_creation()?;
         self.create_logical_device();
 
@@ -140,9 +217,15 @@ impl LogicalDeviceBuilder {
         self.init_unique_queue_family_indices()?;
         self.init_queue_create_infos();
         self.init_device_extensions();
+        self.init_vk_features()?;
         self.init_logical_device_create_info();
         self.init_vk_logical_device()?;
         self.init_swapchain_loader();
+        self.init_conditional_rendering_fn();
+        self.init_push_descriptor_fn();
+        self.init_ray_tracing_fn();
+        self.init_external_memory_fn();
+        self.init_external_semaphore_fn();
         self.init_device_queues()?;
 
         Ok(())
@@ -157,7 +240,7 @@ impl LogicalDeviceBuilder {
         }
 
         self.unique_queue_family_indices.set(
-            Vec::from_iter(unique_queue_family_indices.into_iter())
+            Vec::from_iter(unique_queue_family_indices)
         );
 
         Ok(())
@@ -191,10 +274,38 @@ impl LogicalDeviceBuilder {
         self.device_extensions.set(device_extensions.clone());
     }
 
+    fn init_vk_features(&mut self) -> VulkanResult<()> {
+        let unsupported = self.features.unsupported(&self.physical_device.features());
+        if !unsupported.is_empty() {
+            return Err(VulkanError::DeviceFeaturesNotSupported {
+                features: unsupported.join(", ")
+            });
+        }
+
+        self.vk_features.set(self.features.vk_features());
+
+        Ok(())
+    }
+
+    // Devices supporting VK_EXT_conditional_rendering support its feature as well.
+    // Multiview is enabled together with whichever of its optional features are supported.
     fn init_logical_device_create_info(&mut self) {
-        let builder = vk::DeviceCreateInfo::builder()
+        self.multiview_features = self.physical_device.multiview_features();
+
+        let is_conditional_rendering_enabled = self.is_conditional_rendering_enabled();
+        let mut builder = vk::DeviceCreateInfo::builder()
             .queue_create_infos(self.queue_create_infos.as_slice())
-            .enabled_extension_names(self.device_extensions.pointers());
+            .enabled_extension_names(self.device_extensions.pointers())
+            .enabled_features(&self.vk_features);
+
+        if is_conditional_rendering_enabled {
+            self.conditional_rendering_features.conditional_rendering = vk::TRUE;
+            builder = builder.push_next(&mut self.conditional_rendering_features);
+        }
+
+        if let Some(multiview_features) = &mut self.multiview_features {
+            builder = builder.push_next(multiview_features);
+        }
 
         self.logical_device_create_info.set(*builder);
     }
@@ -218,11 +329,90 @@ impl LogicalDeviceBuilder {
         let vk_instance = self.vulkan_state.instance();
         let swapchain_loader =
             ash::extensions::khr::Swapchain::new(
-                vk_instance.handle(), self.vk_logical_device.as_ref());
+                vk_instance.loader(), self.vk_logical_device.as_ref());
 
         self.swapchain_loader.set(swapchain_loader);
     }
 
+    fn init_conditional_rendering_fn(&mut self) {
+        if !self.is_conditional_rendering_enabled() {
+            self.conditional_rendering_fn.set(None);
+            return;
+        }
+
+        let vk_instance = self.vulkan_state.instance();
+        let vk_device = self.vk_logical_device.handle();
+        let conditional_rendering_fn = vk::ExtConditionalRenderingFn::load(|name| unsafe {
+            std::mem::transmute(vk_instance.get_device_proc_addr(vk_device, name.as_ptr()))
+        });
+
+        self.conditional_rendering_fn.set(Some(conditional_rendering_fn));
+    }
+
+    fn init_push_descriptor_fn(&mut self) {
+        if !self.physical_device.is_extension_enabled(PhysicalDevice::PUSH_DESCRIPTOR_EXTENSION) {
+            self.push_descriptor_fn.set(None);
+            return;
+        }
+
+        let vk_instance = self.vulkan_state.instance();
+        let vk_device = self.vk_logical_device.handle();
+        let push_descriptor_fn = vk::KhrPushDescriptorFn::load(|name| unsafe {
+            std::mem::transmute(vk_instance.get_device_proc_addr(vk_device, name.as_ptr()))
+        });
+
+        self.push_descriptor_fn.set(Some(push_descriptor_fn));
+    }
+
+    fn init_ray_tracing_fn(&mut self) {
+        if self.physical_device.ray_tracing_properties().is_none() {
+            self.ray_tracing_fn.set(None);
+            return;
+        }
+
+        let vk_instance = self.vulkan_state.instance();
+        let vk_device = self.vk_logical_device.handle();
+        let ray_tracing_fn = vk::NvRayTracingFn::load(|name| unsafe {
+            std::mem::transmute(vk_instance.get_device_proc_addr(vk_device, name.as_ptr()))
+        });
+
+        self.ray_tracing_fn.set(Some(ray_tracing_fn));
+    }
+
+    fn init_external_memory_fn(&mut self) {
+        if !self.physical_device.supports_external_memory() {
+            self.external_memory_fn.set(None);
+            return;
+        }
+
+        let vk_instance = self.vulkan_state.instance();
+        let vk_device = self.vk_logical_device.handle();
+        let external_memory_fn = ExternalMemoryFn::load(|name| unsafe {
+            std::mem::transmute(vk_instance.get_device_proc_addr(vk_device, name.as_ptr()))
+        });
+
+        self.external_memory_fn.set(Some(external_memory_fn));
+    }
+
+    fn init_external_semaphore_fn(&mut self) {
+        if !self.physical_device.supports_external_semaphores() {
+            self.external_semaphore_fn.set(None);
+            return;
+        }
+
+        let vk_instance = self.vulkan_state.instance();
+        let vk_device = self.vk_logical_device.handle();
+        let external_semaphore_fn = ExternalSemaphoreFn::load(|name| unsafe {
+            std::mem::transmute(vk_instance.get_device_proc_addr(vk_device, name.as_ptr()))
+        });
+
+        self.external_semaphore_fn.set(Some(external_semaphore_fn));
+    }
+
+    fn is_conditional_rendering_enabled(&self) -> bool {
+        self.physical_device.is_extension_enabled(PhysicalDevice::CONDITIONAL_RENDERING_EXTENSION)
+    }
+
     fn init_device_queues(&mut self) -> VulkanResult<()> {
         let mut device_queues = HashMap::new();
         for queue_family in self.queue_families.as_slice() {
@@ -252,11 +442,26 @@ impl LogicalDeviceBuilder {
     }
 
     fn create_logical_device(&mut self) {
+        debug!(
+            "created logical device with queue families {:?} and extensions {:?}",
+            *self.queue_families, self.device_extensions.strings());
+
         self.logical_device.set(LogicalDevice {
             vk_logical_device: self.vk_logical_device.take(),
             swapchain_loader: Rc::new(self.swapchain_loader.take()),
+            conditional_rendering_fn: self.conditional_rendering_fn.take(),
+            push_descriptor_fn: self.push_descriptor_fn.take(),
+            ray_tracing_fn: self.ray_tracing_fn.take(),
+            external_memory_fn: self.external_memory_fn.take(),
+            external_semaphore_fn: self.external_semaphore_fn.take(),
             device_queues: self.device_queues.take(),
-            _physical_device: self.physical_device.take()
+            enabled_features: self.features,
+            // The chain it was created with is gone by now
+            multiview_features: self.multiview_features.map(|mut multiview_features| {
+                multiview_features.p_next = std::ptr::null_mut();
+                multiview_features
+            }),
+            physical_device: self.physical_device.take()
         });
     }
 }
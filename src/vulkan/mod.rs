@@ -14,6 +14,12 @@ custom_error!{pub VulkanError
         "failed to create vulkan instance: {source}",
     InstanceExtensionsCreationError {source: std::ffi::NulError} =
         "failed to create C-like nul-terminated string (invalid extension name): {source}",
+    EnumerateInstanceVersionError {result: vk::Result} =
+        "failed to enumerate instance version: {result}",
+    EnumerateInstanceExtensionsError {result: vk::Result} =
+        "failed to enumerate instance extensions: {result}",
+    EnumerateInstanceLayersError {result: vk::Result} =
+        "failed to enumerate instance layers: {result}",
     EnumeratePhysicalDevicesError {result: vk::Result}=
         "failed to enumerate GPUs",
     PhysicalDevicePropertiesError {result: vk::Result}=
@@ -28,36 +34,107 @@ custom_error!{pub VulkanError
         "failed to find a GPU fulfilling all criteria",
     LogicalDeviceCreateError {result: vk::Result} =
         "failed to create vulkan device: {result}",
+    DeviceFeaturesNotSupported {features: String} =
+        "physical device does not support requested features: {features}",
     LogicalDeviceGetDeviceQueueError =
         "logical device was asked about a queue it was not created with",
     LogicalDeviceWaitIdleError {result: vk::Result} =
         "failed to wait for logical device to become idle: {result}",
+    SurfacePlatformNotSupported =
+        "window belongs to a platform which has no supported Vulkan surface extension",
+    SurfaceCreateError {result: vk::Result} =
+        "failed to create window surface: {result}",
+    SurfaceWindowDestroyedError =
+        "surface was queried after its window had been destroyed",
     SwapchainCreateError {result: vk::Result} =
         "failed to create vulkan swapchain: {result}",
     SwapchainGetImagesError {result: vk::Result} =
         "failed to acquire swapchain images: {result}",
+    SwapchainImageUsageNotSupported {usage: String} =
+        "swapchain images cannot be created with usage {usage} on this surface",
     ShaderCreateError {result: vk::Result} =
         "failed to create shader: {result}",
     ShaderOpenFileError {error: std::io::Error} =
         "failed to open shader file: {error}",
+    ShaderReflectionError {reason: String} =
+        "failed to reflect shader module: {reason}",
+    ShaderPushConstantsMismatch {stage: String, shader_size: u32, pushed_size: usize} =
+        "{stage} shader's push constants end at byte {shader_size}, \
+        but the pipeline's push constant range for it ends at byte {pushed_size}",
+    ShaderVertexInputMismatch {location: u32} =
+        "vertex shader input at location {location} does not match \
+        the format of the pipeline's vertex attribute at that location",
     RenderPassCreateError {result: vk::Result} =
         "failed to create render pass: {result}",
     PipelineCreateError {result: vk::Result} =
         "failed to create pipeline: {result}",
     PipelineLayoutCreateError {result: vk::Result} =
         "failed to create pipeline layout: {result}",
+    PushConstantsTooLarge {size: u32, max_size: u32} =
+        "push constants take {size} bytes, but the device supports at most {max_size}; \
+        pass larger data through a uniform buffer instead",
+    PushConstantsMisaligned {offset: usize, size: usize} =
+        "push constant range at offset {offset} with size {size} is not aligned to 4 bytes",
+    PushConstantsStageInMultipleRanges {stage: String} =
+        "{stage} shader stage was given more than one push constant range",
     PipelineCreateVertexAttributeDescriptionError =
         "failed to create vertex attribute description for a pipeline",
+    PipelineColorAttachmentOutOfRange {attachment: u32, color_attachment_count: u32} =
+        "blend mode was set for color attachment {attachment}, \
+        but the pipeline has {color_attachment_count} color attachments",
+    PipelineIndependentBlendNotEnabled =
+        "color attachments of a pipeline are blended differently, \
+        but the independent_blend device feature is not enabled",
+    PipelineDepthClampNotEnabled =
+        "pipeline clamps depth, but the depth_clamp device feature is not enabled",
+    PipelineDepthBiasClampNotEnabled =
+        "pipeline clamps its depth bias, but the depth_bias_clamp device feature is not enabled",
+    PipelineParentNotDerivable =
+        "pipeline derives from a parent which was not created with derivatives allowed",
+    PipelineBatchParentOutOfRange {index: usize, parent: usize} =
+        "pipeline {index} of a batch derives from pipeline {parent}, which does not come before it",
+    PipelineBatchDevicesDiffer =
+        "pipelines of a batch have to be created on the same logical device",
+    PushDescriptorsNotEnabled =
+        "descriptor set layout is meant for push descriptors, \
+        but VK_KHR_push_descriptor is not enabled",
+    DescriptorSetFromPushLayout =
+        "descriptor sets cannot be allocated with a layout meant for push descriptors",
+    PushDescriptorsWithoutPushLayout =
+        "descriptors can only be pushed with a layout meant for push descriptors",
+    DescriptorSetLayoutCreateError {result: vk::Result} =
+        "failed to create descriptor set layout: {result}",
+    DescriptorPoolCreateError {result: vk::Result} =
+        "failed to create descriptor pool: {result}",
+    DescriptorSetAllocateError {result: vk::Result} =
+        "failed to allocate descriptor set: {result}",
+    DescriptorPoolResetError {result: vk::Result} =
+        "failed to reset descriptor pool: {result}",
+    DescriptorBindingTypeMismatch {binding: u32} =
+        "descriptor written to binding {binding} does not match \
+        the type declared for it in the set's layout",
+    ImageCreateError {error: vk_mem::Error} =
+        "failed to create image: {error}",
+    SamplerCreateError {result: vk::Result} =
+        "failed to create sampler: {result}",
     ImageViewCreateError {result: vk::Result} =
         "failed to create image view: {result}",
+    CubeImageNotSquare {width: u32, height: u32} =
+        "cube map faces have to be square, but they are {width}x{height}",
+    CubeImageLayerCount {layer_count: u32} =
+        "cube maps have 6 layers, but {layer_count} were requested",
     FramebuffersCreateError {result: vk::Result} =
         "failed to create framebuffers: {result}",
+    FramebufferAttachmentSizeMismatch =
+        "framebuffer attachments are not all of the same size",
     CommandPoolCreateError {result: vk::Result} =
         "failed to create command pool: {result}",
     CommandBufferAllocateError {result: vk::Result} =
         "failed to allocate command buffer: {result}",
     CommandBufferRecordError {result: vk::Result} =
         "failed to record command buffer: {result}",
+    CommandBufferNotSecondaryError =
+        "attempted to record a primary command buffer as a part of a render pass",
     SemaphoreCreateError {result: vk::Result} =
         "failed to create semaphore: {result}",
     FenceCreateError {result: vk::Result} =
@@ -69,22 +146,103 @@ custom_error!{pub VulkanError
     FenceWaitError {result: vk::Result} =
         "failed to wait for fence: {result}",
     FenceResetError {result: vk::Result} =
-        "failed to reset fence: {result}"
+        "failed to reset fence: {result}",
+    QueryPoolCreateError {result: vk::Result} =
+        "failed to create query pool: {result}",
+    QueryPoolResultsError {result: vk::Result} =
+        "failed to get query pool results: {result}",
+    AllocatorCreateError {error: vk_mem::Error} =
+        "failed to create memory allocator: {error}",
+    AllocatorStatisticsError {error: vk_mem::Error} =
+        "failed to calculate memory allocator statistics: {error}",
+    BufferCreateError {error: vk_mem::Error} =
+        "failed to create buffer: {error}",
+    BufferMemoryAccessError {error: vk_mem::Error} =
+        "failed to synchronize host access to buffer memory: {error}",
+    BufferNotHostMappedError =
+        "buffer memory was accessed from the host, but it is not mapped",
+    BufferWriteOutOfBoundsError =
+        "attempted to write past the end of a buffer",
+    MultiviewNotEnabled =
+        "multiview needs Vulkan 1.1 and a device supporting it",
+    MultiviewTooManyViews {view_count: u32, max_view_count: u32} =
+        "view mask covers {view_count} views, but the device supports at most {max_view_count}",
+    MultiviewGeometryShaderNotSupported =
+        "geometry shaders in multiview render passes are not supported by the device",
+    RayTracingNotEnabled =
+        "ray tracing needs VK_NV_ray_tracing, which is not enabled",
+    AccelerationStructureCreateError {result: vk::Result} =
+        "failed to create acceleration structure: {result}",
+    AccelerationStructureAllocateError {error: vk_mem::Error} =
+        "failed to allocate acceleration structure memory: {error}",
+    AccelerationStructureBindError {result: vk::Result} =
+        "failed to bind acceleration structure memory: {result}",
+    AccelerationStructureHandleError {result: vk::Result} =
+        "failed to get acceleration structure handle: {result}",
+    AccelerationStructureLevelMismatch =
+        "acceleration structure needs either geometries or a maximum instance count, not both",
+    AccelerationStructureInstanceCount {count: usize, max_count: u32} =
+        "{count} instances were given to a top level acceleration structure built for {max_count}",
+    RayTracingPipelineWithoutHitGroups =
+        "ray tracing pipeline has no miss shaders or no hit groups",
+    ShaderGroupHandlesError {result: vk::Result} =
+        "failed to get ray tracing shader group handles: {result}",
+    ExternalMemoryNotEnabled =
+        "external memory needs Vulkan 1.1 and the platform's external memory extension",
+    ExternalSemaphoresNotEnabled =
+        "external semaphores need Vulkan 1.1 and the platform's external semaphore extension",
+    ExternalMemoryTypeNotFound =
+        "no memory type is suitable for an image with external memory",
+    ExternalImageCreateError {result: vk::Result} =
+        "failed to create image with external memory: {result}",
+    ExternalMemoryAllocateError {result: vk::Result} =
+        "failed to allocate external image memory: {result}",
+    ExternalMemoryBindError {result: vk::Result} =
+        "failed to bind external image memory: {result}",
+    ExternalMemoryExportError {result: vk::Result} =
+        "failed to export image memory: {result}",
+    ImageMemoryNotExportable =
+        "image memory can be exported only from images built as exportable",
+    ExternalSemaphoreExportError {result: vk::Result} =
+        "failed to export semaphore: {result}",
+    ExternalSemaphoreImportError {result: vk::Result} =
+        "failed to import semaphore: {result}",
+    SemaphoreNotExportable =
+        "semaphore can be exported only if it was created as exportable",
+    BuilderMissingField {builder: &'static str, field: &'static str} =
+        "{builder} was built without setting required field `{field}`"
 }
 
 pub type VulkanResult<T> = Result<T, VulkanError>;
 
+pub mod handle;
+pub mod leak_tracking;
 pub mod state;
 pub mod instance;
-pub mod debug_utils;
+pub(crate) mod debug_utils;
 pub mod physical_device;
 pub mod logical_device;
+pub(crate) mod diagnostics;
+pub(crate) mod device_features;
 pub mod surface;
 pub mod swapchain;
 pub mod shader;
+#[cfg(feature = "shader-reflection")]
+pub mod shader_reflection;
 pub mod render_pass;
+pub mod descriptor;
 pub mod pipeline;
 pub mod framebuffers;
 pub mod command_pool;
 pub mod command_buffer;
 pub mod synchronization;
+pub mod barrier;
+pub mod queue_ownership;
+pub mod allocator;
+pub mod buffer;
+pub mod image;
+pub mod external_memory;
+pub mod sampler;
+pub mod query;
+pub mod acceleration_structure;
+pub mod ray_tracing;
@@ -7,8 +7,10 @@ use std::{
 use ash::{
     self,
     vk,
+    vk_make_version,
     version::{
-        InstanceV1_0
+        InstanceV1_0,
+        InstanceV1_1
     }
 };
 use crate::{
@@ -21,6 +23,7 @@ use crate::{
         self,
         VulkanError,
         VulkanResult,
+        handle::VulkanHandle,
         state::VulkanState
     }
 };
@@ -28,19 +31,43 @@ use crate::{
 pub struct PhysicalDevice {
     vulkan_state: Rc<VulkanState>,
     vk_physical_device: vk::PhysicalDevice,
+    properties: vk::PhysicalDeviceProperties,
     queue_family_indices: QueueFamilyIndices,
     requested_extensions: PhysicalDeviceExtensions
 }
 
 impl PhysicalDevice {
+    pub const MEMORY_BUDGET_EXTENSION: &'static str = "VK_EXT_memory_budget";
+    pub const CONDITIONAL_RENDERING_EXTENSION: &'static str = "VK_EXT_conditional_rendering";
+    pub const PUSH_DESCRIPTOR_EXTENSION: &'static str = "VK_KHR_push_descriptor";
+    pub const RAY_TRACING_EXTENSION: &'static str = "VK_NV_ray_tracing";
+    // Required by VK_NV_ray_tracing on devices without Vulkan 1.1
+    pub const MEMORY_REQUIREMENTS_2_EXTENSION: &'static str = "VK_KHR_get_memory_requirements2";
+    // Platform extensions for sharing memory and semaphores, see `vulkan::external_memory`
+    #[cfg(not(windows))]
+    pub const EXTERNAL_MEMORY_EXTENSION: &'static str = "VK_KHR_external_memory_fd";
+    #[cfg(windows)]
+    pub const EXTERNAL_MEMORY_EXTENSION: &'static str = "VK_KHR_external_memory_win32";
+    #[cfg(not(windows))]
+    pub const EXTERNAL_SEMAPHORE_EXTENSION: &'static str = "VK_KHR_external_semaphore_fd";
+    #[cfg(windows)]
+    pub const EXTERNAL_SEMAPHORE_EXTENSION: &'static str = "VK_KHR_external_semaphore_win32";
+    // Has to be enabled whenever a device supports it, e.g. on MoltenVK,
+    // which implements only a subset of Vulkan on top of Metal
+    pub const PORTABILITY_SUBSET_EXTENSION: &'static str = "VK_KHR_portability_subset";
+
     pub fn selector() -> PhysicalDeviceSelector {
         PhysicalDeviceSelector {
             ..Default::default()
         }
     }
 
-    pub fn handle(&self) -> vk::PhysicalDevice {
-        self.vk_physical_device
+    pub fn properties(&self) -> &vk::PhysicalDeviceProperties {
+        &self.properties
+    }
+
+    pub fn limits(&self) -> &vk::PhysicalDeviceLimits {
+        &self.properties.limits
     }
 
     pub fn queue_family_index(
@@ -54,10 +81,158 @@ impl PhysicalDevice {
         self.queue_family_indices.is_transfer_dedicated()
     }
 
+    pub fn features(&self) -> vk::PhysicalDeviceFeatures {
+        unsafe {
+            self.vulkan_state
+                .instance_loader()
+                .get_physical_device_features(self.vk_physical_device)
+        }
+    }
+
+    pub fn format_properties(&self, format: vk::Format) -> vk::FormatProperties {
+        unsafe {
+            self.vulkan_state
+                .instance_loader()
+                .get_physical_device_format_properties(self.vk_physical_device, format)
+        }
+    }
+
+    // Required extensions and the supported optional ones, all enabled on logical devices
     pub fn requested_extensions(&self) -> &PhysicalDeviceExtensions {
         &self.requested_extensions
     }
 
+    pub fn is_extension_enabled(&self, extension: &str) -> bool {
+        self.requested_extensions
+            .strings()
+            .iter()
+            .any(|enabled| enabled.as_bytes() == extension.as_bytes())
+    }
+
+    pub fn is_portability_subset(&self) -> bool {
+        self.is_extension_enabled(Self::PORTABILITY_SUBSET_EXTENSION)
+    }
+
+    // External memory and semaphores themselves are core in Vulkan 1.1,
+    // only their platform extensions are needed on top of it
+    pub fn supports_external_memory(&self) -> bool {
+        self.is_extension_enabled(Self::EXTERNAL_MEMORY_EXTENSION)
+            && self.vulkan_state.supports_api_version(1, 1)
+            && self.properties.api_version >= vk_make_version!(1, 1, 0)
+    }
+
+    pub fn supports_external_semaphores(&self) -> bool {
+        self.is_extension_enabled(Self::EXTERNAL_SEMAPHORE_EXTENSION)
+            && self.vulkan_state.supports_api_version(1, 1)
+            && self.properties.api_version >= vk_make_version!(1, 1, 0)
+    }
+
+    pub fn memory_properties(&self) -> vk::PhysicalDeviceMemoryProperties {
+        unsafe {
+            self.vulkan_state
+                .instance_loader()
+                .get_physical_device_memory_properties(self.vk_physical_device)
+        }
+    }
+
+    // Budgets and usages of every memory heap, counting other processes' allocations too.
+    // Needs VK_EXT_memory_budget and Vulkan 1.1 on both the instance and the device.
+    pub fn memory_budget(&self) -> Option<vk::PhysicalDeviceMemoryBudgetPropertiesEXT> {
+        let is_supported = self.is_extension_enabled(Self::MEMORY_BUDGET_EXTENSION)
+            && self.vulkan_state.supports_api_version(1, 1)
+            && self.properties.api_version >= vk_make_version!(1, 1, 0);
+
+        if !is_supported {
+            return None;
+        }
+
+        let mut memory_budget = vk::PhysicalDeviceMemoryBudgetPropertiesEXT::default();
+        let mut memory_properties = vk::PhysicalDeviceMemoryProperties2::builder()
+            .push_next(&mut memory_budget)
+            .build();
+
+        unsafe {
+            self.vulkan_state
+                .instance_loader()
+                .get_physical_device_memory_properties2(
+                    self.vk_physical_device, &mut memory_properties);
+        }
+
+        Some(memory_budget)
+    }
+
+    // Core in Vulkan 1.1, which both the instance and the device need to support it
+    pub fn multiview_features(&self) -> Option<vk::PhysicalDeviceMultiviewFeatures> {
+        let is_supported = self.vulkan_state.supports_api_version(1, 1)
+            && self.properties.api_version >= vk_make_version!(1, 1, 0);
+
+        if !is_supported {
+            return None;
+        }
+
+        // The bindings can neither chain onto features nor query them with a wrapper
+        let mut multiview_features = vk::PhysicalDeviceMultiviewFeatures::default();
+        let mut features = vk::PhysicalDeviceFeatures2 {
+            p_next: &mut multiview_features as *mut _ as *mut std::ffi::c_void,
+            ..Default::default()
+        };
+
+        unsafe {
+            self.vulkan_state
+                .instance_loader()
+                .fp_v1_1()
+                .get_physical_device_features2(self.vk_physical_device, &mut features);
+        }
+
+        if multiview_features.multiview != vk::TRUE {
+            return None;
+        }
+
+        Some(multiview_features)
+    }
+
+    // Most views a render pass' view mask can have, if multiview is supported
+    pub fn max_multiview_view_count(&self) -> Option<u32> {
+        self.multiview_features()?;
+
+        let mut multiview_properties = vk::PhysicalDeviceMultiviewProperties::default();
+        let mut properties = vk::PhysicalDeviceProperties2::builder()
+            .push_next(&mut multiview_properties)
+            .build();
+
+        unsafe {
+            self.vulkan_state
+                .instance_loader()
+                .get_physical_device_properties2(self.vk_physical_device, &mut properties);
+        }
+
+        Some(multiview_properties.max_multiview_view_count)
+    }
+
+    // Shader group handle sizes and alignments of shader binding tables, among others.
+    // Needs VK_NV_ray_tracing and Vulkan 1.1 on the instance.
+    pub fn ray_tracing_properties(&self) -> Option<vk::PhysicalDeviceRayTracingPropertiesNV> {
+        let is_supported = self.is_extension_enabled(Self::RAY_TRACING_EXTENSION)
+            && self.vulkan_state.supports_api_version(1, 1);
+
+        if !is_supported {
+            return None;
+        }
+
+        let mut ray_tracing_properties = vk::PhysicalDeviceRayTracingPropertiesNV::default();
+        let mut properties = vk::PhysicalDeviceProperties2::builder()
+            .push_next(&mut ray_tracing_properties)
+            .build();
+
+        unsafe {
+            self.vulkan_state
+                .instance_loader()
+                .get_physical_device_properties2(self.vk_physical_device, &mut properties);
+        }
+
+        Some(ray_tracing_properties)
+    }
+
     pub fn surface_properties(
         &self, surface: &vulkan::surface::Surface
     ) -> VulkanResult<PhysicalDeviceSurfaceProperties> {
@@ -119,11 +294,11 @@ impl PhysicalDevice {
     }
 }
 
-impl std::ops::Deref for PhysicalDevice {
-    type Target = vk::PhysicalDevice;
+impl VulkanHandle for PhysicalDevice {
+    type Handle = vk::PhysicalDevice;
 
-    fn deref(&self) -> &Self::Target {
-        &self.vk_physical_device
+    fn handle(&self) -> Self::Handle {
+        self.vk_physical_device
     }
 }
 
@@ -133,6 +308,9 @@ pub struct PhysicalDeviceSelector {
     required_queue_families: BuilderRequirement<HashSet<QueueFamily>>,
     compatible_surface: BuilderRequirement<Rc<vulkan::surface::Surface>>,
     required_extensions: Option<PhysicalDeviceExtensions>,
+    optional_extensions: Vec<String>,
+    preferred_device_type: Option<vk::PhysicalDeviceType>,
+    required_device: Option<vk::PhysicalDevice>,
 
     devices: BuilderInternal<Vec<vk::PhysicalDevice>>,
     selected_device: BuilderInternal<vk::PhysicalDevice>,
@@ -142,13 +320,15 @@ pub struct PhysicalDeviceSelector {
 }
 
 impl PhysicalDeviceSelector {
+    pub const DEVICE_TYPE_ENVIRONMENT_VARIABLE: &'static str = "MAGMACRAFT_GPU";
+
     pub fn vulkan_state(mut self, state: Rc<VulkanState>) -> Self {
         self.vulkan_state.set(state);
         self
     }
 
     pub fn queue_families(mut self, families: &[QueueFamily]) -> Self {
-        let families = HashSet::from_iter(families.to_owned().into_iter());
+        let families = HashSet::from_iter(families.to_owned());
         self.required_queue_families.set(families);
         self
     }
@@ -163,49 +343,138 @@ impl PhysicalDeviceSelector {
         self
     }
 
+    // Enabled only if the selected device supports it, without affecting the selection
+    pub fn optional_device_extension(mut self, extension: &str) -> Self {
+        self.optional_extensions.push(extension.to_owned());
+        self
+    }
+
+    // Suitable devices of the preferred type are selected over other ones,
+    // defaults to discrete GPUs
+    pub fn preferred_device_type(mut self, device_type: vk::PhysicalDeviceType) -> Self {
+        self.preferred_device_type = Some(device_type);
+        self
+    }
+
+    // Only the given device is considered, e.g. the one an OpenXR runtime renders with
+    pub fn required_device(mut self, device: vk::PhysicalDevice) -> Self {
+        self.required_device = Some(device);
+        self
+    }
+
     pub fn select(mut self) -> VulkanResult<PhysicalDevice> {
+        check_required_fields!(
+            self, VulkanError, "PhysicalDeviceSelector",
+            [vulkan_state, required_queue_families, compatible_surface]);
+
+        self.apply_device_type_override();
         self.get_ready_for_physical_device_creation()?;
+        self.add_supported_optional_extensions()?;
+        self.add_portability_subset_extension()?;
         self.create_physical_device();
 
         Ok(self.physical_device.unwrap())
     }
 
-    pub fn get_ready_for_physical_device_creation(&mut self) -> VulkanResult<()> {
+    fn get_ready_for_physical_device_creation(&mut self) -> VulkanResult<()> {
         self.init_available_devices()?;
         self.select_suitable_device()?;
 
+        if !self.selected_device.is_set() {
+            return Err(VulkanError::SuitableDeviceNotFound);
+        }
+
         Ok(())
     }
 
+    // Lets the integrated or the discrete GPU be forced on hybrid graphics laptops,
+    // where the default choice may not be able to present to the window
+    fn apply_device_type_override(&mut self) {
+        let value = match std::env::var(Self::DEVICE_TYPE_ENVIRONMENT_VARIABLE) {
+            Ok(value) => value,
+            Err(_) => return
+        };
+
+        match value.trim().to_lowercase().as_str() {
+            "integrated" => {
+                self.preferred_device_type = Some(vk::PhysicalDeviceType::INTEGRATED_GPU);
+            },
+            "discrete" => {
+                self.preferred_device_type = Some(vk::PhysicalDeviceType::DISCRETE_GPU);
+            },
+            _ => {
+                warn!(
+                    "ignoring {}={}, expected integrated or discrete",
+                    Self::DEVICE_TYPE_ENVIRONMENT_VARIABLE, value);
+                return;
+            }
+        }
+
+        info!(
+            "preferring {} GPUs because of {}",
+            value.trim().to_lowercase(), Self::DEVICE_TYPE_ENVIRONMENT_VARIABLE);
+    }
+
     fn init_available_devices(&mut self) -> VulkanResult<()> {
-        let devices = unsafe { self.vulkan_state
+        let mut devices = unsafe { self.vulkan_state
             .instance()
             .enumerate_physical_devices()
             .map_err(|result| VulkanError::EnumeratePhysicalDevicesError {result})?
         };
 
+        if let Some(required_device) = self.required_device {
+            devices.retain(|device| *device == required_device);
+        }
+
         self.devices.set(devices);
 
         Ok(())
     }
 
     fn select_suitable_device(&mut self) -> VulkanResult<()> {
+        let preferred_device_type = self.preferred_device_type
+            .unwrap_or(vk::PhysicalDeviceType::DISCRETE_GPU);
+
         for device in self.devices.as_ref() {
-            if self.is_device_suitable(*device)? {
+            let properties = self.device_properties(*device);
+            trace!(
+                "considering physical device {} ({:?})",
+                Self::device_name(&properties), properties.device_type);
+
+            if !self.is_device_suitable(*device)? {
+                continue;
+            }
+
+            let is_preferred = properties.device_type == preferred_device_type;
+            if !self.selected_device.is_set() || is_preferred {
+                debug!("selected physical device {}", Self::device_name(&properties));
                 self.selected_device.set(*device);
                 let queue_family_indices = self.queue_family_indices(*device);
                 self.queue_family_indices.set(queue_family_indices);
             }
 
-            // If selected device is a discrete GPU, it's good enough
-            if self.is_device_discrete(*device) {
+            // A suitable device of the preferred type is good enough
+            if is_preferred {
                 return Ok(());
             }
         }
 
+        if let Some(device_type) = self.preferred_device_type {
+            warn!("no suitable {:?} physical device found, using another one", device_type);
+        }
+
         Ok(())
     }
 
+    fn device_name(properties: &vk::PhysicalDeviceProperties) -> std::borrow::Cow<'_, str> {
+        let device_name_pointer =
+            &properties.device_name as *const std::os::raw::c_char;
+
+        unsafe {
+            std::ffi::CStr::from_ptr(device_name_pointer).to_string_lossy()
+        }
+    }
+
     fn is_device_suitable(&self, device: vk::PhysicalDevice) -> VulkanResult<bool> {
         let is_suitable =
             self.are_required_queue_families_supported(device) &&
@@ -248,7 +517,7 @@ impl PhysicalDeviceSelector {
         if let Some(required_extensions) = &self.required_extensions {
             let are_extensions_supported = Self::are_extensions_supported(
                 &device_extension_properties,
-                &required_extensions);
+                required_extensions);
 
             return Ok(are_extensions_supported);
         }
@@ -256,6 +525,61 @@ impl PhysicalDeviceSelector {
         Ok(true)
     }
 
+    fn add_supported_optional_extensions(&mut self) -> VulkanResult<()> {
+        if self.optional_extensions.is_empty() {
+            return Ok(());
+        }
+
+        let device_extension_properties =
+            self.device_extensions_properties(*self.selected_device)?;
+        let optional_extensions = std::mem::take(&mut self.optional_extensions);
+        let required_extensions = self.required_extensions
+            .get_or_insert_with(PhysicalDeviceExtensions::new);
+
+        for optional_extension in optional_extensions {
+            // Strings with interior null bytes cannot name any extension
+            let is_supported = std::ffi::CString::new(optional_extension.as_bytes())
+                .map(|name| Self::is_extension_supported(&device_extension_properties, &name))
+                .unwrap_or(false);
+
+            if is_supported {
+                required_extensions.push(&optional_extension);
+            }
+            else {
+                info!("optional device extension {} is not supported", optional_extension);
+            }
+        }
+
+        Ok(())
+    }
+
+    // Enabled regardless of what was requested, as devices supporting it require it
+    fn add_portability_subset_extension(&mut self) -> VulkanResult<()> {
+        let device_extension_properties =
+            self.device_extensions_properties(*self.selected_device)?;
+        let extension = PhysicalDevice::PORTABILITY_SUBSET_EXTENSION;
+        let name = std::ffi::CString::new(extension).unwrap();
+
+        if !Self::is_extension_supported(&device_extension_properties, &name) {
+            return Ok(());
+        }
+
+        let required_extensions = self.required_extensions
+            .get_or_insert_with(PhysicalDeviceExtensions::new);
+        let is_requested = required_extensions
+            .strings()
+            .iter()
+            .any(|requested| requested.as_bytes() == extension.as_bytes());
+
+        if !is_requested {
+            required_extensions.push(extension);
+        }
+
+        info!("selected device implements only a portability subset of Vulkan");
+
+        Ok(())
+    }
+
     fn device_extensions_properties(
         &self,
         device: vk::PhysicalDevice
@@ -274,7 +598,7 @@ impl PhysicalDeviceSelector {
         required_extensions: &PhysicalDeviceExtensions
     ) -> bool {
         for required_extension in required_extensions.strings() {
-            if !Self::is_extension_supported(&device_extension_properties, &required_extension) {
+            if !Self::is_extension_supported(device_extension_properties, required_extension) {
                 return false;
             }
         }
@@ -308,40 +632,26 @@ impl PhysicalDeviceSelector {
         }
     }
 
-    fn is_device_discrete(&self, device: vk::PhysicalDevice) -> bool {
-        let properties = self.device_properties(device);
-
-        properties.device_type == vk::PhysicalDeviceType::DISCRETE_GPU
-    }
-
     fn device_properties(&self, device: vk::PhysicalDevice) -> vk::PhysicalDeviceProperties {
-        let properties = unsafe {
+        unsafe {
             self.vulkan_state
                 .instance()
                 .get_physical_device_properties(device)
-        };
-
-        properties
-    }
-
-    fn device_features(&self, device: vk::PhysicalDevice) -> vk::PhysicalDeviceFeatures {
-        let features = unsafe {
-            self.vulkan_state
-                .instance()
-                .get_physical_device_features(device)
-        };
-
-        features
+        }
     }
 
     fn create_physical_device(&mut self) {
         let requested_extensions =
             self.required_extensions.take()
-            .unwrap_or(PhysicalDeviceExtensions::new());
+            .unwrap_or_default();
+
+        let vk_physical_device = self.selected_device.take();
+        let properties = self.device_properties(vk_physical_device);
 
         self.physical_device.set(PhysicalDevice {
             vulkan_state: self.vulkan_state.take(),
-            vk_physical_device: self.selected_device.take(),
+            vk_physical_device,
+            properties,
             queue_family_indices: self.queue_family_indices.take(),
             requested_extensions
         });
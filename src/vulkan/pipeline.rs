@@ -1,5 +1,4 @@
 use std::{
-    collections::HashMap,
     convert::TryFrom,
     rc::Rc
 };
@@ -16,25 +15,58 @@ use crate::{
     vulkan::{
         VulkanError,
         VulkanResult,
+        handle::VulkanHandle,
+        leak_tracking,
         logical_device::LogicalDevice,
         swapchain::Swapchain,
         shader::{
             GeometryShader,
             VertexShader,
             FragmentShader,
+            ComputeShader,
             ShaderStageBuilder,
             ShaderStage
         },
-        render_pass::RenderPass
+        render_pass::RenderPass,
+        descriptor::DescriptorSetLayout
     }
 };
+#[cfg(feature = "shader-reflection")]
+use crate::vulkan::shader_reflection::ShaderReflection;
 
 pub struct Pipeline {
     vk_pipeline: vk::Pipeline,
     pipeline_layout: vk::PipelineLayout,
+    bind_point: vk::PipelineBindPoint,
+    push_constant_ranges: Vec<PushConstantRange>,
+    blend_mode: BlendMode,
+    allows_derivatives: bool,
+    // lifetime extenders
+    _descriptor_set_layouts: Vec<Rc<DescriptorSetLayout>>,
     logical_device: Rc<LogicalDevice>
 }
 
+// Block of push constants visible to the given stages, located at `offset` bytes
+// into the pipeline's push constants storage
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct PushConstantRange {
+    pub stages: Vec<ShaderStage>,
+    pub offset: usize,
+    pub size: usize
+}
+
+impl PushConstantRange {
+    pub fn stage_flags(&self) -> vk::ShaderStageFlags {
+        self.stages
+            .iter()
+            .fold(vk::ShaderStageFlags::empty(), |flags, stage| flags | (*stage).into())
+    }
+
+    pub fn end(&self) -> usize {
+        self.offset + self.size
+    }
+}
+
 impl Pipeline {
     pub fn builder<'a>() -> PipelineBuilder<'a> {
         PipelineBuilder {
@@ -42,17 +74,103 @@ impl Pipeline {
         }
     }
 
-    pub fn handle(&self) -> vk::Pipeline {
-        self.vk_pipeline
+    // Compute pipelines are bound, given descriptor sets and dispatched with the same
+    // recorder methods as graphics ones
+    pub fn compute_builder<'a>() -> ComputePipelineBuilder<'a> {
+        ComputePipelineBuilder {
+            ..Default::default()
+        }
     }
 
     pub fn layout(&self) -> vk::PipelineLayout {
         self.pipeline_layout
     }
+
+    pub fn bind_point(&self) -> vk::PipelineBindPoint {
+        self.bind_point
+    }
+
+    pub fn blend_mode(&self) -> BlendMode {
+        self.blend_mode
+    }
+
+    pub fn allows_derivatives(&self) -> bool {
+        self.allows_derivatives
+    }
+
+    pub fn push_constant_range(&self, stage: ShaderStage) -> Option<&PushConstantRange> {
+        self.push_constant_ranges
+            .iter()
+            .find(|range| range.stages.contains(&stage))
+    }
+
+    // Takes ownership of the pipeline and its layout, for pipelines created
+    // without `PipelineBuilder`
+    pub(crate) fn from_raw_parts(
+        (vk_pipeline, pipeline_layout): (vk::Pipeline, vk::PipelineLayout),
+        bind_point: vk::PipelineBindPoint,
+        push_constant_ranges: Vec<PushConstantRange>,
+        descriptor_set_layouts: Vec<Rc<DescriptorSetLayout>>,
+        logical_device: Rc<LogicalDevice>
+    ) -> Self {
+        let pipeline = Self {
+            vk_pipeline,
+            pipeline_layout,
+            bind_point,
+            push_constant_ranges,
+            blend_mode: BlendMode::default(),
+            allows_derivatives: false,
+            _descriptor_set_layouts: descriptor_set_layouts,
+            logical_device
+        };
+
+        leak_tracking::track_creation(&pipeline);
+        pipeline
+    }
+}
+
+// Layout of pipelines built without `PipelineBuilder`, e.g. compute and ray tracing ones
+pub(crate) fn create_pipeline_layout(
+    logical_device: &LogicalDevice,
+    descriptor_set_layouts: &[Rc<DescriptorSetLayout>],
+    push_constant_ranges: &[PushConstantRange]
+) -> VulkanResult<vk::PipelineLayout> {
+    let push_constant_ranges: Vec<vk::PushConstantRange> = push_constant_ranges
+        .iter()
+        .map(|range| {
+            vk::PushConstantRange::builder()
+                .stage_flags(range.stage_flags())
+                .offset(range.offset as u32)
+                .size(range.size as u32)
+                .build()
+        })
+        .collect();
+
+    let set_layouts: Vec<vk::DescriptorSetLayout> = descriptor_set_layouts
+        .iter()
+        .map(|layout| layout.handle())
+        .collect();
+
+    let pipeline_layout_create_info = vk::PipelineLayoutCreateInfo::builder()
+        .set_layouts(&set_layouts)
+        .push_constant_ranges(&push_constant_ranges);
+
+    unsafe {
+        logical_device.create_pipeline_layout(&pipeline_layout_create_info, None)
+    }.map_err(|result| VulkanError::PipelineLayoutCreateError {result})
+}
+
+impl VulkanHandle for Pipeline {
+    type Handle = vk::Pipeline;
+
+    fn handle(&self) -> Self::Handle {
+        self.vk_pipeline
+    }
 }
 
 impl Drop for Pipeline {
     fn drop(&mut self) {
+        leak_tracking::track_destruction(self);
         unsafe {
             self.logical_device.destroy_pipeline(self.vk_pipeline, None);
             self.logical_device.destroy_pipeline_layout(self.pipeline_layout, None);
@@ -69,9 +187,27 @@ pub struct PipelineBuilder<'a> {
     swapchain: BuilderRequirement<Rc<Swapchain>>,
     render_pass: BuilderRequirement<Rc<RenderPass>>,
     subpass: BuilderRequirement<u32>,
-    push_constants_sizes: Option<HashMap<ShaderStage, usize>>,
+    alpha_to_coverage: bool,
+    blend_mode: BlendMode,
+    cull_mode: CullMode,
+    color_attachment_count: Option<u32>,
+    attachment_blend_modes: Vec<(u32, BlendMode)>,
+    depth_test: bool,
+    depth_write: bool,
+    depth_bias: Option<(f32, f32)>,
+    depth_bias_clamp: f32,
+    dynamic_depth_bias: bool,
+    depth_clamp: bool,
+    topology: Option<vk::PrimitiveTopology>,
+    push_constant_ranges: Vec<PushConstantRange>,
+    descriptor_set_layouts: Vec<Rc<DescriptorSetLayout>>,
     vertex_binding_description_strides: Vec<usize>,
     vertex_attribute_description_infos: Vec<VertexAttributeDescriptionInfo>,
+    allow_derivatives: bool,
+    parent: Option<&'a Pipeline>,
+    batch_parent: Option<usize>,
+
+    shader_stage_create_infos: BuilderInternal<Vec<vk::PipelineShaderStageCreateInfo>>,
 
     vertex_binding_descriptions: BuilderInternal<Vec<vk::VertexInputBindingDescription>>,
     vertex_attribute_descriptions: BuilderInternal<Vec<vk::VertexInputAttributeDescription>>,
@@ -87,9 +223,14 @@ pub struct PipelineBuilder<'a> {
 
     multisample_state_create_info: BuilderInternal<vk::PipelineMultisampleStateCreateInfo>,
 
-    color_blend_attachment_state: BuilderInternal<vk::PipelineColorBlendAttachmentState>,
+    depth_stencil_state_create_info: BuilderInternal<vk::PipelineDepthStencilStateCreateInfo>,
+
+    color_blend_attachment_states: BuilderInternal<Vec<vk::PipelineColorBlendAttachmentState>>,
     color_blend_state_create_info: BuilderInternal<vk::PipelineColorBlendStateCreateInfo>,
 
+    dynamic_states: BuilderInternal<Vec<vk::DynamicState>>,
+    dynamic_state_create_info: BuilderInternal<vk::PipelineDynamicStateCreateInfo>,
+
     pipeline_layout: BuilderInternal<vk::PipelineLayout>,
 
     vk_pipeline: BuilderInternal<vk::Pipeline>,
@@ -99,6 +240,7 @@ pub struct PipelineBuilder<'a> {
 
 impl<'a> PipelineBuilder<'a> {
     const MAX_SHADER_STAGES: usize = 3;
+    pub(crate) const PUSH_CONSTANTS_ALIGNMENT: usize = 4;
 
     pub fn logical_device(mut self, logical_device: Rc<LogicalDevice>) -> Self {
         self.logical_device.set(logical_device);
@@ -135,6 +277,88 @@ impl<'a> PipelineBuilder<'a> {
         self
     }
 
+    // Lets cutout textures be antialiased by turning fragment alpha into MSAA sample coverage
+    pub fn alpha_to_coverage(mut self, alpha_to_coverage: bool) -> Self {
+        self.alpha_to_coverage = alpha_to_coverage;
+        self
+    }
+
+    // Of all color attachments without a blend mode of their own
+    pub fn blend_mode(mut self, blend_mode: BlendMode) -> Self {
+        self.blend_mode = blend_mode;
+        self
+    }
+
+    // Attachments blended differently from each other need the independent_blend feature
+    pub fn attachment_blend_mode(mut self, attachment: u32, blend_mode: BlendMode) -> Self {
+        self.attachment_blend_modes.retain(|(blended, _)| *blended != attachment);
+        self.attachment_blend_modes.push((attachment, blend_mode));
+        self
+    }
+
+    pub fn cull_mode(mut self, cull_mode: CullMode) -> Self {
+        self.cull_mode = cull_mode;
+        self
+    }
+
+    // Defaults to 1, depth only passes have none
+    pub fn color_attachment_count(mut self, color_attachment_count: u32) -> Self {
+        self.color_attachment_count = Some(color_attachment_count);
+        self
+    }
+
+    // Fragments closer or as close as the stored depth pass, the render pass needs
+    // a depth attachment for the test to have any effect
+    pub fn depth_test(mut self, depth_test: bool) -> Self {
+        self.depth_test = depth_test;
+        self
+    }
+
+    pub fn depth_write(mut self, depth_write: bool) -> Self {
+        self.depth_write = depth_write;
+        self
+    }
+
+    // Pushes depths away by a constant and a slope scaled amount, against shadow acne
+    pub fn depth_bias(mut self, constant_factor: f32, slope_factor: f32) -> Self {
+        self.depth_bias = Some((constant_factor, slope_factor));
+        self
+    }
+
+    // Limits the depth bias of steep slopes, which would otherwise detach shadows from their
+    // casters (peter-panning). Any value but 0 needs the depth_bias_clamp feature.
+    pub fn depth_bias_clamp(mut self, clamp: f32) -> Self {
+        self.depth_bias_clamp = clamp;
+        self
+    }
+
+    // Enables depth bias with its factors set by `CommandBufferRecorder::set_depth_bias`
+    // instead of fixed ones, e.g. to tune them per shadow cascade with a single pipeline
+    pub fn dynamic_depth_bias(mut self, dynamic_depth_bias: bool) -> Self {
+        self.dynamic_depth_bias = dynamic_depth_bias;
+        self
+    }
+
+    // Clamps depths to the viewport's range instead of clipping primitives outside of it,
+    // so that shadow casters behind the light's near plane still cast shadows.
+    // Needs the depth_clamp feature.
+    pub fn depth_clamp(mut self, depth_clamp: bool) -> Self {
+        self.depth_clamp = depth_clamp;
+        self
+    }
+
+    // Layouts are assigned set numbers in the order they are added
+    // Defaults to triangle lists
+    pub fn topology(mut self, topology: vk::PrimitiveTopology) -> Self {
+        self.topology = Some(topology);
+        self
+    }
+
+    pub fn descriptor_set_layout(mut self, layout: Rc<DescriptorSetLayout>) -> Self {
+        self.descriptor_set_layouts.push(layout);
+        self
+    }
+
     pub fn vertex_binding_stride(mut self, vertex_binding_description_stride: usize) -> Self {
         self.vertex_binding_description_strides.push(vertex_binding_description_stride);
         self
@@ -155,37 +379,151 @@ impl<'a> PipelineBuilder<'a> {
         self
     }
 
-    pub fn push_constants_size(mut self, shader: ShaderStage, size: usize) -> Self {
-        match self.push_constants_sizes.as_mut() {
-            Some(sizes) => {
-                sizes.insert(shader, size);
-            },
-            None => {
-                let mut push_constants_sizes = HashMap::with_capacity(1);
-                push_constants_sizes.insert(shader, size);
-                self.push_constants_sizes = Some(push_constants_sizes);
-            }
+    // Lets other pipelines be created as derivatives of this one
+    pub fn allow_derivatives(mut self, allow_derivatives: bool) -> Self {
+        self.allow_derivatives = allow_derivatives;
+        self
+    }
+
+    // Derivatives are cheaper to create and to switch to from their parent if they differ
+    // only slightly, e.g. by blend mode. The parent has to allow derivatives.
+    pub fn parent(mut self, parent: &'a Pipeline) -> Self {
+        self.parent = Some(parent);
+        self.batch_parent = None;
+        self
+    }
+
+    // Derives from the pipeline created by an earlier builder of the same `build_batch` call,
+    // which has to allow derivatives
+    pub fn batch_parent(mut self, index: usize) -> Self {
+        self.batch_parent = Some(index);
+        self.parent = None;
+        self
+    }
+
+    // Places push constants of a single stage right after all previously added ranges
+    pub fn push_constants_size(self, shader: ShaderStage, size: usize) -> Self {
+        let offset = self.push_constant_ranges
+            .iter()
+            .map(|range| range.end())
+            .max()
+            .unwrap_or(0);
+
+        self.push_constants_range(&[shader], offset, size)
+    }
+
+    // A single range can be shared by several stages, e.g. vertex and fragment,
+    // but every stage can be given at most one range
+    pub fn push_constants_range(
+        mut self,
+        shaders: &[ShaderStage],
+        offset: usize,
+        size: usize
+    ) -> Self {
+        if size > 0 {
+            self.push_constant_ranges.push(PushConstantRange {
+                stages: shaders.to_vec(),
+                offset,
+                size
+            });
         }
 
         self
     }
 
-    pub fn build(mut self) -> VulkanResult<Pipeline> {
-        self.get_ready_for_creation()?;
-        self.create_pipeline();
+    pub fn build(self) -> VulkanResult<Pipeline> {
+        let mut pipelines = Self::build_batch(vec![self])?;
+        Ok(pipelines.remove(0))
+    }
 
-        Ok(self.pipeline.unwrap())
+    // Creates the pipelines with a single vkCreateGraphicsPipelines call,
+    // which lets drivers share the work between similar ones
+    pub fn build_batch(mut builders: Vec<Self>) -> VulkanResult<Vec<Pipeline>> {
+        if builders.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        for index in 0..builders.len() {
+            let (earlier, remaining) = builders.split_at_mut(index);
+            let result = remaining[0].get_ready_for_creation(index, earlier);
+            if let Err(error) = result {
+                Self::destroy_pipeline_layouts(&mut builders[..index]);
+                return Err(error);
+            }
+        }
+
+        if let Err(error) = Self::init_vk_pipelines(&mut builders) {
+            Self::destroy_pipeline_layouts(&mut builders);
+            return Err(error);
+        }
+
+        let pipelines = builders
+            .into_iter()
+            .map(|mut builder| {
+                builder.create_pipeline();
+                builder.pipeline.unwrap()
+            })
+            .collect();
+
+        Ok(pipelines)
+    }
+
+    fn destroy_pipeline_layouts(builders: &mut [Self]) {
+        for builder in builders {
+            let pipeline_layout = builder.pipeline_layout.take();
+            unsafe {
+                builder.logical_device.destroy_pipeline_layout(pipeline_layout, None);
+            }
+        }
     }
 
-    fn get_ready_for_creation(&mut self) -> VulkanResult<()> {
+    // Builders which come before this one in its batch are ready for creation already
+    fn get_ready_for_creation(&mut self, index: usize, earlier: &[Self]) -> VulkanResult<()> {
+        check_required_fields!(
+            self, VulkanError, "PipelineBuilder",
+            [logical_device, swapchain, render_pass, subpass]);
+
+        self.check_parent(index, earlier)?;
         self.init_vertex_input_state()?;
         self.init_input_assembly_state();
         self.init_viewport_state();
+        self.check_depth_features()?;
+        self.check_multiview_features()?;
         self.init_rasterization_state();
         self.init_multisample_state();
-        self.init_color_blend_state();
+        self.init_depth_stencil_state();
+        self.init_color_blend_state()?;
+        self.init_dynamic_state();
+        self.check_push_constant_ranges()?;
+        #[cfg(feature = "shader-reflection")]
+        self.check_shader_interfaces()?;
+        self.init_shader_stages();
         self.init_pipeline_layout()?;
-        self.init_vk_pipeline()?;
+
+        Ok(())
+    }
+
+    fn check_parent(&self, index: usize, earlier: &[Self]) -> VulkanResult<()> {
+        let same_device = earlier
+            .first()
+            .map_or(true, |first| Rc::ptr_eq(&first.logical_device, &self.logical_device));
+
+        if !same_device {
+            return Err(VulkanError::PipelineBatchDevicesDiffer);
+        }
+
+        let parent_allows_derivatives = match (self.parent, self.batch_parent) {
+            (Some(parent), _) => parent.allows_derivatives,
+            (None, Some(parent)) => earlier
+                .get(parent)
+                .ok_or(VulkanError::PipelineBatchParentOutOfRange {index, parent})?
+                .allow_derivatives,
+            (None, None) => true
+        };
+
+        if !parent_allows_derivatives {
+            return Err(VulkanError::PipelineParentNotDerivable);
+        }
 
         Ok(())
     }
@@ -251,7 +589,7 @@ impl<'a> PipelineBuilder<'a> {
 
     fn init_input_assembly_state(&mut self) {
         let input_assembly_state_create_info = vk::PipelineInputAssemblyStateCreateInfo::builder()
-            .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
+            .topology(self.topology.unwrap_or(vk::PrimitiveTopology::TRIANGLE_LIST))
             .primitive_restart_enable(false)
             .build();
 
@@ -298,15 +636,49 @@ impl<'a> PipelineBuilder<'a> {
             .build()
     }
 
+    fn check_depth_features(&self) -> VulkanResult<()> {
+        let enabled_features = self.logical_device.enabled_features();
+
+        if self.depth_clamp && !enabled_features.depth_clamp {
+            return Err(VulkanError::PipelineDepthClampNotEnabled);
+        }
+
+        if self.depth_bias_clamp != 0.0 && !enabled_features.depth_bias_clamp {
+            return Err(VulkanError::PipelineDepthBiasClampNotEnabled);
+        }
+
+        Ok(())
+    }
+
+    // Pipelines of multiview render passes run every stage once per view
+    fn check_multiview_features(&self) -> VulkanResult<()> {
+        if self.render_pass.view_mask() == 0 || self.geometry_shader.is_none() {
+            return Ok(());
+        }
+
+        let supports_geometry_shader = self.logical_device
+            .multiview_features()
+            .map_or(false, |features| features.multiview_geometry_shader == vk::TRUE);
+
+        if !supports_geometry_shader {
+            return Err(VulkanError::MultiviewGeometryShaderNotSupported);
+        }
+
+        Ok(())
+    }
+
     fn init_rasterization_state(&mut self) {
         let rasterization_state_create_info = vk::PipelineRasterizationStateCreateInfo::builder()
-            .depth_clamp_enable(false)
+            .depth_clamp_enable(self.depth_clamp)
             .rasterizer_discard_enable(false)
             .polygon_mode(vk::PolygonMode::FILL)
             .line_width(1.0)
-            .cull_mode(vk::CullModeFlags::NONE)
+            .cull_mode(self.cull_mode.into())
             .front_face(vk::FrontFace::CLOCKWISE)
-            .depth_bias_enable(false)
+            .depth_bias_enable(self.depth_bias.is_some() || self.dynamic_depth_bias)
+            .depth_bias_constant_factor(self.depth_bias.map_or(0.0, |bias| bias.0))
+            .depth_bias_clamp(self.depth_bias_clamp)
+            .depth_bias_slope_factor(self.depth_bias.map_or(0.0, |bias| bias.1))
             .build();
 
         self.rasterization_state_create_info.set(rasterization_state_create_info);
@@ -317,38 +689,226 @@ impl<'a> PipelineBuilder<'a> {
             .sample_shading_enable(false)
             .rasterization_samples(vk::SampleCountFlags::TYPE_1)
             .min_sample_shading(1.0)
-            .alpha_to_coverage_enable(false)
+            .alpha_to_coverage_enable(self.alpha_to_coverage)
             .alpha_to_one_enable(false)
             .build();
 
         self.multisample_state_create_info.set(multisample_state_create_info);
     }
 
-    fn init_color_blend_state(&mut self) {
-        let color_blend_attachment_state = vk::PipelineColorBlendAttachmentState::builder()
-            .color_write_mask(
-                vk::ColorComponentFlags::R |
-                vk::ColorComponentFlags::G |
-                vk::ColorComponentFlags::B |
-                vk::ColorComponentFlags::A)
-            .blend_enable(false)
+    fn init_depth_stencil_state(&mut self) {
+        let depth_stencil_state_create_info = vk::PipelineDepthStencilStateCreateInfo::builder()
+            .depth_test_enable(self.depth_test)
+            .depth_write_enable(self.depth_write)
+            .depth_compare_op(vk::CompareOp::LESS_OR_EQUAL)
+            .depth_bounds_test_enable(false)
+            .stencil_test_enable(false)
             .build();
 
-        self.color_blend_attachment_state.set(color_blend_attachment_state);
+        self.depth_stencil_state_create_info.set(depth_stencil_state_create_info);
+    }
+
+    fn init_color_blend_state(&mut self) -> VulkanResult<()> {
+        let blend_modes = self.attachment_blend_modes()?;
+
+        let color_blend_attachment_states = blend_modes
+            .iter()
+            .map(|blend_mode| {
+                vk::PipelineColorBlendAttachmentState::builder()
+                    .color_write_mask(
+                        vk::ColorComponentFlags::R |
+                        vk::ColorComponentFlags::G |
+                        vk::ColorComponentFlags::B |
+                        vk::ColorComponentFlags::A)
+                    .blend_enable(*blend_mode != BlendMode::Opaque)
+                    .color_blend_op(vk::BlendOp::ADD)
+                    .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+                    .dst_color_blend_factor(blend_mode.dst_color_blend_factor())
+                    .alpha_blend_op(vk::BlendOp::ADD)
+                    .src_alpha_blend_factor(vk::BlendFactor::ONE)
+                    .dst_alpha_blend_factor(vk::BlendFactor::ZERO)
+                    .build()
+            })
+            .collect();
+
+        self.color_blend_attachment_states.set(color_blend_attachment_states);
 
         let color_blend_state_create_info = vk::PipelineColorBlendStateCreateInfo::builder()
             .logic_op_enable(false)
             .logic_op(vk::LogicOp::COPY)
-            .attachments(std::slice::from_ref(&self.color_blend_attachment_state))
+            .attachments(&self.color_blend_attachment_states)
             .blend_constants([0.0, 0.0, 0.0, 0.0])
             .build();
 
         self.color_blend_state_create_info.set(color_blend_state_create_info);
+
+        Ok(())
+    }
+
+    fn attachment_blend_modes(&self) -> VulkanResult<Vec<BlendMode>> {
+        let color_attachment_count = self.color_attachment_count.unwrap_or(1);
+        let mut blend_modes = vec![self.blend_mode; color_attachment_count as usize];
+
+        for (attachment, blend_mode) in &self.attachment_blend_modes {
+            if *attachment >= color_attachment_count {
+                return Err(VulkanError::PipelineColorAttachmentOutOfRange {
+                    attachment: *attachment,
+                    color_attachment_count
+                });
+            }
+
+            blend_modes[*attachment as usize] = *blend_mode;
+        }
+
+        let is_independent = blend_modes.windows(2).any(|pair| pair[0] != pair[1]);
+        if is_independent && !self.logical_device.enabled_features().independent_blend {
+            return Err(VulkanError::PipelineIndependentBlendNotEnabled);
+        }
+
+        Ok(blend_modes)
+    }
+
+    // Viewport and scissors set at pipeline creation are only defaults,
+    // the renderer sets them every frame to support letterboxing
+    fn init_dynamic_state(&mut self) {
+        let mut dynamic_states = vec![
+            vk::DynamicState::VIEWPORT,
+            vk::DynamicState::SCISSOR
+        ];
+
+        if self.dynamic_depth_bias {
+            dynamic_states.push(vk::DynamicState::DEPTH_BIAS);
+        }
+
+        self.dynamic_states.set(dynamic_states);
+
+        let dynamic_state_create_info = vk::PipelineDynamicStateCreateInfo::builder()
+            .dynamic_states(&self.dynamic_states)
+            .build();
+
+        self.dynamic_state_create_info.set(dynamic_state_create_info);
+    }
+
+    fn check_push_constant_ranges(&self) -> VulkanResult<()> {
+        let mut stages_with_range = Vec::with_capacity(Self::MAX_SHADER_STAGES);
+
+        for range in &self.push_constant_ranges {
+            if range.offset % Self::PUSH_CONSTANTS_ALIGNMENT != 0 ||
+                range.size % Self::PUSH_CONSTANTS_ALIGNMENT != 0 {
+                return Err(VulkanError::PushConstantsMisaligned {
+                    offset: range.offset,
+                    size: range.size
+                });
+            }
+
+            for stage in &range.stages {
+                if stages_with_range.contains(stage) {
+                    return Err(VulkanError::PushConstantsStageInMultipleRanges {
+                        stage: format!("{:?}", stage).to_lowercase()
+                    });
+                }
+
+                stages_with_range.push(*stage);
+            }
+        }
+
+        let size = self.push_constant_ranges
+            .iter()
+            .map(|range| range.end() as u32)
+            .max()
+            .unwrap_or(0);
+
+        let max_size = self.logical_device
+            .physical_device()
+            .limits()
+            .max_push_constants_size;
+
+        if size > max_size {
+            return Err(VulkanError::PushConstantsTooLarge {size, max_size});
+        }
+
+        Ok(())
+    }
+
+    #[cfg(feature = "shader-reflection")]
+    fn check_shader_interfaces(&self) -> VulkanResult<()> {
+        let shaders = [
+            (ShaderStage::Geometry, self.geometry_shader.map(|shader| shader.reflection())),
+            (ShaderStage::Vertex, self.vertex_shader.map(|shader| shader.reflection())),
+            (ShaderStage::Fragment, self.fragment_shader.map(|shader| shader.reflection()))
+        ];
+
+        for (stage, reflection) in shaders.iter() {
+            if let Some(reflection) = reflection {
+                self.check_shader_push_constants(*stage, reflection)?;
+            }
+        }
+
+        if let Some(vertex_shader) = self.vertex_shader {
+            self.check_vertex_inputs(vertex_shader.reflection())?;
+        }
+
+        Ok(())
+    }
+
+    #[cfg(feature = "shader-reflection")]
+    fn check_shader_push_constants(
+        &self,
+        stage: ShaderStage,
+        reflection: &ShaderReflection
+    ) -> VulkanResult<()> {
+        let shader_size = reflection.push_constants_size().unwrap_or(0);
+        let pushed_size = self.push_constant_ranges
+            .iter()
+            .find(|range| range.stages.contains(&stage))
+            .map_or(0, |range| range.end());
+
+        if shader_size as usize != pushed_size {
+            return Err(VulkanError::ShaderPushConstantsMismatch {
+                stage: format!("{:?}", stage).to_lowercase(),
+                shader_size,
+                pushed_size
+            });
+        }
+
+        Ok(())
+    }
+
+    #[cfg(feature = "shader-reflection")]
+    fn check_vertex_inputs(&self, reflection: &ShaderReflection) -> VulkanResult<()> {
+        for input in reflection.inputs() {
+            let attribute_format = self.vertex_attribute_descriptions
+                .iter()
+                .find(|description| description.location == input.location)
+                .map(|description| description.format);
+
+            if attribute_format.is_none() || attribute_format != input.format {
+                return Err(VulkanError::ShaderVertexInputMismatch {location: input.location});
+            }
+        }
+
+        Ok(())
     }
 
     fn init_pipeline_layout(&mut self) -> VulkanResult<()> {
-        let push_constant_ranges = Self::push_constant_ranges(&self.push_constants_sizes);
+        let push_constant_ranges: Vec<vk::PushConstantRange> = self.push_constant_ranges
+            .iter()
+            .map(|range| {
+                vk::PushConstantRange::builder()
+                    .stage_flags(range.stage_flags())
+                    .offset(range.offset as u32)
+                    .size(range.size as u32)
+                    .build()
+            })
+            .collect();
+
+        let set_layouts: Vec<vk::DescriptorSetLayout> = self.descriptor_set_layouts
+            .iter()
+            .map(|layout| layout.handle())
+            .collect();
+
         let pipeline_layout_create_info_builder = vk::PipelineLayoutCreateInfo::builder()
+            .set_layouts(&set_layouts)
             .push_constant_ranges(&push_constant_ranges);
 
         let pipeline_layout = unsafe {
@@ -359,51 +919,91 @@ impl<'a> PipelineBuilder<'a> {
         Ok(())
     }
 
-    fn push_constant_ranges(push_constants_sizes: &Option<HashMap<ShaderStage, usize>>) -> Vec<vk::PushConstantRange> {
-        match push_constants_sizes {
-            Some(sizes) => {
-                let mut sizes = sizes.clone();
-                sizes.retain(|_, size| *size > 0);
-                sizes.iter().map(|(shader, size)| {
-                    vk::PushConstantRange::builder()
-                        .stage_flags((*shader).into())
-                        .offset(0)
-                        .size(*size as u32)
-                        .build()
-                }).collect()
-            },
-            None => Vec::with_capacity(0)
-        }
-    }
-
-    fn init_vk_pipeline(&mut self) -> VulkanResult<()> {
+    fn init_shader_stages(&mut self) {
         let mut stages_create_infos = Vec::with_capacity(Self::MAX_SHADER_STAGES);
         Self::push_shader_stage_if_some(&mut stages_create_infos, &self.geometry_shader);
         Self::push_shader_stage_if_some(&mut stages_create_infos, &self.vertex_shader);
         Self::push_shader_stage_if_some(&mut stages_create_infos, &self.fragment_shader);
 
-        let pipeline_create_info = vk::GraphicsPipelineCreateInfo::builder()
-            .stages(stages_create_infos.as_slice())
+        self.shader_stage_create_infos.set(stages_create_infos);
+    }
+
+    fn init_vk_pipelines(builders: &mut [Self]) -> VulkanResult<()> {
+        let pipeline_create_infos: Vec<vk::GraphicsPipelineCreateInfo> = builders
+            .iter()
+            .map(Self::pipeline_create_info)
+            .collect();
+
+        let logical_device = &builders[0].logical_device;
+        let vk_pipelines = unsafe {
+            logical_device.create_graphics_pipelines(
+                vk::PipelineCache::null(),
+                &pipeline_create_infos,
+                None)
+        };
+
+        let vk_pipelines = match vk_pipelines {
+            Ok(vk_pipelines) => vk_pipelines,
+            Err((vk_pipelines, result)) => {
+                // Pipelines which were created before the failure are not returned to anyone
+                for vk_pipeline in vk_pipelines {
+                    if vk_pipeline != vk::Pipeline::null() {
+                        unsafe {
+                            logical_device.destroy_pipeline(vk_pipeline, None);
+                        }
+                    }
+                }
+
+                return Err(VulkanError::PipelineCreateError {result});
+            }
+        };
+
+        for (builder, vk_pipeline) in builders.iter_mut().zip(vk_pipelines) {
+            debug!(
+                "created pipeline with {} shader stages and push constant ranges {:?}",
+                builder.shader_stage_create_infos.len(), builder.push_constant_ranges);
+
+            builder.vk_pipeline.set(vk_pipeline);
+        }
+
+        Ok(())
+    }
+
+    fn pipeline_create_info(&self) -> vk::GraphicsPipelineCreateInfo {
+        let base_pipeline_handle = self.parent.map_or(vk::Pipeline::null(), Pipeline::handle);
+        let base_pipeline_index = self.batch_parent.map_or(-1, |parent| parent as i32);
+
+        vk::GraphicsPipelineCreateInfo::builder()
+            .flags(self.create_flags())
+            .stages(&self.shader_stage_create_infos)
             .vertex_input_state(&self.vertex_input_state_create_info)
             .input_assembly_state(&self.input_assembly_state_create_info)
             .viewport_state(&self.viewport_state_create_info)
             .rasterization_state(&self.rasterization_state_create_info)
             .multisample_state(&self.multisample_state_create_info)
+            .depth_stencil_state(&self.depth_stencil_state_create_info)
             .color_blend_state(&self.color_blend_state_create_info)
+            .dynamic_state(&self.dynamic_state_create_info)
             .layout(*self.pipeline_layout)
             .render_pass(self.render_pass.handle())
             .subpass(*self.subpass)
-            .build();
+            .base_pipeline_handle(base_pipeline_handle)
+            .base_pipeline_index(base_pipeline_index)
+            .build()
+    }
 
-        let vk_pipeline = unsafe {
-            self.logical_device.create_graphics_pipelines(
-                vk::PipelineCache::null(),
-                &[pipeline_create_info],
-                None)
-        }.map_err(|err| VulkanError::PipelineCreateError {result: err.1})?;
+    fn create_flags(&self) -> vk::PipelineCreateFlags {
+        let mut flags = vk::PipelineCreateFlags::empty();
 
-        self.vk_pipeline.set(vk_pipeline[0]);
-        Ok(())
+        if self.allow_derivatives {
+            flags |= vk::PipelineCreateFlags::ALLOW_DERIVATIVES;
+        }
+
+        if self.parent.is_some() || self.batch_parent.is_some() {
+            flags |= vk::PipelineCreateFlags::DERIVATIVE;
+        }
+
+        flags
     }
 
     fn push_shader_stage_if_some<T: ShaderStageBuilder>(
@@ -423,20 +1023,212 @@ impl<'a> PipelineBuilder<'a> {
         let pipeline = Pipeline {
             vk_pipeline: self.vk_pipeline.take(),
             pipeline_layout: self.pipeline_layout.take(),
+            bind_point: vk::PipelineBindPoint::GRAPHICS,
+            push_constant_ranges: std::mem::take(&mut self.push_constant_ranges),
+            blend_mode: self.blend_mode,
+            allows_derivatives: self.allow_derivatives,
+            _descriptor_set_layouts: std::mem::take(&mut self.descriptor_set_layouts),
             logical_device: self.logical_device.take()
         };
 
+        leak_tracking::track_creation(&pipeline);
+        self.pipeline.set(pipeline);
+    }
+}
+
+#[derive(Default)]
+pub struct ComputePipelineBuilder<'a> {
+    logical_device: BuilderRequirement<Rc<LogicalDevice>>,
+    compute_shader: BuilderRequirement<&'a ComputeShader>,
+    descriptor_set_layouts: Vec<Rc<DescriptorSetLayout>>,
+    push_constants_size: usize,
+
+    pipeline_layout: BuilderInternal<vk::PipelineLayout>,
+
+    vk_pipeline: BuilderInternal<vk::Pipeline>,
+
+    pipeline: BuilderProduct<Pipeline>
+}
+
+impl<'a> ComputePipelineBuilder<'a> {
+    pub fn logical_device(mut self, logical_device: Rc<LogicalDevice>) -> Self {
+        self.logical_device.set(logical_device);
+        self
+    }
+
+    pub fn compute_shader(mut self, compute_shader: &'a ComputeShader) -> Self {
+        self.compute_shader.set(compute_shader);
+        self
+    }
+
+    pub fn descriptor_set_layout(mut self, layout: Rc<DescriptorSetLayout>) -> Self {
+        self.descriptor_set_layouts.push(layout);
+        self
+    }
+
+    pub fn push_constants_size(mut self, size: usize) -> Self {
+        self.push_constants_size = size;
+        self
+    }
+
+    pub fn build(mut self) -> VulkanResult<Pipeline> {
+        check_required_fields!(
+            self, VulkanError, "ComputePipelineBuilder",
+            [logical_device, compute_shader]);
+
+        self.check_push_constants()?;
+        #[cfg(feature = "shader-reflection")]
+        self.check_shader_push_constants()?;
+        self.init_pipeline_layout()?;
+
+        if let Err(error) = self.init_vk_pipeline() {
+            unsafe {
+                self.logical_device.destroy_pipeline_layout(self.pipeline_layout.take(), None);
+            }
+
+            return Err(error);
+        }
+
+        self.create_pipeline();
+
+        Ok(self.pipeline.unwrap())
+    }
+
+    fn check_push_constants(&self) -> VulkanResult<()> {
+        if self.push_constants_size % PipelineBuilder::PUSH_CONSTANTS_ALIGNMENT != 0 {
+            return Err(VulkanError::PushConstantsMisaligned {
+                offset: 0,
+                size: self.push_constants_size
+            });
+        }
+
+        let size = self.push_constants_size as u32;
+        let max_size = self.logical_device
+            .physical_device()
+            .limits()
+            .max_push_constants_size;
+
+        if size > max_size {
+            return Err(VulkanError::PushConstantsTooLarge {size, max_size});
+        }
+
+        Ok(())
+    }
+
+    #[cfg(feature = "shader-reflection")]
+    fn check_shader_push_constants(&self) -> VulkanResult<()> {
+        let shader_size = self.compute_shader.reflection().push_constants_size().unwrap_or(0);
+
+        if shader_size as usize != self.push_constants_size {
+            return Err(VulkanError::ShaderPushConstantsMismatch {
+                stage: String::from("compute"),
+                shader_size,
+                pushed_size: self.push_constants_size
+            });
+        }
+
+        Ok(())
+    }
+
+    fn push_constant_ranges(&self) -> Vec<PushConstantRange> {
+        if self.push_constants_size == 0 {
+            return Vec::new();
+        }
+
+        vec![PushConstantRange {
+            stages: vec![ShaderStage::Compute],
+            offset: 0,
+            size: self.push_constants_size
+        }]
+    }
+
+    fn init_pipeline_layout(&mut self) -> VulkanResult<()> {
+        let pipeline_layout = create_pipeline_layout(
+            &self.logical_device,
+            &self.descriptor_set_layouts,
+            &self.push_constant_ranges())?;
+
+        self.pipeline_layout.set(pipeline_layout);
+        Ok(())
+    }
+
+    fn init_vk_pipeline(&mut self) -> VulkanResult<()> {
+        let pipeline_create_info = vk::ComputePipelineCreateInfo::builder()
+            .stage(self.compute_shader.shader_stage_create_info_builder().build())
+            .layout(*self.pipeline_layout)
+            .build();
+
+        let vk_pipelines = unsafe {
+            self.logical_device.create_compute_pipelines(
+                vk::PipelineCache::null(),
+                &[pipeline_create_info],
+                None)
+        }.map_err(|(_, result)| VulkanError::PipelineCreateError {result})?;
+
+        debug!("created compute pipeline with {} bytes of push constants",
+            self.push_constants_size);
+
+        self.vk_pipeline.set(vk_pipelines[0]);
+        Ok(())
+    }
+
+    fn create_pipeline(&mut self) {
+        let pipeline = Pipeline::from_raw_parts(
+            (self.vk_pipeline.take(), self.pipeline_layout.take()),
+            vk::PipelineBindPoint::COMPUTE,
+            self.push_constant_ranges(),
+            std::mem::take(&mut self.descriptor_set_layouts),
+            self.logical_device.take());
+
         self.pipeline.set(pipeline);
     }
 }
 
+// Opaque writes colors as they are, the other modes blend them using the fragment's alpha
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub enum BlendMode {
+    #[default]
+    Opaque,
+    AlphaBlend,
+    Additive
+}
+
+impl BlendMode {
+    fn dst_color_blend_factor(self) -> vk::BlendFactor {
+        match self {
+            BlendMode::Opaque => vk::BlendFactor::ZERO,
+            BlendMode::AlphaBlend => vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+            BlendMode::Additive => vk::BlendFactor::ONE
+        }
+    }
+}
+
+// Front faces are the ones with clockwise winding
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub enum CullMode {
+    #[default]
+    None,
+    Front,
+    Back
+}
+
+impl From<CullMode> for vk::CullModeFlags {
+    fn from(cull_mode: CullMode) -> Self {
+        match cull_mode {
+            CullMode::None => vk::CullModeFlags::NONE,
+            CullMode::Front => vk::CullModeFlags::FRONT,
+            CullMode::Back => vk::CullModeFlags::BACK
+        }
+    }
+}
+
 struct VertexAttributeDescriptionInfo {
     binding: usize,
     format: VertexAttributeFormat,
     offset: usize
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub enum VertexAttributeFormat {
     I32(u8),
     U32(u8),
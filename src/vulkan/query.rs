@@ -0,0 +1,105 @@
+use std::{
+    ops::Range,
+    rc::Rc
+};
+use ash::{
+    version::DeviceV1_0,
+    vk
+};
+use crate::vulkan::{
+    VulkanError,
+    VulkanResult,
+    handle::VulkanHandle,
+    logical_device::LogicalDevice
+};
+
+// Queries have to be reset with `CommandBufferRecorder::reset_query_pool` outside of
+// render passes before they are begun again, e.g. at the beginning of every frame
+pub struct QueryPool {
+    vk_query_pool: vk::QueryPool,
+    query_type: vk::QueryType,
+    query_count: u32,
+    logical_device: Rc<LogicalDevice>
+}
+
+impl QueryPool {
+    // Every query counts the samples passing the depth and stencil tests between
+    // `begin_occlusion_query` and `end_occlusion_query`, e.g. of a bounding box drawn
+    // with color and depth writes off to tell whether what it bounds is visible
+    pub fn occlusion(logical_device: Rc<LogicalDevice>, query_count: u32) -> VulkanResult<Self> {
+        Self::new(logical_device, vk::QueryType::OCCLUSION, query_count)
+    }
+
+    // Every query holds the GPU's clock written by `write_timestamp`, in ticks lasting
+    // `PhysicalDeviceLimits::timestamp_period` nanoseconds
+    pub fn timestamps(logical_device: Rc<LogicalDevice>, query_count: u32) -> VulkanResult<Self> {
+        Self::new(logical_device, vk::QueryType::TIMESTAMP, query_count)
+    }
+
+    fn new(
+        logical_device: Rc<LogicalDevice>,
+        query_type: vk::QueryType,
+        query_count: u32
+    ) -> VulkanResult<Self> {
+        let create_info = vk::QueryPoolCreateInfo::builder()
+            .query_type(query_type)
+            .query_count(query_count);
+
+        let vk_query_pool = unsafe {
+            logical_device.create_query_pool(&create_info, None)
+        }.map_err(|result| VulkanError::QueryPoolCreateError {result})?;
+
+        Ok(Self {
+            vk_query_pool,
+            query_type,
+            query_count,
+            logical_device
+        })
+    }
+
+    pub fn query_type(&self) -> vk::QueryType {
+        self.query_type
+    }
+
+    pub fn query_count(&self) -> u32 {
+        self.query_count
+    }
+
+    // Results of the given queries if all of them are available, without waiting for them.
+    // For occlusion queries, they are the numbers of samples which passed.
+    // For timestamp queries, they are the written ticks.
+    pub fn results(&self, queries: Range<u32>) -> VulkanResult<Option<Vec<u64>>> {
+        let mut results = vec![0u64; queries.len()];
+
+        let result = unsafe {
+            self.logical_device.get_query_pool_results(
+                self.vk_query_pool,
+                queries.start,
+                queries.len() as u32,
+                &mut results,
+                vk::QueryResultFlags::TYPE_64)
+        };
+
+        match result {
+            Ok(()) => Ok(Some(results)),
+            Err(vk::Result::NOT_READY) => Ok(None),
+            Err(result) => Err(VulkanError::QueryPoolResultsError {result})
+        }
+    }
+}
+
+impl VulkanHandle for QueryPool {
+    type Handle = vk::QueryPool;
+
+    fn handle(&self) -> Self::Handle {
+        self.vk_query_pool
+    }
+}
+
+impl Drop for QueryPool {
+    fn drop(&mut self) {
+        unsafe {
+            self.logical_device.destroy_query_pool(self.vk_query_pool, None);
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use ash::vk;
+use crate::vulkan::{
+    VulkanResult,
+    physical_device::{
+        PhysicalDevice,
+        QueueFamily
+    },
+    buffer::Buffer,
+    image::Image,
+    command_buffer::CommandBufferRecorder,
+    barrier::{
+        PipelineBarrier,
+        MemoryAccess,
+        BufferBarrier,
+        ImageBarrier
+    }
+};
+
+// Hands exclusively owned buffers and images over from one queue family to another,
+// e.g. from the transfer queue which uploaded them to the graphics queue which draws them.
+// The release barriers are recorded on the source queue and the acquire barriers on the
+// destination queue, whose submission has to wait for the release, e.g. on a semaphore.
+// When both families are the same, the release is an ordinary barrier and the acquire is empty.
+pub struct QueueOwnershipTransfer {
+    src_queue_family: u32,
+    dst_queue_family: u32,
+    src_stage: vk::PipelineStageFlags,
+    dst_stage: vk::PipelineStageFlags,
+    buffer_barriers: Vec<BufferBarrier>,
+    image_barriers: Vec<ImageBarrier>
+}
+
+impl QueueOwnershipTransfer {
+    // Stages are the last one writing to the resources on the source queue
+    // and the first one accessing them on the destination queue
+    pub fn new(
+        physical_device: &PhysicalDevice,
+        (src_queue_family, dst_queue_family): (QueueFamily, QueueFamily),
+        (src_stage, dst_stage): (vk::PipelineStageFlags, vk::PipelineStageFlags)
+    ) -> VulkanResult<Self> {
+        Ok(Self {
+            src_queue_family: physical_device.queue_family_index(src_queue_family)?,
+            dst_queue_family: physical_device.queue_family_index(dst_queue_family)?,
+            src_stage,
+            dst_stage,
+            buffer_barriers: Vec::new(),
+            image_barriers: Vec::new()
+        })
+    }
+
+    pub fn is_transfer_needed(&self) -> bool {
+        self.src_queue_family != self.dst_queue_family
+    }
+
+    pub fn buffer(
+        mut self,
+        buffer: &Buffer,
+        (src_access, dst_access): (vk::AccessFlags, vk::AccessFlags)
+    ) -> Self {
+        let (src_queue_family, dst_queue_family) = self.barrier_queue_families();
+        let accesses = (
+            MemoryAccess::new(self.src_stage, src_access),
+            MemoryAccess::new(self.dst_stage, dst_access)
+        );
+
+        self.buffer_barriers.push(BufferBarrier::new(buffer, accesses)
+            .queue_families(src_queue_family, dst_queue_family));
+
+        self
+    }
+
+    // All mip levels and layers are transferred, the layout transition happens during
+    // the transfer and has to be the same on both queues
+    pub fn image(
+        mut self,
+        image: &Image,
+        layouts: (vk::ImageLayout, vk::ImageLayout),
+        (src_access, dst_access): (vk::AccessFlags, vk::AccessFlags)
+    ) -> Self {
+        let (src_queue_family, dst_queue_family) = self.barrier_queue_families();
+        let accesses = (
+            MemoryAccess::new(self.src_stage, src_access),
+            MemoryAccess::new(self.dst_stage, dst_access)
+        );
+
+        self.image_barriers.push(ImageBarrier::new(image, layouts, accesses)
+            .queue_families(src_queue_family, dst_queue_family));
+
+        self
+    }
+
+    fn barrier_queue_families(&self) -> (u32, u32) {
+        if self.is_transfer_needed() {
+            (self.src_queue_family, self.dst_queue_family)
+        }
+        else {
+            (vk::QUEUE_FAMILY_IGNORED, vk::QUEUE_FAMILY_IGNORED)
+        }
+    }
+
+    // Destination accesses are ignored by releases, as they do not happen on the source queue
+    pub fn record_release<'a>(
+        &self,
+        recorder: CommandBufferRecorder<'a>
+    ) -> CommandBufferRecorder<'a> {
+        if !self.is_transfer_needed() {
+            return recorder.pipeline_barrier(&self.barrier(|src, dst| (src, dst)));
+        }
+
+        recorder.pipeline_barrier(&self.barrier(|src, _| (src, MemoryAccess::none())))
+    }
+
+    // Source accesses are ignored by acquires, they are made available by the release
+    pub fn record_acquire<'a>(
+        &self,
+        recorder: CommandBufferRecorder<'a>
+    ) -> CommandBufferRecorder<'a> {
+        if !self.is_transfer_needed() {
+            return recorder;
+        }
+
+        recorder.pipeline_barrier(&self.barrier(|_, dst| (MemoryAccess::none(), dst)))
+    }
+
+    fn barrier<F>(&self, accesses: F) -> PipelineBarrier where
+        F: Fn(MemoryAccess, MemoryAccess) -> (MemoryAccess, MemoryAccess) {
+        let buffer_barriers = self.buffer_barriers
+            .iter()
+            .map(|barrier| {
+                let (src, dst) = accesses(barrier.src(), barrier.dst());
+                barrier.with_accesses(src, dst)
+            });
+
+        let image_barriers = self.image_barriers
+            .iter()
+            .map(|barrier| {
+                let (src, dst) = accesses(barrier.src(), barrier.dst());
+                barrier.with_accesses(src, dst)
+            });
+
+        let barrier = buffer_barriers.fold(PipelineBarrier::new(), PipelineBarrier::buffer);
+        image_barriers.fold(barrier, PipelineBarrier::image)
+    }
+}
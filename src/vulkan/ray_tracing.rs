@@ -0,0 +1,427 @@
+use std::rc::Rc;
+use ash::{
+    version::DeviceV1_0,
+    vk
+};
+use crate::{
+    builder::{
+        BuilderRequirement,
+        BuilderInternal,
+        BuilderProduct
+    },
+    vulkan::{
+        VulkanError,
+        VulkanResult,
+        handle::VulkanHandle,
+        logical_device::LogicalDevice,
+        allocator::{
+            Allocator,
+            MemoryUsage
+        },
+        buffer::Buffer,
+        descriptor::DescriptorSetLayout,
+        pipeline::{
+            self,
+            Pipeline,
+            PipelineBuilder,
+            PushConstantRange
+        },
+        shader::{
+            RayGenShader,
+            MissShader,
+            ClosestHitShader,
+            AnyHitShader,
+            ShaderStage
+        }
+    }
+};
+
+// Shaders run for intersections with the geometry of instances using the group,
+// without any the intersection is only recorded
+#[derive(Clone, Copy, Default)]
+pub struct HitGroup<'a> {
+    pub closest_hit: Option<&'a ClosestHitShader>,
+    pub any_hit: Option<&'a AnyHitShader>
+}
+
+// Shader groups are ordered as the ray generation shader, the miss shaders and the hit groups,
+// each in the order they were given to the builder. Traced rays index misses and hit groups
+// relative to the first of their kind.
+pub struct RayTracingPipeline {
+    pipeline: Rc<Pipeline>,
+    miss_count: usize,
+    hit_group_count: usize,
+    group_handles: Vec<u8>,
+    group_handle_size: usize,
+    group_base_alignment: usize
+}
+
+impl RayTracingPipeline {
+    pub fn builder<'a>() -> RayTracingPipelineBuilder<'a> {
+        RayTracingPipelineBuilder {
+            ..Default::default()
+        }
+    }
+
+    // For binding descriptor sets and pushing constants
+    pub fn pipeline(&self) -> &Rc<Pipeline> {
+        &self.pipeline
+    }
+
+    pub fn miss_count(&self) -> usize {
+        self.miss_count
+    }
+
+    pub fn hit_group_count(&self) -> usize {
+        self.hit_group_count
+    }
+
+    fn group_handle(&self, group: usize) -> &[u8] {
+        let start = group * self.group_handle_size;
+        &self.group_handles[start..start + self.group_handle_size]
+    }
+}
+
+#[derive(Default)]
+pub struct RayTracingPipelineBuilder<'a> {
+    logical_device: BuilderRequirement<Rc<LogicalDevice>>,
+    ray_gen_shader: BuilderRequirement<&'a RayGenShader>,
+    miss_shaders: Vec<&'a MissShader>,
+    hit_groups: Vec<HitGroup<'a>>,
+    descriptor_set_layouts: Vec<Rc<DescriptorSetLayout>>,
+    push_constant_stages: Vec<ShaderStage>,
+    push_constants_size: usize,
+    max_recursion_depth: Option<u32>,
+
+    pipeline_layout: BuilderInternal<vk::PipelineLayout>,
+    vk_pipeline: BuilderInternal<vk::Pipeline>,
+    group_handles: BuilderInternal<Vec<u8>>,
+
+    ray_tracing_pipeline: BuilderProduct<RayTracingPipeline>
+}
+
+impl<'a> RayTracingPipelineBuilder<'a> {
+    pub fn logical_device(mut self, logical_device: Rc<LogicalDevice>) -> Self {
+        self.logical_device.set(logical_device);
+        self
+    }
+
+    pub fn ray_gen_shader(mut self, ray_gen_shader: &'a RayGenShader) -> Self {
+        self.ray_gen_shader.set(ray_gen_shader);
+        self
+    }
+
+    pub fn miss_shader(mut self, miss_shader: &'a MissShader) -> Self {
+        self.miss_shaders.push(miss_shader);
+        self
+    }
+
+    pub fn hit_group(mut self, hit_group: HitGroup<'a>) -> Self {
+        self.hit_groups.push(hit_group);
+        self
+    }
+
+    pub fn descriptor_set_layout(mut self, layout: Rc<DescriptorSetLayout>) -> Self {
+        self.descriptor_set_layouts.push(layout);
+        self
+    }
+
+    // A single range of constants shared by the given stages
+    pub fn push_constants_size(mut self, stages: &[ShaderStage], size: usize) -> Self {
+        self.push_constant_stages = stages.to_vec();
+        self.push_constants_size = size;
+        self
+    }
+
+    // Depth of rays traced from hit shaders, defaults to 1 which allows tracing
+    // only from the ray generation shader. Clamped to the device's limit.
+    pub fn max_recursion_depth(mut self, max_recursion_depth: u32) -> Self {
+        self.max_recursion_depth = Some(max_recursion_depth);
+        self
+    }
+
+    pub fn build(mut self) -> VulkanResult<RayTracingPipeline> {
+        check_required_fields!(
+            self, VulkanError, "RayTracingPipelineBuilder",
+            [logical_device, ray_gen_shader]);
+
+        if self.logical_device.ray_tracing_fn().is_none() {
+            return Err(VulkanError::RayTracingNotEnabled);
+        }
+
+        if self.miss_shaders.is_empty() || self.hit_groups.is_empty() {
+            return Err(VulkanError::RayTracingPipelineWithoutHitGroups);
+        }
+
+        self.check_push_constants()?;
+        self.init_pipeline_layout()?;
+
+        if let Err(error) = self.init_vk_pipeline() {
+            unsafe {
+                self.logical_device.destroy_pipeline_layout(self.pipeline_layout.take(), None);
+            }
+
+            return Err(error);
+        }
+
+        if let Err(error) = self.init_group_handles() {
+            unsafe {
+                self.logical_device.destroy_pipeline(self.vk_pipeline.take(), None);
+                self.logical_device.destroy_pipeline_layout(self.pipeline_layout.take(), None);
+            }
+
+            return Err(error);
+        }
+
+        self.create_ray_tracing_pipeline();
+
+        Ok(self.ray_tracing_pipeline.unwrap())
+    }
+
+    fn properties(&self) -> vk::PhysicalDeviceRayTracingPropertiesNV {
+        self.logical_device
+            .physical_device()
+            .ray_tracing_properties()
+            .unwrap()
+    }
+
+    fn group_count(&self) -> usize {
+        1 + self.miss_shaders.len() + self.hit_groups.len()
+    }
+
+    fn check_push_constants(&self) -> VulkanResult<()> {
+        if self.push_constants_size % PipelineBuilder::PUSH_CONSTANTS_ALIGNMENT != 0 {
+            return Err(VulkanError::PushConstantsMisaligned {
+                offset: 0,
+                size: self.push_constants_size
+            });
+        }
+
+        let size = self.push_constants_size as u32;
+        let max_size = self.logical_device
+            .physical_device()
+            .limits()
+            .max_push_constants_size;
+
+        if size > max_size {
+            return Err(VulkanError::PushConstantsTooLarge {size, max_size});
+        }
+
+        Ok(())
+    }
+
+    fn push_constant_ranges(&self) -> Vec<PushConstantRange> {
+        if self.push_constants_size == 0 {
+            return Vec::new();
+        }
+
+        vec![PushConstantRange {
+            stages: self.push_constant_stages.clone(),
+            offset: 0,
+            size: self.push_constants_size
+        }]
+    }
+
+    fn init_pipeline_layout(&mut self) -> VulkanResult<()> {
+        let pipeline_layout = pipeline::create_pipeline_layout(
+            &self.logical_device,
+            &self.descriptor_set_layouts,
+            &self.push_constant_ranges())?;
+
+        self.pipeline_layout.set(pipeline_layout);
+        Ok(())
+    }
+
+    fn init_vk_pipeline(&mut self) -> VulkanResult<()> {
+        let mut stages = vec![self.ray_gen_shader.shader_stage_create_info_builder().build()];
+        let mut groups = vec![general_group(0)];
+
+        for miss_shader in &self.miss_shaders {
+            groups.push(general_group(stages.len() as u32));
+            stages.push(miss_shader.shader_stage_create_info_builder().build());
+        }
+
+        for hit_group in &self.hit_groups {
+            let mut closest_hit_shader = vk::SHADER_UNUSED_NV;
+            if let Some(closest_hit) = hit_group.closest_hit {
+                closest_hit_shader = stages.len() as u32;
+                stages.push(closest_hit.shader_stage_create_info_builder().build());
+            }
+
+            let mut any_hit_shader = vk::SHADER_UNUSED_NV;
+            if let Some(any_hit) = hit_group.any_hit {
+                any_hit_shader = stages.len() as u32;
+                stages.push(any_hit.shader_stage_create_info_builder().build());
+            }
+
+            groups.push(vk::RayTracingShaderGroupCreateInfoNV::builder()
+                .ty(vk::RayTracingShaderGroupTypeNV::TRIANGLES_HIT_GROUP)
+                .general_shader(vk::SHADER_UNUSED_NV)
+                .closest_hit_shader(closest_hit_shader)
+                .any_hit_shader(any_hit_shader)
+                .intersection_shader(vk::SHADER_UNUSED_NV)
+                .build());
+        }
+
+        let max_recursion_depth = self.max_recursion_depth
+            .unwrap_or(1)
+            .min(self.properties().max_recursion_depth);
+
+        let pipeline_create_info = vk::RayTracingPipelineCreateInfoNV::builder()
+            .stages(&stages)
+            .groups(&groups)
+            .max_recursion_depth(max_recursion_depth)
+            .layout(*self.pipeline_layout)
+            .build();
+
+        let ray_tracing_fn = self.logical_device.ray_tracing_fn().unwrap();
+        let mut vk_pipeline = vk::Pipeline::null();
+        let result = unsafe {
+            ray_tracing_fn.create_ray_tracing_pipelines_nv(
+                self.logical_device.handle(),
+                vk::PipelineCache::null(),
+                1,
+                &pipeline_create_info,
+                std::ptr::null(),
+                &mut vk_pipeline)
+        };
+
+        if result != vk::Result::SUCCESS {
+            return Err(VulkanError::PipelineCreateError {result});
+        }
+
+        debug!("created ray tracing pipeline with {} miss shaders and {} hit groups",
+            self.miss_shaders.len(), self.hit_groups.len());
+
+        self.vk_pipeline.set(vk_pipeline);
+        Ok(())
+    }
+
+    fn init_group_handles(&mut self) -> VulkanResult<()> {
+        let handle_size = self.properties().shader_group_handle_size as usize;
+        let mut group_handles = vec![0u8; handle_size * self.group_count()];
+
+        let ray_tracing_fn = self.logical_device.ray_tracing_fn().unwrap();
+        let result = unsafe {
+            ray_tracing_fn.get_ray_tracing_shader_group_handles_nv(
+                self.logical_device.handle(),
+                *self.vk_pipeline,
+                0,
+                self.group_count() as u32,
+                group_handles.len(),
+                group_handles.as_mut_ptr() as *mut std::ffi::c_void)
+        };
+
+        if result != vk::Result::SUCCESS {
+            return Err(VulkanError::ShaderGroupHandlesError {result});
+        }
+
+        self.group_handles.set(group_handles);
+        Ok(())
+    }
+
+    fn create_ray_tracing_pipeline(&mut self) {
+        let pipeline = Pipeline::from_raw_parts(
+            (self.vk_pipeline.take(), self.pipeline_layout.take()),
+            vk::PipelineBindPoint::RAY_TRACING_NV,
+            self.push_constant_ranges(),
+            std::mem::take(&mut self.descriptor_set_layouts),
+            Rc::clone(&self.logical_device));
+
+        self.ray_tracing_pipeline.set(RayTracingPipeline {
+            pipeline: Rc::new(pipeline),
+            miss_count: self.miss_shaders.len(),
+            hit_group_count: self.hit_groups.len(),
+            group_handles: self.group_handles.take(),
+            group_handle_size: self.properties().shader_group_handle_size as usize,
+            group_base_alignment: self.properties().shader_group_base_alignment as usize
+        });
+    }
+}
+
+fn general_group(shader: u32) -> vk::RayTracingShaderGroupCreateInfoNV {
+    vk::RayTracingShaderGroupCreateInfoNV::builder()
+        .ty(vk::RayTracingShaderGroupTypeNV::GENERAL)
+        .general_shader(shader)
+        .closest_hit_shader(vk::SHADER_UNUSED_NV)
+        .any_hit_shader(vk::SHADER_UNUSED_NV)
+        .intersection_shader(vk::SHADER_UNUSED_NV)
+        .build()
+}
+
+// Region of a shader binding table, in bytes
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct ShaderBindingRegion {
+    pub offset: vk::DeviceSize,
+    pub stride: vk::DeviceSize
+}
+
+// Handles of a pipeline's shader groups in a buffer, for `CommandBufferRecorder::trace_rays`.
+// Its regions hold the ray generation shader, the miss shaders and the hit groups.
+pub struct ShaderBindingTable {
+    buffer: Rc<Buffer>,
+    pipeline: Rc<Pipeline>,
+    ray_gen_region: ShaderBindingRegion,
+    miss_region: ShaderBindingRegion,
+    hit_region: ShaderBindingRegion
+}
+
+impl ShaderBindingTable {
+    pub fn new(allocator: Rc<Allocator>, pipeline: &RayTracingPipeline) -> VulkanResult<Self> {
+        let alignment = pipeline.group_base_alignment;
+        let handle_size = pipeline.group_handle_size;
+        let region_size = |group_count: usize| {
+            (group_count * handle_size + alignment - 1) / alignment * alignment
+        };
+
+        let miss_offset = region_size(1);
+        let hit_offset = miss_offset + region_size(pipeline.miss_count);
+        let size = hit_offset + region_size(pipeline.hit_group_count);
+
+        let mut data = vec![0u8; size];
+        let group_offsets = std::iter::once(0)
+            .chain((0..pipeline.miss_count).map(|miss| miss_offset + miss * handle_size))
+            .chain((0..pipeline.hit_group_count).map(|hit| hit_offset + hit * handle_size));
+
+        for (group, offset) in group_offsets.enumerate() {
+            data[offset..offset + handle_size].copy_from_slice(pipeline.group_handle(group));
+        }
+
+        let mut buffer = Buffer::builder()
+            .allocator(allocator)
+            .size(size)
+            .usage(vk::BufferUsageFlags::RAY_TRACING_NV)
+            .memory_usage(MemoryUsage::CpuToGpu)
+            .host_mapped(true)
+            .build()?;
+
+        buffer.write_data(0, &data)?;
+
+        let region = |offset: usize| ShaderBindingRegion {
+            offset: offset as vk::DeviceSize,
+            stride: handle_size as vk::DeviceSize
+        };
+
+        Ok(Self {
+            buffer: Rc::new(buffer),
+            pipeline: Rc::clone(&pipeline.pipeline),
+            ray_gen_region: region(0),
+            miss_region: region(miss_offset),
+            hit_region: region(hit_offset)
+        })
+    }
+
+    pub fn pipeline(&self) -> &Rc<Pipeline> {
+        &self.pipeline
+    }
+
+    pub(crate) fn buffer(&self) -> &Rc<Buffer> {
+        &self.buffer
+    }
+
+    pub(crate) fn regions(
+        &self
+    ) -> (ShaderBindingRegion, ShaderBindingRegion, ShaderBindingRegion) {
+        (self.ray_gen_region, self.miss_region, self.hit_region)
+    }
+}
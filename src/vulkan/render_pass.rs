@@ -12,6 +12,7 @@ use crate::{
     vulkan::{
         VulkanError,
         VulkanResult,
+        handle::VulkanHandle,
         logical_device::LogicalDevice,
         swapchain::Swapchain
     }
@@ -19,6 +20,8 @@ use crate::{
 
 pub struct RenderPass {
     vk_render_pass: vk::RenderPass,
+    // Bit of every view rendered at once with multiview, 0 without it
+    view_mask: u32,
     logical_device: Rc<LogicalDevice>
 }
 
@@ -29,7 +32,225 @@ impl RenderPass {
         }
     }
 
-    pub fn handle(&self) -> vk::RenderPass {
+    // Single subpass writing only a cleared depth attachment, e.g. of a shadow map
+    pub fn depth_only(
+        logical_device: Rc<LogicalDevice>,
+        format: vk::Format
+    ) -> VulkanResult<RenderPass> {
+        Self::offscreen(logical_device, &[], Some(format))
+    }
+
+    // Single subpass writing cleared color attachments in the order of their formats,
+    // followed by the depth attachment if there is one. The attachments are left
+    // in SHADER_READ_ONLY_OPTIMAL, ready to be sampled by fragment shaders, which also
+    // have to finish sampling them before they are cleared again.
+    pub fn offscreen(
+        logical_device: Rc<LogicalDevice>,
+        color_formats: &[vk::Format],
+        depth_format: Option<vk::Format>
+    ) -> VulkanResult<RenderPass> {
+        Self::offscreen_multiview(logical_device, color_formats, depth_format, 0)
+    }
+
+    // Like `offscreen`, but every draw renders to each array layer whose bit is set in the mask,
+    // e.g. 0b11 for both eyes of a stereo image or 0b111111 for all faces of a cube map.
+    // Shaders tell the views apart with gl_ViewIndex.
+    pub fn offscreen_multiview(
+        logical_device: Rc<LogicalDevice>,
+        color_formats: &[vk::Format],
+        depth_format: Option<vk::Format>,
+        view_mask: u32
+    ) -> VulkanResult<RenderPass> {
+        Self::single_subpass(
+            logical_device,
+            color_formats,
+            depth_format,
+            (vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+            view_mask)
+    }
+
+    // Like `offscreen`, but leaves the attachments in their attachment layouts,
+    // e.g. for swapchain images of an OpenXR runtime, which expects them back in those
+    pub fn external(
+        logical_device: Rc<LogicalDevice>,
+        color_formats: &[vk::Format],
+        depth_format: Option<vk::Format>
+    ) -> VulkanResult<RenderPass> {
+        Self::external_multiview(logical_device, color_formats, depth_format, 0)
+    }
+
+    // Like `external`, with views as in `offscreen_multiview`
+    pub fn external_multiview(
+        logical_device: Rc<LogicalDevice>,
+        color_formats: &[vk::Format],
+        depth_format: Option<vk::Format>,
+        view_mask: u32
+    ) -> VulkanResult<RenderPass> {
+        Self::single_subpass(
+            logical_device,
+            color_formats,
+            depth_format,
+            (
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL
+            ),
+            view_mask)
+    }
+
+    fn single_subpass(
+        logical_device: Rc<LogicalDevice>,
+        color_formats: &[vk::Format],
+        depth_format: Option<vk::Format>,
+        (color_final_layout, depth_final_layout): (vk::ImageLayout, vk::ImageLayout),
+        view_mask: u32
+    ) -> VulkanResult<RenderPass> {
+        if view_mask != 0 {
+            Self::check_view_mask(&logical_device, view_mask)?;
+        }
+
+        let attachment_descriptions: Vec<vk::AttachmentDescription> = color_formats
+            .iter()
+            .chain(depth_format.iter())
+            .enumerate()
+            .map(|(attachment, format)| {
+                let final_layout = if attachment < color_formats.len() {
+                    color_final_layout
+                }
+                else {
+                    depth_final_layout
+                };
+
+                vk::AttachmentDescription::builder()
+                    .format(*format)
+                    .samples(vk::SampleCountFlags::TYPE_1)
+                    .load_op(vk::AttachmentLoadOp::CLEAR)
+                    .store_op(vk::AttachmentStoreOp::STORE)
+                    .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+                    .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+                    .initial_layout(vk::ImageLayout::UNDEFINED)
+                    .final_layout(final_layout)
+                    .build()
+            })
+            .collect();
+
+        let color_attachment_references: Vec<vk::AttachmentReference> = (0..color_formats.len())
+            .map(|attachment| {
+                vk::AttachmentReference::builder()
+                    .attachment(attachment as u32)
+                    .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+                    .build()
+            })
+            .collect();
+
+        let depth_attachment_reference = vk::AttachmentReference::builder()
+            .attachment(color_formats.len() as u32)
+            .layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+            .build();
+
+        let mut subpass_description = vk::SubpassDescription::builder()
+            .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+            .color_attachments(&color_attachment_references);
+
+        let mut attachment_stages = vk::PipelineStageFlags::empty();
+        let mut attachment_writes = vk::AccessFlags::empty();
+
+        if !color_formats.is_empty() {
+            attachment_stages |= vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT;
+            attachment_writes |= vk::AccessFlags::COLOR_ATTACHMENT_WRITE;
+        }
+
+        if depth_format.is_some() {
+            subpass_description =
+                subpass_description.depth_stencil_attachment(&depth_attachment_reference);
+            attachment_stages |= vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+                | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS;
+            attachment_writes |= vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE;
+        }
+
+        let subpass_descriptions = [subpass_description.build()];
+
+        let subpass_dependencies = [
+            vk::SubpassDependency::builder()
+                .src_subpass(vk::SUBPASS_EXTERNAL)
+                .dst_subpass(0)
+                .src_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER)
+                .src_access_mask(vk::AccessFlags::SHADER_READ)
+                .dst_stage_mask(attachment_stages)
+                .dst_access_mask(attachment_writes)
+                .build(),
+            vk::SubpassDependency::builder()
+                .src_subpass(0)
+                .dst_subpass(vk::SUBPASS_EXTERNAL)
+                .src_stage_mask(attachment_stages)
+                .src_access_mask(attachment_writes)
+                .dst_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER)
+                .dst_access_mask(vk::AccessFlags::SHADER_READ)
+                .build()
+        ];
+
+        // Views are assumed to be seen from similar points, which lets drivers share work
+        // between them, e.g. of stereo images
+        let view_masks = [view_mask];
+        let mut multiview_create_info = vk::RenderPassMultiviewCreateInfo::builder()
+            .view_masks(&view_masks)
+            .correlation_masks(&view_masks);
+
+        let mut render_pass_create_info = vk::RenderPassCreateInfo::builder()
+            .attachments(&attachment_descriptions)
+            .subpasses(&subpass_descriptions)
+            .dependencies(&subpass_dependencies);
+
+        if view_mask != 0 {
+            render_pass_create_info = render_pass_create_info.push_next(&mut multiview_create_info);
+        }
+
+        let mut render_pass = Self::from_create_info(logical_device, &render_pass_create_info)?;
+        render_pass.view_mask = view_mask;
+
+        Ok(render_pass)
+    }
+
+    fn check_view_mask(logical_device: &LogicalDevice, view_mask: u32) -> VulkanResult<()> {
+        let max_view_count = logical_device
+            .physical_device()
+            .max_multiview_view_count()
+            .filter(|_| logical_device.is_multiview_enabled())
+            .ok_or(VulkanError::MultiviewNotEnabled)?;
+
+        // Views are numbered by their bits, so the highest one decides how many there are
+        let view_count = u32::BITS - view_mask.leading_zeros();
+        if view_count > max_view_count {
+            return Err(VulkanError::MultiviewTooManyViews {view_count, max_view_count});
+        }
+
+        Ok(())
+    }
+
+    pub fn view_mask(&self) -> u32 {
+        self.view_mask
+    }
+
+    // For render passes laid out by higher level presets, e.g. ones with several subpasses
+    pub fn from_create_info(
+        logical_device: Rc<LogicalDevice>,
+        create_info: &vk::RenderPassCreateInfo
+    ) -> VulkanResult<RenderPass> {
+        let vk_render_pass = unsafe {
+            logical_device.create_render_pass(create_info, None)
+        }.map_err(|result| VulkanError::RenderPassCreateError {result})?;
+
+        Ok(RenderPass {
+            vk_render_pass,
+            view_mask: 0,
+            logical_device
+        })
+    }
+}
+
+impl VulkanHandle for RenderPass {
+    type Handle = vk::RenderPass;
+
+    fn handle(&self) -> Self::Handle {
         self.vk_render_pass
     }
 }
@@ -46,6 +267,8 @@ impl Drop for RenderPass {
 pub struct RenderPassBuilder {
     swapchain: BuilderRequirement<Rc<Swapchain>>,
     logical_device: BuilderRequirement<Rc<LogicalDevice>>,
+    load_op: Option<vk::AttachmentLoadOp>,
+    store_op: Option<vk::AttachmentStoreOp>,
 
     attachment_descriptions: BuilderInternal<Vec<vk::AttachmentDescription>>,
     attachment_references: BuilderInternal<Vec<vk::AttachmentReference>>,
@@ -67,7 +290,24 @@ impl RenderPassBuilder {
         self
     }
 
+    // Defaults to CLEAR. Passes drawing over what earlier passes left in the swapchain
+    // image, e.g. a UI overlay recorded after the main pass, LOAD it instead, which
+    // requires the image to be in the present layout when the pass begins.
+    // DONT_CARE suits passes which overwrite every pixel anyway.
+    pub fn load_op(mut self, load_op: vk::AttachmentLoadOp) -> Self {
+        self.load_op = Some(load_op);
+        self
+    }
+
+    // Defaults to STORE, which is what presenting the image needs
+    pub fn store_op(mut self, store_op: vk::AttachmentStoreOp) -> Self {
+        self.store_op = Some(store_op);
+        self
+    }
+
     pub fn build(mut self) -> VulkanResult<RenderPass> {
+        check_required_fields!(self, VulkanError, "RenderPassBuilder", [swapchain, logical_device]);
+
         self.get_ready_for_creation()?;
         self.create_render_pass();
 
@@ -84,15 +324,27 @@ impl RenderPassBuilder {
         Ok(())
     }
 
+    fn is_loading(&self) -> bool {
+        self.load_op == Some(vk::AttachmentLoadOp::LOAD)
+    }
+
     fn init_attachment_descriptions(&mut self) {
+        // Contents which are not loaded can be discarded by the transition
+        let initial_layout = if self.is_loading() {
+            vk::ImageLayout::PRESENT_SRC_KHR
+        }
+        else {
+            vk::ImageLayout::UNDEFINED
+        };
+
         let attachment_description = vk::AttachmentDescription::builder()
             .format(self.swapchain.image_format())
             .samples(vk::SampleCountFlags::TYPE_1)
-            .load_op(vk::AttachmentLoadOp::CLEAR)
-            .store_op(vk::AttachmentStoreOp::STORE)
+            .load_op(self.load_op.unwrap_or(vk::AttachmentLoadOp::CLEAR))
+            .store_op(self.store_op.unwrap_or(vk::AttachmentStoreOp::STORE))
             .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
             .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
-            .initial_layout(vk::ImageLayout::UNDEFINED)
+            .initial_layout(initial_layout)
             .final_layout(vk::ImageLayout::PRESENT_SRC_KHR)
             .build();
 
@@ -121,13 +373,24 @@ impl RenderPassBuilder {
     }
 
     fn init_subpass_dependencies(&mut self) {
+        // Loaded contents have to be written by the earlier passes before they are read
+        let (src_access_mask, dst_access_mask) = if self.is_loading() {
+            (
+                vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+            )
+        }
+        else {
+            (vk::AccessFlags::empty(), vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+        };
+
         let subpass_dependency = vk::SubpassDependency::builder()
             .src_subpass(vk::SUBPASS_EXTERNAL)
             .dst_subpass(0)
             .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
-            .src_access_mask(vk::AccessFlags::empty())
+            .src_access_mask(src_access_mask)
             .dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
-            .dst_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+            .dst_access_mask(dst_access_mask)
             .build();
 
         let subpass_dependencies = vec![subpass_dependency];
@@ -153,6 +416,7 @@ impl RenderPassBuilder {
     fn create_render_pass(&mut self) {
         self.render_pass.set(RenderPass {
             vk_render_pass: self.vk_render_pass.take(),
+            view_mask: 0,
             logical_device: self.logical_device.take()
         });
     }
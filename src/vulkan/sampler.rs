@@ -0,0 +1,119 @@
+use std::rc::Rc;
+use ash::{
+    version::DeviceV1_0,
+    vk
+};
+use crate::vulkan::{
+    VulkanError,
+    VulkanResult,
+    handle::VulkanHandle,
+    logical_device::LogicalDevice
+};
+
+pub struct Sampler {
+    vk_sampler: vk::Sampler,
+    logical_device: Rc<LogicalDevice>
+}
+
+impl Sampler {
+    // Repeats the texture and blends linearly between all of its mip levels,
+    // `filter` is used for magnification and minification
+    pub fn new(
+        logical_device: Rc<LogicalDevice>,
+        filter: vk::Filter,
+        mip_levels: u32
+    ) -> VulkanResult<Self> {
+        let create_info = vk::SamplerCreateInfo::builder()
+            .mag_filter(filter)
+            .min_filter(filter)
+            .mipmap_mode(vk::SamplerMipmapMode::LINEAR)
+            .address_mode_u(vk::SamplerAddressMode::REPEAT)
+            .address_mode_v(vk::SamplerAddressMode::REPEAT)
+            .address_mode_w(vk::SamplerAddressMode::REPEAT)
+            .anisotropy_enable(false)
+            .max_anisotropy(1.0)
+            .compare_enable(false)
+            .min_lod(0.0)
+            .max_lod(mip_levels as f32)
+            .unnormalized_coordinates(false);
+
+        Self::from_create_info(logical_device, &create_info)
+    }
+
+    // Clamps coordinates to the edges of an image without mip levels, e.g. of a render target
+    // which is stretched over another one, so that the opposite edge does not bleed in
+    pub fn clamped(logical_device: Rc<LogicalDevice>, filter: vk::Filter) -> VulkanResult<Self> {
+        let create_info = vk::SamplerCreateInfo::builder()
+            .mag_filter(filter)
+            .min_filter(filter)
+            .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .anisotropy_enable(false)
+            .max_anisotropy(1.0)
+            .compare_enable(false)
+            .min_lod(0.0)
+            .max_lod(0.0)
+            .unnormalized_coordinates(false);
+
+        Self::from_create_info(logical_device, &create_info)
+    }
+
+    // Compares depth references with a depth image, e.g. a shadow map, through
+    // a `sampler2DArrayShadow`. Coordinates outside of the image compare against
+    // the farthest depth, a LINEAR filter blends the results of 2x2 texels.
+    pub fn depth_comparison(
+        logical_device: Rc<LogicalDevice>,
+        filter: vk::Filter,
+        compare_op: vk::CompareOp
+    ) -> VulkanResult<Self> {
+        let create_info = vk::SamplerCreateInfo::builder()
+            .mag_filter(filter)
+            .min_filter(filter)
+            .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_BORDER)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_BORDER)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_BORDER)
+            .border_color(vk::BorderColor::FLOAT_OPAQUE_WHITE)
+            .anisotropy_enable(false)
+            .max_anisotropy(1.0)
+            .compare_enable(true)
+            .compare_op(compare_op)
+            .min_lod(0.0)
+            .max_lod(0.0)
+            .unnormalized_coordinates(false);
+
+        Self::from_create_info(logical_device, &create_info)
+    }
+
+    fn from_create_info(
+        logical_device: Rc<LogicalDevice>,
+        create_info: &vk::SamplerCreateInfo
+    ) -> VulkanResult<Self> {
+        let vk_sampler = unsafe {
+            logical_device.create_sampler(create_info, None)
+        }.map_err(|result| VulkanError::SamplerCreateError {result})?;
+
+        Ok(Self {
+            vk_sampler,
+            logical_device
+        })
+    }
+}
+
+impl VulkanHandle for Sampler {
+    type Handle = vk::Sampler;
+
+    fn handle(&self) -> Self::Handle {
+        self.vk_sampler
+    }
+}
+
+impl Drop for Sampler {
+    fn drop(&mut self) {
+        unsafe {
+            self.logical_device.destroy_sampler(self.vk_sampler, None);
+        }
+    }
+}
@@ -3,7 +3,11 @@ use std::{
     fs,
     mem,
     rc::Rc,
-    io::Read
+    io::Read,
+    sync::atomic::{
+        AtomicU64,
+        Ordering
+    }
 };
 use ash::{
     self,
@@ -15,9 +19,12 @@ use crate::{
     vulkan::{
         VulkanResult,
         VulkanError,
+        handle::VulkanHandle,
         logical_device::LogicalDevice
     }
 };
+#[cfg(feature = "shader-reflection")]
+use crate::vulkan::shader_reflection::ShaderReflection;
 
 macro_rules! create_shader_wrapper {
     ($name:ident, $shader_stage:expr) => {
@@ -38,7 +45,9 @@ macro_rules! create_shader_wrapper {
         }
 
         impl ShaderStageBuilder for $name {
-            fn shader_stage_create_info_builder(&self) -> vk::PipelineShaderStageCreateInfoBuilder {
+            fn shader_stage_create_info_builder(
+                &self
+            ) -> vk::PipelineShaderStageCreateInfoBuilder<'_> {
                 self.0.shader_stage_create_info_builder()
             }
         }
@@ -74,11 +83,22 @@ macro_rules! create_shader_wrapper {
 create_shader_wrapper!(VertexShader, vk::ShaderStageFlags::VERTEX);
 create_shader_wrapper!(FragmentShader, vk::ShaderStageFlags::FRAGMENT);
 create_shader_wrapper!(GeometryShader, vk::ShaderStageFlags::GEOMETRY);
+create_shader_wrapper!(ComputeShader, vk::ShaderStageFlags::COMPUTE);
+create_shader_wrapper!(RayGenShader, vk::ShaderStageFlags::RAYGEN_NV);
+create_shader_wrapper!(MissShader, vk::ShaderStageFlags::MISS_NV);
+create_shader_wrapper!(ClosestHitShader, vk::ShaderStageFlags::CLOSEST_HIT_NV);
+create_shader_wrapper!(AnyHitShader, vk::ShaderStageFlags::ANY_HIT_NV);
+
+static NEXT_SHADER_ID: AtomicU64 = AtomicU64::new(0);
 
 pub struct Shader {
+    // Unlike module handles, ids are never reused after a shader is destroyed
+    id: u64,
     vk_shader_module: vk::ShaderModule,
     logical_device: Rc<LogicalDevice>,
-    shader_stage: vk::ShaderStageFlags
+    shader_stage: vk::ShaderStageFlags,
+    #[cfg(feature = "shader-reflection")]
+    reflection: ShaderReflection
 }
 
 impl Shader {
@@ -90,12 +110,17 @@ impl Shader {
         shader_stage: vk::ShaderStageFlags
     ) -> VulkanResult<Self> {
         let buffer = Self::load_file_to_buffer(file_path)?;
+        #[cfg(feature = "shader-reflection")]
+        let reflection = ShaderReflection::parse(buffer.as_slice_second())?;
         let vk_shader_module = Self::create_shader_module(&logical_device, &buffer)?;
 
         Ok(Self {
+            id: NEXT_SHADER_ID.fetch_add(1, Ordering::Relaxed),
             logical_device,
             vk_shader_module,
-            shader_stage
+            shader_stage,
+            #[cfg(feature = "shader-reflection")]
+            reflection
         })
     }
 
@@ -106,7 +131,7 @@ impl Shader {
             .map_err(|error| VulkanError::ShaderOpenFileError {error})?;
 
         let mut buffer = Self::create_buffer_for_file(&file)?;
-        Self::read_file_to_buffer(&mut buffer, &mut file);
+        Self::read_file_to_buffer(&mut buffer, &mut file)?;
 
         Ok(buffer)
     }
@@ -122,15 +147,21 @@ impl Shader {
         Ok(DoubleTypeBuffer::with_lengths::<u8, u32>(buffer_size, u32_slice_length))
     }
 
-    fn read_file_to_buffer(buffer: &mut DoubleTypeBuffer<u8, u32>, file: &mut fs::File) {
+    fn read_file_to_buffer(
+        buffer: &mut DoubleTypeBuffer<u8, u32>,
+        file: &mut fs::File
+    ) -> VulkanResult<()> {
         let buffer_slice_u8 = buffer.as_mut_slice_first();
         let mut eof_reached = false;
         let mut current_byte = 0;
         while !eof_reached {
-            let bytes_read = file.read(&mut buffer_slice_u8[current_byte..]).unwrap();
+            let bytes_read = file.read(&mut buffer_slice_u8[current_byte..])
+                .map_err(|error| VulkanError::ShaderOpenFileError {error})?;
             current_byte += bytes_read;
             eof_reached = bytes_read == 0;
         }
+
+        Ok(())
     }
 
     fn create_shader_module(
@@ -148,7 +179,16 @@ impl Shader {
         })
     }
 
-    pub fn shader_stage_create_info_builder(&self) -> vk::PipelineShaderStageCreateInfoBuilder {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    #[cfg(feature = "shader-reflection")]
+    pub fn reflection(&self) -> &ShaderReflection {
+        &self.reflection
+    }
+
+    pub fn shader_stage_create_info_builder(&self) -> vk::PipelineShaderStageCreateInfoBuilder<'_> {
         let entry_point_name =
             std::ffi::CStr::from_bytes_with_nul(Self::SHADER_STAGE_ENTRY_POINT_NAME).unwrap();
 
@@ -160,11 +200,19 @@ impl Shader {
 }
 
 impl ShaderStageBuilder for Shader {
-    fn shader_stage_create_info_builder(&self) -> vk::PipelineShaderStageCreateInfoBuilder {
+    fn shader_stage_create_info_builder(&self) -> vk::PipelineShaderStageCreateInfoBuilder<'_> {
         self.shader_stage_create_info_builder()
     }
 }
 
+impl VulkanHandle for Shader {
+    type Handle = vk::ShaderModule;
+
+    fn handle(&self) -> Self::Handle {
+        self.vk_shader_module
+    }
+}
+
 impl Drop for Shader {
     fn drop(&mut self) {
         unsafe {
@@ -174,22 +222,42 @@ impl Drop for Shader {
 }
 
 pub trait ShaderStageBuilder {
-    fn shader_stage_create_info_builder(&self) -> vk::PipelineShaderStageCreateInfoBuilder;
+    fn shader_stage_create_info_builder(&self) -> vk::PipelineShaderStageCreateInfoBuilder<'_>;
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub enum ShaderStage {
     Geometry,
     Vertex,
-    Fragment
+    Fragment,
+    Compute,
+    RayGen,
+    Miss,
+    ClosestHit,
+    AnyHit
+}
+
+impl ShaderStage {
+    // Stages of graphics pipelines, compute and ray tracing shaders make up pipelines
+    // of their own
+    pub const ALL: [ShaderStage; 3] = [
+        ShaderStage::Geometry,
+        ShaderStage::Vertex,
+        ShaderStage::Fragment
+    ];
 }
 
-impl Into<vk::ShaderStageFlags> for ShaderStage {
-    fn into(self) -> vk::ShaderStageFlags {
-        match self {
+impl From<ShaderStage> for vk::ShaderStageFlags {
+    fn from(shader_stage: ShaderStage) -> Self {
+        match shader_stage {
             ShaderStage::Geometry => vk::ShaderStageFlags::GEOMETRY,
             ShaderStage::Vertex => vk::ShaderStageFlags::VERTEX,
             ShaderStage::Fragment => vk::ShaderStageFlags::FRAGMENT,
+            ShaderStage::Compute => vk::ShaderStageFlags::COMPUTE,
+            ShaderStage::RayGen => vk::ShaderStageFlags::RAYGEN_NV,
+            ShaderStage::Miss => vk::ShaderStageFlags::MISS_NV,
+            ShaderStage::ClosestHit => vk::ShaderStageFlags::CLOSEST_HIT_NV,
+            ShaderStage::AnyHit => vk::ShaderStageFlags::ANY_HIT_NV,
         }
     }
 }
@@ -0,0 +1,355 @@
+use std::collections::HashMap;
+use ash::vk;
+use crate::vulkan::{
+    VulkanError,
+    VulkanResult
+};
+
+// Minimal SPIR-V reflection, extracting only what is needed to validate
+// pipelines against the shaders they are built from
+pub struct ShaderReflection {
+    push_constants_size: Option<u32>,
+    descriptor_bindings: Vec<DescriptorBinding>,
+    inputs: Vec<ShaderInput>
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct DescriptorBinding {
+    pub set: u32,
+    pub binding: u32
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ShaderInput {
+    pub location: u32,
+    // `None` for inputs which cannot be fed by a single vertex attribute (matrices, structs)
+    pub format: Option<vk::Format>
+}
+
+impl ShaderReflection {
+    pub fn parse(code: &[u32]) -> VulkanResult<Self> {
+        let module = SpirvModule::parse(code)?;
+
+        Ok(Self {
+            push_constants_size: module.push_constants_size()?,
+            descriptor_bindings: module.descriptor_bindings(),
+            inputs: module.inputs()
+        })
+    }
+
+    pub fn push_constants_size(&self) -> Option<u32> {
+        self.push_constants_size
+    }
+
+    pub fn descriptor_bindings(&self) -> &[DescriptorBinding] {
+        &self.descriptor_bindings
+    }
+
+    pub fn inputs(&self) -> &[ShaderInput] {
+        &self.inputs
+    }
+}
+
+enum SpirvType {
+    Int {width: u32, signed: bool},
+    Float {width: u32},
+    Vector {component: u32, count: u32},
+    Matrix {column: u32, count: u32},
+    Array {element: u32, length: u32},
+    Struct {members: Vec<u32>},
+    Pointer {pointee: u32}
+}
+
+struct SpirvVariable {
+    id: u32,
+    pointer_type: u32,
+    storage_class: u32
+}
+
+#[derive(Default)]
+struct SpirvModule {
+    types: HashMap<u32, SpirvType>,
+    constants: HashMap<u32, u32>,
+    decorations: HashMap<(u32, u32), u32>,
+    member_decorations: HashMap<(u32, u32, u32), u32>,
+    variables: Vec<SpirvVariable>
+}
+
+impl SpirvModule {
+    const MAGIC_NUMBER: u32 = 0x0723_0203;
+    const HEADER_LENGTH: usize = 5;
+
+    const OP_DECORATE: u32 = 71;
+    const OP_MEMBER_DECORATE: u32 = 72;
+    const OP_TYPE_INT: u32 = 21;
+    const OP_TYPE_FLOAT: u32 = 22;
+    const OP_TYPE_VECTOR: u32 = 23;
+    const OP_TYPE_MATRIX: u32 = 24;
+    const OP_TYPE_ARRAY: u32 = 28;
+    const OP_TYPE_STRUCT: u32 = 30;
+    const OP_TYPE_POINTER: u32 = 32;
+    const OP_CONSTANT: u32 = 43;
+    const OP_VARIABLE: u32 = 59;
+
+    const DECORATION_ARRAY_STRIDE: u32 = 6;
+    const DECORATION_MATRIX_STRIDE: u32 = 7;
+    const DECORATION_BUILT_IN: u32 = 11;
+    const DECORATION_LOCATION: u32 = 30;
+    const DECORATION_BINDING: u32 = 33;
+    const DECORATION_DESCRIPTOR_SET: u32 = 34;
+    const DECORATION_OFFSET: u32 = 35;
+
+    const STORAGE_CLASS_UNIFORM_CONSTANT: u32 = 0;
+    const STORAGE_CLASS_INPUT: u32 = 1;
+    const STORAGE_CLASS_UNIFORM: u32 = 2;
+    const STORAGE_CLASS_PUSH_CONSTANT: u32 = 9;
+    const STORAGE_CLASS_STORAGE_BUFFER: u32 = 12;
+
+    fn parse(code: &[u32]) -> VulkanResult<Self> {
+        if code.len() < Self::HEADER_LENGTH || code[0] != Self::MAGIC_NUMBER {
+            return Err(Self::error("missing SPIR-V header"));
+        }
+
+        let mut module = Self::default();
+        let mut position = Self::HEADER_LENGTH;
+
+        while position < code.len() {
+            let word_count = (code[position] >> 16) as usize;
+            let opcode = code[position] & 0xffff;
+
+            if word_count == 0 || position + word_count > code.len() {
+                return Err(Self::error("instruction runs past the end of the module"));
+            }
+
+            module.add_instruction(opcode, &code[position + 1..position + word_count])?;
+            position += word_count;
+        }
+
+        Ok(module)
+    }
+
+    fn add_instruction(&mut self, opcode: u32, operands: &[u32]) -> VulkanResult<()> {
+        let operand = |index: usize| {
+            operands
+                .get(index)
+                .copied()
+                .ok_or_else(|| Self::error("instruction is missing operands"))
+        };
+
+        match opcode {
+            Self::OP_DECORATE => {
+                let value = operand(2).unwrap_or(0);
+                self.decorations.insert((operand(0)?, operand(1)?), value);
+            },
+            Self::OP_MEMBER_DECORATE => {
+                let value = operand(3).unwrap_or(0);
+                self.member_decorations.insert((operand(0)?, operand(1)?, operand(2)?), value);
+            },
+            Self::OP_TYPE_INT => {
+                self.types.insert(operand(0)?, SpirvType::Int {
+                    width: operand(1)?,
+                    signed: operand(2)? != 0
+                });
+            },
+            Self::OP_TYPE_FLOAT => {
+                self.types.insert(operand(0)?, SpirvType::Float {width: operand(1)?});
+            },
+            Self::OP_TYPE_VECTOR => {
+                self.types.insert(operand(0)?, SpirvType::Vector {
+                    component: operand(1)?,
+                    count: operand(2)?
+                });
+            },
+            Self::OP_TYPE_MATRIX => {
+                self.types.insert(operand(0)?, SpirvType::Matrix {
+                    column: operand(1)?,
+                    count: operand(2)?
+                });
+            },
+            Self::OP_TYPE_ARRAY => {
+                let length = *self.constants
+                    .get(&operand(2)?)
+                    .ok_or_else(|| Self::error("array length is not a constant"))?;
+
+                self.types.insert(operand(0)?, SpirvType::Array {
+                    element: operand(1)?,
+                    length
+                });
+            },
+            Self::OP_TYPE_STRUCT => {
+                self.types.insert(operand(0)?, SpirvType::Struct {
+                    members: operands[1..].to_vec()
+                });
+            },
+            Self::OP_TYPE_POINTER => {
+                self.types.insert(operand(0)?, SpirvType::Pointer {pointee: operand(2)?});
+            },
+            Self::OP_CONSTANT => {
+                self.constants.insert(operand(1)?, operand(2)?);
+            },
+            Self::OP_VARIABLE => {
+                self.variables.push(SpirvVariable {
+                    pointer_type: operand(0)?,
+                    id: operand(1)?,
+                    storage_class: operand(2)?
+                });
+            },
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn error(reason: &str) -> VulkanError {
+        VulkanError::ShaderReflectionError {reason: String::from(reason)}
+    }
+
+    fn pointee(&self, pointer_type: u32) -> Option<u32> {
+        match self.types.get(&pointer_type) {
+            Some(SpirvType::Pointer {pointee}) => Some(*pointee),
+            _ => None
+        }
+    }
+
+    fn push_constants_size(&self) -> VulkanResult<Option<u32>> {
+        let push_constants = self.variables
+            .iter()
+            .find(|variable| variable.storage_class == Self::STORAGE_CLASS_PUSH_CONSTANT);
+
+        let block_type = push_constants.and_then(|variable| self.pointee(variable.pointer_type));
+        let block_type = match block_type {
+            Some(block_type) => block_type,
+            None => return Ok(None)
+        };
+
+        Ok(Some(self.type_size(block_type, None)?))
+    }
+
+    fn type_size(&self, type_id: u32, matrix_stride: Option<u32>) -> VulkanResult<u32> {
+        // Types which are not tracked (bools, images, runtime arrays...) have no usable size
+        let spirv_type = self.types
+            .get(&type_id)
+            .ok_or_else(|| Self::error("block contains a type without a defined size"))?;
+
+        match spirv_type {
+            SpirvType::Int {width, ..} | SpirvType::Float {width} => Ok(width / 8),
+            SpirvType::Vector {component, count} => Ok(count * self.type_size(*component, None)?),
+            SpirvType::Matrix {column, count} => {
+                let column_size = match matrix_stride {
+                    Some(stride) => stride,
+                    None => self.type_size(*column, None)?
+                };
+
+                Ok(count * column_size)
+            },
+            SpirvType::Array {element, length} => {
+                let stride = match self.decorations.get(&(type_id, Self::DECORATION_ARRAY_STRIDE)) {
+                    Some(stride) => *stride,
+                    None => self.type_size(*element, matrix_stride)?
+                };
+
+                Ok(length * stride)
+            },
+            SpirvType::Struct {members} => self.struct_size(type_id, members),
+            _ => Err(Self::error("block contains a type without a defined size"))
+        }
+    }
+
+    fn struct_size(&self, struct_id: u32, members: &[u32]) -> VulkanResult<u32> {
+        let mut size = 0;
+
+        for (index, member) in members.iter().enumerate() {
+            let index = index as u32;
+            let matrix_stride = self.member_decorations
+                .get(&(struct_id, index, Self::DECORATION_MATRIX_STRIDE))
+                .copied();
+
+            let offset = self.member_decorations
+                .get(&(struct_id, index, Self::DECORATION_OFFSET))
+                .copied()
+                .unwrap_or(size);
+
+            size = size.max(offset + self.type_size(*member, matrix_stride)?);
+        }
+
+        Ok(size)
+    }
+
+    fn descriptor_bindings(&self) -> Vec<DescriptorBinding> {
+        self.variables
+            .iter()
+            .filter(|variable| {
+                variable.storage_class == Self::STORAGE_CLASS_UNIFORM_CONSTANT ||
+                variable.storage_class == Self::STORAGE_CLASS_UNIFORM ||
+                variable.storage_class == Self::STORAGE_CLASS_STORAGE_BUFFER
+            })
+            .filter_map(|variable| {
+                let binding = self.decorations.get(&(variable.id, Self::DECORATION_BINDING))?;
+                let set = self.decorations
+                    .get(&(variable.id, Self::DECORATION_DESCRIPTOR_SET))
+                    .copied()
+                    .unwrap_or(0);
+
+                Some(DescriptorBinding {set, binding: *binding})
+            })
+            .collect()
+    }
+
+    fn inputs(&self) -> Vec<ShaderInput> {
+        let mut inputs: Vec<ShaderInput> = self.variables
+            .iter()
+            .filter(|variable| variable.storage_class == Self::STORAGE_CLASS_INPUT)
+            .filter(|variable| {
+                !self.decorations.contains_key(&(variable.id, Self::DECORATION_BUILT_IN))
+            })
+            .filter_map(|variable| {
+                let location = self.decorations.get(&(variable.id, Self::DECORATION_LOCATION))?;
+                let format = self
+                    .pointee(variable.pointer_type)
+                    .and_then(|input_type| self.input_format(input_type));
+
+                Some(ShaderInput {location: *location, format})
+            })
+            .collect();
+
+        inputs.sort_by_key(|input| input.location);
+        inputs
+    }
+
+    fn input_format(&self, type_id: u32) -> Option<vk::Format> {
+        let (component, count) = match self.types.get(&type_id)? {
+            SpirvType::Vector {component, count} => (self.types.get(component)?, *count),
+            scalar => (scalar, 1)
+        };
+
+        let formats = match component {
+            SpirvType::Int {width: 32, signed: true} => [
+                vk::Format::R32_SINT,
+                vk::Format::R32G32_SINT,
+                vk::Format::R32G32B32_SINT,
+                vk::Format::R32G32B32A32_SINT
+            ],
+            SpirvType::Int {width: 32, signed: false} => [
+                vk::Format::R32_UINT,
+                vk::Format::R32G32_UINT,
+                vk::Format::R32G32B32_UINT,
+                vk::Format::R32G32B32A32_UINT
+            ],
+            SpirvType::Float {width: 32} => [
+                vk::Format::R32_SFLOAT,
+                vk::Format::R32G32_SFLOAT,
+                vk::Format::R32G32B32_SFLOAT,
+                vk::Format::R32G32B32A32_SFLOAT
+            ],
+            SpirvType::Float {width: 64} => [
+                vk::Format::R64_SFLOAT,
+                vk::Format::R64G64_SFLOAT,
+                vk::Format::R64G64B64_SFLOAT,
+                vk::Format::R64G64B64A64_SFLOAT
+            ],
+            _ => return None
+        };
+
+        formats.get(count.checked_sub(1)? as usize).copied()
+    }
+}
@@ -1,5 +1,14 @@
-use std::rc::Rc;
-use ash;
+use std::{
+    ffi::CStr,
+    rc::Rc
+};
+use ash::{
+    self,
+    vk_make_version,
+    vk_version_major,
+    vk_version_minor,
+    version::EntryV1_0
+};
 use crate::{
     builder::{
         BuilderRequirement,
@@ -8,18 +17,26 @@ use crate::{
     },
     vulkan::{
         self,
+        VulkanError,
         VulkanResult,
-        instance::InstanceExtensions,
+        instance::{
+            Instance,
+            InstanceExtensions
+        },
         debug_utils::ValidationLayers,
+        leak_tracking
     }
 };
 
 pub struct VulkanState {
     entry: Rc<ash::Entry>,
     instance: Rc<vulkan::instance::Instance>,
-    debug_messenger: Option<vulkan::debug_utils::DebugMessenger>,
+    _debug_messenger: Option<vulkan::debug_utils::DebugMessenger>,
     debug_utils_loader: Rc<ash::extensions::ext::DebugUtils>,
-    surface_loader: Rc<ash::extensions::khr::Surface>
+    surface_loader: Rc<ash::extensions::khr::Surface>,
+    api_version: u32,
+    enabled_instance_extensions: Vec<String>,
+    missing_optional_instance_extensions: Vec<String>
 }
 
 impl VulkanState {
@@ -37,12 +54,8 @@ impl VulkanState {
         Rc::clone(&self.instance)
     }
 
-    pub fn instance_handle(&self) -> &ash::Instance {
-        self.instance.handle()
-    }
-
-    pub fn raw_instance_handle(&self) -> u64 {
-        self.instance.raw_handle()
+    pub fn instance_loader(&self) -> &ash::Instance {
+        self.instance.loader()
     }
 
     pub fn debug_utils_loader(&self) -> Rc<ash::extensions::ext::DebugUtils> {
@@ -52,24 +65,89 @@ impl VulkanState {
     pub fn surface_loader(&self) -> Rc<ash::extensions::khr::Surface> {
         Rc::clone(&self.surface_loader)
     }
+
+    // Highest version both the application asked for and the loader supports.
+    // Physical devices report their own version, which may be lower.
+    pub fn api_version(&self) -> u32 {
+        self.api_version
+    }
+
+    pub fn supports_api_version(&self, major: u32, minor: u32) -> bool {
+        self.api_version >= vk_make_version!(major, minor, 0)
+    }
+
+    // Extensions provided by the loader and implicitly enabled layers,
+    // e.g. for integrations like OpenXR to check what they can request
+    pub fn available_instance_extensions(&self) -> VulkanResult<Vec<String>> {
+        available_instance_extensions(&self.entry)
+    }
+
+    pub fn available_layers(&self) -> VulkanResult<Vec<String>> {
+        let layers = self.entry
+            .enumerate_instance_layer_properties()
+            .map_err(|result| VulkanError::EnumerateInstanceLayersError {result})?;
+
+        Ok(layers.iter().map(|layer| c_string_to_owned(&layer.layer_name)).collect())
+    }
+
+    pub fn enabled_instance_extensions(&self) -> &[String] {
+        &self.enabled_instance_extensions
+    }
+
+    pub fn is_instance_extension_enabled(&self, extension: &str) -> bool {
+        self.enabled_instance_extensions.iter().any(|enabled| enabled == extension)
+    }
+
+    // Optional extensions which were requested but left out, as they are not available
+    pub fn missing_optional_instance_extensions(&self) -> &[String] {
+        &self.missing_optional_instance_extensions
+    }
+}
+
+// Runs before the instance is destroyed, so that leaked objects are reported
+// ahead of the validation layers' messages about them
+impl Drop for VulkanState {
+    fn drop(&mut self) {
+        leak_tracking::track_vulkan_state_destruction();
+    }
+}
+
+fn available_instance_extensions(entry: &ash::Entry) -> VulkanResult<Vec<String>> {
+    let extensions = entry
+        .enumerate_instance_extension_properties()
+        .map_err(|result| VulkanError::EnumerateInstanceExtensionsError {result})?;
+
+    Ok(extensions.iter().map(|extension| c_string_to_owned(&extension.extension_name)).collect())
+}
+
+fn c_string_to_owned(characters: &[std::os::raw::c_char]) -> String {
+    unsafe {
+        CStr::from_ptr(characters.as_ptr()).to_string_lossy().into_owned()
+    }
 }
 
 #[derive(Default)]
 pub struct VulkanStateBuilder {
     debug_mode: bool,
     instance_extensions: BuilderRequirement<InstanceExtensions>,
+    optional_instance_extensions: Vec<String>,
+    requested_api_version: Option<u32>,
 
     entry: BuilderInternal<Rc<ash::Entry>>,
+    api_version: BuilderInternal<u32>,
     instance: BuilderInternal<Rc<vulkan::instance::Instance>>,
     debug_messenger: BuilderInternal<Option<vulkan::debug_utils::DebugMessenger>>,
-    validation_layers: BuilderInternal<ValidationLayers>,
     debug_utils_loader: BuilderInternal<Rc<ash::extensions::ext::DebugUtils>>,
     surface_loader: BuilderInternal<Rc<ash::extensions::khr::Surface>>,
+    enabled_instance_extensions: BuilderInternal<Vec<String>>,
+    missing_optional_instance_extensions: BuilderInternal<Vec<String>>,
 
     vulkan_state: BuilderProduct<VulkanState>
 }
 
 impl VulkanStateBuilder {
+    const VALIDATION_ENVIRONMENT_VARIABLE: &'static str = "MAGMACRAFT_VALIDATION";
+
     pub fn debug_mode(mut self, debug_mode: bool) -> Self {
         self.debug_mode = debug_mode;
         self
@@ -80,7 +158,24 @@ impl VulkanStateBuilder {
         self
     }
 
+    // Enabled only if available, missing ones are reported by
+    // `VulkanState::missing_optional_instance_extensions` instead of failing the build
+    pub fn optional_instance_extension(mut self, extension: &str) -> Self {
+        self.optional_instance_extensions.push(extension.to_owned());
+        self
+    }
+
+    // Defaults to 1.0. Loaders which support only a lower version make the instance
+    // fall back to it instead of failing, `VulkanState::api_version` tells which one is used.
+    pub fn api_version(mut self, major: u32, minor: u32) -> Self {
+        self.requested_api_version = Some(vk_make_version!(major, minor, 0));
+        self
+    }
+
     pub fn build(mut self) -> VulkanResult<VulkanState> {
+        check_required_fields!(self, VulkanError, "VulkanStateBuilder", [instance_extensions]);
+
+        self.apply_validation_override();
         self.get_ready_for_state_creation()?;
         self.create_state();
 
@@ -89,7 +184,9 @@ impl VulkanStateBuilder {
 
     fn get_ready_for_state_creation(&mut self) -> VulkanResult<()> {
         self.init_entry()?;
+        self.init_api_version()?;
         self.add_instance_debug_extension();
+        self.add_optional_instance_extensions()?;
         self.init_instance()?;
         self.init_extension_loaders();
         self.init_debug_messenger()?;
@@ -97,18 +194,80 @@ impl VulkanStateBuilder {
         Ok(())
     }
 
+    // Lets validation layers be turned on in release builds (or off in debug ones),
+    // e.g. when reporting GPU-specific bugs, without recompiling
+    fn apply_validation_override(&mut self) {
+        let value = match std::env::var(Self::VALIDATION_ENVIRONMENT_VARIABLE) {
+            Ok(value) => value,
+            Err(_) => return
+        };
+
+        match value.trim().to_lowercase().as_str() {
+            "1" | "true" | "on" => self.debug_mode = true,
+            "0" | "false" | "off" => self.debug_mode = false,
+            _ => {
+                warn!(
+                    "ignoring {}={}, expected 1 or 0",
+                    Self::VALIDATION_ENVIRONMENT_VARIABLE, value);
+                return;
+            }
+        }
+
+        info!(
+            "validation layers {} by {}",
+            if self.debug_mode { "enabled" } else { "disabled" },
+            Self::VALIDATION_ENVIRONMENT_VARIABLE);
+    }
+
     fn init_entry(&mut self) -> VulkanResult<()> {
         self.entry.set(Rc::new(ash::Entry::new()?));
         Ok(())
     }
 
+    // Loaders older than 1.1 cannot enumerate their version and only support 1.0
+    fn init_api_version(&mut self) -> VulkanResult<()> {
+        let requested_version = self.requested_api_version.unwrap_or(vk_make_version!(1, 0, 0));
+        let loader_version = self.entry
+            .try_enumerate_instance_version()
+            .map_err(|result| VulkanError::EnumerateInstanceVersionError {result})?
+            .unwrap_or(vk_make_version!(1, 0, 0));
+
+        // Patch versions do not matter for the application info
+        let loader_version = vk_make_version!(
+            vk_version_major!(loader_version),
+            vk_version_minor!(loader_version),
+            0);
+        let api_version = requested_version.min(loader_version);
+
+        if api_version < requested_version {
+            warn!(
+                "Vulkan {}.{} was requested, but the loader only supports {}.{}",
+                vk_version_major!(requested_version), vk_version_minor!(requested_version),
+                vk_version_major!(api_version), vk_version_minor!(api_version));
+        }
+
+        self.api_version.set(api_version);
+
+        Ok(())
+    }
+
     fn init_instance(&mut self) -> VulkanResult<()> {
-        let mut instance_builder = vulkan::instance::Instance::builder()
+        let mut instance_builder = Instance::builder()
             .entry(Rc::clone(&self.entry))
+            .api_version(*self.api_version)
             .version(0, 0, 0)
             .name("Magmacraft")
             .extensions(self.instance_extensions.take());
 
+        // Without the flag the extension is enabled, but portability implementations stay hidden
+        let enumerates_portability = self.enabled_instance_extensions
+            .iter()
+            .any(|extension| extension == Instance::PORTABILITY_ENUMERATION_EXTENSION);
+
+        if enumerates_portability {
+            instance_builder = instance_builder.flags(Instance::enumerate_portability_flag());
+        }
+
         if self.debug_mode {
             let mut validation_layers = ValidationLayers::with_capacity(1);
             validation_layers.push("VK_LAYER_KHRONOS_validation");
@@ -122,19 +281,19 @@ impl VulkanStateBuilder {
     }
 
     fn init_extension_loaders(&mut self) {
-        let instance_handle = self.instance.handle();
+        let instance_loader = self.instance.loader();
         // Builder -> &Rc -> &ash::Entry
         let entry = self.entry.as_ref().as_ref();
 
         self.debug_utils_loader.set(
             Rc::new(ash::extensions::ext::DebugUtils::new(
-                entry, instance_handle
+                entry, instance_loader
             )
         ));
 
         self.surface_loader.set(
             Rc::new(ash::extensions::khr::Surface::new(
-                entry, instance_handle
+                entry, instance_loader
             )
         ));
     }
@@ -148,6 +307,36 @@ impl VulkanStateBuilder {
         }
     }
 
+    fn add_optional_instance_extensions(&mut self) -> VulkanResult<()> {
+        let available_extensions = available_instance_extensions(&self.entry)?;
+        let mut enabled_extensions: Vec<String> = self.instance_extensions
+            .strings()
+            .iter()
+            .map(|extension| extension.to_string_lossy().into_owned())
+            .collect();
+        let mut missing_extensions = Vec::new();
+
+        for extension in &self.optional_instance_extensions {
+            if enabled_extensions.contains(extension) {
+                continue;
+            }
+
+            if available_extensions.contains(extension) {
+                self.instance_extensions.push(extension);
+                enabled_extensions.push(extension.clone());
+            }
+            else {
+                warn!("optional instance extension {} is not available", extension);
+                missing_extensions.push(extension.clone());
+            }
+        }
+
+        self.enabled_instance_extensions.set(enabled_extensions);
+        self.missing_optional_instance_extensions.set(missing_extensions);
+
+        Ok(())
+    }
+
     fn init_debug_messenger(&mut self) -> VulkanResult<()> {
         let debug_messenger = if self.debug_mode {
             Some(vulkan::debug_utils::DebugMessenger::new(
@@ -164,12 +353,16 @@ impl VulkanStateBuilder {
     }
 
     fn create_state(&mut self) {
+        leak_tracking::track_vulkan_state_creation();
         self.vulkan_state.set(VulkanState {
             entry: self.entry.take(),
             instance: self.instance.take(),
             debug_utils_loader: self.debug_utils_loader.take(),
             surface_loader: self.surface_loader.take(),
-            debug_messenger: self.debug_messenger.take()
+            _debug_messenger: self.debug_messenger.take(),
+            api_version: self.api_version.take(),
+            enabled_instance_extensions: self.enabled_instance_extensions.take(),
+            missing_optional_instance_extensions: self.missing_optional_instance_extensions.take()
         })
     }
 }
@@ -1,71 +1,283 @@
-use std::rc::Rc;
-use std::cell::RefCell;
+use std::rc::{
+    Rc,
+    Weak
+};
+use std::ffi::CStr;
 use ash::{
     self,
-    vk::{self, Handle}
+    vk
+};
+use raw_window_handle::{
+    HasRawWindowHandle,
+    RawWindowHandle
 };
-use glfw;
-use crate::{
-    window::Window,
-    vulkan::state::VulkanState
+use crate::vulkan::{
+    VulkanError,
+    VulkanResult,
+    handle::VulkanHandle,
+    state::VulkanState,
+    instance::InstanceExtensions
 };
 
+// Anything able to present Vulkan images: GLFW windows, winit windows, SDL2 windows...
+// Sizes are in framebuffer pixels, which can outnumber the window's logical pixels
+// on HiDPI displays by the content scale.
+pub trait SurfaceWindow: HasRawWindowHandle {
+    fn framebuffer_size(&self) -> (u32, u32);
+
+    // Framebuffer pixels per logical pixel horizontally and vertically,
+    // windows unaware of scaling keep the default
+    fn content_scale(&self) -> (f32, f32) {
+        (1.0, 1.0)
+    }
+
+    fn surface_path(&self) -> SurfacePath {
+        SurfacePath::RawWindowHandle
+    }
+
+    // Instance extensions the windowing library creates surfaces with,
+    // None if it cannot create them, e.g. when it found no Vulkan loader
+    fn library_surface_extensions(&self) -> Option<Vec<String>> {
+        None
+    }
+
+    /// Creates a surface for the window through the windowing library.
+    /// None if the library cannot create surfaces, failures carry their VkResult.
+    ///
+    /// # Safety
+    ///
+    /// `instance` has to be a valid instance created with the extensions from
+    /// `library_surface_extensions` enabled. Both the instance and this window (with its
+    /// native window) have to outlive the returned surface, which the caller has to destroy
+    /// with `vkDestroySurfaceKHR` before destroying either of them.
+    unsafe fn create_library_surface(
+        &self,
+        _instance: vk::Instance
+    ) -> Option<Result<vk::SurfaceKHR, vk::Result>> {
+        None
+    }
+}
+
+// How surfaces are created for a window
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SurfacePath {
+    // Through the platform's surface extension, picked from the window's raw handle,
+    // e.g. VK_KHR_xlib_surface for X11 windows
+    RawWindowHandle,
+    // Through the windowing library, e.g. with glfwCreateWindowSurface, which picks
+    // the platform's surface extension itself. Windows whose library cannot create surfaces
+    // fall back to their raw handle.
+    WindowLibrary
+}
+
 pub struct Surface {
     vk_surface: vk::SurfaceKHR,
     vulkan_state: Rc<VulkanState>,
-    window: Rc<RefCell<Window>>
+    window: Weak<dyn SurfaceWindow>
 }
 
 impl Surface {
-    pub fn new(window: Rc<RefCell<Window>>, vulkan_state: Rc<VulkanState>) -> Self {
-        let vk_surface = Self::create_window_surface(&window.borrow(), &vulkan_state);
-        Surface {
+    pub fn new(
+        window: Weak<dyn SurfaceWindow>,
+        vulkan_state: Rc<VulkanState>
+    ) -> VulkanResult<Self> {
+        let vk_surface = {
+            let window = window
+                .upgrade()
+                .ok_or(VulkanError::SurfaceWindowDestroyedError)?;
+
+            Self::create_surface_for(window.as_ref(), &vulkan_state)?
+        };
+
+        Ok(Surface {
             vk_surface,
             vulkan_state,
             window
+        })
+    }
+
+    pub fn required_instance_extensions(
+        window: &dyn SurfaceWindow
+    ) -> VulkanResult<InstanceExtensions> {
+        if let Some(library_extensions) = Self::library_surface_extensions(window) {
+            let mut extensions = InstanceExtensions::with_capacity(library_extensions.len());
+
+            for extension in &library_extensions {
+                extensions.push(extension);
+            }
+
+            return Ok(extensions);
         }
+
+        let platform_extension = Self::platform_extension_name(window.raw_window_handle())?;
+        let mut extensions = InstanceExtensions::with_capacity(2);
+
+        extensions.push(ash::extensions::khr::Surface::name().to_str().unwrap());
+        extensions.push(platform_extension.to_str().unwrap());
+
+        Ok(extensions)
     }
 
-    fn create_window_surface(window: &Window, vulkan_state: &VulkanState) -> vk::SurfaceKHR {
-        let raw_window_handle = window.raw_handle();
-        let raw_instance_handle = vulkan_state.raw_instance_handle();
-        let raw_vk_surface =
-            Self::create_raw_window_surface(raw_window_handle, raw_instance_handle);
+    fn platform_extension_name(raw_window_handle: RawWindowHandle) -> VulkanResult<&'static CStr> {
+        match raw_window_handle {
+            #[cfg(any(
+                target_os = "linux",
+                target_os = "dragonfly",
+                target_os = "freebsd",
+                target_os = "netbsd",
+                target_os = "openbsd"))]
+            RawWindowHandle::Xlib(_) => Ok(ash::extensions::khr::XlibSurface::name()),
+            #[cfg(any(
+                target_os = "linux",
+                target_os = "dragonfly",
+                target_os = "freebsd",
+                target_os = "netbsd",
+                target_os = "openbsd"))]
+            RawWindowHandle::Xcb(_) => Ok(ash::extensions::khr::XcbSurface::name()),
+            #[cfg(any(
+                target_os = "linux",
+                target_os = "dragonfly",
+                target_os = "freebsd",
+                target_os = "netbsd",
+                target_os = "openbsd"))]
+            RawWindowHandle::Wayland(_) => Ok(ash::extensions::khr::WaylandSurface::name()),
+            #[cfg(target_os = "windows")]
+            RawWindowHandle::Windows(_) => Ok(ash::extensions::khr::Win32Surface::name()),
+            #[cfg(target_os = "macos")]
+            RawWindowHandle::MacOS(_) => Ok(ash::extensions::mvk::MacOSSurface::name()),
+            _ => Err(VulkanError::SurfacePlatformNotSupported)
+        }
+    }
+
+    fn library_surface_extensions(window: &dyn SurfaceWindow) -> Option<Vec<String>> {
+        if window.surface_path() != SurfacePath::WindowLibrary {
+            return None;
+        }
+
+        let extensions = window.library_surface_extensions();
+        if extensions.is_none() {
+            warn!("windowing library cannot create surfaces, using the raw window handle instead");
+        }
 
-        vk::SurfaceKHR::from_raw(raw_vk_surface)
+        extensions
     }
 
-    fn create_raw_window_surface(
-        raw_window_handle: *mut glfw::ffi::GLFWwindow,
-        raw_instance_handle: u64
-    ) -> u64 {
-        let mut raw_vk_surface: u64 = unsafe {
-            std::mem::MaybeUninit::uninit().assume_init()
-        };
+    fn create_surface_for(
+        window: &dyn SurfaceWindow,
+        vulkan_state: &VulkanState
+    ) -> VulkanResult<vk::SurfaceKHR> {
+        if window.surface_path() == SurfacePath::WindowLibrary {
+            let instance = vulkan_state.instance().handle();
 
-        unsafe {
-            glfw::ffi::glfwCreateWindowSurface(
-                raw_instance_handle as usize,
-                raw_window_handle,
-                std::ptr::null(),
-                &mut raw_vk_surface as *mut u64);
+            if let Some(surface) = unsafe { window.create_library_surface(instance) } {
+                return surface.map_err(|result| VulkanError::SurfaceCreateError {result});
+            }
         }
 
-        raw_vk_surface
+        Self::create_window_surface(window.raw_window_handle(), vulkan_state)
     }
 
-    pub fn handle(&self) -> vk::SurfaceKHR {
-        self.vk_surface
+    fn create_window_surface(
+        raw_window_handle: RawWindowHandle,
+        vulkan_state: &VulkanState
+    ) -> VulkanResult<vk::SurfaceKHR> {
+        let entry = vulkan_state.entry();
+        let instance = vulkan_state.instance_loader();
+
+        let surface = unsafe {
+            match raw_window_handle {
+                #[cfg(any(
+                    target_os = "linux",
+                    target_os = "dragonfly",
+                    target_os = "freebsd",
+                    target_os = "netbsd",
+                    target_os = "openbsd"))]
+                RawWindowHandle::Xlib(handle) => {
+                    let create_info = vk::XlibSurfaceCreateInfoKHR::builder()
+                        .dpy(handle.display as *mut vk::Display)
+                        .window(handle.window);
+
+                    ash::extensions::khr::XlibSurface::new(entry, instance)
+                        .create_xlib_surface(&create_info, None)
+                },
+                #[cfg(any(
+                    target_os = "linux",
+                    target_os = "dragonfly",
+                    target_os = "freebsd",
+                    target_os = "netbsd",
+                    target_os = "openbsd"))]
+                RawWindowHandle::Xcb(handle) => {
+                    let create_info = vk::XcbSurfaceCreateInfoKHR::builder()
+                        .connection(handle.connection as *mut vk::xcb_connection_t)
+                        .window(handle.window);
+
+                    ash::extensions::khr::XcbSurface::new(entry, instance)
+                        .create_xcb_surface(&create_info, None)
+                },
+                #[cfg(any(
+                    target_os = "linux",
+                    target_os = "dragonfly",
+                    target_os = "freebsd",
+                    target_os = "netbsd",
+                    target_os = "openbsd"))]
+                RawWindowHandle::Wayland(handle) => {
+                    let create_info = vk::WaylandSurfaceCreateInfoKHR::builder()
+                        .display(handle.display)
+                        .surface(handle.surface);
+
+                    ash::extensions::khr::WaylandSurface::new(entry, instance)
+                        .create_wayland_surface(&create_info, None)
+                },
+                #[cfg(target_os = "windows")]
+                RawWindowHandle::Windows(handle) => {
+                    let create_info = vk::Win32SurfaceCreateInfoKHR::builder()
+                        .hinstance(handle.hinstance)
+                        .hwnd(handle.hwnd);
+
+                    ash::extensions::khr::Win32Surface::new(entry, instance)
+                        .create_win32_surface(&create_info, None)
+                },
+                #[cfg(target_os = "macos")]
+                RawWindowHandle::MacOS(handle) => {
+                    let create_info = vk::MacOSSurfaceCreateInfoMVK::builder()
+                        .view(&*handle.ns_view);
+
+                    ash::extensions::mvk::MacOSSurface::new(entry, instance)
+                        .create_mac_os_surface_mvk(&create_info, None)
+                },
+                _ => return Err(VulkanError::SurfacePlatformNotSupported)
+            }
+        };
+
+        surface.map_err(|result| VulkanError::SurfaceCreateError {result})
     }
 
-    pub fn framebuffer_extent(&self) -> vk::Extent2D {
-        let (width, height) = self.window.borrow().framebuffer_size();
+    pub fn framebuffer_extent(&self) -> VulkanResult<vk::Extent2D> {
+        let window = self.window
+            .upgrade()
+            .ok_or(VulkanError::SurfaceWindowDestroyedError)?;
+
+        let (width, height) = window.framebuffer_size();
 
-        *vk::Extent2D::builder()
+        Ok(*vk::Extent2D::builder()
             .width(width)
-            .height(height)
+            .height(height))
     }
 
+    pub fn content_scale(&self) -> VulkanResult<(f32, f32)> {
+        let window = self.window
+            .upgrade()
+            .ok_or(VulkanError::SurfaceWindowDestroyedError)?;
+
+        Ok(window.content_scale())
+    }
+
+    /// Whether the queue family of the device can present to this surface.
+    ///
+    /// # Safety
+    ///
+    /// `physical_device` has to be a valid handle enumerated from the instance this surface
+    /// was created with, and `queue_family_index` has to be lower than its queue family count.
     pub unsafe fn is_supported_by_vk_device(
         &self, physical_device: vk::PhysicalDevice, queue_family_index: u32
     ) -> bool {
@@ -78,10 +290,11 @@ impl Surface {
     }
 }
 
-impl std::ops::Deref for Surface {
-    type Target = vk::SurfaceKHR;
-    fn deref(&self) -> &Self::Target {
-        &self.vk_surface
+impl VulkanHandle for Surface {
+    type Handle = vk::SurfaceKHR;
+
+    fn handle(&self) -> Self::Handle {
+        self.vk_surface
     }
 }
 
@@ -13,6 +13,7 @@ use crate::{
     vulkan::{
         VulkanError,
         VulkanResult,
+        handle::VulkanHandle,
         physical_device::{
             PhysicalDevice,
             PhysicalDeviceSurfaceProperties,
@@ -23,10 +24,47 @@ use crate::{
     }
 };
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PresentModePreference {
+    // Lowest latency, tears
+    Immediate,
+    // Low latency without tearing, renders frames that are never shown
+    Mailbox,
+    // Vertical sync which tears instead of stuttering when a frame is late
+    FifoRelaxed,
+    // Vertical sync, the only mode every surface supports
+    Fifo
+}
+
+impl PresentModePreference {
+    pub fn from_vk(present_mode: vk::PresentModeKHR) -> Option<Self> {
+        match present_mode {
+            vk::PresentModeKHR::IMMEDIATE => Some(PresentModePreference::Immediate),
+            vk::PresentModeKHR::MAILBOX => Some(PresentModePreference::Mailbox),
+            vk::PresentModeKHR::FIFO_RELAXED => Some(PresentModePreference::FifoRelaxed),
+            vk::PresentModeKHR::FIFO => Some(PresentModePreference::Fifo),
+            _ => None
+        }
+    }
+
+    pub fn to_vk(self) -> vk::PresentModeKHR {
+        match self {
+            PresentModePreference::Immediate => vk::PresentModeKHR::IMMEDIATE,
+            PresentModePreference::Mailbox => vk::PresentModeKHR::MAILBOX,
+            PresentModePreference::FifoRelaxed => vk::PresentModeKHR::FIFO_RELAXED,
+            PresentModePreference::Fifo => vk::PresentModeKHR::FIFO
+        }
+    }
+}
+
 pub struct Swapchain {
     vk_swapchain: vk::SwapchainKHR,
     surface_format: vk::SurfaceFormatKHR,
+    available_formats: Vec<vk::SurfaceFormatKHR>,
+    present_mode: PresentModePreference,
+    available_present_modes: Vec<PresentModePreference>,
     extent: vk::Extent2D,
+    image_usage: vk::ImageUsageFlags,
     images: Vec<vk::Image>,
     image_views: Vec<vk::ImageView>,
     swapchain_loader: Rc<ash::extensions::khr::Swapchain>,
@@ -42,10 +80,6 @@ impl Swapchain {
         }
     }
 
-    pub fn handle(&self) -> vk::SwapchainKHR {
-        self.vk_swapchain
-    }
-
     pub fn image_format(&self) -> vk::Format {
         self.surface_format.format
     }
@@ -54,10 +88,32 @@ impl Swapchain {
         self.surface_format.color_space
     }
 
+    // All formats the surface supports, e.g. to offer HDR formats when a swapchain is recreated
+    pub fn available_formats(&self) -> &[vk::SurfaceFormatKHR] {
+        &self.available_formats
+    }
+
+    pub fn present_mode(&self) -> PresentModePreference {
+        self.present_mode
+    }
+
+    // Present modes the surface supports, e.g. for a settings menu
+    pub fn available_present_modes(&self) -> &[PresentModePreference] {
+        &self.available_present_modes
+    }
+
     pub fn extent(&self) -> vk::Extent2D {
         self.extent
     }
 
+    pub fn image_usage(&self) -> vk::ImageUsageFlags {
+        self.image_usage
+    }
+
+    pub fn images(&self) -> &Vec<vk::Image> {
+        &self.images
+    }
+
     pub fn image_views(&self) -> &Vec<vk::ImageView> {
         &self.image_views
     }
@@ -67,6 +123,14 @@ impl Swapchain {
     }
 }
 
+impl VulkanHandle for Swapchain {
+    type Handle = vk::SwapchainKHR;
+
+    fn handle(&self) -> Self::Handle {
+        self.vk_swapchain
+    }
+}
+
 impl Drop for Swapchain {
     fn drop(&mut self) {
         unsafe {
@@ -86,13 +150,17 @@ pub struct SwapchainBuilder {
     physical_device: BuilderRequirement<Rc<PhysicalDevice>>,
     logical_device: BuilderRequirement<Rc<LogicalDevice>>,
     surface: BuilderRequirement<Rc<Surface>>,
-    vsync: BuilderRequirement<bool>,
+    preferred_formats: Vec<vk::SurfaceFormatKHR>,
+    present_mode_preference: Option<Vec<PresentModePreference>>,
+    additional_image_usage: vk::ImageUsageFlags,
+    old_swapchain: vk::SwapchainKHR,
 
     surface_properties: BuilderInternal<PhysicalDeviceSurfaceProperties>,
     image_extent: BuilderInternal<vk::Extent2D>,
     surface_format: BuilderInternal<vk::SurfaceFormatKHR>,
-    present_mode: BuilderInternal<vk::PresentModeKHR>,
+    present_mode: BuilderInternal<PresentModePreference>,
     optimal_image_count: BuilderInternal<u32>,
+    image_usage: BuilderInternal<vk::ImageUsageFlags>,
     image_sharing_mode: BuilderInternal<vk::SharingMode>,
     concurrent_queue_families: BuilderInternal<Vec<u32>>,
     swapchain_create_info: BuilderInternal<vk::SwapchainCreateInfoKHR>,
@@ -107,8 +175,23 @@ impl SwapchainBuilder {
     const IMAGE_ARRAY_LAYERS: u32 = 1;
     const ADDITIONAL_IMAGES_COUNT: u32 = 1;
 
-    const PRESENT_MODE_WITH_VSYNC: vk::PresentModeKHR = vk::PresentModeKHR::MAILBOX;
-    const PRESENT_MODE_WITHOUT_VSYNC: vk::PresentModeKHR = vk::PresentModeKHR::IMMEDIATE;
+    // Used when none of the preferred formats are supported
+    const DEFAULT_PREFERRED_FORMATS: [vk::SurfaceFormatKHR; 2] = [
+        vk::SurfaceFormatKHR {
+            format: vk::Format::B8G8R8A8_SRGB,
+            color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR
+        },
+        vk::SurfaceFormatKHR {
+            format: vk::Format::R8G8B8A8_SRGB,
+            color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR
+        }
+    ];
+
+    const DEFAULT_PRESENT_MODE_PREFERENCE: [PresentModePreference; 3] = [
+        PresentModePreference::Mailbox,
+        PresentModePreference::FifoRelaxed,
+        PresentModePreference::Fifo
+    ];
 
     pub fn physical_device(mut self, physical_device: Rc<PhysicalDevice>) -> Self {
         self.physical_device.set(physical_device);
@@ -125,12 +208,42 @@ impl SwapchainBuilder {
         self
     }
 
-    pub fn vsync(mut self, vsync: bool) -> Self {
-        self.vsync.set(vsync);
+    // Present modes ranked from the most preferred one, FIFO is used if none are supported.
+    // Defaults to mailbox, then relaxed FIFO, then FIFO.
+    pub fn present_mode_preference(mut self, preference: &[PresentModePreference]) -> Self {
+        self.present_mode_preference = Some(preference.to_vec());
+        self
+    }
+
+    // Formats are preferred in the order they were given in, before the sRGB defaults.
+    // Supported formats can be checked beforehand with `PhysicalDevice::surface_properties`.
+    pub fn preferred_format(mut self, surface_format: vk::SurfaceFormatKHR) -> Self {
+        self.preferred_formats.push(surface_format);
+        self
+    }
+
+    // Usages besides COLOR_ATTACHMENT, e.g. STORAGE for post-processing in compute shaders.
+    // TRANSFER_SRC and TRANSFER_DST are added whenever the surface supports them,
+    // so that frames can be captured and presented again.
+    // Building fails if the surface or, for STORAGE, the chosen format does not support them.
+    pub fn additional_image_usage(mut self, usage: vk::ImageUsageFlags) -> Self {
+        self.additional_image_usage |= usage;
+        self
+    }
+
+    // Swapchain of the same surface which the new one replaces, e.g. after the window
+    // was resized. It is retired, so no more images can be acquired from it, but it stays
+    // valid until dropped, e.g. together with the last pipeline built for it.
+    pub fn old_swapchain(mut self, old_swapchain: &Swapchain) -> Self {
+        self.old_swapchain = old_swapchain.handle();
         self
     }
 
     pub fn build(mut self) -> VulkanResult<Swapchain> {
+        check_required_fields!(
+            self, VulkanError, "SwapchainBuilder",
+            [physical_device, logical_device, surface]);
+
         self.get_ready_for_creation()?;
         self.create_swapchain();
 
@@ -140,9 +253,10 @@ impl SwapchainBuilder {
     fn get_ready_for_creation(&mut self) -> VulkanResult<()> {
         self.init_surface_properties()?;
         self.init_surface_format();
-        self.init_image_extent();
+        self.init_image_extent()?;
         self.init_present_mode();
         self.init_optimal_image_count();
+        self.init_image_usage()?;
         self.init_image_sharing_info()?;
         self.init_swapchain_create_info();
         self.init_vk_swapchain()?;
@@ -160,44 +274,67 @@ impl SwapchainBuilder {
     }
 
     fn init_surface_format(&mut self) {
-        let surface_format = self.surface_properties.formats[0];
-        // TODO: select this based on gamma and other things
+        let available_formats = &self.surface_properties.formats;
+        let mut preferred_formats = self.preferred_formats
+            .iter()
+            .chain(Self::DEFAULT_PREFERRED_FORMATS.iter());
+
+        // A single undefined format means that the surface does not prefer any format
+        let is_any_format_supported =
+            available_formats.len() == 1 && available_formats[0].format == vk::Format::UNDEFINED;
+
+        let surface_format = if is_any_format_supported {
+            *preferred_formats.next().unwrap()
+        }
+        else {
+            preferred_formats
+                .find(|preferred| {
+                    available_formats.iter().any(|available| {
+                        available.format == preferred.format &&
+                            available.color_space == preferred.color_space
+                    })
+                })
+                .copied()
+                .unwrap_or(available_formats[0])
+        };
+
         self.surface_format.set(surface_format);
     }
 
-    fn init_image_extent(&mut self) {
+    fn init_image_extent(&mut self) -> VulkanResult<()> {
         let capabilities = self.surface_properties.capabilities;
         let current_extent = capabilities.current_extent;
 
         // TODO: support custom resolutions
         let image_extent = if Self::is_extent_undefined(&current_extent) {
-            self.surface.framebuffer_extent()
+            self.surface.framebuffer_extent()?
         }
         else {
             current_extent
         };
 
         self.image_extent.set(image_extent);
+
+        Ok(())
     }
 
     fn is_extent_undefined(extent: &vk::Extent2D) -> bool {
-        extent.width == u32::max_value()
+        extent.width == u32::MAX
     }
 
     fn init_present_mode(&mut self) {
-        for present_mode in &self.surface_properties.present_modes {
-            if self.is_present_mode_suitable(*present_mode) {
-                self.present_mode.set(*present_mode);
-                return;
-            }
-        }
-
-        self.present_mode.set(vk::PresentModeKHR::FIFO);
-    }
-
-    fn is_present_mode_suitable(&self, present_mode: vk::PresentModeKHR) -> bool {
-        *self.vsync && present_mode == Self::PRESENT_MODE_WITH_VSYNC ||
-        !*self.vsync && present_mode == Self::PRESENT_MODE_WITHOUT_VSYNC
+        let available_present_modes = &self.surface_properties.present_modes;
+        let preference = self.present_mode_preference
+            .as_ref()
+            .map_or(&Self::DEFAULT_PRESENT_MODE_PREFERENCE[..], Vec::as_slice);
+
+        let present_mode = preference
+            .iter()
+            .find(|preferred| available_present_modes.contains(&preferred.to_vk()))
+            .copied()
+            .unwrap_or(PresentModePreference::Fifo);
+
+        self.present_mode.set(present_mode);
     }
 
     fn init_optimal_image_count(&mut self) {
@@ -212,6 +349,43 @@ impl SwapchainBuilder {
         self.optimal_image_count.set(optimal_image_count);
     }
 
+    fn init_image_usage(&mut self) -> VulkanResult<()> {
+        let supported_usage = self.surface_properties.capabilities.supported_usage_flags;
+        let mut image_usage = vk::ImageUsageFlags::COLOR_ATTACHMENT | self.additional_image_usage;
+
+        if !supported_usage.contains(image_usage) || !self.is_storage_supported_by_format() {
+            return Err(VulkanError::SwapchainImageUsageNotSupported {
+                usage: format!("{:?}", image_usage)
+            });
+        }
+
+        // Allows reading rendered frames back, e.g. for screenshots
+        if supported_usage.contains(vk::ImageUsageFlags::TRANSFER_SRC) {
+            image_usage |= vk::ImageUsageFlags::TRANSFER_SRC;
+        }
+
+        // Allows presenting copies of earlier frames
+        if supported_usage.contains(vk::ImageUsageFlags::TRANSFER_DST) {
+            image_usage |= vk::ImageUsageFlags::TRANSFER_DST;
+        }
+
+        self.image_usage.set(image_usage);
+
+        Ok(())
+    }
+
+    // sRGB formats rarely support storage, so a UNORM format has to be preferred for it
+    fn is_storage_supported_by_format(&self) -> bool {
+        if !self.additional_image_usage.contains(vk::ImageUsageFlags::STORAGE) {
+            return true;
+        }
+
+        self.physical_device
+            .format_properties(self.surface_format.format)
+            .optimal_tiling_features
+            .contains(vk::FormatFeatureFlags::STORAGE_IMAGE)
+    }
+
     fn init_image_sharing_info(&mut self) -> VulkanResult<()> {
         let multiple_queue_family_usage = self.physical_device.is_transfer_queue_family_dedicated();
         let graphics_index = self.physical_device.queue_family_index(QueueFamily::Graphics)?;
@@ -244,15 +418,14 @@ impl SwapchainBuilder {
             .image_color_space(surface_format.color_space)
             .image_extent(*self.image_extent)
             .image_array_layers(Self::IMAGE_ARRAY_LAYERS)
-            .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT)
+            .image_usage(*self.image_usage)
             .image_sharing_mode(*self.image_sharing_mode)
             .queue_family_indices(&self.concurrent_queue_families)
-            .present_mode(*self.present_mode)
+            .present_mode(self.present_mode.to_vk())
             .pre_transform(self.surface_properties.capabilities.current_transform)
             .clipped(true)
             .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
-            // TODO: Allow swapchain recreation
-            .old_swapchain(vk::SwapchainKHR::null());
+            .old_swapchain(self.old_swapchain);
 
         self.swapchain_create_info.set(*swapchain_create_info_builder);
     }
@@ -336,10 +509,23 @@ impl SwapchainBuilder {
     }
 
     fn create_swapchain(&mut self) {
+        debug!(
+            "created swapchain: {}x{}, {:?}, {:?}, present mode {:?}, {} images",
+            self.image_extent.width, self.image_extent.height,
+            self.surface_format.format, self.surface_format.color_space,
+            *self.present_mode, self.images.len());
+
         self.swapchain.set(Swapchain {
             vk_swapchain: self.vk_swapchain.take(),
             surface_format: self.surface_format.take(),
+            available_formats: self.surface_properties.formats.clone(),
+            present_mode: self.present_mode.take(),
+            available_present_modes: self.surface_properties.present_modes
+                .iter()
+                .filter_map(|present_mode| PresentModePreference::from_vk(*present_mode))
+                .collect(),
             extent: self.image_extent.take(),
+            image_usage: self.image_usage.take(),
             images: self.images.take(),
             image_views: self.image_views.take(),
             swapchain_loader: self.logical_device.swapchain_loader(),
@@ -1,4 +1,5 @@
 use std::{
+    cell::RefCell,
     convert::TryInto,
     rc::Rc
 };
@@ -9,11 +10,18 @@ use ash::{
 use crate::vulkan::{
     VulkanError,
     VulkanResult,
-    logical_device::LogicalDevice
+    handle::VulkanHandle,
+    leak_tracking,
+    logical_device::LogicalDevice,
+    external_memory::{
+        self,
+        ExternalHandle
+    }
 };
 
 pub struct Semaphore {
     vk_semaphore: vk::Semaphore,
+    is_exportable: bool,
     logical_device: Rc<LogicalDevice>
 }
 
@@ -25,19 +33,58 @@ impl Semaphore {
             logical_device.create_semaphore(&create_info, None)
         }.map_err(|result| VulkanError::SemaphoreCreateError {result})?;
 
-        Ok(Self {
+        let semaphore = Self {
+            vk_semaphore,
+            is_exportable: false,
+            logical_device
+        };
+
+        leak_tracking::track_creation(&semaphore);
+        Ok(semaphore)
+    }
+
+    // Signaled or waited on by other APIs or processes, e.g. when handing over an image
+    // with exported memory, see `export_handle`
+    pub fn exportable(logical_device: Rc<LogicalDevice>) -> VulkanResult<Self> {
+        let vk_semaphore = external_memory::create_exportable_semaphore(&logical_device)?;
+
+        let semaphore = Self {
             vk_semaphore,
+            is_exportable: true,
             logical_device
-        })
+        };
+
+        leak_tracking::track_creation(&semaphore);
+        Ok(semaphore)
+    }
+
+    // The returned handle belongs to the caller
+    pub fn export_handle(&self) -> VulkanResult<ExternalHandle> {
+        if !self.is_exportable {
+            return Err(VulkanError::SemaphoreNotExportable);
+        }
+
+        external_memory::export_semaphore(&self.logical_device, self.vk_semaphore)
+    }
+
+    // Replaces the semaphore's payload with the one of a semaphore exported by another API
+    // or process. Takes ownership of file descriptors, but not of NT handles.
+    pub fn import_handle(&self, handle: ExternalHandle) -> VulkanResult<()> {
+        external_memory::import_semaphore(&self.logical_device, self.vk_semaphore, handle)
     }
+}
+
+impl VulkanHandle for Semaphore {
+    type Handle = vk::Semaphore;
 
-    pub fn handle(&self) -> vk::Semaphore {
+    fn handle(&self) -> Self::Handle {
         self.vk_semaphore
     }
 }
 
 impl Drop for Semaphore {
     fn drop(&mut self) {
+        leak_tracking::track_destruction(self);
         unsafe {
             self.logical_device.destroy_semaphore(self.vk_semaphore, None);
         }
@@ -59,10 +106,13 @@ impl Fence {
             logical_device.create_fence(&create_info, None)
         }.map_err(|result| VulkanError::FenceCreateError {result})?;
 
-        Ok(Self {
+        let fence = Self {
             vk_fence,
             logical_device
-        })
+        };
+
+        leak_tracking::track_creation(&fence);
+        Ok(fence)
     }
 
     fn create_flags(status: FenceStatus) -> vk::FenceCreateFlags {
@@ -74,10 +124,6 @@ impl Fence {
         }
     }
 
-    pub fn handle(&self) -> vk::Fence {
-        self.vk_fence
-    }
-
     pub fn status(&self) -> VulkanResult<FenceStatus> {
         let status = unsafe {
             self.logical_device
@@ -113,8 +159,17 @@ impl Fence {
     }
 }
 
+impl VulkanHandle for Fence {
+    type Handle = vk::Fence;
+
+    fn handle(&self) -> Self::Handle {
+        self.vk_fence
+    }
+}
+
 impl Drop for Fence {
     fn drop(&mut self) {
+        leak_tracking::track_destruction(self);
         unsafe {
             self.logical_device.destroy_fence(self.vk_fence, None);
         }
@@ -126,3 +181,57 @@ pub enum FenceStatus {
     Ready,
     NotReady
 }
+
+// Recycles fences and semaphores of transient work, e.g. uploads or one-off compute,
+// instead of creating and destroying them every time
+pub struct SyncObjectPool {
+    logical_device: Rc<LogicalDevice>,
+    free_fences: RefCell<Vec<Fence>>,
+    free_semaphores: RefCell<Vec<Semaphore>>
+}
+
+impl SyncObjectPool {
+    pub fn new(logical_device: Rc<LogicalDevice>) -> Self {
+        Self {
+            logical_device,
+            free_fences: RefCell::new(Vec::new()),
+            free_semaphores: RefCell::new(Vec::new())
+        }
+    }
+
+    // Fences are always acquired unsignalled
+    pub fn acquire_fence(&self) -> VulkanResult<Fence> {
+        let fence = self.free_fences.borrow_mut().pop();
+        match fence {
+            Some(fence) => Ok(fence),
+            None => Fence::new(Rc::clone(&self.logical_device), FenceStatus::NotReady)
+        }
+    }
+
+    // The fence has to come from a pool of the same device and cannot be used
+    // by a submission which has not finished yet
+    pub fn release_fence(&self, fence: Fence) -> VulkanResult<()> {
+        fence.reset()?;
+        self.free_fences.borrow_mut().push(fence);
+        Ok(())
+    }
+
+    pub fn acquire_semaphore(&self) -> VulkanResult<Semaphore> {
+        let semaphore = self.free_semaphores.borrow_mut().pop();
+        match semaphore {
+            Some(semaphore) => Ok(semaphore),
+            None => Semaphore::new(Rc::clone(&self.logical_device))
+        }
+    }
+
+    // The semaphore has to be unsignalled, with nothing left to signal or wait for it,
+    // e.g. once the submission which waited for it has finished
+    pub fn release_semaphore(&self, semaphore: Semaphore) {
+        self.free_semaphores.borrow_mut().push(semaphore);
+    }
+
+    // Numbers of fences and semaphores waiting to be reused
+    pub fn free_counts(&self) -> (usize, usize) {
+        (self.free_fences.borrow().len(), self.free_semaphores.borrow().len())
+    }
+}
@@ -1,10 +1,30 @@
+use std::rc::{
+    Rc,
+    Weak
+};
 use custom_error::custom_error;
+use image::RgbaImage;
+use ash::vk::{
+    self,
+    Handle
+};
 use glfw::{
     self,
     Context
 };
+use raw_window_handle::{
+    HasRawWindowHandle,
+    RawWindowHandle
+};
 use crate::{
-    vulkan,
+    vulkan::{
+        instance::InstanceExtensions,
+        surface::{
+            Surface,
+            SurfaceWindow,
+            SurfacePath
+        }
+    },
     builder::{
         BuilderInternal,
         BuilderRequirement,
@@ -14,7 +34,9 @@ use crate::{
 
 custom_error!{pub WindowError
     GlfwInitializationError { source: glfw::InitError } = "failed to initialize GLFW",
-    CreateError = "failed to create GLFW window"
+    CreateError = "failed to create GLFW window",
+    BuilderMissingField {builder: &'static str, field: &'static str} =
+        "{builder} was built without setting required field `{field}`"
 }
 
 type WindowResult<T> = Result<T, WindowError>;
@@ -22,7 +44,62 @@ type WindowResult<T> = Result<T, WindowError>;
 pub struct Window {
     glfw_instance: glfw::Glfw,
     glfw_window: glfw::Window,
-    event_receiver: std::sync::mpsc::Receiver<(f64, glfw::WindowEvent)>
+    event_receiver: std::sync::mpsc::Receiver<(f64, glfw::WindowEvent)>,
+    handle: Rc<WindowHandle>,
+    events: Vec<WindowEvent>
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum WindowEvent {
+    // Text typed by the user, already combined from key presses by the platform's
+    // input method, e.g. for input fields and chat boxes
+    Char {codepoint: char, modifiers: KeyModifiers},
+    // The window moved to a display with a different scale or the display's scale changed,
+    // so sizes in logical pixels cover different numbers of framebuffer pixels
+    ContentScaleChanged {x_scale: f32, y_scale: f32},
+    // In framebuffer pixels
+    FramebufferResized {width: u32, height: u32},
+    // Nothing is rendered until the window is restored, see `Window::is_minimized`
+    Minimized,
+    Restored,
+    // Contents of the window were lost, e.g. after being uncovered by another window
+    // on platforms without compositing, and have to be rendered again
+    Damaged
+}
+
+impl WindowEvent {
+    // Whether the last rendered frame does not show correctly anymore after the event,
+    // e.g. for loops rendering with `RedrawMode::OnDemand`
+    pub fn requires_redraw(&self) -> bool {
+        match self {
+            WindowEvent::ContentScaleChanged {..}
+                | WindowEvent::FramebufferResized {..}
+                | WindowEvent::Restored
+                | WindowEvent::Damaged => true,
+            WindowEvent::Char {..} | WindowEvent::Minimized => false
+        }
+    }
+}
+
+// Modifier keys held when an event occurred
+#[derive(PartialEq, Eq, Clone, Copy, Default, Debug)]
+pub struct KeyModifiers {
+    pub shift: bool,
+    pub control: bool,
+    pub alt: bool,
+    // The Windows or Command key
+    pub super_key: bool
+}
+
+impl From<glfw::Modifiers> for KeyModifiers {
+    fn from(modifiers: glfw::Modifiers) -> Self {
+        Self {
+            shift: modifiers.contains(glfw::Modifiers::Shift),
+            control: modifiers.contains(glfw::Modifiers::Control),
+            alt: modifiers.contains(glfw::Modifiers::Alt),
+            super_key: modifiers.contains(glfw::Modifiers::Super)
+        }
+    }
 }
 
 impl Window {
@@ -38,42 +115,246 @@ impl Window {
 
     pub fn poll_events(&mut self) {
         self.glfw_instance.poll_events();
+        self.handle_events();
+    }
+
+    // Like `poll_events`, but sleeps until at least one event arrives,
+    // e.g. while there is nothing to render
+    pub fn wait_events(&mut self) {
+        self.glfw_instance.wait_events();
+        self.handle_events();
+    }
+
+    // Like `wait_events`, but wakes up after the timeout even if no events arrived,
+    // e.g. to redraw a blinking cursor
+    pub fn wait_events_timeout(&mut self, timeout: std::time::Duration) {
+        self.glfw_instance.wait_events_timeout(timeout.as_secs_f64());
+        self.handle_events();
+    }
+
+    fn handle_events(&mut self) {
         for (_, event) in glfw::flush_messages(&self.event_receiver) {
-            Self::match_event(&event, &mut self.glfw_window);
+            Self::match_event(&event, &mut self.glfw_window, &mut self.events);
         }
     }
 
-    fn match_event(event: &glfw::WindowEvent, glfw_window: &mut glfw::Window) {
+    fn match_event(
+        event: &glfw::WindowEvent,
+        glfw_window: &mut glfw::Window,
+        events: &mut Vec<WindowEvent>
+    ) {
         match event {
             glfw::WindowEvent::Key(glfw::Key::Escape, _, glfw::Action::Press, _) => {
                 glfw_window.set_should_close(true);
             },
+            glfw::WindowEvent::CharModifiers(codepoint, modifiers) => {
+                events.push(WindowEvent::Char {
+                    codepoint: *codepoint,
+                    modifiers: KeyModifiers::from(*modifiers)
+                });
+            },
+            glfw::WindowEvent::ContentScale(x_scale, y_scale) => {
+                events.push(WindowEvent::ContentScaleChanged {
+                    x_scale: *x_scale,
+                    y_scale: *y_scale
+                });
+            },
+            glfw::WindowEvent::Iconify(true) => {
+                events.push(WindowEvent::Minimized);
+            },
+            glfw::WindowEvent::Iconify(false) => {
+                events.push(WindowEvent::Restored);
+            },
+            glfw::WindowEvent::Refresh => {
+                events.push(WindowEvent::Damaged);
+            },
+            glfw::WindowEvent::FramebufferSize(width, height) => {
+                events.push(WindowEvent::FramebufferResized {
+                    width: *width as u32,
+                    height: *height as u32
+                });
+            },
             _ => {}
         }
     }
 
-    pub fn required_vulkan_extensions(&self) -> vulkan::instance::InstanceExtensions {
-        let a = self.glfw_instance
-            .get_required_instance_extensions()
-            .unwrap_or(Vec::new());
+    // Events which occurred since the last call, meant to be checked after every `poll_events`
+    pub fn take_events(&mut self) -> Vec<WindowEvent> {
+        std::mem::take(&mut self.events)
+    }
 
-        vulkan::instance::InstanceExtensions::from_vec(a)
+    // None if the clipboard is empty or does not hold text
+    pub fn clipboard_text(&self) -> Option<String> {
+        self.glfw_window.get_clipboard_string()
     }
 
-    pub fn raw_handle(&self) -> *mut glfw::ffi::GLFWwindow {
-        self.glfw_window.window_ptr()
+    pub fn set_clipboard_text(&mut self, text: &str) {
+        self.glfw_window.set_clipboard_string(text);
+    }
+
+    pub fn set_title(&mut self, title: &str) {
+        self.glfw_window.set_title(title);
+    }
+
+    // The platform picks the image closest to the size it needs, e.g. 16x16 for the title bar
+    // and 48x48 for the taskbar. No images restore the default icon.
+    // Ignored on macOS, where applications take their icons from their bundles.
+    pub fn set_icon(&mut self, images: &[RgbaImage]) {
+        self.glfw_window.set_icon(images.to_vec());
     }
 
+    // Highlights the window, e.g. by flashing its taskbar entry, without focusing it
+    pub fn request_attention(&mut self) {
+        self.glfw_window.request_attention();
+    }
+
+    // From 0.0 for a fully transparent window to 1.0 for an opaque one,
+    // ignored where the platform does not support it
+    pub fn set_opacity(&mut self, opacity: f32) {
+        self.glfw_window.set_opacity(opacity.clamp(0.0, 1.0));
+    }
+
+    // Only a weak reference is handed out, so that the handle cannot outlive the window
+    pub fn handle(&self) -> Weak<WindowHandle> {
+        Rc::downgrade(&self.handle)
+    }
+
+    // In framebuffer pixels, which the swapchain and everything rendered is measured in
     pub fn framebuffer_size(&self) -> (u32, u32) {
-        let (width, height) = self.glfw_window.get_framebuffer_size();
+        self.handle.framebuffer_size()
+    }
+
+    // Minimized windows, as well as ones shrunk to nothing, have no framebuffer to render into.
+    // The renderer skips frames while they last, so loops can wait for events instead.
+    pub fn is_minimized(&self) -> bool {
+        let (width, height) = self.framebuffer_size();
+        self.glfw_window.is_iconified() || width == 0 || height == 0
+    }
+
+    // In logical pixels, like the size the window was built with
+    pub fn size(&self) -> (u32, u32) {
+        let (width, height) = self.glfw_window.get_size();
         (width as u32, height as u32)
     }
+
+    // Framebuffer pixels per logical pixel, e.g. 2.0 on most HiDPI displays
+    pub fn content_scale(&self) -> (f32, f32) {
+        self.handle.content_scale()
+    }
+
+    #[deprecated(since = "0.2.0", note = "use `Surface::required_instance_extensions` instead")]
+    pub fn required_vulkan_extensions(&self) -> InstanceExtensions {
+        Surface::required_instance_extensions(self.handle.as_ref())
+            .unwrap_or_else(|_| InstanceExtensions::with_capacity(0))
+    }
+
+    #[deprecated(since = "0.2.0", note = "use `handle()` and `WindowHandle::raw_handle` instead")]
+    pub fn raw_handle(&self) -> *mut glfw::ffi::GLFWwindow {
+        self.handle.raw_handle()
+    }
+}
+
+pub struct WindowHandle {
+    raw_handle: *mut glfw::ffi::GLFWwindow,
+    raw_window_handle: RawWindowHandle,
+    surface_path: SurfacePath
+}
+
+impl WindowHandle {
+    pub fn raw_handle(&self) -> *mut glfw::ffi::GLFWwindow {
+        self.raw_handle
+    }
+}
+
+unsafe impl HasRawWindowHandle for WindowHandle {
+    fn raw_window_handle(&self) -> RawWindowHandle {
+        self.raw_window_handle
+    }
+}
+
+impl SurfaceWindow for WindowHandle {
+    fn framebuffer_size(&self) -> (u32, u32) {
+        let mut width = 0;
+        let mut height = 0;
+
+        unsafe {
+            glfw::ffi::glfwGetFramebufferSize(self.raw_handle, &mut width, &mut height);
+        }
+
+        (width as u32, height as u32)
+    }
+
+    fn content_scale(&self) -> (f32, f32) {
+        let mut x_scale = 1.0;
+        let mut y_scale = 1.0;
+
+        unsafe {
+            glfw::ffi::glfwGetWindowContentScale(self.raw_handle, &mut x_scale, &mut y_scale);
+        }
+
+        (x_scale, y_scale)
+    }
+
+    fn surface_path(&self) -> SurfacePath {
+        self.surface_path
+    }
+
+    fn library_surface_extensions(&self) -> Option<Vec<String>> {
+        unsafe {
+            // Asking for extensions without a Vulkan loader is an error
+            if glfw::ffi::glfwVulkanSupported() != glfw::ffi::TRUE {
+                return None;
+            }
+
+            let mut count = 0;
+            let names = glfw::ffi::glfwGetRequiredInstanceExtensions(&mut count);
+            if names.is_null() {
+                return None;
+            }
+
+            let names = std::slice::from_raw_parts(names, count as usize)
+                .iter()
+                .map(|name| std::ffi::CStr::from_ptr(*name).to_string_lossy().into_owned())
+                .collect();
+
+            Some(names)
+        }
+    }
+
+    unsafe fn create_library_surface(
+        &self,
+        instance: vk::Instance
+    ) -> Option<Result<vk::SurfaceKHR, vk::Result>> {
+        if glfw::ffi::glfwVulkanSupported() != glfw::ffi::TRUE {
+            return None;
+        }
+
+        // Failures are returned instead of going through the error callback,
+        // which panics with `glfw::FAIL_ON_ERRORS`
+        let error_callback = glfw::ffi::glfwSetErrorCallback(None);
+        let mut surface = 0;
+        let result = glfw::ffi::glfwCreateWindowSurface(
+            instance.as_raw() as _,
+            self.raw_handle,
+            std::ptr::null(),
+            &mut surface);
+        glfw::ffi::glfwSetErrorCallback(error_callback);
+
+        match vk::Result::from_raw(result as _) {
+            vk::Result::SUCCESS => Some(Ok(vk::SurfaceKHR::from_raw(surface))),
+            result => Some(Err(result))
+        }
+    }
 }
 
 #[derive(Default)]
 pub struct WindowBuilder {
     size: BuilderRequirement<WindowSize>,
     title: BuilderRequirement<String>,
+    resizable: bool,
+    min_size: Option<WindowSize>,
+    max_size: Option<WindowSize>,
+    surface_path: Option<SurfacePath>,
 
     glfw_instance: BuilderInternal<glfw::Glfw>,
     glfw_window: BuilderInternal<glfw::Window>,
@@ -93,7 +374,33 @@ impl WindowBuilder {
         self
     }
 
+    // Windows are not resizable by default
+    pub fn resizable(mut self, resizable: bool) -> Self {
+        self.resizable = resizable;
+        self
+    }
+
+    // Limits how far the user can resize the window
+    pub fn min_size(mut self, min_size: WindowSize) -> Self {
+        self.min_size = Some(min_size);
+        self
+    }
+
+    pub fn max_size(mut self, max_size: WindowSize) -> Self {
+        self.max_size = Some(max_size);
+        self
+    }
+
+    // Defaults to creating surfaces from the raw window handle. GLFW picks the platform
+    // when it is initialized, the surface path only decides who creates the surface.
+    pub fn surface_path(mut self, surface_path: SurfacePath) -> Self {
+        self.surface_path = Some(surface_path);
+        self
+    }
+
     pub fn build(mut self) -> WindowResult<Window> {
+        check_required_fields!(self, WindowError, "WindowBuilder", [size, title]);
+
         self.ready_for_creation()?;
         self.create_window();
 
@@ -117,7 +424,11 @@ impl WindowBuilder {
         self.glfw_instance.window_hint(
             glfw::WindowHint::ClientApi(glfw::ClientApiHint::NoApi));
         self.glfw_instance.window_hint(
-            glfw::WindowHint::Resizable(false));
+            glfw::WindowHint::Resizable(self.resizable));
+        // Windows and X11 otherwise ignore the content scale when sizing the window,
+        // while macOS already measures it in logical pixels
+        self.glfw_instance.window_hint(
+            glfw::WindowHint::ScaleToMonitor(true));
     }
 
     fn init_glfw_window_and_receiver(&mut self) -> WindowResult<()> {
@@ -140,17 +451,50 @@ impl WindowBuilder {
 
     fn set_window_options(&mut self) {
         self.glfw_window.as_mut().set_key_polling(true);
+        self.glfw_window.as_mut().set_char_mods_polling(true);
+        self.glfw_window.as_mut().set_content_scale_polling(true);
+        self.glfw_window.as_mut().set_framebuffer_size_polling(true);
+        self.glfw_window.as_mut().set_iconify_polling(true);
+        self.glfw_window.as_mut().set_refresh_polling(true);
+        self.set_size_limits();
+    }
+
+    fn set_size_limits(&mut self) {
+        if self.min_size.is_none() && self.max_size.is_none() {
+            return;
+        }
+
+        let dont_care = glfw::ffi::DONT_CARE as u32;
+        let limits = |size: &Option<WindowSize>| match size {
+            Some(size) => (size.width, size.height),
+            None => (dont_care, dont_care)
+        };
+
+        let (min_width, min_height) = limits(&self.min_size);
+        let (max_width, max_height) = limits(&self.max_size);
+
+        self.glfw_window.as_mut().set_size_limits(min_width, min_height, max_width, max_height);
     }
 
     fn create_window(&mut self) {
+        let glfw_window = self.glfw_window.take();
+        let handle = Rc::new(WindowHandle {
+            raw_handle: glfw_window.window_ptr(),
+            raw_window_handle: glfw_window.raw_window_handle(),
+            surface_path: self.surface_path.unwrap_or(SurfacePath::RawWindowHandle)
+        });
+
         self.window.set(Window {
-            glfw_window: self.glfw_window.take(),
+            glfw_window,
             glfw_instance: self.glfw_instance.take(),
-            event_receiver: self.event_receiver.take()
+            event_receiver: self.event_receiver.take(),
+            handle,
+            events: Vec::new()
         })
     }
 }
 
+// In logical pixels, the framebuffer may be larger on HiDPI displays
 pub struct WindowSize {
     pub width: u32,
     pub height: u32